@@ -0,0 +1,51 @@
+// Open file cache benchmark: sequential 4 KiB READs with and without the cache
+//
+// Run with: cargo bench --bench fd_cache
+//
+// A client reads a file front to back in 4 KiB READs. Without the open file
+// cache every READ opens and closes the file; with it the descriptor opened
+// by the first READ serves the rest. The read is repeated a number of times
+// and the total time, time per READ and number of opens are reported.
+
+use std::fs;
+use std::time::Instant;
+
+use arcticwolf::{Filesystem, LocalFilesystem};
+
+const FILE_SIZE: usize = 16 * 1024 * 1024;
+const READ_SIZE: u32 = 4096;
+const PASSES: usize = 10;
+
+fn report(label: &str, fs: &LocalFilesystem) {
+    let file = fs.lookup(&fs.root_handle(), "seq.bin").unwrap();
+    let mut reads = 0;
+    let started = Instant::now();
+    for _ in 0..PASSES {
+        for offset in (0..FILE_SIZE as u64).step_by(READ_SIZE as usize) {
+            let data = fs.read(&file, offset, READ_SIZE).unwrap();
+            assert_eq!(data.len(), READ_SIZE as usize);
+            reads += 1;
+        }
+    }
+    let elapsed = started.elapsed();
+    println!(
+        "{:>10}: {:>10.2?} total, {:>8.2?} per READ, {:>6} opens",
+        label,
+        elapsed,
+        elapsed / reads,
+        fs.open_count()
+    );
+}
+
+fn main() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let content: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    fs::write(temp_dir.path().join("seq.bin"), &content).unwrap();
+    println!("Reading {} MiB in {} KiB READs, {} times", FILE_SIZE >> 20, READ_SIZE >> 10, PASSES);
+
+    let uncached = LocalFilesystem::new(temp_dir.path()).unwrap().with_max_open_files(0);
+    report("uncached", &uncached);
+
+    let cached = LocalFilesystem::new(temp_dir.path()).unwrap().with_max_open_files(16);
+    report("cached", &cached);
+}
@@ -0,0 +1,69 @@
+// GETATTR benchmark: a read-mostly workload with and without the attribute cache
+//
+// Run with: cargo bench --bench getattr
+//
+// Clients poll the attributes of a working set of files, as they do to
+// revalidate their own caches, and one call in a hundred is a WRITE that
+// changes a file. The workload is timed three ways: with entries served for
+// a TTL, with a zero TTL so every GETATTR revalidates with a change-attribute
+// probe, and with a one-entry cache that every GETATTR misses, so each pays
+// for the probe and a full stat. The stat-family calls made are reported
+// alongside the time per GETATTR.
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use arcticwolf::fsal::StableHow;
+use arcticwolf::{FileHandle, Filesystem, LocalFilesystem};
+
+const FILES: usize = 1000;
+const CALLS: usize = 200_000;
+const WRITE_EVERY: usize = 100;
+
+/// Run the workload, returning the time spent in GETATTR and the number of GETATTRs
+fn run(fs: &LocalFilesystem, handles: &[FileHandle]) -> (Duration, usize) {
+    // Fixed LCG so every configuration sees the same sequence of files
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut getattrs = 0;
+    let mut elapsed = Duration::ZERO;
+    for call in 0..CALLS {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let handle = &handles[(state >> 33) as usize % handles.len()];
+        if call % WRITE_EVERY == 0 {
+            fs.write(handle, 0, &call.to_le_bytes(), StableHow::Unstable).unwrap();
+            continue;
+        }
+        let started = Instant::now();
+        fs.getattr(handle).unwrap();
+        elapsed += started.elapsed();
+        getattrs += 1;
+    }
+    (elapsed, getattrs)
+}
+
+fn report(label: &str, fs: LocalFilesystem) {
+    let root = fs.root_handle();
+    let handles: Vec<FileHandle> = (0..FILES).map(|i| fs.lookup(&root, &format!("f{:04}", i)).unwrap()).collect();
+    let stats = fs.stat_count();
+    let (elapsed, getattrs) = run(&fs, &handles);
+    println!(
+        "{:>10}: {:>10.2?} total, {:>8.2?} per GETATTR, {:>7} stats",
+        label,
+        elapsed,
+        elapsed / getattrs as u32,
+        fs.stat_count() - stats
+    );
+}
+
+fn main() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    for i in 0..FILES {
+        fs::write(temp_dir.path().join(format!("f{:04}", i)), b"data").unwrap();
+    }
+    println!("{} calls over {} files, one WRITE every {} calls", CALLS, FILES, WRITE_EVERY);
+
+    let open = || LocalFilesystem::new(temp_dir.path()).unwrap();
+    report("uncached", open().with_attr_cache(Duration::ZERO, 1));
+    report("probe", open().with_attr_cache(Duration::ZERO, FILES * 2));
+    report("cached", open().with_attr_cache(Duration::from_secs(60), FILES * 2));
+}
@@ -0,0 +1,120 @@
+// READ benchmark: allocations of 100k small reads and copies of 1 MiB READs
+//
+// Run with: cargo bench --bench read
+//
+// A client reads a file in 4 KiB READs. `Filesystem::read` hands back a new
+// buffer for every call, while `read_into` fills one the caller keeps. Each
+// way is timed and the heap allocations it makes are counted with a
+// counting global allocator. The allocations left with `read_into` come
+// from resolving the handle to a path and opening the file, and are the
+// same either way.
+//
+// The 1 MiB case runs the whole READ procedure at rtmax. Its data is read
+// straight into the reply, so it costs one allocation of the reply and no
+// copy of the payload; it is compared with a plain `read_into` of 1 MiB.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use arcticwolf::nfs::read::handle_read;
+use arcticwolf::protocol::v3::nfs::{fhandle3, READ3args};
+use arcticwolf::{Filesystem, LocalFilesystem};
+use xdr_codec::Pack;
+
+const READS: usize = 100_000;
+const READ_SIZE: usize = 4096;
+const LARGE_READS: usize = 2_000;
+const LARGE_READ_SIZE: usize = 1024 * 1024;
+const FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// System allocator counting the allocations made through it
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Offset of the i-th READ of `size` bytes, walking the file
+fn offset(i: usize, size: usize) -> u64 {
+    ((i * size) % FILE_SIZE) as u64
+}
+
+/// Run `read` `reads` times with `size`-byte READs and report its time and
+/// allocations
+fn report(label: &str, reads: usize, size: usize, mut read: impl FnMut(u64) -> usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    let mut bytes = 0;
+    for i in 0..reads {
+        bytes += read(offset(i, size));
+    }
+    let elapsed: Duration = started.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    assert_eq!(bytes, reads * size);
+    println!(
+        "{:>18}: {:>10.2?} total, {:>8.2?} per read, {:>7} allocations ({:.2} per read)",
+        label,
+        elapsed,
+        elapsed / reads as u32,
+        allocations,
+        allocations as f64 / reads as f64
+    );
+}
+
+fn main() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let data: Vec<u8> = (0..FILE_SIZE).map(|i| i as u8).collect();
+    fs::write(temp_dir.path().join("data.bin"), &data).unwrap();
+    let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+    let file = fs.lookup(&fs.root_handle(), "data.bin").unwrap();
+    println!("{} reads of {} bytes", READS, READ_SIZE);
+
+    report("read", READS, READ_SIZE, |offset| fs.read(&file, offset, READ_SIZE as u32).unwrap().len());
+
+    let mut buf = vec![0u8; READ_SIZE];
+    report("read_into", READS, READ_SIZE, |offset| fs.read_into(&file, offset, &mut buf).unwrap());
+
+    println!();
+    println!("{} reads of {} bytes", LARGE_READS, LARGE_READ_SIZE);
+
+    let mut buf = vec![0u8; LARGE_READ_SIZE];
+    report("read_into", LARGE_READS, LARGE_READ_SIZE, |offset| {
+        fs.read_into(&file, offset, &mut buf).unwrap()
+    });
+
+    report("READ procedure", LARGE_READS, LARGE_READ_SIZE, |offset| {
+        let mut args = Vec::new();
+        READ3args { file: fhandle3(file.clone()), offset, count: LARGE_READ_SIZE as u32 }
+            .pack(&mut args)
+            .unwrap();
+        let reply = handle_read(1, &args, &fs, LARGE_READ_SIZE as u32).unwrap();
+        // The opaque data ends the reply, which needs no padding at 1 MiB
+        let data = &reply[reply.len() - LARGE_READ_SIZE..];
+        assert_eq!(data[0], offset as u8);
+        data.len()
+    });
+}
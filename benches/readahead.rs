@@ -0,0 +1,47 @@
+// Readahead benchmark: 10k sequential 4 KiB READs with and without readahead
+//
+// Run with: cargo bench --bench readahead
+//
+// A client with a small rsize reads a file front to back in 4 KiB READs.
+// Without readahead each READ is one read from disk; with a 1 MiB window the
+// READs that follow one from disk are served from memory. The READs are
+// timed both ways and the reads issued to disk are reported.
+
+use std::fs;
+use std::time::Instant;
+
+use arcticwolf::{Filesystem, LocalFilesystem};
+
+const READS: u64 = 10_000;
+const READ_SIZE: u32 = 4096;
+const WINDOW: usize = 1024 * 1024;
+
+fn report(label: &str, fs: &LocalFilesystem) {
+    let file = fs.lookup(&fs.root_handle(), "seq.bin").unwrap();
+    let started = Instant::now();
+    for i in 0..READS {
+        let data = fs.read(&file, i * READ_SIZE as u64, READ_SIZE).unwrap();
+        assert_eq!(data.len(), READ_SIZE as usize);
+    }
+    let elapsed = started.elapsed();
+    println!(
+        "{:>10}: {:>10.2?} total, {:>8.2?} per READ, {:>6} disk reads",
+        label,
+        elapsed,
+        elapsed / READS as u32,
+        fs.read_count()
+    );
+}
+
+fn main() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let content: Vec<u8> = (0..READS as usize * READ_SIZE as usize).map(|i| (i % 251) as u8).collect();
+    fs::write(temp_dir.path().join("seq.bin"), &content).unwrap();
+    println!("{} sequential READs of {} KiB", READS, READ_SIZE >> 10);
+
+    let plain = LocalFilesystem::new(temp_dir.path()).unwrap();
+    report("plain", &plain);
+
+    let ahead = LocalFilesystem::new(temp_dir.path()).unwrap().with_readahead(WINDOW);
+    report("readahead", &ahead);
+}
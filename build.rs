@@ -118,6 +118,28 @@ fn main() {
                 "#[derive( Copy , Clone , Debug , Eq , PartialEq )] pub enum MKNOD3res",
                 "#[derive( Clone , Debug , Eq , PartialEq )] pub enum MKNOD3res"
             );
+
+            // xdrgen cannot pack the `default: void` arm of a union because it
+            // has no discriminant to emit. For the sattr3 "set_it" unions and
+            // the SETATTR guard, `default` means "don't change" / "no check",
+            // which is discriminant 0 on the wire.
+            for union_name in [
+                "set_mode3",
+                "set_uid3",
+                "set_gid3",
+                "set_size3",
+                "set_atime",
+                "set_mtime",
+                "sattrguard3",
+            ] {
+                generated_code = generated_code.replace(
+                    &format!(
+                        "& {} :: default => return Err ( xdr_codec :: Error :: invalidcase ( - 1 ) )",
+                        union_name
+                    ),
+                    &format!("& {} :: default => 0i32 . pack ( out ) ?", union_name),
+                );
+            }
         }
 
         fs::write(&output_path, generated_code.as_bytes())
@@ -0,0 +1,951 @@
+// Server Configuration
+//
+// TOML configuration file describing where the server listens and what it
+// exports. Example:
+//
+//   [server]
+//   bind_address = ["0.0.0.0", "::1"]
+//
+//   [server.ports]
+//   portmap = 111
+//   mount = 20048
+//   nfs = 2049
+//
+//   [[export]]
+//   backend = "local"
+//   path = "/srv/nfs"
+//   read_only = false
+//
+//   [[export]]
+//   path = "/srv/archive"
+//   read_only = true
+//
+// Each `[[export]]` table is served under its own path with its own
+// options; a single `[export]` table is accepted as well.
+//
+// `--export <path>` and `--port <port>` on the command line override the
+// exports and ports, so `arcticwolf --export /data --port 2049` serves a
+// directory without any file.
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::export::{
+    ClientSpec, ExportId, ExportOptions, ExportTable, ModePolicy, Squash, TransferSizes, DEFAULT_MAX_TRANSFER,
+    DEFAULT_PREF_READDIR, DEFAULT_PREF_TRANSFER,
+};
+use crate::fsal::BackendConfig;
+use crate::fsal::local::{
+    self, DEFAULT_ATTR_CACHE_ENTRIES, DEFAULT_ATTR_CACHE_TTL, DEFAULT_COMMIT_WINDOW, DEFAULT_DIR_CACHE_TTL,
+    DEFAULT_MAX_OPEN_FILES, DEFAULT_OPEN_TIMEOUT, DEFAULT_READAHEAD, DEFAULT_STATFS_CACHE_TTL,
+};
+use crate::protocol::v3::rpc::auth_flavor;
+use crate::rpc::auth::ANONYMOUS_ID;
+use crate::rpc::breaker::BreakerConfig;
+use crate::rpc::rate_limit::RateLimitConfig;
+use crate::rpc::server::{DEFAULT_MAX_REQUEST_SIZE, DEFAULT_OP_TIMEOUT};
+
+/// Default port shared by all services when none are configured
+pub const DEFAULT_PORT: u16 = 4000;
+
+/// Standard portmapper port
+pub const STANDARD_PORTMAP_PORT: u16 = 111;
+
+/// Conventional MOUNT port
+pub const STANDARD_MOUNT_PORT: u16 = 20048;
+
+/// Standard NFS port
+pub const STANDARD_NFS_PORT: u16 = 2049;
+
+/// Default export root when no configuration file is given
+pub const DEFAULT_EXPORT_PATH: &str = "/tmp/nfs_exports";
+
+/// Largest `export.max_transfer_kib`; a 1 MiB WRITE plus its headers fits the
+/// default request size limit
+const MAX_TRANSFER_KIB: u32 = 1024;
+
+/// Top-level server configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Listener settings
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// The exported filesystems: one `[export]` table or an array of them
+    #[serde(rename = "export", default = "default_exports", deserialize_with = "one_table_or_many")]
+    pub exports: Vec<ExportConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            server: ServerConfig::default(),
+            exports: default_exports(),
+        }
+    }
+}
+
+/// The built-in export served when the configuration names none
+fn default_exports() -> Vec<ExportConfig> {
+    vec![ExportConfig::default()]
+}
+
+/// Listener settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Addresses to bind: one ("0.0.0.0", "::" for dual-stack) or a list
+    #[serde(rename = "bind_address", deserialize_with = "one_or_many")]
+    pub bind_addresses: Vec<String>,
+    /// Per-service ports
+    pub ports: PortsConfig,
+    /// Largest RPC request accepted, in bytes; larger ones close the connection
+    pub max_request_size: usize,
+    /// Milliseconds a call may take before the client is told to retry
+    /// (NFS3ERR_JUKEBOX); 0 waits for every call
+    pub op_timeout_ms: u64,
+    /// Calls per second accepted from one client address; 0 is unlimited
+    pub rate_limit_per_client: u32,
+    /// Calls per second accepted from all clients together; 0 is unlimited
+    pub rate_limit_global: u32,
+    /// Milliseconds a call over a rate limit may be delayed before it is
+    /// dropped instead
+    pub rate_limit_max_delay_ms: u64,
+    /// Consecutive NFS3ERR_IO replies after which an export's calls fail
+    /// fast without reaching its backend; 0 never does
+    pub breaker_threshold: u32,
+    /// Milliseconds calls fail fast before the export root is checked again
+    pub breaker_cooldown_ms: u64,
+    /// Address of the Prometheus metrics endpoint ("host:port"); disabled when unset
+    pub metrics_address: Option<String>,
+    /// Address of the HTTP health probe endpoint ("host:port"); disabled when unset
+    pub health_address: Option<String>,
+    /// Clients allowed to query the admin RPC program ("*", an address, or a CIDR block)
+    pub admin_clients: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_addresses: vec!["0.0.0.0".to_string()],
+            ports: PortsConfig::default(),
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            op_timeout_ms: DEFAULT_OP_TIMEOUT.as_millis() as u64,
+            rate_limit_per_client: 0,
+            rate_limit_global: 0,
+            rate_limit_max_delay_ms: RateLimitConfig::default().max_delay.as_millis() as u64,
+            breaker_threshold: BreakerConfig::default().threshold,
+            breaker_cooldown_ms: BreakerConfig::default().cooldown.as_millis() as u64,
+            metrics_address: None,
+            health_address: None,
+            admin_clients: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Time a call may take before the client is told to retry
+    pub fn op_timeout(&self) -> Duration {
+        Duration::from_millis(self.op_timeout_ms)
+    }
+
+    /// Request rate limits
+    pub fn rate_limit(&self) -> RateLimitConfig {
+        RateLimitConfig {
+            per_client: self.rate_limit_per_client,
+            global: self.rate_limit_global,
+            max_delay: Duration::from_millis(self.rate_limit_max_delay_ms),
+        }
+    }
+
+    /// Circuit breaking of failing backends
+    pub fn breaker(&self) -> BreakerConfig {
+        BreakerConfig {
+            threshold: self.breaker_threshold,
+            cooldown: Duration::from_millis(self.breaker_cooldown_ms),
+        }
+    }
+
+    /// Clients allowed to query the admin RPC program
+    pub fn admin_clients(&self) -> Result<Vec<ClientSpec>> {
+        self.admin_clients.iter().map(|spec| spec.parse::<ClientSpec>()).collect()
+    }
+}
+
+/// Per-service ports
+///
+/// Services may share a port; each listener serves every program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PortsConfig {
+    /// Portmapper (program 100000)
+    pub portmap: u16,
+    /// MOUNT (program 100005)
+    pub mount: u16,
+    /// NFS (program 100003)
+    pub nfs: u16,
+}
+
+impl Default for PortsConfig {
+    fn default() -> Self {
+        Self {
+            portmap: DEFAULT_PORT,
+            mount: DEFAULT_PORT,
+            nfs: DEFAULT_PORT,
+        }
+    }
+}
+
+impl PortsConfig {
+    /// The well-known ports clients use without port overrides
+    pub fn standard() -> Self {
+        Self {
+            portmap: STANDARD_PORTMAP_PORT,
+            mount: STANDARD_MOUNT_PORT,
+            nfs: STANDARD_NFS_PORT,
+        }
+    }
+
+    /// Distinct ports to listen on, in portmap/mount/nfs order
+    pub fn distinct(&self) -> Vec<u16> {
+        let mut ports = Vec::with_capacity(3);
+        for port in [self.portmap, self.mount, self.nfs] {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        ports
+    }
+}
+
+/// Backend kind of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// Local directory tree
+    #[default]
+    Local,
+    /// In-memory filesystem (testing)
+    Memory,
+}
+
+/// Squash policy as written in the configuration file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SquashConfig {
+    /// No squashing
+    None,
+    /// Squash uid/gid 0
+    #[default]
+    Root,
+    /// Squash every caller
+    All,
+}
+
+/// RPC auth flavor as written in the configuration file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthFlavorConfig {
+    /// AUTH_NONE
+    None,
+    /// AUTH_SYS (AUTH_UNIX)
+    Sys,
+}
+
+impl AuthFlavorConfig {
+    /// RPC auth flavor number
+    pub fn number(self) -> i32 {
+        match self {
+            AuthFlavorConfig::None => auth_flavor::AUTH_NONE as i32,
+            AuthFlavorConfig::Sys => auth_flavor::AUTH_SYS as i32,
+        }
+    }
+}
+
+/// The exported filesystem
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Backend kind
+    pub backend: BackendKind,
+    /// Export root (local backend) and the MOUNT dirpath clients request
+    pub path: PathBuf,
+    /// Directory below `path` served as the export root instead; clients
+    /// then mount `path/subpath`
+    pub subpath: Option<String>,
+    /// Refuse all modifying procedures with NFS3ERR_ROFS
+    pub read_only: bool,
+    /// Identity squashing policy
+    pub squash: SquashConfig,
+    /// uid squashed callers are mapped to
+    pub anon_uid: u32,
+    /// gid squashed callers are mapped to
+    pub anon_gid: u32,
+    /// Allowed clients ("*", an address, or a CIDR block)
+    pub clients: Vec<String>,
+    /// Auth flavors advertised by MNT, most preferred first
+    pub auth_flavors: Vec<AuthFlavorConfig>,
+    /// Milliseconds GETATTR results are served without revalidation (0 always revalidates)
+    pub attr_cache_ttl_ms: u64,
+    /// Maximum number of cached GETATTR results
+    pub attr_cache_entries: usize,
+    /// Maximum number of files kept open between READ and WRITE calls (0 disables)
+    pub max_open_files: usize,
+    /// KiB read ahead once READs of a file turn sequential (0 disables)
+    pub readahead_kib: usize,
+    /// Milliseconds directory names are kept for READDIR continuations (0 disables)
+    pub dir_cache_ttl_ms: u64,
+    /// Milliseconds filesystem statistics are kept for FSSTAT (0 disables)
+    pub statfs_cache_ttl_ms: u64,
+    /// Milliseconds COMMITs of a file are gathered into one sync (0 disables)
+    pub commit_window_ms: u64,
+    /// Milliseconds the export root may take to answer at startup (0 waits indefinitely)
+    pub root_timeout_ms: u64,
+    /// List "." and ".." at the start of every directory (local backend)
+    pub dot_entries: bool,
+    /// Permission bits cleared from modes requested by CREATE, MKDIR and MKNOD
+    pub umask: u32,
+    /// Mode of files created without a requested mode
+    pub default_file_mode: u32,
+    /// Mode of directories created without a requested mode
+    pub default_dir_mode: u32,
+    /// fsid clients see for the export (defaults to the export id)
+    pub fsid: Option<u64>,
+    /// Largest READ or WRITE, in KiB, advertised to clients and enforced
+    pub max_transfer_kib: u32,
+    /// Largest READ in KiB (rtmax); `max_transfer_kib` when unset
+    pub rtmax_kib: Option<u32>,
+    /// Preferred READ size in KiB (rtpref); 64 or rtmax if smaller when unset
+    pub rtpref_kib: Option<u32>,
+    /// Largest WRITE in KiB (wtmax); `max_transfer_kib` when unset
+    pub wtmax_kib: Option<u32>,
+    /// Preferred WRITE size in KiB (wtpref); 64 or wtmax if smaller when unset
+    pub wtpref_kib: Option<u32>,
+    /// Preferred READDIR size in KiB (dtpref)
+    pub dtpref_kib: u32,
+    /// Sync every WRITE, even those clients send UNSTABLE
+    pub force_stable_writes: bool,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            backend: BackendKind::default(),
+            path: PathBuf::from(DEFAULT_EXPORT_PATH),
+            subpath: None,
+            read_only: false,
+            squash: SquashConfig::default(),
+            anon_uid: ANONYMOUS_ID,
+            anon_gid: ANONYMOUS_ID,
+            clients: vec!["*".to_string()],
+            auth_flavors: vec![AuthFlavorConfig::Sys, AuthFlavorConfig::None],
+            attr_cache_ttl_ms: DEFAULT_ATTR_CACHE_TTL.as_millis() as u64,
+            attr_cache_entries: DEFAULT_ATTR_CACHE_ENTRIES,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            readahead_kib: DEFAULT_READAHEAD / 1024,
+            dir_cache_ttl_ms: DEFAULT_DIR_CACHE_TTL.as_millis() as u64,
+            statfs_cache_ttl_ms: DEFAULT_STATFS_CACHE_TTL.as_millis() as u64,
+            commit_window_ms: DEFAULT_COMMIT_WINDOW.as_millis() as u64,
+            root_timeout_ms: DEFAULT_OPEN_TIMEOUT.as_millis() as u64,
+            dot_entries: true,
+            umask: ModePolicy::default().umask,
+            default_file_mode: ModePolicy::default().file_mode,
+            default_dir_mode: ModePolicy::default().dir_mode,
+            fsid: None,
+            max_transfer_kib: DEFAULT_MAX_TRANSFER / 1024,
+            rtmax_kib: None,
+            rtpref_kib: None,
+            wtmax_kib: None,
+            wtpref_kib: None,
+            dtpref_kib: DEFAULT_PREF_READDIR / 1024,
+            force_stable_writes: false,
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from a TOML file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        Self::from_toml(&text).with_context(|| format!("Invalid config file {:?}", path))
+    }
+
+    /// Parse configuration from TOML text
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config: Config = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Serve `path` through the local backend instead of the configured exports
+    ///
+    /// Used by `--export`. The first export's other settings (read-only,
+    /// squashing, clients) are kept, but any subpath is dropped since it
+    /// named a directory below the replaced root; further exports are dropped.
+    pub fn with_export(mut self, path: impl Into<PathBuf>) -> Self {
+        let mut export = self.exports.into_iter().next().unwrap_or_default();
+        export.backend = BackendKind::Local;
+        export.path = path.into();
+        export.subpath = None;
+        self.exports = vec![export];
+        self
+    }
+
+    /// Serve portmap, MOUNT and NFS all on `port`
+    ///
+    /// Used by `--port`; a single listener per bind address then answers
+    /// every program.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.server.ports = PortsConfig {
+            portmap: port,
+            mount: port,
+            nfs: port,
+        };
+        self
+    }
+
+    /// Check settings that cannot be expressed in the schema
+    ///
+    /// Run on every loaded file and again after command-line overrides.
+    pub fn validate(&self) -> Result<()> {
+        if self.server.max_request_size == 0 {
+            return Err(anyhow!("server.max_request_size must be greater than zero"));
+        }
+        if self.exports.is_empty() {
+            return Err(anyhow!("at least one export must be configured"));
+        }
+        self.server.admin_clients()?;
+        let mut mount_paths = HashSet::new();
+        for export in &self.exports {
+            if export.auth_flavors.is_empty() {
+                return Err(anyhow!("export.auth_flavors must list at least one flavor"));
+            }
+            export.options()?;
+            if !mount_paths.insert(export.mount_path()) {
+                return Err(anyhow!("export {} is configured more than once", export.mount_path()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the server could start with this configuration, without serving
+    ///
+    /// Builds every export's backend and options, then binds every listen
+    /// address and releases it at once. Used by `--dry-run` so that
+    /// misconfiguration fails in CI rather than at startup.
+    pub fn check(&self) -> Result<()> {
+        self.validate()?;
+        self.export_table()?;
+
+        let endpoints = self.server.metrics_address.iter().chain(&self.server.health_address);
+        for address in self.listen_addresses().iter().chain(endpoints) {
+            TcpListener::bind(address).with_context(|| format!("Cannot bind {}", address))?;
+        }
+        Ok(())
+    }
+
+    /// Open every export's backend and build the table serving them
+    ///
+    /// Exports get ids in the order they are configured, starting at 1.
+    pub fn export_table(&self) -> Result<ExportTable> {
+        let mut table = ExportTable::new();
+        for export in &self.exports {
+            export.add_to(&mut table)?;
+        }
+        Ok(table)
+    }
+
+    /// Addresses the RPC server listens on, for each bind address and distinct service port
+    pub fn listen_addresses(&self) -> Vec<String> {
+        let ports = self.server.ports.distinct();
+        self.server
+            .bind_addresses
+            .iter()
+            .flat_map(|host| ports.iter().map(move |port| bind_spec(host, *port)))
+            .collect()
+    }
+}
+
+/// Combine a host and port into a bind spec, bracketing IPv6 literals
+fn bind_spec(host: &str, port: u16) -> String {
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    match literal.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, port).to_string(),
+        Err(_) => format!("{}:{}", host, port),
+    }
+}
+
+/// Deserialize either a single string or a list of strings
+fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Deserialize either a single `[export]` table or an array of `[[export]]` tables
+///
+/// Dispatches on the TOML shape rather than trying each in turn, so errors
+/// inside a table (an unknown field, say) are reported as they are.
+fn one_table_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<ExportConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct TablesVisitor;
+
+    impl<'de> Visitor<'de> for TablesVisitor {
+        type Value = Vec<ExportConfig>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a table or an array of tables")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> std::result::Result<Self::Value, A::Error> {
+            Ok(vec![ExportConfig::deserialize(MapAccessDeserializer::new(map))?])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> std::result::Result<Self::Value, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(TablesVisitor)
+}
+
+impl ExportConfig {
+    /// Build the backend configuration, failing if the export root is unusable
+    pub fn backend_config(&self) -> Result<BackendConfig> {
+        match self.backend {
+            BackendKind::Local => {
+                // A root on unreachable storage fails after the timeout
+                // instead of blocking startup
+                let timeout = Duration::from_millis(self.root_timeout_ms);
+                let path = self.path.clone();
+                match local::with_root_timeout(&self.path, timeout, move || Ok(fs::metadata(path)))? {
+                    Err(_) => return Err(anyhow!("Export root {:?} does not exist", self.path)),
+                    Ok(metadata) if !metadata.is_dir() => {
+                        return Err(anyhow!("Export root {:?} is not a directory", self.path))
+                    }
+                    Ok(_) => {}
+                }
+                Ok(BackendConfig::local(&self.path)
+                    .with_attr_cache(Duration::from_millis(self.attr_cache_ttl_ms), self.attr_cache_entries)
+                    .with_max_open_files(self.max_open_files)
+                    .with_readahead(self.readahead_kib * 1024)
+                    .with_dir_cache(Duration::from_millis(self.dir_cache_ttl_ms))
+                    .with_statfs_cache(Duration::from_millis(self.statfs_cache_ttl_ms))
+                    .with_commit_window(Duration::from_millis(self.commit_window_ms))
+                    .with_dot_entries(self.dot_entries)
+                    .with_open_timeout(timeout))
+            }
+            BackendKind::Memory => Ok(BackendConfig::memory()),
+        }
+    }
+
+    /// Path clients pass to MOUNT: `path`, extended by `subpath` if set
+    pub fn mount_path(&self) -> String {
+        let path = match &self.subpath {
+            Some(subpath) => self.path.join(subpath.trim_start_matches('/')),
+            None => self.path.clone(),
+        };
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Open the backend and add it to `table` under `mount_path`, returning its id
+    pub fn add_to(&self, table: &mut ExportTable) -> Result<ExportId> {
+        let filesystem = self
+            .backend_config()?
+            .create_filesystem()
+            .with_context(|| format!("Cannot open export {:?}", self.path))?;
+        table.add_subtree(
+            &self.mount_path(),
+            Arc::from(filesystem),
+            self.subpath.as_deref().unwrap_or_default(),
+            self.options()?,
+        )
+    }
+
+    /// Build the per-export options
+    pub fn options(&self) -> Result<ExportOptions> {
+        let clients = self
+            .clients
+            .iter()
+            .map(|spec| spec.parse::<ClientSpec>())
+            .collect::<Result<Vec<_>>>()?;
+        for (name, mode) in [
+            ("umask", self.umask),
+            ("default_file_mode", self.default_file_mode),
+            ("default_dir_mode", self.default_dir_mode),
+        ] {
+            if mode > 0o7777 {
+                return Err(anyhow!("export.{} {:#o} is not a permission mode", name, mode));
+            }
+        }
+        let transfer = self.transfer_sizes()?;
+        let squash = match self.squash {
+            SquashConfig::None => Squash::None,
+            SquashConfig::Root => Squash::Root,
+            SquashConfig::All => Squash::All,
+        };
+
+        Ok(ExportOptions {
+            squash,
+            anon_uid: self.anon_uid,
+            anon_gid: self.anon_gid,
+            clients,
+            read_only: self.read_only,
+            auth_flavors: self.auth_flavors.iter().map(|flavor| flavor.number()).collect(),
+            modes: ModePolicy {
+                umask: self.umask,
+                file_mode: self.default_file_mode,
+                dir_mode: self.default_dir_mode,
+            },
+            fsid: self.fsid,
+            transfer,
+            force_stable_writes: self.force_stable_writes,
+        })
+    }
+
+    /// READ, WRITE and READDIR sizes, validated and in bytes
+    fn transfer_sizes(&self) -> Result<TransferSizes> {
+        let read_max = self.rtmax_kib.unwrap_or(self.max_transfer_kib);
+        let write_max = self.wtmax_kib.unwrap_or(self.max_transfer_kib);
+        let default_pref = DEFAULT_PREF_TRANSFER / 1024;
+        let read_pref = self.rtpref_kib.unwrap_or(default_pref.min(read_max));
+        let write_pref = self.wtpref_kib.unwrap_or(default_pref.min(write_max));
+
+        for (name, kib) in [
+            ("max_transfer_kib", self.max_transfer_kib),
+            ("rtmax_kib", read_max),
+            ("wtmax_kib", write_max),
+        ] {
+            if !(4..=MAX_TRANSFER_KIB).contains(&kib) {
+                return Err(anyhow!("export.{} {} is outside 4..={}", name, kib, MAX_TRANSFER_KIB));
+            }
+        }
+        for (name, kib, max) in [
+            ("rtpref_kib", read_pref, read_max),
+            ("wtpref_kib", write_pref, write_max),
+            ("dtpref_kib", self.dtpref_kib, MAX_TRANSFER_KIB),
+        ] {
+            if !(1..=max).contains(&kib) {
+                return Err(anyhow!("export.{} {} is outside 1..={}", name, kib, max));
+            }
+        }
+
+        Ok(TransferSizes {
+            read_max: read_max * 1024,
+            read_pref: read_pref * 1024,
+            write_max: write_max * 1024,
+            write_pref: write_pref * 1024,
+            readdir_pref: self.dtpref_kib * 1024,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportResolver;
+    use crate::fsal::BackendType;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let text = format!(
+            r#"
+            [server]
+            bind_address = "127.0.0.1"
+            max_request_size = 65536
+            op_timeout_ms = 5000
+            rate_limit_per_client = 200
+            rate_limit_global = 5000
+            rate_limit_max_delay_ms = 250
+            breaker_threshold = 3
+            breaker_cooldown_ms = 10000
+            metrics_address = "127.0.0.1:9100"
+            health_address = "0.0.0.0:8080"
+            admin_clients = ["127.0.0.1"]
+
+            [server.ports]
+            portmap = 111
+            mount = 20048
+            nfs = 2049
+
+            [export]
+            backend = "local"
+            path = "{}"
+            read_only = true
+            squash = "all"
+            clients = ["10.0.0.0/8"]
+            auth_flavors = ["sys"]
+            attr_cache_ttl_ms = 250
+            max_open_files = 32
+            readahead_kib = 512
+            dir_cache_ttl_ms = 750
+            statfs_cache_ttl_ms = 2000
+            commit_window_ms = 2
+            dot_entries = false
+            umask = 0o027
+            default_dir_mode = 0o770
+            fsid = 42
+            max_transfer_kib = 256
+            force_stable_writes = true
+            "#,
+            temp_dir.path().display()
+        );
+
+        let config = Config::from_toml(&text).unwrap();
+        assert_eq!(config.server.ports, PortsConfig::standard());
+        assert_eq!(config.server.max_request_size, 65536);
+        assert_eq!(config.server.op_timeout(), Duration::from_secs(5));
+        let rate_limit = config.server.rate_limit();
+        assert_eq!((rate_limit.per_client, rate_limit.global), (200, 5000));
+        assert_eq!(rate_limit.max_delay, Duration::from_millis(250));
+        assert_eq!(config.server.breaker().threshold, 3);
+        assert_eq!(config.server.breaker().cooldown, Duration::from_secs(10));
+        assert_eq!(config.server.metrics_address.as_deref(), Some("127.0.0.1:9100"));
+        assert_eq!(config.server.health_address.as_deref(), Some("0.0.0.0:8080"));
+        assert_eq!(config.server.admin_clients().unwrap(), vec![ClientSpec::Host("127.0.0.1".parse().unwrap())]);
+        assert_eq!(
+            config.listen_addresses(),
+            vec!["127.0.0.1:111", "127.0.0.1:20048", "127.0.0.1:2049"]
+        );
+
+        let backend = config.exports[0].backend_config().unwrap();
+        assert_eq!(backend.backend_type, BackendType::Local);
+        assert_eq!(backend.attr_cache_ttl, Duration::from_millis(250));
+        assert_eq!(backend.attr_cache_entries, DEFAULT_ATTR_CACHE_ENTRIES);
+        assert_eq!(backend.max_open_files, 32);
+        assert_eq!(backend.readahead, 512 * 1024);
+        assert_eq!(backend.dir_cache_ttl, Duration::from_millis(750));
+        assert_eq!(backend.statfs_cache_ttl, Duration::from_millis(2000));
+        assert_eq!(backend.commit_window, Duration::from_millis(2));
+        assert!(!backend.dot_entries);
+        assert!(backend.create_filesystem().is_ok());
+
+        let options = config.exports[0].options().unwrap();
+        assert!(options.read_only);
+        assert!(options.force_stable_writes);
+        assert_eq!(options.squash, Squash::All);
+        assert!(options.allows("10.2.3.4".parse().unwrap()));
+        assert!(!options.allows("192.0.2.1".parse().unwrap()));
+        assert_eq!(options.auth_flavors, vec![auth_flavor::AUTH_SYS as i32]);
+        assert_eq!(options.fsid, Some(42));
+        assert_eq!(options.transfer.read_max, 256 * 1024);
+        assert_eq!(options.transfer.write_max, 256 * 1024);
+        assert_eq!(options.transfer.read_pref, 64 * 1024);
+        assert_eq!(
+            options.modes,
+            ModePolicy {
+                umask: 0o027,
+                file_mode: 0o644,
+                dir_mode: 0o770,
+            }
+        );
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.listen_addresses(), vec!["0.0.0.0:4000"]);
+        assert_eq!(config.exports[0].path, PathBuf::from(DEFAULT_EXPORT_PATH));
+        assert_eq!(config.exports[0].options().unwrap(), ExportOptions::default());
+        assert_eq!(config.server.max_request_size, DEFAULT_MAX_REQUEST_SIZE);
+        assert_eq!(config.server.op_timeout(), DEFAULT_OP_TIMEOUT);
+        let rate_limit = config.server.rate_limit();
+        assert_eq!((rate_limit.per_client, rate_limit.global), (0, 0), "Rate limiting is off by default");
+    }
+
+    #[test]
+    fn test_ipv6_bind_addresses() {
+        let config = Config::from_toml(
+            "[server]\nbind_address = [\"::\", \"[::1]\", \"localhost\"]\n[server.ports]\nportmap = 111\nmount = 2049\nnfs = 2049\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.listen_addresses(),
+            vec!["[::]:111", "[::]:2049", "[::1]:111", "[::1]:2049", "localhost:111", "localhost:2049"]
+        );
+    }
+
+    #[test]
+    fn test_missing_export_root_fails_fast() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("missing");
+        let text = format!("[export]\npath = \"{}\"\n", missing.display());
+
+        let config = Config::from_toml(&text).unwrap();
+        let err = config.exports[0].backend_config().unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{}", err);
+    }
+
+    #[test]
+    fn test_subpath_export() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("project")).unwrap();
+        let config_for = |subpath: &str| {
+            Config::from_toml(&format!(
+                "[export]\npath = \"{}\"\nsubpath = \"{}\"\n",
+                temp_dir.path().display(),
+                subpath
+            ))
+            .unwrap()
+        };
+
+        // Clients mount the subdirectory's full path
+        let config = config_for("project");
+        let mount_path = temp_dir.path().join("project").to_string_lossy().into_owned();
+        assert_eq!(config.exports[0].mount_path(), mount_path);
+        let table = config.export_table().unwrap();
+        assert!(table.resolve_path(&mount_path).is_some());
+        assert!(table.resolve_path(&temp_dir.path().to_string_lossy()).is_none());
+
+        // A subpath outside the export root or missing is refused at startup
+        assert!(config_for("..").export_table().is_err());
+        assert!(config_for("missing").export_table().is_err());
+    }
+
+    #[test]
+    fn test_multiple_exports() {
+        let home = TempDir::new().unwrap();
+        let archive = TempDir::new().unwrap();
+        let text = format!(
+            r#"
+            [[export]]
+            path = "{}"
+            squash = "root"
+
+            [[export]]
+            path = "{}"
+            read_only = true
+            clients = ["10.0.0.0/8"]
+            "#,
+            home.path().display(),
+            archive.path().display()
+        );
+        let config = Config::from_toml(&text).unwrap();
+        assert_eq!(config.exports.len(), 2);
+
+        // Each root is served under its own path with its own options
+        let table = config.export_table().unwrap();
+        let home_id = table.resolve_path(&home.path().to_string_lossy()).unwrap();
+        let archive_id = table.resolve_path(&archive.path().to_string_lossy()).unwrap();
+        assert_eq!((home_id, archive_id), (1, 2));
+        let options = |id: ExportId| &table.exports()[id as usize - 1].options;
+        assert!(!options(home_id).read_only);
+        assert_eq!(options(home_id).squash, Squash::Root);
+        assert!(options(home_id).allows("192.0.2.1".parse().unwrap()));
+        assert!(options(archive_id).read_only);
+        assert!(!options(archive_id).allows("192.0.2.1".parse().unwrap()));
+
+        // Files are looked up in the export's own root
+        std::fs::write(archive.path().join("old.txt"), b"archived").unwrap();
+        let backend = |id| table.backend(id).unwrap();
+        assert!(backend(archive_id).lookup(&backend(archive_id).root_handle(), "old.txt").is_ok());
+        assert!(backend(home_id).lookup(&backend(home_id).root_handle(), "old.txt").is_err());
+
+        // Two exports under the same path are refused
+        let duplicate = format!("[[export]]\npath = \"{0}\"\n[[export]]\npath = \"{0}\"\n", home.path().display());
+        let err = Config::from_toml(&duplicate).unwrap_err();
+        assert!(err.to_string().contains("more than once"), "{}", err);
+        assert!(Config::from_toml("export = []\n").is_err());
+
+        // `--export` replaces them all with the one directory
+        let config = config.with_export(archive.path());
+        assert_eq!(config.exports.len(), 1);
+        assert_eq!(config.exports[0].squash, SquashConfig::Root, "The first export's settings are kept");
+    }
+
+    #[test]
+    fn test_command_line_export() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // `--export <dir> --port 2049` without a config file
+        let config = Config::default().with_export(temp_dir.path()).with_port(2049);
+        assert_eq!(config.listen_addresses(), vec!["0.0.0.0:2049"]);
+        assert_eq!(config.exports[0].mount_path(), temp_dir.path().to_string_lossy());
+        let backend = config.exports[0].backend_config().unwrap();
+        assert!(backend.create_filesystem().is_ok());
+        assert!(config.export_table().unwrap().resolve_path(&config.exports[0].mount_path()).is_some());
+
+        // Overriding a file's memory-backed subtree export serves the directory itself
+        let text = "[export]\nbackend = \"memory\"\npath = \"/mem\"\nsubpath = \"sub\"\nread_only = true\n";
+        let config = Config::from_toml(text).unwrap().with_export(temp_dir.path());
+        assert_eq!(config.exports[0].backend, BackendKind::Local);
+        assert_eq!(config.exports[0].subpath, None);
+        assert!(config.exports[0].read_only, "Other export settings are kept");
+        assert_eq!(config.listen_addresses(), vec!["0.0.0.0:4000"]);
+
+        // A missing directory still fails at startup
+        let err = Config::default().with_export(temp_dir.path().join("missing")).check().unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{}", err);
+
+        // Overrides do not bypass the file's own validation
+        let mut config = Config::default().with_export(temp_dir.path()).with_port(2049);
+        config.server.max_request_size = 0;
+        assert!(config.validate().is_err());
+        assert!(config.check().is_err());
+    }
+
+    #[test]
+    fn test_check_dry_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_for = |path: &Path, port: u16| {
+            Config::from_toml(&format!(
+                "[server]\nbind_address = \"127.0.0.1\"\n[server.ports]\nportmap = {port}\nmount = {port}\nnfs = {port}\n[export]\npath = \"{}\"\n",
+                path.display()
+            ))
+            .unwrap()
+        };
+
+        // A usable export on a free port passes, and the port is released
+        let free_port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        config_for(temp_dir.path(), free_port).check().unwrap();
+        config_for(temp_dir.path(), free_port).check().unwrap();
+
+        // A missing export root fails
+        let err = config_for(&temp_dir.path().join("missing"), free_port).check().unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{}", err);
+
+        // A port already in use fails
+        let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let busy_port = busy.local_addr().unwrap().port();
+        let err = config_for(temp_dir.path(), busy_port).check().unwrap_err();
+        assert!(err.to_string().contains("Cannot bind"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_config_rejected() {
+        assert!(Config::from_toml("[export]\nbackend = \"s3\"\n").is_err());
+        assert!(Config::from_toml("[export]\nclients = [\"10.0.0.0/99\"]\n").is_err());
+        assert!(Config::from_toml("[export]\nunknown = 1\n").is_err());
+        assert!(Config::from_toml("[export]\nauth_flavors = []\n").is_err());
+        assert!(Config::from_toml("[export]\numask = 0o10000\n").is_err());
+        assert!(Config::from_toml("[export]\nrtmax_kib = 2048\n").is_err());
+        assert!(Config::from_toml("[export]\nrtmax_kib = 32\nrtpref_kib = 64\n").is_err());
+        assert!(Config::from_toml("[server]\nmax_request_size = 0\n").is_err());
+    }
+}
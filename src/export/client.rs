@@ -0,0 +1,126 @@
+// Export Client Specs
+//
+// Which hosts may access an export: a single address, a CIDR block, or "*".
+
+use anyhow::{anyhow, Result};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Client allowed to access an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientSpec {
+    /// Any client ("*")
+    Any,
+    /// A single address
+    Host(IpAddr),
+    /// An address block (network address, prefix length)
+    Network(IpAddr, u8),
+}
+
+impl ClientSpec {
+    /// Whether `client` is covered by this spec
+    ///
+    /// IPv4-mapped IPv6 addresses are compared as IPv4.
+    pub fn matches(&self, client: IpAddr) -> bool {
+        let client = client.to_canonical();
+        match *self {
+            ClientSpec::Any => true,
+            ClientSpec::Host(addr) => addr.to_canonical() == client,
+            ClientSpec::Network(network, prefix) => match (network.to_canonical(), client) {
+                (IpAddr::V4(network), IpAddr::V4(client)) => {
+                    prefix_matches(&network.octets(), &client.octets(), prefix)
+                }
+                (IpAddr::V6(network), IpAddr::V6(client)) => {
+                    prefix_matches(&network.octets(), &client.octets(), prefix)
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+impl FromStr for ClientSpec {
+    type Err = anyhow::Error;
+
+    /// Parse "*", "10.0.0.1", "10.0.0.0/8" or "fd00::/8"
+    fn from_str(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec == "*" {
+            return Ok(ClientSpec::Any);
+        }
+
+        match spec.split_once('/') {
+            None => {
+                let addr = spec
+                    .parse::<IpAddr>()
+                    .map_err(|_| anyhow!("Invalid client address: {}", spec))?;
+                Ok(ClientSpec::Host(addr))
+            }
+            Some((addr, prefix)) => {
+                let addr = addr
+                    .parse::<IpAddr>()
+                    .map_err(|_| anyhow!("Invalid client network: {}", spec))?;
+                let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+                let prefix = prefix
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|prefix| *prefix <= max_prefix)
+                    .ok_or_else(|| anyhow!("Invalid prefix length: {}", spec))?;
+                Ok(ClientSpec::Network(addr, prefix))
+            }
+        }
+    }
+}
+
+/// Compare the first `prefix` bits of two addresses
+fn prefix_matches(network: &[u8], client: &[u8], prefix: u8) -> bool {
+    let full_bytes = (prefix / 8) as usize;
+    let rest_bits = prefix % 8;
+
+    if network[..full_bytes] != client[..full_bytes] {
+        return false;
+    }
+    if rest_bits == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - rest_bits);
+    network[full_bytes] & mask == client[full_bytes] & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_client_specs() {
+        assert_eq!("*".parse::<ClientSpec>().unwrap(), ClientSpec::Any);
+        assert_eq!("10.0.0.1".parse::<ClientSpec>().unwrap(), ClientSpec::Host(ip("10.0.0.1")));
+        assert_eq!(
+            "192.168.0.0/16".parse::<ClientSpec>().unwrap(),
+            ClientSpec::Network(ip("192.168.0.0"), 16)
+        );
+        assert!("10.0.0.0/33".parse::<ClientSpec>().is_err());
+        assert!("not-an-ip".parse::<ClientSpec>().is_err());
+    }
+
+    #[test]
+    fn test_client_spec_matching() {
+        let network: ClientSpec = "10.1.0.0/20".parse().unwrap();
+        assert!(network.matches(ip("10.1.15.200")));
+        assert!(!network.matches(ip("10.1.16.1")));
+        assert!(network.matches(ip("::ffff:10.1.0.7")), "IPv4-mapped address");
+        assert!(!network.matches(ip("fd00::1")));
+
+        let host: ClientSpec = "192.0.2.5".parse().unwrap();
+        assert!(host.matches(ip("192.0.2.5")));
+        assert!(!host.matches(ip("192.0.2.6")));
+
+        let v6: ClientSpec = "fd00::/8".parse().unwrap();
+        assert!(v6.matches(ip("fd12::1")));
+        assert!(ClientSpec::Any.matches(ip("203.0.113.9")));
+    }
+}
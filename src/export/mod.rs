@@ -0,0 +1,647 @@
+// Export Resolution
+//
+// Maps MOUNT dirpaths and NFS file handles to the backend (FSAL) that owns
+// them. Every file handle handed to a client starts with the id of the export
+// it belongs to, so the MOUNT and NFS handle namespaces are unified per export
+// and a single server can serve several heterogeneous backends.
+//
+// Wire handle layout:
+//   bytes 0..4  export id (big-endian)
+//   bytes 4..   backend handle (the local backend's structured layout, with
+//               inode and generation, is described in fsal::handle)
+
+pub mod client;
+pub mod router;
+
+use anyhow::{anyhow, Context, Result};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::fsal::{FileHandle, FileType, Filesystem};
+use crate::protocol::v3::rpc::auth_flavor;
+use crate::rpc::auth::{Credentials, ANONYMOUS_ID};
+
+pub use client::ClientSpec;
+pub use router::ExportRouter;
+
+/// Export identifier (prefix of every wire file handle)
+pub type ExportId = u32;
+
+/// Length of the export id prefix in a file handle
+pub const EXPORT_ID_LEN: usize = 4;
+
+/// Default largest READ or WRITE (1 MiB)
+pub const DEFAULT_MAX_TRANSFER: u32 = 1024 * 1024;
+
+/// Default preferred READ or WRITE size (64 KiB)
+pub const DEFAULT_PREF_TRANSFER: u32 = 64 * 1024;
+
+/// Default preferred READDIR size (8 KiB)
+pub const DEFAULT_PREF_READDIR: u32 = 8 * 1024;
+
+/// Prefix a backend handle with its export id
+pub fn encode_handle(export_id: ExportId, backend_handle: &[u8]) -> FileHandle {
+    let mut handle = Vec::with_capacity(EXPORT_ID_LEN + backend_handle.len());
+    handle.extend_from_slice(&export_id.to_be_bytes());
+    handle.extend_from_slice(backend_handle);
+    handle
+}
+
+/// Split a wire handle into its export id and backend handle
+pub fn split_handle(handle: &[u8]) -> Option<(ExportId, &[u8])> {
+    if handle.len() <= EXPORT_ID_LEN {
+        return None;
+    }
+    let (prefix, rest) = handle.split_at(EXPORT_ID_LEN);
+    let export_id = u32::from_be_bytes(prefix.try_into().ok()?);
+    Some((export_id, rest))
+}
+
+/// Export id of the file handle that starts NFSv3 procedure arguments
+///
+/// Every NFSv3 procedure except NULL takes a file handle (nfs_fh3) as its
+/// first argument; this decodes it without parsing the rest.
+pub fn args_export_id(args_data: &[u8]) -> Option<ExportId> {
+    let len_bytes: [u8; 4] = args_data.get(..4)?.try_into().ok()?;
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    let handle = args_data.get(4..4usize.checked_add(len)?)?;
+    split_handle(handle).map(|(export_id, _)| export_id)
+}
+
+/// Identity squashing policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Squash {
+    /// Use caller identities as sent
+    None,
+    /// Map uid 0 (and gid 0) to the anonymous identity
+    #[default]
+    Root,
+    /// Map every caller to the anonymous identity
+    All,
+}
+
+/// READ, WRITE and READDIR sizes of an export, in bytes
+///
+/// FSINFO advertises them and READ and WRITE enforce the maximums, so
+/// clients are never told one limit and held to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferSizes {
+    /// Largest READ (rtmax); longer READs return short
+    pub read_max: u32,
+    /// Preferred READ size (rtpref)
+    pub read_pref: u32,
+    /// Largest WRITE (wtmax); longer WRITEs are refused with NFS3ERR_INVAL
+    pub write_max: u32,
+    /// Preferred WRITE size (wtpref)
+    pub write_pref: u32,
+    /// Preferred READDIR size (dtpref)
+    pub readdir_pref: u32,
+}
+
+impl Default for TransferSizes {
+    fn default() -> Self {
+        Self {
+            read_max: DEFAULT_MAX_TRANSFER,
+            read_pref: DEFAULT_PREF_TRANSFER,
+            write_max: DEFAULT_MAX_TRANSFER,
+            write_pref: DEFAULT_PREF_TRANSFER,
+            readdir_pref: DEFAULT_PREF_READDIR,
+        }
+    }
+}
+
+/// Modes given to objects clients create
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModePolicy {
+    /// Permission bits cleared from modes clients request
+    pub umask: u32,
+    /// Mode of a file created without a requested mode
+    pub file_mode: u32,
+    /// Mode of a directory created without a requested mode
+    pub dir_mode: u32,
+}
+
+impl Default for ModePolicy {
+    fn default() -> Self {
+        Self {
+            umask: 0,
+            file_mode: 0o644,
+            dir_mode: 0o755,
+        }
+    }
+}
+
+impl ModePolicy {
+    /// Mode of a new file: the requested mode under the umask, or the default
+    pub fn file(&self, requested: Option<u32>) -> u32 {
+        requested.map_or(self.file_mode, |mode| self.masked(mode))
+    }
+
+    /// Mode of a new directory: the requested mode under the umask, or the default
+    pub fn dir(&self, requested: Option<u32>) -> u32 {
+        requested.map_or(self.dir_mode, |mode| self.masked(mode))
+    }
+
+    /// Apply the umask to a mode a client requested
+    pub fn masked(&self, mode: u32) -> u32 {
+        mode & !self.umask
+    }
+}
+
+/// Per-export options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Identity squashing policy
+    pub squash: Squash,
+    /// uid squashed callers are mapped to
+    pub anon_uid: u32,
+    /// gid squashed callers are mapped to
+    pub anon_gid: u32,
+    /// Clients allowed to mount and access the export
+    pub clients: Vec<ClientSpec>,
+    /// Refuse modifying procedures with NFS3ERR_ROFS
+    pub read_only: bool,
+    /// RPC auth flavor numbers advertised by MNT, most preferred first
+    pub auth_flavors: Vec<i32>,
+    /// Modes of files and directories clients create
+    pub modes: ModePolicy,
+    /// fsid reported in the attributes of the export's objects; the export
+    /// id when unset
+    pub fsid: Option<u64>,
+    /// READ, WRITE and READDIR sizes advertised and enforced
+    pub transfer: TransferSizes,
+    /// Sync every WRITE and report it FILE_SYNC, even when the client asked for UNSTABLE
+    pub force_stable_writes: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            squash: Squash::default(),
+            anon_uid: ANONYMOUS_ID,
+            anon_gid: ANONYMOUS_ID,
+            clients: vec![ClientSpec::Any],
+            read_only: false,
+            auth_flavors: vec![auth_flavor::AUTH_SYS as i32, auth_flavor::AUTH_NONE as i32],
+            modes: ModePolicy::default(),
+            fsid: None,
+            transfer: TransferSizes::default(),
+            force_stable_writes: false,
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Whether `client` may access the export
+    pub fn allows(&self, client: IpAddr) -> bool {
+        self.clients.iter().any(|spec| spec.matches(client))
+    }
+
+    /// Apply the squashing policy to caller credentials
+    pub fn squash_credentials(&self, credentials: Credentials) -> Credentials {
+        let squash_all = self.squash == Squash::All;
+        let squash_root = self.squash == Squash::Root;
+
+        let mut credentials = credentials;
+        if squash_all || (squash_root && credentials.uid == 0) {
+            credentials.uid = self.anon_uid;
+        }
+        if squash_all || (squash_root && credentials.gid == 0) {
+            credentials.gid = self.anon_gid;
+        }
+        if squash_all {
+            credentials.gids.clear();
+        } else if squash_root {
+            for gid in credentials.gids.iter_mut().filter(|gid| **gid == 0) {
+                *gid = self.anon_gid;
+            }
+        }
+        credentials
+    }
+}
+
+/// Export resolver
+///
+/// Central routing abstraction: MOUNT uses it to map a dirpath to an export,
+/// the NFS layer uses it to map a handle's export prefix to a backend.
+pub trait ExportResolver: Send + Sync {
+    /// Resolve a MOUNT dirpath to an export id
+    fn resolve_path(&self, dirpath: &str) -> Option<ExportId>;
+
+    /// Get the backend serving an export
+    fn backend(&self, export_id: ExportId) -> Option<Arc<dyn Filesystem>>;
+
+    /// Export used when a handle-less operation needs a default
+    fn default_export(&self) -> Option<ExportId>;
+
+    /// Ids of all exports
+    fn export_ids(&self) -> Vec<ExportId> {
+        self.default_export().into_iter().collect()
+    }
+
+    /// Resolve a wire file handle to its export id, backend and backend handle
+    fn resolve_handle<'a>(
+        &self,
+        handle: &'a [u8],
+    ) -> Option<(ExportId, Arc<dyn Filesystem>, &'a [u8])> {
+        let (export_id, backend_handle) = split_handle(handle)?;
+        let backend = self.backend(export_id)?;
+        Some((export_id, backend, backend_handle))
+    }
+
+    /// Options of an export
+    fn options(&self, _export_id: ExportId) -> ExportOptions {
+        ExportOptions::default()
+    }
+
+    /// fsid clients see for an export's objects
+    ///
+    /// The backend's own fsid (the device number of a local export) is
+    /// shared by exports of one device, and clients would merge their
+    /// caches. Each export instead reports its configured fsid or its id,
+    /// which stays the same across restarts as long as the configuration
+    /// lists the exports in the same order.
+    fn fsid(&self, export_id: ExportId) -> u64 {
+        self.options(export_id).fsid.unwrap_or(export_id as u64)
+    }
+
+    /// Wire root handle of an export
+    fn root_handle(&self, export_id: ExportId) -> Option<FileHandle> {
+        let backend = self.backend(export_id)?;
+        Some(encode_handle(export_id, &backend.root_handle()))
+    }
+
+    /// File handles tracked by all backends together
+    fn handle_count(&self) -> usize {
+        0
+    }
+}
+
+/// A single export: a dirpath served by a backend
+pub struct Export {
+    /// Export id (handle prefix)
+    pub id: ExportId,
+    /// Path clients pass to MOUNT
+    pub path: String,
+    /// Backend serving the export
+    pub filesystem: Arc<dyn Filesystem>,
+    /// Backend handle of the directory clients see as the export root
+    pub root: FileHandle,
+    /// Export options
+    pub options: ExportOptions,
+}
+
+/// Static table of exports
+#[derive(Default)]
+pub struct ExportTable {
+    exports: Vec<Export>,
+}
+
+impl ExportTable {
+    /// Create an empty export table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a table with a single export
+    pub fn single(path: &str, filesystem: Arc<dyn Filesystem>) -> Self {
+        let mut table = Self::new();
+        table.add(path, filesystem);
+        table
+    }
+
+    /// Add an export, returning its id
+    ///
+    /// Ids are assigned sequentially starting at 1.
+    pub fn add(&mut self, path: &str, filesystem: Arc<dyn Filesystem>) -> ExportId {
+        self.add_with_options(path, filesystem, ExportOptions::default())
+    }
+
+    /// Add an export with explicit options, returning its id
+    pub fn add_with_options(
+        &mut self,
+        path: &str,
+        filesystem: Arc<dyn Filesystem>,
+        options: ExportOptions,
+    ) -> ExportId {
+        let root = filesystem.root_handle();
+        self.push(path, filesystem, root, options)
+    }
+
+    /// Add an export of a directory below the backend root, returning its id
+    ///
+    /// The subpath is resolved by the backend's `handle_for_path`, so its
+    /// own traversal checks apply and it cannot name anything outside the
+    /// backend root. It must name a directory; an empty subpath exports the
+    /// backend root.
+    ///
+    /// # Arguments
+    /// * `path` - Path clients pass to MOUNT
+    /// * `filesystem` - Backend serving the export
+    /// * `subpath` - Directory below the backend root, relative to it
+    /// * `options` - Export options
+    pub fn add_subtree(
+        &mut self,
+        path: &str,
+        filesystem: Arc<dyn Filesystem>,
+        subpath: &str,
+        options: ExportOptions,
+    ) -> Result<ExportId> {
+        let (root, attrs) = filesystem
+            .handle_for_path(subpath)
+            .with_context(|| format!("Export subpath {:?} cannot be resolved", subpath))?;
+        if attrs.ftype != FileType::Directory {
+            return Err(anyhow!("Export subpath {:?} is not a directory", subpath));
+        }
+        Ok(self.push(path, filesystem, root, options))
+    }
+
+    /// Append an export rooted at `root`, assigning the next id
+    fn push(
+        &mut self,
+        path: &str,
+        filesystem: Arc<dyn Filesystem>,
+        root: FileHandle,
+        options: ExportOptions,
+    ) -> ExportId {
+        let id = self.exports.len() as ExportId + 1;
+        self.exports.push(Export {
+            id,
+            path: normalize_path(path),
+            filesystem,
+            root,
+            options,
+        });
+        id
+    }
+
+    /// All exports
+    pub fn exports(&self) -> &[Export] {
+        &self.exports
+    }
+}
+
+impl ExportResolver for ExportTable {
+    fn resolve_path(&self, dirpath: &str) -> Option<ExportId> {
+        let dirpath = normalize_path(dirpath);
+        self.exports
+            .iter()
+            .find(|export| export.path == dirpath)
+            .map(|export| export.id)
+    }
+
+    fn backend(&self, export_id: ExportId) -> Option<Arc<dyn Filesystem>> {
+        self.exports
+            .iter()
+            .find(|export| export.id == export_id)
+            .map(|export| export.filesystem.clone())
+    }
+
+    fn default_export(&self) -> Option<ExportId> {
+        self.exports.first().map(|export| export.id)
+    }
+
+    fn export_ids(&self) -> Vec<ExportId> {
+        self.exports.iter().map(|export| export.id).collect()
+    }
+
+    fn options(&self, export_id: ExportId) -> ExportOptions {
+        self.exports
+            .iter()
+            .find(|export| export.id == export_id)
+            .map(|export| export.options.clone())
+            .unwrap_or_default()
+    }
+
+    fn fsid(&self, export_id: ExportId) -> u64 {
+        self.exports
+            .iter()
+            .find(|export| export.id == export_id)
+            .and_then(|export| export.options.fsid)
+            .unwrap_or(export_id as u64)
+    }
+
+    fn root_handle(&self, export_id: ExportId) -> Option<FileHandle> {
+        self.exports
+            .iter()
+            .find(|export| export.id == export_id)
+            .map(|export| encode_handle(export.id, &export.root))
+    }
+
+    fn handle_count(&self) -> usize {
+        self.exports.iter().map(|export| export.filesystem.handle_count()).sum()
+    }
+}
+
+/// Normalize a dirpath for comparison (no trailing slash, except for "/")
+fn normalize_path(path: &str) -> String {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        "/".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsal::{LocalFilesystem, MemoryFilesystem, StableHow};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_handle_encoding() {
+        let handle = encode_handle(7, &[1, 2, 3]);
+        assert_eq!(handle, vec![0, 0, 0, 7, 1, 2, 3]);
+        assert_eq!(split_handle(&handle), Some((7, &[1u8, 2, 3][..])));
+        assert_eq!(split_handle(&[0, 0, 0, 7]), None, "Empty backend handle is invalid");
+    }
+
+    #[test]
+    fn test_squash_credentials() {
+        let root = Credentials {
+            uid: 0,
+            gid: 0,
+            gids: vec![0, 10],
+            ..Credentials::anonymous()
+        };
+        let user = Credentials {
+            uid: 1000,
+            gid: 1000,
+            gids: vec![10],
+            ..Credentials::anonymous()
+        };
+        let options = |squash| ExportOptions {
+            squash,
+            anon_uid: 4242,
+            anon_gid: 4343,
+            ..ExportOptions::default()
+        };
+
+        let squashed = options(Squash::Root).squash_credentials(root.clone());
+        assert_eq!((squashed.uid, squashed.gid, squashed.gids), (4242, 4343, vec![4343, 10]));
+        assert_eq!(options(Squash::Root).squash_credentials(user.clone()), user);
+
+        let squashed = options(Squash::All).squash_credentials(user.clone());
+        assert_eq!((squashed.uid, squashed.gid, squashed.gids.len()), (4242, 4343, 0));
+
+        assert_eq!(options(Squash::None).squash_credentials(root.clone()), root);
+        assert_eq!(ExportOptions::default().squash, Squash::Root);
+    }
+
+    #[test]
+    fn test_args_export_id() {
+        let handle = encode_handle(3, &[9, 9, 9, 9]);
+        let mut args = (handle.len() as u32).to_be_bytes().to_vec();
+        args.extend_from_slice(&handle);
+        args.extend_from_slice(&[0, 0, 0, 1]);
+        assert_eq!(args_export_id(&args), Some(3));
+        assert_eq!(args_export_id(&[]), None);
+        assert_eq!(args_export_id(&[0, 0, 0, 64, 1]), None, "Truncated handle");
+    }
+
+    #[test]
+    fn test_path_resolution() {
+        let mut table = ExportTable::new();
+        let id = table.add("/export/", Arc::new(MemoryFilesystem::new()));
+
+        assert_eq!(table.resolve_path("/export"), Some(id));
+        assert_eq!(table.resolve_path("/export/"), Some(id));
+        assert_eq!(table.resolve_path("/other"), None);
+    }
+
+    #[test]
+    fn test_local_and_memory_exports_route_to_own_backend() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut table = ExportTable::new();
+        let local_id = table.add("/local", Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap()));
+        let memory_id = table.add("/memory", Arc::new(MemoryFilesystem::new()));
+        let table: Arc<dyn ExportResolver> = Arc::new(table);
+        let router = ExportRouter::new(table.clone());
+
+        // Mount each export
+        let local_root = table.root_handle(table.resolve_path("/local").unwrap()).unwrap();
+        let memory_root = table.root_handle(table.resolve_path("/memory").unwrap()).unwrap();
+        assert_eq!(split_handle(&local_root).unwrap().0, local_id);
+        assert_eq!(split_handle(&memory_root).unwrap().0, memory_id);
+
+        // Create a file through each root
+        let local_file = router.create(&local_root, "local.txt", 0o644).unwrap();
+        let memory_file = router.create(&memory_root, "memory.txt", 0o644).unwrap();
+        router.write(&memory_file, 0, b"in memory", StableHow::FileSync).unwrap();
+
+        // Handles carry their export prefix
+        assert_eq!(split_handle(&local_file).unwrap().0, local_id);
+        assert_eq!(split_handle(&memory_file).unwrap().0, memory_id);
+
+        // Only the local export touches disk
+        assert!(temp_dir.path().join("local.txt").exists());
+        assert!(!temp_dir.path().join("memory.txt").exists());
+
+        // Each handle resolves against its own backend
+        assert_eq!(router.read(&memory_file, 0, 100).unwrap(), &b"in memory"[..]);
+        assert!(router.lookup(&local_root, "memory.txt").is_err());
+        assert!(router.lookup(&memory_root, "local.txt").is_err());
+        assert_eq!(router.lookup(&memory_root, "memory.txt").unwrap(), memory_file);
+    }
+
+    #[test]
+    fn test_subtree_must_be_a_directory_inside_the_backend() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("export");
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/file.txt"), b"not a directory").unwrap();
+        std::os::unix::fs::symlink(temp_dir.path(), root.join("escape")).unwrap();
+        let fs: Arc<dyn Filesystem> = Arc::new(LocalFilesystem::new(&root).unwrap());
+
+        let mut table = ExportTable::new();
+        let id = table.add_subtree("/b", fs.clone(), "/a/./b/", ExportOptions::default()).unwrap();
+        let b_handle = fs.lookup(&fs.lookup(&fs.root_handle(), "a").unwrap(), "b").unwrap();
+        assert_eq!(table.root_handle(id), Some(encode_handle(id, &b_handle)));
+
+        // An empty subpath is the backend root
+        let id = table.add_subtree("/all", fs.clone(), "", ExportOptions::default()).unwrap();
+        assert_eq!(table.root_handle(id), Some(encode_handle(id, &fs.root_handle())));
+
+        for subpath in ["..", "a/../..", "escape", "escape/export", "a/file.txt", "missing"] {
+            assert!(
+                table.add_subtree("/bad", fs.clone(), subpath, ExportOptions::default()).is_err(),
+                "subpath {:?} should be refused",
+                subpath
+            );
+        }
+        assert_eq!(table.exports().len(), 2);
+    }
+
+    #[test]
+    fn test_exports_on_one_device_report_distinct_fsids() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("sub/file"), b"data").unwrap();
+        let fs: Arc<dyn Filesystem> = Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap());
+        let mut table = ExportTable::new();
+        let whole_id = table.add("/whole", fs.clone());
+        let sub_id = table.add_subtree("/sub", fs.clone(), "sub", ExportOptions::default()).unwrap();
+        let pinned_id = table.add_with_options(
+            "/pinned",
+            fs.clone(),
+            ExportOptions {
+                fsid: Some(0x5eed),
+                ..Default::default()
+            },
+        );
+        let table: Arc<dyn ExportResolver> = Arc::new(table);
+        let router = ExportRouter::new(table.clone());
+
+        let fsid = |export_id| router.getattr(&table.root_handle(export_id).unwrap()).unwrap().fsid;
+        assert_eq!(fsid(whole_id), whole_id as u64);
+        assert_eq!(fsid(sub_id), sub_id as u64);
+        assert_eq!(fsid(pinned_id), 0x5eed);
+
+        // Objects below a root, and READDIRPLUS attributes, carry it too
+        let sub_root = table.root_handle(sub_id).unwrap();
+        let file = router.lookup(&sub_root, "file").unwrap();
+        assert_eq!(router.getattr(&file).unwrap().fsid, sub_id as u64);
+        let (entries, _) = router.readdir_plus(&sub_root, 0, 16, 65536).unwrap();
+        assert_eq!(entries[0].attributes.as_ref().unwrap().fsid, sub_id as u64);
+    }
+
+    #[test]
+    fn test_memory_exports_do_not_share_handles() {
+        let mut table = ExportTable::new();
+        let first_id = table.add("/first", Arc::new(MemoryFilesystem::new()));
+        let second_id = table.add("/second", Arc::new(MemoryFilesystem::new()));
+        let table: Arc<dyn ExportResolver> = Arc::new(table);
+        let router = ExportRouter::new(table.clone());
+
+        let first_root = table.root_handle(first_id).unwrap();
+        let second_root = table.root_handle(second_id).unwrap();
+        assert_ne!(first_root, second_root);
+
+        // Both backends number their objects alike, so the backend handles
+        // of the two files are identical; only the export prefix differs
+        let first_file = router.create(&first_root, "file", 0o644).unwrap();
+        let second_file = router.create(&second_root, "file", 0o644).unwrap();
+        assert_eq!(split_handle(&first_file).unwrap().1, split_handle(&second_file).unwrap().1);
+        assert_ne!(first_file, second_file);
+
+        router.write(&first_file, 0, b"first", StableHow::FileSync).unwrap();
+        router.write(&second_file, 0, b"second export", StableHow::FileSync).unwrap();
+        assert_eq!(router.read(&first_file, 0, 64).unwrap(), &b"first"[..]);
+        assert_eq!(router.read(&second_file, 0, 64).unwrap(), &b"second export"[..]);
+        assert_eq!(router.lookup(&second_root, "file").unwrap(), second_file);
+
+        // Operations spanning two exports are refused
+        let cross = router.rename(&first_root, "file", &second_root, "moved").unwrap_err();
+        assert_eq!(crate::fsal::error::errno_of(&cross), Some(libc::EXDEV));
+        assert!(router.link(&first_file, &second_root, "linked").is_err());
+
+        // Each export reports its own fsid
+        assert_eq!(router.getattr(&first_root).unwrap().fsid, first_id as u64);
+        assert_eq!(router.getattr(&second_file).unwrap().fsid, second_id as u64);
+
+        // A handle for an export that does not exist resolves nowhere
+        let unknown = encode_handle(99, split_handle(&first_file).unwrap().1);
+        assert!(router.getattr(&unknown).is_err());
+    }
+}
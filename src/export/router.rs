@@ -0,0 +1,309 @@
+// Export Router
+//
+// A Filesystem implementation that dispatches every operation to the backend
+// owning the handle's export, translating between wire handles (export id
+// prefix + backend handle) and backend handles.
+
+use anyhow::Result;
+use bytes::Bytes;
+use std::io;
+use std::sync::Arc;
+
+use super::{encode_handle, split_handle, ExportId, ExportResolver};
+use crate::fsal::{
+    Acl, DirEntry, DirEntryPlus, FileAttributes, FileHandle, FileTime, FileType, Filesystem, FsStats, FsalError,
+    SetAttributes, SetTime, StableHow,
+};
+
+/// Routes filesystem operations to per-export backends
+pub struct ExportRouter {
+    resolver: Arc<dyn ExportResolver>,
+}
+
+impl ExportRouter {
+    /// Create a router over an export resolver
+    pub fn new(resolver: Arc<dyn ExportResolver>) -> Self {
+        Self { resolver }
+    }
+
+    /// The resolver backing this router
+    pub fn resolver(&self) -> &Arc<dyn ExportResolver> {
+        &self.resolver
+    }
+
+    /// Resolve a wire handle to (export id, backend, backend handle)
+    fn route(&self, handle: &FileHandle) -> Result<(ExportId, Arc<dyn Filesystem>, FileHandle)> {
+        self.resolver
+            .resolve_handle(handle)
+            .map(|(export_id, backend, backend_handle)| (export_id, backend, backend_handle.to_vec()))
+            .ok_or_else(|| {
+                let error = match split_handle(handle) {
+                    None => FsalError::bad_handle(format!("{} bytes, too short for an export id", handle.len())),
+                    Some((export_id, _)) => FsalError::stale(format!("export {} does not exist", export_id)),
+                };
+                error.into()
+            })
+    }
+
+    /// Resolve two handles that must belong to the same export
+    fn route_pair(
+        &self,
+        first: &FileHandle,
+        second: &FileHandle,
+    ) -> Result<(ExportId, Arc<dyn Filesystem>, FileHandle, FileHandle)> {
+        let (export_id, backend, first) = self.route(first)?;
+        let (other_id, _, second) = self.route(second)?;
+        if export_id != other_id {
+            return Err(io::Error::from_raw_os_error(libc::EXDEV).into());
+        }
+        Ok((export_id, backend, first, second))
+    }
+}
+
+impl Filesystem for ExportRouter {
+    fn root_handle(&self) -> FileHandle {
+        self.resolver
+            .default_export()
+            .and_then(|export_id| self.resolver.root_handle(export_id))
+            .unwrap_or_default()
+    }
+
+    fn lookup(&self, dir_handle: &FileHandle, name: &str) -> Result<FileHandle> {
+        let (export_id, backend, dir) = self.route(dir_handle)?;
+        let handle = backend.lookup(&dir, name)?;
+        Ok(encode_handle(export_id, &handle))
+    }
+
+    fn parent_handle(&self, dir_handle: &FileHandle) -> Result<FileHandle> {
+        let (export_id, backend, dir) = self.route(dir_handle)?;
+        // A subtree export's root is its own parent, like the backend root
+        if self.resolver.root_handle(export_id).as_ref() == Some(dir_handle) {
+            return Ok(dir_handle.clone());
+        }
+        let handle = backend.parent_handle(&dir)?;
+        Ok(encode_handle(export_id, &handle))
+    }
+
+    fn getattr(&self, handle: &FileHandle) -> Result<FileAttributes> {
+        let (export_id, backend, handle) = self.route(handle)?;
+        let mut attrs = backend.getattr(&handle)?;
+        attrs.fsid = self.resolver.fsid(export_id);
+        Ok(attrs)
+    }
+
+    fn read(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<Bytes> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.read(&handle, offset, count)
+    }
+
+    fn read_into(&self, handle: &FileHandle, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.read_into(&handle, offset, buf)
+    }
+
+    fn readdir(&self, dir_handle: &FileHandle, cookie: u64, count: u32) -> Result<(Vec<DirEntry>, bool)> {
+        let (_, backend, dir) = self.route(dir_handle)?;
+        backend.readdir(&dir, cookie, count)
+    }
+
+    fn readdir_plus(
+        &self,
+        dir_handle: &FileHandle,
+        cookie: u64,
+        dircount: u32,
+        maxcount: u32,
+    ) -> Result<(Vec<DirEntryPlus>, bool)> {
+        let (export_id, backend, dir) = self.route(dir_handle)?;
+        let (mut entries, eof) = backend.readdir_plus(&dir, cookie, dircount, maxcount)?;
+        let fsid = self.resolver.fsid(export_id);
+        for entry in &mut entries {
+            entry.handle = entry.handle.take().map(|handle| encode_handle(export_id, &handle));
+            if let Some(attrs) = &mut entry.attributes {
+                attrs.fsid = fsid;
+            }
+        }
+        Ok((entries, eof))
+    }
+
+    fn write(&self, handle: &FileHandle, offset: u64, data: &[u8], stable: StableHow) -> Result<u32> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.write(&handle, offset, data, stable)
+    }
+
+    fn setattr_size(&self, handle: &FileHandle, size: u64) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.setattr_size(&handle, size)
+    }
+
+    fn setattr_mode(&self, handle: &FileHandle, mode: u32) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.setattr_mode(&handle, mode)
+    }
+
+    fn setattr_owner(&self, handle: &FileHandle, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.setattr_owner(&handle, uid, gid)
+    }
+
+    fn setattr_times(&self, handle: &FileHandle, atime: Option<SetTime>, mtime: Option<SetTime>) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.setattr_times(&handle, atime, mtime)
+    }
+
+    fn setattr(&self, handle: &FileHandle, attrs: &SetAttributes, guard: Option<FileTime>) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.setattr(&handle, attrs, guard)
+    }
+
+    fn create(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        let (export_id, backend, dir) = self.route(dir_handle)?;
+        let handle = backend.create(&dir, name, mode)?;
+        Ok(encode_handle(export_id, &handle))
+    }
+
+    fn create_guarded(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        let (export_id, backend, dir) = self.route(dir_handle)?;
+        let handle = backend.create_guarded(&dir, name, mode)?;
+        Ok(encode_handle(export_id, &handle))
+    }
+
+    fn remove(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
+        let (_, backend, dir) = self.route(dir_handle)?;
+        backend.remove(&dir, name)
+    }
+
+    fn mkdir(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        let (export_id, backend, dir) = self.route(dir_handle)?;
+        let handle = backend.mkdir(&dir, name, mode)?;
+        Ok(encode_handle(export_id, &handle))
+    }
+
+    fn rmdir(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
+        let (_, backend, dir) = self.route(dir_handle)?;
+        backend.rmdir(&dir, name)
+    }
+
+    fn rename(
+        &self,
+        from_dir_handle: &FileHandle,
+        from_name: &str,
+        to_dir_handle: &FileHandle,
+        to_name: &str,
+    ) -> Result<()> {
+        let (_, backend, from_dir, to_dir) = self.route_pair(from_dir_handle, to_dir_handle)?;
+        backend.rename(&from_dir, from_name, &to_dir, to_name)
+    }
+
+    fn symlink(&self, dir_handle: &FileHandle, name: &str, target: &str) -> Result<FileHandle> {
+        let (export_id, backend, dir) = self.route(dir_handle)?;
+        let handle = backend.symlink(&dir, name, target)?;
+        Ok(encode_handle(export_id, &handle))
+    }
+
+    fn readlink(&self, handle: &FileHandle) -> Result<String> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.readlink(&handle)
+    }
+
+    fn link(&self, file_handle: &FileHandle, dir_handle: &FileHandle, name: &str) -> Result<FileHandle> {
+        let (export_id, backend, file, dir) = self.route_pair(file_handle, dir_handle)?;
+        let handle = backend.link(&file, &dir, name)?;
+        Ok(encode_handle(export_id, &handle))
+    }
+
+    fn commit(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.commit(&handle, offset, count)
+    }
+
+    fn issued_handles(&self) -> Vec<(FileHandle, String)> {
+        // A backend serving several exports is listed once, under the first
+        let mut listed: Vec<Arc<dyn Filesystem>> = Vec::new();
+        let mut handles = Vec::new();
+        for export_id in self.resolver.export_ids() {
+            let Some(backend) = self.resolver.backend(export_id) else {
+                continue;
+            };
+            if listed.iter().any(|seen| Arc::ptr_eq(seen, &backend)) {
+                continue;
+            }
+            handles.extend(
+                backend
+                    .issued_handles()
+                    .into_iter()
+                    .map(|(handle, path)| (encode_handle(export_id, &handle), path)),
+            );
+            listed.push(backend);
+        }
+        handles
+    }
+
+    fn invalidate_handle(&self, handle: &FileHandle) -> bool {
+        self.route(handle)
+            .is_ok_and(|(_, backend, handle)| backend.invalidate_handle(&handle))
+    }
+
+    fn flush(&self) -> Result<usize> {
+        // Exports sharing a backend are flushed once; a second flush of it
+        // finds nothing left to sync
+        let mut flushed = 0;
+        for export_id in self.resolver.export_ids() {
+            if let Some(backend) = self.resolver.backend(export_id) {
+                flushed += backend.flush()?;
+            }
+        }
+        Ok(flushed)
+    }
+
+    fn mknod(
+        &self,
+        dir_handle: &FileHandle,
+        name: &str,
+        file_type: FileType,
+        mode: u32,
+        rdev: (u32, u32),
+    ) -> Result<FileHandle> {
+        let (export_id, backend, dir) = self.route(dir_handle)?;
+        let handle = backend.mknod(&dir, name, file_type, mode, rdev)?;
+        Ok(encode_handle(export_id, &handle))
+    }
+
+    fn get_xattr(&self, handle: &FileHandle, name: &str) -> Result<Option<Vec<u8>>> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.get_xattr(&handle, name)
+    }
+
+    fn set_xattr(&self, handle: &FileHandle, name: &str, value: &[u8]) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.set_xattr(&handle, name, value)
+    }
+
+    fn list_xattr(&self, handle: &FileHandle) -> Result<Vec<String>> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.list_xattr(&handle)
+    }
+
+    fn remove_xattr(&self, handle: &FileHandle, name: &str) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.remove_xattr(&handle, name)
+    }
+
+    fn get_acl(&self, handle: &FileHandle) -> Result<Option<Acl>> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.get_acl(&handle)
+    }
+
+    fn set_acl(&self, handle: &FileHandle, acl: &Acl) -> Result<()> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.set_acl(&handle, acl)
+    }
+
+    fn statfs(&self, handle: &FileHandle) -> Result<FsStats> {
+        let (_, backend, handle) = self.route(handle)?;
+        backend.statfs(&handle)
+    }
+
+    fn handle_count(&self) -> usize {
+        self.resolver.handle_count()
+    }
+}
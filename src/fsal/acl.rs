@@ -0,0 +1,314 @@
+// POSIX Access Control Lists
+//
+// Parsed form of a POSIX.1e access ACL and its Linux xattr encoding
+// ("system.posix_acl_access"), plus the permission check the kernel applies.
+// Backends store ACLs as that xattr, so no libacl is needed.
+
+use anyhow::{anyhow, Result};
+
+/// Extended attribute holding a file's access ACL
+pub const ACL_ACCESS_XATTR: &str = "system.posix_acl_access";
+
+/// Version of the xattr encoding
+const ACL_XATTR_VERSION: u32 = 2;
+
+/// Id stored for entries that do not name a user or group
+const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+// Tag values of the xattr encoding
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+/// Permission bits of an ACL entry
+pub const ACL_READ: u32 = 0o4;
+pub const ACL_WRITE: u32 = 0o2;
+pub const ACL_EXECUTE: u32 = 0o1;
+
+/// Whom an ACL entry applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclTag {
+    /// The file owner
+    UserObj,
+    /// A named user
+    User(u32),
+    /// The owning group
+    GroupObj,
+    /// A named group
+    Group(u32),
+    /// Upper bound for named users and all groups
+    Mask,
+    /// Everyone else
+    Other,
+}
+
+/// A single ACL entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclEntry {
+    /// Whom the entry applies to
+    pub tag: AclTag,
+    /// rwx bits (`ACL_READ`, `ACL_WRITE`, `ACL_EXECUTE`)
+    pub perm: u32,
+}
+
+/// A POSIX access ACL
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    /// Entries in the order they were given
+    pub entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// The minimal ACL equivalent to a file mode
+    pub fn from_mode(mode: u32) -> Self {
+        Self {
+            entries: vec![
+                AclEntry { tag: AclTag::UserObj, perm: (mode >> 6) & 0o7 },
+                AclEntry { tag: AclTag::GroupObj, perm: (mode >> 3) & 0o7 },
+                AclEntry { tag: AclTag::Other, perm: mode & 0o7 },
+            ],
+        }
+    }
+
+    /// Add an entry (builder style)
+    pub fn with_entry(mut self, tag: AclTag, perm: u32) -> Self {
+        self.entries.push(AclEntry { tag, perm: perm & 0o7 });
+        self
+    }
+
+    /// Apply a chmod to the ACL, as the kernel's posix_acl_chmod does
+    ///
+    /// The owner and other entries take the owner and other bits; the mask,
+    /// or the owning group without one, takes the group bits. Named entries
+    /// keep their permissions, still limited by the mask.
+    pub fn chmod(&mut self, mode: u32) {
+        let has_mask = self.entries.iter().any(|e| e.tag == AclTag::Mask);
+        for entry in &mut self.entries {
+            match entry.tag {
+                AclTag::UserObj => entry.perm = (mode >> 6) & 0o7,
+                AclTag::Mask => entry.perm = (mode >> 3) & 0o7,
+                AclTag::GroupObj if !has_mask => entry.perm = (mode >> 3) & 0o7,
+                AclTag::Other => entry.perm = mode & 0o7,
+                _ => {}
+            }
+        }
+    }
+
+    /// Check the structure rules of POSIX.1e
+    ///
+    /// Exactly one owner, owning group and other entry; no duplicate named
+    /// entries; and a mask whenever named entries are present.
+    pub fn validate(&self) -> Result<()> {
+        let count = |wanted: AclTag| self.entries.iter().filter(|e| e.tag == wanted).count();
+        for required in [AclTag::UserObj, AclTag::GroupObj, AclTag::Other] {
+            if count(required) != 1 {
+                return Err(anyhow!("ACL needs exactly one {:?} entry", required));
+            }
+        }
+        if count(AclTag::Mask) > 1 {
+            return Err(anyhow!("ACL has more than one mask entry"));
+        }
+
+        let mut named = 0;
+        for (index, entry) in self.entries.iter().enumerate() {
+            if let AclTag::User(_) | AclTag::Group(_) = entry.tag {
+                named += 1;
+                if self.entries[..index].iter().any(|e| e.tag == entry.tag) {
+                    return Err(anyhow!("ACL has duplicate entry {:?}", entry.tag));
+                }
+            }
+        }
+        if named > 0 && count(AclTag::Mask) == 0 {
+            return Err(anyhow!("ACL with named entries needs a mask entry"));
+        }
+        Ok(())
+    }
+
+    /// Whether the ACL says more than the mode bits can
+    pub fn is_extended(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|e| matches!(e.tag, AclTag::User(_) | AclTag::Group(_) | AclTag::Mask))
+    }
+
+    /// rwx bits granted to a caller by the POSIX access check algorithm
+    ///
+    /// # Arguments
+    /// * `uid` - Caller's uid
+    /// * `in_group` - Whether the caller is a member of a gid
+    /// * `owner` - File owner uid
+    /// * `group` - File owning gid
+    pub fn permissions(&self, uid: u32, in_group: impl Fn(u32) -> bool, owner: u32, group: u32) -> u32 {
+        let perm_of = |wanted: AclTag| self.entries.iter().find(|e| e.tag == wanted).map(|e| e.perm);
+        let mask = perm_of(AclTag::Mask).unwrap_or(0o7);
+
+        if uid == owner {
+            return perm_of(AclTag::UserObj).unwrap_or(0);
+        }
+        if let Some(perm) = perm_of(AclTag::User(uid)) {
+            return perm & mask;
+        }
+
+        // Every matching group entry counts; matching any of them means
+        // "other" no longer applies, even if they grant nothing
+        let mut group_perm = None;
+        for entry in &self.entries {
+            let matches = match entry.tag {
+                AclTag::GroupObj => in_group(group),
+                AclTag::Group(gid) => in_group(gid),
+                _ => false,
+            };
+            if matches {
+                group_perm = Some(group_perm.unwrap_or(0) | entry.perm);
+            }
+        }
+        if let Some(perm) = group_perm {
+            return perm & mask;
+        }
+
+        perm_of(AclTag::Other).unwrap_or(0)
+    }
+
+    /// Decode the Linux xattr representation
+    pub fn from_xattr(bytes: &[u8]) -> Result<Self> {
+        let word = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        if bytes.len() < 4 || !(bytes.len() - 4).is_multiple_of(8) {
+            return Err(anyhow!("Malformed ACL xattr ({} bytes)", bytes.len()));
+        }
+        if word(0) != ACL_XATTR_VERSION {
+            return Err(anyhow!("Unsupported ACL xattr version {}", word(0)));
+        }
+
+        let entries = bytes[4..]
+            .chunks_exact(8)
+            .map(|entry| {
+                let tag = u16::from_le_bytes([entry[0], entry[1]]);
+                let perm = u16::from_le_bytes([entry[2], entry[3]]) as u32;
+                let id = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+                let tag = match tag {
+                    ACL_USER_OBJ => AclTag::UserObj,
+                    ACL_USER => AclTag::User(id),
+                    ACL_GROUP_OBJ => AclTag::GroupObj,
+                    ACL_GROUP => AclTag::Group(id),
+                    ACL_MASK => AclTag::Mask,
+                    ACL_OTHER => AclTag::Other,
+                    other => return Err(anyhow!("Unknown ACL tag {:#x}", other)),
+                };
+                Ok(AclEntry { tag, perm })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Encode as the Linux xattr representation
+    pub fn to_xattr(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + 8 * self.entries.len());
+        bytes.extend_from_slice(&ACL_XATTR_VERSION.to_le_bytes());
+
+        // The kernel requires entries sorted by tag, then id
+        let mut entries: Vec<(u16, u32, u32)> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let (tag, id) = match entry.tag {
+                    AclTag::UserObj => (ACL_USER_OBJ, ACL_UNDEFINED_ID),
+                    AclTag::User(uid) => (ACL_USER, uid),
+                    AclTag::GroupObj => (ACL_GROUP_OBJ, ACL_UNDEFINED_ID),
+                    AclTag::Group(gid) => (ACL_GROUP, gid),
+                    AclTag::Mask => (ACL_MASK, ACL_UNDEFINED_ID),
+                    AclTag::Other => (ACL_OTHER, ACL_UNDEFINED_ID),
+                };
+                (tag, id, entry.perm)
+            })
+            .collect();
+        entries.sort_unstable_by_key(|&(tag, id, _)| (tag, id));
+
+        for (tag, id, perm) in entries {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&(perm as u16).to_le_bytes());
+            bytes.extend_from_slice(&id.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Acl {
+        Acl::from_mode(0o640)
+            .with_entry(AclTag::User(1001), ACL_READ | ACL_WRITE)
+            .with_entry(AclTag::Group(2002), ACL_READ)
+            .with_entry(AclTag::Mask, ACL_READ)
+    }
+
+    #[test]
+    fn test_xattr_round_trip() {
+        let acl = sample();
+        acl.validate().unwrap();
+
+        let decoded = Acl::from_xattr(&acl.to_xattr()).unwrap();
+        // Decoding yields the kernel's sorted order
+        assert_eq!(decoded.entries.len(), acl.entries.len());
+        for entry in &acl.entries {
+            assert!(decoded.entries.contains(entry), "{:?} lost", entry);
+        }
+
+        assert!(Acl::from_xattr(&[2, 0, 0, 0, 1]).is_err());
+        assert!(Acl::from_xattr(&[1, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_permissions() {
+        let acl = sample();
+        let no_groups = |_: u32| false;
+
+        // Owner entry, unaffected by the mask
+        assert_eq!(acl.permissions(500, no_groups, 500, 600), ACL_READ | ACL_WRITE);
+        // Named user, limited by the mask
+        assert_eq!(acl.permissions(1001, no_groups, 500, 600), ACL_READ);
+        // Named group
+        assert_eq!(acl.permissions(7, |gid| gid == 2002, 500, 600), ACL_READ);
+        // Owning group
+        assert_eq!(acl.permissions(7, |gid| gid == 600, 500, 600), ACL_READ);
+        // Everyone else
+        assert_eq!(acl.permissions(7, no_groups, 500, 600), 0);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Acl::from_mode(0o644).validate().is_ok());
+        assert!(!Acl::from_mode(0o644).is_extended());
+        assert!(sample().is_extended());
+
+        // Named entries without a mask
+        assert!(Acl::from_mode(0o644).with_entry(AclTag::User(1), ACL_READ).validate().is_err());
+        // Missing other
+        assert!(Acl::default().with_entry(AclTag::UserObj, 7).with_entry(AclTag::GroupObj, 7).validate().is_err());
+        // Duplicate named user
+        let duplicate = sample().with_entry(AclTag::User(1001), ACL_READ);
+        assert!(duplicate.validate().is_err());
+    }
+
+    #[test]
+    fn test_chmod() {
+        // With a mask, the group bits go to the mask and named entries stay
+        let mut acl = sample();
+        acl.chmod(0o750);
+        let perm_of = |acl: &Acl, tag| acl.entries.iter().find(|e| e.tag == tag).unwrap().perm;
+        assert_eq!(perm_of(&acl, AclTag::UserObj), 0o7);
+        assert_eq!(perm_of(&acl, AclTag::Mask), 0o5);
+        assert_eq!(perm_of(&acl, AclTag::Other), 0);
+        assert_eq!(perm_of(&acl, AclTag::GroupObj), perm_of(&sample(), AclTag::GroupObj));
+        assert_eq!(perm_of(&acl, AclTag::User(1001)), perm_of(&sample(), AclTag::User(1001)));
+
+        // Without one, they go to the owning group
+        let mut minimal = Acl::from_mode(0o600);
+        minimal.chmod(0o640);
+        assert_eq!(minimal, Acl::from_mode(0o640));
+    }
+}
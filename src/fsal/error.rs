@@ -0,0 +1,119 @@
+// FSAL Error Types
+//
+// Structured errors returned by filesystem backends. They travel inside
+// anyhow::Error, so handlers can downcast to recover details (errno, the
+// operation that failed) while still sending only a status code on the wire.
+
+use std::io;
+use thiserror::Error;
+
+/// Structured FSAL error
+#[derive(Debug, Error)]
+pub enum FsalError {
+    /// An I/O error from the backend, with the operation context that hit it
+    #[error("{context}: {source}")]
+    Io {
+        /// What the backend was doing (operation and path)
+        context: String,
+        /// Underlying OS error
+        #[source]
+        source: io::Error,
+    },
+
+    /// A guarded SETATTR found a ctime different from the client's guard
+    #[error("ctime does not match SETATTR guard")]
+    NotSync,
+
+    /// The handle is malformed (wrong length or layout) or fails its
+    /// consistency checks, so it can never have been issued as is
+    #[error("Invalid file handle: {reason}")]
+    BadHandle {
+        /// What is wrong with the handle
+        reason: String,
+    },
+
+    /// The handle is well formed but the object it named is gone
+    #[error("Invalid file handle: {reason}")]
+    Stale {
+        /// Why the object cannot be found
+        reason: String,
+    },
+
+    /// The object is not available yet (e.g. being recalled from cold
+    /// storage); the client should retry the request later
+    #[error("{context}: not available yet, retry later")]
+    Retry {
+        /// What the backend was waiting for
+        context: String,
+    },
+}
+
+impl FsalError {
+    /// Wrap an I/O error with context
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        FsalError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Reject a malformed handle
+    pub fn bad_handle(reason: impl Into<String>) -> Self {
+        FsalError::BadHandle {
+            reason: reason.into(),
+        }
+    }
+
+    /// Reject a handle whose object no longer exists
+    pub fn stale(reason: impl Into<String>) -> Self {
+        FsalError::Stale {
+            reason: reason.into(),
+        }
+    }
+
+    /// Ask the client to retry later
+    pub fn retry(context: impl Into<String>) -> Self {
+        FsalError::Retry {
+            context: context.into(),
+        }
+    }
+
+    /// Raw OS error number, if known
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            FsalError::Io { source, .. } => source.raw_os_error(),
+            FsalError::NotSync
+            | FsalError::BadHandle { .. }
+            | FsalError::Stale { .. }
+            | FsalError::Retry { .. } => None,
+        }
+    }
+}
+
+/// Extract the errno from an FSAL error, whether it is an `FsalError` or a bare `io::Error`
+pub fn errno_of(error: &anyhow::Error) -> Option<i32> {
+    if let Some(fsal_error) = error.downcast_ref::<FsalError>() {
+        return fsal_error.errno();
+    }
+    error
+        .downcast_ref::<io::Error>()
+        .and_then(|io_error| io_error.raw_os_error())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_keeps_errno_and_context() {
+        let error = FsalError::io("read \"/export/file\"", io::Error::from_raw_os_error(libc::EIO));
+        assert_eq!(error.errno(), Some(libc::EIO));
+        assert!(error.to_string().starts_with("read \"/export/file\": "));
+
+        let error: anyhow::Error = error.into();
+        assert_eq!(errno_of(&error), Some(libc::EIO));
+
+        let bare: anyhow::Error = io::Error::from_raw_os_error(libc::ENOSPC).into();
+        assert_eq!(errno_of(&bare), Some(libc::ENOSPC));
+    }
+}
@@ -0,0 +1,278 @@
+// Fault Injection Backend (tests only)
+//
+// Wraps another backend and makes chosen operations fail with an errno (or
+// stall), so handlers can be tested against backend failures that are hard
+// to provoke on a real filesystem.
+
+use anyhow::Result;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::error::FsalError;
+use super::{
+    Acl, DirEntry, FileAttributes, FileHandle, FileTime, FileType, Filesystem, FsStats, SetAttributes, SetTime,
+    StableHow,
+};
+
+/// How an operation fails
+#[derive(Clone, Copy)]
+enum Fault {
+    /// An I/O error with this errno
+    Errno(i32),
+    /// `FsalError::Retry`, as a slow backend would return
+    Retry,
+    /// A panic, as a bug in a handler or backend would raise
+    Panic,
+    /// A delay before the operation runs, as a hung backend would cause
+    Stall(Duration),
+}
+
+/// Backend whose operations can be made to fail
+pub struct FaultyFilesystem {
+    inner: Box<dyn Filesystem>,
+    /// Operation name (the trait method name) → how it fails
+    faults: Mutex<HashMap<&'static str, Fault>>,
+}
+
+impl FaultyFilesystem {
+    /// Wrap a backend; every operation succeeds until `fail` is called
+    pub fn new(inner: Box<dyn Filesystem>) -> Self {
+        Self {
+            inner,
+            faults: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Make `operation` (e.g. "getattr") fail with `errno` from now on
+    pub fn fail(&self, operation: &'static str, errno: i32) {
+        self.faults.lock().unwrap().insert(operation, Fault::Errno(errno));
+    }
+
+    /// Make `operation` ask the client to retry later from now on
+    pub fn retry(&self, operation: &'static str) {
+        self.faults.lock().unwrap().insert(operation, Fault::Retry);
+    }
+
+    /// Make `operation` panic from now on
+    pub fn panic(&self, operation: &'static str) {
+        self.faults.lock().unwrap().insert(operation, Fault::Panic);
+    }
+
+    /// Make `operation` block for `delay` before running from now on
+    pub fn stall(&self, operation: &'static str, delay: Duration) {
+        self.faults.lock().unwrap().insert(operation, Fault::Stall(delay));
+    }
+
+    /// Let `operation` succeed again
+    pub fn heal(&self, operation: &'static str) {
+        self.faults.lock().unwrap().remove(operation);
+    }
+
+    fn check(&self, operation: &'static str) -> Result<()> {
+        // Copy the fault out, so a panic does not poison the table
+        let fault = self.faults.lock().unwrap().get(operation).copied();
+        match fault {
+            Some(Fault::Errno(errno)) => Err(io::Error::from_raw_os_error(errno).into()),
+            Some(Fault::Retry) => Err(FsalError::retry(format!("{} is recalling the object", operation)).into()),
+            Some(Fault::Panic) => panic!("injected panic in {}", operation),
+            Some(Fault::Stall(delay)) => {
+                std::thread::sleep(delay);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Filesystem for FaultyFilesystem {
+    fn root_handle(&self) -> FileHandle {
+        self.inner.root_handle()
+    }
+
+    fn lookup(&self, dir_handle: &FileHandle, name: &str) -> Result<FileHandle> {
+        self.check("lookup")?;
+        self.inner.lookup(dir_handle, name)
+    }
+
+    fn parent_handle(&self, dir_handle: &FileHandle) -> Result<FileHandle> {
+        self.check("lookup")?;
+        self.inner.parent_handle(dir_handle)
+    }
+
+    fn getattr(&self, handle: &FileHandle) -> Result<FileAttributes> {
+        self.check("getattr")?;
+        self.inner.getattr(handle)
+    }
+
+    fn read(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<Bytes> {
+        self.check("read")?;
+        self.inner.read(handle, offset, count)
+    }
+
+    fn read_into(&self, handle: &FileHandle, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.check("read")?;
+        self.inner.read_into(handle, offset, buf)
+    }
+
+    fn readdir(&self, dir_handle: &FileHandle, cookie: u64, count: u32) -> Result<(Vec<DirEntry>, bool)> {
+        self.check("readdir")?;
+        self.inner.readdir(dir_handle, cookie, count)
+    }
+
+    fn write(&self, handle: &FileHandle, offset: u64, data: &[u8], stable: StableHow) -> Result<u32> {
+        self.check("write")?;
+        self.inner.write(handle, offset, data, stable)
+    }
+
+    fn setattr_size(&self, handle: &FileHandle, size: u64) -> Result<()> {
+        self.check("setattr_size")?;
+        self.inner.setattr_size(handle, size)
+    }
+
+    fn setattr_mode(&self, handle: &FileHandle, mode: u32) -> Result<()> {
+        self.check("setattr_mode")?;
+        self.inner.setattr_mode(handle, mode)
+    }
+
+    fn setattr_owner(&self, handle: &FileHandle, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.check("setattr_owner")?;
+        self.inner.setattr_owner(handle, uid, gid)
+    }
+
+    fn setattr_times(&self, handle: &FileHandle, atime: Option<SetTime>, mtime: Option<SetTime>) -> Result<()> {
+        self.check("setattr_times")?;
+        self.inner.setattr_times(handle, atime, mtime)
+    }
+
+    fn setattr(&self, handle: &FileHandle, attrs: &SetAttributes, guard: Option<FileTime>) -> Result<()> {
+        self.check("setattr")?;
+        self.inner.setattr(handle, attrs, guard)
+    }
+
+    fn create(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        self.check("create")?;
+        self.inner.create(dir_handle, name, mode)
+    }
+
+    fn create_guarded(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        self.check("create")?;
+        self.inner.create_guarded(dir_handle, name, mode)
+    }
+
+    fn remove(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
+        self.check("remove")?;
+        self.inner.remove(dir_handle, name)
+    }
+
+    fn mkdir(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        self.check("mkdir")?;
+        self.inner.mkdir(dir_handle, name, mode)
+    }
+
+    fn rmdir(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
+        self.check("rmdir")?;
+        self.inner.rmdir(dir_handle, name)
+    }
+
+    fn rename(
+        &self,
+        from_dir_handle: &FileHandle,
+        from_name: &str,
+        to_dir_handle: &FileHandle,
+        to_name: &str,
+    ) -> Result<()> {
+        self.check("rename")?;
+        self.inner.rename(from_dir_handle, from_name, to_dir_handle, to_name)
+    }
+
+    fn symlink(&self, dir_handle: &FileHandle, name: &str, target: &str) -> Result<FileHandle> {
+        self.check("symlink")?;
+        self.inner.symlink(dir_handle, name, target)
+    }
+
+    fn readlink(&self, handle: &FileHandle) -> Result<String> {
+        self.check("readlink")?;
+        self.inner.readlink(handle)
+    }
+
+    fn link(&self, file_handle: &FileHandle, dir_handle: &FileHandle, name: &str) -> Result<FileHandle> {
+        self.check("link")?;
+        self.inner.link(file_handle, dir_handle, name)
+    }
+
+    fn commit(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<()> {
+        self.check("commit")?;
+        self.inner.commit(handle, offset, count)
+    }
+
+    fn issued_handles(&self) -> Vec<(FileHandle, String)> {
+        self.inner.issued_handles()
+    }
+
+    fn invalidate_handle(&self, handle: &FileHandle) -> bool {
+        self.inner.invalidate_handle(handle)
+    }
+
+    fn flush(&self) -> Result<usize> {
+        self.check("flush")?;
+        self.inner.flush()
+    }
+
+    fn mknod(
+        &self,
+        dir_handle: &FileHandle,
+        name: &str,
+        file_type: FileType,
+        mode: u32,
+        rdev: (u32, u32),
+    ) -> Result<FileHandle> {
+        self.check("mknod")?;
+        self.inner.mknod(dir_handle, name, file_type, mode, rdev)
+    }
+
+    fn get_xattr(&self, handle: &FileHandle, name: &str) -> Result<Option<Vec<u8>>> {
+        self.check("get_xattr")?;
+        self.inner.get_xattr(handle, name)
+    }
+
+    fn set_xattr(&self, handle: &FileHandle, name: &str, value: &[u8]) -> Result<()> {
+        self.check("set_xattr")?;
+        self.inner.set_xattr(handle, name, value)
+    }
+
+    fn list_xattr(&self, handle: &FileHandle) -> Result<Vec<String>> {
+        self.check("list_xattr")?;
+        self.inner.list_xattr(handle)
+    }
+
+    fn remove_xattr(&self, handle: &FileHandle, name: &str) -> Result<()> {
+        self.check("remove_xattr")?;
+        self.inner.remove_xattr(handle, name)
+    }
+
+    fn get_acl(&self, handle: &FileHandle) -> Result<Option<Acl>> {
+        self.check("get_acl")?;
+        self.inner.get_acl(handle)
+    }
+
+    fn set_acl(&self, handle: &FileHandle, acl: &Acl) -> Result<()> {
+        self.check("set_acl")?;
+        self.inner.set_acl(handle, acl)
+    }
+
+    fn statfs(&self, handle: &FileHandle) -> Result<FsStats> {
+        self.check("statfs")?;
+        self.inner.statfs(handle)
+    }
+
+    fn release_cached_files(&self) {
+        self.inner.release_cached_files()
+    }
+
+    fn handle_count(&self) -> usize {
+        self.inner.handle_count()
+    }
+}
@@ -2,79 +2,161 @@
 //
 // File handles are opaque identifiers used by NFS to reference files/directories.
 // This module manages the bidirectional mapping between file handles and paths.
+//
+// Backend handle layout (the wire handle prefixes it with the 4-byte export
+// id, so these are wire bytes 4..36):
+//   bytes 0..8    inode
+//   bytes 8..12   generation of the inode when the handle was issued
+//   bytes 12..20  handle id (unique per manager)
+//   bytes 20..28  hash of the path the handle was issued for
+//   bytes 28..32  inode generation reported by the filesystem (0 if unknown)
+//
+// An inode number freed by a delete can be reused by the next create. Each
+// inode retired through the server gets its generation bumped, so handles
+// issued before the delete no longer validate and resolve as stale. Files
+// deleted and recreated behind the server's back are told apart by the
+// filesystem's own inode generation, which changes whenever it reuses an
+// inode number.
 
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 /// File handle type (opaque bytes)
 pub type FileHandle = Vec<u8>;
 
+/// Length of a handle issued by `HandleManager`
+pub const HANDLE_LEN: usize = 32;
+
+/// Decoded fields of a structured backend handle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandleFields {
+    /// Inode the handle was issued for
+    pub inode: u64,
+    /// Generation of the inode when the handle was issued
+    pub generation: u32,
+    /// Unique handle id
+    pub id: u64,
+    /// Hash of the path the handle was issued for
+    pub path_hash: u64,
+    /// Inode generation reported by the filesystem, 0 if unknown
+    pub fs_generation: u32,
+}
+
+/// Lay out handle fields in the structured handle format
+pub fn encode_handle(fields: &HandleFields) -> FileHandle {
+    let mut handle = vec![0u8; HANDLE_LEN];
+    handle[0..8].copy_from_slice(&fields.inode.to_be_bytes());
+    handle[8..12].copy_from_slice(&fields.generation.to_be_bytes());
+    handle[12..20].copy_from_slice(&fields.id.to_be_bytes());
+    handle[20..28].copy_from_slice(&fields.path_hash.to_be_bytes());
+    handle[28..32].copy_from_slice(&fields.fs_generation.to_be_bytes());
+    handle
+}
+
+/// Read the fields of a structured handle, or None if it is malformed
+pub fn decode_handle(handle: &[u8]) -> Option<HandleFields> {
+    if handle.len() != HANDLE_LEN {
+        return None;
+    }
+    Some(HandleFields {
+        inode: u64::from_be_bytes(handle[0..8].try_into().ok()?),
+        generation: u32::from_be_bytes(handle[8..12].try_into().ok()?),
+        id: u64::from_be_bytes(handle[12..20].try_into().ok()?),
+        path_hash: u64::from_be_bytes(handle[20..28].try_into().ok()?),
+        fs_generation: u32::from_be_bytes(handle[28..32].try_into().ok()?),
+    })
+}
+
+/// Number of lock shards per map
+const SHARD_COUNT: usize = 64;
+
+/// One lock-protected bucket of a sharded map
+type Shard<K, V> = RwLock<HashMap<K, V>>;
+
 /// File handle manager
 ///
 /// Maintains the mapping between file handles and filesystem paths.
 /// Thread-safe for concurrent access.
+///
+/// Both maps are split into `SHARD_COUNT` buckets keyed by hash, each with
+/// its own lock, so concurrent lookups and creates for different files rarely
+/// contend. When both maps are locked, the path shard is always taken first.
 #[derive(Clone)]
 pub struct HandleManager {
-    /// Map from file handle to path
-    handle_to_path: Arc<RwLock<HashMap<FileHandle, PathBuf>>>,
-    /// Map from path to file handle (for quick lookups)
-    path_to_handle: Arc<RwLock<HashMap<PathBuf, FileHandle>>>,
+    /// Map from file handle to path, sharded by handle hash
+    handle_to_path: Arc<[Shard<FileHandle, PathBuf>]>,
+    /// Map from path to file handle (for quick lookups), sharded by path hash
+    path_to_handle: Arc<[Shard<PathBuf, FileHandle>]>,
+    /// Current generation of every inode retired at least once
+    generations: Arc<[Shard<u64, u32>]>,
     /// Counter for generating unique handles
-    next_id: Arc<RwLock<u64>>,
+    next_id: Arc<AtomicU64>,
 }
 
 impl HandleManager {
     /// Create a new handle manager
     pub fn new() -> Self {
         Self {
-            handle_to_path: Arc::new(RwLock::new(HashMap::new())),
-            path_to_handle: Arc::new(RwLock::new(HashMap::new())),
-            next_id: Arc::new(RwLock::new(1)), // Start from 1 (0 could be reserved)
+            handle_to_path: new_shards(),
+            path_to_handle: new_shards(),
+            generations: new_shards(),
+            next_id: Arc::new(AtomicU64::new(1)), // Start from 1 (0 could be reserved)
         }
     }
 
     /// Generate a new file handle for a path
     ///
-    /// If the path already has a handle, return the existing one.
-    /// Otherwise, create a new handle.
-    pub fn create_handle(&self, path: PathBuf) -> FileHandle {
+    /// If the path already has a handle for the same inode, return the
+    /// existing one. Otherwise, create a new handle; a handle the path had
+    /// for another inode, or an earlier generation of this one (replaced
+    /// behind the server's back), stops resolving.
+    ///
+    /// # Arguments
+    /// * `path` - Path the handle refers to
+    /// * `inode` - Inode the path currently names
+    /// * `fs_generation` - Filesystem generation of that inode, 0 if unknown
+    pub fn create_handle(&self, path: PathBuf, inode: u64, fs_generation: u32) -> FileHandle {
+        let path_shard = self.path_shard(&path);
+        let same_inode = |handle: &FileHandle| {
+            decode_handle(handle).is_some_and(|fields| fields.inode == inode && fields.fs_generation == fs_generation)
+        };
+
         // Check if path already has a handle
+        if let Some(handle) = path_shard.read().unwrap().get(&path)
+            && same_inode(handle)
         {
-            let path_map = self.path_to_handle.read().unwrap();
-            if let Some(handle) = path_map.get(&path) {
+            return handle.clone();
+        }
+
+        // Re-check under the write lock: another caller may have created it
+        let mut path_map = path_shard.write().unwrap();
+        if let Some(handle) = path_map.get(&path) {
+            if same_inode(handle) {
                 return handle.clone();
             }
+            let replaced = handle.clone();
+            self.handle_shard(&replaced).write().unwrap().remove(&replaced);
         }
 
         // Generate new handle
-        let id = {
-            let mut next_id = self.next_id.write().unwrap();
-            let current = *next_id;
-            *next_id += 1;
-            current
-        };
-
-        // Create handle from ID (32 bytes with ID in first 8 bytes)
-        let mut handle = vec![0u8; 32];
-        handle[0..8].copy_from_slice(&id.to_be_bytes());
-
-        // Store path hash in bytes 8-16 for verification
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        let mut hasher = DefaultHasher::new();
-        path.hash(&mut hasher);
-        let path_hash = hasher.finish();
-        handle[8..16].copy_from_slice(&path_hash.to_be_bytes());
+        let handle = encode_handle(&HandleFields {
+            inode,
+            generation: self.generation(inode),
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            path_hash: hash_of(&path),
+            fs_generation,
+        });
 
         // Store mappings
-        {
-            let mut handle_map = self.handle_to_path.write().unwrap();
-            let mut path_map = self.path_to_handle.write().unwrap();
-
-            handle_map.insert(handle.clone(), path.clone());
-            path_map.insert(path.clone(), handle.clone());
-        }
+        self.handle_shard(&handle)
+            .write()
+            .unwrap()
+            .insert(handle.clone(), path.clone());
+        path_map.insert(path.clone(), handle.clone());
 
         tracing::debug!("Created file handle for path: {:?}", path);
         handle
@@ -82,35 +164,132 @@ impl HandleManager {
 
     /// Look up the path for a file handle
     pub fn lookup_path(&self, handle: &FileHandle) -> Option<PathBuf> {
-        let handle_map = self.handle_to_path.read().unwrap();
+        let handle_map = self.handle_shard(handle).read().unwrap();
         handle_map.get(handle).cloned()
     }
 
+    /// Look up the handle already issued for a path, without creating one
+    pub fn lookup_handle(&self, path: &Path) -> Option<FileHandle> {
+        self.path_shard(path).read().unwrap().get(path).cloned()
+    }
+
     /// Check if a file handle exists
     pub fn is_valid(&self, handle: &FileHandle) -> bool {
-        let handle_map = self.handle_to_path.read().unwrap();
+        let handle_map = self.handle_shard(handle).read().unwrap();
         handle_map.contains_key(handle)
     }
 
+    /// Check that a handle is known and its embedded path hash matches
+    ///
+    /// A handle whose stored path hashes differently is corrupt or forged.
+    pub fn validate(&self, handle: &FileHandle) -> bool {
+        let Some(fields) = decode_handle(handle) else {
+            return false;
+        };
+        self.lookup_path(handle)
+            .is_some_and(|path| hash_of(&path) == fields.path_hash)
+    }
+
+    /// Whether a handle was issued for the current generation of its inode
+    pub fn is_current(&self, handle: &FileHandle) -> bool {
+        decode_handle(handle).is_some_and(|fields| fields.generation == self.generation(fields.inode))
+    }
+
+    /// Current generation of an inode
+    pub fn generation(&self, inode: u64) -> u32 {
+        self.generation_shard(inode)
+            .read()
+            .unwrap()
+            .get(&inode)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Forget the handle of a path that was removed through the server
+    ///
+    /// # Arguments
+    /// * `path` - Path that no longer exists
+    /// * `freed_inode` - Inode freed by the removal (its last link), whose
+    ///   generation is bumped so every handle to it goes stale
+    pub fn retire(&self, path: &Path, freed_inode: Option<u64>) {
+        if let Some(inode) = freed_inode {
+            let mut generations = self.generation_shard(inode).write().unwrap();
+            let generation = generations.entry(inode).or_insert(0);
+            *generation = generation.wrapping_add(1);
+        }
+        if let Some(handle) = self.lookup_handle(path) {
+            self.remove_handle(&handle);
+        }
+    }
+
     /// Remove a file handle (e.g., when file is deleted)
     pub fn remove_handle(&self, handle: &FileHandle) -> Option<PathBuf> {
-        let mut handle_map = self.handle_to_path.write().unwrap();
-        let mut path_map = self.path_to_handle.write().unwrap();
+        let path = self.lookup_path(handle)?;
 
-        if let Some(path) = handle_map.remove(handle) {
+        // Lock in path → handle order, then confirm nothing changed meanwhile
+        let mut path_map = self.path_shard(&path).write().unwrap();
+        let mut handle_map = self.handle_shard(handle).write().unwrap();
+
+        let path = handle_map.remove(handle)?;
+        if path_map.get(&path) == Some(handle) {
             path_map.remove(&path);
-            tracing::debug!("Removed file handle for path: {:?}", path);
-            Some(path)
-        } else {
-            None
         }
+        tracing::debug!("Removed file handle for path: {:?}", path);
+        Some(path)
+    }
+
+    /// Every issued handle with the path it names
+    pub fn entries(&self) -> Vec<(FileHandle, PathBuf)> {
+        self.handle_to_path
+            .iter()
+            .flat_map(|shard| {
+                let map = shard.read().unwrap();
+                map.iter().map(|(handle, path)| (handle.clone(), path.clone())).collect::<Vec<_>>()
+            })
+            .collect()
     }
 
     /// Get total number of handles
     pub fn count(&self) -> usize {
-        let handle_map = self.handle_to_path.read().unwrap();
-        handle_map.len()
+        self.handle_to_path
+            .iter()
+            .map(|shard| shard.read().unwrap().len())
+            .sum()
+    }
+
+    /// Shard holding the handle for `path`
+    fn path_shard(&self, path: &Path) -> &Shard<PathBuf, FileHandle> {
+        &self.path_to_handle[shard_index(hash_of(path))]
     }
+
+    /// Shard holding the path for `handle`
+    fn handle_shard(&self, handle: &[u8]) -> &Shard<FileHandle, PathBuf> {
+        &self.handle_to_path[shard_index(hash_of(handle))]
+    }
+
+    /// Shard holding the generation of `inode`
+    fn generation_shard(&self, inode: u64) -> &Shard<u64, u32> {
+        &self.generations[shard_index(hash_of(&inode))]
+    }
+}
+
+/// Allocate an empty sharded map
+fn new_shards<K, V>() -> Arc<[Shard<K, V>]> {
+    (0..SHARD_COUNT)
+        .map(|_| RwLock::new(HashMap::new()))
+        .collect()
+}
+
+/// Stable hash of a key (also embedded in handles to identify the path)
+fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Bucket for a hash
+fn shard_index(hash: u64) -> usize {
+    (hash % SHARD_COUNT as u64) as usize
 }
 
 impl Default for HandleManager {
@@ -128,7 +307,7 @@ mod tests {
         let manager = HandleManager::new();
         let path = PathBuf::from("/test/file.txt");
 
-        let handle = manager.create_handle(path.clone());
+        let handle = manager.create_handle(path.clone(), 1, 0);
         assert_eq!(manager.lookup_path(&handle), Some(path));
     }
 
@@ -137,8 +316,8 @@ mod tests {
         let manager = HandleManager::new();
         let path = PathBuf::from("/test/file.txt");
 
-        let handle1 = manager.create_handle(path.clone());
-        let handle2 = manager.create_handle(path.clone());
+        let handle1 = manager.create_handle(path.clone(), 1, 0);
+        let handle2 = manager.create_handle(path.clone(), 1, 0);
 
         assert_eq!(handle1, handle2);
     }
@@ -148,11 +327,133 @@ mod tests {
         let manager = HandleManager::new();
         let path = PathBuf::from("/test/file.txt");
 
-        let handle = manager.create_handle(path.clone());
+        let handle = manager.create_handle(path.clone(), 1, 0);
         assert!(manager.is_valid(&handle));
 
         let removed_path = manager.remove_handle(&handle);
         assert_eq!(removed_path, Some(path));
         assert!(!manager.is_valid(&handle));
     }
+
+    #[test]
+    fn test_validate_path_hash() {
+        let manager = HandleManager::new();
+        let path = PathBuf::from("/test/file.txt");
+        let handle = manager.create_handle(path.clone(), 1, 0);
+        assert!(manager.validate(&handle));
+
+        // A flipped byte no longer names a known handle
+        let mut flipped = handle.clone();
+        flipped[3] ^= 0x01;
+        assert!(!manager.validate(&flipped));
+
+        // A handle mapped to a path it was not created for fails the hash check
+        let mut forged = handle.clone();
+        forged[0] ^= 0x80;
+        manager
+            .handle_shard(&forged)
+            .write()
+            .unwrap()
+            .insert(forged.clone(), PathBuf::from("/etc/shadow"));
+        assert!(manager.lookup_path(&forged).is_some());
+        assert!(!manager.validate(&forged));
+
+        assert!(!manager.validate(&vec![0u8; 4]));
+    }
+
+    #[test]
+    fn test_concurrent_create_handle() {
+        const THREADS: usize = 16;
+        const PATHS_PER_THREAD: usize = 500;
+        const SHARED_PATHS: usize = 200;
+
+        let manager = HandleManager::new();
+
+        let results: Vec<Vec<(PathBuf, FileHandle)>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..THREADS)
+                .map(|thread| {
+                    let manager = &manager;
+                    scope.spawn(move || {
+                        let mut created = Vec::new();
+                        for i in 0..PATHS_PER_THREAD {
+                            let path = PathBuf::from(format!("/t{}/file{}", thread, i));
+                            created.push((path.clone(), manager.create_handle(path, 1, 0)));
+                        }
+                        // Every thread also races on the same shared paths
+                        for i in 0..SHARED_PATHS {
+                            let path = PathBuf::from(format!("/shared/file{}", i));
+                            created.push((path.clone(), manager.create_handle(path, 1, 0)));
+                        }
+                        created
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+
+        assert_eq!(manager.count(), THREADS * PATHS_PER_THREAD + SHARED_PATHS);
+
+        let mut by_path: HashMap<PathBuf, FileHandle> = HashMap::new();
+        for (path, handle) in results.into_iter().flatten() {
+            assert_eq!(manager.lookup_path(&handle).as_ref(), Some(&path));
+            let first = by_path.entry(path).or_insert_with(|| handle.clone());
+            assert_eq!(*first, handle, "Racing creates must agree on one handle");
+        }
+
+        let distinct: std::collections::HashSet<_> = by_path.values().collect();
+        assert_eq!(distinct.len(), by_path.len(), "Handles must be unique per path");
+    }
+
+    #[test]
+    fn test_handle_layout_round_trip() {
+        let fields = HandleFields {
+            inode: 0x0102_0304_0506_0708,
+            generation: 9,
+            id: 42,
+            path_hash: u64::MAX,
+            fs_generation: 0xdead_beef,
+        };
+        let handle = encode_handle(&fields);
+        assert_eq!(handle.len(), HANDLE_LEN);
+        assert_eq!(&handle[0..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(&handle[8..12], &[0, 0, 0, 9]);
+        assert_eq!(&handle[28..32], &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(decode_handle(&handle), Some(fields));
+
+        // A handle of the wrong length is malformed
+        assert_eq!(decode_handle(&handle[..16]), None);
+        assert_eq!(decode_handle(&[handle.clone(), vec![0]].concat()), None);
+    }
+
+    #[test]
+    fn test_retire_bumps_generation() {
+        let manager = HandleManager::new();
+        let path = PathBuf::from("/test/file.txt");
+
+        let old = manager.create_handle(path.clone(), 7, 0);
+        assert!(manager.is_current(&old));
+
+        // The inode is freed, then its number reused at the same path
+        manager.retire(&path, Some(7));
+        assert!(!manager.is_valid(&old));
+        assert!(!manager.is_current(&old));
+        let new = manager.create_handle(path.clone(), 7, 0);
+        assert_ne!(old, new);
+        assert_eq!(decode_handle(&new).unwrap().generation, 1);
+        assert!(manager.is_current(&new));
+
+        // A path now naming another inode gets a new handle; the old one dies
+        let replaced = manager.create_handle(path.clone(), 8, 0);
+        assert_ne!(replaced, new);
+        assert!(!manager.is_valid(&new));
+        assert_eq!(manager.lookup_path(&replaced), Some(path.clone()));
+
+        // So does one naming a new filesystem generation of the same inode
+        let recreated = manager.create_handle(path.clone(), 8, 3);
+        assert_ne!(recreated, replaced);
+        assert!(!manager.is_valid(&replaced));
+        assert_eq!(decode_handle(&recreated).unwrap().fs_generation, 3);
+        assert_eq!(manager.create_handle(path, 8, 3), recreated);
+    }
 }
+
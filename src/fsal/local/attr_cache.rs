@@ -0,0 +1,249 @@
+// Attribute Cache
+//
+// Caches FileAttributes per file handle. Entries younger than the TTL are
+// served without touching the filesystem at all. Older entries are
+// revalidated by a cheap change-attribute probe (statx with a minimal mask):
+// if the probe shows the file is unchanged, GETATTR is served from the cache
+// instead of building a full stat result.
+//
+// Mutations made through the server invalidate the files they touch, along
+// with every other cached handle of the same inode (hard links), so they are
+// always visible. Changes made behind the server's back can go unnoticed for
+// up to one TTL.
+
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use super::super::{FileAttributes, FileHandle};
+use super::beneath::ExportRoot;
+
+/// Default time attributes are served without revalidation
+pub const DEFAULT_TTL: Duration = Duration::from_secs(1);
+
+/// Default maximum number of cached entries before the cache is reset
+pub const DEFAULT_MAX_ENTRIES: usize = 65536;
+
+/// Change attribute
+///
+/// Any modification to a file's data or metadata updates its ctime. Size and
+/// mtime are included as well so that writes landing within the same
+/// timestamp tick are still detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeAttr {
+    ino: u64,
+    size: u64,
+    ctime: (i64, u32),
+    mtime: (i64, u32),
+}
+
+/// Probe the change attribute of a path below the export root with statx
+///
+/// A symlink is probed itself, not its target. Returns None if statx is
+/// unavailable or does not report the required fields, in which case
+/// callers fall back to a full stat.
+pub fn probe_change_attr(root: &ExportRoot, path: &Path) -> Option<ChangeAttr> {
+    let mask = libc::STATX_INO | libc::STATX_SIZE | libc::STATX_CTIME | libc::STATX_MTIME;
+    let stx = root.statx(path, mask).ok()?;
+    if stx.stx_mask & mask != mask {
+        return None;
+    }
+
+    Some(ChangeAttr {
+        ino: stx.stx_ino,
+        size: stx.stx_size,
+        ctime: (stx.stx_ctime.tv_sec, stx.stx_ctime.tv_nsec),
+        mtime: (stx.stx_mtime.tv_sec, stx.stx_mtime.tv_nsec),
+    })
+}
+
+impl ChangeAttr {
+    /// Change attribute of metadata already obtained from a stat
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        Self {
+            ino: metadata.ino(),
+            size: metadata.size(),
+            ctime: (metadata.ctime(), metadata.ctime_nsec() as u32),
+            mtime: (metadata.mtime(), metadata.mtime_nsec() as u32),
+        }
+    }
+}
+
+/// A cached GETATTR result
+struct Entry {
+    attrs: FileAttributes,
+    change: ChangeAttr,
+    cached_at: Instant,
+}
+
+/// Generation-guarded contents of the cache
+struct Entries {
+    map: HashMap<FileHandle, Entry>,
+    /// Cached handles per inode, so hard links are invalidated together
+    by_fileid: HashMap<u64, Vec<FileHandle>>,
+    /// Bumped by every invalidation
+    generation: u64,
+}
+
+impl Entries {
+    /// Remove every cached handle of an inode
+    fn remove_fileid(&mut self, fileid: u64) {
+        for handle in self.by_fileid.remove(&fileid).unwrap_or_default() {
+            self.map.remove(&handle);
+        }
+    }
+}
+
+/// Handle → (attributes, change attribute) cache
+pub struct AttrCache {
+    entries: RwLock<Entries>,
+    /// Age below which entries are served without a probe (zero disables)
+    ttl: Duration,
+    /// Entry count at which the cache is reset
+    max_entries: usize,
+}
+
+impl AttrCache {
+    /// Create an empty cache
+    ///
+    /// # Arguments
+    /// * `ttl` - How long entries are served without revalidation; zero
+    ///   always revalidates with a change-attribute probe
+    /// * `max_entries` - Size bound; the cache is reset when it is reached
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            entries: RwLock::new(Entries {
+                map: HashMap::new(),
+                by_fileid: HashMap::new(),
+                generation: 0,
+            }),
+            ttl,
+            max_entries: max_entries.max(1),
+        }
+    }
+
+    /// Current invalidation generation
+    ///
+    /// Take this before stat'ing a file and pass it to `insert`, so that
+    /// attributes read before a concurrent mutation are not cached after it.
+    pub fn generation(&self) -> u64 {
+        self.entries.read().unwrap().generation
+    }
+
+    /// Whether entries are ever served without revalidation
+    pub fn caches_fresh(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Return cached attributes that are younger than the TTL
+    pub fn get_fresh(&self, handle: &FileHandle) -> Option<FileAttributes> {
+        if !self.caches_fresh() {
+            return None;
+        }
+        let entries = self.entries.read().unwrap();
+        entries
+            .map
+            .get(handle)
+            .filter(|entry| entry.cached_at.elapsed() < self.ttl)
+            .map(|entry| entry.attrs.clone())
+    }
+
+    /// Return cached attributes if they were cached with the given change attribute
+    ///
+    /// A hit restarts the entry's TTL.
+    pub fn get(&self, handle: &FileHandle, change: &ChangeAttr) -> Option<FileAttributes> {
+        let mut entries = self.entries.write().unwrap();
+        match entries.map.get_mut(handle) {
+            Some(entry) if entry.change == *change => {
+                entry.cached_at = Instant::now();
+                Some(entry.attrs.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Cache attributes for a handle
+    ///
+    /// Skipped if anything was invalidated since `generation` was taken.
+    pub fn insert(&self, handle: &FileHandle, attrs: FileAttributes, change: ChangeAttr, generation: u64) {
+        let mut entries = self.entries.write().unwrap();
+        if entries.generation != generation {
+            return;
+        }
+        if entries.map.len() >= self.max_entries && !entries.map.contains_key(handle) {
+            entries.map.clear();
+            entries.by_fileid.clear();
+        }
+        let fileid = attrs.fileid;
+        let previous = entries.map.insert(
+            handle.clone(),
+            Entry {
+                attrs,
+                change,
+                cached_at: Instant::now(),
+            },
+        );
+        if previous.as_ref().map(|entry| entry.attrs.fileid) != Some(fileid) {
+            entries.by_fileid.entry(fileid).or_default().push(handle.clone());
+        }
+    }
+
+    /// Drop the cached attributes for a handle and every handle of the same inode
+    pub fn invalidate(&self, handle: &FileHandle) {
+        let mut entries = self.entries.write().unwrap();
+        entries.generation += 1;
+        if let Some(entry) = entries.map.remove(handle) {
+            entries.remove_fileid(entry.attrs.fileid);
+        }
+    }
+
+    /// Drop the cached attributes of every handle of an inode
+    pub fn invalidate_fileid(&self, fileid: u64) {
+        let mut entries = self.entries.write().unwrap();
+        entries.generation += 1;
+        entries.remove_fileid(fileid);
+    }
+}
+
+impl Default for AttrCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL, DEFAULT_MAX_ENTRIES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A temporary export root
+    fn test_root() -> (ExportRoot, std::path::PathBuf, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let root_path = temp_dir.path().canonicalize().unwrap();
+        (ExportRoot::open(&root_path).unwrap(), root_path, temp_dir)
+    }
+
+    #[test]
+    fn test_probe_detects_write() {
+        let (root, root_path, _temp_dir) = test_root();
+        let path = root_path.join("file.txt");
+        fs::write(&path, b"one").unwrap();
+
+        let before = probe_change_attr(&root, &path).expect("statx should be available");
+        assert_eq!(probe_change_attr(&root, &path), Some(before), "Probe should be stable");
+
+        fs::write(&path, b"two two").unwrap();
+        let after = probe_change_attr(&root, &path).unwrap();
+        assert_ne!(before, after, "Write should change the change attribute");
+    }
+
+    #[test]
+    fn test_probe_missing_file() {
+        let (root, root_path, _temp_dir) = test_root();
+        assert!(probe_change_attr(&root, &root_path.join("missing")).is_none());
+    }
+}
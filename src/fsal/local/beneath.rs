@@ -0,0 +1,443 @@
+// Confined Opens
+//
+// Handles map to paths below the export root, but a path checked to lie
+// inside the root can still escape it if one of its components is swapped
+// for a symlink between the check and the open. Files are therefore opened
+// with openat2(2) relative to a descriptor of the root, with RESOLVE_BENEATH
+// and RESOLVE_NO_SYMLINKS: the kernel refuses, atomically with the open, any
+// resolution that leaves the root or crosses a symlink. Paths the server
+// builds never cross one (clients follow symlinks themselves via READLINK).
+//
+// Operations on a name (chmod, stat, unlink, rename, ...) resolve the
+// directory holding it the same way and then act with the *at syscall
+// relative to that directory, never following a symlink in the final
+// component: a client that creates a symlink to a file outside the export
+// and then changes or stats it reaches only the link. Extended attributes
+// (and so ACLs) and directory listings go through descriptors opened the
+// same way, never through a path.
+//
+// Kernels older than 5.6 lack openat2. There the path is canonicalized and
+// checked to lie inside the root before a plain open, which leaves the race
+// open but still refuses symlinks that already point outside.
+
+use anyhow::{anyhow, Context, Result};
+use std::ffi::CString;
+use std::fs::{File, Metadata};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, warn};
+
+/// Attempts of an open whose resolution raced with a rename (EAGAIN)
+const MAX_ATTEMPTS: usize = 8;
+
+/// Set once openat2 turned out to be missing, so it is not retried
+static OPENAT2_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Export root that files are opened beneath
+pub struct ExportRoot {
+    /// O_PATH descriptor of the root directory
+    dir: OwnedFd,
+    /// Canonical path of the root directory
+    path: PathBuf,
+}
+
+impl ExportRoot {
+    /// Open the root directory at a canonical path
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let dir = raw_open(path, libc::O_PATH | libc::O_DIRECTORY, 0)?;
+        Ok(Self {
+            dir,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Open a file below the root with open(2) `flags` and creation `mode`
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file, inside the root
+    /// * `flags` - open(2) flags (O_CLOEXEC is always added)
+    /// * `mode` - Mode of a file created with O_CREAT
+    ///
+    /// # Returns
+    /// The open file; EXDEV if resolving the path would leave the root and
+    /// ELOOP if it crosses a symlink
+    pub fn open_file(&self, path: &Path, flags: i32, mode: u32) -> io::Result<File> {
+        self.open_beneath(path, flags, mode).map(File::from)
+    }
+
+    /// Open `path` beneath the root, as `open_file` describes
+    fn open_beneath(&self, path: &Path, flags: i32, mode: u32) -> io::Result<OwnedFd> {
+        let relative = path
+            .strip_prefix(&self.path)
+            .map_err(|_| io::Error::from_raw_os_error(libc::EXDEV))?;
+        let relative = if relative.as_os_str().is_empty() { Path::new(".") } else { relative };
+
+        if !OPENAT2_UNSUPPORTED.load(Ordering::Relaxed) {
+            match self.openat2(relative, flags, mode) {
+                Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                    warn!("openat2 is not supported by this kernel; confining opens by path checks");
+                    OPENAT2_UNSUPPORTED.store(true, Ordering::Relaxed);
+                }
+                Err(e) if matches!(e.raw_os_error(), Some(libc::EXDEV | libc::ELOOP)) => {
+                    warn!("Refused to open {:?}: it resolves outside the export root or through a symlink", path);
+                    return Err(e);
+                }
+                result => return result,
+            }
+        }
+
+        validate(&self.path, path).map_err(|e| {
+            warn!("Refused to open {:?}: {:#}", path, e);
+            io::Error::from_raw_os_error(libc::EXDEV)
+        })?;
+        raw_open(path, flags, mode)
+    }
+
+    /// Directory holding `path`, opened beneath the root, and the name of
+    /// `path` in it
+    ///
+    /// The root itself is named "." in a directory opened by its path, so
+    /// a root removed or mounted over since startup is noticed.
+    ///
+    /// # Returns
+    /// (O_PATH descriptor of the directory, final component of `path`)
+    pub fn parent(&self, path: &Path) -> io::Result<(OwnedFd, CString)> {
+        if path == self.path {
+            let dir = raw_open(&self.path, libc::O_PATH | libc::O_DIRECTORY, 0)?;
+            return Ok((dir, CString::from(c".")));
+        }
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(io::Error::from_raw_os_error(libc::EXDEV));
+        };
+        let dir = self.open_beneath(parent, libc::O_PATH | libc::O_DIRECTORY, 0)?;
+        Ok((dir, CString::new(name.as_bytes())?))
+    }
+
+    /// O_PATH descriptor of `path` itself, a symlink rather than its target
+    pub fn open_path(&self, path: &Path) -> io::Result<OwnedFd> {
+        let (dir, name) = self.parent(path)?;
+        let flags = libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        // SAFETY: the descriptor is open and name is NUL terminated
+        let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+        check(fd)?;
+        // SAFETY: openat returned a new descriptor we now own
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// lstat of `path`: a symlink's own metadata, not its target's
+    pub fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        File::from(self.open_path(path)?).metadata()
+    }
+
+    /// Change the mode of `path`; symlinks are refused (EOPNOTSUPP)
+    pub fn chmod(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let (dir, name) = self.parent(path)?;
+        // SAFETY: the descriptor is open and name is NUL terminated
+        check(unsafe { libc::fchmodat(dir.as_raw_fd(), name.as_ptr(), mode, libc::AT_SYMLINK_NOFOLLOW) })
+    }
+
+    /// Set the access and modification times of `path` (of a symlink itself)
+    pub fn set_times(&self, path: &Path, times: &[libc::timespec; 2]) -> io::Result<()> {
+        let (dir, name) = self.parent(path)?;
+        // SAFETY: the descriptor is open, name is NUL terminated and times
+        // holds the two entries utimensat reads
+        check(unsafe { libc::utimensat(dir.as_raw_fd(), name.as_ptr(), times.as_ptr(), libc::AT_SYMLINK_NOFOLLOW) })
+    }
+
+    /// Remove the non-directory `path`
+    pub fn unlink(&self, path: &Path) -> io::Result<()> {
+        let (dir, name) = self.parent(path)?;
+        // SAFETY: the descriptor is open and name is NUL terminated
+        check(unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), 0) })
+    }
+
+    /// Remove the empty directory `path`
+    pub fn rmdir(&self, path: &Path) -> io::Result<()> {
+        let (dir, name) = self.parent(path)?;
+        // SAFETY: the descriptor is open and name is NUL terminated
+        check(unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), libc::AT_REMOVEDIR) })
+    }
+
+    /// Rename `from` to `to`, replacing `to` if it exists
+    pub fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from_dir, from_name) = self.parent(from)?;
+        let (to_dir, to_name) = self.parent(to)?;
+        // SAFETY: both descriptors are open and both names NUL terminated
+        check(unsafe { libc::renameat(from_dir.as_raw_fd(), from_name.as_ptr(), to_dir.as_raw_fd(), to_name.as_ptr()) })
+    }
+
+    /// Create the directory `path` (mode bits are masked by the umask)
+    pub fn mkdir(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let (dir, name) = self.parent(path)?;
+        // SAFETY: the descriptor is open and name is NUL terminated
+        check(unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), mode as libc::mode_t) })
+    }
+
+    /// Create the symlink `path` pointing to `target`
+    pub fn symlink(&self, target: &str, path: &Path) -> io::Result<()> {
+        let (dir, name) = self.parent(path)?;
+        let target = CString::new(target)?;
+        // SAFETY: the descriptor is open and both strings NUL terminated
+        check(unsafe { libc::symlinkat(target.as_ptr(), dir.as_raw_fd(), name.as_ptr()) })
+    }
+
+    /// Create the hard link `to` of `from` (of a symlink itself)
+    pub fn link(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from_dir, from_name) = self.parent(from)?;
+        let (to_dir, to_name) = self.parent(to)?;
+        // SAFETY: both descriptors are open and both names NUL terminated
+        check(unsafe {
+            libc::linkat(from_dir.as_raw_fd(), from_name.as_ptr(), to_dir.as_raw_fd(), to_name.as_ptr(), 0)
+        })
+    }
+
+    /// Change the owner and/or group of `path` (of a symlink itself)
+    pub fn chown(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        let (dir, name) = self.parent(path)?;
+        // -1 leaves the id unchanged
+        let (uid, gid) = (uid.unwrap_or(u32::MAX), gid.unwrap_or(u32::MAX));
+        // SAFETY: the descriptor is open and name is NUL terminated
+        check(unsafe { libc::fchownat(dir.as_raw_fd(), name.as_ptr(), uid, gid, libc::AT_SYMLINK_NOFOLLOW) })
+    }
+
+    /// Create the special file `path` with mknod(2) `mode` (type and permissions)
+    pub fn mknod(&self, path: &Path, mode: u32, dev: libc::dev_t) -> io::Result<()> {
+        let (dir, name) = self.parent(path)?;
+        // SAFETY: the descriptor is open and name is NUL terminated
+        check(unsafe { libc::mknodat(dir.as_raw_fd(), name.as_ptr(), mode as libc::mode_t, dev) })
+    }
+
+    /// Open `path` itself for its extended attributes (fgetxattr and friends)
+    ///
+    /// Opened read-only without following a final symlink, and non-blocking
+    /// so that a FIFO does not wait for a writer.
+    pub fn open_attributes(&self, path: &Path) -> io::Result<File> {
+        let flags = libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_NONBLOCK | libc::O_NOCTTY;
+        self.open_file(path, flags, 0)
+    }
+
+    /// Names in the directory `path`, without "." and "..", in listing order
+    pub fn read_dir(&self, path: &Path) -> io::Result<Vec<std::ffi::OsString>> {
+        let dir = self.open_beneath(path, libc::O_RDONLY | libc::O_DIRECTORY, 0)?;
+        // SAFETY: the descriptor is open; on success the stream owns it and
+        // closedir closes it
+        let stream = unsafe { libc::fdopendir(dir.as_raw_fd()) };
+        if stream.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let _ = dir.into_raw_fd();
+
+        let mut names = Vec::new();
+        let result = loop {
+            // readdir reports errors only through errno
+            // SAFETY: errno is thread-local
+            unsafe { *libc::__errno_location() = 0 };
+            // SAFETY: stream is a valid open directory stream
+            let entry = unsafe { libc::readdir64(stream) };
+            if entry.is_null() {
+                let error = io::Error::last_os_error();
+                break if error.raw_os_error() == Some(0) { Ok(names) } else { Err(error) };
+            }
+            // SAFETY: readdir returned an entry whose name is NUL terminated
+            let name = unsafe { std::ffi::CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if !matches!(name.to_bytes(), b"." | b"..") {
+                names.push(std::ffi::OsStr::from_bytes(name.to_bytes()).to_os_string());
+            }
+        };
+        // SAFETY: stream is open and not used after this
+        unsafe { libc::closedir(stream) };
+        result
+    }
+
+    /// Target of the symlink `path`
+    pub fn readlink(&self, path: &Path) -> io::Result<PathBuf> {
+        let (dir, name) = self.parent(path)?;
+        let mut buffer = vec![0u8; libc::PATH_MAX as usize];
+        // SAFETY: the descriptor is open, name is NUL terminated and the
+        // buffer holds the length passed
+        let len = unsafe {
+            libc::readlinkat(dir.as_raw_fd(), name.as_ptr(), buffer.as_mut_ptr().cast(), buffer.len())
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buffer.truncate(len as usize);
+        Ok(PathBuf::from(std::ffi::OsString::from_vec(buffer)))
+    }
+
+    /// statx of `path` itself (not following a symlink)
+    pub fn statx(&self, path: &Path, mask: u32) -> io::Result<libc::statx> {
+        let (dir, name) = self.parent(path)?;
+        // SAFETY: statx is plain data, all-zero is a valid value
+        let mut stx: libc::statx = unsafe { std::mem::zeroed() };
+        let flags = libc::AT_STATX_SYNC_AS_STAT | libc::AT_SYMLINK_NOFOLLOW;
+        // SAFETY: the descriptor is open, name is NUL terminated and stx
+        // outlives the call
+        check(unsafe { libc::statx(dir.as_raw_fd(), name.as_ptr(), flags, mask, &mut stx) })?;
+        Ok(stx)
+    }
+
+    /// openat2 relative to the root, retried while renames race with it
+    fn openat2(&self, relative: &Path, flags: i32, mode: u32) -> io::Result<OwnedFd> {
+        let c_path = CString::new(relative.as_os_str().as_bytes())?;
+        // SAFETY: open_how is plain data, all-zero is a valid value
+        let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+        how.flags = (flags | libc::O_CLOEXEC) as u64;
+        how.mode = if flags & (libc::O_CREAT | libc::O_TMPFILE) != 0 { mode as u64 } else { 0 };
+        how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_SYMLINKS | libc::RESOLVE_NO_MAGICLINKS;
+
+        let mut attempts = 0;
+        loop {
+            // SAFETY: the descriptor is open, c_path is NUL terminated and
+            // `how` outlives the call, whose size argument matches it
+            let fd = unsafe {
+                libc::syscall(
+                    libc::SYS_openat2,
+                    self.dir.as_raw_fd(),
+                    c_path.as_ptr(),
+                    &how as *const libc::open_how,
+                    std::mem::size_of::<libc::open_how>(),
+                )
+            };
+            if fd >= 0 {
+                // SAFETY: openat2 returned a new descriptor we now own
+                return Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) });
+            }
+            let error = io::Error::last_os_error();
+            attempts += 1;
+            if error.raw_os_error() != Some(libc::EAGAIN) || attempts == MAX_ATTEMPTS {
+                return Err(error);
+            }
+            debug!("openat2 of {:?} raced with a rename, retrying", relative);
+        }
+    }
+}
+
+/// Turn the return value of a syscall into its error
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// open(2) by path
+fn raw_open(path: &Path, flags: i32, mode: u32) -> io::Result<OwnedFd> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: c_path is NUL terminated; mode is read only with O_CREAT
+    let fd = unsafe { libc::open(c_path.as_ptr(), flags | libc::O_CLOEXEC, mode as libc::c_uint) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: open returned a new descriptor we now own
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Check that a path resolves inside a (canonical) root
+///
+/// An existing path is canonicalized; for one that does not exist yet the
+/// parent is, and the final component must not be a traversal.
+pub fn validate(root: &Path, path: &Path) -> Result<()> {
+    let absolute_path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        // This shouldn't happen in our code, but handle it defensively
+        root.join(path)
+    };
+
+    let canonical = if absolute_path.exists() {
+        absolute_path
+            .canonicalize()
+            .context(format!("Failed to canonicalize existing path: {:?}", path))?
+    } else {
+        let parent = absolute_path
+            .parent()
+            .ok_or_else(|| anyhow!("Path has no parent: {:?}", absolute_path))?;
+        let canonical_parent = parent
+            .canonicalize()
+            .context(format!("Failed to canonicalize parent path: {:?}", parent))?;
+        if !canonical_parent.starts_with(root) {
+            warn!(
+                "Path traversal attempt: parent {:?} is outside root {:?}",
+                canonical_parent, root
+            );
+            return Err(anyhow!("Path is outside export root"));
+        }
+
+        // The final component must not be a traversal attempt
+        let file_name = absolute_path
+            .file_name()
+            .ok_or_else(|| anyhow!("Path has no filename component"))?;
+        let file_name_str = file_name
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid filename encoding"))?;
+        if file_name_str.contains("..") || file_name_str.contains('/') {
+            return Err(anyhow!("Invalid filename: {}", file_name_str));
+        }
+        canonical_parent.join(file_name)
+    };
+
+    if !canonical.starts_with(root) {
+        warn!(
+            "Path traversal attempt: {:?} is outside root {:?}",
+            canonical, root
+        );
+        return Err(anyhow!("Path is outside export root"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{Read, Write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_symlink_out_of_root_is_not_traversed() {
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("secret"), b"secret").unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let root_path = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir(root_path.join("dir")).unwrap();
+        fs::write(root_path.join("dir/file"), b"data").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root_path.join("escape")).unwrap();
+        let root = ExportRoot::open(&root_path).unwrap();
+
+        let mut contents = String::new();
+        root.open_file(&root_path.join("dir/file"), libc::O_RDONLY, 0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "data");
+
+        // Neither reading nor writing through the symlink reaches outside
+        assert!(root.open_file(&root_path.join("escape/secret"), libc::O_RDONLY, 0).is_err());
+        assert!(root.open_file(&root_path.join("escape/secret"), libc::O_WRONLY | libc::O_TRUNC, 0).is_err());
+        assert!(root.open_file(&root_path.join("escape/new"), libc::O_WRONLY | libc::O_CREAT, 0o644).is_err());
+        assert!(!outside.path().join("new").exists());
+        assert_eq!(fs::read(outside.path().join("secret")).unwrap(), b"secret");
+
+        // A directory swapped for such a symlink after its path was handed
+        // out is not traversed either
+        fs::rename(root_path.join("dir"), root_path.join("moved")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), root_path.join("dir")).unwrap();
+        fs::write(outside.path().join("file"), b"outside").unwrap();
+        let opened = root.open_file(&root_path.join("dir/file"), libc::O_RDWR, 0);
+        assert!(opened.is_err());
+        let mut file = root.open_file(&root_path.join("moved/file"), libc::O_WRONLY, 0).unwrap();
+        file.write_all(b"DATA").unwrap();
+
+        // Paths outside the root are refused outright
+        assert_eq!(
+            root.open_file(&outside.path().join("secret"), libc::O_RDONLY, 0).unwrap_err().raw_os_error(),
+            Some(libc::EXDEV)
+        );
+    }
+}
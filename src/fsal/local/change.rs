@@ -0,0 +1,238 @@
+// Change Tracker
+//
+// Clients validate their caches by comparing ctime and mtime with what they
+// saw last. On filesystems with coarse timestamps two mutations can land in
+// the same tick, leaving the times unchanged and the client serving stale
+// data. The tracker remembers the times last reported for each inode; after
+// a mutation made through the server, a report whose on-disk time has not
+// moved past the previous one is advanced by one nanosecond instead.
+//
+// Reported times therefore never go backwards and always move after a
+// mutation through the server. Changes made behind the server's back are
+// only seen when the on-disk time moves.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::super::{FileAttributes, FileHandle, FileTime};
+
+/// Default maximum number of tracked inodes before the tracker is reset
+pub const DEFAULT_MAX_ENTRIES: usize = 65536;
+
+/// Times last reported for an inode
+struct Reported {
+    ctime: FileTime,
+    mtime: FileTime,
+    /// Metadata or data changed since the last report
+    ctime_pending: bool,
+    /// Data changed since the last report
+    mtime_pending: bool,
+    /// mtime was set explicitly, so the on-disk one is reported even if older
+    mtime_set: bool,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_fileid: HashMap<u64, Reported>,
+    /// Inode each reported handle refers to
+    fileids: HashMap<FileHandle, u64>,
+}
+
+/// Inode → last reported ctime/mtime
+pub struct ChangeTracker {
+    entries: Mutex<Entries>,
+    /// Entry count at which the tracker is reset
+    max_entries: usize,
+}
+
+impl Default for ChangeTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+impl ChangeTracker {
+    /// Create an empty tracker
+    ///
+    /// Reaching `max_entries` forgets every reported time, after which
+    /// reports fall back to the on-disk times.
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: Mutex::new(Entries::default()),
+            max_entries: max_entries.max(1),
+        }
+    }
+
+    /// Record attributes about to be reported, advancing their times if needed
+    ///
+    /// # Arguments
+    /// * `handle` - Handle the attributes were read through
+    /// * `attrs` - Attributes built from the on-disk metadata
+    pub fn report(&self, handle: &FileHandle, attrs: &mut FileAttributes) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.by_fileid.len() >= self.max_entries && !entries.by_fileid.contains_key(&attrs.fileid) {
+            entries.by_fileid.clear();
+            entries.fileids.clear();
+        }
+        if entries.fileids.len() >= self.max_entries {
+            entries.fileids.clear();
+        }
+        entries.fileids.insert(handle.clone(), attrs.fileid);
+
+        let reported = entries.by_fileid.entry(attrs.fileid).or_insert(Reported {
+            ctime: attrs.ctime,
+            mtime: attrs.mtime,
+            ctime_pending: false,
+            mtime_pending: false,
+            mtime_set: false,
+        });
+        attrs.ctime = advance(&mut reported.ctime, attrs.ctime, reported.ctime_pending);
+        if reported.mtime_set {
+            reported.mtime = attrs.mtime;
+        } else {
+            attrs.mtime = advance(&mut reported.mtime, attrs.mtime, reported.mtime_pending);
+        }
+        reported.ctime_pending = false;
+        reported.mtime_pending = false;
+        reported.mtime_set = false;
+    }
+
+    /// Note that the data behind a handle changed (advances ctime and mtime)
+    pub fn data_changed(&self, handle: &FileHandle) {
+        self.mark(handle, true);
+    }
+
+    /// Note that the metadata behind a handle changed (advances ctime)
+    pub fn metadata_changed(&self, handle: &FileHandle) {
+        self.mark(handle, false);
+    }
+
+    /// Note that the times behind a handle were set explicitly (advances
+    /// ctime; the next mtime is reported as set, even if it went backwards)
+    pub fn times_set(&self, handle: &FileHandle) {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(&fileid) = entries.fileids.get(handle) else {
+            return;
+        };
+        if let Some(reported) = entries.by_fileid.get_mut(&fileid) {
+            reported.ctime_pending = true;
+            reported.mtime_set = true;
+        }
+    }
+
+    /// Note that the metadata of an inode changed (advances ctime)
+    pub fn metadata_changed_fileid(&self, fileid: u64) {
+        if let Some(reported) = self.entries.lock().unwrap().by_fileid.get_mut(&fileid) {
+            reported.ctime_pending = true;
+        }
+    }
+
+    /// Forget an inode that was freed, so a reuse of its number starts afresh
+    pub fn forget_fileid(&self, fileid: u64) {
+        self.entries.lock().unwrap().by_fileid.remove(&fileid);
+    }
+
+    fn mark(&self, handle: &FileHandle, data: bool) {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(&fileid) = entries.fileids.get(handle) else {
+            // Never reported, so there is no earlier time to move past
+            return;
+        };
+        if let Some(reported) = entries.by_fileid.get_mut(&fileid) {
+            reported.ctime_pending = true;
+            reported.mtime_pending |= data;
+        }
+    }
+}
+
+/// Time to report given the last reported one and the on-disk one
+fn advance(reported: &mut FileTime, on_disk: FileTime, pending: bool) -> FileTime {
+    if on_disk > *reported {
+        *reported = on_disk;
+    } else if pending {
+        *reported = if reported.nseconds >= 999_999_999 {
+            FileTime { seconds: reported.seconds + 1, nseconds: 0 }
+        } else {
+            FileTime { seconds: reported.seconds, nseconds: reported.nseconds + 1 }
+        };
+    }
+    *reported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsal::FileType;
+
+    fn attrs(fileid: u64, seconds: u64) -> FileAttributes {
+        let time = FileTime { seconds, nseconds: 0 };
+        FileAttributes {
+            ftype: FileType::RegularFile,
+            mode: 0o644,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            used: 0,
+            rdev: (0, 0),
+            fsid: 0,
+            fileid,
+            atime: time,
+            mtime: time,
+            ctime: time,
+        }
+    }
+
+    #[test]
+    fn test_same_tick_mutations_advance_times() {
+        let tracker = ChangeTracker::default();
+        let handle = vec![1u8; 16];
+
+        let mut first = attrs(7, 100);
+        tracker.report(&handle, &mut first);
+        assert_eq!(first.ctime, FileTime { seconds: 100, nseconds: 0 });
+
+        // Unchanged on disk and nothing happened: stable
+        let mut again = attrs(7, 100);
+        tracker.report(&handle, &mut again);
+        assert_eq!(again.ctime, first.ctime);
+
+        // A metadata change within the same tick advances ctime only
+        tracker.metadata_changed(&handle);
+        let mut chmod = attrs(7, 100);
+        tracker.report(&handle, &mut chmod);
+        assert!(chmod.ctime > first.ctime);
+        assert_eq!(chmod.mtime, first.mtime);
+
+        // A data change advances both, and never below what was reported
+        tracker.data_changed(&handle);
+        let mut write = attrs(7, 100);
+        tracker.report(&handle, &mut write);
+        assert!(write.ctime > chmod.ctime);
+        assert!(write.mtime > chmod.mtime);
+
+        // Once the disk moves ahead its times are reported as they are
+        let mut later = attrs(7, 101);
+        tracker.report(&handle, &mut later);
+        assert_eq!(later.ctime, FileTime { seconds: 101, nseconds: 0 });
+    }
+
+    #[test]
+    fn test_hard_links_share_times() {
+        let tracker = ChangeTracker::default();
+        let (link_a, link_b) = (vec![1u8; 16], vec![2u8; 16]);
+
+        let mut a = attrs(7, 100);
+        tracker.report(&link_a, &mut a);
+        tracker.data_changed(&link_a);
+
+        let mut b = attrs(7, 100);
+        tracker.report(&link_b, &mut b);
+        assert!(b.mtime > a.mtime);
+
+        tracker.metadata_changed_fileid(7);
+        let mut a_again = attrs(7, 100);
+        tracker.report(&link_a, &mut a_again);
+        assert!(a_again.ctime > b.ctime);
+    }
+}
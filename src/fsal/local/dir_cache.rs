@@ -0,0 +1,141 @@
+// Directory Listing Cache
+//
+// Clients list a large directory in a run of READDIR calls, each resuming
+// at the cookie the previous one ended with. Cookies are positions in the
+// listing, so without a cache every continuation reads the directory from
+// the start to find its position, and a listing costs O(n²).
+//
+// The first READDIR of a directory keeps a snapshot of its names in listing
+// order; continuations are served from it. A snapshot is used only while
+// the directory's change attribute (mtime, ctime, size) is the one it was
+// taken with, which is also what the cookie verifier is derived from, so a
+// snapshot never outlives the cookies issued from it. Changes made through
+// the server drop the snapshot at once, and a TTL bounds how long one is
+// kept on filesystems with coarse timestamps.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::attr_cache::ChangeAttr;
+use super::super::FileHandle;
+
+/// Default time a directory snapshot is kept (zero disables the cache)
+pub const DEFAULT_TTL: Duration = Duration::from_secs(5);
+
+/// Number of snapshots at which the cache is reset
+const MAX_SNAPSHOTS: usize = 16;
+
+/// Names of a directory in listing order, as read at one point in time
+pub type Names = Arc<Vec<OsString>>;
+
+struct Snapshot {
+    change: ChangeAttr,
+    taken: Instant,
+    names: Names,
+}
+
+/// Directory handle → snapshot of its names
+pub struct DirCache {
+    ttl: Duration,
+    snapshots: Mutex<HashMap<FileHandle, Snapshot>>,
+}
+
+impl Default for DirCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+impl DirCache {
+    /// Create a cache keeping snapshots for `ttl`; zero disables it
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            snapshots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Snapshot of a directory, if one is fresh and the directory unchanged
+    ///
+    /// # Arguments
+    /// * `handle` - Directory handle
+    /// * `change` - Current change attribute of the directory
+    pub fn get(&self, handle: &FileHandle, change: &ChangeAttr) -> Option<Names> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let snapshot = snapshots.get(handle)?;
+        if snapshot.change == *change && snapshot.taken.elapsed() < self.ttl {
+            return Some(snapshot.names.clone());
+        }
+        snapshots.remove(handle);
+        None
+    }
+
+    /// Keep the names of a directory read with the given change attribute
+    pub fn insert(&self, handle: &FileHandle, change: ChangeAttr, names: Names) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut snapshots = self.snapshots.lock().unwrap();
+        if snapshots.len() >= MAX_SNAPSHOTS && !snapshots.contains_key(handle) {
+            snapshots.clear();
+        }
+        let taken = Instant::now();
+        snapshots.insert(handle.clone(), Snapshot { change, taken, names });
+    }
+
+    /// Drop the snapshot of a directory whose entries changed
+    pub fn invalidate(&self, handle: &FileHandle) {
+        self.snapshots.lock().unwrap().remove(handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn change_of(path: &std::path::Path) -> ChangeAttr {
+        ChangeAttr::from_metadata(&fs::metadata(path).unwrap())
+    }
+
+    #[test]
+    fn test_snapshot_follows_directory_changes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = DirCache::new(Duration::from_secs(60));
+        let handle = vec![1u8; 8];
+        let names: Names = Arc::new(vec!["a".into(), "b".into()]);
+
+        let change = change_of(temp_dir.path());
+        cache.insert(&handle, change, names.clone());
+        assert_eq!(cache.get(&handle, &change), Some(names.clone()));
+
+        // A new entry changes the directory, and the snapshot is dropped
+        std::thread::sleep(Duration::from_millis(10));
+        fs::write(temp_dir.path().join("c"), b"").unwrap();
+        assert_eq!(cache.get(&handle, &change_of(temp_dir.path())), None);
+        assert_eq!(cache.get(&handle, &change), None, "A stale snapshot is not kept");
+
+        cache.insert(&handle, change, names);
+        cache.invalidate(&handle);
+        assert_eq!(cache.get(&handle, &change), None);
+    }
+
+    #[test]
+    fn test_snapshot_expires() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let change = change_of(temp_dir.path());
+        let handle = vec![1u8; 8];
+
+        let cache = DirCache::new(Duration::from_millis(20));
+        cache.insert(&handle, change, Arc::new(Vec::new()));
+        assert!(cache.get(&handle, &change).is_some());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(cache.get(&handle, &change).is_none());
+
+        let disabled = DirCache::new(Duration::ZERO);
+        disabled.insert(&handle, change, Arc::new(Vec::new()));
+        assert!(disabled.get(&handle, &change).is_none());
+    }
+}
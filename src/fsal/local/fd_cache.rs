@@ -0,0 +1,374 @@
+// File Descriptor Cache
+//
+// Keeps recently used files open so that a run of READ or WRITE calls on the
+// same file costs one open instead of an open and close per call. Entries
+// are keyed by file handle and evicted least recently used first.
+//
+// Every descriptor the cache opens holds a permit from a counting semaphore
+// sized to the cap, so the backend never has more files open at once than
+// configured. When no permit is free the least recently used entry is
+// evicted; its descriptor closes as soon as any in-flight call using it
+// finishes. Callers wait for a permit rather than exceed the cap.
+//
+// Names removed or renamed through the server drop their entries. A file
+// replaced behind the server's back keeps being served from the old
+// descriptor until its entry is evicted.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+use super::super::FileHandle;
+use super::beneath::ExportRoot;
+
+/// Default maximum number of files the cache keeps open
+pub const DEFAULT_MAX_OPEN_FILES: usize = 256;
+
+/// How long a caller waiting for a permit sleeps before retrying eviction
+const PERMIT_RETRY: Duration = Duration::from_millis(10);
+
+/// Counting semaphore bounding the number of open descriptors
+struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A held semaphore slot, returned when dropped
+struct Permit {
+    semaphore: Arc<Semaphore>,
+}
+
+impl Semaphore {
+    fn new(permits: usize) -> Arc<Self> {
+        Arc::new(Self {
+            available: Mutex::new(permits),
+            released: Condvar::new(),
+        })
+    }
+
+    /// Take a permit if one is free
+    fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let mut available = self.available.lock().unwrap();
+        if *available == 0 {
+            return None;
+        }
+        *available -= 1;
+        Some(Permit { semaphore: Arc::clone(self) })
+    }
+
+    /// Wait until a permit is released or the timeout expires
+    fn wait_for_release(&self, timeout: Duration) {
+        let available = self.available.lock().unwrap();
+        if *available == 0 {
+            let _ = self.released.wait_timeout(available, timeout).unwrap();
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        *self.semaphore.available.lock().unwrap() += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+/// An open file handed out by the cache
+///
+/// The descriptor is closed, and its permit returned, when the last
+/// reference is dropped.
+pub struct OpenFile {
+    file: File,
+    readable: bool,
+    writable: bool,
+    _permit: Option<Permit>,
+}
+
+impl Deref for OpenFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        &self.file
+    }
+}
+
+/// A cached descriptor and when it was last used
+struct Slot {
+    file: Arc<OpenFile>,
+    last_used: u64,
+}
+
+/// Cached descriptors with a use counter for LRU ordering
+#[derive(Default)]
+struct Slots {
+    map: HashMap<FileHandle, Slot>,
+    tick: u64,
+}
+
+/// Handle → open file LRU cache
+pub struct FdCache {
+    slots: Mutex<Slots>,
+    permits: Arc<Semaphore>,
+    /// Maximum number of open descriptors (zero disables caching)
+    capacity: usize,
+    /// Number of files opened so far
+    opens: AtomicU64,
+}
+
+impl Default for FdCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_OPEN_FILES)
+    }
+}
+
+impl FdCache {
+    /// Create a cache keeping at most `capacity` files open
+    ///
+    /// The capacity is clamped to half the process descriptor limit, leaving
+    /// the rest for sockets and other files. A capacity of zero disables
+    /// caching: every call opens and closes its own file.
+    pub fn new(capacity: usize) -> Self {
+        let limit = fd_limit() / 2;
+        let capacity = if capacity > limit {
+            warn!("max_open_files {} exceeds half the descriptor limit, using {}", capacity, limit);
+            limit
+        } else {
+            capacity
+        };
+
+        Self {
+            slots: Mutex::new(Slots::default()),
+            permits: Semaphore::new(capacity),
+            capacity,
+            opens: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of files opened so far
+    pub fn open_count(&self) -> u64 {
+        self.opens.load(Ordering::Relaxed)
+    }
+
+    /// Get an open file for a handle, opening it if it is not cached
+    ///
+    /// A cached descriptor is reused when it allows the requested access and
+    /// replaced otherwise. Writable opens also ask for read access, so that
+    /// reads following writes share the descriptor, and create the file if
+    /// it does not exist.
+    ///
+    /// # Arguments
+    /// * `root` - Export root the file is opened beneath on a miss
+    /// * `handle` - Handle the file is cached under
+    /// * `path` - Path to open on a miss
+    /// * `writable` - Whether the caller needs to write
+    pub fn get(
+        &self,
+        root: &ExportRoot,
+        handle: &FileHandle,
+        path: &Path,
+        writable: bool,
+    ) -> io::Result<Arc<OpenFile>> {
+        if self.capacity == 0 {
+            return Ok(Arc::new(self.open(root, path, writable, None)?));
+        }
+
+        {
+            let mut slots = self.slots.lock().unwrap();
+            slots.tick += 1;
+            let tick = slots.tick;
+            if let Some(slot) = slots.map.get_mut(handle)
+                && (if writable { slot.file.writable } else { slot.file.readable })
+            {
+                slot.last_used = tick;
+                return Ok(Arc::clone(&slot.file));
+            }
+        }
+
+        let permit = self.acquire();
+        let file = Arc::new(self.open(root, path, writable, Some(permit))?);
+
+        let mut slots = self.slots.lock().unwrap();
+        slots.tick += 1;
+        let last_used = slots.tick;
+        slots.map.insert(handle.clone(), Slot { file: Arc::clone(&file), last_used });
+        Ok(file)
+    }
+
+    /// Drop the cached descriptor of a handle
+    pub fn invalidate(&self, handle: &FileHandle) {
+        self.slots.lock().unwrap().map.remove(handle);
+    }
+
+    /// Drop every cached descriptor
+    ///
+    /// Descriptors still in use close when their last user finishes.
+    pub fn clear(&self) {
+        self.slots.lock().unwrap().map.clear();
+    }
+
+    /// Number of cached descriptors
+    pub fn len(&self) -> usize {
+        self.slots.lock().unwrap().map.len()
+    }
+
+    /// Take a permit, evicting cached descriptors until one is free
+    fn acquire(&self) -> Permit {
+        loop {
+            if let Some(permit) = self.permits.try_acquire() {
+                return permit;
+            }
+
+            // An evicted descriptor still in use returns its permit once the
+            // caller holding it finishes, so wait a little and retry
+            let evicted = {
+                let mut slots = self.slots.lock().unwrap();
+                let oldest = slots
+                    .map
+                    .iter()
+                    .min_by_key(|(_, slot)| slot.last_used)
+                    .map(|(handle, _)| handle.clone());
+                oldest.and_then(|handle| slots.map.remove(&handle))
+            };
+            if evicted.is_none() {
+                self.permits.wait_for_release(PERMIT_RETRY);
+            }
+        }
+    }
+
+    fn open(&self, root: &ExportRoot, path: &Path, writable: bool, permit: Option<Permit>) -> io::Result<OpenFile> {
+        let open_for_write = |read: bool| {
+            self.opens.fetch_add(1, Ordering::Relaxed);
+            let access = if read { libc::O_RDWR } else { libc::O_WRONLY };
+            root.open_file(path, access | libc::O_CREAT, 0o666)
+        };
+
+        let (file, readable) = if !writable {
+            self.opens.fetch_add(1, Ordering::Relaxed);
+            (root.open_file(path, libc::O_RDONLY, 0)?, true)
+        } else {
+            // Write-only files can still be written
+            match open_for_write(true) {
+                Ok(file) => (file, true),
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => (open_for_write(false)?, false),
+                Err(e) => return Err(e),
+            }
+        };
+        Ok(OpenFile { file, readable, writable, _permit: permit })
+    }
+}
+
+/// Soft limit on open descriptors for this process
+fn fd_limit() -> usize {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 || limit.rlim_cur == libc::RLIM_INFINITY {
+        return usize::MAX;
+    }
+    limit.rlim_cur as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::FileExt;
+    use tempfile::TempDir;
+
+    fn handle(id: u8) -> FileHandle {
+        vec![id; 16]
+    }
+
+    fn root(temp_dir: &TempDir) -> ExportRoot {
+        ExportRoot::open(temp_dir.path()).unwrap()
+    }
+
+    #[test]
+    fn test_reuses_and_upgrades_descriptors() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("file");
+        fs::write(&path, b"data").unwrap();
+        let root = root(&temp_dir);
+        let cache = FdCache::new(4);
+
+        cache.get(&root, &handle(1), &path, false).unwrap();
+        cache.get(&root, &handle(1), &path, false).unwrap();
+        assert_eq!(cache.open_count(), 1);
+
+        // A write needs a writable descriptor, which then serves reads too
+        cache.get(&root, &handle(1), &path, true).unwrap().write_at(b"DA", 0).unwrap();
+        let mut buffer = [0u8; 4];
+        cache.get(&root, &handle(1), &path, false).unwrap().read_at(&mut buffer, 0).unwrap();
+        assert_eq!(&buffer, b"DAta");
+        assert_eq!(cache.open_count(), 2);
+
+        cache.invalidate(&handle(1));
+        cache.get(&root, &handle(1), &path, false).unwrap();
+        assert_eq!(cache.open_count(), 3);
+    }
+
+    #[test]
+    fn test_capacity_bounds_open_descriptors() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = root(&temp_dir);
+        let cache = FdCache::new(2);
+        let paths: Vec<_> = (0..3u8)
+            .map(|id| {
+                let path = temp_dir.path().join(id.to_string());
+                fs::write(&path, [id]).unwrap();
+                path
+            })
+            .collect();
+
+        cache.get(&root, &handle(0), &paths[0], false).unwrap();
+        cache.get(&root, &handle(1), &paths[1], false).unwrap();
+        cache.get(&root, &handle(0), &paths[0], false).unwrap();
+
+        // The third file evicts the least recently used one (handle 1)
+        let held = cache.get(&root, &handle(2), &paths[2], false).unwrap();
+        assert_eq!(cache.slots.lock().unwrap().map.len(), 2);
+        assert!(cache.slots.lock().unwrap().map.contains_key(&handle(0)));
+
+        drop(held);
+    }
+
+    #[test]
+    fn test_busy_descriptor_holds_its_permit() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = temp_dir.path().join("first");
+        let second = temp_dir.path().join("second");
+        fs::write(&first, b"1").unwrap();
+        fs::write(&second, b"2").unwrap();
+        let root = root(&temp_dir);
+        let cache = FdCache::new(1);
+
+        // Opening a second file evicts the first, but the descriptor is
+        // still in use, so the open waits until it is released
+        let held = cache.get(&root, &handle(1), &first, false).unwrap();
+        std::thread::scope(|scope| {
+            let opener = scope.spawn(|| cache.get(&root, &handle(2), &second, false).unwrap());
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!opener.is_finished());
+            drop(held);
+            opener.join().unwrap();
+        });
+        assert_eq!(cache.open_count(), 2);
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_caching() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("file");
+        fs::write(&path, b"data").unwrap();
+        let root = root(&temp_dir);
+        let cache = FdCache::new(0);
+
+        cache.get(&root, &handle(1), &path, false).unwrap();
+        cache.get(&root, &handle(1), &path, false).unwrap();
+        assert_eq!(cache.open_count(), 2);
+        assert!(cache.slots.lock().unwrap().map.is_empty());
+    }
+}
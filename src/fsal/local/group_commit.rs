@@ -0,0 +1,178 @@
+// Group Commit
+//
+// Databases on NFS send many small UNSTABLE WRITEs, each followed by a
+// COMMIT of the same file, so every COMMIT costs a sync. With a window
+// configured, the first COMMIT of a file waits that long for others (from
+// other connections; one connection's calls run in turn) and then syncs the
+// whole file once on behalf of all of them.
+//
+// A COMMIT only joins a batch whose sync has not started yet, so the sync
+// answering it always begins after the COMMIT arrived and covers every write
+// acknowledged before it. COMMITs arriving while a sync runs wait for it to
+// finish and form the next batch.
+
+use std::collections::HashMap;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::fsal::FileHandle;
+
+/// Default batching window (zero syncs every COMMIT on its own)
+pub const DEFAULT_WINDOW: Duration = Duration::ZERO;
+
+/// Outcome of a batch's sync: success or the errno it failed with
+type SyncResult = Result<(), i32>;
+
+/// COMMITs answered by one sync
+#[derive(Default)]
+struct Batch {
+    result: Mutex<Option<SyncResult>>,
+    synced: Condvar,
+}
+
+impl Batch {
+    fn finish(&self, result: SyncResult) {
+        *self.result.lock().unwrap() = Some(result);
+        self.synced.notify_all();
+    }
+
+    fn wait(&self) -> SyncResult {
+        let mut result = self.result.lock().unwrap();
+        loop {
+            if let Some(result) = *result {
+                return result;
+            }
+            result = self.synced.wait(result).unwrap();
+        }
+    }
+}
+
+/// Batching state of one file
+#[derive(Default)]
+struct FileState {
+    /// Batch still taking COMMITs; its sync has not started
+    open: Option<Arc<Batch>>,
+    /// A sync of the file is running
+    syncing: bool,
+}
+
+/// File handle → COMMIT batch
+pub struct GroupCommit {
+    window: Duration,
+    files: Mutex<HashMap<FileHandle, FileState>>,
+    /// Signalled whenever a file's sync finishes
+    idle: Condvar,
+}
+
+impl Default for GroupCommit {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl GroupCommit {
+    /// Batch COMMITs arriving within `window` of each other; zero disables batching
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            files: Mutex::new(HashMap::new()),
+            idle: Condvar::new(),
+        }
+    }
+
+    /// Whether COMMITs are batched
+    pub fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    /// Commit a file, sharing one sync with the COMMITs of it that arrive alongside
+    ///
+    /// Returns once a sync of the file that started after this call has
+    /// completed, with that sync's result.
+    ///
+    /// # Arguments
+    /// * `handle` - File being committed
+    /// * `sync` - Syncs the whole file; run by one of the batched callers
+    pub fn commit(&self, handle: &FileHandle, sync: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let batch = loop {
+            let state = files.entry(handle.clone()).or_default();
+            if let Some(batch) = state.open.clone() {
+                drop(files);
+                return batch.wait().map_err(io::Error::from_raw_os_error);
+            }
+            if !state.syncing {
+                let batch = Arc::new(Batch::default());
+                state.open = Some(batch.clone());
+                break batch;
+            }
+            files = self.idle.wait(files).unwrap();
+        };
+        drop(files);
+
+        // Lead the batch: give other COMMITs the window to join, then close it
+        std::thread::sleep(self.window);
+        {
+            let mut files = self.files.lock().unwrap();
+            let state = files.entry(handle.clone()).or_default();
+            state.open = None;
+            state.syncing = true;
+        }
+
+        // The batch's waiters are answered even if the sync panics
+        let result = panic::catch_unwind(AssertUnwindSafe(sync));
+        self.files.lock().unwrap().remove(handle);
+        self.idle.notify_all();
+        match result {
+            Ok(result) => {
+                batch.finish(result.as_ref().map(|_| ()).map_err(|e| e.raw_os_error().unwrap_or(libc::EIO)));
+                result
+            }
+            Err(payload) => {
+                batch.finish(Err(libc::EIO));
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_failed_sync_fails_whole_batch() {
+        const COMMITS: usize = 4;
+        let group = GroupCommit::new(Duration::from_millis(200));
+        let barrier = Barrier::new(COMMITS);
+        let syncs = AtomicUsize::new(0);
+
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..COMMITS)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        group.commit(&vec![1], || {
+                            syncs.fetch_add(1, Ordering::Relaxed);
+                            Err(io::Error::from_raw_os_error(libc::ENOSPC))
+                        })
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+
+        assert_eq!(syncs.load(Ordering::Relaxed), 1);
+        for result in results {
+            assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::ENOSPC));
+        }
+
+        // The next COMMIT gets a sync of its own
+        group.commit(&vec![1], || Ok(())).unwrap();
+        assert!(group.files.lock().unwrap().is_empty());
+    }
+}
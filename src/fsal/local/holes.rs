@@ -0,0 +1,83 @@
+// Sparse File Reads
+//
+// Sparse files (VM images, databases) have holes: ranges never written,
+// which read as zeros without any blocks behind them. A READ entirely
+// inside a hole is answered with zeros from a shared buffer instead of
+// reading the range, once SEEK_DATA shows no data between the start of the
+// READ and its end. Files with as many blocks as their size have no holes
+// and are read without the extra lseek. A READ that overlaps data in any
+// part is read as usual.
+
+use bytes::Bytes;
+use std::fs::File;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+
+/// Zeros served for holes; larger holes get a buffer of their own
+static ZEROS: [u8; 1024 * 1024] = [0; 1024 * 1024];
+
+/// Zeros for a range of `length` bytes at `offset`, if it lies in a hole
+///
+/// The range is clipped at end of file. Filesystems without SEEK_DATA, and
+/// any failure to check, leave the range to be read.
+///
+/// # Returns
+/// The zeros to serve, or None if the range holds data (or is past end of file)
+pub fn read_hole(file: &File, offset: u64, length: usize) -> Option<Bytes> {
+    let metadata = file.metadata().ok()?;
+    if offset >= metadata.len() || metadata.blocks() * 512 >= metadata.len() {
+        return None;
+    }
+    let end = metadata.len().min(offset.saturating_add(length as u64));
+    let offset_arg = libc::off_t::try_from(offset).ok()?;
+
+    // The shared file offset moves, but READ and WRITE use positioned I/O
+    // SAFETY: lseek on a descriptor owned by `file`
+    let data = unsafe { libc::lseek(file.as_raw_fd(), offset_arg, libc::SEEK_DATA) };
+    let in_hole = if data < 0 {
+        // ENXIO: no data from offset to end of file
+        std::io::Error::last_os_error().raw_os_error() == Some(libc::ENXIO)
+    } else {
+        data as u64 >= end
+    };
+    if !in_hole {
+        return None;
+    }
+
+    let hole_len = (end - offset) as usize;
+    if hole_len <= ZEROS.len() {
+        Some(Bytes::from_static(&ZEROS[..hole_len]))
+    } else {
+        Some(Bytes::from(vec![0u8; hole_len]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::FileExt;
+
+    #[test]
+    fn test_hole_detection() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("sparse");
+        let file = File::options().read(true).write(true).create_new(true).open(&path).unwrap();
+        file.set_len(16 * 1024 * 1024).unwrap();
+        file.write_all_at(&[1u8; 4096], 0).unwrap();
+        file.write_all_at(&[2u8; 4096], 8 * 1024 * 1024).unwrap();
+
+        let zeros = read_hole(&file, 1024 * 1024, 64 * 1024).unwrap();
+        assert_eq!(zeros.len(), 64 * 1024);
+        assert!(zeros.iter().all(|&b| b == 0));
+
+        // Ranges touching data are read, and holes end at end of file
+        assert!(read_hole(&file, 0, 4096).is_none());
+        assert!(read_hole(&file, 8 * 1024 * 1024 - 4096, 8192).is_none());
+        assert_eq!(read_hole(&file, 16 * 1024 * 1024 - 100, 4096).unwrap().len(), 100);
+        assert!(read_hole(&file, 16 * 1024 * 1024, 4096).is_none());
+
+        // A dense file is never checked
+        std::fs::write(temp_dir.path().join("dense"), vec![0u8; 8192]).unwrap();
+        assert!(read_hole(&File::open(temp_dir.path().join("dense")).unwrap(), 0, 4096).is_none());
+    }
+}
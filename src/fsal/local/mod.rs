@@ -2,24 +2,97 @@
 //
 // Implements the Filesystem trait for local filesystem access.
 
+mod attr_cache;
+mod beneath;
+mod change;
+mod dir_cache;
+mod direct_io;
+mod fd_cache;
+mod group_commit;
+mod holes;
+mod readahead;
+mod statfs_cache;
+
 use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use std::collections::HashSet;
 use std::fs;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::fs::{FileExt, MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use tracing::{debug, warn};
-
-use super::handle::{FileHandle, HandleManager};
-use super::{DirEntry, FileAttributes, FileTime, FileType, Filesystem};
+use xattr::FileExt as _;
+
+use super::acl::{Acl, ACL_ACCESS_XATTR};
+use super::handle::{decode_handle, FileHandle, HandleManager};
+use super::{
+    DirEntry, DirEntryPlus, FileAttributes, FileTime, FileType, Filesystem, FsStats, FsalError, SetAttributes, SetTime,
+    StableHow,
+};
+use attr_cache::AttrCache;
+use beneath::ExportRoot;
+use change::ChangeTracker;
+use dir_cache::DirCache;
+use fd_cache::FdCache;
+use group_commit::GroupCommit;
+use readahead::Readahead;
+use statfs_cache::StatfsCache;
+
+pub use attr_cache::{DEFAULT_MAX_ENTRIES as DEFAULT_ATTR_CACHE_ENTRIES, DEFAULT_TTL as DEFAULT_ATTR_CACHE_TTL};
+pub use dir_cache::DEFAULT_TTL as DEFAULT_DIR_CACHE_TTL;
+pub use fd_cache::DEFAULT_MAX_OPEN_FILES;
+pub use group_commit::DEFAULT_WINDOW as DEFAULT_COMMIT_WINDOW;
+pub use readahead::DEFAULT_WINDOW as DEFAULT_READAHEAD;
+pub use statfs_cache::DEFAULT_TTL as DEFAULT_STATFS_CACHE_TTL;
+
+/// Default time the export root may take to answer at startup
+pub const DEFAULT_OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Directory entries with their paths and lstat metadata, as `scan_dir` lists them
+type ScannedEntries = Vec<(DirEntry, PathBuf, fs::Metadata)>;
 
 /// Local filesystem implementation
 pub struct LocalFilesystem {
     /// Root directory for exports
     root_path: PathBuf,
+    /// Root directory files are opened beneath
+    root_dir: ExportRoot,
     /// File handle manager
     handle_manager: HandleManager,
     /// Root file handle
     root_handle: FileHandle,
+    /// GETATTR cache, TTL-bound and validated by change attribute
+    attr_cache: AttrCache,
+    /// Times last reported per inode, kept advancing across mutations
+    changes: ChangeTracker,
+    /// Open files kept for reuse by READ, WRITE and COMMIT
+    fd_cache: FdCache,
+    /// Windows read ahead for sequential READs
+    readahead: Readahead,
+    /// Directory names kept for READDIR continuations
+    dir_cache: DirCache,
+    /// statvfs results kept for FSSTAT
+    statfs_cache: StatfsCache,
+    /// COMMITs of a file batched into one sync
+    group_commit: GroupCommit,
+    /// Write block-aligned data with O_DIRECT
+    direct_io: bool,
+    /// List "." and ".." first in every directory
+    dot_entries: bool,
+    /// Serializes SETATTR so a guard check and the update it protects are atomic
+    setattr_lock: Mutex<()>,
+    /// Files written UNSTABLE since their last full sync
+    unstable: Mutex<HashSet<FileHandle>>,
+    /// Number of syncs issued by WRITE and COMMIT
+    syncs: AtomicU64,
+    /// Number of stat-family calls issued by GETATTR, LOOKUP and READDIR
+    stats: AtomicU64,
+    /// Number of reads issued to disk by READ
+    reads: AtomicU64,
+    /// Number of directories read from disk by READDIR
+    dir_reads: AtomicU64,
 }
 
 impl LocalFilesystem {
@@ -41,95 +114,508 @@ impl LocalFilesystem {
             return Err(anyhow!("Root path is not a directory: {:?}", root_path));
         }
 
+        let root_dir = ExportRoot::open(&root_path)
+            .context(format!("Failed to open root path: {:?}", root_path))?;
+
         let handle_manager = HandleManager::new();
 
         // Create root handle
-        let root_handle = handle_manager.create_handle(root_path.clone());
+        let root_generation = fs_generation(&root_dir, &root_path, &metadata).unwrap_or(0);
+        let root_handle = handle_manager.create_handle(root_path.clone(), metadata.ino(), root_generation);
 
         debug!("LocalFilesystem created with root: {:?}", root_path);
 
         Ok(Self {
             root_path,
+            root_dir,
             handle_manager,
             root_handle,
+            attr_cache: AttrCache::default(),
+            changes: ChangeTracker::default(),
+            fd_cache: FdCache::default(),
+            readahead: Readahead::default(),
+            dir_cache: DirCache::default(),
+            statfs_cache: StatfsCache::default(),
+            group_commit: GroupCommit::default(),
+            direct_io: false,
+            dot_entries: false,
+            setattr_lock: Mutex::new(()),
+            unstable: Mutex::new(HashSet::new()),
+            syncs: AtomicU64::new(0),
+            stats: AtomicU64::new(0),
+            reads: AtomicU64::new(0),
+            dir_reads: AtomicU64::new(0),
         })
     }
 
+    /// Create a local filesystem backend, giving up after `timeout`
+    ///
+    /// `new` resolves and stats the root, which takes as long as the storage
+    /// behind it: forever for a hard-mounted NFS server that is down or an
+    /// automount that cannot be mounted. This runs `new` under
+    /// `with_root_timeout`, so startup fails with a clear error instead.
+    ///
+    /// # Arguments
+    /// * `root_path` - Root directory to export
+    /// * `timeout` - Time the root may take to answer; zero waits indefinitely
+    pub fn open_with_timeout<P: AsRef<Path>>(root_path: P, timeout: Duration) -> Result<Self> {
+        let root_path = root_path.as_ref().to_path_buf();
+        with_root_timeout(&root_path.clone(), timeout, move || Self::new(root_path))
+    }
+
+    /// Enable or disable the O_DIRECT write path
+    ///
+    /// When enabled, the block-aligned part of each WRITE bypasses the page
+    /// cache; unaligned offsets and tails are written through buffered I/O.
+    pub fn with_direct_io(mut self, enabled: bool) -> Self {
+        self.direct_io = enabled;
+        self
+    }
+
+    /// List "." and ".." at the start of every directory
+    ///
+    /// Some clients expect them in READDIR output. They take the first two
+    /// cookies; ".." of the export root is the root itself.
+    pub fn with_dot_entries(mut self, enabled: bool) -> Self {
+        self.dot_entries = enabled;
+        self
+    }
+
+    /// Configure the attribute cache
+    ///
+    /// Attributes younger than `ttl` are served without a syscall; older
+    /// ones are revalidated with a change-attribute probe. A zero TTL always
+    /// probes. The cache is reset when it reaches `max_entries`.
+    pub fn with_attr_cache(mut self, ttl: Duration, max_entries: usize) -> Self {
+        self.attr_cache = AttrCache::new(ttl, max_entries);
+        self
+    }
+
+    /// Configure the open file cache
+    ///
+    /// Up to `max_open_files` files stay open between READ, WRITE and COMMIT
+    /// calls; zero opens and closes a file on every call.
+    pub fn with_max_open_files(mut self, max_open_files: usize) -> Self {
+        self.fd_cache = FdCache::new(max_open_files);
+        self
+    }
+
+    /// Configure readahead
+    ///
+    /// A READ starting where the previous READ of the file ended reads
+    /// `window` bytes from disk, and the READs that follow within them are
+    /// served from memory. Zero reads exactly what each READ asks for.
+    pub fn with_readahead(mut self, window: usize) -> Self {
+        self.readahead = Readahead::new(window);
+        self
+    }
+
+    /// Configure the directory listing cache
+    ///
+    /// A READDIR keeps the names of the directory for `ttl`, and READDIRs
+    /// resuming the listing are served from them for as long as the
+    /// directory is unchanged. Zero reads the directory on every READDIR.
+    pub fn with_dir_cache(mut self, ttl: Duration) -> Self {
+        self.dir_cache = DirCache::new(ttl);
+        self
+    }
+
+    /// Configure the FSSTAT cache
+    ///
+    /// The statvfs result for a handle is kept for `ttl`, or until a large
+    /// amount of data has been written through the server. Zero calls
+    /// statvfs on every FSSTAT.
+    pub fn with_statfs_cache(mut self, ttl: Duration) -> Self {
+        self.statfs_cache = StatfsCache::new(ttl);
+        self
+    }
+
+    /// Configure COMMIT batching
+    ///
+    /// The first COMMIT of a file waits `window` for COMMITs of the same file
+    /// from other connections, then one sync of the whole file answers them
+    /// all. Zero syncs each COMMIT on its own.
+    pub fn with_commit_window(mut self, window: Duration) -> Self {
+        self.group_commit = GroupCommit::new(window);
+        self
+    }
+
+    /// Number of data syncs issued so far by WRITE and COMMIT
+    pub fn sync_count(&self) -> u64 {
+        self.syncs.load(Ordering::Relaxed)
+    }
+
+    /// Number of stat-family calls issued so far by GETATTR, LOOKUP and READDIR
+    pub fn stat_count(&self) -> u64 {
+        self.stats.load(Ordering::Relaxed)
+    }
+
+    /// Number of reads issued to disk so far by READ
+    pub fn read_count(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// Number of directories read from disk so far by READDIR
+    pub fn dir_read_count(&self) -> u64 {
+        self.dir_reads.load(Ordering::Relaxed)
+    }
+
+    /// Number of files opened so far by READ, WRITE and COMMIT
+    pub fn open_count(&self) -> u64 {
+        self.fd_cache.open_count()
+    }
+
+    /// Number of files currently kept open for reuse
+    pub fn cached_file_count(&self) -> usize {
+        self.fd_cache.len()
+    }
+
+    /// Count one stat-family call
+    fn count_stat(&self) {
+        self.stats.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Inode a name is about to stop (or start) referring to, for invalidation
+    fn inode_of(&self, path: &Path) -> Option<u64> {
+        self.root_dir.symlink_metadata(path).ok().map(|metadata| metadata.ino())
+    }
+
+    /// Drop cached attributes for an inode whose link count or name changed
+    fn invalidate_inode_attrs(&self, path: &Path, inode: Option<u64>) {
+        if let Some(handle) = self.handle_manager.lookup_handle(path) {
+            self.metadata_changed(&handle);
+        }
+        if let Some(inode) = inode {
+            self.attr_cache.invalidate_fileid(inode);
+            self.changes.metadata_changed_fileid(inode);
+        }
+    }
+
+    /// Record a change to the data (or directory entries) behind a handle
+    fn data_changed(&self, handle: &FileHandle) {
+        if let Some(fields) = decode_handle(handle) {
+            self.readahead.invalidate(fields.inode);
+        }
+        self.dir_cache.invalidate(handle);
+        self.attr_cache.invalidate(handle);
+        self.changes.data_changed(handle);
+    }
+
+    /// Record a change to the metadata behind a handle
+    fn metadata_changed(&self, handle: &FileHandle) {
+        self.attr_cache.invalidate(handle);
+        self.changes.metadata_changed(handle);
+    }
+
+    /// Close the cached file of a name that was removed or replaced
+    fn close_cached_file(&self, path: &Path) {
+        if let Some(handle) = self.handle_manager.lookup_handle(path) {
+            self.fd_cache.invalidate(&handle);
+        }
+    }
+
+    /// Issue (or reuse) the handle of a path for the inode it names now
+    fn issue_handle(&self, path: PathBuf) -> Result<FileHandle> {
+        let metadata = self.root_dir.symlink_metadata(&path).context(format!("Failed to stat: {:?}", path))?;
+        Ok(self.handle_for(path, &metadata))
+    }
+
+    /// Handle of a path naming the file `metadata` (its lstat) describes
+    ///
+    /// A handle already issued for the same inode is reused; GETATTR checks
+    /// its generation the next time it stats the file. Reading the
+    /// generation costs an open, so it is read only for new handles.
+    fn handle_for(&self, path: PathBuf, metadata: &fs::Metadata) -> FileHandle {
+        if let Some(handle) = self.handle_manager.lookup_handle(&path)
+            && decode_handle(&handle).is_some_and(|fields| fields.inode == metadata.ino())
+        {
+            return handle;
+        }
+        let generation = fs_generation(&self.root_dir, &path, metadata).unwrap_or(0);
+        self.handle_manager.create_handle(path, metadata.ino(), generation)
+    }
+
+    /// Fail with a stale error if `path` no longer names the file `handle`
+    /// was issued for
+    ///
+    /// A file deleted and recreated behind the server's back has the same
+    /// path and may even get the same inode number; the filesystem's inode
+    /// generation tells the two apart. The replaced file's handle is
+    /// forgotten, so a new LOOKUP issues a fresh one.
+    ///
+    /// # Arguments
+    /// * `handle` - Handle being resolved
+    /// * `path` - Path the handle resolved to
+    /// * `metadata` - lstat of the path, as GETATTR takes it
+    fn check_identity(&self, handle: &FileHandle, path: &Path, metadata: &fs::Metadata) -> Result<()> {
+        let Some(fields) = decode_handle(handle) else {
+            return Ok(());
+        };
+        let replaced = metadata.ino() != fields.inode
+            || (fields.fs_generation != 0
+                && fs_generation(&self.root_dir, path, metadata)
+                    .is_some_and(|current| current != fields.fs_generation));
+        if replaced {
+            debug!("File handle for {:?} names a file since deleted and recreated", path);
+            self.handle_manager.remove_handle(handle);
+            self.attr_cache.invalidate(handle);
+            self.fd_cache.invalidate(handle);
+            return Err(FsalError::stale("file was replaced").into());
+        }
+        Ok(())
+    }
+
+    /// Forget the handle of a removed path, staling every handle to its
+    /// inode when the removal freed it
+    fn retire_path(&self, path: &Path, removed: Option<&fs::Metadata>) {
+        let freed = removed
+            .filter(|metadata| metadata.is_dir() || metadata.nlink() <= 1)
+            .map(|metadata| metadata.ino());
+        if let Some(inode) = freed {
+            self.changes.forget_fileid(inode);
+            self.readahead.invalidate(inode);
+        }
+        self.handle_manager.retire(path, freed);
+    }
+
     /// Resolve a file handle to a full path
     fn resolve_handle(&self, handle: &FileHandle) -> Result<PathBuf> {
-        self.handle_manager
+        if decode_handle(handle).is_none() {
+            return Err(FsalError::bad_handle(format!("malformed ({} bytes)", handle.len())).into());
+        }
+        let path = self
+            .handle_manager
             .lookup_path(handle)
-            .ok_or_else(|| anyhow!("Invalid file handle"))
+            .ok_or_else(|| FsalError::stale("not issued by this server"))?;
+        if !self.handle_manager.validate(handle) {
+            warn!("File handle path hash mismatch for {:?}", path);
+            return Err(FsalError::bad_handle("path hash mismatch").into());
+        }
+        if !self.handle_manager.is_current(handle) {
+            debug!("File handle for {:?} predates its inode's reuse", path);
+            return Err(FsalError::stale("generation mismatch").into());
+        }
+        Ok(path)
     }
 
     /// Validate that a path is within the export root
     ///
     /// This prevents path traversal attacks (e.g., "../../../etc/passwd")
     fn validate_path(&self, path: &Path) -> Result<()> {
-        // For paths that don't exist yet, we need to validate the parent directory
-        // and then check if the final component would be safe
-        let absolute_path = if path.is_absolute() {
-            path.to_path_buf()
-        } else {
-            // This shouldn't happen in our code, but handle it defensively
-            self.root_path.join(path)
-        };
+        beneath::validate(&self.root_path, path)
+    }
 
-        // Check if path exists - if yes, canonicalize it
-        // If not, canonicalize the parent and validate the final component
-        let canonical = if absolute_path.exists() {
-            absolute_path
-                .canonicalize()
-                .context(format!("Failed to canonicalize existing path: {:?}", path))?
-        } else {
-            // Get parent and canonicalize that
-            let parent = absolute_path
-                .parent()
-                .ok_or_else(|| anyhow!("Path has no parent: {:?}", absolute_path))?;
-
-            let canonical_parent = parent
-                .canonicalize()
-                .context(format!("Failed to canonicalize parent path: {:?}", parent))?;
-
-            // Check if parent is within root
-            if !canonical_parent.starts_with(&self.root_path) {
-                warn!(
-                    "Path traversal attempt: parent {:?} is outside root {:?}",
-                    canonical_parent, self.root_path
-                );
-                return Err(anyhow!("Path is outside export root"));
+    /// List a window of a directory with each entry's path and lstat metadata
+    ///
+    /// The directory's names come from the listing cache when an earlier
+    /// READDIR read them, so a continuation stats only the entries it
+    /// returns instead of reading the directory up to its cookie again.
+    ///
+    /// # Returns
+    /// Tuple of (entries, eof) where eof indicates if all entries were returned
+    fn scan_dir(&self, dir_handle: &FileHandle, cookie: u64, count: u32) -> Result<(ScannedEntries, bool)> {
+        let dir_path = self.resolve_handle(dir_handle)?;
+        let generation = self.attr_cache.generation();
+
+        // Verify it's a directory
+        self.count_stat();
+        let metadata = self
+            .root_dir
+            .symlink_metadata(&dir_path)
+            .context(format!("Failed to stat directory: {:?}", dir_path))?;
+
+        if !metadata.is_dir() {
+            return Err(anyhow!("Not a directory: {:?}", dir_path));
+        }
+
+        // Collect all entries
+        let mut entries: ScannedEntries = Vec::new();
+
+        // "." and ".." take the first two positions when listed
+        let dots = if self.dot_entries { 2 } else { 0 };
+        if self.dot_entries {
+            let parent = match dir_path.parent() {
+                Some(parent) if dir_path != self.root_path => parent.to_path_buf(),
+                _ => dir_path.clone(),
+            };
+            for (index, (name, path)) in [(".", dir_path.clone()), ("..", parent)].into_iter().enumerate() {
+                if (index as u64) < cookie {
+                    continue;
+                }
+                let entry_metadata = if index == 0 {
+                    metadata.clone()
+                } else {
+                    self.count_stat();
+                    self.root_dir
+                        .symlink_metadata(&path)
+                        .context(format!("Failed to stat parent directory: {:?}", path))?
+                };
+                let dir_entry = DirEntry {
+                    fileid: entry_metadata.ino(),
+                    name: name.to_string(),
+                    file_type: FileType::Directory,
+                };
+                entries.push((dir_entry, path, entry_metadata));
+                if entries.len() >= count as usize {
+                    return Ok((entries, false));
+                }
             }
+        }
 
-            // Get final component and validate it doesn't contain traversal attempts
-            if let Some(file_name) = absolute_path.file_name() {
-                let file_name_str = file_name
-                    .to_str()
-                    .ok_or_else(|| anyhow!("Invalid filename encoding"))?;
+        // Names in listing order: kept from an earlier READDIR of the
+        // unchanged directory, or read now
+        let change = attr_cache::ChangeAttr::from_metadata(&metadata);
+        let names = match self.dir_cache.get(dir_handle, &change) {
+            Some(names) => names,
+            None => {
+                self.dir_reads.fetch_add(1, Ordering::Relaxed);
+                let names = self
+                    .root_dir
+                    .read_dir(&dir_path)
+                    .context(format!("Failed to read directory: {:?}", dir_path))?;
+                let names = std::sync::Arc::new(names);
+                self.dir_cache.insert(dir_handle, change, names.clone());
+                names
+            }
+        };
+
+        // Skip entries before cookie (cookie is 0-based index + 1)
+        let skip = cookie.saturating_sub(dots as u64) as usize;
+        for file_name in names.iter().skip(skip) {
+            let entry_path = dir_path.join(file_name);
+            self.count_stat();
+            let entry_metadata = match self.root_dir.symlink_metadata(&entry_path) {
+                Ok(entry_metadata) => entry_metadata,
+                // Removed since the names were read
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context(format!("Failed to get metadata for: {:?}", entry_path)),
+            };
 
-                if file_name_str.contains("..") || file_name_str.contains('/') {
-                    return Err(anyhow!("Invalid filename: {}", file_name_str));
+            #[cfg(unix)]
+            let file_type = {
+                use std::os::unix::fs::FileTypeExt;
+                let ft = entry_metadata.file_type();
+
+                if ft.is_dir() {
+                    FileType::Directory
+                } else if ft.is_file() {
+                    FileType::RegularFile
+                } else if ft.is_symlink() {
+                    FileType::SymbolicLink
+                } else if ft.is_fifo() {
+                    FileType::NamedPipe
+                } else if ft.is_char_device() {
+                    FileType::CharDevice
+                } else if ft.is_block_device() {
+                    FileType::BlockDevice
+                } else if ft.is_socket() {
+                    FileType::Socket
+                } else {
+                    FileType::RegularFile // Default
                 }
+            };
 
-                // Return the would-be canonical path
-                canonical_parent.join(file_name)
+            #[cfg(not(unix))]
+            let file_type = if entry_metadata.is_dir() {
+                FileType::Directory
+            } else if entry_metadata.is_file() {
+                FileType::RegularFile
+            } else if entry_metadata.is_symlink() {
+                FileType::SymbolicLink
             } else {
-                return Err(anyhow!("Path has no filename component"));
+                FileType::RegularFile // Default
+            };
+
+            let name = file_name.to_string_lossy().to_string();
+
+            // The entry was just stat'ed: prime the attribute cache so the
+            // LOOKUP + GETATTR of a following READDIRPLUS need no syscalls
+            if self.attr_cache.caches_fresh() {
+                let handle = self.handle_for(entry_path.clone(), &entry_metadata);
+                let mut attrs = self.metadata_to_attr(&entry_metadata, &entry_path);
+                self.changes.report(&handle, &mut attrs);
+                let change = attr_cache::ChangeAttr::from_metadata(&entry_metadata);
+                self.attr_cache.insert(&handle, attrs, change, generation);
+            }
+
+            let dir_entry = DirEntry {
+                fileid: entry_metadata.ino(),
+                name,
+                file_type,
+            };
+            entries.push((dir_entry, entry_path, entry_metadata));
+
+            // Check if we've reached the requested count
+            if entries.len() >= count as usize {
+                debug!(
+                    "READDIR: {:?} cookie={} count={} -> {} entries (more available)",
+                    dir_path, cookie, count, entries.len()
+                );
+                return Ok((entries, false)); // Not EOF, more entries available
+            }
+        }
+
+        debug!(
+            "READDIR: {:?} cookie={} count={} -> {} entries (EOF)",
+            dir_path, cookie, count, entries.len()
+        );
+
+        Ok((entries, true)) // EOF reached
+    }
+
+    /// Create a regular file, or open an existing one unless `guarded`
+    ///
+    /// O_EXCL makes the existence check and the creation one step, so a
+    /// guarded create never opens a file another client created first.
+    fn create_file(&self, dir_handle: &FileHandle, name: &str, mode: u32, guarded: bool) -> Result<FileHandle> {
+        let dir_path = self.resolve_handle(dir_handle)?;
+
+        // Security: prevent path traversal
+        if name.contains('/') || name.contains("..") {
+            return Err(anyhow!("Invalid filename: {}", name));
+        }
+
+        let full_path = dir_path.join(name);
+
+        // Validate path is within export root
+        self.validate_path(&full_path)?;
+
+        // Create file, or open an existing one without truncating it; a
+        // guarded create fails with EEXIST instead
+        let created = self.root_dir.open_file(&full_path, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, 0o666);
+        let created = match created {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && !guarded => {
+                self.root_dir
+                    .open_file(&full_path, libc::O_WRONLY, 0)
+                    .context(format!("Failed to open existing file: {:?}", full_path))?;
+                None
             }
+            Err(e) => return Err(e).context(format!("Failed to create file: {:?}", full_path)),
         };
 
-        if !canonical.starts_with(&self.root_path) {
-            warn!(
-                "Path traversal attempt: {:?} is outside root {:?}",
-                canonical, self.root_path
-            );
-            return Err(anyhow!("Path is outside export root"));
+        // Set permissions of a new file
+        let is_new = created.is_some();
+        if let Some(file) = created {
+            let permissions = fs::Permissions::from_mode(mode);
+            file.set_permissions(permissions)
+                .context("Failed to set permissions")?;
         }
 
-        Ok(())
+        // Create handle
+        let handle = self.issue_handle(full_path.clone())?;
+        if is_new {
+            self.data_changed(&handle);
+            self.data_changed(dir_handle);
+        }
+
+        debug!("CREATE: {:?} mode={:o} -> handle", full_path, mode);
+
+        Ok(handle)
     }
 
     /// Convert std::fs::Metadata to FileAttributes
-    fn metadata_to_attr(&self, metadata: &fs::Metadata, path: &Path) -> FileAttributes {
+    fn metadata_to_attr(&self, metadata: &fs::Metadata, _path: &Path) -> FileAttributes {
         #[cfg(unix)]
         let ftype = {
             use std::os::unix::fs::FileTypeExt;
@@ -192,6 +678,41 @@ impl LocalFilesystem {
     }
 }
 
+/// Fill `buf` from `offset` with positioned reads, stopping early only at end of file
+///
+/// # Returns
+/// Number of bytes read into the start of `buf`
+fn read_fully_at(file: &fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    let mut bytes_read = 0;
+    while bytes_read < buf.len() {
+        match file.read_at(&mut buf[bytes_read..], offset + bytes_read as u64) {
+            Ok(0) => break,
+            Ok(n) => bytes_read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(bytes_read)
+}
+
+/// Start writeback of the dirty pages of `[offset, offset + count)`
+///
+/// sync_file_range persists neither block allocations nor the file size and
+/// does not flush the device's write cache, so it only gets the range's
+/// pages moving early; COMMIT still ends with fdatasync.
+fn start_range_writeback(file: &fs::File, offset: u64, count: u32) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let offset = i64::try_from(offset).map_err(|_| std::io::Error::from_raw_os_error(libc::EINVAL))?;
+    // SAFETY: the fd is valid for the lifetime of `file`
+    let result = unsafe { libc::sync_file_range(file.as_raw_fd(), offset, count as i64, libc::SYNC_FILE_RANGE_WRITE) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
 impl Filesystem for LocalFilesystem {
     fn root_handle(&self) -> FileHandle {
         self.root_handle.clone()
@@ -210,168 +731,283 @@ impl Filesystem for LocalFilesystem {
         // Validate path is within export root
         self.validate_path(&full_path)?;
 
+        // A handle with fresh cached attributes names an existing file
+        if let Some(handle) = self.handle_manager.lookup_handle(&full_path)
+            && self.attr_cache.get_fresh(&handle).is_some()
+        {
+            debug!("LOOKUP: {:?}/{} -> cached handle", dir_path, name);
+            return Ok(handle);
+        }
+
         // Check if file exists
-        if !full_path.exists() {
+        self.count_stat();
+        let Ok(metadata) = self.root_dir.symlink_metadata(&full_path) else {
             return Err(anyhow!("File not found: {}", name));
-        }
+        };
 
         // Create or get existing handle
-        let handle = self.handle_manager.create_handle(full_path);
+        let handle = self.handle_for(full_path, &metadata);
 
         debug!("LOOKUP: {:?}/{} -> handle", dir_path, name);
 
         Ok(handle)
     }
 
-    fn getattr(&self, handle: &FileHandle) -> Result<FileAttributes> {
-        let path = self.resolve_handle(handle)?;
-
-        let metadata = fs::metadata(&path).context(format!("Failed to stat: {:?}", path))?;
-
-        Ok(self.metadata_to_attr(&metadata, &path))
-    }
+    fn parent_handle(&self, dir_handle: &FileHandle) -> Result<FileHandle> {
+        let dir_path = self.resolve_handle(dir_handle)?;
 
-    fn read(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<Vec<u8>> {
-        let path = self.resolve_handle(handle)?;
+        self.count_stat();
+        let metadata = self
+            .root_dir
+            .symlink_metadata(&dir_path)
+            .context(format!("Failed to stat directory: {:?}", dir_path))?;
+        if !metadata.is_dir() {
+            return Err(anyhow!("Not a directory: {:?}", dir_path));
+        }
 
-        let mut file =
-            fs::File::open(&path).context(format!("Failed to open file: {:?}", path))?;
+        // The export root is its own parent
+        let parent_path = match dir_path.parent() {
+            Some(parent) if dir_path != self.root_path => parent.to_path_buf(),
+            _ => return Ok(self.root_handle.clone()),
+        };
+        self.validate_path(&parent_path)?;
 
-        // Seek to offset
-        file.seek(SeekFrom::Start(offset))
-            .context("Failed to seek")?;
+        self.count_stat();
+        let metadata = self
+            .root_dir
+            .symlink_metadata(&parent_path)
+            .context(format!("Failed to stat parent directory: {:?}", parent_path))?;
+        let handle = self.handle_for(parent_path, &metadata);
 
-        // Read up to count bytes
-        let mut buffer = vec![0u8; count as usize];
-        let bytes_read = file.read(&mut buffer).context("Failed to read file")?;
+        debug!("LOOKUP: {:?}/.. -> handle", dir_path);
 
-        // Truncate buffer to actual bytes read
-        buffer.truncate(bytes_read);
+        Ok(handle)
+    }
 
-        debug!(
-            "READ: {:?} offset={} count={} -> {} bytes",
-            path, offset, count, bytes_read
-        );
+    fn handle_for_path(&self, path: &str) -> Result<(FileHandle, FileAttributes)> {
+        // One lstat per component, without issuing handles on the way; a
+        // symlink is not a directory here, so it cannot be traversed
+        let mut full_path = self.root_path.clone();
+        let mut metadata = self
+            .root_dir
+            .symlink_metadata(&full_path)
+            .context(format!("Failed to stat: {:?}", full_path))?;
+        for name in path.split('/').filter(|name| !name.is_empty() && *name != ".") {
+            if name.contains("..") {
+                return Err(anyhow!("Path {:?} leaves the export root", path));
+            }
+            if !metadata.is_dir() {
+                return Err(anyhow!("Path {:?}: {:?} is not a directory", path, full_path));
+            }
+            full_path.push(name);
+            self.count_stat();
+            metadata = self
+                .root_dir
+                .symlink_metadata(&full_path)
+                .map_err(|_| anyhow!("File not found: {}", path))?;
+        }
+        self.validate_path(&full_path)?;
 
-        Ok(buffer)
+        let handle = if full_path == self.root_path {
+            self.root_handle.clone()
+        } else {
+            self.handle_for(full_path, &metadata)
+        };
+        let attrs = self.getattr(&handle)?;
+        Ok((handle, attrs))
     }
 
-    fn readdir(&self, dir_handle: &FileHandle, cookie: u64, count: u32) -> Result<(Vec<DirEntry>, bool)> {
-        let dir_path = self.resolve_handle(dir_handle)?;
-
-        // Verify it's a directory
-        let metadata = fs::metadata(&dir_path)
-            .context(format!("Failed to stat directory: {:?}", dir_path))?;
+    fn getattr(&self, handle: &FileHandle) -> Result<FileAttributes> {
+        let path = self.resolve_handle(handle)?;
 
-        if !metadata.is_dir() {
-            return Err(anyhow!("Not a directory: {:?}", dir_path));
+        // Attributes cached within the TTL need no syscall at all
+        if let Some(attrs) = self.attr_cache.get_fresh(handle) {
+            return Ok(attrs);
+        }
+        let generation = self.attr_cache.generation();
+
+        // Cheap change-attribute probe: if nothing changed since the last
+        // GETATTR, serve the cached attributes
+        self.count_stat();
+        let change = attr_cache::probe_change_attr(&self.root_dir, &path);
+        if let Some(change) = &change
+            && let Some(attrs) = self.attr_cache.get(handle, change)
+        {
+            return Ok(attrs);
         }
 
-        // Read directory entries
-        let read_dir = fs::read_dir(&dir_path)
-            .context(format!("Failed to read directory: {:?}", dir_path))?;
-
-        // Collect all entries
-        let mut entries: Vec<DirEntry> = Vec::new();
+        self.count_stat();
+        let metadata = self.root_dir.symlink_metadata(&path).context(format!("Failed to stat: {:?}", path))?;
+        self.check_identity(handle, &path, &metadata)?;
+        let mut attrs = self.metadata_to_attr(&metadata, &path);
+        self.changes.report(handle, &mut attrs);
 
-        for (index, entry_result) in read_dir.enumerate() {
-            let entry = entry_result.context("Failed to read directory entry")?;
-            let entry_path = entry.path();
-            let entry_metadata = entry.metadata()
-                .context(format!("Failed to get metadata for: {:?}", entry_path))?;
+        match change {
+            Some(change) => self.attr_cache.insert(handle, attrs.clone(), change, generation),
+            // Change attribute unavailable: never serve this handle from cache
+            None => self.attr_cache.invalidate(handle),
+        }
 
-            #[cfg(unix)]
-            let file_type = {
-                use std::os::unix::fs::FileTypeExt;
-                let ft = entry_metadata.file_type();
+        Ok(attrs)
+    }
 
-                if ft.is_dir() {
-                    FileType::Directory
-                } else if ft.is_file() {
-                    FileType::RegularFile
-                } else if ft.is_symlink() {
-                    FileType::SymbolicLink
-                } else if ft.is_fifo() {
-                    FileType::NamedPipe
-                } else if ft.is_char_device() {
-                    FileType::CharDevice
-                } else if ft.is_block_device() {
-                    FileType::BlockDevice
-                } else if ft.is_socket() {
-                    FileType::Socket
-                } else {
-                    FileType::RegularFile // Default
-                }
-            };
+    fn read(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<Bytes> {
+        let path = self.resolve_handle(handle)?;
 
-            #[cfg(not(unix))]
-            let file_type = if entry_metadata.is_dir() {
-                FileType::Directory
-            } else if entry_metadata.is_file() {
-                FileType::RegularFile
-            } else if entry_metadata.is_symlink() {
-                FileType::SymbolicLink
-            } else {
-                FileType::RegularFile // Default
-            };
+        let file = self
+            .fd_cache
+            .get(&self.root_dir, handle, &path, false)
+            .map_err(|e| FsalError::io(format!("Failed to open file: {:?}", path), e))?;
+
+        // Read up to length bytes with positioned reads (no shared file
+        // offset, so concurrent READs on a cached descriptor cannot race),
+        // handing the buffer over without copying. A short read only ends
+        // the loop at end of file. Ranges inside a hole are not read.
+        let read_at = |offset: u64, length: usize| {
+            if let Some(zeros) = holes::read_hole(&file, offset, length) {
+                return Ok(zeros);
+            }
+            let mut buffer = vec![0u8; length];
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            let bytes_read = read_fully_at(&file, &mut buffer, offset)?;
+            buffer.truncate(bytes_read);
+            Ok(Bytes::from(buffer))
+        };
+        let data = match decode_handle(handle) {
+            Some(fields) => self.readahead.read(fields.inode, offset, count as usize, read_at),
+            None => read_at(offset, count as usize),
+        }
+        .map_err(|e| FsalError::io(format!("Failed to read file: {:?}", path), e))?;
 
-            let name = entry.file_name()
-                .to_string_lossy()
-                .to_string();
+        debug!(
+            "READ: {:?} offset={} count={} -> {} bytes",
+            path, offset, count, data.len()
+        );
 
-            // Skip entries before cookie (cookie is 0-based index + 1)
-            if cookie > 0 && (index as u64) < cookie {
-                continue;
-            }
+        Ok(data)
+    }
 
-            entries.push(DirEntry {
-                fileid: entry_metadata.ino(),
-                name,
-                file_type,
-            });
+    fn read_into(&self, handle: &FileHandle, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        // Readahead serves READs out of its window: copy from there
+        if self.readahead.is_enabled() {
+            let data = self.read(handle, offset, u32::try_from(buf.len()).unwrap_or(u32::MAX))?;
+            buf[..data.len()].copy_from_slice(&data);
+            return Ok(data.len());
+        }
 
-            // Check if we've reached the requested count
-            if entries.len() >= count as usize {
-                debug!(
-                    "READDIR: {:?} cookie={} count={} -> {} entries (more available)",
-                    dir_path, cookie, count, entries.len()
-                );
-                return Ok((entries, false)); // Not EOF, more entries available
-            }
+        let path = self.resolve_handle(handle)?;
+        let file = self
+            .fd_cache
+            .get(&self.root_dir, handle, &path, false)
+            .map_err(|e| FsalError::io(format!("Failed to open file: {:?}", path), e))?;
+        if let Some(zeros) = holes::read_hole(&file, offset, buf.len()) {
+            buf[..zeros.len()].fill(0);
+            return Ok(zeros.len());
         }
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let bytes_read = read_fully_at(&file, buf, offset)
+            .map_err(|e| FsalError::io(format!("Failed to read file: {:?}", path), e))?;
 
         debug!(
-            "READDIR: {:?} cookie={} count={} -> {} entries (EOF)",
-            dir_path, cookie, count, entries.len()
+            "READ: {:?} offset={} count={} -> {} bytes",
+            path, offset, buf.len(), bytes_read
         );
 
-        Ok((entries, true)) // EOF reached
+        Ok(bytes_read)
     }
 
-    fn write(&self, handle: &FileHandle, offset: u64, data: &[u8]) -> Result<u32> {
-        let path = self.resolve_handle(handle)?;
+    fn readdir(&self, dir_handle: &FileHandle, cookie: u64, count: u32) -> Result<(Vec<DirEntry>, bool)> {
+        let (entries, eof) = self.scan_dir(dir_handle, cookie, count)?;
+        Ok((entries.into_iter().map(|(entry, _, _)| entry).collect(), eof))
+    }
+
+    fn readdir_plus(
+        &self,
+        dir_handle: &FileHandle,
+        cookie: u64,
+        dircount: u32,
+        _maxcount: u32,
+    ) -> Result<(Vec<DirEntryPlus>, bool)> {
+        let (entries, eof) = self.scan_dir(dir_handle, cookie, dircount)?;
+        let entries = entries
+            .into_iter()
+            .map(|(entry, path, metadata)| {
+                if entry.name == ".." {
+                    // The parent of an export's root may lie outside the
+                    // export: give its attributes but no handle
+                    let attributes = Some(self.metadata_to_attr(&metadata, &path));
+                    return DirEntryPlus { entry, attributes, handle: None };
+                }
+                let handle = self.handle_for(path.clone(), &metadata);
+                let mut attributes = self.metadata_to_attr(&metadata, &path);
+                self.changes.report(&handle, &mut attributes);
+                DirEntryPlus {
+                    entry,
+                    attributes: Some(attributes),
+                    handle: Some(handle),
+                }
+            })
+            .collect();
+        Ok((entries, eof))
+    }
 
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&path)
-            .context(format!("Failed to open file for writing: {:?}", path))?;
+    fn write(&self, handle: &FileHandle, offset: u64, data: &[u8], stable: StableHow) -> Result<u32> {
+        let path = self.resolve_handle(handle)?;
 
-        // Seek to offset
-        file.seek(SeekFrom::Start(offset))
-            .context("Failed to seek")?;
+        let file = self
+            .fd_cache
+            .get(&self.root_dir, handle, &path, true)
+            .map_err(|e| FsalError::io(format!("Failed to open file for writing: {:?}", path), e))?;
+
+        // Direct I/O fast path for the block-aligned prefix
+        let mut direct_len = 0;
+        if self.direct_io {
+            let aligned_len = direct_io::aligned_prefix_len(offset, data.len());
+            if aligned_len > 0 {
+                match direct_io::write_direct(&self.root_dir, &path, offset, &data[..aligned_len]) {
+                    Ok(()) => direct_len = aligned_len,
+                    Err(e) => debug!("WRITE: O_DIRECT unavailable for {:?}, using buffered I/O: {}", path, e),
+                }
+            }
+        }
 
-        // Write data
-        let bytes_written = file.write(data).context("Failed to write file")?;
+        // Write data (remaining unaligned tail when the direct path was used)
+        // with positioned writes, so WRITEs sharing a cached descriptor never
+        // race on its file offset; short writes are retried to the end
+        let tail = &data[direct_len..];
+        let written = file.write_all_at(tail, offset + direct_len as u64);
+        self.data_changed(handle);
+        written.map_err(|e| FsalError::io(format!("Failed to write file: {:?}", path), e))?;
+        let bytes_written = direct_len + tail.len();
+        self.statfs_cache.note_write(bytes_written as u64);
+
+        // Flush to disk unless the client will COMMIT later
+        let synced = match stable {
+            StableHow::Unstable => Ok(()),
+            StableHow::DataSync => file.sync_data(),
+            StableHow::FileSync => file.sync_all(),
+        };
+        if stable != StableHow::Unstable {
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+        }
+        synced.map_err(|e| FsalError::io(format!("Failed to sync file: {:?}", path), e))?;
 
-        // Flush to disk
-        file.sync_all().context("Failed to sync file")?;
+        // A stable write synced the whole file; an unstable one leaves it
+        // for COMMIT, or for the flush at shutdown
+        let mut unstable = self.unstable.lock().unwrap();
+        if stable == StableHow::Unstable {
+            unstable.insert(handle.clone());
+        } else {
+            unstable.remove(handle);
+        }
+        drop(unstable);
 
         debug!(
-            "WRITE: {:?} offset={} count={} -> {} bytes",
+            "WRITE: {:?} offset={} count={} stable={:?} -> {} bytes",
             path,
             offset,
             data.len(),
+            stable,
             bytes_written
         );
 
@@ -381,13 +1017,14 @@ impl Filesystem for LocalFilesystem {
     fn setattr_size(&self, handle: &FileHandle, size: u64) -> Result<()> {
         let path = self.resolve_handle(handle)?;
 
-        let file = fs::OpenOptions::new()
-            .write(true)
-            .open(&path)
+        let file = self
+            .root_dir
+            .open_file(&path, libc::O_WRONLY, 0)
             .context(format!("Failed to open file for setattr: {:?}", path))?;
 
-        file.set_len(size)
-            .context("Failed to set file size")?;
+        let result = file.set_len(size);
+        self.data_changed(handle);
+        result.context("Failed to set file size")?;
 
         debug!("SETATTR: {:?} size={}", path, size);
 
@@ -397,9 +1034,9 @@ impl Filesystem for LocalFilesystem {
     fn setattr_mode(&self, handle: &FileHandle, mode: u32) -> Result<()> {
         let path = self.resolve_handle(handle)?;
 
-        let permissions = fs::Permissions::from_mode(mode);
-        fs::set_permissions(&path, permissions)
-            .context(format!("Failed to set permissions: {:?}", path))?;
+        let result = self.root_dir.chmod(&path, mode);
+        self.metadata_changed(handle);
+        result.context(format!("Failed to set permissions: {:?}", path))?;
 
         debug!("SETATTR: {:?} mode={:o}", path, mode);
 
@@ -409,42 +1046,79 @@ impl Filesystem for LocalFilesystem {
     fn setattr_owner(&self, handle: &FileHandle, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
         let path = self.resolve_handle(handle)?;
 
-        // Note: chown requires root privileges on Unix systems
-        // For now, we'll just log this and return success
-        // In production, you might want to use nix::unistd::chown
-        debug!("SETATTR: {:?} uid={:?} gid={:?} (not implemented)", path, uid, gid);
+        // Giving a file away needs CAP_CHOWN: without it this is EPERM
+        let result = self.root_dir.chown(&path, uid, gid);
+        self.metadata_changed(handle);
+        result.map_err(|e| FsalError::io(format!("Failed to change owner: {:?}", path), e))?;
+
+        debug!("SETATTR: {:?} uid={:?} gid={:?}", path, uid, gid);
 
         Ok(())
     }
 
-    fn create(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
-        let dir_path = self.resolve_handle(dir_handle)?;
+    fn setattr_times(&self, handle: &FileHandle, atime: Option<SetTime>, mtime: Option<SetTime>) -> Result<()> {
+        let path = self.resolve_handle(handle)?;
 
-        // Security: prevent path traversal
-        if name.contains('/') || name.contains("..") {
-            return Err(anyhow!("Invalid filename: {}", name));
-        }
+        let timespec = |time: Option<SetTime>| match time {
+            None => libc::timespec { tv_sec: 0, tv_nsec: libc::UTIME_OMIT },
+            Some(SetTime::ServerTime) => libc::timespec { tv_sec: 0, tv_nsec: libc::UTIME_NOW },
+            Some(SetTime::ClientTime(time)) => libc::timespec {
+                tv_sec: time.seconds as libc::time_t,
+                tv_nsec: time.nseconds as libc::c_long,
+            },
+        };
+        let result = self.root_dir.set_times(&path, &[timespec(atime), timespec(mtime)]);
+        self.attr_cache.invalidate(handle);
+        self.changes.times_set(handle);
+        result.map_err(|e| FsalError::io(format!("Failed to set times: {:?}", path), e))?;
 
-        let full_path = dir_path.join(name);
+        debug!("SETATTR: {:?} atime={:?} mtime={:?}", path, atime, mtime);
 
-        // Validate path is within export root
-        self.validate_path(&full_path)?;
+        Ok(())
+    }
 
-        // Create file
-        let file = fs::File::create(&full_path)
-            .context(format!("Failed to create file: {:?}", full_path))?;
+    fn setattr(&self, handle: &FileHandle, attrs: &SetAttributes, guard: Option<FileTime>) -> Result<()> {
+        let path = self.resolve_handle(handle)?;
 
-        // Set permissions
-        let permissions = fs::Permissions::from_mode(mode);
-        file.set_permissions(permissions)
-            .context("Failed to set permissions")?;
+        let _serialized = self.setattr_lock.lock().unwrap();
+
+        if let Some(guard) = guard {
+            let metadata = self
+                .root_dir
+                .symlink_metadata(&path)
+                .context(format!("Failed to stat for setattr guard: {:?}", path))?;
+            // Compare with the ctime clients are shown, not the on-disk one
+            let mut attrs = self.metadata_to_attr(&metadata, &path);
+            self.changes.report(handle, &mut attrs);
+            let ctime = attrs.ctime;
+            if ctime != guard {
+                debug!("SETATTR: {:?} guard mismatch ({:?} != {:?})", path, ctime, guard);
+                return Err(FsalError::NotSync.into());
+            }
+        }
 
-        // Create handle
-        let handle = self.handle_manager.create_handle(full_path.clone());
+        if let Some(size) = attrs.size {
+            self.setattr_size(handle, size)?;
+        }
+        if let Some(mode) = attrs.mode {
+            self.setattr_mode(handle, mode)?;
+        }
+        if attrs.uid.is_some() || attrs.gid.is_some() {
+            self.setattr_owner(handle, attrs.uid, attrs.gid)?;
+        }
+        if attrs.atime.is_some() || attrs.mtime.is_some() {
+            self.setattr_times(handle, attrs.atime, attrs.mtime)?;
+        }
 
-        debug!("CREATE: {:?} mode={:o} -> handle", full_path, mode);
+        Ok(())
+    }
 
-        Ok(handle)
+    fn create(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        self.create_file(dir_handle, name, mode, false)
+    }
+
+    fn create_guarded(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        self.create_file(dir_handle, name, mode, true)
     }
 
     fn remove(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
@@ -461,7 +1135,13 @@ impl Filesystem for LocalFilesystem {
         self.validate_path(&full_path)?;
 
         // Remove file
-        fs::remove_file(&full_path).context(format!("Failed to remove file: {:?}", full_path))?;
+        let removed = self.root_dir.symlink_metadata(&full_path).ok();
+        let result = self.root_dir.unlink(&full_path);
+        self.invalidate_inode_attrs(&full_path, removed.as_ref().map(|metadata| metadata.ino()));
+        self.close_cached_file(&full_path);
+        self.data_changed(dir_handle);
+        result.context(format!("Failed to remove file: {:?}", full_path))?;
+        self.retire_path(&full_path, removed.as_ref());
 
         debug!("REMOVE: {:?}", full_path);
 
@@ -481,15 +1161,16 @@ impl Filesystem for LocalFilesystem {
         // Validate path is within export root
         self.validate_path(&full_path)?;
 
-        // Create directory
-        fs::create_dir(&full_path).context(format!("Failed to create directory: {:?}", full_path))?;
-
-        // Set permissions
-        let permissions = fs::Permissions::from_mode(mode);
-        fs::set_permissions(&full_path, permissions).context("Failed to set permissions")?;
+        // Create directory, then set the exact mode the umask masked
+        self.root_dir
+            .mkdir(&full_path, mode)
+            .context(format!("Failed to create directory: {:?}", full_path))?;
+        self.root_dir.chmod(&full_path, mode).context("Failed to set permissions")?;
 
         // Create handle
-        let handle = self.handle_manager.create_handle(full_path.clone());
+        let handle = self.issue_handle(full_path.clone())?;
+        self.metadata_changed(&handle);
+        self.data_changed(dir_handle);
 
         debug!("MKDIR: {:?} mode={:o} -> handle", full_path, mode);
 
@@ -510,8 +1191,12 @@ impl Filesystem for LocalFilesystem {
         self.validate_path(&full_path)?;
 
         // Remove directory
-        fs::remove_dir(&full_path)
-            .context(format!("Failed to remove directory: {:?}", full_path))?;
+        let removed = self.root_dir.symlink_metadata(&full_path).ok();
+        let result = self.root_dir.rmdir(&full_path);
+        self.invalidate_inode_attrs(&full_path, removed.as_ref().map(|metadata| metadata.ino()));
+        self.data_changed(dir_handle);
+        result.context(format!("Failed to remove directory: {:?}", full_path))?;
+        self.retire_path(&full_path, removed.as_ref());
 
         debug!("RMDIR: {:?}", full_path);
 
@@ -544,8 +1229,23 @@ impl Filesystem for LocalFilesystem {
         self.validate_path(&to_full_path)?;
 
         // Rename/move the file or directory
-        fs::rename(&from_full_path, &to_full_path)
-            .context(format!("Failed to rename {:?} to {:?}", from_full_path, to_full_path))?;
+        let from_inode = self.inode_of(&from_full_path);
+        let replaced = self.root_dir.symlink_metadata(&to_full_path).ok();
+        let to_inode = replaced.as_ref().map(|metadata| metadata.ino());
+        let result = self.root_dir.rename(&from_full_path, &to_full_path);
+        self.invalidate_inode_attrs(&from_full_path, from_inode);
+        self.invalidate_inode_attrs(&to_full_path, to_inode);
+        self.close_cached_file(&from_full_path);
+        self.close_cached_file(&to_full_path);
+        self.data_changed(from_dir_handle);
+        self.data_changed(to_dir_handle);
+        result.context(format!("Failed to rename {:?} to {:?}", from_full_path, to_full_path))?;
+
+        // An object the rename replaced is gone (renaming a name onto
+        // another link of the same inode replaces nothing)
+        if to_inode.is_some() && to_inode != from_inode {
+            self.retire_path(&to_full_path, replaced.as_ref());
+        }
 
         debug!("RENAME: {:?} -> {:?}", from_full_path, to_full_path);
 
@@ -560,28 +1260,44 @@ impl Filesystem for LocalFilesystem {
             return Err(anyhow!("Invalid symlink name: {}", name));
         }
 
+        // Reject targets the kernel would refuse (or report confusingly)
+        // with the errno the NFS layer maps them by
+        let target_errno = if target.is_empty() {
+            Some(libc::EINVAL)
+        } else if target.len() >= libc::PATH_MAX as usize {
+            Some(libc::ENAMETOOLONG)
+        } else {
+            None
+        };
+        if let Some(errno) = target_errno {
+            return Err(FsalError::io(
+                format!("Invalid symlink target ({} bytes)", target.len()),
+                std::io::Error::from_raw_os_error(errno),
+            )
+            .into());
+        }
+
         let symlink_path = dir_path.join(name);
 
         // Validate symlink path is within export root
         self.validate_path(&symlink_path)?;
 
         // Check if file/symlink already exists
-        if symlink_path.exists() {
+        if self.root_dir.symlink_metadata(&symlink_path).is_ok() {
             return Err(anyhow!("File or symlink already exists: {:?}", symlink_path));
         }
 
         // Create symbolic link
-        #[cfg(unix)]
-        std::os::unix::fs::symlink(target, &symlink_path)
+        self.root_dir
+            .symlink(target, &symlink_path)
             .context(format!("Failed to create symlink {:?} -> {}", symlink_path, target))?;
 
-        #[cfg(not(unix))]
-        return Err(anyhow!("Symbolic links are only supported on Unix systems"));
-
         debug!("SYMLINK: {:?} -> {}", symlink_path, target);
 
         // Create handle for the new symlink
-        let handle = self.handle_manager.create_handle(symlink_path.clone());
+        let handle = self.issue_handle(symlink_path.clone())?;
+        self.metadata_changed(&handle);
+        self.data_changed(dir_handle);
         Ok(handle)
     }
 
@@ -589,7 +1305,9 @@ impl Filesystem for LocalFilesystem {
         let path = self.resolve_handle(handle)?;
 
         // Verify the path is a symlink
-        let metadata = fs::symlink_metadata(&path)
+        let metadata = self
+            .root_dir
+            .symlink_metadata(&path)
             .context(format!("Failed to get metadata for {:?}", path))?;
 
         if !metadata.file_type().is_symlink() {
@@ -597,7 +1315,9 @@ impl Filesystem for LocalFilesystem {
         }
 
         // Read the symlink target
-        let target = fs::read_link(&path)
+        let target = self
+            .root_dir
+            .readlink(&path)
             .context(format!("Failed to read symlink {:?}", path))?;
 
         let target_str = target.to_string_lossy().to_string();
@@ -622,12 +1342,14 @@ impl Filesystem for LocalFilesystem {
         self.validate_path(&link_path)?;
 
         // Check if target already exists
-        if link_path.exists() {
+        if self.root_dir.symlink_metadata(&link_path).is_ok() {
             return Err(anyhow!("File already exists: {:?}", link_path));
         }
 
         // Get source file metadata to check if it's a directory
-        let metadata = fs::metadata(&file_path)
+        let metadata = self
+            .root_dir
+            .symlink_metadata(&file_path)
             .context(format!("Failed to get metadata for {:?}", file_path))?;
 
         // Cannot create hard link to a directory (POSIX restriction)
@@ -636,118 +1358,325 @@ impl Filesystem for LocalFilesystem {
         }
 
         // Create hard link
-        fs::hard_link(&file_path, &link_path)
-            .context(format!("Failed to create hard link {:?} -> {:?}", link_path, file_path))?;
+        let result = self.root_dir.link(&file_path, &link_path);
+        self.invalidate_inode_attrs(&file_path, Some(metadata.ino()));
+        self.metadata_changed(file_handle);
+        self.data_changed(dir_handle);
+        // Keep the errno, so EMLINK reaches the client as NFS3ERR_MLINK
+        result.map_err(|e| {
+            FsalError::io(format!("Failed to create hard link {:?} -> {:?}", link_path, file_path), e)
+        })?;
 
         debug!("LINK: {:?} -> {:?}", link_path, file_path);
 
-        // Return the same file handle (hard links share the same inode)
-        Ok(file_handle.clone())
+        // Return the same file handle (hard links share the same inode)
+        Ok(file_handle.clone())
+    }
+
+    fn commit(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<()> {
+        let path = self.resolve_handle(handle)?;
+
+        // Sync through the cached descriptor WRITE used, opening one if it
+        // was evicted (syncing needs no write access)
+        let file = self
+            .fd_cache
+            .get(&self.root_dir, handle, &path, false)
+            .map_err(|e| FsalError::io(format!("Failed to open file for commit: {:?}", path), e))?;
+
+        // count == 0 means "through end of file" (RFC 1813). A range only
+        // gets its writeback started early: the reply promises the data
+        // survives a crash, which takes fdatasync (allocations, size and the
+        // device cache) whatever the range. Batched COMMITs share one sync.
+        let synced = if self.group_commit.is_enabled() {
+            self.group_commit.commit(handle, || {
+                self.syncs.fetch_add(1, Ordering::Relaxed);
+                file.sync_data()
+            })
+        } else {
+            if count != 0
+                && let Err(e) = start_range_writeback(&file, offset, count)
+            {
+                debug!("COMMIT: range writeback unavailable for {:?} ({})", path, e);
+            }
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+            file.sync_data()
+        };
+        synced.map_err(|e| FsalError::io(format!("Failed to sync file: {:?}", path), e))?;
+        // Every COMMIT synced the whole file's data, so nothing is left unstable
+        self.unstable.lock().unwrap().remove(handle);
+
+        debug!(
+            "COMMIT: {:?} (offset={}, count={})",
+            path, offset, count
+        );
+
+        Ok(())
+    }
+
+    fn issued_handles(&self) -> Vec<(FileHandle, String)> {
+        self.handle_manager
+            .entries()
+            .into_iter()
+            .map(|(handle, path)| (handle, path.display().to_string()))
+            .collect()
+    }
+
+    fn invalidate_handle(&self, handle: &FileHandle) -> bool {
+        // Clients hold the root handle from MOUNT; it must keep resolving
+        if *handle == self.root_handle {
+            return false;
+        }
+        let Some(path) = self.handle_manager.remove_handle(handle) else {
+            return false;
+        };
+        debug!("Invalidated file handle for {:?}", path);
+        self.attr_cache.invalidate(handle);
+        self.fd_cache.invalidate(handle);
+        true
+    }
+
+    fn flush(&self) -> Result<usize> {
+        let pending: Vec<_> = self.unstable.lock().unwrap().drain().collect();
+        let mut flushed = 0;
+        for handle in pending {
+            // Files removed since they were written have nothing to keep
+            let Ok(path) = self.resolve_handle(&handle) else {
+                continue;
+            };
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+            let synced = self
+                .fd_cache
+                .get(&self.root_dir, &handle, &path, false)
+                .and_then(|file| file.sync_data());
+            match synced {
+                Ok(()) => flushed += 1,
+                Err(e) => warn!("Failed to flush {:?}: {}", path, e),
+            }
+        }
+        Ok(flushed)
+    }
+
+    fn mknod(
+        &self,
+        dir_handle: &FileHandle,
+        name: &str,
+        file_type: FileType,
+        mode: u32,
+        rdev: (u32, u32),
+    ) -> Result<FileHandle> {
+        let dir_path = self.resolve_handle(dir_handle)?;
+        let file_path = dir_path.join(name);
+
+        debug!(
+            "MKNOD: {:?}/{} type={:?} mode={:o} rdev=({}, {})",
+            dir_path, name, file_type, mode, rdev.0, rdev.1
+        );
+
+        // All four MKNOD types are supported; NFS refuses the others with
+        // NFS3ERR_BADTYPE before they get here
+        let kind = match file_type {
+            FileType::NamedPipe => libc::S_IFIFO,
+            FileType::Socket => libc::S_IFSOCK,
+            FileType::CharDevice => libc::S_IFCHR,
+            FileType::BlockDevice => libc::S_IFBLK,
+            _ => return Err(anyhow!("Invalid file type for MKNOD: {:?}", file_type)),
+        };
+        let dev = match file_type {
+            FileType::CharDevice | FileType::BlockDevice => libc::makedev(rdev.0, rdev.1),
+            _ => 0,
+        };
+
+        // mknod(2) creates FIFOs and sockets for any caller that may write
+        // the directory; device nodes need CAP_MKNOD and fail with EPERM
+        // otherwise. The errno is kept so the NFS layer can map it.
+        self.root_dir
+            .mknod(&file_path, kind | (mode & 0o7777), dev)
+            .map_err(|e| FsalError::io(format!("Failed to create {:?} {:?}", file_type, file_path), e))?;
+
+        // Create handle for the new special file
+        let handle = self.issue_handle(file_path.clone())?;
+        self.metadata_changed(&handle);
+        self.data_changed(dir_handle);
+        Ok(handle)
+    }
+
+    fn get_xattr(&self, handle: &FileHandle, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.resolve_handle(handle)?;
+        let value = self
+            .root_dir
+            .open_attributes(&path)
+            .and_then(|file| file.get_xattr(name))
+            .map_err(|e| FsalError::io(format!("Failed to get xattr {} on {:?}", name, path), e))?;
+        debug!("GETXATTR: {:?} {} -> {:?} bytes", path, name, value.as_ref().map(Vec::len));
+        Ok(value)
+    }
+
+    fn set_xattr(&self, handle: &FileHandle, name: &str, value: &[u8]) -> Result<()> {
+        let path = self.resolve_handle(handle)?;
+        let result = self.root_dir.open_attributes(&path).and_then(|file| file.set_xattr(name, value));
+        self.metadata_changed(handle);
+        result.map_err(|e| FsalError::io(format!("Failed to set xattr {} on {:?}", name, path), e))?;
+        debug!("SETXATTR: {:?} {} ({} bytes)", path, name, value.len());
+        Ok(())
+    }
+
+    fn list_xattr(&self, handle: &FileHandle) -> Result<Vec<String>> {
+        let path = self.resolve_handle(handle)?;
+        let names = self
+            .root_dir
+            .open_attributes(&path)
+            .and_then(|file| file.list_xattr())
+            .map_err(|e| FsalError::io(format!("Failed to list xattrs on {:?}", path), e))?
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        Ok(names)
     }
 
-    fn commit(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<()> {
+    fn remove_xattr(&self, handle: &FileHandle, name: &str) -> Result<()> {
         let path = self.resolve_handle(handle)?;
+        let result = self.root_dir.open_attributes(&path).and_then(|file| file.remove_xattr(name));
+        self.metadata_changed(handle);
+        result.map_err(|e| FsalError::io(format!("Failed to remove xattr {} on {:?}", name, path), e))?;
+        debug!("REMOVEXATTR: {:?} {}", path, name);
+        Ok(())
+    }
 
-        // Open file for syncing
-        let file = fs::OpenOptions::new()
-            .write(true)
-            .open(&path)
-            .context(format!("Failed to open file for commit: {:?}", path))?;
-
-        // Sync data to disk
-        // Note: For a more sophisticated implementation, we could:
-        // 1. Only sync the specified range (offset, count) if the OS supports it
-        // 2. Use sync_data() instead of sync_all() to skip metadata sync
-        // 3. Track UNSTABLE writes and only sync those
-        //
-        // For now, we sync all data in the file for simplicity
-        file.sync_all()
-            .context(format!("Failed to sync file: {:?}", path))?;
+    fn get_acl(&self, handle: &FileHandle) -> Result<Option<Acl>> {
+        let path = self.resolve_handle(handle)?;
+        let value = match self.root_dir.open_attributes(&path).and_then(|file| file.get_xattr(ACL_ACCESS_XATTR)) {
+            Ok(value) => value,
+            // No ACL support on this filesystem: only mode bits apply
+            Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => None,
+            Err(e) => return Err(FsalError::io(format!("Failed to read ACL of {:?}", path), e).into()),
+        };
+        value.map(|bytes| Acl::from_xattr(&bytes)).transpose()
+    }
 
-        debug!(
-            "COMMIT: {:?} (offset={}, count={})",
-            path, offset, count
-        );
+    fn set_acl(&self, handle: &FileHandle, acl: &Acl) -> Result<()> {
+        let path = self.resolve_handle(handle)?;
+        if let Err(e) = acl.validate() {
+            debug!("SETACL: {:?} rejected: {}", path, e);
+            return Err(FsalError::io(
+                format!("Invalid ACL for {:?}", path),
+                std::io::Error::from_raw_os_error(libc::EINVAL),
+            )
+            .into());
+        }
 
+        // The kernel also updates the mode bits from the ACL
+        let result = self
+            .root_dir
+            .open_attributes(&path)
+            .and_then(|file| file.set_xattr(ACL_ACCESS_XATTR, &acl.to_xattr()));
+        self.metadata_changed(handle);
+        result.map_err(|e| FsalError::io(format!("Failed to set ACL of {:?}", path), e))?;
+        debug!("SETACL: {:?} ({} entries)", path, acl.entries.len());
         Ok(())
     }
 
-    fn mknod(
-        &self,
-        dir_handle: &FileHandle,
-        name: &str,
-        file_type: FileType,
-        mode: u32,
-        rdev: (u32, u32),
-    ) -> Result<FileHandle> {
-        let dir_path = self.resolve_handle(dir_handle)?;
-        let file_path = dir_path.join(name);
+    fn statfs(&self, handle: &FileHandle) -> Result<FsStats> {
+        let path = self.resolve_handle(handle)?;
+        if let Some(stats) = self.statfs_cache.get(handle) {
+            return Ok(stats);
+        }
+        // fstatvfs of the object itself, as statvfs by path would follow a symlink
+        let file = self
+            .root_dir
+            .open_path(&path)
+            .map_err(|e| FsalError::io(format!("Failed to open {:?}", path), e))?;
+        // SAFETY: statvfs is plain data, all-zero is a valid value
+        let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+        // SAFETY: the descriptor is open and stats outlives the call
+        if unsafe { libc::fstatvfs(std::os::fd::AsRawFd::as_raw_fd(&file), &mut stats) } != 0 {
+            let error = std::io::Error::last_os_error();
+            return Err(FsalError::io(format!("Failed to statvfs {:?}", path), error).into());
+        }
+        let stats = FsStats {
+            total_bytes: stats.f_blocks * stats.f_frsize,
+            free_bytes: stats.f_bfree * stats.f_frsize,
+            avail_bytes: stats.f_bavail * stats.f_frsize,
+            total_files: stats.f_files,
+            free_files: stats.f_ffree,
+            avail_files: stats.f_favail,
+        };
+        self.statfs_cache.insert(handle, stats);
+        Ok(stats)
+    }
 
-        debug!(
-            "MKNOD: {:?}/{} type={:?} mode={:o} rdev=({}, {})",
-            dir_path, name, file_type, mode, rdev.0, rdev.1
-        );
+    fn release_cached_files(&self) {
+        let released = self.fd_cache.len();
+        self.fd_cache.clear();
+        debug!("Released {} cached file(s) under {:?}", released, self.root_path);
+    }
 
-        // On Unix systems, we can create special files using libc functions
-        // For portability, we'll use std::os::unix::fs
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::DirBuilderExt;
-            use std::os::unix::io::AsRawFd;
-
-            match file_type {
-                FileType::NamedPipe => {
-                    // Create FIFO using mkfifo
-                    use std::ffi::CString;
-                    let c_path = CString::new(file_path.to_str().unwrap())?;
-                    let result = unsafe { libc::mkfifo(c_path.as_ptr(), mode) };
-                    if result != 0 {
-                        return Err(anyhow::anyhow!("Failed to create FIFO: {}", std::io::Error::last_os_error()));
-                    }
-                }
-                FileType::Socket => {
-                    // Unix domain sockets are typically created by bind(), not mknod
-                    // For now, we'll create a placeholder file
-                    // A real implementation would need socket creation logic
-                    return Err(anyhow::anyhow!("Socket creation via MKNOD not fully supported"));
-                }
-                FileType::CharDevice | FileType::BlockDevice => {
-                    // Create device file using mknod
-                    use std::ffi::CString;
-                    let c_path = CString::new(file_path.to_str().unwrap())?;
-                    let dev = libc::makedev(rdev.0, rdev.1);
-                    let mode_with_type = mode | match file_type {
-                        FileType::CharDevice => libc::S_IFCHR,
-                        FileType::BlockDevice => libc::S_IFBLK,
-                        _ => 0,
-                    };
-                    let result = unsafe { libc::mknod(c_path.as_ptr(), mode_with_type, dev) };
-                    if result != 0 {
-                        return Err(anyhow::anyhow!("Failed to create device: {}", std::io::Error::last_os_error()));
-                    }
-                }
-                _ => {
-                    return Err(anyhow::anyhow!("Invalid file type for MKNOD: {:?}", file_type));
-                }
-            }
-        }
+    fn handle_count(&self) -> usize {
+        self.handle_manager.count()
+    }
+}
 
-        #[cfg(not(unix))]
-        {
-            return Err(anyhow::anyhow!("MKNOD is only supported on Unix systems"));
+/// Run a check of an export root on its own thread, failing after `timeout`
+///
+/// A check still blocked at the deadline is left running; its thread ends
+/// whenever the storage answers. A zero timeout runs the check in place.
+///
+/// # Arguments
+/// * `root` - Export root being checked, for the error message
+/// * `timeout` - Time the check may take
+/// * `check` - Work touching the root
+pub fn with_root_timeout<T: Send + 'static>(
+    root: &Path,
+    timeout: Duration,
+    check: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    if timeout.is_zero() {
+        return check();
+    }
+    let (result_tx, result_rx) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("export-root-check".to_string())
+        .spawn(move || {
+            let _ = result_tx.send(check());
+        })
+        .context("Failed to start export root check")?;
+    match result_rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Err(anyhow!(
+            "Export root {:?} did not respond within {:?}; is it on an unreachable network filesystem \
+             or an automount that cannot be mounted?",
+            root,
+            timeout
+        )),
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+            Err(anyhow!("Check of export root {:?} failed without a result", root))
         }
+    }
+}
 
-        // Create handle for the new special file
-        let handle = self.handle_manager.create_handle(file_path.clone());
-        Ok(handle)
+/// Inode generation the filesystem reports for a file (FS_IOC_GETVERSION)
+///
+/// statx does not report it, so the file is opened to ask. Only regular
+/// files and directories are opened, since opening a device can have side
+/// effects. None for other types, for filesystems without generations and
+/// for files the server cannot open.
+fn fs_generation(root: &ExportRoot, path: &Path, metadata: &fs::Metadata) -> Option<u32> {
+    if !metadata.is_file() && !metadata.is_dir() {
+        return None;
     }
+    let flags = libc::O_RDONLY | libc::O_NONBLOCK | libc::O_NOFOLLOW | libc::O_NOCTTY;
+    let file = root.open_file(path, flags, 0).ok()?;
+    // Filesystems store an int although the ioctl number declares a long:
+    // the buffer has room for a long and the int is read from its start
+    let mut buffer = [0u8; std::mem::size_of::<libc::c_long>()];
+    // SAFETY: the descriptor is open and the buffer outlives the call
+    let result = unsafe {
+        libc::ioctl(std::os::fd::AsRawFd::as_raw_fd(&file), libc::FS_IOC_GETVERSION, buffer.as_mut_ptr())
+    };
+    (result == 0).then(|| u32::from_ne_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-    use std::io::Write;
     use tempfile::TempDir;
 
     /// Helper: Create a test filesystem with a temporary directory
@@ -757,6 +1686,68 @@ mod tests {
         (fs, temp_dir)
     }
 
+    #[test]
+    fn test_handle_of_recreated_file_is_stale() {
+        let (fs, temp_dir) = create_test_fs();
+        let path = temp_dir.path().join("file");
+        std::fs::write(&path, b"old").unwrap();
+        let old = fs.lookup(&fs.root_handle(), "file").unwrap();
+        let old_inode = decode_handle(&old).unwrap().inode;
+
+        // Deleted and recreated behind the server's back; the filesystem
+        // usually hands out the freed inode number again, but the old
+        // handle must go stale either way
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, b"new").unwrap();
+        let reused = std::fs::metadata(&path).unwrap().ino() == old_inode;
+
+        let error = fs.getattr(&old).unwrap_err();
+        assert!(
+            matches!(error.downcast_ref::<FsalError>(), Some(FsalError::Stale { .. })),
+            "inode reused: {}, error: {:#}",
+            reused,
+            error
+        );
+        assert_eq!(
+            crate::nfs::error::handle_error_status(&error),
+            Some(crate::protocol::v3::nfs::nfsstat3::NFS3ERR_STALE)
+        );
+        assert!(fs.read(&old, 0, 3).is_err(), "The stale handle stays stale");
+
+        // A new lookup issues a fresh handle for the new file
+        let new = fs.lookup(&fs.root_handle(), "file").unwrap();
+        assert_ne!(new, old);
+        assert_eq!(fs.read(&new, 0, 3).unwrap().as_ref(), b"new");
+    }
+
+    #[test]
+    fn test_open_missing_root_fails_promptly() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("missing");
+
+        let started = std::time::Instant::now();
+        let error = LocalFilesystem::open_with_timeout(&missing, Duration::from_secs(5)).err().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+        let message = format!("{:#}", error);
+        assert!(message.contains("Failed to canonicalize root path"), "{}", message);
+        assert!(message.contains("missing"), "{}", message);
+
+        LocalFilesystem::open_with_timeout(temp_dir.path(), Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn test_hung_root_check_times_out() {
+        let started = std::time::Instant::now();
+        let error = with_root_timeout(Path::new("/mnt/unreachable"), Duration::from_millis(50), || {
+            std::thread::sleep(Duration::from_secs(2));
+            Ok(())
+        })
+        .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+        let message = error.to_string();
+        assert!(message.contains("\"/mnt/unreachable\" did not respond within 50ms"), "{}", message);
+    }
+
     #[test]
     fn test_root_handle() {
         let (fs, _temp_dir) = create_test_fs();
@@ -774,6 +1765,21 @@ mod tests {
         assert_eq!(attr.ftype, FileType::Directory, "Root should be a directory");
     }
 
+    #[test]
+    fn test_corrupted_handle_fails_to_resolve() {
+        let (fs, _temp_dir) = create_test_fs();
+        let root = fs.root_handle();
+        let file_handle = fs.create(&root, "victim.txt", 0o644).unwrap();
+        assert!(fs.getattr(&file_handle).is_ok());
+
+        for byte in [0, 7, 8, 15] {
+            let mut corrupted = file_handle.clone();
+            corrupted[byte] ^= 0x01;
+            let err = fs.getattr(&corrupted).expect_err("Corrupted handle should not resolve");
+            assert!(err.to_string().contains("Invalid file handle"), "byte {}: {}", byte, err);
+        }
+    }
+
     #[test]
     fn test_create_and_lookup_file() {
         let (fs, _temp_dir) = create_test_fs();
@@ -806,19 +1812,19 @@ mod tests {
 
         // Write data
         let data = b"Hello, NFS World!";
-        let written = fs.write(&file_handle, 0, data)
+        let written = fs.write(&file_handle, 0, data, StableHow::FileSync)
             .expect("Failed to write");
         assert_eq!(written, data.len() as u32, "Should write all bytes");
 
         // Read data back
         let read_data = fs.read(&file_handle, 0, data.len() as u32)
             .expect("Failed to read");
-        assert_eq!(read_data, data, "Read data should match written data");
+        assert_eq!(read_data, &data[..], "Read data should match written data");
 
         // Read partial data
         let partial = fs.read(&file_handle, 7, 3)
             .expect("Failed to read partial");
-        assert_eq!(partial, b"NFS", "Partial read should work");
+        assert_eq!(partial, &b"NFS"[..], "Partial read should work");
     }
 
     #[test]
@@ -858,12 +1864,12 @@ mod tests {
             .expect("Failed to create nested file");
 
         // Write and read
-        fs.write(&file, 0, b"nested content")
+        fs.write(&file, 0, b"nested content", StableHow::FileSync)
             .expect("Failed to write");
 
         let content = fs.read(&file, 0, 100)
             .expect("Failed to read");
-        assert_eq!(content, b"nested content");
+        assert_eq!(content, &b"nested content"[..]);
     }
 
     #[test]
@@ -916,6 +1922,109 @@ mod tests {
         assert!(result.is_err(), "Should prevent / in filename");
     }
 
+    #[test]
+    fn test_chmod_does_not_follow_symlink_out_of_export() {
+        let (fs, temp_dir) = create_test_fs();
+        let inside = temp_dir.path().join("inside");
+        fs::write(&inside, b"x").unwrap();
+        fs::set_permissions(&inside, fs::Permissions::from_mode(0o600)).unwrap();
+        let link_path = temp_dir.path().join("link");
+        std::os::unix::fs::symlink(&inside, &link_path).unwrap();
+        let link = fs.lookup(&fs.root_handle(), "link").unwrap();
+
+        // Changing a symlink's mode never reaches its target
+        let _ = fs.setattr_mode(&link, 0o777);
+        assert_eq!(fs::metadata(&inside).unwrap().permissions().mode() & 0o7777, 0o600);
+
+        // Nor once the link is retargeted outside the export
+        let outside = TempDir::new().unwrap();
+        let secret = outside.path().join("secret");
+        fs::write(&secret, b"x").unwrap();
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o600)).unwrap();
+        fs::remove_file(&link_path).unwrap();
+        std::os::unix::fs::symlink(&secret, &link_path).unwrap();
+        let _ = fs.setattr_mode(&link, 0o777);
+        assert_eq!(fs::metadata(&secret).unwrap().permissions().mode() & 0o7777, 0o600);
+    }
+
+    #[test]
+    fn test_getattr_of_symlink_is_not_its_target() {
+        let (fs, temp_dir) = create_test_fs();
+        let inside = temp_dir.path().join("inside");
+        fs::write(&inside, b"target contents").unwrap();
+        let link_path = temp_dir.path().join("link");
+        std::os::unix::fs::symlink(&inside, &link_path).unwrap();
+        let link = fs.lookup(&fs.root_handle(), "link").unwrap();
+
+        let attrs = fs.getattr(&link).unwrap();
+        assert_eq!(attrs.ftype, FileType::SymbolicLink);
+        assert_eq!(attrs.size, inside.as_os_str().len() as u64);
+
+        // A link retargeted outside the export still reports only itself
+        let outside = TempDir::new().unwrap();
+        let secret = outside.path().join("secret");
+        fs::write(&secret, b"outside").unwrap();
+        fs::remove_file(&link_path).unwrap();
+        std::os::unix::fs::symlink(&secret, &link_path).unwrap();
+        if let Ok(attrs) = fs.getattr(&link) {
+            assert_eq!(attrs.ftype, FileType::SymbolicLink);
+            assert_eq!(attrs.size, secret.as_os_str().len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_directory_swapped_for_symlink_does_not_escape() {
+        let (fs, temp_dir) = create_test_fs();
+        let outside = TempDir::new().unwrap();
+        let secret = outside.path().join("f");
+        fs::write(&secret, b"outside").unwrap();
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let sub = fs.mkdir(&fs.root_handle(), "sub", 0o755).unwrap();
+        let file = fs.create(&sub, "f", 0o644).unwrap();
+
+        // Swap the directory for a symlink to one outside the export
+        fs::remove_dir_all(temp_dir.path().join("sub")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), temp_dir.path().join("sub")).unwrap();
+
+        assert!(fs.getattr(&file).is_err());
+        assert!(fs.setattr_mode(&file, 0o777).is_err());
+        assert_eq!(fs::metadata(&secret).unwrap().permissions().mode() & 0o7777, 0o600);
+
+        // Nor do extended attributes, ACLs or listings
+        assert!(fs.set_xattr(&file, "user.test", b"value").is_err());
+        assert!(fs.get_xattr(&file, "user.test").is_err());
+        assert!(fs.set_acl(&file, &Acl::from_mode(0o777)).is_err());
+        assert_eq!(xattr::get(&secret, "user.test").unwrap(), None);
+        assert_eq!(fs::metadata(&secret).unwrap().permissions().mode() & 0o7777, 0o600);
+        assert!(fs.readdir(&sub, 0, 100).is_err());
+    }
+
+    #[test]
+    fn test_handle_for_path() {
+        let (fs, temp_dir) = create_test_fs();
+        let root = fs.root_handle();
+        fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        fs::write(temp_dir.path().join("a/file.txt"), b"data").unwrap();
+        std::os::unix::fs::symlink(temp_dir.path().join("a"), temp_dir.path().join("link")).unwrap();
+
+        for path in ["", "/", ".", "./"] {
+            let (handle, attrs) = fs.handle_for_path(path).unwrap();
+            assert_eq!(handle, root, "{:?} is the root", path);
+            assert_eq!(attrs.ftype, FileType::Directory);
+        }
+
+        // The handle is the one LOOKUP issues
+        let (handle, _) = fs.handle_for_path("/a//./b/").unwrap();
+        assert_eq!(handle, fs.lookup(&fs.lookup(&root, "a").unwrap(), "b").unwrap());
+        let (_, attrs) = fs.handle_for_path("a/file.txt").unwrap();
+        assert_eq!(attrs.size, 4);
+
+        for path in ["..", "a/../..", "missing", "a/file.txt/x", "link/b"] {
+            assert!(fs.handle_for_path(path).is_err(), "{:?} should not resolve", path);
+        }
+    }
+
     #[test]
     fn test_lookup_nonexistent() {
         let (fs, _temp_dir) = create_test_fs();
@@ -925,6 +2034,131 @@ mod tests {
         assert!(result.is_err(), "Lookup should fail for nonexistent file");
     }
 
+    #[test]
+    fn test_getattr_after_write_is_fresh() {
+        let (fs, _temp_dir) = create_test_fs();
+        let root = fs.root_handle();
+
+        let file = fs.create(&root, "cached.txt", 0o644)
+            .expect("Failed to create file");
+
+        let before = fs.getattr(&file).expect("Failed to get attributes");
+        assert_eq!(before.size, 0);
+
+        // Second GETATTR with no change is served from the cache
+        let cached = fs.getattr(&file).expect("Failed to get attributes");
+        assert_eq!(cached.size, 0);
+
+        fs.write(&file, 0, b"twelve bytes", StableHow::FileSync).expect("Failed to write");
+
+        let after = fs.getattr(&file).expect("Failed to get attributes");
+        assert_eq!(after.size, 12, "GETATTR after WRITE must see the new size");
+    }
+
+    #[test]
+    fn test_direct_io_aligned_and_unaligned_writes() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let fs = LocalFilesystem::new(temp_dir.path())
+            .expect("Failed to create filesystem")
+            .with_direct_io(true);
+        let root = fs.root_handle();
+        let file = fs.create(&root, "direct.bin", 0o644)
+            .expect("Failed to create file");
+
+        let block = direct_io::DIRECT_IO_ALIGN;
+        let pattern = |len: usize, seed: u8| -> Vec<u8> {
+            (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+        };
+
+        // Aligned offset, two blocks plus an unaligned tail
+        let aligned = pattern(2 * block + 100, 1);
+        assert_eq!(fs.write(&file, 0, &aligned, StableHow::FileSync).unwrap(), aligned.len() as u32);
+
+        // Unaligned offset, entirely buffered
+        let unaligned = pattern(block + 7, 2);
+        let unaligned_offset = 3 * block as u64 + 13;
+        assert_eq!(fs.write(&file, unaligned_offset, &unaligned, StableHow::FileSync).unwrap(), unaligned.len() as u32);
+
+        let mut expected = aligned.clone();
+        expected.resize(unaligned_offset as usize, 0);
+        expected.extend_from_slice(&unaligned);
+
+        let contents = std::fs::read(temp_dir.path().join("direct.bin")).unwrap();
+        assert_eq!(contents, expected);
+        assert_eq!(fs.read(&file, 0, expected.len() as u32).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_commit_range_after_unstable_write() {
+        let (fs, temp_dir) = create_test_fs();
+        let root = fs.root_handle();
+        let file = fs.create(&root, "range.bin", 0o644)
+            .expect("Failed to create file");
+
+        let data = vec![0xa5u8; 1024 * 1024];
+        let written = fs.write(&file, 0, &data, StableHow::Unstable)
+            .expect("Failed to write");
+        assert_eq!(written as usize, data.len());
+        assert_eq!(fs.sync_count(), 0);
+
+        // Commit a 4 KiB range in the middle: it is made durable with an
+        // fdatasync, leaving nothing unstable for a later flush
+        fs.commit(&file, 512 * 1024, 4096).expect("Range COMMIT should succeed");
+        assert_eq!(fs.sync_count(), 1);
+        assert_eq!(fs.flush().unwrap(), 0, "A range COMMIT leaves the file stable");
+
+        // Then the whole file
+        fs.commit(&file, 0, 0).expect("Whole-file COMMIT should succeed");
+        assert_eq!(fs.sync_count(), 2);
+
+        let contents = std::fs::read(temp_dir.path().join("range.bin")).unwrap();
+        assert_eq!(contents, data);
+    }
+
+    #[test]
+    fn test_concurrent_commits_share_one_sync() {
+        const COMMITS: usize = 8;
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path())
+            .unwrap()
+            .with_commit_window(Duration::from_millis(200));
+        let file = fs.create(&fs.root_handle(), "db.bin", 0o644).unwrap();
+        for i in 0..COMMITS {
+            fs.write(&file, i as u64 * 4096, &[i as u8; 4096], StableHow::Unstable).unwrap();
+        }
+
+        let barrier = std::sync::Barrier::new(COMMITS);
+        std::thread::scope(|scope| {
+            for i in 0..COMMITS {
+                let (fs, file, barrier) = (&fs, &file, &barrier);
+                scope.spawn(move || {
+                    barrier.wait();
+                    fs.commit(file, i as u64 * 4096, 4096).expect("COMMIT should succeed");
+                });
+            }
+        });
+        assert_eq!(fs.sync_count(), 1, "One sync should answer every COMMIT");
+
+        // A later COMMIT is not answered by the earlier sync
+        fs.commit(&file, 0, 0).unwrap();
+        assert_eq!(fs.sync_count(), 2);
+    }
+
+    #[test]
+    fn test_read_error_preserves_errno() {
+        let (fs, _temp_dir) = create_test_fs();
+        let root = fs.root_handle();
+
+        let dir = fs.mkdir(&root, "not_a_file", 0o755)
+            .expect("Failed to create directory");
+
+        // Reading a directory fails in read(2) with EISDIR
+        let error = fs.read(&dir, 0, 16).expect_err("Read of a directory should fail");
+        let fsal_error = error.downcast_ref::<FsalError>().expect("Should be an FsalError");
+        assert_eq!(fsal_error.errno(), Some(libc::EISDIR));
+        assert!(fsal_error.to_string().contains("not_a_file"));
+    }
+
     #[test]
     fn test_handle_idempotency() {
         let (fs, _temp_dir) = create_test_fs();
@@ -940,4 +2174,548 @@ mod tests {
 
         assert_eq!(handle1, handle2, "Multiple lookups should return same handle");
     }
+
+    #[test]
+    fn test_xattr_round_trip() {
+        let (fs, _temp_dir) = create_test_fs();
+        let root = fs.root_handle();
+        let file = fs.create(&root, "xattr.txt", 0o644).expect("Failed to create file");
+
+        assert_eq!(fs.get_xattr(&file, "user.test").unwrap(), None);
+
+        fs.set_xattr(&file, "user.test", b"value").expect("Failed to set xattr");
+        assert_eq!(fs.get_xattr(&file, "user.test").unwrap(), Some(b"value".to_vec()));
+        assert!(fs.list_xattr(&file).unwrap().contains(&"user.test".to_string()));
+
+        // Setting again replaces the value
+        fs.set_xattr(&file, "user.test", b"other").unwrap();
+        assert_eq!(fs.get_xattr(&file, "user.test").unwrap(), Some(b"other".to_vec()));
+
+        fs.remove_xattr(&file, "user.test").expect("Failed to remove xattr");
+        assert_eq!(fs.get_xattr(&file, "user.test").unwrap(), None);
+        let error = fs.remove_xattr(&file, "user.test").expect_err("Second remove should fail");
+        assert_eq!(crate::fsal::error::errno_of(&error), Some(libc::ENODATA));
+    }
+
+    #[test]
+    fn test_attr_cache_sees_server_mutations() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path())
+            .unwrap()
+            .with_attr_cache(Duration::from_secs(3600), 1024);
+        let root = fs.root_handle();
+        let file = fs.create(&root, "cached.txt", 0o644).unwrap();
+
+        // Within the TTL a repeated GETATTR issues no syscall
+        assert_eq!(fs.getattr(&file).unwrap().size, 0);
+        let stats = fs.stat_count();
+        fs.getattr(&file).unwrap();
+        assert_eq!(fs.stat_count(), stats);
+
+        // Mutations through the server are visible immediately
+        fs.write(&file, 0, b"twelve bytes", StableHow::Unstable).unwrap();
+        assert_eq!(fs.getattr(&file).unwrap().size, 12);
+
+        fs.setattr_mode(&file, 0o600).unwrap();
+        assert_eq!(fs.getattr(&file).unwrap().mode & 0o777, 0o600);
+
+        let link = fs.link(&file, &root, "other.txt").unwrap();
+        assert_eq!(fs.getattr(&link).unwrap().nlink, 2);
+
+        // A second name seen through its own handle shares the inode
+        let other = fs.lookup(&root, "other.txt").unwrap();
+        assert_eq!(fs.getattr(&other).unwrap().nlink, 2);
+        fs.remove(&root, "other.txt").unwrap();
+        assert_eq!(fs.getattr(&file).unwrap().nlink, 1);
+        fs.remove(&root, "cached.txt").unwrap();
+        assert!(fs.lookup(&root, "cached.txt").is_err());
+        assert!(fs.getattr(&file).is_err());
+    }
+
+    #[test]
+    fn test_attr_cache_zero_ttl_revalidates() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path())
+            .unwrap()
+            .with_attr_cache(Duration::ZERO, 1024);
+        let file = fs.create(&fs.root_handle(), "f.txt", 0o644).unwrap();
+
+        fs.getattr(&file).unwrap();
+        // Changes made behind the server's back are seen at once
+        fs::write(temp_dir.path().join("f.txt"), b"external").unwrap();
+        assert_eq!(fs.getattr(&file).unwrap().size, 8);
+    }
+
+    #[test]
+    fn test_fd_cache_sequential_read_opens_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let content: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+        fs::write(temp_dir.path().join("seq.bin"), &content).unwrap();
+
+        // Read the file in 64 KiB chunks like a client doing sequential READs
+        let read_sequentially = |fs: &LocalFilesystem| {
+            let file = fs.lookup(&fs.root_handle(), "seq.bin").unwrap();
+            let mut data = Vec::new();
+            for offset in (0..content.len() as u64).step_by(64 * 1024) {
+                data.extend_from_slice(&fs.read(&file, offset, 64 * 1024).unwrap());
+            }
+            assert_eq!(data, content);
+            fs.open_count()
+        };
+
+        let uncached = LocalFilesystem::new(temp_dir.path()).unwrap().with_max_open_files(0);
+        let cached = LocalFilesystem::new(temp_dir.path()).unwrap().with_max_open_files(16);
+        assert_eq!(read_sequentially(&uncached), 16);
+        assert_eq!(read_sequentially(&cached), 1);
+    }
+
+    #[test]
+    fn test_concurrent_writes_at_distinct_offsets() {
+        const WRITERS: usize = 8;
+        const CHUNKS_PER_WRITER: usize = 64;
+        const CHUNK: usize = 4096;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+        let handle = fs.create(&fs.root_handle(), "shared.bin", 0o644).unwrap();
+
+        // Every writer shares the cached descriptor and interleaves its
+        // chunks with the others'
+        std::thread::scope(|scope| {
+            for writer in 0..WRITERS {
+                let (fs, handle) = (&fs, &handle);
+                scope.spawn(move || {
+                    for round in 0..CHUNKS_PER_WRITER {
+                        let index = round * WRITERS + writer;
+                        let chunk = vec![(index % 251) as u8; CHUNK];
+                        let offset = (index * CHUNK) as u64;
+                        assert_eq!(fs.write(handle, offset, &chunk, StableHow::Unstable).unwrap(), CHUNK as u32);
+                    }
+                });
+            }
+        });
+
+        let contents = std::fs::read(temp_dir.path().join("shared.bin")).unwrap();
+        assert_eq!(contents.len(), WRITERS * CHUNKS_PER_WRITER * CHUNK);
+        for (index, chunk) in contents.chunks(CHUNK).enumerate() {
+            assert!(chunk.iter().all(|byte| *byte == (index % 251) as u8), "chunk {} is corrupt", index);
+        }
+
+        // Concurrent READs see the same data
+        std::thread::scope(|scope| {
+            for reader in 0..WRITERS {
+                let (fs, handle, contents) = (&fs, &handle, &contents);
+                scope.spawn(move || {
+                    for round in 0..CHUNKS_PER_WRITER {
+                        let offset = (round * WRITERS + reader) * CHUNK;
+                        let data = fs.read(handle, offset as u64, CHUNK as u32).unwrap();
+                        assert_eq!(&data[..], &contents[offset..offset + CHUNK]);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_readahead_sequential_4k_reads() {
+        const READS: u64 = 10_000;
+        const CHUNK: u32 = 4096;
+        let temp_dir = TempDir::new().unwrap();
+        let content: Vec<u8> = (0..READS as usize * CHUNK as usize).map(|i| (i % 251) as u8).collect();
+        fs::write(temp_dir.path().join("seq.bin"), &content).unwrap();
+
+        // 10k sequential 4 KiB READs, as a client with a small rsize issues them
+        let read_sequentially = |fs: &LocalFilesystem| {
+            let file = fs.lookup(&fs.root_handle(), "seq.bin").unwrap();
+            let mut data = Vec::with_capacity(content.len());
+            for i in 0..READS {
+                data.extend_from_slice(&fs.read(&file, i * CHUNK as u64, CHUNK).unwrap());
+            }
+            assert!(data == content);
+            fs.read_count()
+        };
+
+        let plain = LocalFilesystem::new(temp_dir.path()).unwrap();
+        let ahead = LocalFilesystem::new(temp_dir.path()).unwrap().with_readahead(1024 * 1024);
+        assert_eq!(read_sequentially(&plain), READS);
+        // One positioning READ, then one per 1 MiB window
+        assert_eq!(read_sequentially(&ahead), 1 + (READS * CHUNK as u64 - CHUNK as u64).div_ceil(1024 * 1024));
+    }
+
+    #[test]
+    fn test_read_of_hole_returns_zeros_without_disk_read() {
+        const SIZE: u64 = 64 * 1024 * 1024;
+        let temp_dir = TempDir::new().unwrap();
+        let file = fs::File::create(temp_dir.path().join("disk.img")).unwrap();
+        file.set_len(SIZE).unwrap();
+        file.write_all_at(b"boot sector", 0).unwrap();
+        file.write_all_at(b"tail", SIZE - 4).unwrap();
+        drop(file);
+
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+        let handle = fs.lookup(&fs.root_handle(), "disk.img").unwrap();
+        let data = fs.read(&handle, 32 * 1024 * 1024, 1024 * 1024).unwrap();
+        assert_eq!(data.len(), 1024 * 1024);
+        assert!(data.iter().all(|byte| *byte == 0));
+        assert_eq!(fs.read_count(), 0, "A hole is not read from disk");
+
+        // Ranges with data are read as usual, zeros around it included
+        assert_eq!(&fs.read(&handle, 0, 16).unwrap()[..], b"boot sector\0\0\0\0\0");
+        assert_eq!(&fs.read(&handle, SIZE - 8, 16).unwrap()[..], b"\0\0\0\0tail");
+        assert_eq!(fs.read_count(), 2);
+    }
+
+    #[test]
+    fn test_read_into_fills_caller_buffer() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("f.bin"), b"0123456789").unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+        let file = fs.lookup(&fs.root_handle(), "f.bin").unwrap();
+
+        let mut buf = [0xffu8; 8];
+        assert_eq!(fs.read_into(&file, 2, &mut buf[..4]).unwrap(), 4);
+        assert_eq!(&buf, b"2345\xff\xff\xff\xff");
+
+        // Short at end of file, the rest of the buffer untouched
+        assert_eq!(fs.read_into(&file, 6, &mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"6789\xff\xff\xff\xff");
+        assert_eq!(fs.read_into(&file, 10, &mut buf).unwrap(), 0);
+        assert_eq!(fs.read_count(), 3);
+
+        // Through readahead the data comes out the same
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap().with_readahead(64 * 1024);
+        let file = fs.lookup(&fs.root_handle(), "f.bin").unwrap();
+        assert_eq!(fs.read_into(&file, 0, &mut buf[..5]).unwrap(), 5);
+        assert_eq!(fs.read_into(&file, 5, &mut buf[5..]).unwrap(), 3);
+        assert_eq!(&buf, b"01234567");
+    }
+
+    #[test]
+    fn test_statfs_cached_until_large_write() {
+        const CHUNK: usize = 1024 * 1024;
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("big.bin"), b"").unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap().with_statfs_cache(Duration::from_secs(60));
+        let root = fs.root_handle();
+        let file = fs.lookup(&root, "big.bin").unwrap();
+        let before = fs.statfs(&root).unwrap();
+
+        // Space used behind the server's back is not seen within the TTL
+        fs::write(temp_dir.path().join("outside.bin"), vec![1u8; 4 * CHUNK]).unwrap();
+        assert_eq!(fs.statfs(&root).unwrap(), before);
+
+        // Writing a large file through the server refreshes the statistics
+        let chunk = vec![7u8; CHUNK];
+        for i in 0..64 {
+            fs.write(&file, (i * CHUNK) as u64, &chunk, StableHow::FileSync).unwrap();
+        }
+        let after = fs.statfs(&root).unwrap();
+        assert!(after.free_bytes < before.free_bytes, "{:?} after writing 64 MiB, {:?} before", after, before);
+    }
+
+    #[test]
+    fn test_readahead_sees_interleaved_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("f.bin"), vec![b'a'; 64 * 1024]).unwrap();
+        fs::hard_link(temp_dir.path().join("f.bin"), temp_dir.path().join("link.bin")).unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap().with_readahead(32 * 1024);
+        let root = fs.root_handle();
+        let file = fs.lookup(&root, "f.bin").unwrap();
+        let link = fs.lookup(&root, "link.bin").unwrap();
+
+        // Start a window covering 4..36 KiB
+        fs.read(&file, 0, 4096).unwrap();
+        fs.read(&file, 4096, 4096).unwrap();
+
+        // A write inside the window through the same handle is seen
+        fs.write(&file, 8192, b"bbbb", StableHow::Unstable).unwrap();
+        assert_eq!(&fs.read(&file, 8192, 4096).unwrap()[..5], b"bbbba");
+
+        // So is one through another link to the same inode
+        fs.read(&file, 12288, 4096).unwrap();
+        fs.write(&link, 16384, b"cccc", StableHow::Unstable).unwrap();
+        assert_eq!(&fs.read(&file, 16384, 4096).unwrap()[..5], b"cccca");
+
+        // And a truncation inside the window
+        fs.read(&file, 20480, 4096).unwrap();
+        fs.setattr_size(&file, 24576 + 100).unwrap();
+        assert_eq!(fs.read(&file, 24576, 4096).unwrap().len(), 100);
+        assert!(fs.read(&file, 24676, 4096).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fd_cache_write_read_commit_consistent() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap().with_max_open_files(4);
+        let root = fs.root_handle();
+        let file = fs.create(&root, "cached.txt", 0o644).unwrap();
+
+        // WRITE opens the file once; READ and COMMIT reuse that descriptor
+        fs.write(&file, 0, b"hello", StableHow::Unstable).unwrap();
+        fs.write(&file, 5, b" world", StableHow::Unstable).unwrap();
+        assert_eq!(fs.read(&file, 0, 64).unwrap(), &b"hello world"[..]);
+        fs.commit(&file, 0, 0).unwrap();
+        assert_eq!(fs.open_count(), 1);
+        assert_eq!(fs.sync_count(), 1);
+        assert_eq!(fs::read(temp_dir.path().join("cached.txt")).unwrap(), b"hello world");
+
+        // A name recreated after REMOVE is not served from the old descriptor
+        fs.remove(&root, "cached.txt").unwrap();
+        let file = fs.create(&root, "cached.txt", 0o644).unwrap();
+        fs::write(temp_dir.path().join("cached.txt"), b"new").unwrap();
+        assert_eq!(fs.read(&file, 0, 64).unwrap(), &b"new"[..]);
+
+        // Likewise for a name replaced by RENAME; the replaced file's
+        // handle is stale and the name now has the renamed file's handle
+        fs::write(temp_dir.path().join("other.txt"), b"renamed").unwrap();
+        fs.rename(&root, "other.txt", &root, "cached.txt").unwrap();
+        assert!(fs.read(&file, 0, 64).is_err());
+        let file = fs.lookup(&root, "cached.txt").unwrap();
+        assert_eq!(fs.read(&file, 0, 64).unwrap(), &b"renamed"[..]);
+    }
+
+    #[test]
+    fn test_write_advances_reported_times() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+        let root = fs.root_handle();
+        let file = fs.create(&root, "times.txt", 0o644).unwrap();
+
+        // Back-to-back writes usually land within one timestamp tick
+        let mut before = fs.getattr(&file).unwrap();
+        for offset in 0..8u64 {
+            fs.write(&file, offset, b"x", StableHow::Unstable).unwrap();
+            let after = fs.getattr(&file).unwrap();
+            assert!(after.mtime > before.mtime, "{:?} !> {:?}", after.mtime, before.mtime);
+            assert!(after.ctime > before.ctime, "{:?} !> {:?}", after.ctime, before.ctime);
+            before = after;
+        }
+
+        // Metadata changes advance ctime but leave mtime alone
+        fs.setattr_mode(&file, 0o600).unwrap();
+        let after = fs.getattr(&file).unwrap();
+        assert!(after.ctime > before.ctime);
+        assert!(after.mtime >= before.mtime);
+
+        // The guard of a SETATTR is checked against the reported ctime
+        let attrs = SetAttributes { mode: Some(0o640), ..Default::default() };
+        fs.setattr(&file, &attrs, Some(after.ctime)).unwrap();
+        assert!(fs.setattr(&file, &attrs, Some(after.ctime)).is_err());
+    }
+
+    #[test]
+    fn test_handle_stale_after_recreate() {
+        use crate::fsal::handle::decode_handle;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+        let root = fs.root_handle();
+
+        let old = fs.create(&root, "reused.txt", 0o644).unwrap();
+        fs.write(&old, 0, b"old", StableHow::FileSync).unwrap();
+        fs.remove(&root, "reused.txt").unwrap();
+        let new = fs.create(&root, "reused.txt", 0o644).unwrap();
+
+        assert_ne!(old, new);
+        let err = fs.getattr(&old).unwrap_err();
+        assert!(err.to_string().contains("Invalid file handle"), "{}", err);
+        assert!(fs.read(&old, 0, 16).is_err());
+        assert_eq!(fs.getattr(&new).unwrap().size, 0);
+        assert_eq!(fs.lookup(&root, "reused.txt").unwrap(), new);
+
+        // When the freed inode number is reused, its generation moved on
+        let (old_fields, new_fields) = (decode_handle(&old).unwrap(), decode_handle(&new).unwrap());
+        if old_fields.inode == new_fields.inode {
+            assert_eq!(new_fields.generation, old_fields.generation + 1);
+        }
+
+        // Removing one of two hard links keeps the inode, and its handles, alive
+        let file = fs.create(&root, "linked.txt", 0o644).unwrap();
+        fs.link(&file, &root, "other.txt").unwrap();
+        let other = fs.lookup(&root, "other.txt").unwrap();
+        fs.remove(&root, "linked.txt").unwrap();
+        assert!(fs.getattr(&other).is_ok());
+
+        // A rename over a file stales the replaced file's handle
+        let victim = fs.create(&root, "victim.txt", 0o644).unwrap();
+        fs.rename(&root, "other.txt", &root, "victim.txt").unwrap();
+        assert!(fs.getattr(&victim).is_err());
+        assert!(fs.getattr(&fs.lookup(&root, "victim.txt").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_readdir_plus_matches_lookup_and_getattr() {
+        let (fs, temp_dir) = create_test_fs();
+        fs::write(temp_dir.path().join("file"), b"data").unwrap();
+        fs::create_dir(temp_dir.path().join("subdir")).unwrap();
+        std::os::unix::fs::symlink("file", temp_dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("missing", temp_dir.path().join("dangling")).unwrap();
+        let root = fs.root_handle();
+
+        let summary = |attrs: &Option<FileAttributes>| {
+            attrs.as_ref().map(|a| (a.ftype, a.mode, a.size, a.fileid, a.nlink, a.mtime, a.ctime))
+        };
+        let (entries, eof) = fs.readdir_plus(&root, 0, 100, 65536).unwrap();
+        assert!(eof);
+        assert_eq!(entries.len(), 4);
+        for DirEntryPlus { entry, attributes, handle } in &entries {
+            let expected_handle = fs.lookup(&root, &entry.name).ok();
+            let expected_attributes = expected_handle.as_ref().and_then(|h| fs.getattr(h).ok());
+            assert_eq!(handle, &expected_handle, "{}", entry.name);
+            assert_eq!(summary(attributes), summary(&expected_attributes), "{}", entry.name);
+            // A symlink has attributes of its own, even when dangling
+            assert!(attributes.is_some(), "{}", entry.name);
+        }
+
+        // Windows line up with readdir's
+        let (first, eof) = fs.readdir_plus(&root, 0, 2, 65536).unwrap();
+        assert!(!eof);
+        let (rest, _) = fs.readdir_plus(&root, 2, 100, 65536).unwrap();
+        let names: Vec<_> = first.iter().chain(&rest).map(|e| e.entry.name.clone()).collect();
+        let (listed, _) = fs.readdir(&root, 0, 100).unwrap();
+        assert_eq!(names, listed.iter().map(|e| e.name.clone()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_readdir_lists_dot_entries_first() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        fs::write(temp_dir.path().join("sub/file"), b"x").unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap().with_dot_entries(true);
+        let root = fs.root_handle();
+        let root_ino = fs::metadata(temp_dir.path()).unwrap().ino();
+        let sub_ino = fs::metadata(temp_dir.path().join("sub")).unwrap().ino();
+        let listing = |dir: &FileHandle, cookie: u64, count: u32| -> Vec<(String, u64)> {
+            let (entries, _) = fs.readdir(dir, cookie, count).unwrap();
+            entries.into_iter().map(|entry| (entry.name, entry.fileid)).collect()
+        };
+
+        // At the export root ".." is the root itself
+        let names = |entries: &[(String, u64)]| entries.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+        let entries = listing(&root, 0, 100);
+        assert_eq!(names(&entries), [".", "..", "sub"]);
+        assert_eq!((entries[0].1, entries[1].1, entries[2].1), (root_ino, root_ino, sub_ino));
+
+        // Below it ".." is the parent, and cookies count the dot entries
+        let sub = fs.lookup(&root, "sub").unwrap();
+        let entries = listing(&sub, 0, 100);
+        assert_eq!(names(&entries), [".", "..", "file"]);
+        assert_eq!((entries[0].1, entries[1].1), (sub_ino, root_ino));
+        assert_eq!(names(&listing(&sub, 0, 1)), ["."]);
+        assert_eq!(names(&listing(&sub, 1, 1)), [".."]);
+        assert_eq!(names(&listing(&sub, 2, 100)), ["file"]);
+
+        // READDIRPLUS gives ".." attributes but no handle
+        let (entries, _) = fs.readdir_plus(&sub, 0, 2, 65536).unwrap();
+        assert_eq!(entries[0].handle.as_ref(), Some(&sub));
+        assert_eq!(entries[1].attributes.as_ref().unwrap().fileid, root_ino);
+        assert!(entries[1].handle.is_none());
+    }
+
+    #[test]
+    fn test_readdir_continuations_read_directory_once() {
+        const ENTRIES: usize = 10_000;
+        const CHUNK: u32 = 100;
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..ENTRIES {
+            fs::File::create(temp_dir.path().join(format!("f{:05}", i))).unwrap();
+        }
+
+        // List the whole directory CHUNK entries at a time, as a client does
+        let list_in_chunks = |fs: &LocalFilesystem| {
+            let root = fs.root_handle();
+            let started = std::time::Instant::now();
+            let mut names = Vec::with_capacity(ENTRIES);
+            let mut cookie = 0;
+            loop {
+                let (entries, eof) = fs.readdir(&root, cookie, CHUNK).unwrap();
+                cookie += entries.len() as u64;
+                names.extend(entries.into_iter().map(|entry| entry.name));
+                if eof {
+                    break;
+                }
+            }
+            let elapsed = started.elapsed();
+            names.sort();
+            assert_eq!(names.len(), ENTRIES);
+            assert_eq!(names.first().map(String::as_str), Some("f00000"));
+            assert_eq!(names.last().map(String::as_str), Some("f09999"));
+            (fs.dir_read_count(), fs.stat_count(), elapsed)
+        };
+
+        let uncached = LocalFilesystem::new(temp_dir.path()).unwrap().with_dir_cache(Duration::ZERO);
+        let cached = LocalFilesystem::new(temp_dir.path()).unwrap().with_dir_cache(Duration::from_secs(60));
+        let (uncached_reads, uncached_stats, uncached_time) = list_in_chunks(&uncached);
+        let (cached_reads, cached_stats, cached_time) = list_in_chunks(&cached);
+        debug!(
+            "10k entries in chunks of {}: {} directory reads in {:?} without the cache, {} in {:?} with",
+            CHUNK, uncached_reads, uncached_time, cached_reads, cached_time
+        );
+
+        // Every continuation reads the directory again without the cache;
+        // with it the directory is read once and the listing is linear
+        let chunks = (ENTRIES as u64).div_ceil(CHUNK as u64) + 1;
+        assert!(uncached_reads >= chunks - 1, "uncached: {}", uncached_reads);
+        assert_eq!(cached_reads, 1);
+        // Either way each entry is stat'ed once, plus the directory per READDIR
+        for stats in [uncached_stats, cached_stats] {
+            assert!(stats <= ENTRIES as u64 + chunks, "{} stats", stats);
+        }
+    }
+
+    #[test]
+    fn test_readdir_continuation_sees_changes() {
+        let (fs, temp_dir) = create_test_fs();
+        for name in ["a", "b", "c"] {
+            fs::write(temp_dir.path().join(name), b"x").unwrap();
+        }
+        let root = fs.root_handle();
+        let names = |cookie: u64| {
+            let (entries, _) = fs.readdir(&root, cookie, 100).unwrap();
+            let mut names: Vec<String> = entries.into_iter().map(|entry| entry.name).collect();
+            names.sort();
+            names
+        };
+
+        let (first, _) = fs.readdir(&root, 0, 1).unwrap();
+        assert_eq!(names(1).len(), 2);
+        assert_eq!(fs.dir_read_count(), 1);
+
+        // A change through the server drops the kept names
+        fs.remove(&root, &first[0].name).unwrap();
+        assert_eq!(names(0).len(), 2);
+        assert_eq!(fs.dir_read_count(), 2);
+
+        // So does a change made behind the server's back
+        std::thread::sleep(Duration::from_millis(10));
+        fs::write(temp_dir.path().join("d"), b"x").unwrap();
+        assert_eq!(names(0).len(), 3);
+        assert_eq!(fs.dir_read_count(), 3);
+    }
+
+    #[test]
+    fn test_setattr_times_can_move_mtime_backwards() {
+        let (fs, _temp_dir) = create_test_fs();
+        let root = fs.root_handle();
+        let file = fs.create(&root, "old.txt", 0o644).unwrap();
+        fs.write(&file, 0, b"data", StableHow::Unstable).unwrap();
+        let before = fs.getattr(&file).unwrap();
+
+        // An explicit time is reported as set, even though it is older
+        let stamp = FileTime { seconds: 1_000_000_000, nseconds: 1 };
+        fs.setattr_times(&file, Some(SetTime::ClientTime(stamp)), Some(SetTime::ClientTime(stamp)))
+            .unwrap();
+        let after = fs.getattr(&file).unwrap();
+        assert_eq!(after.mtime, stamp);
+        assert_eq!(after.atime, stamp);
+        assert!(after.ctime > before.ctime);
+
+        // Only the requested time changes
+        fs.setattr_times(&file, Some(SetTime::ServerTime), None).unwrap();
+        let touched = fs.getattr(&file).unwrap();
+        assert_eq!(touched.mtime, stamp);
+        assert!(touched.atime > stamp);
+    }
 }
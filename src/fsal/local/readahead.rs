@@ -0,0 +1,193 @@
+// Readahead
+//
+// Clients reading a file front to back send a run of READs, each starting
+// where the previous one ended. Once a READ continues the previous one, the
+// backend reads a whole window from that offset in one call and serves the
+// following READs that fall inside it from memory.
+//
+// Streams are keyed by inode, so writes through any hard link reach them.
+// Every write, truncation or removal made through the server drops the
+// stream of its inode. A file changed behind the server's back keeps being
+// served from a window read before the change until the window is consumed.
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+
+/// Default readahead window in bytes (0 disables readahead)
+pub const DEFAULT_WINDOW: usize = 0;
+
+/// Number of tracked streams at which the table is reset
+const MAX_STREAMS: usize = 64;
+
+/// Read position and buffered window of one inode
+#[derive(Default)]
+struct Stream {
+    /// Offset a READ continuing the last one starts at
+    next_offset: u64,
+    /// Offset of the first buffered byte
+    buffer_offset: u64,
+    /// Data read ahead from `buffer_offset`
+    buffer: Bytes,
+    /// The window read hit end of file, so nothing lies past the buffer
+    buffer_at_eof: bool,
+}
+
+impl Stream {
+    /// Serve `count` bytes at `offset` from the buffer if it holds them
+    fn serve(&self, offset: u64, count: usize) -> Option<Bytes> {
+        let start = offset.checked_sub(self.buffer_offset)? as usize;
+        if start > self.buffer.len() || (start == self.buffer.len() && !self.buffer_at_eof) {
+            return None;
+        }
+        let end = start.saturating_add(count);
+        if end > self.buffer.len() && !self.buffer_at_eof {
+            return None;
+        }
+        Some(self.buffer.slice(start..end.min(self.buffer.len())))
+    }
+}
+
+#[derive(Default)]
+struct Streams {
+    by_inode: HashMap<u64, Stream>,
+    /// Bumped by every invalidation, so a window read concurrently with a
+    /// change is not kept
+    epoch: u64,
+}
+
+/// Inode → sequential read position and window
+pub struct Readahead {
+    /// Bytes read ahead once a READ continues the previous one
+    window: usize,
+    streams: Mutex<Streams>,
+}
+
+impl Default for Readahead {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl Readahead {
+    /// Create a readahead table reading `window` bytes ahead; zero disables it
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            streams: Mutex::new(Streams::default()),
+        }
+    }
+
+    /// Whether READs are read ahead (the window is not zero)
+    pub fn is_enabled(&self) -> bool {
+        self.window > 0
+    }
+
+    /// Read through the readahead window
+    ///
+    /// # Arguments
+    /// * `inode` - Inode being read
+    /// * `offset` - Offset of the READ
+    /// * `count` - Bytes requested
+    /// * `read_at` - Reads up to the given length at the given offset from disk
+    ///
+    /// # Returns
+    /// Up to `count` bytes at `offset`, short only at end of file
+    pub fn read<F>(&self, inode: u64, offset: u64, count: usize, read_at: F) -> io::Result<Bytes>
+    where
+        F: FnOnce(u64, usize) -> io::Result<Bytes>,
+    {
+        if self.window == 0 {
+            return read_at(offset, count);
+        }
+
+        let (sequential, epoch) = {
+            let mut streams = self.streams.lock().unwrap();
+            let epoch = streams.epoch;
+            match streams.by_inode.get_mut(&inode) {
+                Some(stream) => {
+                    if let Some(data) = stream.serve(offset, count) {
+                        stream.next_offset = offset + data.len() as u64;
+                        return Ok(data);
+                    }
+                    (stream.next_offset == offset, epoch)
+                }
+                None => (false, epoch),
+            }
+        };
+
+        // Read outside the lock so other files are not held up by the disk
+        let length = if sequential { count.max(self.window) } else { count };
+        let buffer = read_at(offset, length)?;
+        let data = buffer.slice(..count.min(buffer.len()));
+
+        let mut streams = self.streams.lock().unwrap();
+        if streams.epoch != epoch {
+            // Changed while reading: the data is as fresh as a plain read,
+            // but the window may predate the change
+            streams.by_inode.remove(&inode);
+            return Ok(data);
+        }
+        if streams.by_inode.len() >= MAX_STREAMS && !streams.by_inode.contains_key(&inode) {
+            streams.by_inode.clear();
+        }
+        let stream = streams.by_inode.entry(inode).or_default();
+        stream.next_offset = offset + data.len() as u64;
+        if sequential {
+            stream.buffer_at_eof = buffer.len() < length;
+            stream.buffer_offset = offset;
+            stream.buffer = buffer;
+        }
+        Ok(data)
+    }
+
+    /// Drop the stream of an inode whose data changed or was freed
+    pub fn invalidate(&self, inode: u64) {
+        let mut streams = self.streams.lock().unwrap();
+        streams.epoch += 1;
+        streams.by_inode.remove(&inode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_sequential_reads_are_served_from_window() {
+        let readahead = Readahead::new(16);
+        let data: Bytes = (0..30u8).collect::<Vec<_>>().into();
+        let reads = Cell::new(0);
+        let read = |offset: u64, count: usize| {
+            readahead
+                .read(1, offset, count, |at, length| {
+                    reads.set(reads.get() + 1);
+                    let start = (at as usize).min(data.len());
+                    Ok(data.slice(start..(start + length).min(data.len())))
+                })
+                .unwrap()
+        };
+
+        // The first READ only positions the stream; the second reads ahead
+        assert_eq!(read(0, 4), data.slice(0..4));
+        assert_eq!(read(4, 4), data.slice(4..8));
+        assert_eq!(reads.get(), 2);
+        assert_eq!(read(8, 4), data.slice(8..12));
+        assert_eq!(read(12, 8), data.slice(12..20));
+        assert_eq!(reads.get(), 2);
+
+        // Past the window the next one is read; it ends at EOF, so short
+        // READs at the end are served from it too
+        assert_eq!(read(20, 8), data.slice(20..28));
+        assert_eq!(read(28, 8), data.slice(28..30));
+        assert_eq!(read(30, 8), Bytes::new());
+        assert_eq!(reads.get(), 3);
+
+        // Invalidation forces the next READ to disk
+        readahead.invalidate(1);
+        assert_eq!(read(28, 2), data.slice(28..30));
+        assert_eq!(reads.get(), 4);
+    }
+}
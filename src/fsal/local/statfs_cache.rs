@@ -0,0 +1,118 @@
+// Filesystem Statistics Cache
+//
+// Clients send FSSTAT often (df, and some before every large write), while
+// free space and file counts change slowly. The statvfs result of each
+// handle asked about is kept for a short TTL. Writes through the server
+// are counted, and once they add up to REFRESH_AFTER_BYTES every result is
+// dropped, so a large file written in one go is reflected at once rather
+// than at the end of the TTL.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::super::{FileHandle, FsStats};
+
+/// Default time a statvfs result is kept (zero disables the cache)
+pub const DEFAULT_TTL: Duration = Duration::from_secs(5);
+
+/// Bytes written through the server after which results are refreshed
+const REFRESH_AFTER_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Number of handles at which the cache is reset
+const MAX_ENTRIES: usize = 16;
+
+#[derive(Default)]
+struct Entries {
+    stats: HashMap<FileHandle, (Instant, FsStats)>,
+    /// Bytes written since the results were last dropped
+    written: u64,
+}
+
+/// Handle → statvfs result
+pub struct StatfsCache {
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl Default for StatfsCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+impl StatfsCache {
+    /// Create a cache keeping results for `ttl`; zero disables it
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Statistics of the filesystem holding `handle`, if fetched within the TTL
+    pub fn get(&self, handle: &FileHandle) -> Option<FsStats> {
+        let entries = self.entries.lock().unwrap();
+        let (fetched, stats) = entries.stats.get(handle)?;
+        (fetched.elapsed() < self.ttl).then_some(*stats)
+    }
+
+    /// Keep the statistics just fetched for `handle`
+    pub fn insert(&self, handle: &FileHandle, stats: FsStats) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.stats.len() >= MAX_ENTRIES && !entries.stats.contains_key(handle) {
+            entries.stats.clear();
+        }
+        entries.stats.insert(handle.clone(), (Instant::now(), stats));
+    }
+
+    /// Count bytes written, dropping every result once enough add up
+    pub fn note_write(&self, bytes: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.written += bytes;
+        if entries.written >= REFRESH_AFTER_BYTES {
+            entries.stats.clear();
+            entries.written = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(free_bytes: u64) -> FsStats {
+        FsStats {
+            total_bytes: 1 << 40,
+            free_bytes,
+            avail_bytes: free_bytes,
+            total_files: 1000,
+            free_files: 500,
+            avail_files: 500,
+        }
+    }
+
+    #[test]
+    fn test_results_expire_and_large_writes_refresh() {
+        let handle = vec![1u8; 8];
+        let cache = StatfsCache::new(Duration::from_millis(20));
+        cache.insert(&handle, stats(100));
+        assert_eq!(cache.get(&handle), Some(stats(100)));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&handle), None);
+
+        let cache = StatfsCache::new(Duration::from_secs(60));
+        cache.insert(&handle, stats(100));
+        cache.note_write(REFRESH_AFTER_BYTES / 2);
+        assert!(cache.get(&handle).is_some(), "Small writes keep the result");
+        cache.note_write(REFRESH_AFTER_BYTES / 2);
+        assert_eq!(cache.get(&handle), None);
+
+        let disabled = StatfsCache::new(Duration::ZERO);
+        disabled.insert(&handle, stats(100));
+        assert_eq!(disabled.get(&handle), None);
+    }
+}
@@ -0,0 +1,783 @@
+// In-Memory Filesystem Backend
+//
+// Implements the Filesystem trait entirely in memory. Useful for tests and
+// as a scratch export; all data is lost when the server stops.
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use super::acl::{Acl, AclTag, ACL_ACCESS_XATTR};
+use super::handle::FileHandle;
+use super::{
+    DirEntry, FileAttributes, FileTime, FileType, Filesystem, FsStats, FsalError, SetAttributes, SetTime, StableHow,
+};
+
+/// Inode number of the root directory
+const ROOT_INO: u64 = 1;
+
+/// Largest file the backend holds: a WRITE or SETATTR past it is EFBIG
+/// rather than an allocation that could abort the server
+const MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// Source of distinct filesystem IDs for memory backends
+static NEXT_FSID: AtomicU64 = AtomicU64::new(1);
+
+/// Node contents
+enum NodeData {
+    File(Vec<u8>),
+    Directory(BTreeMap<String, u64>),
+    Symlink(String),
+    Special,
+}
+
+/// A single inode
+struct Node {
+    ftype: FileType,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    rdev: (u32, u32),
+    atime: FileTime,
+    mtime: FileTime,
+    ctime: FileTime,
+    data: NodeData,
+    xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl Node {
+    fn new(ftype: FileType, mode: u32, data: NodeData) -> Self {
+        let now = now();
+        Self {
+            ftype,
+            mode,
+            uid: unsafe { libc::geteuid() },
+            gid: unsafe { libc::getegid() },
+            nlink: if ftype == FileType::Directory { 2 } else { 1 },
+            rdev: (0, 0),
+            atime: now,
+            mtime: now,
+            ctime: now,
+            data,
+            xattrs: BTreeMap::new(),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match &self.data {
+            NodeData::File(data) => data.len() as u64,
+            NodeData::Directory(entries) => entries.len() as u64,
+            NodeData::Symlink(target) => target.len() as u64,
+            NodeData::Special => 0,
+        }
+    }
+
+    fn touch(&mut self) {
+        let now = now();
+        self.mtime = now;
+        self.ctime = now;
+    }
+}
+
+struct State {
+    nodes: HashMap<u64, Node>,
+    next_ino: u64,
+}
+
+/// In-memory filesystem implementation
+pub struct MemoryFilesystem {
+    state: RwLock<State>,
+    fsid: u64,
+}
+
+fn now() -> FileTime {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    FileTime {
+        seconds: elapsed.as_secs(),
+        nseconds: elapsed.subsec_nanos(),
+    }
+}
+
+fn errno(code: i32) -> anyhow::Error {
+    io::Error::from_raw_os_error(code).into()
+}
+
+/// Resize file contents to `len` bytes, zero-filling any growth
+///
+/// # Returns
+/// EFBIG past MAX_FILE_SIZE; ENOSPC when the memory cannot be allocated
+fn set_len(contents: &mut Vec<u8>, len: u64) -> Result<()> {
+    if len > MAX_FILE_SIZE {
+        return Err(errno(libc::EFBIG));
+    }
+    let len = len as usize;
+    if let Some(growth) = len.checked_sub(contents.len()) {
+        contents.try_reserve_exact(growth).map_err(|_| errno(libc::ENOSPC))?;
+    }
+    contents.resize(len, 0);
+    Ok(())
+}
+
+fn encode_handle(ino: u64) -> FileHandle {
+    ino.to_be_bytes().to_vec()
+}
+
+fn decode_handle(handle: &FileHandle) -> Result<u64> {
+    let bytes: [u8; 8] = handle
+        .as_slice()
+        .try_into()
+        .map_err(|_| FsalError::bad_handle(format!("{} bytes, expected 8", handle.len())))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(anyhow!("Invalid filename: {}", name));
+    }
+    Ok(())
+}
+
+impl State {
+    fn node(&self, handle: &FileHandle) -> Result<&Node> {
+        let ino = decode_handle(handle)?;
+        self.nodes
+            .get(&ino)
+            .ok_or_else(|| FsalError::stale(format!("inode {} no longer exists", ino)).into())
+    }
+
+    fn node_mut(&mut self, handle: &FileHandle) -> Result<&mut Node> {
+        let ino = decode_handle(handle)?;
+        self.nodes
+            .get_mut(&ino)
+            .ok_or_else(|| FsalError::stale(format!("inode {} no longer exists", ino)).into())
+    }
+
+    fn dir_entries(&self, handle: &FileHandle) -> Result<&BTreeMap<String, u64>> {
+        match &self.node(handle)?.data {
+            NodeData::Directory(entries) => Ok(entries),
+            _ => Err(errno(libc::ENOTDIR)),
+        }
+    }
+
+    fn dir_entries_mut(&mut self, handle: &FileHandle) -> Result<&mut BTreeMap<String, u64>> {
+        match &mut self.node_mut(handle)?.data {
+            NodeData::Directory(entries) => Ok(entries),
+            _ => Err(errno(libc::ENOTDIR)),
+        }
+    }
+
+    /// Insert a new node into a directory, failing if the name is taken
+    fn insert(&mut self, dir_handle: &FileHandle, name: &str, node: Node) -> Result<FileHandle> {
+        validate_name(name)?;
+        if self.dir_entries(dir_handle)?.contains_key(name) {
+            return Err(errno(libc::EEXIST));
+        }
+
+        let ino = self.next_ino;
+        self.next_ino += 1;
+
+        let is_dir = node.ftype == FileType::Directory;
+        self.nodes.insert(ino, node);
+        self.dir_entries_mut(dir_handle)?.insert(name.to_string(), ino);
+
+        let dir = self.node_mut(dir_handle)?;
+        if is_dir {
+            dir.nlink += 1;
+        }
+        dir.touch();
+
+        Ok(encode_handle(ino))
+    }
+
+    /// Drop one link to an inode, freeing it when no links remain
+    fn unlink_ino(&mut self, ino: u64) {
+        if let Some(node) = self.nodes.get_mut(&ino) {
+            node.nlink = node.nlink.saturating_sub(1);
+            node.ctime = now();
+            if node.nlink == 0 || node.ftype == FileType::Directory {
+                self.nodes.remove(&ino);
+            }
+        }
+    }
+}
+
+impl MemoryFilesystem {
+    /// Create an empty in-memory filesystem
+    pub fn new() -> Self {
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            ROOT_INO,
+            Node::new(FileType::Directory, 0o755, NodeData::Directory(BTreeMap::new())),
+        );
+
+        Self {
+            state: RwLock::new(State {
+                nodes,
+                next_ino: ROOT_INO + 1,
+            }),
+            fsid: NEXT_FSID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for MemoryFilesystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Filesystem for MemoryFilesystem {
+    fn root_handle(&self) -> FileHandle {
+        encode_handle(ROOT_INO)
+    }
+
+    fn lookup(&self, dir_handle: &FileHandle, name: &str) -> Result<FileHandle> {
+        let state = self.state.read().unwrap();
+        let entries = state.dir_entries(dir_handle)?;
+
+        match name {
+            "." => Ok(dir_handle.clone()),
+            _ => entries
+                .get(name)
+                .map(|ino| encode_handle(*ino))
+                .ok_or_else(|| anyhow!("File not found: {}", name)),
+        }
+    }
+
+    fn parent_handle(&self, dir_handle: &FileHandle) -> Result<FileHandle> {
+        let state = self.state.read().unwrap();
+        state.dir_entries(dir_handle)?;
+
+        // Directories have a single link from their parent; the root has none
+        let ino = decode_handle(dir_handle)?;
+        let parent = state.nodes.iter().find_map(|(parent_ino, node)| match &node.data {
+            NodeData::Directory(entries) if entries.values().any(|child| *child == ino) => Some(*parent_ino),
+            _ => None,
+        });
+        Ok(encode_handle(parent.unwrap_or(ROOT_INO)))
+    }
+
+    fn getattr(&self, handle: &FileHandle) -> Result<FileAttributes> {
+        let state = self.state.read().unwrap();
+        let node = state.node(handle)?;
+        let size = node.size();
+
+        Ok(FileAttributes {
+            ftype: node.ftype,
+            mode: node.mode,
+            nlink: node.nlink,
+            uid: node.uid,
+            gid: node.gid,
+            size,
+            used: size,
+            rdev: node.rdev,
+            fsid: self.fsid,
+            fileid: decode_handle(handle)?,
+            atime: node.atime,
+            mtime: node.mtime,
+            ctime: node.ctime,
+        })
+    }
+
+    fn read(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<Bytes> {
+        let state = self.state.read().unwrap();
+        match &state.node(handle)?.data {
+            NodeData::File(data) => {
+                let start = (offset as usize).min(data.len());
+                let end = start.saturating_add(count as usize).min(data.len());
+                Ok(Bytes::copy_from_slice(&data[start..end]))
+            }
+            NodeData::Directory(_) => Err(errno(libc::EISDIR)),
+            _ => Err(errno(libc::EINVAL)),
+        }
+    }
+
+    fn readdir(&self, dir_handle: &FileHandle, cookie: u64, count: u32) -> Result<(Vec<DirEntry>, bool)> {
+        let state = self.state.read().unwrap();
+        let entries = state.dir_entries(dir_handle)?;
+
+        let mut result = Vec::new();
+        for (name, ino) in entries.iter().skip(cookie as usize) {
+            if result.len() >= count as usize {
+                return Ok((result, false));
+            }
+            let node = state.nodes.get(ino).ok_or_else(|| anyhow!("Dangling directory entry"))?;
+            result.push(DirEntry {
+                fileid: *ino,
+                name: name.clone(),
+                file_type: node.ftype,
+            });
+        }
+
+        Ok((result, true))
+    }
+
+    fn write(&self, handle: &FileHandle, offset: u64, data: &[u8], _stable: StableHow) -> Result<u32> {
+        // Nothing to flush: every stability level is satisfied once the data is in memory
+        let mut state = self.state.write().unwrap();
+        let node = state.node_mut(handle)?;
+
+        match &mut node.data {
+            NodeData::File(contents) => {
+                let end = offset.checked_add(data.len() as u64).ok_or_else(|| errno(libc::EFBIG))?;
+                if (contents.len() as u64) < end {
+                    set_len(contents, end)?;
+                }
+                contents[offset as usize..end as usize].copy_from_slice(data);
+            }
+            NodeData::Directory(_) => return Err(errno(libc::EISDIR)),
+            _ => return Err(errno(libc::EINVAL)),
+        }
+        node.touch();
+
+        debug!("MEMORY WRITE: offset={} count={}", offset, data.len());
+
+        Ok(data.len() as u32)
+    }
+
+    fn setattr_size(&self, handle: &FileHandle, size: u64) -> Result<()> {
+        self.setattr(handle, &SetAttributes { size: Some(size), ..Default::default() }, None)
+    }
+
+    fn setattr_mode(&self, handle: &FileHandle, mode: u32) -> Result<()> {
+        self.setattr(handle, &SetAttributes { mode: Some(mode), ..Default::default() }, None)
+    }
+
+    fn setattr_owner(&self, handle: &FileHandle, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.setattr(handle, &SetAttributes { uid, gid, ..Default::default() }, None)
+    }
+
+    fn setattr_times(&self, handle: &FileHandle, atime: Option<SetTime>, mtime: Option<SetTime>) -> Result<()> {
+        self.setattr(handle, &SetAttributes { atime, mtime, ..Default::default() }, None)
+    }
+
+    fn setattr(&self, handle: &FileHandle, attrs: &SetAttributes, guard: Option<FileTime>) -> Result<()> {
+        // Guard check and update happen under the same write lock
+        let mut state = self.state.write().unwrap();
+        let node = state.node_mut(handle)?;
+
+        if let Some(guard) = guard
+            && node.ctime != guard
+        {
+            return Err(FsalError::NotSync.into());
+        }
+
+        if let Some(size) = attrs.size {
+            match &mut node.data {
+                NodeData::File(contents) => set_len(contents, size)?,
+                NodeData::Directory(_) => return Err(errno(libc::EISDIR)),
+                _ => return Err(errno(libc::EINVAL)),
+            }
+            node.mtime = now();
+        }
+        if let Some(mode) = attrs.mode {
+            node.mode = mode & 0o7777;
+            // The access ACL follows the mode, as the kernel keeps it
+            if let Some(bytes) = node.xattrs.get_mut(ACL_ACCESS_XATTR) {
+                let mut acl = Acl::from_xattr(bytes)?;
+                acl.chmod(mode);
+                *bytes = acl.to_xattr();
+            }
+        }
+        if let Some(uid) = attrs.uid {
+            node.uid = uid;
+        }
+        if let Some(gid) = attrs.gid {
+            node.gid = gid;
+        }
+        let resolve = |time: SetTime| match time {
+            SetTime::ServerTime => now(),
+            SetTime::ClientTime(time) => time,
+        };
+        if let Some(atime) = attrs.atime {
+            node.atime = resolve(atime);
+        }
+        if let Some(mtime) = attrs.mtime {
+            node.mtime = resolve(mtime);
+        }
+        node.ctime = now();
+
+        Ok(())
+    }
+
+    fn create(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        let mut state = self.state.write().unwrap();
+
+        // Creating over an existing regular file opens it unchanged
+        if let Some(ino) = state.dir_entries(dir_handle)?.get(name).copied() {
+            let node = state.nodes.get(&ino).ok_or_else(|| anyhow!("Dangling directory entry"))?;
+            return match &node.data {
+                NodeData::File(_) => Ok(encode_handle(ino)),
+                NodeData::Directory(_) => Err(errno(libc::EISDIR)),
+                _ => Err(errno(libc::EEXIST)),
+            };
+        }
+
+        let node = Node::new(FileType::RegularFile, mode & 0o7777, NodeData::File(Vec::new()));
+        state.insert(dir_handle, name, node)
+    }
+
+    fn create_guarded(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        let mut state = self.state.write().unwrap();
+        if state.dir_entries(dir_handle)?.contains_key(name) {
+            return Err(errno(libc::EEXIST));
+        }
+
+        let node = Node::new(FileType::RegularFile, mode & 0o7777, NodeData::File(Vec::new()));
+        state.insert(dir_handle, name, node)
+    }
+
+    fn remove(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        let ino = *state.dir_entries(dir_handle)?.get(name).ok_or_else(|| errno(libc::ENOENT))?;
+        if state.nodes.get(&ino).is_some_and(|n| n.ftype == FileType::Directory) {
+            return Err(errno(libc::EISDIR));
+        }
+
+        state.dir_entries_mut(dir_handle)?.remove(name);
+        state.node_mut(dir_handle)?.touch();
+        state.unlink_ino(ino);
+
+        Ok(())
+    }
+
+    fn mkdir(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        let mut state = self.state.write().unwrap();
+        let node = Node::new(FileType::Directory, mode & 0o7777, NodeData::Directory(BTreeMap::new()));
+        state.insert(dir_handle, name, node)
+    }
+
+    fn rmdir(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        let ino = *state.dir_entries(dir_handle)?.get(name).ok_or_else(|| errno(libc::ENOENT))?;
+        match state.nodes.get(&ino).map(|n| &n.data) {
+            Some(NodeData::Directory(entries)) if !entries.is_empty() => {
+                return Err(errno(libc::ENOTEMPTY));
+            }
+            Some(NodeData::Directory(_)) => {}
+            _ => return Err(errno(libc::ENOTDIR)),
+        }
+
+        state.dir_entries_mut(dir_handle)?.remove(name);
+        let dir = state.node_mut(dir_handle)?;
+        dir.nlink = dir.nlink.saturating_sub(1);
+        dir.touch();
+        state.unlink_ino(ino);
+
+        Ok(())
+    }
+
+    fn rename(
+        &self,
+        from_dir_handle: &FileHandle,
+        from_name: &str,
+        to_dir_handle: &FileHandle,
+        to_name: &str,
+    ) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        validate_name(to_name)?;
+
+        let ino = *state
+            .dir_entries(from_dir_handle)?
+            .get(from_name)
+            .ok_or_else(|| errno(libc::ENOENT))?;
+        let is_dir = state.nodes.get(&ino).is_some_and(|n| n.ftype == FileType::Directory);
+
+        // Replace an existing target following rename(2) rules
+        if let Some(target) = state.dir_entries(to_dir_handle)?.get(to_name).copied() {
+            if target == ino {
+                return Ok(());
+            }
+            match state.nodes.get(&target).map(|n| &n.data) {
+                Some(NodeData::Directory(_)) if !is_dir => return Err(errno(libc::EISDIR)),
+                Some(NodeData::Directory(entries)) if !entries.is_empty() => {
+                    return Err(errno(libc::ENOTEMPTY));
+                }
+                Some(NodeData::Directory(_)) => {
+                    state.node_mut(to_dir_handle)?.nlink -= 1;
+                }
+                Some(_) if is_dir => return Err(errno(libc::ENOTDIR)),
+                _ => {}
+            }
+            state.unlink_ino(target);
+        }
+
+        state.dir_entries_mut(from_dir_handle)?.remove(from_name);
+        state.dir_entries_mut(to_dir_handle)?.insert(to_name.to_string(), ino);
+
+        if is_dir && from_dir_handle != to_dir_handle {
+            state.node_mut(from_dir_handle)?.nlink -= 1;
+            state.node_mut(to_dir_handle)?.nlink += 1;
+        }
+        state.node_mut(from_dir_handle)?.touch();
+        state.node_mut(to_dir_handle)?.touch();
+        if let Some(node) = state.nodes.get_mut(&ino) {
+            node.ctime = now();
+        }
+
+        Ok(())
+    }
+
+    fn symlink(&self, dir_handle: &FileHandle, name: &str, target: &str) -> Result<FileHandle> {
+        let mut state = self.state.write().unwrap();
+        let node = Node::new(FileType::SymbolicLink, 0o777, NodeData::Symlink(target.to_string()));
+        state.insert(dir_handle, name, node)
+    }
+
+    fn readlink(&self, handle: &FileHandle) -> Result<String> {
+        let state = self.state.read().unwrap();
+        match &state.node(handle)?.data {
+            NodeData::Symlink(target) => Ok(target.clone()),
+            _ => Err(anyhow!("Not a symbolic link")),
+        }
+    }
+
+    fn link(&self, file_handle: &FileHandle, dir_handle: &FileHandle, name: &str) -> Result<FileHandle> {
+        let mut state = self.state.write().unwrap();
+        validate_name(name)?;
+
+        let ino = decode_handle(file_handle)?;
+        if state.node(file_handle)?.ftype == FileType::Directory {
+            return Err(anyhow!("Cannot create hard link to directory"));
+        }
+        if state.dir_entries(dir_handle)?.contains_key(name) {
+            return Err(errno(libc::EEXIST));
+        }
+
+        state.dir_entries_mut(dir_handle)?.insert(name.to_string(), ino);
+        state.node_mut(dir_handle)?.touch();
+        let node = state.node_mut(file_handle)?;
+        node.nlink += 1;
+        node.ctime = now();
+
+        Ok(file_handle.clone())
+    }
+
+    fn commit(&self, handle: &FileHandle, _offset: u64, _count: u32) -> Result<()> {
+        // Nothing to flush; just validate the handle
+        self.state.read().unwrap().node(handle)?;
+        Ok(())
+    }
+
+    fn mknod(
+        &self,
+        dir_handle: &FileHandle,
+        name: &str,
+        file_type: FileType,
+        mode: u32,
+        rdev: (u32, u32),
+    ) -> Result<FileHandle> {
+        match file_type {
+            FileType::CharDevice
+            | FileType::BlockDevice
+            | FileType::Socket
+            | FileType::NamedPipe => {}
+            _ => return Err(anyhow!("Invalid file type for MKNOD: {:?}", file_type)),
+        }
+
+        let mut state = self.state.write().unwrap();
+        let mut node = Node::new(file_type, mode & 0o7777, NodeData::Special);
+        node.rdev = rdev;
+        state.insert(dir_handle, name, node)
+    }
+
+    fn get_xattr(&self, handle: &FileHandle, name: &str) -> Result<Option<Vec<u8>>> {
+        let state = self.state.read().unwrap();
+        Ok(state.node(handle)?.xattrs.get(name).cloned())
+    }
+
+    fn set_xattr(&self, handle: &FileHandle, name: &str, value: &[u8]) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let node = state.node_mut(handle)?;
+        node.xattrs.insert(name.to_string(), value.to_vec());
+        node.ctime = now();
+        Ok(())
+    }
+
+    fn list_xattr(&self, handle: &FileHandle) -> Result<Vec<String>> {
+        let state = self.state.read().unwrap();
+        Ok(state.node(handle)?.xattrs.keys().cloned().collect())
+    }
+
+    fn remove_xattr(&self, handle: &FileHandle, name: &str) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let node = state.node_mut(handle)?;
+        node.xattrs.remove(name).ok_or_else(|| errno(libc::ENODATA))?;
+        node.ctime = now();
+        Ok(())
+    }
+
+    fn get_acl(&self, handle: &FileHandle) -> Result<Option<Acl>> {
+        self.get_xattr(handle, ACL_ACCESS_XATTR)?
+            .map(|bytes| Acl::from_xattr(&bytes))
+            .transpose()
+    }
+
+    fn set_acl(&self, handle: &FileHandle, acl: &Acl) -> Result<()> {
+        if let Err(e) = acl.validate() {
+            debug!("SETACL rejected: {}", e);
+            return Err(errno(libc::EINVAL));
+        }
+
+        let mut state = self.state.write().unwrap();
+        let node = state.node_mut(handle)?;
+
+        // Mirror the ACL into the mode bits, the mask standing in for the group
+        let perm_of = |tag: AclTag| acl.entries.iter().find(|e| e.tag == tag).map(|e| e.perm);
+        let group = perm_of(AclTag::Mask).or(perm_of(AclTag::GroupObj)).unwrap_or(0);
+        let owner = perm_of(AclTag::UserObj).unwrap_or(0);
+        let other = perm_of(AclTag::Other).unwrap_or(0);
+        node.mode = (node.mode & !0o777) | (owner << 6) | (group << 3) | other;
+
+        if acl.is_extended() {
+            node.xattrs.insert(ACL_ACCESS_XATTR.to_string(), acl.to_xattr());
+        } else {
+            node.xattrs.remove(ACL_ACCESS_XATTR);
+        }
+        node.ctime = now();
+        Ok(())
+    }
+
+    fn statfs(&self, handle: &FileHandle) -> Result<FsStats> {
+        let state = self.state.read().unwrap();
+        state.node(handle)?;
+        let used: u64 = state.nodes.values().map(Node::size).sum();
+        let nodes = state.nodes.len() as u64;
+
+        // Contents live in memory, so free space is the host's free memory;
+        // inode numbers never run out
+        let mut info: libc::sysinfo = unsafe { std::mem::zeroed() };
+        let free = if unsafe { libc::sysinfo(&mut info) } == 0 {
+            info.freeram * info.mem_unit as u64
+        } else {
+            0
+        };
+        Ok(FsStats {
+            total_bytes: used + free,
+            free_bytes: free,
+            avail_bytes: free,
+            total_files: u32::MAX as u64,
+            free_files: (u32::MAX as u64).saturating_sub(nodes),
+            avail_files: (u32::MAX as u64).saturating_sub(nodes),
+        })
+    }
+
+    fn handle_count(&self) -> usize {
+        self.state.read().unwrap().nodes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_write_read() {
+        let fs = MemoryFilesystem::new();
+        let root = fs.root_handle();
+
+        let file = fs.create(&root, "data.txt", 0o644).unwrap();
+        assert_eq!(fs.lookup(&root, "data.txt").unwrap(), file);
+
+        fs.write(&file, 0, b"Hello, NFS World!", StableHow::FileSync).unwrap();
+        assert_eq!(fs.read(&file, 7, 3).unwrap(), &b"NFS"[..]);
+        assert_eq!(fs.getattr(&file).unwrap().size, 17);
+    }
+
+    #[test]
+    fn test_handle_for_path() {
+        let fs = MemoryFilesystem::new();
+        let root = fs.root_handle();
+        let dir = fs.mkdir(&root, "dir", 0o755).unwrap();
+        let file = fs.create(&dir, "file", 0o644).unwrap();
+
+        assert_eq!(fs.handle_for_path("/").unwrap().0, root);
+        assert_eq!(fs.handle_for_path("").unwrap().0, root);
+        assert_eq!(fs.handle_for_path("dir/file").unwrap().0, file);
+        assert!(fs.handle_for_path("dir/../dir").is_err());
+        assert!(fs.handle_for_path("dir/missing").is_err());
+    }
+
+    #[test]
+    fn test_directories() {
+        let fs = MemoryFilesystem::new();
+        let root = fs.root_handle();
+
+        let dir = fs.mkdir(&root, "dir", 0o755).unwrap();
+        fs.create(&dir, "file", 0o644).unwrap();
+        assert_eq!(fs.getattr(&root).unwrap().nlink, 3);
+
+        let rmdir_err = fs.rmdir(&root, "dir").unwrap_err();
+        assert_eq!(
+            rmdir_err.downcast_ref::<io::Error>().and_then(|e| e.raw_os_error()),
+            Some(libc::ENOTEMPTY)
+        );
+
+        fs.remove(&dir, "file").unwrap();
+        fs.rmdir(&root, "dir").unwrap();
+        assert!(fs.getattr(&dir).is_err(), "Removed directory handle should be invalid");
+        assert_eq!(fs.readdir(&root, 0, 100).unwrap().0.len(), 0);
+    }
+
+    #[test]
+    fn test_rename_and_link() {
+        let fs = MemoryFilesystem::new();
+        let root = fs.root_handle();
+        let dir = fs.mkdir(&root, "dir", 0o755).unwrap();
+
+        let file = fs.create(&root, "a", 0o644).unwrap();
+        fs.rename(&root, "a", &dir, "b").unwrap();
+        assert!(fs.lookup(&root, "a").is_err());
+        assert_eq!(fs.lookup(&dir, "b").unwrap(), file);
+
+        fs.link(&file, &root, "c").unwrap();
+        assert_eq!(fs.getattr(&file).unwrap().nlink, 2);
+        fs.remove(&dir, "b").unwrap();
+        assert_eq!(fs.getattr(&file).unwrap().nlink, 1);
+    }
+
+    #[test]
+    fn test_xattr_round_trip() {
+        let fs = MemoryFilesystem::new();
+        let file = fs.create(&fs.root_handle(), "f", 0o644).unwrap();
+
+        fs.set_xattr(&file, "user.test", b"value").unwrap();
+        assert_eq!(fs.get_xattr(&file, "user.test").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(fs.list_xattr(&file).unwrap(), vec!["user.test".to_string()]);
+
+        fs.remove_xattr(&file, "user.test").unwrap();
+        assert_eq!(fs.get_xattr(&file, "user.test").unwrap(), None);
+        assert!(fs.remove_xattr(&file, "user.test").is_err());
+    }
+
+    #[test]
+    fn test_growth_past_max_file_size_is_efbig() {
+        use crate::fsal::error::errno_of;
+
+        let fs = MemoryFilesystem::new();
+        let file = fs.create(&fs.root_handle(), "f", 0o644).unwrap();
+
+        // Neither a far WRITE nor a huge truncate allocates: both are EFBIG
+        let err = fs.write(&file, 1 << 40, b"x", StableHow::Unstable).unwrap_err();
+        assert_eq!(errno_of(&err), Some(libc::EFBIG));
+        let err = fs.write(&file, u64::MAX, b"x", StableHow::Unstable).unwrap_err();
+        assert_eq!(errno_of(&err), Some(libc::EFBIG));
+        let err = fs.setattr_size(&file, 1 << 40).unwrap_err();
+        assert_eq!(errno_of(&err), Some(libc::EFBIG));
+
+        // The file is untouched and the backend still serves calls
+        assert_eq!(fs.getattr(&file).unwrap().size, 0);
+        fs.write(&file, 4096, b"x", StableHow::Unstable).unwrap();
+        assert_eq!(fs.getattr(&file).unwrap().size, 4097);
+    }
+}
@@ -3,22 +3,32 @@
 // Provides a common interface for filesystem operations, abstracting the
 // underlying storage backend (local filesystem, network filesystem, etc.)
 
+pub mod acl;
+pub mod error;
+#[cfg(test)]
+pub mod faulty;
 pub mod handle;
 pub mod local;
+pub mod memory;
+pub mod overlay;
 
 // Future backends (uncomment when implemented)
 // #[cfg(feature = "s3")]
 // pub mod s3;
 // #[cfg(feature = "ceph")]
 // pub mod ceph;
-// #[cfg(test)]
-// pub mod memory;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
 use std::path::PathBuf;
+use std::time::Duration;
 
+pub use acl::{Acl, AclEntry, AclTag};
+pub use error::FsalError;
 pub use handle::{FileHandle, HandleManager};
 pub use local::LocalFilesystem;
+pub use memory::MemoryFilesystem;
+pub use overlay::OverlayFilesystem;
 
 /// File attributes
 ///
@@ -67,12 +77,57 @@ pub enum FileType {
 }
 
 /// File time (seconds, nanoseconds)
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FileTime {
     pub seconds: u64,
     pub nseconds: u32,
 }
 
+/// Write stability level
+///
+/// Maps to NFSv3 stable_how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StableHow {
+    /// Data may stay in server memory until COMMIT
+    Unstable,
+    /// Data (but not necessarily metadata) is on stable storage before replying
+    DataSync,
+    /// Data and metadata are on stable storage before replying
+    FileSync,
+}
+
+/// Attributes to change in a SETATTR
+///
+/// Maps to the settable fields of the NFSv3 sattr3 structure.
+/// `None` leaves the attribute unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetAttributes {
+    /// New file mode (permissions)
+    pub mode: Option<u32>,
+    /// New user ID
+    pub uid: Option<u32>,
+    /// New group ID
+    pub gid: Option<u32>,
+    /// New file size
+    pub size: Option<u64>,
+    /// New access time
+    pub atime: Option<SetTime>,
+    /// New modification time
+    pub mtime: Option<SetTime>,
+}
+
+/// New value of a timestamp
+///
+/// Maps to the set_atime/set_mtime unions of sattr3; leaving a time
+/// unchanged is `None` in `SetAttributes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetTime {
+    /// The server's current time
+    ServerTime,
+    /// A time supplied by the client
+    ClientTime(FileTime),
+}
+
 /// Directory entry
 ///
 /// Represents a single entry in a directory listing.
@@ -86,6 +141,36 @@ pub struct DirEntry {
     pub file_type: FileType,
 }
 
+/// Directory entry with the attributes and handle READDIRPLUS returns
+#[derive(Debug, Clone)]
+pub struct DirEntryPlus {
+    /// The entry as `readdir` lists it
+    pub entry: DirEntry,
+    /// Attributes of the entry; None if they could not be fetched (e.g. the
+    /// entry was removed after being listed)
+    pub attributes: Option<FileAttributes>,
+    /// Handle of the entry; None if it could not be looked up
+    pub handle: Option<FileHandle>,
+}
+
+/// Space and file counts of the filesystem holding an object, as FSSTAT
+/// reports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsStats {
+    /// Size of the filesystem in bytes
+    pub total_bytes: u64,
+    /// Free bytes
+    pub free_bytes: u64,
+    /// Free bytes available to unprivileged users
+    pub avail_bytes: u64,
+    /// Number of file slots (inodes)
+    pub total_files: u64,
+    /// Free file slots
+    pub free_files: u64,
+    /// Free file slots available to unprivileged users
+    pub avail_files: u64,
+}
+
 /// Filesystem trait
 ///
 /// This trait defines the interface that all filesystem backends must implement.
@@ -109,6 +194,42 @@ pub trait Filesystem: Send + Sync {
     /// File handle of the found entry
     fn lookup(&self, dir_handle: &FileHandle, name: &str) -> Result<FileHandle>;
 
+    /// Get the handle of a directory's parent (LOOKUP of "..")
+    ///
+    /// Never leaves the backend root: the root's parent is the root itself.
+    ///
+    /// # Arguments
+    /// * `dir_handle` - File handle of the directory
+    ///
+    /// # Returns
+    /// File handle of the parent directory
+    fn parent_handle(&self, dir_handle: &FileHandle) -> Result<FileHandle>;
+
+    /// Resolve a path below the backend root to a handle and its attributes
+    ///
+    /// Used when exports are set up and mounted, rather than per NFS call.
+    /// The path is relative to the backend root; empty components and "."
+    /// are skipped, and ".." is refused, so the result never lies outside
+    /// the root. The default resolves one component at a time through
+    /// `lookup`, so the backend's own traversal checks apply.
+    ///
+    /// # Arguments
+    /// * `path` - Path relative to the backend root ("" or "/" is the root)
+    ///
+    /// # Returns
+    /// Tuple of (handle, attributes) of the object the path names
+    fn handle_for_path(&self, path: &str) -> Result<(FileHandle, FileAttributes)> {
+        let mut handle = self.root_handle();
+        for name in path.split('/').filter(|name| !name.is_empty() && *name != ".") {
+            if name == ".." {
+                return Err(anyhow!("Path {:?} leaves the backend root", path));
+            }
+            handle = self.lookup(&handle, name).with_context(|| format!("Path {:?} not found", path))?;
+        }
+        let attrs = self.getattr(&handle)?;
+        Ok((handle, attrs))
+    }
+
     /// Get file attributes
     ///
     /// # Arguments
@@ -126,8 +247,29 @@ pub trait Filesystem: Send + Sync {
     /// * `count` - Number of bytes to read
     ///
     /// # Returns
-    /// Vector of bytes read (may be shorter than count if EOF reached)
-    fn read(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<Vec<u8>>;
+    /// Bytes read (may be shorter than count if EOF reached). Returned as
+    /// `Bytes` so callers can hand the buffer on without copying it.
+    fn read(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<Bytes>;
+
+    /// Read data from a file into a caller's buffer
+    ///
+    /// Lets callers reuse one buffer across reads instead of receiving a new
+    /// one each time. The default copies out of `read`; backends that can
+    /// read straight into `buf` override it.
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    /// * `offset` - Starting offset
+    /// * `buf` - Buffer to fill, as many bytes as are wanted
+    ///
+    /// # Returns
+    /// Number of bytes read into the start of `buf` (short only at end of file)
+    fn read_into(&self, handle: &FileHandle, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let count = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+        let data = self.read(handle, offset, count)?;
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
 
     /// Read directory entries
     ///
@@ -140,16 +282,53 @@ pub trait Filesystem: Send + Sync {
     /// Tuple of (entries, eof) where eof indicates if all entries were returned
     fn readdir(&self, dir_handle: &FileHandle, cookie: u64, count: u32) -> Result<(Vec<DirEntry>, bool)>;
 
+    /// Read directory entries with their attributes and handles
+    ///
+    /// The default looks up and stats every entry `readdir` returns.
+    /// Backends that can stat entries while listing them should override it.
+    ///
+    /// # Arguments
+    /// * `dir_handle` - Directory handle
+    /// * `cookie` - Starting position (0 = from beginning)
+    /// * `dircount` - Maximum number of entries to return, as `readdir`'s count
+    /// * `maxcount` - Size limit of the reply the entries go into (a hint)
+    ///
+    /// # Returns
+    /// Tuple of (entries, eof) where eof indicates if all entries were returned
+    fn readdir_plus(
+        &self,
+        dir_handle: &FileHandle,
+        cookie: u64,
+        dircount: u32,
+        maxcount: u32,
+    ) -> Result<(Vec<DirEntryPlus>, bool)> {
+        let _ = maxcount;
+        let (entries, eof) = self.readdir(dir_handle, cookie, dircount)?;
+        let entries = entries
+            .into_iter()
+            .map(|entry| {
+                let handle = self.lookup(dir_handle, &entry.name).ok();
+                let attributes = handle.as_ref().and_then(|handle| self.getattr(handle).ok());
+                DirEntryPlus { entry, attributes, handle }
+            })
+            .collect();
+        Ok((entries, eof))
+    }
+
     /// Write data to a file
     ///
+    /// With `StableHow::Unstable` the backend may return before the data is
+    /// durable; COMMIT is then responsible for flushing it.
+    ///
     /// # Arguments
     /// * `handle` - File handle
     /// * `offset` - Starting offset
     /// * `data` - Data to write
+    /// * `stable` - Requested stability level
     ///
     /// # Returns
     /// Number of bytes actually written
-    fn write(&self, handle: &FileHandle, offset: u64, data: &[u8]) -> Result<u32>;
+    fn write(&self, handle: &FileHandle, offset: u64, data: &[u8], stable: StableHow) -> Result<u32>;
 
     /// Set file size (truncate/extend)
     ///
@@ -173,17 +352,56 @@ pub trait Filesystem: Send + Sync {
     /// * `gid` - New group ID (None to keep current)
     fn setattr_owner(&self, handle: &FileHandle, uid: Option<u32>, gid: Option<u32>) -> Result<()>;
 
+    /// Set access and modification times
+    ///
+    /// Symbolic links get their own times set, not their target's.
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    /// * `atime` - New access time (None to keep current)
+    /// * `mtime` - New modification time (None to keep current)
+    fn setattr_times(&self, handle: &FileHandle, atime: Option<SetTime>, mtime: Option<SetTime>) -> Result<()>;
+
+    /// Set attributes, optionally guarded by ctime
+    ///
+    /// When `guard` is given, the backend checks that the file's current ctime
+    /// equals it and applies the changes atomically with that check; on a
+    /// mismatch nothing is changed and `FsalError::NotSync` is returned.
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    /// * `attrs` - Attributes to change
+    /// * `guard` - Expected ctime (None = unconditional)
+    fn setattr(&self, handle: &FileHandle, attrs: &SetAttributes, guard: Option<FileTime>) -> Result<()>;
+
     /// Create a file
     ///
+    /// An existing regular file of the same name is opened as it is: its
+    /// contents and mode are left unchanged.
+    ///
     /// # Arguments
     /// * `dir_handle` - Directory handle
     /// * `name` - Name of new file
-    /// * `mode` - File permissions
+    /// * `mode` - Permissions of a new file
     ///
     /// # Returns
     /// File handle of created file
     fn create(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle>;
 
+    /// Create a new regular file that must not exist yet
+    ///
+    /// Unlike `create`, an existing object of the same name is left alone
+    /// and the call fails with EEXIST.
+    ///
+    /// # Arguments
+    /// * `dir_handle` - Directory handle
+    /// * `name` - Name of new file
+    /// * `mode` - Permissions of the file
+    ///
+    /// # Returns
+    /// File handle of created file
+    fn create_guarded(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle>;
+
     /// Remove a file
     ///
     /// # Arguments
@@ -266,6 +484,39 @@ pub trait Filesystem: Send + Sync {
     /// Ok if data is committed to stable storage
     fn commit(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<()>;
 
+    /// Handles the backend has issued, with the path each names
+    ///
+    /// For operators debugging stale handles. Backends whose handles name
+    /// objects directly have no table to list.
+    fn issued_handles(&self) -> Vec<(FileHandle, String)> {
+        Vec::new()
+    }
+
+    /// Forget an issued handle, so it resolves as stale from now on
+    ///
+    /// The object is untouched; a new LOOKUP issues it a fresh handle. The
+    /// root handle is never forgotten.
+    ///
+    /// # Returns
+    /// Whether the handle was issued and is now forgotten
+    fn invalidate_handle(&self, handle: &FileHandle) -> bool {
+        let _ = handle;
+        false
+    }
+
+    /// Commit every file written UNSTABLE and not committed since
+    ///
+    /// Called once the server has stopped serving, so data clients wrote
+    /// but had not yet committed survives the shutdown. Files that can no
+    /// longer be synced are logged and skipped. Backends without volatile
+    /// writes have nothing to flush.
+    ///
+    /// # Returns
+    /// Number of files synced
+    fn flush(&self) -> Result<usize> {
+        Ok(0)
+    }
+
     /// Create a special file (device, FIFO, socket)
     ///
     /// # Arguments
@@ -285,6 +536,82 @@ pub trait Filesystem: Send + Sync {
         mode: u32,
         rdev: (u32, u32),
     ) -> Result<FileHandle>;
+
+    /// Read an extended attribute
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    /// * `name` - Attribute name including its namespace (e.g. "user.test")
+    ///
+    /// # Returns
+    /// The attribute value, or None if the file has no such attribute
+    fn get_xattr(&self, handle: &FileHandle, name: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Set an extended attribute, replacing any existing value
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    /// * `name` - Attribute name including its namespace
+    /// * `value` - New attribute value
+    fn set_xattr(&self, handle: &FileHandle, name: &str, value: &[u8]) -> Result<()>;
+
+    /// List the names of a file's extended attributes
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    ///
+    /// # Returns
+    /// Attribute names visible to the server process
+    fn list_xattr(&self, handle: &FileHandle) -> Result<Vec<String>>;
+
+    /// Remove an extended attribute
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    /// * `name` - Attribute name including its namespace
+    ///
+    /// # Returns
+    /// Ok if removed; an ENODATA error if the attribute does not exist
+    fn remove_xattr(&self, handle: &FileHandle, name: &str) -> Result<()>;
+
+    /// Read a file's POSIX access ACL
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    ///
+    /// # Returns
+    /// The ACL, or None if the file has none beyond its mode bits
+    fn get_acl(&self, handle: &FileHandle) -> Result<Option<Acl>>;
+
+    /// Replace a file's POSIX access ACL
+    ///
+    /// The owner, mask (or owning group) and other entries also become the
+    /// permission bits of the file mode, as with setfacl(1).
+    ///
+    /// # Arguments
+    /// * `handle` - File handle
+    /// * `acl` - New ACL; must pass `Acl::validate`
+    fn set_acl(&self, handle: &FileHandle, acl: &Acl) -> Result<()>;
+
+    /// Space and file counts of the filesystem holding an object
+    ///
+    /// # Arguments
+    /// * `handle` - Handle of any object on the filesystem
+    fn statfs(&self, handle: &FileHandle) -> Result<FsStats>;
+
+    /// Close files the backend keeps open for reuse
+    ///
+    /// Called when no client has the backend's export mounted any more;
+    /// files are reopened if a client comes back. Backends that keep no
+    /// files open do nothing.
+    fn release_cached_files(&self) {}
+
+    /// Number of file handles the backend currently tracks
+    ///
+    /// Reported as a metric; backends without a handle table report 0.
+    fn handle_count(&self) -> usize {
+        0
+    }
 }
 
 /// Filesystem backend types
@@ -299,7 +626,6 @@ pub enum BackendType {
     #[allow(dead_code)]
     Ceph,
     /// In-memory backend (testing)
-    #[allow(dead_code)]
     Memory,
 }
 
@@ -310,6 +636,26 @@ pub struct BackendConfig {
     pub backend_type: BackendType,
     /// Root path for local backend
     pub local_root: Option<PathBuf>,
+    /// Use O_DIRECT for block-aligned writes (local backend)
+    pub direct_io: bool,
+    /// How long GETATTR results are served without revalidation (local backend)
+    pub attr_cache_ttl: Duration,
+    /// Maximum number of cached GETATTR results (local backend)
+    pub attr_cache_entries: usize,
+    /// Maximum number of files kept open between READ and WRITE calls (local backend)
+    pub max_open_files: usize,
+    /// Bytes read ahead for sequential READs, 0 to disable (local backend)
+    pub readahead: usize,
+    /// How long directory names are kept for READDIR continuations, 0 to disable (local backend)
+    pub dir_cache_ttl: Duration,
+    /// How long statvfs results are kept for FSSTAT, 0 to disable (local backend)
+    pub statfs_cache_ttl: Duration,
+    /// Time the root may take to answer at startup, 0 to wait indefinitely (local backend)
+    pub open_timeout: Duration,
+    /// Window COMMITs of a file are batched into one sync, 0 to disable (local backend)
+    pub commit_window: Duration,
+    /// List "." and ".." in directories (local backend)
+    pub dot_entries: bool,
     /// S3 configuration (future)
     #[allow(dead_code)]
     pub s3_config: Option<S3Config>,
@@ -340,11 +686,96 @@ impl BackendConfig {
         Self {
             backend_type: BackendType::Local,
             local_root: Some(root.into()),
+            direct_io: false,
+            attr_cache_ttl: local::DEFAULT_ATTR_CACHE_TTL,
+            attr_cache_entries: local::DEFAULT_ATTR_CACHE_ENTRIES,
+            max_open_files: local::DEFAULT_MAX_OPEN_FILES,
+            readahead: local::DEFAULT_READAHEAD,
+            dir_cache_ttl: local::DEFAULT_DIR_CACHE_TTL,
+            statfs_cache_ttl: local::DEFAULT_STATFS_CACHE_TTL,
+            open_timeout: local::DEFAULT_OPEN_TIMEOUT,
+            commit_window: local::DEFAULT_COMMIT_WINDOW,
+            dot_entries: false,
+            s3_config: None,
+            ceph_config: None,
+        }
+    }
+
+    /// Create an in-memory backend configuration
+    pub fn memory() -> Self {
+        Self {
+            backend_type: BackendType::Memory,
+            local_root: None,
+            direct_io: false,
+            attr_cache_ttl: local::DEFAULT_ATTR_CACHE_TTL,
+            attr_cache_entries: local::DEFAULT_ATTR_CACHE_ENTRIES,
+            max_open_files: local::DEFAULT_MAX_OPEN_FILES,
+            readahead: local::DEFAULT_READAHEAD,
+            dir_cache_ttl: local::DEFAULT_DIR_CACHE_TTL,
+            statfs_cache_ttl: local::DEFAULT_STATFS_CACHE_TTL,
+            open_timeout: local::DEFAULT_OPEN_TIMEOUT,
+            commit_window: local::DEFAULT_COMMIT_WINDOW,
+            dot_entries: false,
             s3_config: None,
             ceph_config: None,
         }
     }
 
+    /// Enable the O_DIRECT write path (local backend only)
+    pub fn with_direct_io(mut self, enabled: bool) -> Self {
+        self.direct_io = enabled;
+        self
+    }
+
+    /// Configure the GETATTR cache (local backend only); a zero TTL always revalidates
+    pub fn with_attr_cache(mut self, ttl: Duration, max_entries: usize) -> Self {
+        self.attr_cache_ttl = ttl;
+        self.attr_cache_entries = max_entries;
+        self
+    }
+
+    /// Limit the open file cache (local backend only); zero disables it
+    pub fn with_max_open_files(mut self, max_open_files: usize) -> Self {
+        self.max_open_files = max_open_files;
+        self
+    }
+
+    /// Read ahead `window` bytes for sequential READs (local backend only); zero disables it
+    pub fn with_readahead(mut self, window: usize) -> Self {
+        self.readahead = window;
+        self
+    }
+
+    /// Serve READDIR continuations from names kept for `ttl` (local backend only); zero disables it
+    pub fn with_dir_cache(mut self, ttl: Duration) -> Self {
+        self.dir_cache_ttl = ttl;
+        self
+    }
+
+    /// Answer FSSTAT from statvfs results kept for `ttl` (local backend only); zero disables it
+    pub fn with_statfs_cache(mut self, ttl: Duration) -> Self {
+        self.statfs_cache_ttl = ttl;
+        self
+    }
+
+    /// Batch COMMITs of a file arriving within `window` into one sync (local backend only); zero disables it
+    pub fn with_commit_window(mut self, window: Duration) -> Self {
+        self.commit_window = window;
+        self
+    }
+
+    /// List "." and ".." at the start of every directory (local backend only)
+    pub fn with_dot_entries(mut self, enabled: bool) -> Self {
+        self.dot_entries = enabled;
+        self
+    }
+
+    /// Fail startup if the root does not answer within `timeout` (local backend only); zero waits
+    pub fn with_open_timeout(mut self, timeout: Duration) -> Self {
+        self.open_timeout = timeout;
+        self
+    }
+
     /// Create filesystem instance from configuration
     pub fn create_filesystem(&self) -> Result<Box<dyn Filesystem>> {
         match self.backend_type {
@@ -353,7 +784,15 @@ impl BackendConfig {
                     .local_root
                     .as_ref()
                     .ok_or_else(|| anyhow::anyhow!("Local root path not configured"))?;
-                let fs = LocalFilesystem::new(root)?;
+                let fs = LocalFilesystem::open_with_timeout(root, self.open_timeout)?
+                    .with_direct_io(self.direct_io)
+                    .with_attr_cache(self.attr_cache_ttl, self.attr_cache_entries)
+                    .with_max_open_files(self.max_open_files)
+                    .with_readahead(self.readahead)
+                    .with_dir_cache(self.dir_cache_ttl)
+                    .with_statfs_cache(self.statfs_cache_ttl)
+                    .with_commit_window(self.commit_window)
+                    .with_dot_entries(self.dot_entries);
                 Ok(Box::new(fs))
             }
             BackendType::S3 => {
@@ -364,10 +803,7 @@ impl BackendConfig {
                 // TODO: Implement Ceph backend
                 Err(anyhow::anyhow!("Ceph backend not yet implemented"))
             }
-            BackendType::Memory => {
-                // TODO: Implement memory backend
-                Err(anyhow::anyhow!("Memory backend not yet implemented"))
-            }
+            BackendType::Memory => Ok(Box::new(MemoryFilesystem::new())),
         }
     }
 }
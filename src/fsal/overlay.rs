@@ -0,0 +1,788 @@
+// Overlay Filesystem Adapter
+//
+// Layers a writable backend over a read-only one, for exports that serve a
+// golden image that clients may change without changing the image itself.
+//
+// - Lookups and reads see the upper layer first, then the lower one. A
+//   directory present in both lists the entries of both.
+// - Everything that modifies an object first copies it up: the object (and
+//   any missing parent directories) is recreated in the upper layer with
+//   the lower one's data and attributes. The lower layer is never modified.
+// - Removing a name the lower layer has leaves a whiteout in the upper
+//   layer, an empty file named ".wh.<name>" that hides the lower entry.
+//   Whiteouts are kept when the name is created again, so removing the new
+//   object does not bring the old one back. Names starting with ".wh." are
+//   reserved and never listed.
+// - Renaming a directory that exists in the lower layer, or onto one, fails
+//   with EXDEV, as with Linux overlayfs without redirects; clients fall back
+//   to copying.
+//
+// Overlay handles are node numbers assigned as names are looked up, so like
+// those of the memory backend they do not survive a restart.
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::Mutex;
+
+use super::handle::FileHandle;
+use super::{
+    Acl, DirEntry, FileAttributes, FileTime, FileType, Filesystem, FsStats, FsalError, SetAttributes, SetTime,
+    StableHow,
+};
+
+/// Node number of the root directory
+const ROOT_ID: u64 = 1;
+
+/// Prefix of whiteout names; names with it are reserved
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// Bytes copied per READ/WRITE when a file is copied up
+const COPY_CHUNK: u32 = 1024 * 1024;
+
+/// Entries requested per READDIR when a layer's directory is listed
+const LIST_CHUNK: u32 = 1024;
+
+/// A name in the merged tree and the object each layer has under it
+#[derive(Debug, Clone)]
+struct Node {
+    parent: u64,
+    name: String,
+    /// Object in the upper layer; data and attributes come from it if set
+    upper: Option<FileHandle>,
+    /// Object in the lower layer, unless a whiteout hides it
+    lower: Option<FileHandle>,
+}
+
+impl Node {
+    /// Handle and layer serving the node's data and attributes
+    fn data<'a>(&'a self, overlay: &'a OverlayFilesystem) -> Result<(&'a dyn Filesystem, &'a FileHandle)> {
+        match (&self.upper, &self.lower) {
+            (Some(upper), _) => Ok((overlay.upper.as_ref(), upper)),
+            (None, Some(lower)) => Ok((overlay.lower.as_ref(), lower)),
+            (None, None) => Err(FsalError::stale(format!("{:?} is in neither layer", self.name)).into()),
+        }
+    }
+}
+
+struct Nodes {
+    by_id: HashMap<u64, Node>,
+    /// (parent, name) → node
+    by_name: HashMap<(u64, String), u64>,
+    next_id: u64,
+}
+
+/// Copy-on-write union of a read-only lower and a writable upper backend
+pub struct OverlayFilesystem {
+    lower: Box<dyn Filesystem>,
+    upper: Box<dyn Filesystem>,
+    nodes: Mutex<Nodes>,
+    /// fsid reported for every object, the upper layer's
+    fsid: u64,
+}
+
+fn errno(code: i32) -> anyhow::Error {
+    io::Error::from_raw_os_error(code).into()
+}
+
+fn encode_handle(id: u64) -> FileHandle {
+    id.to_be_bytes().to_vec()
+}
+
+fn decode_handle(handle: &FileHandle) -> Result<u64> {
+    let bytes: [u8; 8] = handle
+        .as_slice()
+        .try_into()
+        .map_err(|_| FsalError::bad_handle(format!("{} bytes, expected 8", handle.len())))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn whiteout(name: &str) -> String {
+    format!("{}{}", WHITEOUT_PREFIX, name)
+}
+
+fn is_reserved(name: &str) -> bool {
+    name.starts_with(WHITEOUT_PREFIX)
+}
+
+/// Every entry of a directory in one layer, without "." and ".."
+fn list_all(layer: &dyn Filesystem, dir: &FileHandle) -> Result<Vec<DirEntry>> {
+    let mut entries = Vec::new();
+    let mut cookie = 0;
+    loop {
+        let (chunk, eof) = layer.readdir(dir, cookie, LIST_CHUNK)?;
+        cookie += chunk.len() as u64;
+        let done = eof || chunk.is_empty();
+        entries.extend(chunk.into_iter().filter(|entry| entry.name != "." && entry.name != ".."));
+        if done {
+            return Ok(entries);
+        }
+    }
+}
+
+impl OverlayFilesystem {
+    /// Layer `upper` over `lower`
+    ///
+    /// # Arguments
+    /// * `lower` - Read-only layer; never modified through the overlay
+    /// * `upper` - Writable layer receiving every change
+    pub fn new(lower: Box<dyn Filesystem>, upper: Box<dyn Filesystem>) -> Self {
+        let root = Node {
+            parent: ROOT_ID,
+            name: String::new(),
+            upper: Some(upper.root_handle()),
+            lower: Some(lower.root_handle()),
+        };
+        let fsid = upper.getattr(&upper.root_handle()).map(|attrs| attrs.fsid).unwrap_or_default();
+        Self {
+            lower,
+            upper,
+            nodes: Mutex::new(Nodes {
+                by_id: HashMap::from([(ROOT_ID, root)]),
+                by_name: HashMap::new(),
+                next_id: ROOT_ID + 1,
+            }),
+            fsid,
+        }
+    }
+
+    /// Node behind an overlay handle, resolved in both layers
+    fn node(&self, handle: &FileHandle) -> Result<(u64, Node)> {
+        let id = decode_handle(handle)?;
+        self.node_by_id(id)
+    }
+
+    fn node_by_id(&self, id: u64) -> Result<(u64, Node)> {
+        let node = self.nodes.lock().unwrap().by_id.get(&id).cloned();
+        let node = node.ok_or_else(|| FsalError::stale(format!("overlay node {} no longer exists", id)))?;
+        if node.upper.is_some() || node.lower.is_some() {
+            return Ok((id, node));
+        }
+
+        // Listed by READDIR but not looked up yet
+        let (_, parent) = self.node_by_id(node.parent)?;
+        let (upper, lower) = self
+            .resolve(&parent, &node.name)
+            .ok_or_else(|| FsalError::stale(format!("{:?} no longer exists", node.name)))?;
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes
+            .by_id
+            .get_mut(&id)
+            .ok_or_else(|| FsalError::stale(format!("overlay node {} no longer exists", id)))?;
+        node.upper = upper;
+        node.lower = lower;
+        Ok((id, node.clone()))
+    }
+
+    /// Node number of a name, assigning one if it has none yet
+    fn node_id(&self, parent: u64, name: &str) -> u64 {
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(id) = nodes.by_name.get(&(parent, name.to_string())) {
+            return *id;
+        }
+        let id = nodes.next_id;
+        nodes.next_id += 1;
+        let node = Node { parent, name: name.to_string(), upper: None, lower: None };
+        nodes.by_id.insert(id, node);
+        nodes.by_name.insert((parent, name.to_string()), id);
+        id
+    }
+
+    /// Record what each layer has under a name and return its handle
+    fn set_node(&self, parent: u64, name: &str, upper: Option<FileHandle>, lower: Option<FileHandle>) -> FileHandle {
+        let id = self.node_id(parent, name);
+        if let Some(node) = self.nodes.lock().unwrap().by_id.get_mut(&id) {
+            node.upper = upper;
+            node.lower = lower;
+        }
+        encode_handle(id)
+    }
+
+    /// Drop a name whose object is gone; its handle turns stale
+    fn forget(&self, parent: u64, name: &str) {
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(id) = nodes.by_name.remove(&(parent, name.to_string())) {
+            nodes.by_id.remove(&id);
+        }
+    }
+
+    /// Whether the upper directory holds a whiteout for `name`
+    fn whited_out(&self, dir_upper: &FileHandle, name: &str) -> bool {
+        self.upper.lookup(dir_upper, &whiteout(name)).is_ok()
+    }
+
+    /// The objects each layer has under `name` in a directory
+    ///
+    /// # Returns
+    /// (upper, lower), or None if neither layer has a visible object
+    fn resolve(&self, dir: &Node, name: &str) -> Option<(Option<FileHandle>, Option<FileHandle>)> {
+        if is_reserved(name) {
+            return None;
+        }
+        let upper = dir.upper.as_ref().and_then(|dir| self.upper.lookup(dir, name).ok());
+        let hidden = dir.upper.as_ref().is_some_and(|dir| self.whited_out(dir, name));
+        let lower = match &dir.lower {
+            Some(dir) if !hidden => self.lower.lookup(dir, name).ok(),
+            _ => None,
+        };
+        if upper.is_none() && lower.is_none() {
+            return None;
+        }
+        Some((upper, lower))
+    }
+
+    /// Entries of a merged directory: the upper layer's, then those of the
+    /// lower layer that the upper one neither has nor whites out
+    fn merged_entries(&self, dir: &Node) -> Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        let mut whiteouts = HashSet::new();
+        if let Some(upper) = &dir.upper {
+            for entry in list_all(self.upper.as_ref(), upper)? {
+                match entry.name.strip_prefix(WHITEOUT_PREFIX) {
+                    Some(name) => {
+                        whiteouts.insert(name.to_string());
+                    }
+                    None => entries.push(entry),
+                }
+            }
+        }
+
+        // A lower object shadowed by an upper file has no entries to merge
+        let upper_is_dir = dir.upper.is_none() || dir.data(self).is_ok_and(|(layer, handle)| {
+            layer.getattr(handle).is_ok_and(|attrs| attrs.ftype == FileType::Directory)
+        });
+        if let Some(lower) = &dir.lower
+            && upper_is_dir
+            && self.lower.getattr(lower).is_ok_and(|attrs| attrs.ftype == FileType::Directory)
+        {
+            let upper_names: HashSet<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+            for entry in list_all(self.lower.as_ref(), lower)? {
+                if !is_reserved(&entry.name) && !upper_names.contains(&entry.name) && !whiteouts.contains(&entry.name) {
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Make sure a node exists in the upper layer, copying it up if not
+    ///
+    /// Missing parent directories are copied up first. Ownership and times
+    /// are carried over where the upper layer allows it.
+    ///
+    /// # Returns
+    /// Handle of the node in the upper layer
+    fn copy_up(&self, id: u64) -> Result<FileHandle> {
+        let (id, node) = self.node_by_id(id)?;
+        if let Some(upper) = node.upper {
+            return Ok(upper);
+        }
+        let lower = node.lower.ok_or_else(|| FsalError::stale(format!("{:?} is in neither layer", node.name)))?;
+        let parent = self.copy_up(node.parent)?;
+        let attrs = self.lower.getattr(&lower)?;
+        let mode = attrs.mode & 0o7777;
+
+        let upper = match attrs.ftype {
+            FileType::Directory => self.upper.mkdir(&parent, &node.name, mode)?,
+            FileType::SymbolicLink => self.upper.symlink(&parent, &node.name, &self.lower.readlink(&lower)?)?,
+            FileType::RegularFile => {
+                let upper = self.upper.create_guarded(&parent, &node.name, mode)?;
+                let mut offset = 0;
+                loop {
+                    let data = self.lower.read(&lower, offset, COPY_CHUNK)?;
+                    if data.is_empty() {
+                        break;
+                    }
+                    self.upper.write(&upper, offset, &data, StableHow::Unstable)?;
+                    offset += data.len() as u64;
+                }
+                self.upper.commit(&upper, 0, 0)?;
+                upper
+            }
+            special => self.upper.mknod(&parent, &node.name, special, mode, attrs.rdev)?,
+        };
+
+        // An unprivileged server cannot give the copy its original owner
+        let _ = self.upper.setattr_owner(&upper, Some(attrs.uid), Some(attrs.gid));
+        if attrs.ftype != FileType::SymbolicLink {
+            let _ = self.upper.setattr_mode(&upper, mode);
+        }
+        let _ = self.upper.setattr_times(
+            &upper,
+            Some(SetTime::ClientTime(attrs.atime)),
+            Some(SetTime::ClientTime(attrs.mtime)),
+        );
+
+        if let Some(node) = self.nodes.lock().unwrap().by_id.get_mut(&id) {
+            node.upper = Some(upper.clone());
+        }
+        Ok(upper)
+    }
+
+    /// Create a new object under a name no layer shows
+    ///
+    /// The parent is copied up and `make` creates the object in it. A
+    /// whiteout of the name is kept, so the lower object stays hidden.
+    fn create_new<F>(&self, dir_handle: &FileHandle, name: &str, make: F) -> Result<FileHandle>
+    where
+        F: FnOnce(&FileHandle) -> Result<FileHandle>,
+    {
+        if is_reserved(name) {
+            return Err(errno(libc::EINVAL));
+        }
+        let (dir_id, dir) = self.node(dir_handle)?;
+        if self.resolve(&dir, name).is_some() {
+            return Err(errno(libc::EEXIST));
+        }
+        let dir_upper = self.copy_up(dir_id)?;
+        let upper = make(&dir_upper)?;
+        Ok(self.set_node(dir_id, name, Some(upper), None))
+    }
+
+    /// Remove a name: its upper object, and a whiteout for the lower one
+    fn remove_name(&self, dir_id: u64, name: &str, node: &Node, is_dir: bool) -> Result<()> {
+        if node.upper.is_some() {
+            let (_, dir) = self.node_by_id(dir_id)?;
+            let dir_upper = dir.upper.ok_or_else(|| anyhow!("Upper object without upper parent"))?;
+            if is_dir {
+                self.upper.rmdir(&dir_upper, name)?;
+            } else {
+                self.upper.remove(&dir_upper, name)?;
+            }
+        }
+        if node.lower.is_some() {
+            let dir_upper = self.copy_up(dir_id)?;
+            self.upper.create(&dir_upper, &whiteout(name), 0o600)?;
+        }
+        self.forget(dir_id, name);
+        Ok(())
+    }
+
+    /// Drop the whiteouts left in an upper directory, so it can be removed
+    fn clear_whiteouts(&self, dir_upper: &FileHandle) -> Result<()> {
+        for entry in list_all(self.upper.as_ref(), dir_upper)? {
+            if is_reserved(&entry.name) {
+                self.upper.remove(dir_upper, &entry.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Filesystem for OverlayFilesystem {
+    fn root_handle(&self) -> FileHandle {
+        encode_handle(ROOT_ID)
+    }
+
+    fn lookup(&self, dir_handle: &FileHandle, name: &str) -> Result<FileHandle> {
+        let (dir_id, dir) = self.node(dir_handle)?;
+        match name {
+            "." => Ok(dir_handle.clone()),
+            ".." => self.parent_handle(dir_handle),
+            _ => {
+                let (upper, lower) = self.resolve(&dir, name).ok_or_else(|| anyhow!("File not found: {}", name))?;
+                Ok(self.set_node(dir_id, name, upper, lower))
+            }
+        }
+    }
+
+    fn parent_handle(&self, dir_handle: &FileHandle) -> Result<FileHandle> {
+        let (_, dir) = self.node(dir_handle)?;
+        Ok(encode_handle(dir.parent))
+    }
+
+    fn getattr(&self, handle: &FileHandle) -> Result<FileAttributes> {
+        let (id, node) = self.node(handle)?;
+        let (layer, handle) = node.data(self)?;
+        let mut attrs = layer.getattr(handle)?;
+        attrs.fileid = id;
+        attrs.fsid = self.fsid;
+        Ok(attrs)
+    }
+
+    fn read(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<Bytes> {
+        let (_, node) = self.node(handle)?;
+        let (layer, handle) = node.data(self)?;
+        layer.read(handle, offset, count)
+    }
+
+    fn readdir(&self, dir_handle: &FileHandle, cookie: u64, count: u32) -> Result<(Vec<DirEntry>, bool)> {
+        let (dir_id, dir) = self.node(dir_handle)?;
+        let merged = self.merged_entries(&dir)?;
+        let total = merged.len();
+        let entries: Vec<DirEntry> = merged
+            .into_iter()
+            .skip(cookie as usize)
+            .take(count as usize)
+            .map(|entry| DirEntry { fileid: self.node_id(dir_id, &entry.name), ..entry })
+            .collect();
+        let eof = cookie as usize + entries.len() >= total;
+        Ok((entries, eof))
+    }
+
+    fn write(&self, handle: &FileHandle, offset: u64, data: &[u8], stable: StableHow) -> Result<u32> {
+        let (id, _) = self.node(handle)?;
+        let upper = self.copy_up(id)?;
+        self.upper.write(&upper, offset, data, stable)
+    }
+
+    fn setattr_size(&self, handle: &FileHandle, size: u64) -> Result<()> {
+        self.setattr(handle, &SetAttributes { size: Some(size), ..Default::default() }, None)
+    }
+
+    fn setattr_mode(&self, handle: &FileHandle, mode: u32) -> Result<()> {
+        self.setattr(handle, &SetAttributes { mode: Some(mode), ..Default::default() }, None)
+    }
+
+    fn setattr_owner(&self, handle: &FileHandle, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.setattr(handle, &SetAttributes { uid, gid, ..Default::default() }, None)
+    }
+
+    fn setattr_times(&self, handle: &FileHandle, atime: Option<SetTime>, mtime: Option<SetTime>) -> Result<()> {
+        self.setattr(handle, &SetAttributes { atime, mtime, ..Default::default() }, None)
+    }
+
+    fn setattr(&self, handle: &FileHandle, attrs: &SetAttributes, guard: Option<FileTime>) -> Result<()> {
+        let (id, node) = self.node(handle)?;
+
+        // The client's ctime is the lower object's until it is copied up
+        let guard = match (&node.upper, &node.lower, guard) {
+            (None, Some(lower), Some(guard)) => {
+                if self.lower.getattr(lower)?.ctime != guard {
+                    return Err(FsalError::NotSync.into());
+                }
+                None
+            }
+            _ => guard,
+        };
+        let upper = self.copy_up(id)?;
+        self.upper.setattr(&upper, attrs, guard)
+    }
+
+    fn create(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        // Creating over an existing regular file opens it unchanged
+        if let Ok(handle) = self.lookup(dir_handle, name) {
+            return match self.getattr(&handle)?.ftype {
+                FileType::RegularFile => Ok(handle),
+                FileType::Directory => Err(errno(libc::EISDIR)),
+                _ => Err(errno(libc::EEXIST)),
+            };
+        }
+        self.create_new(dir_handle, name, |dir| self.upper.create(dir, name, mode))
+    }
+
+    fn create_guarded(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        self.create_new(dir_handle, name, |dir| self.upper.create_guarded(dir, name, mode))
+    }
+
+    fn remove(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
+        let (dir_id, dir) = self.node(dir_handle)?;
+        let (upper, lower) = self.resolve(&dir, name).ok_or_else(|| anyhow!("File not found: {}", name))?;
+        let node = Node { parent: dir_id, name: name.to_string(), upper, lower };
+        let (layer, handle) = node.data(self)?;
+        if layer.getattr(handle)?.ftype == FileType::Directory {
+            return Err(errno(libc::EISDIR));
+        }
+        self.remove_name(dir_id, name, &node, false)
+    }
+
+    fn mkdir(&self, dir_handle: &FileHandle, name: &str, mode: u32) -> Result<FileHandle> {
+        self.create_new(dir_handle, name, |dir| self.upper.mkdir(dir, name, mode))
+    }
+
+    fn rmdir(&self, dir_handle: &FileHandle, name: &str) -> Result<()> {
+        let handle = self.lookup(dir_handle, name)?;
+        let (dir_id, _) = self.node(dir_handle)?;
+        let (_, node) = self.node(&handle)?;
+        let (layer, data) = node.data(self)?;
+        if layer.getattr(data)?.ftype != FileType::Directory {
+            return Err(errno(libc::ENOTDIR));
+        }
+        if !self.merged_entries(&node)?.is_empty() {
+            return Err(errno(libc::ENOTEMPTY));
+        }
+        if let Some(upper) = &node.upper {
+            self.clear_whiteouts(upper)?;
+        }
+        self.remove_name(dir_id, name, &node, true)
+    }
+
+    fn rename(
+        &self,
+        from_dir_handle: &FileHandle,
+        from_name: &str,
+        to_dir_handle: &FileHandle,
+        to_name: &str,
+    ) -> Result<()> {
+        if is_reserved(to_name) {
+            return Err(errno(libc::EINVAL));
+        }
+        let handle = self.lookup(from_dir_handle, from_name)?;
+        let (id, node) = self.node(&handle)?;
+        let (from_dir_id, _) = self.node(from_dir_handle)?;
+        let (to_dir_id, to_dir) = self.node(to_dir_handle)?;
+        let is_dir = self.getattr(&handle)?.ftype == FileType::Directory;
+
+        // The target's lower object stays visible to the renamed one unless
+        // whited out; directories would merge with it
+        let target = self.resolve(&to_dir, to_name);
+        let target_lower = target.as_ref().and_then(|(_, lower)| lower.clone());
+        if is_dir && (node.lower.is_some() || target_lower.is_some()) {
+            return Err(errno(libc::EXDEV));
+        }
+
+        let upper = self.copy_up(id)?;
+        let from_dir_upper = self.copy_up(from_dir_id)?;
+        let to_dir_upper = self.copy_up(to_dir_id)?;
+        self.upper.rename(&from_dir_upper, from_name, &to_dir_upper, to_name)?;
+        if node.lower.is_some() {
+            self.upper.create(&from_dir_upper, &whiteout(from_name), 0o600)?;
+        }
+
+        // The object keeps its handle under the new name; a replaced
+        // target's handle turns stale
+        let upper = self.upper.lookup(&to_dir_upper, to_name).unwrap_or(upper);
+        self.forget(to_dir_id, to_name);
+        let mut nodes = self.nodes.lock().unwrap();
+        nodes.by_name.remove(&(from_dir_id, from_name.to_string()));
+        nodes.by_name.insert((to_dir_id, to_name.to_string()), id);
+        let moved = Node { parent: to_dir_id, name: to_name.to_string(), upper: Some(upper), lower: target_lower };
+        nodes.by_id.insert(id, moved);
+        Ok(())
+    }
+
+    fn symlink(&self, dir_handle: &FileHandle, name: &str, target: &str) -> Result<FileHandle> {
+        self.create_new(dir_handle, name, |dir| self.upper.symlink(dir, name, target))
+    }
+
+    fn readlink(&self, handle: &FileHandle) -> Result<String> {
+        let (_, node) = self.node(handle)?;
+        let (layer, handle) = node.data(self)?;
+        layer.readlink(handle)
+    }
+
+    fn link(&self, file_handle: &FileHandle, dir_handle: &FileHandle, name: &str) -> Result<FileHandle> {
+        let (id, _) = self.node(file_handle)?;
+        let upper = self.copy_up(id)?;
+        self.create_new(dir_handle, name, |dir| self.upper.link(&upper, dir, name))
+    }
+
+    fn commit(&self, handle: &FileHandle, offset: u64, count: u32) -> Result<()> {
+        // Nothing was written to an object that was never copied up
+        match self.node(handle)?.1.upper {
+            Some(upper) => self.upper.commit(&upper, offset, count),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&self) -> Result<usize> {
+        self.upper.flush()
+    }
+
+    fn mknod(
+        &self,
+        dir_handle: &FileHandle,
+        name: &str,
+        file_type: FileType,
+        mode: u32,
+        rdev: (u32, u32),
+    ) -> Result<FileHandle> {
+        self.create_new(dir_handle, name, |dir| self.upper.mknod(dir, name, file_type, mode, rdev))
+    }
+
+    fn get_xattr(&self, handle: &FileHandle, name: &str) -> Result<Option<Vec<u8>>> {
+        let (_, node) = self.node(handle)?;
+        let (layer, handle) = node.data(self)?;
+        layer.get_xattr(handle, name)
+    }
+
+    fn set_xattr(&self, handle: &FileHandle, name: &str, value: &[u8]) -> Result<()> {
+        let (id, _) = self.node(handle)?;
+        self.upper.set_xattr(&self.copy_up(id)?, name, value)
+    }
+
+    fn list_xattr(&self, handle: &FileHandle) -> Result<Vec<String>> {
+        let (_, node) = self.node(handle)?;
+        let (layer, handle) = node.data(self)?;
+        layer.list_xattr(handle)
+    }
+
+    fn remove_xattr(&self, handle: &FileHandle, name: &str) -> Result<()> {
+        let (id, _) = self.node(handle)?;
+        self.upper.remove_xattr(&self.copy_up(id)?, name)
+    }
+
+    fn get_acl(&self, handle: &FileHandle) -> Result<Option<Acl>> {
+        let (_, node) = self.node(handle)?;
+        let (layer, handle) = node.data(self)?;
+        layer.get_acl(handle)
+    }
+
+    fn set_acl(&self, handle: &FileHandle, acl: &Acl) -> Result<()> {
+        let (id, _) = self.node(handle)?;
+        self.upper.set_acl(&self.copy_up(id)?, acl)
+    }
+
+    fn statfs(&self, _handle: &FileHandle) -> Result<FsStats> {
+        // New data goes to the upper layer, so its space is what is left
+        self.upper.statfs(&self.upper.root_handle())
+    }
+
+    fn release_cached_files(&self) {
+        self.lower.release_cached_files();
+        self.upper.release_cached_files();
+    }
+
+    fn handle_count(&self) -> usize {
+        self.nodes.lock().unwrap().by_id.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsal::LocalFilesystem;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    /// Overlay of an empty upper directory on a lower one holding
+    /// "image.txt" and "etc/config"; the layers are returned to inspect
+    fn overlay() -> (OverlayFilesystem, TempDir, TempDir) {
+        let lower = TempDir::new().unwrap();
+        fs::write(lower.path().join("image.txt"), b"golden image").unwrap();
+        fs::create_dir(lower.path().join("etc")).unwrap();
+        fs::write(lower.path().join("etc/config"), b"lower").unwrap();
+        let upper = TempDir::new().unwrap();
+
+        let overlay = OverlayFilesystem::new(
+            Box::new(LocalFilesystem::new(lower.path()).unwrap()),
+            Box::new(LocalFilesystem::new(upper.path()).unwrap()),
+        );
+        (overlay, lower, upper)
+    }
+
+    fn names(fs: &dyn Filesystem, dir: &FileHandle) -> Vec<String> {
+        let (entries, eof) = fs.readdir(dir, 0, 100).unwrap();
+        assert!(eof);
+        let mut names: Vec<String> = entries.into_iter().map(|entry| entry.name).collect();
+        names.sort();
+        names
+    }
+
+    fn read_all(fs: &dyn Filesystem, handle: &FileHandle) -> Vec<u8> {
+        fs.read(handle, 0, 1024).unwrap().to_vec()
+    }
+
+    #[test]
+    fn test_reads_fall_through_to_lower() {
+        let (fs, _lower, upper) = overlay();
+        let root = fs.root_handle();
+
+        let image = fs.lookup(&root, "image.txt").unwrap();
+        assert_eq!(read_all(&fs, &image), b"golden image");
+        assert_eq!(fs.getattr(&image).unwrap().size, 12);
+        assert!(!upper.path().join("image.txt").exists(), "Reads do not copy up");
+
+        // Upper entries are listed along with the lower ones
+        fs.create(&root, "new.txt", 0o644).unwrap();
+        assert_eq!(names(&fs, &root), ["etc", "image.txt", "new.txt"]);
+        let etc = fs.lookup(&root, "etc").unwrap();
+        assert_eq!(names(&fs, &etc), ["config"]);
+        assert_eq!(fs.parent_handle(&etc).unwrap(), root);
+
+        // READDIR fileids are the ones GETATTR reports
+        let (entries, _) = fs.readdir(&root, 0, 100).unwrap();
+        let listed = entries.iter().find(|entry| entry.name == "image.txt").unwrap();
+        assert_eq!(listed.fileid, fs.getattr(&image).unwrap().fileid);
+
+        // Listings resume at the cookie
+        let (first, eof) = fs.readdir(&root, 0, 2).unwrap();
+        assert!(!eof);
+        let (rest, eof) = fs.readdir(&root, 2, 2).unwrap();
+        assert!(eof);
+        assert_eq!(first.len() + rest.len(), 3);
+    }
+
+    #[test]
+    fn test_write_copies_up() {
+        let (fs, lower, upper) = overlay();
+        let root = fs.root_handle();
+        let etc = fs.lookup(&root, "etc").unwrap();
+        let config = fs.lookup(&etc, "config").unwrap();
+        fs::set_permissions(lower.path().join("etc/config"), fs::Permissions::from_mode(0o600)).unwrap();
+
+        fs.write(&config, 0, b"UPPER", StableHow::FileSync).unwrap();
+        assert_eq!(read_all(&fs, &config), b"UPPER");
+        assert_eq!(fs.getattr(&config).unwrap().mode & 0o777, 0o600, "Mode is copied up");
+
+        // The parent was copied up with it, and the lower layer is untouched
+        assert_eq!(fs::read(upper.path().join("etc/config")).unwrap(), b"UPPER");
+        assert_eq!(fs::read(lower.path().join("etc/config")).unwrap(), b"lower");
+        assert_eq!(names(&fs, &etc), ["config"]);
+
+        // A partial write keeps the rest of the lower data
+        let image = fs.lookup(&root, "image.txt").unwrap();
+        fs.write(&image, 0, b"GOLDEN", StableHow::Unstable).unwrap();
+        fs.commit(&image, 0, 0).unwrap();
+        assert_eq!(read_all(&fs, &image), b"GOLDEN image");
+
+        // As does a truncation, which also copies up
+        fs.setattr_size(&image, 6).unwrap();
+        assert_eq!(read_all(&fs, &image), b"GOLDEN");
+        assert_eq!(fs::read(lower.path().join("image.txt")).unwrap(), b"golden image");
+    }
+
+    #[test]
+    fn test_remove_leaves_whiteout() {
+        let (fs, lower, upper) = overlay();
+        let root = fs.root_handle();
+
+        fs.remove(&root, "image.txt").unwrap();
+        assert!(fs.lookup(&root, "image.txt").is_err());
+        assert_eq!(names(&fs, &root), ["etc"], "Whiteouts are not listed");
+        assert!(upper.path().join(".wh.image.txt").exists());
+        assert!(lower.path().join("image.txt").exists());
+
+        // A new file under the name is empty, and removing it again does not
+        // bring back the lower one
+        let image = fs.create(&root, "image.txt", 0o644).unwrap();
+        assert_eq!(read_all(&fs, &image), b"");
+        fs.remove(&root, "image.txt").unwrap();
+        assert!(fs.lookup(&root, "image.txt").is_err());
+
+        // A file copied up before removal is whited out too
+        let etc = fs.lookup(&root, "etc").unwrap();
+        let config = fs.lookup(&etc, "config").unwrap();
+        fs.write(&config, 0, b"x", StableHow::FileSync).unwrap();
+        fs.remove(&etc, "config").unwrap();
+        assert!(fs.lookup(&etc, "config").is_err());
+        assert!(names(&fs, &etc).is_empty());
+
+        // Directories empty in the merged view can be removed
+        fs.rmdir(&root, "etc").unwrap();
+        assert!(fs.lookup(&root, "etc").is_err());
+        assert!(fs.lookup(&root, ".wh.etc").is_err(), "Reserved names cannot be looked up");
+        let etc = fs.mkdir(&root, "etc", 0o755).unwrap();
+        assert!(names(&fs, &etc).is_empty(), "The lower directory stays hidden");
+        assert!(lower.path().join("etc/config").exists());
+    }
+
+    #[test]
+    fn test_rename_of_lower_file() {
+        let (fs, lower, _upper) = overlay();
+        let root = fs.root_handle();
+        let image = fs.lookup(&root, "image.txt").unwrap();
+
+        fs.rename(&root, "image.txt", &root, "copy.txt").unwrap();
+        assert_eq!(names(&fs, &root), ["copy.txt", "etc"]);
+        assert_eq!(fs.lookup(&root, "copy.txt").unwrap(), image, "The handle follows the file");
+        assert_eq!(read_all(&fs, &image), b"golden image");
+        assert!(lower.path().join("image.txt").exists());
+
+        // Lower directories cannot be moved
+        let err = fs.rename(&root, "etc", &root, "etc2").unwrap_err();
+        assert_eq!(crate::fsal::error::errno_of(&err), Some(libc::EXDEV));
+    }
+}
@@ -0,0 +1,203 @@
+// Health Endpoint
+//
+// A plain HTTP liveness/readiness probe on its own port, so orchestrators
+// can check the server without crafting an RPC NULL call:
+//
+//   GET /health -> 200 {"status":"ok","uptime_s":12,"active_connections":2,"handles":118,"open_breakers":[]}
+//
+// The reply is 503 with "status":"unavailable" when the root of any export
+// cannot be read, e.g. because the volume behind it went away.
+// "open_breakers" lists the exports whose calls are currently failed
+// without reaching the backend (see rpc::breaker).
+
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+use crate::export::{ExportId, ExportResolver};
+use crate::metrics::{self, Metrics};
+use crate::rpc::breaker::CircuitBreaker;
+
+/// Server state reported by the health endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Every export root can be read
+    pub available: bool,
+    /// Seconds since the endpoint started serving
+    pub uptime_s: u64,
+    /// Open client connections
+    pub active_connections: usize,
+    /// File handles tracked by the backends
+    pub handles: usize,
+    /// Exports whose circuit breaker is open
+    pub open_breakers: Vec<ExportId>,
+}
+
+impl HealthReport {
+    /// HTTP status line of the report
+    pub fn status(&self) -> &'static str {
+        if self.available { "200 OK" } else { "503 Service Unavailable" }
+    }
+
+    /// Render the report as a JSON object
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"status\":\"{}\",\"uptime_s\":{},\"active_connections\":{},\"handles\":{},\"open_breakers\":[{}]}}\n",
+            if self.available { "ok" } else { "unavailable" },
+            self.uptime_s,
+            self.active_connections,
+            self.handles,
+            self.open_breakers.iter().map(ExportId::to_string).collect::<Vec<_>>().join(",")
+        )
+    }
+}
+
+/// Whether the root of every export can be read
+pub fn exports_available(exports: &dyn ExportResolver) -> bool {
+    exports.export_ids().into_iter().all(|export_id| export_available(exports, export_id))
+}
+
+/// Whether the root of an export can be read
+pub fn export_available(exports: &dyn ExportResolver, export_id: ExportId) -> bool {
+    let Some(root_handle) = exports.root_handle(export_id) else {
+        return false;
+    };
+    let Some((_, backend, root)) = exports.resolve_handle(&root_handle) else {
+        return false;
+    };
+    match backend.getattr(&root.to_vec()) {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Health check: root of export {} is not accessible: {}", export_id, e);
+            false
+        }
+    }
+}
+
+/// Serve `GET /health` on a bound listener until the task is dropped
+///
+/// # Arguments
+/// * `listener` - Bound HTTP listener
+/// * `metrics` - Registry the connection count is read from
+/// * `exports` - Exports whose roots are checked and handles counted
+/// * `breakers` - Circuit breakers whose open exports are listed
+pub async fn serve(
+    listener: TcpListener,
+    metrics: Arc<Metrics>,
+    exports: Arc<dyn ExportResolver>,
+    breakers: CircuitBreaker,
+) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("Health endpoint listening on http://{}/health", addr);
+    }
+    let started = Instant::now();
+    loop {
+        let Ok((stream, peer_addr)) = listener.accept().await else {
+            continue;
+        };
+        let metrics = Arc::clone(&metrics);
+        let exports = Arc::clone(&exports);
+        let breakers = breakers.clone();
+        tokio::spawn(async move {
+            if let Err(e) = answer(stream, started, &metrics, exports, &breakers).await {
+                debug!("Health request from {} failed: {}", peer_addr, e);
+            }
+        });
+    }
+}
+
+/// Answer one HTTP request and close the connection
+async fn answer(
+    mut stream: TcpStream,
+    started: Instant,
+    metrics: &Metrics,
+    exports: Arc<dyn ExportResolver>,
+    breakers: &CircuitBreaker,
+) -> std::io::Result<()> {
+    let (method, path) = metrics::read_request(&mut stream).await?;
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/health") => {
+            // Checking the roots stats the backends, which may block
+            let checked = Arc::clone(&exports);
+            let available = tokio::task::spawn_blocking(move || exports_available(checked.as_ref()))
+                .await
+                .unwrap_or(false);
+            let report = HealthReport {
+                available,
+                uptime_s: started.elapsed().as_secs(),
+                active_connections: metrics.active_connections(),
+                handles: exports.handle_count(),
+                open_breakers: breakers.open_exports(),
+            };
+            metrics::respond(stream, report.status(), "application/json", &report.to_json()).await
+        }
+        ("GET", _) => metrics::respond(stream, "404 Not Found", "text/plain", "Not found\n").await,
+        _ => metrics::respond(stream, "405 Method Not Allowed", "text/plain", "Method not allowed\n").await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportTable;
+    use crate::fsal::BackendConfig;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_health_endpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("export");
+        std::fs::create_dir(&root).unwrap();
+        // Revalidate on every GETATTR so the removal below is seen at once
+        let backend = BackendConfig::local(&root)
+            .with_attr_cache(Duration::ZERO, 16)
+            .create_filesystem()
+            .unwrap();
+        let exports: Arc<dyn ExportResolver> = Arc::new(ExportTable::single("/", Arc::from(backend)));
+        let metrics = Arc::new(Metrics::new());
+        let _connection = metrics.connection_opened();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let breakers = CircuitBreaker::default();
+        let server = tokio::spawn(serve(listener, metrics, exports, breakers.clone()));
+
+        let fetch = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = fetch("/health").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Content-Type: application/json\r\n"), "{}", response);
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        assert!(body.starts_with("{\"status\":\"ok\",\"uptime_s\":"), "{}", body);
+        assert!(body.contains(",\"active_connections\":1,"), "{}", body);
+        // The local backend's root counts as one handle
+        assert!(body.ends_with(",\"handles\":1,\"open_breakers\":[]}\n"), "{}", body);
+
+        // The export root going away makes the server unavailable
+        std::fs::remove_dir(&root).unwrap();
+        let response = fetch("/health").await;
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", response);
+        assert!(response.contains("{\"status\":\"unavailable\","), "{}", response);
+
+        // Exports failing fast are listed
+        for _ in 0..crate::rpc::breaker::BreakerConfig::default().threshold {
+            breakers.record(1, true);
+        }
+        assert!(fetch("/health").await.contains(",\"open_breakers\":[1]}"));
+
+        assert!(fetch("/metrics").await.starts_with("HTTP/1.1 404"));
+        server.abort();
+    }
+}
@@ -2,7 +2,11 @@
 //
 // This library provides the core components for building an NFSv3 server
 
+pub mod config;
+pub mod export;
 pub mod fsal;
+pub mod health;
+pub mod metrics;
 pub mod mount;
 pub mod nfs;
 pub mod portmap;
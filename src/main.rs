@@ -1,21 +1,17 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tracing_subscriber;
 
-mod fsal;
-mod mount;
-mod nfs;
-mod portmap;
-mod protocol;
-mod rpc;
-
-use fsal::BackendConfig;
-use protocol::v3::portmap::mapping;
+use arcticwolf::config::{Config, PortsConfig};
+use arcticwolf::export::ExportResolver;
+use arcticwolf::{mount, nfs, portmap};
+use arcticwolf::protocol::v3::portmap::mapping;
+use arcticwolf::rpc;
 
 /// Register all RPC services in the portmapper registry
 ///
 /// This makes services discoverable via PMAPPROC_GETPORT queries.
-fn register_services(registry: &portmap::Registry, port: u32) {
+fn register_services(registry: &portmap::Registry, ports: PortsConfig) {
     const IPPROTO_TCP: u32 = 6;
 
     println!("Registering services:");
@@ -25,40 +21,131 @@ fn register_services(registry: &portmap::Registry, port: u32) {
         prog: 100000,  // PORTMAP
         vers: 2,       // Version 2
         prot: IPPROTO_TCP,
-        port,
+        port: ports.portmap as u32,
     };
     registry.set(&portmap_tcp);
-    println!("  ✓ Portmapper v2 (TCP) on port {}", port);
-
-    // Register MOUNT protocol (program 100005)
+    println!("  ✓ Portmapper v2 (TCP) on port {}", ports.portmap);
+
+    // rpcbind v3/v4 are served by the same listener
+    for vers in [portmap::rpcbind::RPCBIND_V3, portmap::rpcbind::RPCBIND_V4] {
+        registry.set(&mapping {
+            prog: 100000,
+            vers,
+            prot: IPPROTO_TCP,
+            port: ports.portmap as u32,
+        });
+    }
+    println!("  ✓ rpcbind v3/v4 (TCP) on port {}", ports.portmap);
+
+    // Register MOUNT protocol (program 100005); only v3 is served, and calls
+    // for other versions are answered PROG_MISMATCH
     let mount_tcp = mapping {
-        prog: 100005,  // MOUNT
-        vers: 3,       // MOUNTv3
+        prog: mount::MOUNT_PROGRAM,
+        vers: mount::MOUNT_V3,
         prot: IPPROTO_TCP,
-        port,
+        port: ports.mount as u32,
     };
     registry.set(&mount_tcp);
-    println!("  ✓ MOUNT v3 (TCP) on port {}", port);
+    println!("  ✓ MOUNT v3 (TCP) on port {}", ports.mount);
 
-    // Register NFS protocol (program 100003)
+    // Register NFS protocol (program 100003); v2 and v4 are not registered,
+    // so clients asking the portmapper for them fall back to v3
     let nfs_tcp = mapping {
-        prog: 100003,  // NFS
-        vers: 3,       // NFSv3
+        prog: nfs::NFS_PROGRAM,
+        vers: nfs::NFS_V3,
         prot: IPPROTO_TCP,
-        port,
+        port: ports.nfs as u32,
     };
     registry.set(&nfs_tcp);
-    println!("  ✓ NFS v3 (TCP) on port {}", port);
+    println!("  ✓ NFS v3 (TCP) on port {}", ports.nfs);
 
     println!();
 }
 
+/// Command-line arguments
+struct Args {
+    /// Configuration file (`--config <path>`)
+    config_path: Option<PathBuf>,
+    /// Local directory to serve instead of the configured export (`--export <path>`)
+    export: Option<PathBuf>,
+    /// Port for every service instead of the configured ports (`--port <port>`)
+    port: Option<u16>,
+    /// Validate the configuration and exit without serving (`--dry-run`)
+    dry_run: bool,
+}
+
+const USAGE: &str = "usage: arcticwolf [--config <file>] [--export <path>] [--port <port>] [--dry-run]";
+
+/// Parse command-line arguments
+fn parse_args() -> Result<Args> {
+    let mut args = std::env::args().skip(1);
+    let mut parsed = Args {
+        config_path: None,
+        export: None,
+        port: None,
+        dry_run: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" | "-c" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--config requires a file path"))?;
+                parsed.config_path = Some(PathBuf::from(path));
+            }
+            "--export" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--export requires a directory path"))?;
+                parsed.export = Some(PathBuf::from(path));
+            }
+            "--port" => {
+                let port = args.next().ok_or_else(|| anyhow!("--port requires a port number"))?;
+                let port: u16 = port.parse().with_context(|| format!("Invalid port: {}", port))?;
+                if port == 0 {
+                    return Err(anyhow!("--port must be between 1 and 65535"));
+                }
+                parsed.port = Some(port);
+            }
+            "--dry-run" => parsed.dry_run = true,
+            _ => return Err(anyhow!("Unknown argument: {} ({})", arg, USAGE)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Load the configuration file (built-in defaults when none is given),
+/// apply the `--export` and `--port` overrides and validate the result
+fn load_config(args: &Args) -> Result<Config> {
+    let mut config = match &args.config_path {
+        Some(path) => Config::from_file(path)?,
+        None => Config::default(),
+    };
+    if let Some(path) = &args.export {
+        config = config.with_export(path);
+    }
+    if let Some(port) = args.port {
+        config = config.with_port(port);
+    }
+    config.validate()?;
+    Ok(config)
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
+    let args = parse_args()?;
+    if args.dry_run {
+        // Check the configuration, backend and ports, then exit
+        let config = load_config(&args)?;
+        config.check()?;
+        let paths: Vec<String> = config.exports.iter().map(|export| export.mount_path()).collect();
+        println!("Configuration OK: exports {} on {}", paths.join(", "), config.listen_addresses().join(", "));
+        return Ok(());
+    }
+
     println!("Arctic Wolf NFS Server");
     println!("======================");
     println!("Architecture:");
@@ -67,33 +154,64 @@ async fn main() -> Result<()> {
     println!("- Middleware: Type-safe serialization/deserialization");
     println!("- FSAL: File System Abstraction Layer");
     println!();
-    println!("Starting RPC server on 0.0.0.0:4000");
+
+    // Load configuration (built-in defaults when no file is given)
+    if let Some(path) = &args.config_path {
+        println!("Configuration: {}", path.display());
+    }
+    let config = load_config(&args)?;
+    let listen_addresses = config.listen_addresses();
+    println!("Starting RPC server on {}", listen_addresses.join(", "));
     println!();
 
     // Initialize FSAL (File System Abstraction Layer)
-    // Export /tmp/nfs_exports as the NFS export root
-    let export_path = std::path::PathBuf::from("/tmp/nfs_exports");
     println!("Initializing FSAL:");
-    println!("  Export path: {}", export_path.display());
-
-    let fsal_config = BackendConfig::local(&export_path);
-    let filesystem: Arc<dyn fsal::Filesystem> = Arc::from(fsal_config.create_filesystem()?);
 
-    let root_handle = filesystem.root_handle();
-    println!("  Root handle: {} bytes", root_handle.len());
+    // Serve each backend (or its subtree) as an export under its own path
+    let exports = Arc::new(config.export_table()?);
+    for export in &config.exports {
+        let export_id = exports.resolve_path(&export.mount_path()).unwrap_or_default();
+        let root_handle = exports.root_handle(export_id).unwrap_or_default();
+        println!("  Export path: {}", export.path.display());
+        println!("  Backend: {:?}{}", export.backend, if export.read_only { " (read-only)" } else { "" });
+        if let Some(subpath) = &export.subpath {
+            println!("  Subtree: {}", subpath);
+        }
+        println!("  Export id: {}", export_id);
+        println!("  Root handle: {} bytes", root_handle.len());
+    }
+    // Fix the write verifier for this boot before any WRITE/COMMIT is served
+    println!("  Write verifier: {:02x?}", arcticwolf::nfs::verifier::write_verifier());
     println!();
 
     // Create portmapper registry
     let registry = portmap::Registry::new();
 
-    // Register services in portmapper
-    // Note: Currently all services share port 4000
-    // In production, these would be on different ports (111, 2049, 20048)
-    register_services(&registry, 4000);
+    // Register services in portmapper at the ports they listen on
+    register_services(&registry, config.server.ports);
 
     // Create and run RPC server with filesystem
-    let server = rpc::server::RpcServer::new("0.0.0.0:4000".to_string(), registry, filesystem);
-    server.run().await?;
+    let mut server = rpc::server::RpcServer::with_exports(listen_addresses[0].clone(), registry, exports)
+        .with_addresses(listen_addresses)
+        .with_max_request_size(config.server.max_request_size)
+        .with_op_timeout(config.server.op_timeout())
+        .with_rate_limit(config.server.rate_limit())
+        .with_circuit_breaker(config.server.breaker())
+        .with_admin_clients(config.server.admin_clients()?);
+    if let Some(metrics_address) = config.server.metrics_address {
+        println!("Metrics: http://{}/metrics", metrics_address);
+        server = server.with_metrics_address(metrics_address);
+    }
+    if let Some(health_address) = config.server.health_address {
+        println!("Health: http://{}/health", health_address);
+        server = server.with_health_address(health_address);
+    }
+    // Stop cleanly on Ctrl-C: finish in-flight requests, then exit
+    server
+        .run_until(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
 
     Ok(())
 }
@@ -0,0 +1,342 @@
+// Metrics
+//
+// Request counters, latency histograms and gauges, rendered in the
+// Prometheus text exposition format and served over plain HTTP:
+//
+//   nfs_requests_total{proc="READ",status="OK"} 1
+//   nfs_request_duration_seconds_bucket{proc="READ",le="0.001"} 1
+//   arcticwolf_active_connections 2
+//   arcticwolf_file_handles 118
+//
+// Each program ("nfs", "mount", "portmap") gets its own metric families.
+// Statuses drop their protocol prefix, so NFS3_OK becomes "OK" and
+// NFS3ERR_NOENT becomes "NOENT"; RPC-level failures keep their names.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use crate::export::ExportResolver;
+
+/// Upper bounds (seconds) of the latency histogram buckets
+const LATENCY_BUCKETS: [f64; 14] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+/// Largest HTTP request head accepted by the metrics endpoint
+const MAX_HTTP_REQUEST: usize = 8192;
+
+/// Counter key: (program, procedure, status)
+type RequestKey = (&'static str, &'static str, String);
+
+/// Completed and failed calls of one procedure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcedureCount {
+    /// Program name ("nfs", "mount", "portmap")
+    pub program: &'static str,
+    /// Procedure name
+    pub procedure: &'static str,
+    /// Completed calls
+    pub calls: u64,
+    /// Calls answered with anything but OK or SUCCESS
+    pub errors: u64,
+}
+
+/// Latency distribution of one procedure
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket (not cumulative); the last slot is +Inf
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Server-wide metrics registry
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestKey, u64>>,
+    latencies: Mutex<BTreeMap<(&'static str, &'static str), Histogram>>,
+    active_connections: AtomicUsize,
+}
+
+/// Marks a connection as active until dropped
+pub struct ConnectionGuard {
+    metrics: Arc<Metrics>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.metrics.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a completed call and record its latency
+    ///
+    /// # Arguments
+    /// * `program` - Program name ("nfs", "mount", "portmap")
+    /// * `procedure` - Procedure name
+    /// * `status` - Outcome, e.g. "NFS3_OK" or "PROG_UNAVAIL"
+    /// * `elapsed` - Time spent handling the call
+    pub fn record_request(&self, program: &'static str, procedure: &'static str, status: &str, elapsed: Duration) {
+        let key = (program, procedure, status_label(status).to_string());
+        *self.requests.lock().unwrap().entry(key).or_insert(0) += 1;
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry((program, procedure))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Number of calls counted for a (program, procedure, status) triple
+    pub fn request_count(&self, program: &str, procedure: &str, status: &str) -> u64 {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|((p, proc_, s), _)| *p == program && *proc_ == procedure && s == status)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    /// Calls and failed calls per (program, procedure), ordered by program and procedure name
+    pub fn procedure_counts(&self) -> Vec<ProcedureCount> {
+        let mut counts: Vec<ProcedureCount> = Vec::new();
+        for ((program, procedure, status), count) in self.requests.lock().unwrap().iter() {
+            let errors = if status == "OK" || status == "SUCCESS" { 0 } else { *count };
+            match counts.last_mut() {
+                Some(last) if last.program == *program && last.procedure == *procedure => {
+                    last.calls += count;
+                    last.errors += errors;
+                }
+                _ => counts.push(ProcedureCount { program, procedure, calls: *count, errors }),
+            }
+        }
+        counts
+    }
+
+    /// Count a connection as active until the returned guard is dropped
+    pub fn connection_opened(self: &Arc<Self>) -> ConnectionGuard {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { metrics: Arc::clone(self) }
+    }
+
+    /// Number of currently open connections
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// Render all metrics in the Prometheus text format
+    ///
+    /// # Arguments
+    /// * `handle_count` - Current size of the backends' handle tables
+    pub fn render(&self, handle_count: usize) -> String {
+        let mut out = String::new();
+
+        let requests = self.requests.lock().unwrap();
+        let mut described = None;
+        for ((program, procedure, status), count) in requests.iter() {
+            if described != Some(*program) {
+                let _ = writeln!(out, "# HELP {}_requests_total Completed {} calls", program, program);
+                let _ = writeln!(out, "# TYPE {}_requests_total counter", program);
+                described = Some(*program);
+            }
+            let _ = writeln!(
+                out,
+                "{}_requests_total{{proc=\"{}\",status=\"{}\"}} {}",
+                program, procedure, status, count
+            );
+        }
+        drop(requests);
+
+        let latencies = self.latencies.lock().unwrap();
+        let mut described = None;
+        for ((program, procedure), histogram) in latencies.iter() {
+            let name = format!("{}_request_duration_seconds", program);
+            if described != Some(*program) {
+                let _ = writeln!(out, "# HELP {} Time spent handling {} calls", name, program);
+                let _ = writeln!(out, "# TYPE {} histogram", name);
+                described = Some(*program);
+            }
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "{}_bucket{{proc=\"{}\",le=\"{}\"}} {}", name, procedure, bound, cumulative);
+            }
+            let _ = writeln!(out, "{}_bucket{{proc=\"{}\",le=\"+Inf\"}} {}", name, procedure, histogram.count);
+            let _ = writeln!(out, "{}_sum{{proc=\"{}\"}} {}", name, procedure, histogram.sum);
+            let _ = writeln!(out, "{}_count{{proc=\"{}\"}} {}", name, procedure, histogram.count);
+        }
+        drop(latencies);
+
+        let _ = writeln!(out, "# HELP arcticwolf_active_connections Open client connections");
+        let _ = writeln!(out, "# TYPE arcticwolf_active_connections gauge");
+        let _ = writeln!(out, "arcticwolf_active_connections {}", self.active_connections());
+        let _ = writeln!(out, "# HELP arcticwolf_file_handles File handles tracked by the backends");
+        let _ = writeln!(out, "# TYPE arcticwolf_file_handles gauge");
+        let _ = writeln!(out, "arcticwolf_file_handles {}", handle_count);
+        out
+    }
+}
+
+/// Status label for a reply status: protocol prefixes removed
+fn status_label(status: &str) -> &str {
+    ["NFS3ERR_", "NFS3_", "MNT3ERR_", "MNT3_"]
+        .iter()
+        .find_map(|prefix| status.strip_prefix(prefix))
+        .unwrap_or(status)
+}
+
+/// Serve `GET /metrics` on a bound listener until the task is dropped
+///
+/// # Arguments
+/// * `listener` - Bound HTTP listener
+/// * `metrics` - Registry to render
+/// * `exports` - Exports whose handle tables are reported
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>, exports: Arc<dyn ExportResolver>) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("Metrics endpoint listening on http://{}/metrics", addr);
+    }
+    loop {
+        let Ok((stream, peer_addr)) = listener.accept().await else {
+            continue;
+        };
+        let metrics = Arc::clone(&metrics);
+        let exports = Arc::clone(&exports);
+        tokio::spawn(async move {
+            if let Err(e) = answer(stream, &metrics, exports.as_ref()).await {
+                debug!("Metrics request from {} failed: {}", peer_addr, e);
+            }
+        });
+    }
+}
+
+/// Answer one HTTP request and close the connection
+async fn answer(mut stream: TcpStream, metrics: &Metrics, exports: &dyn ExportResolver) -> std::io::Result<()> {
+    let (method, path) = read_request(&mut stream).await?;
+    let (status, body) = match (method.as_str(), path.as_str()) {
+        ("GET", "/metrics") => ("200 OK", metrics.render(exports.handle_count())),
+        ("GET", _) => ("404 Not Found", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Method not allowed\n".to_string()),
+    };
+    respond(stream, status, "text/plain; version=0.0.4", &body).await
+}
+
+/// Read an HTTP request head and return its method and path
+///
+/// Only the request line matters; the rest of the head is read and ignored.
+/// Missing parts come back empty.
+pub(crate) async fn read_request(stream: &mut TcpStream) -> std::io::Result<(String, String)> {
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_HTTP_REQUEST {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..read]);
+    }
+
+    let request_line = String::from_utf8_lossy(&request);
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    Ok((method, path))
+}
+
+/// Send an HTTP response and close the connection
+pub(crate) async fn respond(mut stream: TcpStream, status: &str, content_type: &str, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportTable;
+    use crate::fsal::MemoryFilesystem;
+
+    #[test]
+    fn test_render_counters_and_histograms() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_request("nfs", "READ", "NFS3_OK", Duration::from_micros(300));
+        metrics.record_request("nfs", "READ", "NFS3_OK", Duration::from_secs(10));
+        metrics.record_request("nfs", "LOOKUP", "NFS3ERR_NOENT", Duration::from_micros(50));
+        metrics.record_request("mount", "MNT", "MNT3_OK", Duration::from_micros(50));
+        let connection = metrics.connection_opened();
+
+        let text = metrics.render(7);
+        assert!(text.contains("nfs_requests_total{proc=\"READ\",status=\"OK\"} 2\n"), "{}", text);
+        assert!(text.contains("nfs_requests_total{proc=\"LOOKUP\",status=\"NOENT\"} 1\n"));
+        assert!(text.contains("mount_requests_total{proc=\"MNT\",status=\"OK\"} 1\n"));
+        assert!(text.contains("nfs_request_duration_seconds_bucket{proc=\"READ\",le=\"0.0005\"} 1\n"));
+        assert!(text.contains("nfs_request_duration_seconds_bucket{proc=\"READ\",le=\"5\"} 1\n"));
+        assert!(text.contains("nfs_request_duration_seconds_bucket{proc=\"READ\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("nfs_request_duration_seconds_count{proc=\"READ\"} 2\n"));
+        assert!(text.contains("arcticwolf_active_connections 1\n"));
+        assert!(text.contains("arcticwolf_file_handles 7\n"));
+        assert_eq!(text.matches("# TYPE nfs_requests_total counter").count(), 1);
+
+        drop(connection);
+        assert_eq!(metrics.active_connections(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_http_endpoint() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_request("nfs", "GETATTR", "NFS3_OK", Duration::from_micros(10));
+        let exports: Arc<dyn ExportResolver> = Arc::new(ExportTable::single("/", Arc::new(MemoryFilesystem::new())));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, metrics, exports));
+
+        let fetch = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = fetch("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("nfs_requests_total{proc=\"GETATTR\",status=\"OK\"} 1\n"));
+        // The memory backend's root counts as one handle
+        assert!(response.contains("arcticwolf_file_handles 1\n"));
+
+        assert!(fetch("/other").await.starts_with("HTTP/1.1 404"));
+        server.abort();
+    }
+}
@@ -3,43 +3,98 @@
 // Procedure: 1 (MNT)
 // Purpose: Mount a directory and return a file handle
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bytes::BytesMut;
-use tracing::{debug, info};
+use std::net::IpAddr;
+use tracing::{debug, info, warn};
 
-use crate::protocol::v3::mount::MountMessage;
+use crate::export::ExportResolver;
+use crate::mount::MountTable;
+use crate::protocol::v3::mount::{mountstat3, MountMessage, MNTPATHLEN};
 use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
+use crate::rpc::wire_log;
 
 /// Handle MOUNT MNT procedure
 ///
 /// This procedure takes a directory path and returns a file handle that can be used
 /// for subsequent NFS operations.
 ///
+/// The dirpath must name a configured export exactly. Errors:
+/// * MNT3ERR_NAMETOOLONG - dirpath longer than MNTPATHLEN
+/// * MNT3ERR_INVAL - empty or relative dirpath
+/// * MNT3ERR_NOENT - no export at that path
+/// * MNT3ERR_ACCES - client not covered by the export's client list
+/// * MNT3ERR_IO - the export's root cannot be read
+///
+/// A successful mount is recorded in `mounts` until the client's UMNT.
+/// Clients retransmit MNT, and remount after a reboot of either side, so
+/// the reply depends only on the export: each MNT of it returns the same
+/// root handle, and records the client's mount once.
+///
 /// Arguments: dirpath (string)
 /// Returns: mountres3 (file handle + auth flavors on success)
 pub fn handle(
     call: &rpc_call_msg,
     args_data: &[u8],
-    filesystem: &dyn crate::fsal::Filesystem,
+    exports: &dyn ExportResolver,
+    mounts: &MountTable,
+    client: IpAddr,
 ) -> Result<BytesMut> {
     debug!(
         "MOUNT MNT: xid={}, prog={}, vers={}, proc={}",
         call.xid, call.prog, call.vers, call.proc_
     );
 
-    debug!("MOUNT MNT: args_data = {} bytes, hex: {:02x?}",
-           args_data.len(), &args_data[..args_data.len().min(50)]);
+    wire_log::dump("MNT args", args_data);
+
+    // Check the declared length first: the XDR decoder rejects over-long
+    // strings without saying why
+    let declared_len = args_data
+        .get(..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as i64);
+    if declared_len.is_some_and(|len| len > MNTPATHLEN) {
+        warn!("MOUNT MNT dirpath too long ({:?} bytes)", declared_len);
+        return error_reply(call.xid, mountstat3::MNT3ERR_NAMETOOLONG);
+    }
 
     // Deserialize the directory path from the arguments
     let dirpath = MountMessage::deserialize_dirpath(args_data)?;
 
     info!("MOUNT MNT request for path: '{}'", dirpath);
 
-    // For root path "/" or empty, return the root file handle
-    // In a production NFS server, we would validate export permissions here
-    // For now, accept any path and return root handle (temporary workaround for path parsing issue)
-    let fhandle_bytes = filesystem.root_handle();
+    if !dirpath.starts_with('/') {
+        warn!("MOUNT MNT dirpath '{}' is not absolute", dirpath);
+        return error_reply(call.xid, mountstat3::MNT3ERR_INVAL);
+    }
+
+    // Resolve the dirpath to an export and return that export's root handle
+    let Some(export_id) = exports.resolve_path(&dirpath) else {
+        warn!("MOUNT MNT: no export matches '{}'", dirpath);
+        return error_reply(call.xid, mountstat3::MNT3ERR_NOENT);
+    };
+
+    let options = exports.options(export_id);
+    if !options.allows(client) {
+        warn!("MOUNT MNT of '{}' denied for client {}", dirpath, client);
+        return error_reply(call.xid, mountstat3::MNT3ERR_ACCESS);
+    }
 
+    let fhandle_bytes = exports
+        .root_handle(export_id)
+        .ok_or_else(|| anyhow!("Export {} has no backend", export_id))?;
+
+    // Hand out only a root the NFS layer can actually resolve
+    let (_, backend, root) = exports
+        .resolve_handle(&fhandle_bytes)
+        .ok_or_else(|| anyhow!("Export {} has no backend", export_id))?;
+    if let Err(e) = backend.getattr(&root.to_vec()) {
+        warn!("MOUNT MNT: root of export '{}' is not accessible: {}", dirpath, e);
+        return error_reply(call.xid, mountstat3::MNT3ERR_IO);
+    }
+
+    if !mounts.add(client, export_id) {
+        debug!("MOUNT MNT: {} already has '{}' mounted", client, dirpath);
+    }
     info!(
         "Generated file handle ({} bytes) for path '{}'",
         fhandle_bytes.len(),
@@ -47,7 +102,7 @@ pub fn handle(
     );
 
     // Create successful mount response
-    let mount_res = MountMessage::create_mount_ok(fhandle_bytes.clone());
+    let mount_res = MountMessage::create_mount_ok(fhandle_bytes.clone(), options.auth_flavors);
 
     debug!("MOUNT MNT: Created mountres3 with {} byte handle", fhandle_bytes.len());
 
@@ -58,8 +113,7 @@ pub fn handle(
     // Serialize MOUNT result
     let mount_data = MountMessage::serialize_mountres3(&mount_res)?;
 
-    debug!("MOUNT MNT: Serialized mount_data = {} bytes, hex: {:02x?}",
-           mount_data.len(), &mount_data[..mount_data.len().min(100)]);
+    wire_log::dump("MNT result", &mount_data);
 
     // Combine RPC header + MOUNT result
     // RPC wire format: [RPC Reply Header][Procedure Result Data]
@@ -72,3 +126,172 @@ pub fn handle(
     Ok(response)
 }
 
+/// Build an RPC reply carrying a MOUNT error status
+fn error_reply(xid: u32, status: mountstat3) -> Result<BytesMut> {
+    let rpc_reply = RpcMessage::create_null_reply(xid);
+    let rpc_header = RpcMessage::serialize_reply(&rpc_reply)?;
+    let mount_data = MountMessage::serialize_mount_error(status)?;
+
+    let mut response = BytesMut::with_capacity(rpc_header.len() + mount_data.len());
+    response.extend_from_slice(&rpc_header);
+    response.extend_from_slice(&mount_data);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{ExportOptions, ExportTable};
+    use crate::fsal::BackendConfig;
+    use crate::protocol::v3::rpc::{auth_flavor, msg_type, opaque_auth};
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn mnt_call() -> rpc_call_msg {
+        rpc_call_msg {
+            xid: 1,
+            mtype: msg_type::CALL,
+            rpcvers: 2,
+            prog: 100005,
+            vers: 3,
+            proc_: 1,
+            cred: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+            verf: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+        }
+    }
+
+    fn mount(exports: &ExportTable, path: &str) -> BytesMut {
+        mount_in(exports, &MountTable::new(), path)
+    }
+
+    fn mount_in(exports: &ExportTable, mounts: &MountTable, path: &str) -> BytesMut {
+        let mut args = Vec::new();
+        xdr_codec::pack_string(path, None, &mut args).unwrap();
+        handle(&mnt_call(), &args, exports, mounts, IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap()
+    }
+
+    fn root_of(reply: &[u8]) -> Vec<u8> {
+        let len = u32::from_be_bytes([reply[28], reply[29], reply[30], reply[31]]) as usize;
+        reply[32..32 + len].to_vec()
+    }
+
+    fn status(reply: &[u8]) -> i32 {
+        i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+    }
+
+    #[test]
+    fn test_mnt_export_handle_resolves() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let exports = ExportTable::single("/srv/data", Arc::from(fs));
+
+        let reply = mount(&exports, "/srv/data/");
+        assert_eq!(status(&reply), mountstat3::MNT3_OK as i32);
+
+        // The handle is the export's real root and GETATTR accepts it
+        let len = u32::from_be_bytes([reply[28], reply[29], reply[30], reply[31]]) as usize;
+        let handle = &reply[32..32 + len];
+        assert_eq!(Some(handle.to_vec()), exports.root_handle(exports.resolve_path("/srv/data").unwrap()));
+        let (_, backend, backend_handle) = exports.resolve_handle(handle).unwrap();
+        assert!(backend.getattr(&backend_handle.to_vec()).is_ok());
+    }
+
+    #[test]
+    fn test_mnt_retransmit_returns_same_handle() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let exports = ExportTable::single("/srv/data", Arc::from(fs));
+        let mounts = MountTable::new();
+
+        let first = mount_in(&exports, &mounts, "/srv/data");
+        let second = mount_in(&exports, &mounts, "/srv/data");
+        assert_eq!(status(&first), mountstat3::MNT3_OK as i32);
+        assert_eq!(status(&second), mountstat3::MNT3_OK as i32);
+        assert_eq!(root_of(&first), root_of(&second));
+        assert_eq!(mounts.len(), 1, "A retransmitted MNT is recorded once");
+
+        // A server restart serves the export under the same handle
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let restarted = ExportTable::single("/srv/data", Arc::from(fs));
+        assert_eq!(root_of(&mount(&restarted, "/srv/data")), root_of(&first));
+    }
+
+    #[test]
+    fn test_mnt_subtree_export_root() {
+        use crate::export::ExportRouter;
+        use crate::fsal::Filesystem;
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("project/src")).unwrap();
+        std::fs::write(temp_dir.path().join("project/inside.txt"), b"in").unwrap();
+        std::fs::write(temp_dir.path().join("secret.txt"), b"out").unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let mut exports = ExportTable::new();
+        exports
+            .add_subtree("/data/project", Arc::from(fs), "project", ExportOptions::default())
+            .unwrap();
+
+        let reply = mount(&exports, "/data/project");
+        assert_eq!(status(&reply), mountstat3::MNT3_OK as i32);
+        let len = u32::from_be_bytes([reply[28], reply[29], reply[30], reply[31]]) as usize;
+        let root = reply[32..32 + len].to_vec();
+
+        // The client's root is the subdirectory, not the backend root
+        let exports: Arc<dyn ExportResolver> = Arc::new(exports);
+        let router = ExportRouter::new(exports);
+        let project_ino = std::fs::metadata(temp_dir.path().join("project")).unwrap().ino();
+        assert_eq!(router.getattr(&root).unwrap().fileid, project_ino);
+        assert!(router.lookup(&root, "inside.txt").is_ok());
+        assert!(router.lookup(&root, "src").is_ok());
+        assert!(router.lookup(&root, "secret.txt").is_err());
+        assert!(router.lookup(&root, "..").is_err());
+    }
+
+    #[test]
+    fn test_mnt_advertises_auth_flavors() {
+        use crate::export::ExportOptions;
+        use crate::protocol::v3::mount::mountres3;
+        use crate::protocol::v3::rpc::auth_flavor;
+        use std::io::Cursor;
+        use xdr_codec::Unpack;
+
+        let flavors = |reply: &[u8]| match mountres3::unpack(&mut Cursor::new(&reply[24..])).unwrap().0 {
+            mountres3::MNT3_OK(ok) => ok.auth_flavors,
+            _ => panic!("MNT should succeed"),
+        };
+
+        let mut exports = ExportTable::new();
+        exports.add("/default", Arc::from(BackendConfig::memory().create_filesystem().unwrap()));
+        exports.add_with_options(
+            "/sys-only",
+            Arc::from(BackendConfig::memory().create_filesystem().unwrap()),
+            ExportOptions {
+                auth_flavors: vec![auth_flavor::AUTH_SYS as i32],
+                ..ExportOptions::default()
+            },
+        );
+
+        let advertised = flavors(&mount(&exports, "/default"));
+        assert!(advertised.contains(&(auth_flavor::AUTH_SYS as i32)));
+        assert!(advertised.contains(&(auth_flavor::AUTH_NONE as i32)));
+
+        assert_eq!(flavors(&mount(&exports, "/sys-only")), vec![auth_flavor::AUTH_SYS as i32]);
+    }
+
+    #[test]
+    fn test_mnt_rejects_bad_paths() {
+        let exports = ExportTable::single("/srv/data", Arc::from(BackendConfig::memory().create_filesystem().unwrap()));
+
+        let reply = mount(&exports, "/anything");
+        assert_eq!(status(&reply), mountstat3::MNT3ERR_NOENT as i32);
+        assert_eq!(reply.len(), 28);
+
+        assert_eq!(status(&mount(&exports, "")), mountstat3::MNT3ERR_INVAL as i32);
+        assert_eq!(status(&mount(&exports, "srv/data")), mountstat3::MNT3ERR_INVAL as i32);
+
+        let long = format!("/{}", "p".repeat(MNTPATHLEN as usize));
+        assert_eq!(status(&mount(&exports, &long)), mountstat3::MNT3ERR_NAMETOOLONG as i32);
+    }
+}
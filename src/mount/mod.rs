@@ -9,13 +9,19 @@
 
 pub mod mnt;
 pub mod null;
+pub mod table;
 pub mod umnt;
 
+pub use table::MountTable;
+
 use anyhow::{anyhow, Result};
 use bytes::BytesMut;
+use std::net::IpAddr;
 use tracing::{debug, warn};
 
-use crate::protocol::v3::rpc::rpc_call_msg;
+use crate::export::ExportResolver;
+use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
+use crate::rpc::drc::DuplicateRequestCache;
 
 /// MOUNT program number (RFC 1813)
 pub const MOUNT_PROGRAM: u32 = 100005;
@@ -37,10 +43,24 @@ pub mod procedures {
 ///
 /// This function routes the RPC call to the correct MOUNT procedure handler
 /// based on the procedure number.
+///
+/// # Arguments
+/// * `call` - Parsed RPC call message
+/// * `args_data` - Procedure arguments data
+/// * `exports` - Export resolver
+/// * `mounts` - Mount table, updated by MNT and UMNT
+/// * `drc` - Duplicate request cache, cleared of a client's replies by its last UMNT
+/// * `client` - Address of the calling host, checked against export client lists
+///
+/// # Returns
+/// Serialized RPC reply message
 pub fn handle_mount_call(
     call: &rpc_call_msg,
     args_data: &[u8],
-    filesystem: &dyn crate::fsal::Filesystem,
+    exports: &dyn ExportResolver,
+    mounts: &MountTable,
+    drc: &DuplicateRequestCache,
+    client: IpAddr,
 ) -> Result<BytesMut> {
     debug!(
         "Dispatching MOUNT call: proc={}, prog={}, vers={}",
@@ -62,12 +82,8 @@ pub fn handle_mount_call(
 
     // Verify version 3
     if call.vers != MOUNT_V3 {
-        warn!("Expected MOUNT version {}, got {}", MOUNT_V3, call.vers);
-        return Err(anyhow!(
-            "Unsupported MOUNT version: expected {}, got {}",
-            MOUNT_V3,
-            call.vers
-        ));
+        debug!("MOUNT version {} not supported, answering PROG_MISMATCH", call.vers);
+        return RpcMessage::create_prog_mismatch_reply(call.xid, MOUNT_V3, MOUNT_V3);
     }
 
     // Dispatch to handler based on procedure number
@@ -78,11 +94,11 @@ pub fn handle_mount_call(
         }
         procedures::MNT => {
             debug!("Routing to MOUNT MNT handler");
-            mnt::handle(call, args_data, filesystem)
+            mnt::handle(call, args_data, exports, mounts, client)
         }
         procedures::UMNT => {
             debug!("Routing to MOUNT UMNT handler");
-            umnt::handle(call, args_data)
+            umnt::handle(call, args_data, exports, mounts, drc, client)
         }
         procedures::DUMP => {
             warn!("MOUNT DUMP not yet implemented");
@@ -7,7 +7,7 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::debug;
 
-use crate::protocol::v3::rpc::{rpc_call_msg, rpc_reply_msg, RpcMessage};
+use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
 
 /// Handle MOUNT NULL procedure
 ///
@@ -19,9 +19,32 @@ pub fn handle(call: &rpc_call_msg) -> Result<BytesMut> {
         call.xid, call.prog, call.vers, call.proc_
     );
 
-    // Create successful reply (same as RPC NULL)
-    let reply = RpcMessage::create_null_reply(call.xid);
+    // Same void reply as NFS and PORTMAP NULL
+    RpcMessage::create_void_reply(call.xid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::v3::rpc::{auth_flavor, msg_type, opaque_auth};
+
+    #[test]
+    fn test_null_reply_is_void_success() {
+        let call = rpc_call_msg {
+            xid: 7,
+            mtype: msg_type::CALL,
+            rpcvers: 2,
+            prog: 100005,
+            vers: 3,
+            proc_: 0,
+            cred: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+            verf: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+        };
 
-    // Serialize reply
-    RpcMessage::serialize_reply(&reply)
+        let reply = handle(&call).unwrap();
+        assert_eq!(&reply[0..4], &7u32.to_be_bytes());
+        // Accepted, SUCCESS, and no result bytes at all
+        assert_eq!(RpcMessage::success_reply_results(&reply), Some(&[][..]));
+        assert_eq!(reply, RpcMessage::create_void_reply(7).unwrap());
+    }
 }
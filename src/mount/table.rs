@@ -0,0 +1,104 @@
+// Mount Table
+//
+// Records which clients have mounted which exports: MNT adds an entry and
+// UMNT removes it. When a client's last mount goes away, or an export's last
+// client, UMNT releases the state kept for them, so clients that come and go
+// (e.g. pods rescheduled across nodes) do not leave cached state behind.
+
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::export::ExportId;
+
+/// Client → exports it has mounted
+///
+/// Thread-safe and cheap to clone (shared across connections).
+#[derive(Clone, Default)]
+pub struct MountTable {
+    mounts: Arc<Mutex<HashMap<IpAddr, BTreeSet<ExportId>>>>,
+}
+
+impl MountTable {
+    /// Create an empty table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `client` mounted an export
+    ///
+    /// Mounting an export the client already has mounted (e.g. a
+    /// retransmitted MNT) leaves the table unchanged.
+    ///
+    /// # Returns
+    /// Whether the mount is new
+    pub fn add(&self, client: IpAddr, export_id: ExportId) -> bool {
+        self.mounts.lock().unwrap().entry(client).or_default().insert(export_id)
+    }
+
+    /// Forget that `client` mounted an export
+    ///
+    /// # Returns
+    /// Whether the client had it mounted
+    pub fn remove(&self, client: IpAddr, export_id: ExportId) -> bool {
+        let mut mounts = self.mounts.lock().unwrap();
+        let Some(exports) = mounts.get_mut(&client) else {
+            return false;
+        };
+        let removed = exports.remove(&export_id);
+        if exports.is_empty() {
+            mounts.remove(&client);
+        }
+        removed
+    }
+
+    /// Whether `client` has any export mounted
+    pub fn has_client(&self, client: IpAddr) -> bool {
+        self.mounts.lock().unwrap().contains_key(&client)
+    }
+
+    /// Whether any client has an export mounted
+    pub fn is_mounted(&self, export_id: ExportId) -> bool {
+        self.mounts.lock().unwrap().values().any(|exports| exports.contains(&export_id))
+    }
+
+    /// Number of (client, export) mounts
+    pub fn len(&self) -> usize {
+        self.mounts.lock().unwrap().values().map(BTreeSet::len).sum()
+    }
+
+    /// Check if no client has anything mounted
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    #[test]
+    fn test_add_and_remove() {
+        let table = MountTable::new();
+        assert!(table.add(CLIENT, 1));
+        assert!(!table.add(CLIENT, 1), "Already mounted");
+        assert!(table.add(CLIENT, 2));
+        assert!(table.add(OTHER, 1));
+        assert_eq!(table.len(), 3);
+
+        assert!(table.remove(CLIENT, 2));
+        assert!(!table.remove(CLIENT, 2), "Already unmounted");
+        assert!(!table.is_mounted(2));
+        assert!(table.has_client(CLIENT));
+
+        assert!(table.remove(CLIENT, 1));
+        assert!(!table.has_client(CLIENT));
+        assert!(table.is_mounted(1), "Still mounted by the other client");
+        assert!(table.remove(OTHER, 1));
+        assert!(table.is_empty());
+    }
+}
@@ -5,19 +5,34 @@
 
 use anyhow::Result;
 use bytes::BytesMut;
+use std::net::IpAddr;
 use tracing::{debug, info};
 
+use crate::export::{ExportId, ExportResolver};
+use crate::mount::MountTable;
 use crate::protocol::v3::mount::MountMessage;
 use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
+use crate::rpc::drc::DuplicateRequestCache;
 
 /// Handle MOUNT UMNT procedure
 ///
 /// This procedure unmounts a previously mounted directory path.
 /// It takes a directory path as argument and returns void (just RPC success).
 ///
+/// The mount is removed from `mounts`. A client's last UMNT drops its
+/// cached replies from the DRC, and an export's last UMNT closes the files
+/// its backend keeps open.
+///
 /// Arguments: dirpath (string)
 /// Returns: void (RPC success reply only)
-pub fn handle(call: &rpc_call_msg, args_data: &[u8]) -> Result<BytesMut> {
+pub fn handle(
+    call: &rpc_call_msg,
+    args_data: &[u8],
+    exports: &dyn ExportResolver,
+    mounts: &MountTable,
+    drc: &DuplicateRequestCache,
+    client: IpAddr,
+) -> Result<BytesMut> {
     debug!(
         "MOUNT UMNT: xid={}, prog={}, vers={}, proc={}",
         call.xid, call.prog, call.vers, call.proc_
@@ -29,19 +44,111 @@ pub fn handle(call: &rpc_call_msg, args_data: &[u8]) -> Result<BytesMut> {
         Err(e) => {
             info!("Failed to deserialize dirpath: {}", e);
             // Even on error, return success (UMNT is idempotent)
-            let reply = RpcMessage::create_null_reply(call.xid);
-            return RpcMessage::serialize_reply(&reply);
+            return RpcMessage::create_void_reply(call.xid);
         }
     };
 
     info!("MOUNT UMNT request for path: '{}'", dirpath);
 
-    // TODO: Remove the mount entry from internal state
-    // For now, we just acknowledge the unmount request
-
-    info!("Unmounted path '{}'", dirpath);
+    match exports.resolve_path(&dirpath) {
+        Some(export_id) if mounts.remove(client, export_id) => {
+            release_state(exports, mounts, drc, client, export_id);
+            info!("Unmounted path '{}' for {}", dirpath, client);
+        }
+        _ => debug!("UMNT of '{}' by {}, which had not mounted it", dirpath, client),
+    }
 
     // Return simple success reply (void result)
-    let reply = RpcMessage::create_null_reply(call.xid);
-    RpcMessage::serialize_reply(&reply)
+    RpcMessage::create_void_reply(call.xid)
+}
+
+/// Release state no longer needed once `client` unmounted `export_id`
+fn release_state(
+    exports: &dyn ExportResolver,
+    mounts: &MountTable,
+    drc: &DuplicateRequestCache,
+    client: IpAddr,
+    export_id: ExportId,
+) {
+    if !mounts.has_client(client) {
+        let forgotten = drc.forget_client(client);
+        debug!("Dropped {} cached replies of {}", forgotten, client);
+    }
+    if !mounts.is_mounted(export_id)
+        && let Some(backend) = exports.backend(export_id)
+    {
+        backend.release_cached_files();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportTable;
+    use crate::fsal::{Filesystem, LocalFilesystem, StableHow};
+    use crate::mount::mnt;
+    use crate::protocol::v3::rpc::{auth_flavor, msg_type, opaque_auth};
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    fn call(proc_: u32) -> rpc_call_msg {
+        rpc_call_msg {
+            xid: 1,
+            mtype: msg_type::CALL,
+            rpcvers: 2,
+            prog: 100005,
+            vers: 3,
+            proc_,
+            cred: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+            verf: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+        }
+    }
+
+    fn dirpath(path: &str) -> Vec<u8> {
+        let mut args = Vec::new();
+        xdr_codec::pack_string(path, None, &mut args).unwrap();
+        args
+    }
+
+    #[test]
+    fn test_last_umnt_releases_cached_files_and_replies() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap());
+        let exports = ExportTable::single("/data", fs.clone());
+        let mounts = MountTable::new();
+        let drc = DuplicateRequestCache::default();
+
+        for client in [CLIENT, OTHER] {
+            mnt::handle(&call(1), &dirpath("/data"), &exports, &mounts, client).unwrap();
+        }
+        assert_eq!(mounts.len(), 2);
+
+        // Each client writes a file, leaving a descriptor in the cache and a
+        // reply in the DRC
+        for (client, name) in [(CLIENT, "a"), (OTHER, "b")] {
+            let file = fs.create(&fs.root_handle(), name, 0o644).unwrap();
+            fs.write(&file, 0, b"data", StableHow::Unstable).unwrap();
+            drc.insert(client, 7, 8, BytesMut::from(&b"reply"[..]));
+        }
+        assert_eq!(fs.cached_file_count(), 2);
+
+        // The export stays mounted by the other client: its files stay open
+        handle(&call(3), &dirpath("/data"), &exports, &mounts, &drc, CLIENT).unwrap();
+        assert!(drc.get(CLIENT, 7, 8).is_none(), "The client's replies are dropped");
+        assert!(drc.get(OTHER, 7, 8).is_some());
+        assert_eq!(fs.cached_file_count(), 2);
+
+        // A repeated UMNT is harmless
+        handle(&call(3), &dirpath("/data"), &exports, &mounts, &drc, CLIENT).unwrap();
+        assert_eq!(fs.cached_file_count(), 2);
+
+        handle(&call(3), &dirpath("/data"), &exports, &mounts, &drc, OTHER).unwrap();
+        assert!(mounts.is_empty());
+        assert_eq!(fs.cached_file_count(), 0, "The last UMNT closes the export's files");
+        assert!(drc.is_empty());
+    }
 }
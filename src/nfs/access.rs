@@ -6,9 +6,11 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::debug;
 
-use crate::fsal::Filesystem;
+use crate::fsal::{Acl, FileAttributes, FileType, Filesystem};
+use crate::nfs::error::handle_error_status;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
+use crate::rpc::auth::Credentials;
 
 // Access mode bits (from RFC 1813)
 const ACCESS3_READ: u32 = 0x0001;
@@ -26,6 +28,7 @@ const ACCESS3_EXECUTE: u32 = 0x0020;
 /// * `xid` - Transaction ID from the request
 /// * `args_data` - Serialized ACCESS3args (file handle + access bits)
 /// * `filesystem` - Filesystem instance
+/// * `credentials` - Caller identity the permissions are evaluated for
 ///
 /// # Returns
 /// Serialized RPC reply message with granted access rights
@@ -33,6 +36,7 @@ pub fn handle_access(
     xid: u32,
     args_data: &[u8],
     filesystem: &dyn Filesystem,
+    credentials: &Credentials,
 ) -> Result<BytesMut> {
     debug!("NFS ACCESS called (xid={})", xid);
 
@@ -51,9 +55,9 @@ pub fn handle_access(
         Err(e) => {
             debug!("ACCESS failed: {}", e);
             // Return appropriate NFS error
-            let error_status = if e.to_string().contains("not found")
-                || e.to_string().contains("Invalid handle")
-            {
+            let error_status = if let Some(status) = handle_error_status(&e) {
+                status
+            } else if e.to_string().contains("not found") {
                 nfsstat3::NFS3ERR_STALE
             } else {
                 nfsstat3::NFS3ERR_IO
@@ -69,34 +73,18 @@ pub fn handle_access(
         }
     };
 
-    // For simplicity, grant all requested permissions
-    // In a production implementation, this would check actual file permissions
-    // against the user's UID/GID from the RPC credentials
-    let mut granted_access = 0u32;
+    // An ACL, where the backend has one, refines the mode bits. Root is
+    // decided by the mode bits alone, so its checks skip the ACL lookup.
+    let acl = if credentials.uid == 0 {
+        None
+    } else {
+        filesystem.get_acl(&args.object.0).unwrap_or_else(|e| {
+            debug!("ACCESS: could not read ACL, using mode bits: {}", e);
+            None
+        })
+    };
 
-    // Check each requested access bit
-    if args.access & ACCESS3_READ != 0 {
-        granted_access |= ACCESS3_READ;
-    }
-    if args.access & ACCESS3_LOOKUP != 0 {
-        // LOOKUP is only valid for directories
-        use crate::fsal::FileType;
-        if file_attrs.ftype == FileType::Directory {
-            granted_access |= ACCESS3_LOOKUP;
-        }
-    }
-    if args.access & ACCESS3_MODIFY != 0 {
-        granted_access |= ACCESS3_MODIFY;
-    }
-    if args.access & ACCESS3_EXTEND != 0 {
-        granted_access |= ACCESS3_EXTEND;
-    }
-    if args.access & ACCESS3_DELETE != 0 {
-        granted_access |= ACCESS3_DELETE;
-    }
-    if args.access & ACCESS3_EXECUTE != 0 {
-        granted_access |= ACCESS3_EXECUTE;
-    }
+    let granted_access = granted_access(&file_attrs, acl.as_ref(), credentials, args.access);
 
     debug!(
         "ACCESS success: requested={:#06x}, granted={:#06x}",
@@ -127,10 +115,57 @@ pub fn handle_access(
     RpcMessage::create_success_reply_with_data(xid, res_data)
 }
 
+/// Compute the subset of `requested` access bits granted to `credentials`
+///
+/// With an ACL, the rwx bits come from the POSIX ACL check; otherwise from
+/// the owner, group or other bits of the file mode, whichever class the
+/// caller falls in. Root is granted everything except EXECUTE on files with
+/// no execute bit set.
+fn granted_access(attrs: &FileAttributes, acl: Option<&Acl>, credentials: &Credentials, requested: u32) -> u32 {
+    let is_dir = attrs.ftype == FileType::Directory;
+
+    let (can_read, can_write, can_exec) = if credentials.uid == 0 {
+        (true, true, is_dir || attrs.mode & 0o111 != 0)
+    } else {
+        let class_bits = if let Some(acl) = acl {
+            acl.permissions(credentials.uid, |gid| credentials.in_group(gid), attrs.uid, attrs.gid)
+        } else if credentials.uid == attrs.uid {
+            (attrs.mode >> 6) & 0o7
+        } else if credentials.in_group(attrs.gid) {
+            (attrs.mode >> 3) & 0o7
+        } else {
+            attrs.mode & 0o7
+        };
+        (class_bits & 0o4 != 0, class_bits & 0o2 != 0, class_bits & 0o1 != 0)
+    };
+
+    let mut granted = 0u32;
+    if can_read {
+        granted |= ACCESS3_READ;
+    }
+    if can_write {
+        granted |= ACCESS3_MODIFY | ACCESS3_EXTEND;
+    }
+    if is_dir {
+        // LOOKUP is only valid for directories; DELETE removes entries
+        // from the directory, which needs write (and search) permission on it
+        if can_exec {
+            granted |= ACCESS3_LOOKUP;
+        }
+        if can_write && can_exec {
+            granted |= ACCESS3_DELETE;
+        }
+    } else if can_exec {
+        granted |= ACCESS3_EXECUTE;
+    }
+
+    granted & requested
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::fsal::{BackendConfig, Filesystem};
+    use crate::fsal::BackendConfig;
     use std::fs;
     use tempfile::TempDir;
 
@@ -161,7 +196,7 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call ACCESS
-        let result = handle_access(12345, &args_buf, fs.as_ref());
+        let result = handle_access(12345, &args_buf, fs.as_ref(), &Credentials::anonymous());
 
         assert!(result.is_ok(), "ACCESS should succeed for existing file");
 
@@ -192,7 +227,7 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call ACCESS
-        let result = handle_access(12345, &args_buf, fs.as_ref());
+        let result = handle_access(12345, &args_buf, fs.as_ref(), &Credentials::anonymous());
 
         assert!(result.is_ok(), "ACCESS should succeed for directory");
     }
@@ -217,8 +252,123 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call ACCESS
-        let result = handle_access(12345, &args_buf, fs.as_ref());
+        let result = handle_access(12345, &args_buf, fs.as_ref(), &Credentials::anonymous());
 
         assert!(result.is_ok(), "ACCESS should return error response (not panic)");
     }
+
+    /// Credentials of an AUTH_SYS caller
+    fn sys_credentials(uid: u32, gid: u32) -> Credentials {
+        Credentials {
+            flavor: crate::protocol::v3::rpc::auth_flavor::AUTH_SYS,
+            uid,
+            gid,
+            gids: vec![],
+            machine_name: "client".to_string(),
+        }
+    }
+
+    /// Run ACCESS and return the granted bits (last word of the reply)
+    fn access_for(fs: &dyn Filesystem, handle: &[u8], access: u32, credentials: &Credentials) -> u32 {
+        use crate::protocol::v3::nfs::{fhandle3, ACCESS3args};
+        use xdr_codec::Pack;
+
+        let args = ACCESS3args {
+            object: fhandle3(handle.to_vec()),
+            access,
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        let reply = handle_access(1, &args_buf, fs, credentials).unwrap();
+        let at = reply.len() - 4;
+        u32::from_be_bytes([reply[at], reply[at + 1], reply[at + 2], reply[at + 3]])
+    }
+
+    #[test]
+    fn test_access_evaluates_mode_for_caller() {
+        let fs = BackendConfig::memory().create_filesystem().unwrap();
+        let root_handle = fs.root_handle();
+        let file_handle = fs.create(&root_handle, "owned.txt", 0o644).unwrap();
+        fs.setattr_owner(&file_handle, Some(1000), Some(1000)).unwrap();
+
+        let requested = ACCESS3_READ | ACCESS3_MODIFY | ACCESS3_EXTEND | ACCESS3_EXECUTE;
+
+        // Another uid only gets the "other" read bit
+        let other = access_for(fs.as_ref(), &file_handle, requested, &sys_credentials(2000, 2000));
+        assert_eq!(other, ACCESS3_READ, "MODIFY must not be granted to a non-owner");
+
+        // Group member gets the group read bit
+        let group = access_for(fs.as_ref(), &file_handle, requested, &sys_credentials(2000, 1000));
+        assert_eq!(group, ACCESS3_READ);
+
+        // Owner can read and write, but not execute
+        let owner = access_for(fs.as_ref(), &file_handle, requested, &sys_credentials(1000, 1000));
+        assert_eq!(owner, ACCESS3_READ | ACCESS3_MODIFY | ACCESS3_EXTEND);
+
+        // Root can write anyway
+        let root = access_for(fs.as_ref(), &file_handle, requested, &sys_credentials(0, 0));
+        assert_eq!(root, ACCESS3_READ | ACCESS3_MODIFY | ACCESS3_EXTEND);
+    }
+
+    #[test]
+    fn test_access_directory_delete_needs_write() {
+        let fs = BackendConfig::memory().create_filesystem().unwrap();
+        let root_handle = fs.root_handle();
+        let dir_handle = fs.mkdir(&root_handle, "shared", 0o755).unwrap();
+        fs.setattr_owner(&dir_handle, Some(1000), Some(1000)).unwrap();
+
+        let requested = ACCESS3_LOOKUP | ACCESS3_DELETE | ACCESS3_EXECUTE;
+
+        let other = access_for(fs.as_ref(), &dir_handle, requested, &sys_credentials(2000, 2000));
+        assert_eq!(other, ACCESS3_LOOKUP);
+
+        let owner = access_for(fs.as_ref(), &dir_handle, requested, &sys_credentials(1000, 1000));
+        assert_eq!(owner, ACCESS3_LOOKUP | ACCESS3_DELETE);
+    }
+
+    #[test]
+    fn test_access_honours_acl() {
+        use crate::fsal::acl::{AclTag, ACL_READ};
+
+        let temp_dir = TempDir::new().unwrap();
+        for config in [BackendConfig::local(temp_dir.path()), BackendConfig::memory()] {
+            let fs = config.create_filesystem().unwrap();
+            let root_handle = fs.root_handle();
+            let file_handle = fs.create(&root_handle, "acl.txt", 0o600).unwrap();
+            let owner = fs.getattr(&file_handle).unwrap().uid;
+            let reader = sys_credentials(owner.wrapping_add(4242), 54321);
+            let stranger = sys_credentials(owner.wrapping_add(4343), 54321);
+
+            // Mode bits alone shut everyone but the owner out
+            assert_eq!(fs.get_acl(&file_handle).unwrap(), None);
+            assert_eq!(access_for(fs.as_ref(), &file_handle, ACCESS3_READ, &reader), 0);
+
+            // Grant one uid read access through the ACL
+            let acl = Acl::from_mode(0o600)
+                .with_entry(AclTag::User(reader.uid), ACL_READ)
+                .with_entry(AclTag::Mask, ACL_READ);
+            fs.set_acl(&file_handle, &acl).unwrap();
+
+            let stored = fs.get_acl(&file_handle).unwrap().expect("ACL should be stored");
+            assert!(stored.entries.iter().any(|e| e.tag == AclTag::User(reader.uid) && e.perm == ACL_READ));
+
+            let requested = ACCESS3_READ | ACCESS3_MODIFY;
+            assert_eq!(access_for(fs.as_ref(), &file_handle, requested, &reader), ACCESS3_READ);
+            assert_eq!(access_for(fs.as_ref(), &file_handle, requested, &stranger), 0);
+
+            // A chmod rewrites the mask, shutting the named user out again
+            fs.setattr_mode(&file_handle, 0o000).unwrap();
+            let stored = fs.get_acl(&file_handle).unwrap().expect("ACL should be kept");
+            assert!(stored.entries.iter().any(|e| e.tag == AclTag::Mask && e.perm == 0));
+            assert!(stored.entries.iter().any(|e| e.tag == AclTag::UserObj && e.perm == 0));
+            assert_eq!(access_for(fs.as_ref(), &file_handle, requested, &reader), 0);
+            fs.setattr_mode(&file_handle, 0o640).unwrap();
+            assert_eq!(access_for(fs.as_ref(), &file_handle, requested, &reader), ACCESS3_READ);
+
+            // An invalid ACL is refused
+            let no_mask = Acl::from_mode(0o600).with_entry(AclTag::User(reader.uid), ACL_READ);
+            assert!(fs.set_acl(&file_handle, &no_mask).is_err());
+        }
+    }
 }
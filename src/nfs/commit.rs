@@ -12,6 +12,8 @@ use bytes::BytesMut;
 use tracing::{debug, warn};
 
 use crate::fsal::Filesystem;
+use crate::nfs::error::{handle_error_status, io_error_status};
+use crate::nfs::verifier::write_verifier;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
@@ -56,19 +58,14 @@ pub fn handle_commit(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
                 }
             };
 
-            // Create write verifier (8 bytes)
-            // In a production implementation, this should be:
-            // - Unique per server boot
-            // - Persistent across commits
-            // - Changed only when server reboots
-            // For now, we use a constant value
-            let writeverf: [u8; 8] = [0; 8];
+            // Write verifier (8 bytes), same boot verifier WRITE returns
+            let writeverf = write_verifier();
 
             create_commit_response(xid, nfsstat3::NFS3_OK, file_after, Some(writeverf))
         }
         Err(e) => {
             warn!("COMMIT failed: {}", e);
-            let status = map_error_to_status(&e);
+            let status = map_error_to_status(&args.file.0, &e);
             let file_attr = file_before.map(|attr| NfsMessage::fsal_to_fattr3(&attr));
             create_commit_response(xid, status, file_attr, None)
         }
@@ -78,7 +75,7 @@ pub fn handle_commit(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
 /// Create COMMIT3res response
 ///
 /// COMMIT3res structure (RFC 1813):
-/// ```
+/// ```text
 /// union COMMIT3res switch (nfsstat3 status) {
 ///     case NFS3_OK:
 ///         struct {
@@ -134,7 +131,11 @@ fn create_commit_response(
 }
 
 /// Map filesystem errors to NFS status codes
-fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
+fn map_error_to_status(handle: &[u8], error: &anyhow::Error) -> nfsstat3 {
+    if let Some(status) = handle_error_status(error) {
+        return status;
+    }
+
     let error_msg = error.to_string().to_lowercase();
 
     if error_msg.contains("not found") || error_msg.contains("no such file") {
@@ -146,6 +147,6 @@ fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
     } else if error_msg.contains("read-only") {
         nfsstat3::NFS3ERR_ROFS // 30 - Read-only filesystem
     } else {
-        nfsstat3::NFS3ERR_IO // 5 - I/O error
+        io_error_status("COMMIT", handle, error) // 5 - I/O error
     }
 }
@@ -4,11 +4,18 @@
 
 use anyhow::Result;
 use bytes::BytesMut;
-use tracing::debug;
-
-use crate::fsal::Filesystem;
+use tracing::{debug, warn};
+
+use crate::export::ModePolicy;
+use crate::fsal::error::errno_of;
+use crate::fsal::{FileTime, FileType, Filesystem, SetAttributes, SetTime};
+use crate::nfs::error::{handle_error_status, io_error_status, permission_error_status, storage_error_status};
+use crate::nfs::name::validate_name;
+use crate::nfs::setattr::requested_times;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
+use crate::rpc::auth::Credentials;
+use crate::rpc::wire_log;
 
 /// Handle NFS CREATE procedure (procedure 8)
 ///
@@ -18,6 +25,8 @@ use crate::protocol::v3::rpc::RpcMessage;
 /// * `xid` - Transaction ID from the request
 /// * `args_data` - Serialized CREATE3args (dir handle + filename + how)
 /// * `filesystem` - Filesystem instance
+/// * `credentials` - Caller identity (after export squashing), owner of the new file
+/// * `modes` - Umask and default modes of the export
 ///
 /// # Returns
 /// Serialized RPC reply message with new file handle
@@ -25,13 +34,11 @@ pub fn handle_create(
     xid: u32,
     args_data: &[u8],
     filesystem: &dyn Filesystem,
+    credentials: &Credentials,
+    modes: &ModePolicy,
 ) -> Result<BytesMut> {
     debug!("NFS CREATE called (xid={})", xid);
-    debug!(
-        "CREATE: args_data = {} bytes, hex: {:02x?}",
-        args_data.len(),
-        args_data
-    );
+    wire_log::dump("CREATE args", args_data);
 
     // Deserialize arguments
     let args = NfsMessage::deserialize_create3args(args_data)?;
@@ -43,27 +50,47 @@ pub fn handle_create(
         filename
     );
 
+    if let Err(status) = validate_name(filename) {
+        let res_data = NfsMessage::create_create_error_response(status)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
     // Get directory attributes before create (for wcc_data)
     let _before_dir_attrs = filesystem.getattr(&args.where_dir.0).ok();
 
+    // UNCHECKED may reopen an existing file, whose owner must not change
+    let existed = filesystem.lookup(&args.where_dir.0, filename).is_ok();
+
     // Create the file based on mode
     let file_handle = match &args.how {
         crate::protocol::v3::nfs::createhow3::UNCHECKED(attrs)
         | crate::protocol::v3::nfs::createhow3::GUARDED(attrs) => {
-            // For UNCHECKED: create or truncate existing file
-            // For GUARDED: fail if file exists (checked by filesystem layer)
+            // For UNCHECKED: create or open existing file
+            // For GUARDED: fail if file exists, checked atomically by the backend
+            let guarded = matches!(args.how, crate::protocol::v3::nfs::createhow3::GUARDED(_));
 
-            let mode = match &attrs.mode {
-                crate::protocol::v3::nfs::set_mode3::SET_MODE(m) => *m,
-                _ => 0o644, // Default mode
-            };
+            let mode = modes.file(match &attrs.mode {
+                crate::protocol::v3::nfs::set_mode3::SET_MODE(m) => Some(*m),
+                _ => None,
+            });
 
             // Create the file
-            match filesystem.create(&args.where_dir.0, &filename, mode) {
+            let created = if guarded {
+                filesystem.create_guarded(&args.where_dir.0, filename, mode)
+            } else {
+                filesystem.create(&args.where_dir.0, filename, mode)
+            };
+            match created {
                 Ok(handle) => handle,
                 Err(e) => {
                     debug!("CREATE failed: {}", e);
-                    let error_status = if e.to_string().contains("exists") {
+                    let error_status = if let Some(status) = handle_error_status(&e) {
+                        status
+                    } else if let Some(status) = storage_error_status(&e) {
+                        status
+                    } else if let Some(status) = permission_error_status(&e) {
+                        status
+                    } else if errno_of(&e) == Some(libc::EEXIST) || e.to_string().contains("exists") {
                         nfsstat3::NFS3ERR_EXIST
                     } else if e.to_string().contains("not found") {
                         nfsstat3::NFS3ERR_NOENT
@@ -71,10 +98,6 @@ pub fn handle_create(
                         nfsstat3::NFS3ERR_NOTDIR
                     } else if e.to_string().contains("Permission denied") {
                         nfsstat3::NFS3ERR_ACCES
-                    } else if e.to_string().contains("No space") {
-                        nfsstat3::NFS3ERR_NOSPC
-                    } else if e.to_string().contains("Read-only") {
-                        nfsstat3::NFS3ERR_ROFS
                     } else {
                         nfsstat3::NFS3ERR_IO
                     };
@@ -83,54 +106,132 @@ pub fn handle_create(
                 }
             }
         }
-        crate::protocol::v3::nfs::createhow3::EXCLUSIVE(_verf) => {
-            // EXCLUSIVE mode: create file with verifier stored in mtime/atime
-            // This is for safe concurrent creation
-            // For simplicity, we'll treat it like GUARDED for now
-            match filesystem.create(&args.where_dir.0, &filename, 0o644) {
-                Ok(handle) => handle,
+        crate::protocol::v3::nfs::createhow3::EXCLUSIVE(verf) => {
+            // EXCLUSIVE: the verifier is stored in atime/mtime, so that a
+            // retransmission of the same CREATE finds its own file
+            let (atime, mtime) = verifier_times(&verf.0);
+            let failed = |e: &anyhow::Error| {
+                if let Some(status) = handle_error_status(e) {
+                    status
+                } else if let Some(status) = storage_error_status(e) {
+                    status
+                } else if let Some(status) = permission_error_status(e) {
+                    status
+                } else {
+                    nfsstat3::NFS3ERR_IO
+                }
+            };
+            match filesystem.create_guarded(&args.where_dir.0, filename, modes.file(None)) {
+                Ok(handle) => {
+                    let stored = filesystem.setattr_times(
+                        &handle,
+                        Some(SetTime::ClientTime(atime)),
+                        Some(SetTime::ClientTime(mtime)),
+                    );
+                    if let Err(e) = stored {
+                        // Without its verifier a retransmission would be refused
+                        debug!("CREATE (EXCLUSIVE): failed to store verifier: {}", e);
+                        if let Err(e) = filesystem.remove(&args.where_dir.0, filename) {
+                            warn!("CREATE (EXCLUSIVE): failed to remove {}: {}", filename, e);
+                        }
+                        let res_data = NfsMessage::create_create_error_response(failed(&e))?;
+                        return RpcMessage::create_success_reply_with_data(xid, res_data);
+                    }
+                    handle
+                }
+                Err(e) if errno_of(&e) == Some(libc::EEXIST) || e.to_string().contains("exists") => {
+                    // Succeed only for the file this CREATE made before
+                    let existing = filesystem.lookup(&args.where_dir.0, filename).ok().filter(|handle| {
+                        filesystem
+                            .getattr(handle)
+                            .is_ok_and(|attrs| attrs.atime == atime && attrs.mtime == mtime)
+                    });
+                    let Some(handle) = existing else {
+                        debug!("CREATE (EXCLUSIVE): {} exists with another verifier", filename);
+                        let res_data = NfsMessage::create_create_error_response(nfsstat3::NFS3ERR_EXIST)?;
+                        return RpcMessage::create_success_reply_with_data(xid, res_data);
+                    };
+                    debug!("CREATE (EXCLUSIVE): {} exists with this verifier", filename);
+                    handle
+                }
                 Err(e) => {
                     debug!("CREATE (EXCLUSIVE) failed: {}", e);
-                    let error_status = if e.to_string().contains("exists") {
-                        nfsstat3::NFS3ERR_EXIST
-                    } else {
-                        nfsstat3::NFS3ERR_IO
-                    };
-                    let res_data = NfsMessage::create_create_error_response(error_status)?;
+                    let res_data = NfsMessage::create_create_error_response(failed(&e))?;
                     return RpcMessage::create_success_reply_with_data(xid, res_data);
                 }
             }
         }
     };
 
-    // Get file attributes
-    let file_attrs = match filesystem.getattr(&file_handle) {
-        Ok(attrs) => attrs,
-        Err(e) => {
-            debug!("CREATE: failed to get file attributes: {}", e);
-            let error_status = nfsstat3::NFS3ERR_IO;
+    if !existed
+        && let Err(status) = set_creator_owner(filesystem, &args.where_dir.0, filename, &file_handle, credentials)
+    {
+        let res_data = NfsMessage::create_create_error_response(status)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
+    // The backend leaves an existing file as it is: apply the requested
+    // size (O_TRUNC sends SET_SIZE(0)) and, for an existing file, the mode;
+    // then the requested times, last so the size change does not move them
+    if let crate::protocol::v3::nfs::createhow3::UNCHECKED(attrs)
+    | crate::protocol::v3::nfs::createhow3::GUARDED(attrs) = &args.how
+    {
+        let changes = SetAttributes {
+            size: match &attrs.size {
+                crate::protocol::v3::nfs::set_size3::SET_SIZE(size) => Some(*size),
+                _ => None,
+            },
+            mode: match &attrs.mode {
+                crate::protocol::v3::nfs::set_mode3::SET_MODE(mode) if existed => Some(modes.masked(*mode)),
+                _ => None,
+            },
+            ..Default::default()
+        };
+        let (atime, mtime) = requested_times(attrs);
+        let mut applied = Ok(());
+        if changes != SetAttributes::default() {
+            applied = filesystem.setattr(&file_handle, &changes, None);
+        }
+        if applied.is_ok() && (atime.is_some() || mtime.is_some()) {
+            applied = filesystem.setattr_times(&file_handle, atime, mtime);
+        }
+        if let Err(e) = applied {
+            debug!("CREATE: failed to apply {:?} atime={:?} mtime={:?}: {}", changes, atime, mtime, e);
+            let error_status = if let Some(status) = storage_error_status(&e) {
+                status
+            } else if let Some(status) = permission_error_status(&e) {
+                status
+            } else if e.to_string().contains("Permission denied") {
+                nfsstat3::NFS3ERR_ACCES
+            } else {
+                io_error_status("CREATE", &file_handle, &e)
+            };
             let res_data = NfsMessage::create_create_error_response(error_status)?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
+    }
+
+    // The file exists now, so a failed attribute fetch only drops the
+    // attributes from the reply; the client can still use the handle
+    let nfs_file_attrs = match filesystem.getattr(&file_handle) {
+        Ok(attrs) => Some(NfsMessage::fsal_to_fattr3(&attrs)),
+        Err(e) => {
+            warn!("CREATE: failed to get attributes of new file {}: {}", filename, e);
+            None
+        }
     };
 
     // Get directory attributes after create
-    let dir_attrs = match filesystem.getattr(&args.where_dir.0) {
-        Ok(attrs) => attrs,
+    let nfs_dir_attrs = match filesystem.getattr(&args.where_dir.0) {
+        Ok(attrs) => Some(NfsMessage::fsal_to_fattr3(&attrs)),
         Err(e) => {
             debug!("CREATE: failed to get dir attributes: {}", e);
-            let error_status = nfsstat3::NFS3ERR_IO;
-            let res_data = NfsMessage::create_create_error_response(error_status)?;
-            return RpcMessage::create_success_reply_with_data(xid, res_data);
+            None
         }
     };
 
     debug!("CREATE success: new file handle {} bytes", file_handle.len());
 
-    // Convert FSAL attributes to NFS fattr3
-    let nfs_file_attrs = NfsMessage::fsal_to_fattr3(&file_attrs);
-    let nfs_dir_attrs = NfsMessage::fsal_to_fattr3(&dir_attrs);
-
     // Create CREATE response
     use xdr_codec::Pack;
     let mut buf = Vec::new();
@@ -151,16 +252,30 @@ pub fn handle_create(
     buf.extend_from_slice(&vec![0u8; padding]);
 
     // obj_attributes: post_op_attr (optional attributes)
-    true.pack(&mut buf)?; // attributes_follow = TRUE
-    nfs_file_attrs.pack(&mut buf)?;
+    match &nfs_file_attrs {
+        Some(attrs) => {
+            true.pack(&mut buf)?; // attributes_follow = TRUE
+            attrs.pack(&mut buf)?;
+        }
+        None => {
+            false.pack(&mut buf)?; // attributes_follow = FALSE
+        }
+    }
 
     // dir_wcc: wcc_data (directory weak cache consistency)
     // pre_op_attr
     false.pack(&mut buf)?; // pre_op_attr = FALSE
 
     // post_op_attr
-    true.pack(&mut buf)?; // attributes_follow = TRUE
-    nfs_dir_attrs.pack(&mut buf)?;
+    match &nfs_dir_attrs {
+        Some(attrs) => {
+            true.pack(&mut buf)?; // attributes_follow = TRUE
+            attrs.pack(&mut buf)?;
+        }
+        None => {
+            false.pack(&mut buf)?; // attributes_follow = FALSE
+        }
+    }
 
     let res_data = BytesMut::from(&buf[..]);
 
@@ -168,10 +283,70 @@ pub fn handle_create(
     RpcMessage::create_success_reply_with_data(xid, res_data)
 }
 
+/// The atime and mtime that store an EXCLUSIVE CREATE's verifier
+///
+/// Each holds half of the verifier in its seconds, as other servers store
+/// it, so the times stay valid for any verifier.
+fn verifier_times(verf: &[u8; 8]) -> (FileTime, FileTime) {
+    let half = |bytes: &[u8]| FileTime {
+        seconds: u32::from_be_bytes(bytes.try_into().unwrap()) as u64,
+        nseconds: 0,
+    };
+    (half(&verf[..4]), half(&verf[4..]))
+}
+
+/// Give a newly created object to the caller's uid/gid
+///
+/// A server running as root that fails to give an object away removes it
+/// again rather than leave it owned by root. An unprivileged server cannot
+/// give objects away at all: they keep its own identity, as before.
+///
+/// # Returns
+/// Err with the status to reply instead when the owner could not be set
+pub(super) fn set_creator_owner(
+    filesystem: &dyn Filesystem,
+    dir_handle: &[u8],
+    name: &str,
+    handle: &[u8],
+    credentials: &Credentials,
+) -> std::result::Result<(), nfsstat3> {
+    let handle = handle.to_vec();
+    let Err(e) = filesystem.setattr_owner(&handle, Some(credentials.uid), Some(credentials.gid)) else {
+        return Ok(());
+    };
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        warn!(
+            "Failed to set owner {}:{} on new object {:?}: {}",
+            credentials.uid, credentials.gid, name, e
+        );
+        return Ok(());
+    }
+    warn!(
+        "Failed to set owner {}:{} on new object {:?}, removing it: {}",
+        credentials.uid, credentials.gid, name, e
+    );
+
+    let dir_handle = dir_handle.to_vec();
+    let is_dir = filesystem.getattr(&handle).is_ok_and(|attr| attr.ftype == FileType::Directory);
+    let removed = if is_dir {
+        filesystem.rmdir(&dir_handle, name)
+    } else {
+        filesystem.remove(&dir_handle, name)
+    };
+    if let Err(remove_error) = removed {
+        warn!("Failed to remove new object {:?}: {}", name, remove_error);
+    }
+
+    Err(handle_error_status(&e)
+        .or_else(|| permission_error_status(&e))
+        .unwrap_or(nfsstat3::NFS3ERR_IO))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::fsal::{BackendConfig, Filesystem};
+    use crate::fsal::BackendConfig;
     use std::fs;
     use tempfile::TempDir;
 
@@ -187,7 +362,7 @@ mod tests {
         // Serialize CREATE3args
         use crate::protocol::v3::nfs::{
             createhow3, fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3,
-            set_mtime, set_size3, set_uid3, time_how, CREATE3args,
+            set_mtime, set_size3, set_uid3, CREATE3args,
         };
         use xdr_codec::Pack;
 
@@ -209,7 +384,7 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call CREATE
-        let result = handle_create(12345, &args_buf, fs.as_ref());
+        let result = handle_create(12345, &args_buf, fs.as_ref(), &Credentials::anonymous(), &ModePolicy::default());
 
         assert!(result.is_ok(), "CREATE should succeed");
 
@@ -234,7 +409,7 @@ mod tests {
         // Serialize CREATE3args with UNCHECKED mode
         use crate::protocol::v3::nfs::{
             createhow3, fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3,
-            set_mtime, set_size3, set_uid3, time_how, CREATE3args,
+            set_mtime, set_size3, set_uid3, CREATE3args,
         };
         use xdr_codec::Pack;
 
@@ -255,8 +430,310 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call CREATE - should succeed (UNCHECKED allows overwriting)
-        let result = handle_create(12345, &args_buf, fs.as_ref());
+        let result = handle_create(12345, &args_buf, fs.as_ref(), &Credentials::anonymous(), &ModePolicy::default());
 
         assert!(result.is_ok(), "CREATE UNCHECKED should succeed even if file exists");
     }
+
+    #[test]
+    fn test_create_guarded_over_existing_file_is_exist() {
+        use crate::protocol::v3::nfs::{
+            createhow3, fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
+            CREATE3args,
+        };
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        fs::write(temp_dir.path().join("existing.txt"), b"old content").unwrap();
+
+        // A truncating GUARDED create must not reach the existing file
+        let create = |name: &str| {
+            let args = CREATE3args {
+                where_dir: fhandle3(fs.root_handle()),
+                name: filename3(name.to_string()),
+                how: createhow3::GUARDED(sattr3 {
+                    mode: set_mode3::SET_MODE(0o644),
+                    uid: set_uid3::default,
+                    gid: set_gid3::default,
+                    size: set_size3::SET_SIZE(0),
+                    atime: set_atime::default,
+                    mtime: set_mtime::default,
+                }),
+            };
+            let mut args_buf = Vec::new();
+            args.pack(&mut args_buf).unwrap();
+            let reply = handle_create(1, &args_buf, fs.as_ref(), &Credentials::anonymous(), &ModePolicy::default())
+                .unwrap();
+            u32::from_be_bytes(reply[24..28].try_into().unwrap())
+        };
+
+        assert_eq!(create("existing.txt"), nfsstat3::NFS3ERR_EXIST as u32);
+        assert_eq!(fs::read(temp_dir.path().join("existing.txt")).unwrap(), b"old content");
+        assert_eq!(create("new.txt"), nfsstat3::NFS3_OK as u32);
+        assert!(temp_dir.path().join("new.txt").exists());
+    }
+
+    #[test]
+    fn test_create_exclusive_retransmission_matches_verifier() {
+        use crate::protocol::v3::nfs::{createhow3, createverf3, fhandle3, filename3, CREATE3args};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        fs::write(temp_dir.path().join("existing.txt"), b"old content").unwrap();
+
+        let create = |name: &str, verf: [u8; 8]| {
+            let args = CREATE3args {
+                where_dir: fhandle3(fs.root_handle()),
+                name: filename3(name.to_string()),
+                how: createhow3::EXCLUSIVE(createverf3(verf)),
+            };
+            let mut args_buf = Vec::new();
+            args.pack(&mut args_buf).unwrap();
+            let reply = handle_create(1, &args_buf, fs.as_ref(), &Credentials::anonymous(), &ModePolicy::default())
+                .unwrap();
+            u32::from_be_bytes(reply[24..28].try_into().unwrap())
+        };
+        let verf = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
+
+        assert_eq!(create("new.txt", verf), nfsstat3::NFS3_OK as u32);
+        let handle = fs.lookup(&fs.root_handle(), "new.txt").unwrap();
+        assert_eq!(fs.getattr(&handle).unwrap().mtime.seconds, 0x9abc_def0);
+
+        // A retransmission with the same verifier finds its own file
+        assert_eq!(create("new.txt", verf), nfsstat3::NFS3_OK as u32);
+
+        // Another client's CREATE, or a file that was already there, is EXIST
+        assert_eq!(create("new.txt", [1; 8]), nfsstat3::NFS3ERR_EXIST as u32);
+        assert_eq!(create("existing.txt", verf), nfsstat3::NFS3ERR_EXIST as u32);
+        assert_eq!(fs::read(temp_dir.path().join("existing.txt")).unwrap(), b"old content");
+    }
+
+    #[test]
+    fn test_create_name_too_long() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = BackendConfig::local(temp_dir.path());
+        let fs = config.create_filesystem().unwrap();
+
+        use crate::protocol::v3::nfs::{
+            createhow3, fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3,
+            set_mtime, set_size3, set_uid3, CREATE3args,
+        };
+        use xdr_codec::Pack;
+
+        let long_name = "n".repeat(256);
+        let args = CREATE3args {
+            where_dir: fhandle3(fs.root_handle()),
+            name: filename3(long_name.clone()),
+            how: createhow3::UNCHECKED(sattr3 {
+                mode: set_mode3::SET_MODE(0o644),
+                uid: set_uid3::default,
+                gid: set_gid3::default,
+                size: set_size3::default,
+                atime: set_atime::default,
+                mtime: set_mtime::default,
+            }),
+        };
+
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        let reply =
+            handle_create(12345, &args_buf, fs.as_ref(), &Credentials::anonymous(), &ModePolicy::default())
+                .unwrap();
+        let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+        assert_eq!(status, nfsstat3::NFS3ERR_NAMETOOLONG as i32);
+        assert!(!temp_dir.path().join(&long_name).exists());
+    }
+
+    #[test]
+    fn test_create_returns_handle_when_getattr_fails() {
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::protocol::v3::nfs::{
+            createhow3, fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3,
+            set_mtime, set_size3, set_uid3, CREATE3args,
+        };
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = BackendConfig::local(temp_dir.path());
+        let fs = FaultyFilesystem::new(config.create_filesystem().unwrap());
+        let root_handle = fs.root_handle();
+
+        let args = CREATE3args {
+            where_dir: fhandle3(root_handle.clone()),
+            name: filename3("no_attrs.txt".to_string()),
+            how: createhow3::UNCHECKED(sattr3 {
+                mode: set_mode3::SET_MODE(0o644),
+                uid: set_uid3::default,
+                gid: set_gid3::default,
+                size: set_size3::default,
+                atime: set_atime::default,
+                mtime: set_mtime::default,
+            }),
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        fs.fail("getattr", libc::EIO);
+        let reply = handle_create(12345, &args_buf, &fs, &Credentials::anonymous(), &ModePolicy::default()).unwrap();
+        let word = |offset: usize| u32::from_be_bytes(reply[offset..offset + 4].try_into().unwrap());
+
+        assert_eq!(word(24), nfsstat3::NFS3_OK as u32);
+        assert_eq!(word(28), 1, "handle_follows");
+        let handle_len = word(32) as usize;
+        let handle = reply[36..36 + handle_len].to_vec();
+        let after_handle = 36 + handle_len.div_ceil(4) * 4;
+        assert_eq!(word(after_handle), 0, "obj attributes_follow");
+        assert_eq!(word(after_handle + 4), 0, "dir pre_op_attr");
+        assert_eq!(word(after_handle + 8), 0, "dir attributes_follow");
+        assert_eq!(reply.len(), after_handle + 12);
+
+        // The returned handle is usable once the backend recovers
+        fs.heal("getattr");
+        assert_eq!(fs.lookup(&root_handle, "no_attrs.txt").unwrap(), handle);
+        fs.write(&handle, 0, b"data", crate::fsal::StableHow::FileSync).unwrap();
+        assert_eq!(fs.getattr(&handle).unwrap().size, 4);
+    }
+
+    #[test]
+    fn test_create_unchecked_applies_size_and_mode_to_existing_file() {
+        use crate::protocol::v3::nfs::{
+            createhow3, fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3,
+            set_mtime, set_size3, set_uid3, CREATE3args,
+        };
+        use std::os::unix::fs::PermissionsExt;
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = BackendConfig::local(temp_dir.path());
+        let fs = config.create_filesystem().unwrap();
+        let test_file = temp_dir.path().join("existing.bin");
+        fs::write(&test_file, [7u8; 100]).unwrap();
+        fs::set_permissions(&test_file, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let create = |mode: set_mode3, size: set_size3| {
+            let args = CREATE3args {
+                where_dir: fhandle3(fs.root_handle()),
+                name: filename3("existing.bin".to_string()),
+                how: createhow3::UNCHECKED(sattr3 {
+                    mode,
+                    uid: set_uid3::default,
+                    gid: set_gid3::default,
+                    size,
+                    atime: set_atime::default,
+                    mtime: set_mtime::default,
+                }),
+            };
+            let mut args_buf = Vec::new();
+            args.pack(&mut args_buf).unwrap();
+            let reply =
+            handle_create(12345, &args_buf, fs.as_ref(), &Credentials::anonymous(), &ModePolicy::default())
+                .unwrap();
+            i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+        };
+
+        // Without a size the existing contents and mode are kept
+        assert_eq!(create(set_mode3::default, set_size3::default), nfsstat3::NFS3_OK as i32);
+        assert_eq!(fs::read(&test_file).unwrap().len(), 100);
+        assert_eq!(fs::metadata(&test_file).unwrap().permissions().mode() & 0o7777, 0o600);
+
+        // O_TRUNC: SET_SIZE(0) empties the file, SET_MODE applies too
+        assert_eq!(
+            create(set_mode3::SET_MODE(0o640), set_size3::SET_SIZE(0)),
+            nfsstat3::NFS3_OK as i32
+        );
+        assert!(fs::read(&test_file).unwrap().is_empty());
+        assert_eq!(fs::metadata(&test_file).unwrap().permissions().mode() & 0o7777, 0o640);
+    }
+
+    #[test]
+    fn test_create_applies_client_times() {
+        use crate::fsal::{FileTime, MemoryFilesystem};
+        use crate::protocol::v3::nfs::{
+            createhow3, fhandle3, filename3, nfstime3, sattr3, set_atime, set_gid3, set_mode3,
+            set_mtime, set_size3, set_uid3, CREATE3args,
+        };
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let local = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let memory = MemoryFilesystem::new();
+        let stamp = nfstime3 { seconds: 1_000_000_000, nseconds: 123_456_789 };
+
+        for fs in [local.as_ref(), &memory as &dyn Filesystem] {
+            // As `cp -p` sends it: truncate, a fixed mtime and the server's atime
+            let args = CREATE3args {
+                where_dir: fhandle3(fs.root_handle()),
+                name: filename3("stamped.txt".to_string()),
+                how: createhow3::UNCHECKED(sattr3 {
+                    mode: set_mode3::SET_MODE(0o644),
+                    uid: set_uid3::default,
+                    gid: set_gid3::default,
+                    size: set_size3::SET_SIZE(0),
+                    atime: set_atime::SET_TO_SERVER_TIME,
+                    mtime: set_mtime::SET_TO_CLIENT_TIME(stamp),
+                }),
+            };
+            let mut args_buf = Vec::new();
+            args.pack(&mut args_buf).unwrap();
+
+            let reply = handle_create(12345, &args_buf, fs, &Credentials::anonymous(), &ModePolicy::default()).unwrap();
+            let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+            assert_eq!(status, nfsstat3::NFS3_OK as i32);
+
+            let handle = fs.lookup(&fs.root_handle(), "stamped.txt").unwrap();
+            let attrs = fs.getattr(&handle).unwrap();
+            assert_eq!(attrs.mtime, FileTime { seconds: 1_000_000_000, nseconds: 123_456_789 });
+            assert!(attrs.atime.seconds > 1_000_000_000, "atime should be the server's time");
+        }
+    }
+
+    #[test]
+    fn test_create_applies_umask() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{
+            createhow3, fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3, set_mtime,
+            set_size3, set_uid3, CREATE3args,
+        };
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let local = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let memory = MemoryFilesystem::new();
+        let modes = ModePolicy {
+            umask: 0o027,
+            file_mode: 0o600,
+            ..ModePolicy::default()
+        };
+
+        for fs in [local.as_ref(), &memory as &dyn Filesystem] {
+            let create = |name: &str, mode: set_mode3| {
+                let args = CREATE3args {
+                    where_dir: fhandle3(fs.root_handle()),
+                    name: filename3(name.to_string()),
+                    how: createhow3::GUARDED(sattr3 {
+                        mode,
+                        uid: set_uid3::default,
+                        gid: set_gid3::default,
+                        size: set_size3::default,
+                        atime: set_atime::default,
+                        mtime: set_mtime::default,
+                    }),
+                };
+                let mut args_buf = Vec::new();
+                args.pack(&mut args_buf).unwrap();
+                let reply = handle_create(12345, &args_buf, fs, &Credentials::anonymous(), &modes).unwrap();
+                let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+                assert_eq!(status, nfsstat3::NFS3_OK as i32);
+                let handle = fs.lookup(&fs.root_handle(), name).unwrap();
+                fs.getattr(&handle).unwrap().mode & 0o7777
+            };
+
+            // The requested mode is masked; without one the default applies as is
+            assert_eq!(create("requested", set_mode3::SET_MODE(0o666)), 0o640);
+            assert_eq!(create("defaulted", set_mode3::default), 0o600);
+        }
+    }
 }
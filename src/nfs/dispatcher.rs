@@ -2,21 +2,53 @@
 //
 // Routes incoming NFS RPC calls to the appropriate procedure handler
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
+use crate::export::ExportOptions;
 use crate::fsal::Filesystem;
-use crate::protocol::v3::rpc::rpc_call_msg;
+use crate::nfs::error::error_reply;
+use crate::nfs::name::is_invalid_utf8;
+use crate::nfs::NFS_V3;
+use crate::protocol::v3::nfs::nfsstat3;
+use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
+use crate::rpc::auth::Credentials;
 
 use super::{access, commit, create, fsinfo, fsstat, getattr, link, lookup, mkdir, mknod, null, pathconf, read, readdir, readdirplus, readlink, remove, rename, rmdir, setattr, symlink, write};
 
+/// Procedures that modify the filesystem (refused on read-only exports)
+///
+/// The only list the read-only check consults. SETATTR is refused whatever
+/// it sets (a size change truncates data), and COMMIT although it writes
+/// nothing itself, since it only follows WRITEs.
+const MUTATING_PROCS: &[u32] = &[
+    2,  // SETATTR
+    7,  // WRITE
+    8,  // CREATE
+    9,  // MKDIR
+    10, // SYMLINK
+    11, // MKNOD
+    12, // REMOVE
+    13, // RMDIR
+    14, // RENAME
+    15, // LINK
+    21, // COMMIT
+];
+
+/// Check whether an NFSv3 procedure modifies the filesystem
+pub fn is_mutating(procedure: u32) -> bool {
+    MUTATING_PROCS.contains(&procedure)
+}
+
 /// Dispatch NFS procedure call to appropriate handler
 ///
 /// # Arguments
 /// * `call` - Parsed RPC call message
 /// * `args_data` - Procedure arguments data
 /// * `filesystem` - Filesystem instance
+/// * `credentials` - Caller identity from the RPC credential
+/// * `options` - Options of the export the call targets
 ///
 /// # Returns
 /// Serialized RPC reply message
@@ -24,6 +56,8 @@ pub fn dispatch(
     call: &rpc_call_msg,
     args_data: &[u8],
     filesystem: &dyn Filesystem,
+    credentials: &Credentials,
+    options: &ExportOptions,
 ) -> Result<BytesMut> {
     let procedure = call.proc_;
     let xid = call.xid;
@@ -34,13 +68,15 @@ pub fn dispatch(
     );
 
     // Verify NFS version
-    if call.vers != 3 {
-        warn!("Unsupported NFS version: {}", call.vers);
-        return Err(anyhow!("NFS version {} not supported", call.vers));
+    // Clients probe for v4 (and old ones for v2) before settling on v3:
+    // tell them which versions are served rather than dropping the call
+    if call.vers != NFS_V3 {
+        debug!("NFS version {} not supported, answering PROG_MISMATCH", call.vers);
+        return RpcMessage::create_prog_mismatch_reply(xid, NFS_V3, NFS_V3);
     }
 
     // Dispatch based on procedure number
-    match procedure {
+    let result = match procedure {
         0 => {
             // NULL - test procedure
             null::handle_null(xid)
@@ -51,7 +87,7 @@ pub fn dispatch(
         }
         2 => {
             // SETATTR - set file attributes
-            setattr::handle_setattr(xid, args_data, filesystem)
+            setattr::handle_setattr(xid, args_data, filesystem, credentials)
         }
         3 => {
             // LOOKUP - lookup filename
@@ -59,7 +95,7 @@ pub fn dispatch(
         }
         4 => {
             // ACCESS - check file access permissions
-            access::handle_access(xid, args_data, filesystem)
+            access::handle_access(xid, args_data, filesystem, credentials)
         }
         5 => {
             // READLINK - read symbolic link
@@ -67,7 +103,7 @@ pub fn dispatch(
         }
         6 => {
             // READ - read from file
-            read::handle_read(xid, args_data, filesystem)
+            read::handle_read(xid, args_data, filesystem, options.transfer.read_max)
         }
         16 => {
             // READDIR - read directory entries
@@ -79,7 +115,7 @@ pub fn dispatch(
         }
         19 => {
             // FSINFO - get filesystem information
-            fsinfo::handle_fsinfo(xid, args_data, filesystem)
+            fsinfo::handle_fsinfo(xid, args_data, filesystem, &options.transfer)
         }
         20 => {
             // PATHCONF - get filesystem path configuration
@@ -91,23 +127,23 @@ pub fn dispatch(
         }
         7 => {
             // WRITE - write to file
-            write::handle_write(xid, args_data, filesystem)
+            write::handle_write(xid, args_data, filesystem, options.transfer.write_max, options.force_stable_writes)
         }
         8 => {
             // CREATE - create file
-            create::handle_create(xid, args_data, filesystem)
+            create::handle_create(xid, args_data, filesystem, credentials, &options.modes)
         }
         9 => {
             // MKDIR - create directory
-            mkdir::handle_mkdir(xid, args_data, filesystem)
+            mkdir::handle_mkdir(xid, args_data, filesystem, credentials, &options.modes)
         }
         10 => {
             // SYMLINK - create symbolic link
-            symlink::handle_symlink(xid, args_data, filesystem)
+            symlink::handle_symlink(xid, args_data, filesystem, credentials)
         }
         11 => {
             // MKNOD - create special file
-            mknod::handle_mknod(xid, args_data, filesystem)
+            mknod::handle_mknod(xid, args_data, filesystem, credentials, &options.modes)
         }
         12 => {
             // REMOVE - remove file
@@ -133,6 +169,16 @@ pub fn dispatch(
             warn!("Unknown NFS procedure: {}", procedure);
             create_notsupp_response(xid)
         }
+    };
+
+    // A name that is not valid UTF-8 fails to decode; it is refused like
+    // any other invalid name rather than failing the whole call
+    match result {
+        Err(e) if is_invalid_utf8(&e) => {
+            debug!("NFS proc {} refused: name is not valid UTF-8", procedure);
+            error_reply(xid, procedure, nfsstat3::NFS3ERR_INVAL)
+        }
+        result => result,
     }
 }
 
@@ -143,5 +189,99 @@ fn create_notsupp_response(xid: u32) -> Result<BytesMut> {
     let mut buf = Vec::new();
     (crate::protocol::v3::nfs::nfsstat3::NFS3ERR_NOTSUPP as i32).pack(&mut buf)?;
     let res_data = BytesMut::from(&buf[..]);
-    crate::protocol::v3::rpc::RpcMessage::create_success_reply_with_data(xid, res_data)
+    RpcMessage::create_success_reply_with_data(xid, res_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsal::BackendConfig;
+    use crate::protocol::v3::rpc::{auth_flavor, msg_type, opaque_auth};
+    use tempfile::TempDir;
+
+    fn call(proc_: u32) -> rpc_call_msg {
+        rpc_call_msg {
+            xid: 1,
+            mtype: msg_type::CALL,
+            rpcvers: 2,
+            prog: 100003,
+            vers: 3,
+            proc_,
+            cred: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+            verf: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+        }
+    }
+
+    /// Directory handle followed by a raw name, as in LOOKUP3args and diropargs3
+    fn diropargs(dir: &[u8], name: &[u8]) -> Vec<u8> {
+        let mut args = Vec::new();
+        xdr_codec::pack_opaque_flex(dir, None, &mut args).unwrap();
+        xdr_codec::pack_opaque_flex(name, None, &mut args).unwrap();
+        args
+    }
+
+    fn status(reply: &BytesMut) -> u32 {
+        u32::from_be_bytes(reply[24..28].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_name_with_nul_is_invalid() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a"), b"").unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let root = fs.root_handle();
+
+        // "a\0b" must not be truncated to "a" on its way to the filesystem
+        let args = diropargs(&root, b"a\0b");
+        let reply = dispatch(&call(3), &args, fs.as_ref(), &Credentials::anonymous(), &ExportOptions::default())
+            .unwrap();
+        assert_eq!(status(&reply), nfsstat3::NFS3ERR_INVAL as u32);
+
+        let reply = dispatch(&call(12), &args, fs.as_ref(), &Credentials::anonymous(), &ExportOptions::default())
+            .unwrap();
+        assert_eq!(status(&reply), nfsstat3::NFS3ERR_INVAL as u32);
+        assert!(temp_dir.path().join("a").exists());
+    }
+
+    #[test]
+    fn test_name_with_invalid_utf8_is_invalid() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let root = fs.root_handle();
+
+        let args = diropargs(&root, &[b'f', 0xff, 0xfe]);
+        let reply = dispatch(&call(3), &args, fs.as_ref(), &Credentials::anonymous(), &ExportOptions::default())
+            .unwrap();
+        assert_eq!(u32::from_be_bytes(reply[4..8].try_into().unwrap()), msg_type::REPLY as u32);
+        assert_eq!(status(&reply), nfsstat3::NFS3ERR_INVAL as u32);
+    }
+
+    #[test]
+    fn test_configured_rtmax_is_advertised_and_enforced() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("big"), vec![7u8; 300 * 1024]).unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let config = crate::config::Config::from_toml("[export]\nrtmax_kib = 256\n").unwrap();
+        let options = config.exports[0].options().unwrap();
+        let word = |reply: &BytesMut, at: usize| u32::from_be_bytes(reply[at..at + 4].try_into().unwrap());
+
+        // FSINFO: status, post_op_attr, then rtmax
+        let mut args = Vec::new();
+        xdr_codec::pack_opaque_flex(&fs.root_handle(), None, &mut args).unwrap();
+        let reply = dispatch(&call(19), &args, fs.as_ref(), &Credentials::anonymous(), &options).unwrap();
+        assert_eq!(status(&reply), nfsstat3::NFS3_OK as u32);
+        assert_eq!(word(&reply, 24 + 4 + 4 + 84), 256 * 1024, "rtmax");
+
+        // READ of the whole file returns short at rtmax
+        let file = fs.lookup(&fs.root_handle(), "big").unwrap();
+        let mut args = Vec::new();
+        xdr_codec::pack_opaque_flex(&file, None, &mut args).unwrap();
+        args.extend_from_slice(&0u64.to_be_bytes());
+        args.extend_from_slice(&(300u32 * 1024).to_be_bytes());
+        let reply = dispatch(&call(6), &args, fs.as_ref(), &Credentials::anonymous(), &options).unwrap();
+        assert_eq!(status(&reply), nfsstat3::NFS3_OK as u32);
+        let count_at = 24 + 4 + 4 + 84;
+        assert_eq!(word(&reply, count_at), 256 * 1024, "count");
+        assert_eq!(word(&reply, count_at + 4), 0, "eof");
+    }
 }
@@ -0,0 +1,300 @@
+// NFS Error Mapping
+//
+// Shared helpers for turning FSAL errors into NFSv3 status codes.
+
+use anyhow::Result;
+use bytes::BytesMut;
+use tracing::warn;
+use xdr_codec::Pack;
+
+use crate::fsal::FsalError;
+use crate::fsal::error::errno_of;
+use crate::protocol::v3::nfs::nfsstat3;
+use crate::protocol::v3::rpc::RpcMessage;
+
+/// Describe a file handle for logs (hex, truncated)
+pub fn describe_handle(handle: &[u8]) -> String {
+    handle
+        .iter()
+        .take(16)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Whether the backend asked the client to retry later (NFS3ERR_JUKEBOX)
+pub fn is_retry(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<FsalError>(), Some(FsalError::Retry { .. }))
+}
+
+/// Classify a failure to resolve a file handle
+///
+/// Malformed handles are NFS3ERR_BADHANDLE and handles whose object is gone
+/// are NFS3ERR_STALE. Returns None for errors that are not about the handle.
+pub fn handle_error_status(error: &anyhow::Error) -> Option<nfsstat3> {
+    match error.downcast_ref::<FsalError>()? {
+        FsalError::BadHandle { .. } => Some(nfsstat3::NFS3ERR_BADHANDLE),
+        FsalError::Stale { .. } => Some(nfsstat3::NFS3ERR_STALE),
+        _ => None,
+    }
+}
+
+/// Classify a storage failure by the errno the backend hit
+///
+/// Lets clients tell a full disk from an exhausted quota or an oversized
+/// file. Returns None for errors that are not storage failures.
+pub fn storage_error_status(error: &anyhow::Error) -> Option<nfsstat3> {
+    match errno_of(error)? {
+        libc::ENOSPC => Some(nfsstat3::NFS3ERR_NOSPC),
+        libc::EDQUOT => Some(nfsstat3::NFS3ERR_DQUOT),
+        libc::EFBIG => Some(nfsstat3::NFS3ERR_FBIG),
+        libc::EROFS => Some(nfsstat3::NFS3ERR_ROFS),
+        _ => None,
+    }
+}
+
+/// Classify a permission failure by the errno the backend hit
+///
+/// EPERM (the caller is not the owner or privileged, e.g. a chown) is
+/// NFS3ERR_PERM and EACCES (the mode bits deny access) is NFS3ERR_ACCES;
+/// `io::ErrorKind::PermissionDenied` covers both, so the errno decides.
+/// Returns None for errors that are not permission failures.
+pub fn permission_error_status(error: &anyhow::Error) -> Option<nfsstat3> {
+    match errno_of(error)? {
+        libc::EPERM => Some(nfsstat3::NFS3ERR_PERM),
+        libc::EACCES => Some(nfsstat3::NFS3ERR_ACCES),
+        _ => None,
+    }
+}
+
+/// Classify the failure to remove or replace a directory that still has entries
+///
+/// POSIX lets rmdir and rename report a non-empty directory as ENOTEMPTY or
+/// EEXIST; both are NFS3ERR_NOTEMPTY. The errno decides, as the error text
+/// depends on the C library and locale. Returns None for other errors.
+pub fn not_empty_error_status(error: &anyhow::Error) -> Option<nfsstat3> {
+    matches!(errno_of(error)?, libc::ENOTEMPTY | libc::EEXIST).then_some(nfsstat3::NFS3ERR_NOTEMPTY)
+}
+
+/// Log a backend failure that is reported to the client as NFS3ERR_IO
+///
+/// Only the status code goes on the wire; the errno and backend context are
+/// kept in the server log so the failure can be diagnosed.
+pub fn io_error_status(operation: &str, handle: &[u8], error: &anyhow::Error) -> nfsstat3 {
+    match error.downcast_ref::<FsalError>() {
+        Some(FsalError::Io { context, source }) => {
+            warn!(
+                "{} failed with NFS3ERR_IO: handle={} errno={} ({}) context={}",
+                operation,
+                describe_handle(handle),
+                source.raw_os_error().unwrap_or(0),
+                source,
+                context
+            );
+        }
+        _ => {
+            warn!(
+                "{} failed with NFS3ERR_IO: handle={} error={:#}",
+                operation,
+                describe_handle(handle),
+                error
+            );
+        }
+    }
+    nfsstat3::NFS3ERR_IO
+}
+
+/// Classify a failure to query the filesystem holding an object (FSINFO, FSSTAT)
+///
+/// Only an object that is gone (ENOENT, ESTALE) is NFS3ERR_STALE. A backend
+/// asking for a retry is NFS3ERR_JUKEBOX, and other failures are logged and
+/// reported as NFS3ERR_IO, so a transient error does not make the client
+/// drop the handle.
+pub fn query_error_status(operation: &str, handle: &[u8], error: &anyhow::Error) -> nfsstat3 {
+    if is_retry(error) {
+        return nfsstat3::NFS3ERR_JUKEBOX;
+    }
+    if let Some(status) = handle_error_status(error) {
+        return status;
+    }
+    match errno_of(error) {
+        Some(libc::ENOENT | libc::ESTALE) => nfsstat3::NFS3ERR_STALE,
+        _ => io_error_status(operation, handle, error),
+    }
+}
+
+/// Number of optional attribute slots in a procedure's error result
+///
+/// Each post_op_attr counts one slot and each wcc_data counts two. An error
+/// result for the procedure is the status followed by that many FALSE words.
+fn error_attr_slots(procedure: u32) -> usize {
+    match procedure {
+        // GETATTR: status only
+        1 => 0,
+        // LOOKUP, ACCESS, READLINK, READ, READDIR, READDIRPLUS, FSSTAT, FSINFO, PATHCONF
+        3 | 4 | 5 | 6 | 16 | 17 | 18 | 19 | 20 => 1,
+        // LINK: file post_op_attr + linkdir wcc_data
+        15 => 3,
+        // RENAME: fromdir and todir wcc_data
+        14 => 4,
+        // SETATTR, WRITE, CREATE, MKDIR, SYMLINK, MKNOD, REMOVE, RMDIR, COMMIT
+        _ => 2,
+    }
+}
+
+/// Build an RPC reply carrying an NFS error status for any procedure
+///
+/// Used when a request is rejected before reaching its handler, so no
+/// attributes are returned.
+///
+/// # Arguments
+/// * `xid` - Transaction ID from the request
+/// * `procedure` - NFSv3 procedure number
+/// * `status` - Error status to return
+///
+/// # Returns
+/// Serialized RPC reply message
+pub fn error_reply(xid: u32, procedure: u32, status: nfsstat3) -> Result<BytesMut> {
+    let mut buf = Vec::new();
+    (status as i32).pack(&mut buf)?;
+    for _ in 0..error_attr_slots(procedure) {
+        false.pack(&mut buf)?;
+    }
+    RpcMessage::create_success_reply_with_data(xid, BytesMut::from(&buf[..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// Log sink capturing formatted tracing output
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for LogBuffer {
+        type Writer = LogBuffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_io_error_logs_errno_context() {
+        let logs = LogBuffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(logs.clone())
+            .with_ansi(false)
+            .finish();
+
+        let error: anyhow::Error = FsalError::io(
+            "Failed to read file: \"/export/data.bin\"",
+            io::Error::from_raw_os_error(libc::EIO),
+        )
+        .into();
+
+        let status = tracing::subscriber::with_default(subscriber, || {
+            io_error_status("READ", &[0xde, 0xad, 0xbe, 0xef], &error)
+        });
+        assert_eq!(status, nfsstat3::NFS3ERR_IO);
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("WARN"), "Should log at warn: {}", output);
+        assert!(output.contains("READ failed"), "Should name the operation: {}", output);
+        assert!(output.contains("handle=deadbeef"), "Should describe the handle: {}", output);
+        assert!(output.contains(&format!("errno={}", libc::EIO)), "Should log errno: {}", output);
+        assert!(output.contains("/export/data.bin"), "Should log backend context: {}", output);
+    }
+
+    #[test]
+    fn test_storage_error_status_by_errno() {
+        let status = |errno| {
+            let error: anyhow::Error = FsalError::io("write", io::Error::from_raw_os_error(errno)).into();
+            storage_error_status(&error)
+        };
+        assert_eq!(status(libc::ENOSPC), Some(nfsstat3::NFS3ERR_NOSPC));
+        assert_eq!(status(libc::EDQUOT), Some(nfsstat3::NFS3ERR_DQUOT));
+        assert_eq!(status(libc::EFBIG), Some(nfsstat3::NFS3ERR_FBIG));
+        assert_eq!(status(libc::EROFS), Some(nfsstat3::NFS3ERR_ROFS));
+        assert_eq!(status(libc::EIO), None);
+
+        // Bare and context-wrapped io::Errors classify the same way
+        let bare: anyhow::Error = io::Error::from_raw_os_error(libc::EDQUOT).into();
+        assert_eq!(storage_error_status(&bare), Some(nfsstat3::NFS3ERR_DQUOT));
+        let wrapped = anyhow::Error::from(io::Error::from_raw_os_error(libc::ENOSPC)).context("Failed to create file");
+        assert_eq!(storage_error_status(&wrapped), Some(nfsstat3::NFS3ERR_NOSPC));
+        assert_eq!(storage_error_status(&anyhow::anyhow!("No space left")), None);
+    }
+
+    #[test]
+    fn test_permission_error_status_by_errno() {
+        let status = |errno| {
+            let error: anyhow::Error = FsalError::io("chown", io::Error::from_raw_os_error(errno)).into();
+            permission_error_status(&error)
+        };
+        assert_eq!(status(libc::EPERM), Some(nfsstat3::NFS3ERR_PERM));
+        assert_eq!(status(libc::EACCES), Some(nfsstat3::NFS3ERR_ACCES));
+        assert_eq!(status(libc::EIO), None);
+
+        let wrapped = anyhow::Error::from(io::Error::from_raw_os_error(libc::EPERM)).context("Failed to create file");
+        assert_eq!(permission_error_status(&wrapped), Some(nfsstat3::NFS3ERR_PERM));
+        assert_eq!(permission_error_status(&anyhow::anyhow!("Permission denied")), None);
+    }
+
+    #[test]
+    fn test_not_empty_error_status_by_errno() {
+        let status = |errno| {
+            let error: anyhow::Error = FsalError::io("rmdir", io::Error::from_raw_os_error(errno)).into();
+            not_empty_error_status(&error)
+        };
+        assert_eq!(status(libc::ENOTEMPTY), Some(nfsstat3::NFS3ERR_NOTEMPTY));
+        assert_eq!(status(libc::EEXIST), Some(nfsstat3::NFS3ERR_NOTEMPTY));
+        assert_eq!(status(libc::ENOENT), None);
+
+        let wrapped =
+            anyhow::Error::from(io::Error::from_raw_os_error(libc::ENOTEMPTY)).context("Failed to remove directory");
+        assert_eq!(not_empty_error_status(&wrapped), Some(nfsstat3::NFS3ERR_NOTEMPTY));
+        assert_eq!(not_empty_error_status(&anyhow::anyhow!("Directory not empty")), None);
+    }
+
+    #[test]
+    fn test_handle_error_status_from_router() {
+        use crate::export::{ExportRouter, ExportTable};
+        use crate::fsal::{Filesystem, MemoryFilesystem};
+
+        let mut table = ExportTable::new();
+        table.add("/mem", Arc::new(MemoryFilesystem::new()));
+        let router = ExportRouter::new(Arc::new(table));
+        let status = |handle: &[u8]| handle_error_status(&router.getattr(&handle.to_vec()).unwrap_err());
+
+        // Too short to carry an export id, or a bad backend handle
+        assert_eq!(status(&[]), Some(nfsstat3::NFS3ERR_BADHANDLE));
+        assert_eq!(status(&[0, 0, 0, 1]), Some(nfsstat3::NFS3ERR_BADHANDLE));
+        let mut truncated = router.root_handle();
+        truncated.pop();
+        assert_eq!(status(&truncated), Some(nfsstat3::NFS3ERR_BADHANDLE));
+
+        // Well formed, but the export or object is gone
+        let mut other_export = router.root_handle();
+        other_export[3] ^= 0xff;
+        assert_eq!(status(&other_export), Some(nfsstat3::NFS3ERR_STALE));
+        let file = router.create(&router.root_handle(), "gone", 0o644).unwrap();
+        router.remove(&router.root_handle(), "gone").unwrap();
+        assert_eq!(status(&file), Some(nfsstat3::NFS3ERR_STALE));
+
+        assert_eq!(handle_error_status(&anyhow::anyhow!("Invalid file handle")), None);
+    }
+}
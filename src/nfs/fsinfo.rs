@@ -6,9 +6,12 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::debug;
 
+use crate::export::TransferSizes;
 use crate::fsal::Filesystem;
-use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
+use crate::nfs::error::{describe_handle, query_error_status};
+use crate::protocol::v3::nfs::NfsMessage;
 use crate::protocol::v3::rpc::RpcMessage;
+use crate::rpc::wire_log;
 
 // FSINFO property constants
 const FSF3_LINK: u32 = 0x0001; // Server supports hard links
@@ -16,14 +19,27 @@ const FSF3_SYMLINK: u32 = 0x0002; // Server supports symbolic links
 const FSF3_HOMOGENEOUS: u32 = 0x0008; // PATHCONF is valid for all files
 const FSF3_CANSETTIME: u32 = 0x0010; // Server can set time on server
 
+/// Largest file size advertised in FSINFO: the largest offset a POSIX
+/// `off_t` can hold
+pub const MAX_FILE_SIZE: u64 = i64::MAX as u64;
+
+/// End of the byte range `offset..offset + count`, if it lies within
+/// `MAX_FILE_SIZE`
+pub fn range_end(offset: u64, count: u32) -> Option<u64> {
+    offset.checked_add(count as u64).filter(|end| *end <= MAX_FILE_SIZE)
+}
+
 /// Handle NFS FSINFO procedure (procedure 19)
 ///
 /// Returns static filesystem information such as maximum sizes and capabilities.
+/// Clients may ask about any object of the export, not just its root; the
+/// answer is the same for all of them.
 ///
 /// # Arguments
 /// * `xid` - Transaction ID from the request
 /// * `args_data` - Serialized FSINFO3args (fsroot handle)
 /// * `filesystem` - Filesystem instance
+/// * `transfer` - READ, WRITE and READDIR sizes of the export
 ///
 /// # Returns
 /// Serialized RPC reply message with filesystem information
@@ -31,46 +47,38 @@ pub fn handle_fsinfo(
     xid: u32,
     args_data: &[u8],
     filesystem: &dyn Filesystem,
+    transfer: &TransferSizes,
 ) -> Result<BytesMut> {
     debug!("NFS FSINFO called (xid={})", xid);
 
     // Deserialize arguments (fsroot handle)
-    debug!("FSINFO: args_data = {} bytes, hex: {:02x?}",
-           args_data.len(), &args_data[..args_data.len().min(100)]);
+    wire_log::dump("FSINFO args", args_data);
 
     let args = NfsMessage::deserialize_fsinfo3args(args_data)?;
 
-    debug!("FSINFO: fsroot_handle={} bytes, hex: {:02x?}",
-           args.fsroot.0.len(), &args.fsroot.0);
+    debug!("FSINFO: fsroot_handle={}", describe_handle(&args.fsroot.0));
 
     // Get filesystem attributes
     let obj_attrs = match filesystem.getattr(&args.fsroot.0) {
         Ok(attrs) => attrs,
         Err(e) => {
             debug!("FSINFO failed: {}", e);
-            let error_status = if e.to_string().contains("not found")
-                || e.to_string().contains("Invalid handle")
-            {
-                nfsstat3::NFS3ERR_STALE
-            } else {
-                nfsstat3::NFS3ERR_IO
-            };
-
+            let error_status = query_error_status("FSINFO", &args.fsroot.0, &e);
             let res_data = NfsMessage::create_fsinfo_error_response(error_status)?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
 
     // Define filesystem capabilities and limits
-    // These values are based on RFC 1813 recommendations
-    let rtmax = 1024 * 1024; // 1 MB - max read request
-    let rtpref = 64 * 1024; // 64 KB - preferred read size
+    // Transfer sizes are the export's, the same ones READ and WRITE enforce
+    let rtmax = transfer.read_max; // max read request
+    let rtpref = transfer.read_pref; // preferred read size
     let rtmult = 4096; // 4 KB - suggested read multiple
-    let wtmax = 1024 * 1024; // 1 MB - max write request
-    let wtpref = 64 * 1024; // 64 KB - preferred write size
+    let wtmax = transfer.write_max; // max write request
+    let wtpref = transfer.write_pref; // preferred write size
     let wtmult = 4096; // 4 KB - suggested write multiple
-    let dtpref = 8192; // 8 KB - preferred READDIR size
-    let maxfilesize = 0xFFFFFFFFFFFFFFFFu64; // Maximum file size (unlimited)
+    let dtpref = transfer.readdir_pref; // preferred READDIR size
+    let maxfilesize = MAX_FILE_SIZE; // Largest offset the backends can address
 
     // Time precision - 1 nanosecond
     let time_delta_seconds = 0u32;
@@ -110,7 +118,8 @@ pub fn handle_fsinfo(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::fsal::{BackendConfig, Filesystem};
+    use crate::protocol::v3::nfs::nfsstat3;
+    use crate::fsal::BackendConfig;
     use tempfile::TempDir;
 
     #[test]
@@ -135,7 +144,7 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call FSINFO
-        let result = handle_fsinfo(12345, &args_buf, fs.as_ref());
+        let result = handle_fsinfo(12345, &args_buf, fs.as_ref(), &TransferSizes::default());
 
         assert!(result.is_ok(), "FSINFO should succeed");
 
@@ -162,8 +171,57 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call FSINFO
-        let result = handle_fsinfo(12345, &args_buf, fs.as_ref());
+        let result = handle_fsinfo(12345, &args_buf, fs.as_ref(), &TransferSizes::default());
 
         assert!(result.is_ok(), "FSINFO should return error response (not panic)");
     }
+
+    /// Capability fields of an FSINFO reply: everything after the status and
+    /// post_op_attr (flag + 84-byte fattr3)
+    fn capabilities(reply: &[u8]) -> &[u8] {
+        assert_eq!(&reply[24..28], &[0, 0, 0, 0], "FSINFO failed");
+        &reply[24 + 4 + 4 + 84..]
+    }
+
+    fn fsinfo(fs: &dyn Filesystem, handle: Vec<u8>, transfer: &TransferSizes) -> BytesMut {
+        use crate::protocol::v3::nfs::{fhandle3, FSINFO3args};
+        use xdr_codec::Pack;
+
+        let mut args_buf = Vec::new();
+        FSINFO3args { fsroot: fhandle3(handle) }.pack(&mut args_buf).unwrap();
+        handle_fsinfo(1, &args_buf, fs, transfer).unwrap()
+    }
+
+    #[test]
+    fn test_fsinfo_subdirectory_matches_root() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("sub/file"), "x").unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let sub = fs.lookup(&fs.root_handle(), "sub").unwrap();
+        let file = fs.lookup(&sub, "file").unwrap();
+
+        let transfer = TransferSizes { read_max: 256 * 1024, ..TransferSizes::default() };
+        let root_reply = fsinfo(fs.as_ref(), fs.root_handle(), &transfer);
+        let root = capabilities(&root_reply);
+        assert_eq!(&root[..4], &(256u32 * 1024).to_be_bytes(), "rtmax follows the export");
+        assert_eq!(capabilities(&fsinfo(fs.as_ref(), sub, &transfer)), root);
+        assert_eq!(capabilities(&fsinfo(fs.as_ref(), file, &transfer)), root);
+    }
+
+    #[test]
+    fn test_fsinfo_transient_failure_is_not_stale() {
+        use crate::fsal::faulty::FaultyFilesystem;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = FaultyFilesystem::new(BackendConfig::local(temp_dir.path()).create_filesystem().unwrap());
+        let status = |reply: &[u8]| u32::from_be_bytes(reply[24..28].try_into().unwrap());
+
+        fs.fail("getattr", libc::EIO);
+        assert_eq!(status(&fsinfo(&fs, fs.root_handle(), &TransferSizes::default())), nfsstat3::NFS3ERR_IO as u32);
+        fs.retry("getattr");
+        assert_eq!(status(&fsinfo(&fs, fs.root_handle(), &TransferSizes::default())), nfsstat3::NFS3ERR_JUKEBOX as u32);
+        fs.fail("getattr", libc::ENOENT);
+        assert_eq!(status(&fsinfo(&fs, fs.root_handle(), &TransferSizes::default())), nfsstat3::NFS3ERR_STALE as u32);
+    }
 }
@@ -7,12 +7,14 @@ use bytes::BytesMut;
 use tracing::debug;
 
 use crate::fsal::Filesystem;
+use crate::nfs::error::query_error_status;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
 /// Handle NFS FSSTAT procedure (procedure 18)
 ///
-/// Returns dynamic filesystem information such as total/free space.
+/// Returns dynamic filesystem information such as total/free space, for the
+/// filesystem holding the object the handle names.
 ///
 /// # Arguments
 /// * `xid` - Transaction ID from the request
@@ -33,32 +35,26 @@ pub fn handle_fsstat(
 
     debug!("FSSTAT: fsroot_handle={} bytes", args.fsroot.0.len());
 
-    // Get filesystem attributes
-    let obj_attrs = match filesystem.getattr(&args.fsroot.0) {
-        Ok(attrs) => attrs,
+    // Get filesystem attributes and statistics
+    let stats = filesystem
+        .getattr(&args.fsroot.0)
+        .and_then(|attrs| Ok((attrs, filesystem.statfs(&args.fsroot.0)?)));
+    let (obj_attrs, stats) = match stats {
+        Ok(result) => result,
         Err(e) => {
             debug!("FSSTAT failed: {}", e);
-            let error_status = if e.to_string().contains("not found")
-                || e.to_string().contains("Invalid handle")
-            {
-                nfsstat3::NFS3ERR_STALE
-            } else {
-                nfsstat3::NFS3ERR_IO
-            };
-
+            let error_status = query_error_status("FSSTAT", &args.fsroot.0, &e);
             let res_data = NfsMessage::create_fsstat_error_response(error_status)?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
 
-    // Get filesystem statistics
-    // For now, use hardcoded values - in production this would query the actual filesystem
-    let tbytes = 1024 * 1024 * 1024 * 100u64; // 100 GB total
-    let fbytes = 1024 * 1024 * 1024 * 50u64; // 50 GB free
-    let abytes = 1024 * 1024 * 1024 * 50u64; // 50 GB available to non-root
-    let tfiles = 1000000u64; // 1M total inodes
-    let ffiles = 500000u64; // 500k free inodes
-    let afiles = 500000u64; // 500k available inodes to non-root
+    let tbytes = stats.total_bytes;
+    let fbytes = stats.free_bytes;
+    let abytes = stats.avail_bytes; // available to non-root
+    let tfiles = stats.total_files;
+    let ffiles = stats.free_files;
+    let afiles = stats.avail_files; // available to non-root
     let invarsec = 0u32; // filesystem not expected to change without client intervention
 
     debug!(
@@ -98,7 +94,7 @@ pub fn handle_fsstat(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::fsal::{BackendConfig, Filesystem};
+    use crate::fsal::BackendConfig;
     use tempfile::TempDir;
 
     #[test]
@@ -154,4 +150,30 @@ mod tests {
 
         assert!(result.is_ok(), "FSSTAT should return error response (not panic)");
     }
+
+    #[test]
+    fn test_fsstat_reports_filesystem_of_handle() {
+        use crate::protocol::v3::nfs::{fhandle3, FSSTAT3args};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let sub = fs.lookup(&fs.root_handle(), "sub").unwrap();
+
+        let mut args_buf = Vec::new();
+        FSSTAT3args { fsroot: fhandle3(sub.clone()) }.pack(&mut args_buf).unwrap();
+        let reply = handle_fsstat(1, &args_buf, fs.as_ref()).unwrap();
+        assert_eq!(&reply[24..28], &[0, 0, 0, 0], "FSSTAT failed");
+
+        // tbytes follows the status and post_op_attr (flag + 84-byte fattr3)
+        let tbytes_offset = 24 + 4 + 4 + 84;
+        let tbytes = u64::from_be_bytes(reply[tbytes_offset..tbytes_offset + 8].try_into().unwrap());
+        assert_eq!(tbytes, fs.statfs(&sub).unwrap().total_bytes);
+
+        let c_path = std::ffi::CString::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let mut expected: libc::statvfs = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { libc::statvfs(c_path.as_ptr(), &mut expected) }, 0);
+        assert_eq!(tbytes, expected.f_blocks * expected.f_frsize);
+    }
 }
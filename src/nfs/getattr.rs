@@ -7,7 +7,8 @@ use bytes::BytesMut;
 use tracing::debug;
 
 use crate::fsal::Filesystem;
-use crate::protocol::v3::nfs::{NfsMessage, nfsstat3};
+use crate::nfs::error::{handle_error_status, is_retry};
+use crate::protocol::v3::nfs::NfsMessage;
 use crate::protocol::v3::rpc::RpcMessage;
 
 /// Handle NFS GETATTR procedure (procedure 1)
@@ -38,9 +39,16 @@ pub fn handle_getattr(
         Ok(attrs) => attrs,
         Err(e) => {
             debug!("GETATTR failed: {}", e);
-            // Return NFS error - use STALE for invalid handle, IO for other errors
+            // Return NFS error - JUKEBOX for objects not available yet,
+            // BADHANDLE for malformed handles, STALE otherwise
             use crate::protocol::v3::nfs::nfsstat3;
-            let error_status = nfsstat3::NFS3ERR_STALE; // File handle error
+            let error_status = if is_retry(&e) {
+                nfsstat3::NFS3ERR_JUKEBOX
+            } else if let Some(status) = handle_error_status(&e) {
+                status
+            } else {
+                nfsstat3::NFS3ERR_STALE // File handle error
+            };
             let res_data = NfsMessage::create_getattr_error_response(error_status)?;
 
             return RpcMessage::create_success_reply_with_data(xid, res_data);
@@ -68,9 +76,8 @@ pub fn handle_getattr(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
     use tempfile::TempDir;
-    use crate::fsal::{BackendConfig, LocalFilesystem};
+    use crate::fsal::BackendConfig;
 
     #[test]
     fn test_getattr_root() {
@@ -101,4 +108,83 @@ mod tests {
         let reply = result.unwrap();
         assert!(!reply.is_empty(), "Reply should contain data");
     }
+
+    #[test]
+    fn test_getattr_retry_is_jukebox() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::protocol::v3::nfs::{GETATTR3args, fhandle3, nfsstat3};
+        use xdr_codec::Pack;
+
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        fs.retry("getattr");
+
+        let mut args_buf = Vec::new();
+        GETATTR3args { object: fhandle3(fs.root_handle()) }.pack(&mut args_buf).unwrap();
+
+        let reply = handle_getattr(12345, &args_buf, &fs).unwrap();
+        let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+        assert_eq!(status, nfsstat3::NFS3ERR_JUKEBOX as i32);
+    }
+
+    #[test]
+    fn test_getattr_after_recreate_is_stale() {
+        use crate::protocol::v3::nfs::{GETATTR3args, fhandle3, nfsstat3};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let root = fs.root_handle();
+        let old = fs.create(&root, "file", 0o644).unwrap();
+        fs.remove(&root, "file").unwrap();
+        fs.create(&root, "file", 0o644).unwrap();
+
+        let mut args_buf = Vec::new();
+        GETATTR3args { object: fhandle3(old) }.pack(&mut args_buf).unwrap();
+        let reply = handle_getattr(12345, &args_buf, fs.as_ref()).unwrap();
+        let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+        assert_eq!(status, nfsstat3::NFS3ERR_STALE as i32);
+    }
+
+    #[test]
+    fn test_getattr_malformed_handle_is_badhandle() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{GETATTR3args, fhandle3, nfsstat3};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let local = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let memory = MemoryFilesystem::new();
+
+        for fs in [local.as_ref(), &memory as &dyn Filesystem] {
+            let mut args_buf = Vec::new();
+            GETATTR3args { object: fhandle3(Vec::new()) }.pack(&mut args_buf).unwrap();
+            let reply = handle_getattr(12345, &args_buf, fs).unwrap();
+            let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+            assert_eq!(status, nfsstat3::NFS3ERR_BADHANDLE as i32);
+        }
+    }
+
+    #[test]
+    fn test_getattr_deleted_file_is_stale() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{GETATTR3args, fhandle3, nfsstat3};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let local = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let memory = MemoryFilesystem::new();
+
+        for fs in [local.as_ref(), &memory as &dyn Filesystem] {
+            let root = fs.root_handle();
+            let deleted = fs.create(&root, "gone", 0o644).unwrap();
+            fs.remove(&root, "gone").unwrap();
+
+            let mut args_buf = Vec::new();
+            GETATTR3args { object: fhandle3(deleted) }.pack(&mut args_buf).unwrap();
+            let reply = handle_getattr(12345, &args_buf, fs).unwrap();
+            let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+            assert_eq!(status, nfsstat3::NFS3ERR_STALE as i32);
+        }
+    }
 }
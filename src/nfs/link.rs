@@ -7,11 +7,14 @@
 // - Returns updated file attributes (link count increases)
 // - Returns wcc_data for the target directory
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
 use crate::fsal::Filesystem;
+use crate::fsal::error::errno_of;
+use crate::nfs::error::handle_error_status;
+use crate::nfs::name::validate_name;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
@@ -39,6 +42,10 @@ pub fn handle_link(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -> R
         args.name.0
     );
 
+    if let Err(status) = validate_name(&args.name.0) {
+        return create_link_response(xid, status, None, None);
+    }
+
     // Get source file attributes before operation (for post_op_attr)
     let file_before = filesystem.getattr(&args.file.0).ok();
 
@@ -83,7 +90,7 @@ pub fn handle_link(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -> R
 /// Create LINK3res response
 ///
 /// LINK3res structure (RFC 1813):
-/// ```
+/// ```text
 /// union LINK3res switch (nfsstat3 status) {
 ///     case NFS3_OK:
 ///         struct {
@@ -142,6 +149,18 @@ fn create_link_response(
 
 /// Map filesystem errors to NFS status codes
 fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
+    if let Some(status) = handle_error_status(error) {
+        return status;
+    }
+
+    match errno_of(error) {
+        // The file already has the most links the filesystem allows
+        Some(libc::EMLINK) => return nfsstat3::NFS3ERR_MLINK,
+        // Source and directory are on different filesystems or exports
+        Some(libc::EXDEV) => return nfsstat3::NFS3ERR_XDEV,
+        _ => {}
+    }
+
     let error_msg = error.to_string().to_lowercase();
 
     if error_msg.contains("not found") || error_msg.contains("no such file") {
@@ -162,3 +181,49 @@ fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
         nfsstat3::NFS3ERR_IO // 5 - I/O error
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{ExportResolver, ExportRouter, ExportTable};
+    use crate::fsal::FsalError;
+    use crate::fsal::memory::MemoryFilesystem;
+    use crate::protocol::v3::nfs::{fhandle3, filename3, LINK3args};
+    use std::sync::Arc;
+    use xdr_codec::Pack;
+
+    #[test]
+    fn test_link_errno_mapping() {
+        let too_many: anyhow::Error =
+            FsalError::io("Failed to create hard link", std::io::Error::from_raw_os_error(libc::EMLINK)).into();
+        assert_eq!(map_error_to_status(&too_many), nfsstat3::NFS3ERR_MLINK);
+
+        let bare: anyhow::Error = std::io::Error::from_raw_os_error(libc::EMLINK).into();
+        assert_eq!(map_error_to_status(&bare), nfsstat3::NFS3ERR_MLINK);
+
+        let cross: anyhow::Error = std::io::Error::from_raw_os_error(libc::EXDEV).into();
+        assert_eq!(map_error_to_status(&cross), nfsstat3::NFS3ERR_XDEV);
+    }
+
+    #[test]
+    fn test_link_across_exports_is_xdev() {
+        let mut table = ExportTable::new();
+        let first_id = table.add("/first", Arc::new(MemoryFilesystem::new()));
+        let second_id = table.add("/second", Arc::new(MemoryFilesystem::new()));
+        let table: Arc<dyn ExportResolver> = Arc::new(table);
+        let router = ExportRouter::new(table.clone());
+        let file = router.create(&table.root_handle(first_id).unwrap(), "file", 0o644).unwrap();
+
+        let args = LINK3args {
+            file: fhandle3(file),
+            link_dir: fhandle3(table.root_handle(second_id).unwrap()),
+            name: filename3("linked".to_string()),
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        let reply = handle_link(1, &args_buf, &router).unwrap();
+        let status = u32::from_be_bytes(reply[24..28].try_into().unwrap());
+        assert_eq!(status, nfsstat3::NFS3ERR_XDEV as u32);
+    }
+}
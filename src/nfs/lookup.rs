@@ -6,7 +6,9 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::debug;
 
-use crate::fsal::Filesystem;
+use crate::fsal::{FileType, Filesystem};
+use crate::nfs::error::{handle_error_status, is_retry};
+use crate::nfs::name::validate_name;
 use crate::protocol::v3::nfs::{NfsMessage, nfsstat3};
 use crate::protocol::v3::rpc::RpcMessage;
 
@@ -41,23 +43,50 @@ pub fn handle_lookup(
         name
     );
 
-    // Look up the file in the directory
-    let file_handle = match filesystem.lookup(&args.what_dir.0, name) {
+    // Directory attributes go into the reply whether or not the name is found
+    // (LOOKUP does not change the directory, so they stay valid)
+    let dir_attrs = filesystem.getattr(&args.what_dir.0).ok();
+    let nfs_dir_attrs = dir_attrs.as_ref().map(NfsMessage::fsal_to_fattr3);
+
+    if let Err(status) = validate_name(name) {
+        let res_data = NfsMessage::create_lookup_error_response(status, nfs_dir_attrs.as_ref())?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
+    // Names can only be looked up in a directory
+    if let Some(attrs) = &dir_attrs
+        && attrs.ftype != FileType::Directory
+    {
+        debug!("LOOKUP refused: {:?} handle is not a directory", attrs.ftype);
+        let res_data = NfsMessage::create_lookup_error_response(nfsstat3::NFS3ERR_NOTDIR, nfs_dir_attrs.as_ref())?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
+    // Look up the file in the directory; ".." names its parent, which the
+    // backend resolves without leaving the export
+    let found = if name == ".." {
+        filesystem.parent_handle(&args.what_dir.0)
+    } else {
+        filesystem.lookup(&args.what_dir.0, name)
+    };
+    let file_handle = match found {
         Ok(handle) => handle,
         Err(e) => {
             debug!("LOOKUP failed: {}", e);
             // Return appropriate NFS error
-            let error_status = if e.to_string().contains("not found") {
+            let error_status = if is_retry(&e) {
+                nfsstat3::NFS3ERR_JUKEBOX
+            } else if let Some(status) = handle_error_status(&e) {
+                status
+            } else if e.to_string().contains("not found") {
                 nfsstat3::NFS3ERR_NOENT
             } else if e.to_string().contains("Invalid filename") {
                 nfsstat3::NFS3ERR_INVAL
-            } else if e.to_string().contains("Not a directory") {
-                nfsstat3::NFS3ERR_NOTDIR
             } else {
                 nfsstat3::NFS3ERR_IO
             };
 
-            let res_data = NfsMessage::create_lookup_error_response(error_status)?;
+            let res_data = NfsMessage::create_lookup_error_response(error_status, nfs_dir_attrs.as_ref())?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
@@ -67,24 +96,16 @@ pub fn handle_lookup(
         Ok(attrs) => attrs,
         Err(e) => {
             debug!("LOOKUP: failed to get attributes for found file: {}", e);
-            let error_status = nfsstat3::NFS3ERR_IO;
-            let res_data = NfsMessage::create_lookup_error_response(error_status)?;
+            let error_status = if is_retry(&e) {
+                nfsstat3::NFS3ERR_JUKEBOX
+            } else {
+                nfsstat3::NFS3ERR_IO
+            };
+            let res_data = NfsMessage::create_lookup_error_response(error_status, nfs_dir_attrs.as_ref())?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
 
-    // Get attributes for the directory (optional but recommended)
-    let dir_attrs = match filesystem.getattr(&args.what_dir.0) {
-        Ok(attrs) => attrs,
-        Err(e) => {
-            debug!("LOOKUP: failed to get directory attributes: {}", e);
-            // We can still return success even if dir attrs fail
-            // For now, use the obj_attrs as a placeholder
-            // TODO: Make dir_attributes optional in response
-            obj_attrs.clone()
-        }
-    };
-
     debug!(
         "LOOKUP success: {} -> handle, type={:?}, size={}",
         name, obj_attrs.ftype, obj_attrs.size
@@ -92,7 +113,6 @@ pub fn handle_lookup(
 
     // Convert FSAL attributes to NFS fattr3
     let nfs_obj_attrs = NfsMessage::fsal_to_fattr3(&obj_attrs);
-    let nfs_dir_attrs = NfsMessage::fsal_to_fattr3(&dir_attrs);
 
     // Wrap file_handle in fhandle3 (newtype wrapper)
     use crate::protocol::v3::nfs::fhandle3;
@@ -113,8 +133,15 @@ pub fn handle_lookup(
     nfs_obj_attrs.pack(&mut buf)?;
 
     // 4. post_op_attr (dir_attributes)
-    true.pack(&mut buf)?;  // attributes_follow = TRUE
-    nfs_dir_attrs.pack(&mut buf)?;
+    match &nfs_dir_attrs {
+        Some(attrs) => {
+            true.pack(&mut buf)?; // attributes_follow = TRUE
+            attrs.pack(&mut buf)?;
+        }
+        None => {
+            false.pack(&mut buf)?; // attributes_follow = FALSE
+        }
+    }
 
     let res_data = BytesMut::from(&buf[..]);
 
@@ -127,7 +154,7 @@ mod tests {
     use super::*;
     use std::fs;
     use tempfile::TempDir;
-    use crate::fsal::{BackendConfig, Filesystem};
+    use crate::fsal::BackendConfig;
 
     #[test]
     fn test_lookup_existing_file() {
@@ -190,4 +217,122 @@ mod tests {
 
         assert!(result.is_ok(), "LOOKUP should return error response (not panic)");
     }
+
+    #[test]
+    fn test_lookup_noent_carries_dir_attributes() {
+        use crate::protocol::v3::nfs::{LOOKUP3args, fattr3, filename3, fhandle3, ftype3};
+        use xdr_codec::{Pack, Unpack};
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let root_attrs = fs.getattr(&fs.root_handle()).unwrap();
+
+        let args = LOOKUP3args {
+            what_dir: fhandle3(fs.root_handle()),
+            name: filename3("missing.txt".to_string()),
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        let reply = handle_lookup(12345, &args_buf, fs.as_ref()).unwrap();
+        assert_eq!(&reply[24..28], &(nfsstat3::NFS3ERR_NOENT as u32).to_be_bytes());
+        assert_eq!(&reply[28..32], &1u32.to_be_bytes(), "dir_attributes should follow");
+        let (dir_attrs, _) = fattr3::unpack(&mut std::io::Cursor::new(&reply[32..])).unwrap();
+        assert_eq!(dir_attrs.type_, ftype3::NF3DIR);
+        assert_eq!(dir_attrs.fileid, root_attrs.fileid);
+    }
+
+    #[test]
+    fn test_lookup_in_regular_file_is_notdir() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("plain.txt"), b"not a directory").unwrap();
+
+        let config = BackendConfig::local(temp_dir.path());
+        let fs = config.create_filesystem().unwrap();
+        let file_handle = fs.lookup(&fs.root_handle(), "plain.txt").unwrap();
+
+        use crate::protocol::v3::nfs::{LOOKUP3args, filename3, fhandle3};
+        use xdr_codec::Pack;
+
+        let args = LOOKUP3args {
+            what_dir: fhandle3(file_handle),
+            name: filename3("child".to_string()),
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        let reply = handle_lookup(12345, &args_buf, fs.as_ref()).unwrap();
+        assert_eq!(&reply[24..28], &(nfsstat3::NFS3ERR_NOTDIR as u32).to_be_bytes());
+    }
+
+    #[test]
+    fn test_lookup_retry_is_jukebox() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::protocol::v3::nfs::{LOOKUP3args, filename3, fhandle3};
+        use xdr_codec::Pack;
+
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        fs.create(&fs.root_handle(), "cold.bin", 0o644).unwrap();
+
+        let mut args_buf = Vec::new();
+        LOOKUP3args {
+            what_dir: fhandle3(fs.root_handle()),
+            name: filename3("cold.bin".to_string()),
+        }
+        .pack(&mut args_buf)
+        .unwrap();
+        let status = |reply: BytesMut| i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+
+        fs.retry("lookup");
+        let reply = handle_lookup(12345, &args_buf, &fs).unwrap();
+        assert_eq!(status(reply), nfsstat3::NFS3ERR_JUKEBOX as i32);
+
+        // Found, but its attributes are still being recalled
+        fs.heal("lookup");
+        fs.retry("getattr");
+        let reply = handle_lookup(12345, &args_buf, &fs).unwrap();
+        assert_eq!(status(reply), nfsstat3::NFS3ERR_JUKEBOX as i32);
+    }
+
+    /// LOOKUP of ".." returns the parent directory, and the root at the root
+    fn check_lookup_parent(fs: &dyn Filesystem) {
+        use crate::protocol::v3::nfs::{LOOKUP3args, filename3, fhandle3};
+        use xdr_codec::Pack;
+
+        let lookup_parent = |dir: &Vec<u8>| {
+            let mut args_buf = Vec::new();
+            LOOKUP3args {
+                what_dir: fhandle3(dir.clone()),
+                name: filename3("..".to_string()),
+            }
+            .pack(&mut args_buf)
+            .unwrap();
+            let reply = handle_lookup(12345, &args_buf, fs).unwrap();
+            assert_eq!(&reply[24..28], &(nfsstat3::NFS3_OK as u32).to_be_bytes());
+            let len = u32::from_be_bytes(reply[28..32].try_into().unwrap()) as usize;
+            reply[32..32 + len].to_vec()
+        };
+
+        let root = fs.root_handle();
+        let sub = fs.mkdir(&root, "sub", 0o755).unwrap();
+        let deeper = fs.mkdir(&sub, "deeper", 0o755).unwrap();
+
+        assert_eq!(lookup_parent(&deeper), sub);
+        assert_eq!(lookup_parent(&sub), root);
+        assert_eq!(lookup_parent(&root), root, "The export root is its own parent");
+    }
+
+    #[test]
+    fn test_lookup_parent_local() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        check_lookup_parent(fs.as_ref());
+    }
+
+    #[test]
+    fn test_lookup_parent_memory() {
+        let fs = BackendConfig::memory().create_filesystem().unwrap();
+        check_lookup_parent(fs.as_ref());
+    }
 }
@@ -6,9 +6,15 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
+use crate::export::ModePolicy;
 use crate::fsal::Filesystem;
+use crate::nfs::error::{handle_error_status, permission_error_status};
+use crate::nfs::create::set_creator_owner;
+use crate::nfs::name::validate_name;
+use crate::nfs::setattr::requested_times;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
+use crate::rpc::auth::Credentials;
 
 /// Handle NFS MKDIR request
 ///
@@ -18,10 +24,18 @@ use crate::protocol::v3::rpc::RpcMessage;
 /// * `xid` - Transaction ID from RPC call
 /// * `args_data` - Serialized MKDIR3args
 /// * `filesystem` - Filesystem instance
+/// * `credentials` - Caller identity (after export squashing), owner of the new object
+/// * `modes` - Umask and default modes of the export
 ///
 /// # Returns
 /// Serialized RPC reply with MKDIR3res
-pub fn handle_mkdir(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -> Result<BytesMut> {
+pub fn handle_mkdir(
+    xid: u32,
+    args_data: &[u8],
+    filesystem: &dyn Filesystem,
+    credentials: &Credentials,
+    modes: &ModePolicy,
+) -> Result<BytesMut> {
     debug!("NFS MKDIR: xid={}", xid);
 
     // Parse arguments
@@ -33,18 +47,36 @@ pub fn handle_mkdir(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
         args.name.0
     );
 
+    if let Err(status) = validate_name(&args.name.0) {
+        return create_mkdir_response(xid, status, None, None, None);
+    }
+
     // Get parent directory attributes before operation (for wcc_data)
-    let dir_before = filesystem.getattr(&args.where_dir.0).ok();
+    let _dir_before = filesystem.getattr(&args.where_dir.0).ok();
 
-    // Extract mode from sattr3, default to 0755
-    let mode = match args.attributes.mode {
-        crate::protocol::v3::nfs::set_mode3::SET_MODE(m) => m,
-        crate::protocol::v3::nfs::set_mode3::default => 0o755,
-    };
+    // Extract mode from sattr3, under the export's umask and default
+    let mode = modes.dir(match args.attributes.mode {
+        crate::protocol::v3::nfs::set_mode3::SET_MODE(m) => Some(m),
+        crate::protocol::v3::nfs::set_mode3::default => None,
+    });
 
     // Perform mkdir operation
     match filesystem.mkdir(&args.where_dir.0, &args.name.0, mode) {
         Ok(new_dir_handle) => {
+            if let Err(status) =
+                set_creator_owner(filesystem, &args.where_dir.0, &args.name.0, &new_dir_handle, credentials)
+            {
+                return create_mkdir_response(xid, status, None, None, None);
+            }
+
+            // Apply requested times, as archive extraction sets them at creation
+            let (atime, mtime) = requested_times(&args.attributes);
+            if (atime.is_some() || mtime.is_some())
+                && let Err(e) = filesystem.setattr_times(&new_dir_handle, atime, mtime)
+            {
+                warn!("Failed to set times atime={:?} mtime={:?} on new directory: {}", atime, mtime, e);
+            }
+
             debug!("MKDIR OK: created directory '{}'", args.name.0);
 
             // Get new directory attributes
@@ -78,7 +110,11 @@ pub fn handle_mkdir(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
 
             // Determine appropriate error code
             let error_string = e.to_string();
-            let status = if error_string.contains("already exists") || error_string.contains("File exists") {
+            let status = if let Some(status) = handle_error_status(&e) {
+                status
+            } else if let Some(status) = permission_error_status(&e) {
+                status
+            } else if error_string.contains("already exists") || error_string.contains("File exists") {
                 nfsstat3::NFS3ERR_EXIST
             } else if error_string.contains("not found") || error_string.contains("No such") {
                 nfsstat3::NFS3ERR_NOENT
@@ -194,7 +230,7 @@ mod tests {
         fs::create_dir_all(&test_dir).unwrap();
 
         // Create filesystem
-        let fs = LocalFilesystem::new("/tmp/nfs_test_mkdir".to_string()).unwrap();
+        let fs = LocalFilesystem::new("/tmp/nfs_test_mkdir").unwrap();
 
         // Get root handle
         let root_handle = fs.root_handle();
@@ -213,17 +249,17 @@ mod tests {
 
         // attributes (sattr3)
         let sattr = crate::protocol::v3::nfs::sattr3 {
-            mode: Some(0o755),
-            uid: None,
-            gid: None,
-            size: None,
-            atime: crate::protocol::v3::nfs::set_atime::DONT_CHANGE,
-            mtime: crate::protocol::v3::nfs::set_mtime::DONT_CHANGE,
+            mode: crate::protocol::v3::nfs::set_mode3::SET_MODE(0o755),
+            uid: crate::protocol::v3::nfs::set_uid3::default,
+            gid: crate::protocol::v3::nfs::set_gid3::default,
+            size: crate::protocol::v3::nfs::set_size3::default,
+            atime: crate::protocol::v3::nfs::set_atime::default,
+            mtime: crate::protocol::v3::nfs::set_mtime::default,
         };
         sattr.pack(&mut args_buf).unwrap();
 
         // Call MKDIR
-        let result = handle_mkdir(12345, &args_buf, &fs);
+        let result = handle_mkdir(12345, &args_buf, &fs, &Credentials::anonymous(), &ModePolicy::default());
         assert!(result.is_ok(), "MKDIR should succeed");
 
         // Verify directory was created
@@ -236,48 +272,68 @@ mod tests {
     }
 
     #[test]
-    fn test_mkdir_already_exists() {
-        // Create test directory
-        let test_dir = PathBuf::from("/tmp/nfs_test_mkdir_exists");
-        let _ = fs::remove_dir_all(&test_dir);
-        fs::create_dir_all(&test_dir).unwrap();
-
-        // Create the directory beforehand
-        fs::create_dir(test_dir.join("existingdir")).unwrap();
-
-        // Create filesystem
-        let fs = LocalFilesystem::new("/tmp/nfs_test_mkdir_exists".to_string()).unwrap();
-
-        // Get root handle
-        let root_handle = fs.root_handle();
-
-        // Create MKDIR3args manually
+    fn test_mkdir_permission_errno() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::protocol::v3::nfs::{
+            fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
+        };
         use xdr_codec::Pack;
-        let mut args_buf = Vec::new();
 
-        let fhandle = crate::protocol::v3::nfs::fhandle3(root_handle.clone());
-        fhandle.pack(&mut args_buf).unwrap();
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        let mut args_buf = Vec::new();
+        fhandle3(fs.root_handle()).pack(&mut args_buf).unwrap();
+        filename3("denied".to_string()).pack(&mut args_buf).unwrap();
+        sattr3 {
+            mode: set_mode3::default,
+            uid: set_uid3::default,
+            gid: set_gid3::default,
+            size: set_size3::default,
+            atime: set_atime::default,
+            mtime: set_mtime::default,
+        }
+        .pack(&mut args_buf)
+        .unwrap();
+        let status = |errno| {
+            fs.fail("mkdir", errno);
+            let reply =
+                handle_mkdir(1, &args_buf, &fs, &Credentials::anonymous(), &ModePolicy::default()).unwrap();
+            i32::from_be_bytes(reply[24..28].try_into().unwrap())
+        };
 
-        let dirname = crate::protocol::v3::nfs::filename3("existingdir".to_string());
-        dirname.pack(&mut args_buf).unwrap();
+        assert_eq!(status(libc::EPERM), nfsstat3::NFS3ERR_PERM as i32);
+        assert_eq!(status(libc::EACCES), nfsstat3::NFS3ERR_ACCES as i32);
+    }
 
-        let sattr = crate::protocol::v3::nfs::sattr3 {
-            mode: Some(0o755),
-            uid: None,
-            gid: None,
-            size: None,
-            atime: crate::protocol::v3::nfs::set_atime::DONT_CHANGE,
-            mtime: crate::protocol::v3::nfs::set_mtime::DONT_CHANGE,
+    #[test]
+    fn test_mkdir_removed_when_owner_cannot_be_set() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::protocol::v3::nfs::{
+            fhandle3, filename3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
         };
-        sattr.pack(&mut args_buf).unwrap();
+        use xdr_codec::Pack;
 
-        // Call MKDIR - should return error response
-        let result = handle_mkdir(12345, &args_buf, &fs);
-        assert!(result.is_ok(), "MKDIR should return response (not crash)");
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        let mut args_buf = Vec::new();
+        fhandle3(fs.root_handle()).pack(&mut args_buf).unwrap();
+        filename3("unowned".to_string()).pack(&mut args_buf).unwrap();
+        sattr3 {
+            mode: set_mode3::default,
+            uid: set_uid3::default,
+            gid: set_gid3::default,
+            size: set_size3::default,
+            atime: set_atime::default,
+            mtime: set_mtime::default,
+        }
+        .pack(&mut args_buf)
+        .unwrap();
 
-        // TODO: Parse response and verify status is NFS3ERR_EXIST
+        fs.fail("setattr_owner", libc::EPERM);
+        let reply = handle_mkdir(1, &args_buf, &fs, &Credentials::anonymous(), &ModePolicy::default()).unwrap();
+        assert_eq!(i32::from_be_bytes(reply[24..28].try_into().unwrap()), nfsstat3::NFS3ERR_PERM as i32);
 
-        // Cleanup
-        fs::remove_dir_all(&test_dir).unwrap();
+        // The directory is not left behind with the server's identity
+        assert!(fs.lookup(&fs.root_handle(), "unowned").is_err());
     }
 }
@@ -6,14 +6,25 @@
 // - Creates character devices, block devices, FIFOs (named pipes), or sockets
 // - Requires appropriate permissions (typically root for device files)
 // - Returns file handle and attributes of the created special file
+// - Any other type (regular file, directory, symlink, or a value that is no
+//   file type at all) is NFS3ERR_BADTYPE
+//
+// The local backend creates all four types: FIFOs and sockets for any caller
+// that may write the directory, device nodes only with CAP_MKNOD.
 
 use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
+use crate::export::ModePolicy;
 use crate::fsal::{FileType, Filesystem};
+use crate::fsal::error::errno_of;
+use crate::nfs::error::{handle_error_status, permission_error_status, storage_error_status};
+use crate::nfs::create::set_creator_owner;
+use crate::nfs::name::validate_name;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
+use crate::rpc::auth::Credentials;
 
 /// Handle NFS MKNOD procedure (11)
 ///
@@ -23,14 +34,32 @@ use crate::protocol::v3::rpc::RpcMessage;
 /// * `xid` - RPC transaction ID
 /// * `args_data` - Serialized MKNOD3args
 /// * `filesystem` - Filesystem instance
+/// * `credentials` - Caller identity (after export squashing), owner of the new object
+/// * `modes` - Umask and default modes of the export
 ///
 /// # Returns
 /// Serialized MKNOD3res wrapped in RPC reply
-pub fn handle_mknod(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -> Result<BytesMut> {
+pub fn handle_mknod(
+    xid: u32,
+    args_data: &[u8],
+    filesystem: &dyn Filesystem,
+    credentials: &Credentials,
+    modes: &ModePolicy,
+) -> Result<BytesMut> {
     debug!("NFS MKNOD: xid={}", xid);
 
-    // Parse arguments
-    let args = NfsMessage::deserialize_mknod3args(args_data)?;
+    // Parse arguments; a type that is not a file type at all fails to decode
+    let args = match NfsMessage::deserialize_mknod3args(args_data) {
+        Ok(args) => args,
+        Err(e) => {
+            let Some(dir) = invalid_type_dir(args_data) else {
+                return Err(e);
+            };
+            debug!("MKNOD refused: invalid type");
+            let dir_attr = filesystem.getattr(&dir).ok().map(|attr| NfsMessage::fsal_to_fattr3(&attr));
+            return create_mknod_response(xid, nfsstat3::NFS3ERR_BADTYPE, None, None, dir_attr);
+        }
+    };
 
     debug!(
         "  dir: {} bytes, name: {}, type: {:?}",
@@ -39,6 +68,10 @@ pub fn handle_mknod(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
         args.what
     );
 
+    if let Err(status) = validate_name(&args.name.0) {
+        return create_mknod_response(xid, status, None, None, None);
+    }
+
     // Get directory attributes before operation (for wcc_data)
     let dir_before = filesystem.getattr(&args.where_dir.0).ok();
 
@@ -46,31 +79,40 @@ pub fn handle_mknod(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
     let (file_type, mode, rdev) = match &args.what {
         crate::protocol::v3::nfs::mknoddata3::NF3CHR(dev) => {
             debug!("  Creating character device: major={}, minor={}", dev.major, dev.minor);
-            let mode = extract_mode(&dev.dev_attributes);
+            let mode = extract_mode(&dev.dev_attributes, modes);
             (FileType::CharDevice, mode, (dev.major, dev.minor))
         }
         crate::protocol::v3::nfs::mknoddata3::NF3BLK(dev) => {
             debug!("  Creating block device: major={}, minor={}", dev.major, dev.minor);
-            let mode = extract_mode(&dev.dev_attributes);
+            let mode = extract_mode(&dev.dev_attributes, modes);
             (FileType::BlockDevice, mode, (dev.major, dev.minor))
         }
         crate::protocol::v3::nfs::mknoddata3::NF3SOCK(attrs) => {
             debug!("  Creating socket");
-            let mode = extract_mode(attrs);
+            let mode = extract_mode(attrs, modes);
             (FileType::Socket, mode, (0, 0))
         }
         crate::protocol::v3::nfs::mknoddata3::NF3FIFO(attrs) => {
             debug!("  Creating FIFO (named pipe)");
-            let mode = extract_mode(attrs);
+            let mode = extract_mode(attrs, modes);
             (FileType::NamedPipe, mode, (0, 0))
         }
+        crate::protocol::v3::nfs::mknoddata3::default => {
+            debug!("MKNOD refused: not a special file type");
+            let dir_attr = dir_before.map(|attr| NfsMessage::fsal_to_fattr3(&attr));
+            return create_mknod_response(xid, nfsstat3::NFS3ERR_BADTYPE, None, None, dir_attr);
+        }
     };
 
     let name = &args.name.0;
 
     // Perform mknod operation
-    match filesystem.mknod(&args.where_dir.0, &name, file_type, mode, rdev) {
+    match filesystem.mknod(&args.where_dir.0, name, file_type, mode, rdev) {
         Ok(handle) => {
+            if let Err(status) = set_creator_owner(filesystem, &args.where_dir.0, name, &handle, credentials) {
+                return create_mknod_response(xid, status, None, None, None);
+            }
+
             debug!("MKNOD OK: created {:?}", name);
 
             // Get attributes of the created special file
@@ -102,10 +144,27 @@ pub fn handle_mknod(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
     }
 }
 
-/// Extract mode from sattr3
-fn extract_mode(sattr: &crate::protocol::v3::nfs::sattr3) -> u32 {
+/// Directory of MKNOD3args whose type discriminator is not an ftype3
+///
+/// # Returns
+/// The directory handle if the arguments decode up to an invalid type, None
+/// if they are malformed before it or the type is valid
+fn invalid_type_dir(args_data: &[u8]) -> Option<Vec<u8>> {
+    use crate::protocol::v3::nfs::{fhandle3, filename3, ftype3};
+    use xdr_codec::Unpack;
+
+    let mut cursor = std::io::Cursor::new(args_data);
+    let (dir, _) = fhandle3::unpack(&mut cursor).ok()?;
+    filename3::unpack(&mut cursor).ok()?;
+    let (file_type, _) = i32::unpack(&mut cursor).ok()?;
+    let file_types = ftype3::NF3REG as i32..=ftype3::NF3FIFO as i32;
+    (!file_types.contains(&file_type)).then_some(dir.0)
+}
+
+/// Extract mode from sattr3, masked by the export's umask
+fn extract_mode(sattr: &crate::protocol::v3::nfs::sattr3, modes: &ModePolicy) -> u32 {
     match &sattr.mode {
-        crate::protocol::v3::nfs::set_mode3::SET_MODE(mode) => *mode,
+        crate::protocol::v3::nfs::set_mode3::SET_MODE(mode) => modes.masked(*mode),
         crate::protocol::v3::nfs::set_mode3::default => 0o666, // Default mode
     }
 }
@@ -113,7 +172,7 @@ fn extract_mode(sattr: &crate::protocol::v3::nfs::sattr3) -> u32 {
 /// Create MKNOD3res response
 ///
 /// MKNOD3res structure (RFC 1813):
-/// ```
+/// ```text
 /// union MKNOD3res switch (nfsstat3 status) {
 ///     case NFS3_OK:
 ///         struct {
@@ -194,12 +253,35 @@ fn create_mknod_response(
 }
 
 /// Map filesystem errors to NFS status codes
+///
+/// Backend errors carrying an errno are classified by it: an unprivileged
+/// device node is EPERM (NFS3ERR_PERM), a filesystem without special files
+/// EOPNOTSUPP (NFS3ERR_NOTSUPP). Messages are matched only for errors
+/// without one.
 fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
+    if let Some(status) = handle_error_status(error)
+        .or_else(|| storage_error_status(error))
+        .or_else(|| permission_error_status(error))
+    {
+        return status;
+    }
+
+    match errno_of(error) {
+        // ENOTSUP is the same errno on Linux
+        Some(libc::EOPNOTSUPP) => return nfsstat3::NFS3ERR_NOTSUPP,
+        Some(libc::EEXIST) => return nfsstat3::NFS3ERR_EXIST,
+        Some(libc::ENOENT) => return nfsstat3::NFS3ERR_NOENT,
+        Some(libc::ENOTDIR) => return nfsstat3::NFS3ERR_NOTDIR,
+        Some(libc::ENAMETOOLONG) => return nfsstat3::NFS3ERR_NAMETOOLONG,
+        Some(_) => return nfsstat3::NFS3ERR_IO,
+        None => {}
+    }
+
     let error_msg = error.to_string().to_lowercase();
 
     if error_msg.contains("not found") || error_msg.contains("no such file") {
         nfsstat3::NFS3ERR_NOENT // 2 - No such file or directory
-    } else if error_msg.contains("permission denied") || error_msg.contains("access denied") || error_msg.contains("operation not permitted") {
+    } else if error_msg.contains("permission denied") || error_msg.contains("access denied") {
         nfsstat3::NFS3ERR_ACCES // 13 - Permission denied
     } else if error_msg.contains("exists") || error_msg.contains("already") {
         nfsstat3::NFS3ERR_EXIST // 17 - File exists
@@ -207,9 +289,145 @@ fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
         nfsstat3::NFS3ERR_NOTDIR // 20 - Not a directory
     } else if error_msg.contains("read-only") {
         nfsstat3::NFS3ERR_ROFS // 30 - Read-only filesystem
-    } else if error_msg.contains("not supported") || error_msg.contains("not fully supported") {
+    } else if error_msg.contains("not supported") {
         nfsstat3::NFS3ERR_NOTSUPP // 10004 - Operation not supported
     } else {
         nfsstat3::NFS3ERR_IO // 5 - I/O error
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsal::faulty::FaultyFilesystem;
+    use crate::fsal::local::LocalFilesystem;
+    use crate::fsal::MemoryFilesystem;
+    use crate::protocol::v3::nfs::{
+        devicedata3, fhandle3, filename3, mknoddata3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3,
+        set_uid3,
+    };
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use tempfile::TempDir;
+    use xdr_codec::Pack;
+
+    fn attributes() -> sattr3 {
+        sattr3 {
+            mode: set_mode3::SET_MODE(0o640),
+            uid: set_uid3::default,
+            gid: set_gid3::default,
+            size: set_size3::default,
+            atime: set_atime::default,
+            mtime: set_mtime::default,
+        }
+    }
+
+    fn mknod_status(filesystem: &dyn Filesystem, name: &str, what: mknoddata3) -> i32 {
+        let mut args_buf = Vec::new();
+        fhandle3(filesystem.root_handle()).pack(&mut args_buf).unwrap();
+        filename3(name.to_string()).pack(&mut args_buf).unwrap();
+        what.pack(&mut args_buf).unwrap();
+        let reply =
+            handle_mknod(1, &args_buf, filesystem, &Credentials::anonymous(), &ModePolicy::default()).unwrap();
+        i32::from_be_bytes(reply[24..28].try_into().unwrap())
+    }
+
+    fn char_device() -> mknoddata3 {
+        // 1:3 is /dev/null
+        mknoddata3::NF3CHR(devicedata3 {
+            dev_attributes: attributes(),
+            major: 1,
+            minor: 3,
+        })
+    }
+
+    /// Run `f` on a thread whose effective uid is nobody, which drops
+    /// CAP_MKNOD (a no-op when the tests already run unprivileged)
+    fn unprivileged<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    // SAFETY: the raw syscall changes this thread's credentials
+                    // only, unlike libc's setresuid, which changes every thread's
+                    if unsafe { libc::geteuid() } == 0 {
+                        let result = unsafe { libc::syscall(libc::SYS_setresuid, u32::MAX, 65534u32, u32::MAX) };
+                        assert_eq!(result, 0, "setresuid: {}", std::io::Error::last_os_error());
+                    }
+                    f()
+                })
+                .join()
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn test_mknod_fifo_and_socket_unprivileged() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::set_permissions(temp_dir.path(), fs::Permissions::from_mode(0o777)).unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+
+        let (fifo, socket) = unprivileged(|| {
+            (
+                mknod_status(&fs, "pipe", mknoddata3::NF3FIFO(attributes())),
+                mknod_status(&fs, "sock", mknoddata3::NF3SOCK(attributes())),
+            )
+        });
+        assert_eq!(fifo, nfsstat3::NFS3_OK as i32);
+        assert_eq!(socket, nfsstat3::NFS3_OK as i32);
+
+        let pipe = fs::symlink_metadata(temp_dir.path().join("pipe")).unwrap();
+        assert!(pipe.file_type().is_fifo());
+        assert_eq!(pipe.permissions().mode() & 0o7777, 0o640);
+        assert!(fs::symlink_metadata(temp_dir.path().join("sock")).unwrap().file_type().is_socket());
+
+        // An existing name is NFS3ERR_EXIST, from the errno
+        assert_eq!(mknod_status(&fs, "pipe", mknoddata3::NF3FIFO(attributes())), nfsstat3::NFS3ERR_EXIST as i32);
+    }
+
+    #[test]
+    fn test_mknod_device_unprivileged_is_perm() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::set_permissions(temp_dir.path(), fs::Permissions::from_mode(0o777)).unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+
+        let status = unprivileged(|| mknod_status(&fs, "null", char_device()));
+        assert_eq!(status, nfsstat3::NFS3ERR_PERM as i32);
+        assert!(!temp_dir.path().join("null").exists());
+    }
+
+    #[test]
+    fn test_mknod_unsupported_is_notsupp() {
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        fs.fail("mknod", libc::EOPNOTSUPP);
+        assert_eq!(mknod_status(&fs, "null", char_device()), nfsstat3::NFS3ERR_NOTSUPP as i32);
+        fs.fail("mknod", libc::EACCES);
+        assert_eq!(mknod_status(&fs, "null", char_device()), nfsstat3::NFS3ERR_ACCES as i32);
+    }
+
+    #[test]
+    fn test_mknod_invalid_type_is_badtype() {
+        use crate::protocol::v3::nfs::ftype3;
+
+        let fs = MemoryFilesystem::new();
+        let mknod_type_status = |file_type: i32| {
+            let mut args_buf = Vec::new();
+            fhandle3(fs.root_handle()).pack(&mut args_buf).unwrap();
+            filename3("node".to_string()).pack(&mut args_buf).unwrap();
+            file_type.pack(&mut args_buf).unwrap();
+            let reply =
+                handle_mknod(1, &args_buf, &fs, &Credentials::anonymous(), &ModePolicy::default()).unwrap();
+            i32::from_be_bytes(reply[24..28].try_into().unwrap())
+        };
+
+        // Types MKNOD does not create, and a discriminator that is no type
+        for file_type in [ftype3::NF3REG as i32, ftype3::NF3DIR as i32, ftype3::NF3LNK as i32, 99] {
+            assert_eq!(mknod_type_status(file_type), nfsstat3::NFS3ERR_BADTYPE as i32, "type {}", file_type);
+        }
+        assert!(fs.lookup(&fs.root_handle(), "node").is_err());
+
+        // Arguments cut short before the type are still malformed
+        let mut args_buf = Vec::new();
+        fhandle3(fs.root_handle()).pack(&mut args_buf).unwrap();
+        assert!(handle_mknod(1, &args_buf, &fs, &Credentials::anonymous(), &ModePolicy::default()).is_err());
+    }
+}
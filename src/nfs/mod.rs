@@ -7,6 +7,7 @@ pub mod dispatcher;
 mod access;
 mod commit;
 mod create;
+pub mod error;
 mod fsinfo;
 mod fsstat;
 mod getattr;
@@ -14,9 +15,10 @@ mod link;
 mod lookup;
 mod mkdir;
 mod mknod;
+pub mod name;
 mod null;
 mod pathconf;
-mod read;
+pub mod read;
 mod readdir;
 mod readdirplus;
 mod readlink;
@@ -25,6 +27,13 @@ mod rename;
 mod rmdir;
 mod setattr;
 mod symlink;
+pub mod verifier;
 mod write;
 
-pub use dispatcher::dispatch;
+pub use dispatcher::{dispatch, is_mutating};
+
+/// NFS program number (RFC 1813)
+pub const NFS_PROGRAM: u32 = 100003;
+
+/// NFS version 3, the only version served
+pub const NFS_V3: u32 = 3;
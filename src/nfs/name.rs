@@ -0,0 +1,86 @@
+// Filename Validation
+//
+// Checks applied to every filename3 argument (and to symlink targets) before
+// it reaches the FSAL, so that limits advertised by PATHCONF are enforced
+// with the right status code instead of surfacing as an OS error. Path
+// traversal ("/", or ".." inside a name) is still rejected by the backends;
+// LOOKUP resolves the name ".." itself through the parent handle.
+//
+// Names reach the FSAL as UTF-8 strings. A name that is not valid UTF-8
+// fails to decode and is answered NFS3ERR_INVAL rather than converted
+// lossily, which would make it name a different file.
+
+use crate::protocol::v3::nfs::nfsstat3;
+
+/// Maximum filename length in bytes (PATHCONF name_max)
+pub const NAME_MAX: usize = 255;
+
+/// Maximum symlink target length in bytes, including the terminating NUL
+pub const PATH_MAX: usize = 4096;
+
+/// Validate a filename3 argument
+///
+/// # Returns
+/// NFS3ERR_INVAL for an empty name or one with an embedded NUL,
+/// NFS3ERR_NAMETOOLONG for a name longer than `NAME_MAX` bytes
+pub fn validate_name(name: &str) -> Result<(), nfsstat3> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(nfsstat3::NFS3ERR_INVAL);
+    }
+    if name.len() > NAME_MAX {
+        return Err(nfsstat3::NFS3ERR_NAMETOOLONG);
+    }
+    Ok(())
+}
+
+/// Validate a SYMLINK target (nfspath3)
+///
+/// Targets are stored verbatim, so relative and absolute paths are both
+/// accepted; only the length is checked.
+///
+/// # Returns
+/// NFS3ERR_INVAL for an empty target or one with an embedded NUL,
+/// NFS3ERR_NAMETOOLONG for a target that does not fit in `PATH_MAX` bytes
+/// with its terminating NUL
+pub fn validate_symlink_target(target: &str) -> Result<(), nfsstat3> {
+    if target.is_empty() || target.contains('\0') {
+        return Err(nfsstat3::NFS3ERR_INVAL);
+    }
+    if target.len() >= PATH_MAX {
+        return Err(nfsstat3::NFS3ERR_NAMETOOLONG);
+    }
+    Ok(())
+}
+
+/// Whether decoding arguments failed on a string (filename3, nfspath3) that
+/// is not valid UTF-8
+pub fn is_invalid_utf8(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<xdr_codec::Error>().map(|e| e.kind()),
+        Some(xdr_codec::ErrorKind::InvalidUtf8(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert_eq!(validate_name("file.txt"), Ok(()));
+        assert_eq!(validate_name(&"a".repeat(NAME_MAX)), Ok(()));
+        assert_eq!(validate_name(&"a".repeat(NAME_MAX + 1)), Err(nfsstat3::NFS3ERR_NAMETOOLONG));
+        assert_eq!(validate_name(""), Err(nfsstat3::NFS3ERR_INVAL));
+        assert_eq!(validate_name("a\0b"), Err(nfsstat3::NFS3ERR_INVAL));
+    }
+
+    #[test]
+    fn test_validate_symlink_target() {
+        assert_eq!(validate_symlink_target("../relative/target"), Ok(()));
+        assert_eq!(validate_symlink_target("/absolute/target"), Ok(()));
+        assert_eq!(validate_symlink_target(&"a".repeat(PATH_MAX - 1)), Ok(()));
+        assert_eq!(validate_symlink_target(&"a".repeat(PATH_MAX)), Err(nfsstat3::NFS3ERR_NAMETOOLONG));
+        assert_eq!(validate_symlink_target(""), Err(nfsstat3::NFS3ERR_INVAL));
+        assert_eq!(validate_symlink_target("target\0/etc/passwd"), Err(nfsstat3::NFS3ERR_INVAL));
+    }
+}
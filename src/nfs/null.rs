@@ -22,11 +22,8 @@ use crate::protocol::v3::rpc::RpcMessage;
 pub fn handle_null(xid: u32) -> Result<BytesMut> {
     debug!("NFS NULL called (xid={})", xid);
 
-    // Create successful reply (same as RPC/MOUNT NULL)
-    let reply = RpcMessage::create_null_reply(xid);
-
-    // Serialize reply
-    RpcMessage::serialize_reply(&reply)
+    // Same void reply as MOUNT and PORTMAP NULL
+    RpcMessage::create_void_reply(xid)
 }
 
 #[cfg(test)]
@@ -46,4 +43,11 @@ mod tests {
         // Reply should be at least 24 bytes (RPC header minimum)
         assert!(reply.len() >= 24, "Reply should have RPC header");
     }
+
+    #[test]
+    fn test_null_reply_is_void_success() {
+        let reply = handle_null(7).unwrap();
+        assert_eq!(&reply[0..4], &7u32.to_be_bytes());
+        assert_eq!(RpcMessage::success_reply_results(&reply), Some(&[][..]));
+    }
 }
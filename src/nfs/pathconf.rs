@@ -8,6 +8,8 @@ use tracing::debug;
 use xdr_codec::Pack;
 
 use crate::fsal::Filesystem;
+use crate::nfs::error::handle_error_status;
+use crate::nfs::name::NAME_MAX;
 use crate::protocol::v3::nfs::{fattr3, nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
@@ -36,7 +38,8 @@ pub fn handle_pathconf(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem)
         Ok(attr) => NfsMessage::fsal_to_fattr3(&attr),
         Err(e) => {
             debug!("PATHCONF failed: {}", e);
-            return create_pathconf_error(xid, nfsstat3::NFS3ERR_STALE);
+            let status = handle_error_status(&e).unwrap_or(nfsstat3::NFS3ERR_STALE);
+            return create_pathconf_error(xid, status);
         }
     };
 
@@ -44,7 +47,7 @@ pub fn handle_pathconf(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem)
     let response = create_pathconf_ok(
         obj_attrs,
         255,    // linkmax - maximum number of hard links
-        255,    // name_max - maximum filename length
+        NAME_MAX as u32, // name_max - maximum filename length
         true,   // no_trunc - server will reject names longer than name_max
         true,   // chown_restricted - only privileged user can change file ownership
         false,  // case_insensitive - filenames are case-sensitive
@@ -3,13 +3,19 @@
 // Reads data from a file
 
 use anyhow::Result;
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
 use tracing::debug;
 
-use crate::fsal::Filesystem;
+use crate::fsal::{FileType, Filesystem};
+use crate::nfs::error::{handle_error_status, io_error_status, is_retry};
+use crate::nfs::fsinfo::range_end;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
+/// Size of a successful READ3resok ahead of the data: status, post_op_attr
+/// (flag + 84-byte fattr3), count, eof and the opaque length
+const READ_RESULT_HEADER_LEN: usize = 4 + 4 + 84 + 4 + 4 + 4;
+
 /// Handle NFS READ procedure (procedure 6)
 ///
 /// Reads data from a file at a specified offset.
@@ -18,6 +24,7 @@ use crate::protocol::v3::rpc::RpcMessage;
 /// * `xid` - Transaction ID from the request
 /// * `args_data` - Serialized READ3args (file handle + offset + count)
 /// * `filesystem` - Filesystem instance
+/// * `read_max` - Largest READ the export allows (rtmax); longer READs return short
 ///
 /// # Returns
 /// Serialized RPC reply message with file data
@@ -25,6 +32,7 @@ pub fn handle_read(
     xid: u32,
     args_data: &[u8],
     filesystem: &dyn Filesystem,
+    read_max: u32,
 ) -> Result<BytesMut> {
     debug!("NFS READ called (xid={})", xid);
 
@@ -38,22 +46,48 @@ pub fn handle_read(
         args.count
     );
 
-    // Read data from the file
-    let data = match filesystem.read(&args.file.0, args.offset, args.count) {
-        Ok(data) => data,
+    // A range past the largest file size (or wrapping around) holds nothing
+    // a client could have written
+    if range_end(args.offset, args.count).is_none() {
+        debug!("READ refused: offset {} + count {} is out of range", args.offset, args.count);
+        let res_data = NfsMessage::create_read_error_response(nfsstat3::NFS3ERR_INVAL)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
+    // Only regular files hold data: refuse directories by their type up front
+    if let Ok(attrs) = filesystem.getattr(&args.file.0)
+        && attrs.ftype == FileType::Directory
+    {
+        debug!("READ refused: handle is a directory");
+        let res_data = NfsMessage::create_read_error_response(nfsstat3::NFS3ERR_ISDIR)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
+    // Never read more than FSINFO advertised; the client reads the rest next
+    let count = args.count.min(read_max) as usize;
+
+    // The reply is allocated once with room for the data, which is read
+    // straight into it: the RPC and READ3resok headers are filled in ahead
+    // of it afterwards, once the attributes are known
+    let rpc_header = RpcMessage::serialize_reply(&RpcMessage::create_null_reply(xid))?;
+    let data_start = rpc_header.len() + READ_RESULT_HEADER_LEN;
+    let mut response = BytesMut::with_capacity(data_start + count + 3);
+    response.resize(data_start + count, 0);
+    let bytes_read = match filesystem.read_into(&args.file.0, args.offset, &mut response[data_start..]) {
+        Ok(bytes_read) => bytes_read,
         Err(e) => {
             debug!("READ failed: {}", e);
             // Return appropriate NFS error
-            let error_status = if e.to_string().contains("not found")
-                || e.to_string().contains("Invalid handle")
-            {
+            let error_status = if is_retry(&e) {
+                nfsstat3::NFS3ERR_JUKEBOX
+            } else if let Some(status) = handle_error_status(&e) {
+                status
+            } else if e.to_string().contains("not found") {
                 nfsstat3::NFS3ERR_STALE
-            } else if e.to_string().contains("Not a file") {
-                nfsstat3::NFS3ERR_ISDIR
             } else if e.to_string().contains("Permission denied") {
                 nfsstat3::NFS3ERR_ACCES
             } else {
-                nfsstat3::NFS3ERR_IO
+                io_error_status("READ", &args.file.0, &e)
             };
 
             let res_data = NfsMessage::create_read_error_response(error_status)?;
@@ -67,14 +101,17 @@ pub fn handle_read(
         Err(e) => {
             debug!("READ: failed to get file attributes: {}", e);
             // Still return error even if we read successfully but can't get attrs
-            let error_status = nfsstat3::NFS3ERR_IO;
+            let error_status = if is_retry(&e) {
+                nfsstat3::NFS3ERR_JUKEBOX
+            } else {
+                nfsstat3::NFS3ERR_IO
+            };
             let res_data = NfsMessage::create_read_error_response(error_status)?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
 
     // Determine if we've reached end of file
-    let bytes_read = data.len() as u32;
     let eof = (args.offset + bytes_read as u64) >= file_attrs.size;
 
     debug!(
@@ -87,43 +124,37 @@ pub fn handle_read(
 
     // Create READ response manually with post_op_attr format
     use xdr_codec::Pack;
-    let mut buf = Vec::new();
+    response[..rpc_header.len()].copy_from_slice(&rpc_header);
+    let mut writer = &mut response[rpc_header.len()..data_start];
 
     // 1. nfsstat3 status = NFS3_OK (0)
-    (nfsstat3::NFS3_OK as i32).pack(&mut buf)?;
+    (nfsstat3::NFS3_OK as i32).pack(&mut writer)?;
 
     // 2. post_op_attr (file_attributes)
-    true.pack(&mut buf)?;  // attributes_follow = TRUE
-    nfs_attrs.pack(&mut buf)?;
+    true.pack(&mut writer)?;  // attributes_follow = TRUE
+    nfs_attrs.pack(&mut writer)?;
 
     // 3. count (bytes read)
-    bytes_read.pack(&mut buf)?;
+    (bytes_read as u32).pack(&mut writer)?;
 
     // 4. eof (end of file)
-    eof.pack(&mut buf)?;
-
-    // 5. data (opaque<>) - pack as variable-length opaque data
-    // XDR opaque format: length (u32) + data + padding to 4-byte boundary
-    let data_len = data.len() as u32;
-    data_len.pack(&mut buf)?;
-    buf.extend_from_slice(&data);
-
-    // Add padding to align to 4-byte boundary
-    let padding = (4 - (data.len() % 4)) % 4;
-    for _ in 0..padding {
-        buf.push(0);
-    }
+    eof.pack(&mut writer)?;
 
-    let res_data = BytesMut::from(&buf[..]);
+    // 5. data (opaque<>) - length, then the bytes already in place, then
+    // padding to a 4-byte boundary
+    (bytes_read as u32).pack(&mut writer)?;
+    debug_assert!(writer.is_empty(), "READ3resok header is {} bytes", READ_RESULT_HEADER_LEN);
+    response.truncate(data_start + bytes_read);
+    response.put_bytes(0, (4 - bytes_read % 4) % 4);
 
-    // Wrap in RPC reply
-    RpcMessage::create_success_reply_with_data(xid, res_data)
+    Ok(response)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::fsal::{BackendConfig, Filesystem};
+    use crate::export::DEFAULT_MAX_TRANSFER;
+    use crate::fsal::BackendConfig;
     use std::fs;
     use tempfile::TempDir;
 
@@ -156,7 +187,7 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call READ
-        let result = handle_read(12345, &args_buf, fs.as_ref());
+        let result = handle_read(12345, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER);
 
         assert!(result.is_ok(), "READ should succeed");
 
@@ -193,11 +224,68 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call READ
-        let result = handle_read(12345, &args_buf, fs.as_ref());
+        let result = handle_read(12345, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER);
 
         assert!(result.is_ok(), "Partial READ should succeed");
     }
 
+    #[test]
+    fn test_read_large_file_single_buffer() {
+        use crate::protocol::v3::nfs::READ3args;
+        use xdr_codec::Pack;
+
+        // 1 MiB plus an odd tail so the reply needs XDR padding
+        let temp_dir = TempDir::new().unwrap();
+        let content: Vec<u8> = (0..(1 << 20) + 3).map(|i| (i % 251) as u8).collect();
+        fs::write(temp_dir.path().join("large.bin"), &content).unwrap();
+
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let file_handle = fs.lookup(&fs.root_handle(), "large.bin").unwrap();
+
+        let args = READ3args {
+            file: crate::protocol::v3::nfs::fhandle3(file_handle),
+            offset: 0,
+            count: content.len() as u32,
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        let reply = handle_read(7, &args_buf, fs.as_ref(), content.len() as u32).unwrap();
+        let word = |at: usize| u32::from_be_bytes([reply[at], reply[at + 1], reply[at + 2], reply[at + 3]]);
+
+        // RPC header (24) + READ3resok header, then the data and one padding byte
+        let data_start = 24 + READ_RESULT_HEADER_LEN;
+        assert_eq!(word(24), nfsstat3::NFS3_OK as u32);
+        assert_eq!(word(data_start - 12), content.len() as u32, "count");
+        assert_eq!(word(data_start - 8), 1, "eof");
+        assert_eq!(word(data_start - 4), content.len() as u32, "opaque length");
+        assert_eq!(&reply[data_start..data_start + content.len()], &content[..]);
+        assert_eq!(&reply[data_start + content.len()..], &[0u8]);
+        assert_eq!(reply.len() % 4, 0);
+    }
+
+    #[test]
+    fn test_read_directory_is_isdir() {
+        use crate::protocol::v3::nfs::READ3args;
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("subdir")).unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let dir_handle = fs.lookup(&fs.root_handle(), "subdir").unwrap();
+
+        let args = READ3args {
+            file: crate::protocol::v3::nfs::fhandle3(dir_handle),
+            offset: 0,
+            count: 100,
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        let reply = handle_read(8, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER).unwrap();
+        assert_eq!(&reply[24..28], &(nfsstat3::NFS3ERR_ISDIR as u32).to_be_bytes());
+    }
+
     #[test]
     fn test_read_nonexistent_handle() {
         // Create temp filesystem
@@ -219,8 +307,82 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call READ
-        let result = handle_read(12345, &args_buf, fs.as_ref());
+        let result = handle_read(12345, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER);
 
         assert!(result.is_ok(), "READ should return error response (not panic)");
     }
+
+    #[test]
+    fn test_read_retry_is_jukebox() {
+        use crate::fsal::{MemoryFilesystem, StableHow};
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::protocol::v3::nfs::{READ3args, fhandle3};
+        use xdr_codec::Pack;
+
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        let file = fs.create(&fs.root_handle(), "cold.bin", 0o644).unwrap();
+        fs.write(&file, 0, b"archived", StableHow::FileSync).unwrap();
+
+        let mut args_buf = Vec::new();
+        READ3args { file: fhandle3(file), offset: 0, count: 64 }.pack(&mut args_buf).unwrap();
+
+        // The object is in cold storage: the client is told to retry
+        fs.retry("read");
+        let reply = handle_read(12345, &args_buf, &fs, DEFAULT_MAX_TRANSFER).unwrap();
+        let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+        assert_eq!(status, nfsstat3::NFS3ERR_JUKEBOX as i32);
+
+        // Once recalled the same READ succeeds
+        fs.heal("read");
+        let reply = handle_read(12345, &args_buf, &fs, DEFAULT_MAX_TRANSFER).unwrap();
+        let status = i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+        assert_eq!(status, nfsstat3::NFS3_OK as i32);
+    }
+
+    #[test]
+    fn test_read_handle_errors() {
+        use crate::protocol::v3::nfs::{READ3args, fhandle3, nfsstat3};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let root = fs.root_handle();
+        let deleted = fs.create(&root, "gone", 0o644).unwrap();
+        fs.remove(&root, "gone").unwrap();
+
+        let status = |handle: Vec<u8>| {
+            let mut args_buf = Vec::new();
+            READ3args { file: fhandle3(handle), offset: 0, count: 100 }.pack(&mut args_buf).unwrap();
+            let reply = handle_read(12345, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER).unwrap();
+            i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+        };
+        assert_eq!(status(Vec::new()), nfsstat3::NFS3ERR_BADHANDLE as i32);
+        assert_eq!(status(vec![0xDE, 0xAD, 0xBE, 0xEF]), nfsstat3::NFS3ERR_BADHANDLE as i32);
+        assert_eq!(status(deleted), nfsstat3::NFS3ERR_STALE as i32);
+    }
+
+    #[test]
+    fn test_read_out_of_range_offset_is_inval() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{READ3args, fhandle3, nfsstat3};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let local = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let memory = MemoryFilesystem::new();
+
+        for fs in [local.as_ref(), &memory as &dyn Filesystem] {
+            let file = fs.create(&fs.root_handle(), "small.txt", 0o644).unwrap();
+            let status = |offset: u64| {
+                let mut args_buf = Vec::new();
+                READ3args { file: fhandle3(file.clone()), offset, count: 100 }.pack(&mut args_buf).unwrap();
+                let reply = handle_read(12345, &args_buf, fs, DEFAULT_MAX_TRANSFER).unwrap();
+                i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+            };
+            assert_eq!(status(u64::MAX - 10), nfsstat3::NFS3ERR_INVAL as i32);
+            assert_eq!(status(i64::MAX as u64), nfsstat3::NFS3ERR_INVAL as i32);
+            // Past EOF but addressable is a plain empty read
+            assert_eq!(status(1 << 40), nfsstat3::NFS3_OK as i32);
+        }
+    }
 }
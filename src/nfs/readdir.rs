@@ -6,8 +6,10 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
-use crate::fsal::Filesystem;
-use crate::protocol::v3::nfs::{cookieverf3, entry3, fileid3, nfsstat3, NfsMessage, COOKIEVERFSIZE};
+use crate::fsal::{FileType, Filesystem};
+use crate::nfs::error::handle_error_status;
+use crate::nfs::verifier::{cookie_verifier, cookie_verifier_matches};
+use crate::protocol::v3::nfs::{cookieverf3, fileid3, nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
 /// Handle NFS READDIR request
@@ -34,20 +36,36 @@ pub fn handle_readdir(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -
 
     // Get directory attributes
     let dir_attr = match filesystem.getattr(&args.dir.0) {
-        Ok(attr) => NfsMessage::fsal_to_fattr3(&attr),
+        Ok(attr) if attr.ftype != FileType::Directory => {
+            warn!("READDIR refused: {:?} handle is not a directory", attr.ftype);
+            let res_data = NfsMessage::create_readdir_error_response(nfsstat3::NFS3ERR_NOTDIR)?;
+            return RpcMessage::create_success_reply_with_data(xid, res_data);
+        }
+        Ok(attr) => attr,
         Err(e) => {
             warn!("READDIR failed: getattr error: {}", e);
-            let res_data = NfsMessage::create_readdir_error_response(nfsstat3::NFS3ERR_IO)?;
+            let status = handle_error_status(&e).unwrap_or(nfsstat3::NFS3ERR_IO);
+            let res_data = NfsMessage::create_readdir_error_response(status)?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
 
+    // A listing resumed across a change to the directory must restart
+    if !cookie_verifier_matches(args.cookie, &args.cookieverf.0, &dir_attr) {
+        debug!("READDIR: directory changed since cookie {} was issued", args.cookie);
+        let res_data = NfsMessage::create_readdir_error_response(nfsstat3::NFS3ERR_BAD_COOKIE)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+    let cookieverf = cookieverf3(cookie_verifier(&dir_attr));
+    let dir_attr = NfsMessage::fsal_to_fattr3(&dir_attr);
+
     // Read directory entries
     let (entries, eof) = match filesystem.readdir(&args.dir.0, args.cookie, args.count) {
         Ok(result) => result,
         Err(e) => {
             warn!("READDIR failed: {}", e);
-            let res_data = NfsMessage::create_readdir_error_response(nfsstat3::NFS3ERR_IO)?;
+            let status = handle_error_status(&e).unwrap_or(nfsstat3::NFS3ERR_IO);
+            let res_data = NfsMessage::create_readdir_error_response(status)?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
@@ -67,7 +85,6 @@ pub fn handle_readdir(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -
     dir_attr.pack(&mut buf)?;
 
     // 3. cookieverf
-    let cookieverf = cookieverf3([0u8; COOKIEVERFSIZE as usize]);
     cookieverf.pack(&mut buf)?;
 
     // 4. dirlist3 (entry list)
@@ -109,3 +126,59 @@ pub fn handle_readdir(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -
     // Wrap in RPC reply
     RpcMessage::create_success_reply_with_data(xid, res_data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsal::local::LocalFilesystem;
+    use crate::protocol::v3::nfs::{fhandle3, COOKIEVERFSIZE};
+    use std::fs;
+    use std::time::Duration;
+    use xdr_codec::Pack;
+
+    /// Offset of the cookie verifier in a successful reply: RPC header,
+    /// status, then post_op_attr with an 84-byte fattr3
+    const VERF_OFFSET: usize = 24 + 4 + 4 + 84;
+
+    fn readdir(fs: &LocalFilesystem, cookie: u64, verifier: [u8; COOKIEVERFSIZE as usize]) -> BytesMut {
+        let mut args_buf = Vec::new();
+        fhandle3(fs.root_handle()).pack(&mut args_buf).unwrap();
+        cookie.pack(&mut args_buf).unwrap();
+        cookieverf3(verifier).pack(&mut args_buf).unwrap();
+        8192u32.pack(&mut args_buf).unwrap();
+        handle_readdir(1, &args_buf, fs).unwrap()
+    }
+
+    fn status(reply: &[u8]) -> u32 {
+        u32::from_be_bytes(reply[24..28].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_resume_after_directory_change_is_bad_cookie() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in ["a", "b", "c"] {
+            fs::write(temp_dir.path().join(name), "x").unwrap();
+        }
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap().with_attr_cache(Duration::ZERO, 16);
+
+        let reply = readdir(&fs, 0, [0u8; COOKIEVERFSIZE as usize]);
+        assert_eq!(status(&reply), nfsstat3::NFS3_OK as u32);
+        let verifier: [u8; 8] = reply[VERF_OFFSET..VERF_OFFSET + 8].try_into().unwrap();
+        assert_ne!(verifier, [0u8; 8]);
+
+        // Resuming an unchanged directory is fine
+        assert_eq!(status(&readdir(&fs, 2, verifier)), nfsstat3::NFS3_OK as u32);
+
+        // Directory timestamps come from a coarse clock; let it tick
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(temp_dir.path().join("d"), "x").unwrap();
+        assert_eq!(status(&readdir(&fs, 2, verifier)), nfsstat3::NFS3ERR_BAD_COOKIE as u32);
+
+        // Restarting the scan hands out the new verifier
+        let reply = readdir(&fs, 0, verifier);
+        assert_eq!(status(&reply), nfsstat3::NFS3_OK as u32);
+        let new_verifier: [u8; 8] = reply[VERF_OFFSET..VERF_OFFSET + 8].try_into().unwrap();
+        assert_ne!(new_verifier, verifier);
+        assert_eq!(status(&readdir(&fs, 2, new_verifier)), nfsstat3::NFS3_OK as u32);
+    }
+}
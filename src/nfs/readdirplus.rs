@@ -7,8 +7,10 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
-use crate::fsal::Filesystem;
-use crate::protocol::v3::nfs::{cookieverf3, nfsstat3, NfsMessage, COOKIEVERFSIZE};
+use crate::fsal::{DirEntryPlus, FileType, Filesystem};
+use crate::nfs::error::handle_error_status;
+use crate::nfs::verifier::{cookie_verifier, cookie_verifier_matches};
+use crate::protocol::v3::nfs::{cookieverf3, nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
 /// Handle NFS READDIRPLUS request
@@ -47,21 +49,37 @@ pub fn handle_readdirplus(
 
     // Get directory attributes
     let dir_attr = match filesystem.getattr(&args.dir.0) {
-        Ok(attr) => NfsMessage::fsal_to_fattr3(&attr),
+        Ok(attr) if attr.ftype != FileType::Directory => {
+            warn!("READDIRPLUS refused: {:?} handle is not a directory", attr.ftype);
+            let res_data = NfsMessage::create_readdirplus_error_response(nfsstat3::NFS3ERR_NOTDIR)?;
+            return RpcMessage::create_success_reply_with_data(xid, res_data);
+        }
+        Ok(attr) => attr,
         Err(e) => {
             warn!("READDIRPLUS failed: getattr error: {}", e);
-            let res_data = NfsMessage::create_readdirplus_error_response(nfsstat3::NFS3ERR_IO)?;
+            let status = handle_error_status(&e).unwrap_or(nfsstat3::NFS3ERR_IO);
+            let res_data = NfsMessage::create_readdirplus_error_response(status)?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
 
-    // Read directory entries
+    // A listing resumed across a change to the directory must restart
+    if !cookie_verifier_matches(args.cookie, &args.cookieverf.0, &dir_attr) {
+        debug!("READDIRPLUS: directory changed since cookie {} was issued", args.cookie);
+        let res_data = NfsMessage::create_readdirplus_error_response(nfsstat3::NFS3ERR_BAD_COOKIE)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+    let cookieverf = cookieverf3(cookie_verifier(&dir_attr));
+    let dir_attr = NfsMessage::fsal_to_fattr3(&dir_attr);
+
+    // Read directory entries with their attributes and handles
     // Use dircount as the count parameter (RFC 1813 says dircount is for entry names)
-    let (entries, eof) = match filesystem.readdir(&args.dir.0, args.cookie, args.dircount) {
+    let (entries, eof) = match filesystem.readdir_plus(&args.dir.0, args.cookie, args.dircount, args.maxcount) {
         Ok(result) => result,
         Err(e) => {
             warn!("READDIRPLUS failed: {}", e);
-            let res_data = NfsMessage::create_readdirplus_error_response(nfsstat3::NFS3ERR_IO)?;
+            let status = handle_error_status(&e).unwrap_or(nfsstat3::NFS3ERR_IO);
+            let res_data = NfsMessage::create_readdirplus_error_response(status)?;
             return RpcMessage::create_success_reply_with_data(xid, res_data);
         }
     };
@@ -81,7 +99,6 @@ pub fn handle_readdirplus(
     dir_attr.pack(&mut buf)?;
 
     // 3. cookieverf
-    let cookieverf = cookieverf3([0u8; COOKIEVERFSIZE as usize]);
     cookieverf.pack(&mut buf)?;
 
     // 4. dirlistplus3 (entry list with attributes and handles)
@@ -90,7 +107,7 @@ pub fn handle_readdirplus(
     // entryplus3 = fileid + name + cookie + post_op_attr + post_op_fh3
     // End of list: false
     let mut cookie_counter = args.cookie;
-    for dir_entry in entries.iter() {
+    for DirEntryPlus { entry: dir_entry, attributes, handle } in entries.iter() {
         cookie_counter += 1;
 
         // Boolean discriminator: true = entry follows
@@ -105,36 +122,27 @@ pub fn handle_readdirplus(
 
         cookie_counter.pack(&mut buf)?;
 
-        // post_op_attr: Get attributes for this entry
-        // We need to lookup the file handle first
-        match filesystem.lookup(&args.dir.0, &dir_entry.name) {
-            Ok(entry_handle) => {
-                // Get attributes for this entry
-                match filesystem.getattr(&entry_handle) {
-                    Ok(entry_attr) => {
-                        // post_op_attr: true + fattr3
-                        true.pack(&mut buf)?;
-                        let fattr = NfsMessage::fsal_to_fattr3(&entry_attr);
-                        fattr.pack(&mut buf)?;
-
-                        // post_op_fh3: true + fhandle3
-                        true.pack(&mut buf)?;
-                        let fhandle = crate::protocol::v3::nfs::fhandle3(entry_handle);
-                        fhandle.pack(&mut buf)?;
-                    }
-                    Err(e) => {
-                        // Failed to get attributes - return empty post_op_attr and post_op_fh3
-                        warn!("READDIRPLUS: failed to get attributes for {}: {}", dir_entry.name, e);
-                        false.pack(&mut buf)?; // post_op_attr: no attributes
-                        false.pack(&mut buf)?; // post_op_fh3: no handle
-                    }
-                }
+        // post_op_attr: true + fattr3, or false if the backend could not
+        // fetch the entry's attributes
+        match attributes {
+            Some(entry_attr) => {
+                true.pack(&mut buf)?;
+                NfsMessage::fsal_to_fattr3(entry_attr).pack(&mut buf)?;
+            }
+            None => {
+                debug!("READDIRPLUS: no attributes for {}", dir_entry.name);
+                false.pack(&mut buf)?;
+            }
+        }
+
+        // post_op_fh3: true + fhandle3, or false without a handle
+        match handle {
+            Some(entry_handle) => {
+                true.pack(&mut buf)?;
+                crate::protocol::v3::nfs::fhandle3(entry_handle.clone()).pack(&mut buf)?;
             }
-            Err(e) => {
-                // Failed to lookup - return empty post_op_attr and post_op_fh3
-                warn!("READDIRPLUS: failed to lookup {}: {}", dir_entry.name, e);
-                false.pack(&mut buf)?; // post_op_attr: no attributes
-                false.pack(&mut buf)?; // post_op_fh3: no handle
+            None => {
+                false.pack(&mut buf)?;
             }
         }
     }
@@ -162,6 +170,7 @@ pub fn handle_readdirplus(
 mod tests {
     use super::*;
     use crate::fsal::local::LocalFilesystem;
+    use crate::protocol::v3::nfs::COOKIEVERFSIZE;
     use std::fs;
     use std::path::PathBuf;
 
@@ -178,7 +187,7 @@ mod tests {
         fs::create_dir(test_dir.join("subdir")).unwrap();
 
         // Create filesystem
-        let fs = LocalFilesystem::new("/tmp/nfs_test_readdirplus".to_string()).unwrap();
+        let fs = LocalFilesystem::new("/tmp/nfs_test_readdirplus").unwrap();
 
         // Get root handle
         let root_handle = fs.root_handle();
@@ -214,4 +223,41 @@ mod tests {
         // Cleanup
         fs::remove_dir_all(&test_dir).unwrap();
     }
+
+    #[test]
+    fn test_readdirplus_stats_each_entry_once() {
+        use std::time::Duration;
+        use xdr_codec::Pack;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for i in 0..1000 {
+            fs::write(temp_dir.path().join(format!("file{:04}", i)), "x").unwrap();
+        }
+
+        let list = |fs: &LocalFilesystem| {
+            let mut args_buf = Vec::new();
+            crate::protocol::v3::nfs::fhandle3(fs.root_handle()).pack(&mut args_buf).unwrap();
+            0u64.pack(&mut args_buf).unwrap();
+            cookieverf3([0u8; COOKIEVERFSIZE as usize]).pack(&mut args_buf).unwrap();
+            2000u32.pack(&mut args_buf).unwrap(); // dircount
+            1_048_576u32.pack(&mut args_buf).unwrap(); // maxcount
+            handle_readdirplus(1, &args_buf, fs).unwrap()
+        };
+
+        let uncached = LocalFilesystem::new(temp_dir.path())
+            .unwrap()
+            .with_attr_cache(Duration::ZERO, 4096);
+        let cached = LocalFilesystem::new(temp_dir.path())
+            .unwrap()
+            .with_attr_cache(Duration::from_secs(3600), 4096);
+
+        let uncached_reply = list(&uncached);
+        let cached_reply = list(&cached);
+        assert_eq!(uncached_reply.len(), cached_reply.len());
+
+        // Attributes come from the listing's own stat of each entry, so no
+        // LOOKUP or GETATTR stats follow, with or without the cache
+        assert!(uncached.stat_count() <= 1100, "uncached: {}", uncached.stat_count());
+        assert!(cached.stat_count() <= 1100, "cached: {}", cached.stat_count());
+    }
 }
@@ -7,6 +7,7 @@ use bytes::BytesMut;
 use tracing::{debug, warn};
 
 use crate::fsal::Filesystem;
+use crate::nfs::error::handle_error_status;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
@@ -62,6 +63,10 @@ pub fn handle_readlink(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem)
 
 /// Map filesystem error to NFS status code
 fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
+    if let Some(status) = handle_error_status(error) {
+        return status;
+    }
+
     let error_str = format!("{:?}", error);
 
     // Check for specific error patterns
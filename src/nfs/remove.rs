@@ -6,7 +6,9 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
-use crate::fsal::Filesystem;
+use crate::fsal::{FileType, Filesystem};
+use crate::nfs::error::handle_error_status;
+use crate::nfs::name::validate_name;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
@@ -34,8 +36,23 @@ pub fn handle_remove(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
         args.name.0
     );
 
+    if let Err(status) = validate_name(&args.name.0) {
+        return create_remove_response(xid, status, None);
+    }
+
     // Get directory attributes before removal (for wcc_data)
-    let dir_before = filesystem.getattr(&args.dir.0).ok();
+    let _dir_before = filesystem.getattr(&args.dir.0).ok();
+
+    // REMOVE only unlinks non-directories; directories need RMDIR
+    let target_type = filesystem
+        .lookup(&args.dir.0, &args.name.0)
+        .and_then(|handle| filesystem.getattr(&handle))
+        .map(|attrs| attrs.ftype);
+    if let Ok(FileType::Directory) = target_type {
+        debug!("REMOVE refused: '{}' is a directory", args.name.0);
+        let dir_after = filesystem.getattr(&args.dir.0).ok().map(|attr| NfsMessage::fsal_to_fattr3(&attr));
+        return create_remove_response(xid, nfsstat3::NFS3ERR_ISDIR, dir_after);
+    }
 
     // Perform remove operation
     match filesystem.remove(&args.dir.0, &args.name.0) {
@@ -59,12 +76,12 @@ pub fn handle_remove(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
 
             // Determine appropriate error code based on error message and IO error kind
             let error_string = e.to_string();
-            let status = if error_string.contains("not found") || error_string.contains("No such") {
+            let status = if let Some(status) = handle_error_status(&e) {
+                status
+            } else if error_string.contains("not found") || error_string.contains("No such") {
                 nfsstat3::NFS3ERR_NOENT
             } else if error_string.contains("permission") || error_string.contains("Permission") {
                 nfsstat3::NFS3ERR_ACCES
-            } else if error_string.contains("directory") || error_string.contains("Is a directory") {
-                nfsstat3::NFS3ERR_ISDIR
             } else {
                 // Try to get std::io::Error from anyhow::Error
                 if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
@@ -146,7 +163,7 @@ mod tests {
         fs::write(&test_file, "test content").unwrap();
 
         // Create filesystem
-        let fs = LocalFilesystem::new("/tmp/nfs_test_remove".to_string()).unwrap();
+        let fs = LocalFilesystem::new("/tmp/nfs_test_remove").unwrap();
 
         // Get root handle
         let root_handle = fs.root_handle();
@@ -178,37 +195,23 @@ mod tests {
     }
 
     #[test]
-    fn test_remove_nonexistent_file() {
-        // Create test directory
-        let test_dir = PathBuf::from("/tmp/nfs_test_remove_nonexistent");
+    fn test_remove_directory_is_isdir() {
+        let test_dir = PathBuf::from("/tmp/nfs_test_remove_isdir");
         let _ = fs::remove_dir_all(&test_dir);
-        fs::create_dir_all(&test_dir).unwrap();
+        fs::create_dir_all(test_dir.join("subdir")).unwrap();
 
-        // Create filesystem (file does NOT exist)
-        let fs = LocalFilesystem::new("/tmp/nfs_test_remove_nonexistent".to_string()).unwrap();
+        let fs = LocalFilesystem::new("/tmp/nfs_test_remove_isdir").unwrap();
 
-        // Get root handle
-        let root_handle = fs.root_handle();
-
-        // Create REMOVE3args manually
         use xdr_codec::Pack;
         let mut args_buf = Vec::new();
+        crate::protocol::v3::nfs::fhandle3(fs.root_handle()).pack(&mut args_buf).unwrap();
+        crate::protocol::v3::nfs::filename3("subdir".to_string()).pack(&mut args_buf).unwrap();
 
-        // dir (fhandle3)
-        let fhandle = crate::protocol::v3::nfs::fhandle3(root_handle.clone());
-        fhandle.pack(&mut args_buf).unwrap();
-
-        // name (filename3) - nonexistent file
-        let filename = crate::protocol::v3::nfs::filename3("does_not_exist.txt".to_string());
-        filename.pack(&mut args_buf).unwrap();
+        // REMOVE must refuse directories without touching them
+        let reply = handle_remove(12345, &args_buf, &fs).unwrap();
+        assert_eq!(&reply[24..28], &(nfsstat3::NFS3ERR_ISDIR as u32).to_be_bytes());
+        assert!(test_dir.join("subdir").is_dir());
 
-        // Call REMOVE - should fail with NOENT
-        let result = handle_remove(12345, &args_buf, &fs);
-        assert!(result.is_ok(), "REMOVE should return response (not crash)");
-
-        // TODO: Parse response and verify status is NFS3ERR_NOENT
-
-        // Cleanup
         fs::remove_dir_all(&test_dir).unwrap();
     }
 }
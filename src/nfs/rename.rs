@@ -6,7 +6,10 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
+use crate::fsal::error::errno_of;
 use crate::fsal::Filesystem;
+use crate::nfs::error::{handle_error_status, not_empty_error_status};
+use crate::nfs::name::validate_name;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
@@ -38,12 +41,16 @@ pub fn handle_rename(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
         args.to_name.0
     );
 
+    if let Err(status) = validate_name(&args.from_name.0).and_then(|_| validate_name(&args.to_name.0)) {
+        return create_rename_response(xid, status, None, None);
+    }
+
     // Get source directory attributes before operation (for wcc_data)
-    let fromdir_before = filesystem.getattr(&args.from_dir.0).ok();
+    let _fromdir_before = filesystem.getattr(&args.from_dir.0).ok();
 
     // Get target directory attributes before operation (for wcc_data)
     // Only if different from source directory
-    let todir_before = if args.from_dir.0 == args.to_dir.0 {
+    let _todir_before = if args.from_dir.0 == args.to_dir.0 {
         None  // Same directory, use fromdir_before
     } else {
         filesystem.getattr(&args.to_dir.0).ok()
@@ -73,7 +80,7 @@ pub fn handle_rename(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
 
             // Get target directory attributes after operation
             let todir_after = if args.from_dir.0 == args.to_dir.0 {
-                fromdir_after.clone()  // Same directory
+                fromdir_after  // Same directory
             } else {
                 match filesystem.getattr(&args.to_dir.0) {
                     Ok(attr) => Some(NfsMessage::fsal_to_fattr3(&attr)),
@@ -91,7 +98,15 @@ pub fn handle_rename(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
 
             // Determine appropriate error code
             let error_string = e.to_string();
-            let status = if error_string.contains("not found") || error_string.contains("No such") {
+            let status = if let Some(status) = handle_error_status(&e) {
+                status
+            } else if errno_of(&e) == Some(libc::EXDEV) {
+                // The directories are on different filesystems or exports
+                nfsstat3::NFS3ERR_XDEV
+            } else if let Some(status) = not_empty_error_status(&e) {
+                // Replacing a directory that still has entries
+                status
+            } else if error_string.contains("not found") || error_string.contains("No such") {
                 nfsstat3::NFS3ERR_NOENT
             } else if error_string.contains("already exists") || error_string.contains("File exists") {
                 nfsstat3::NFS3ERR_EXIST
@@ -101,8 +116,6 @@ pub fn handle_rename(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
                 nfsstat3::NFS3ERR_NOTDIR
             } else if error_string.contains("is a directory") || error_string.contains("Is a directory") {
                 nfsstat3::NFS3ERR_ISDIR
-            } else if error_string.contains("not empty") || error_string.contains("Directory not empty") {
-                nfsstat3::NFS3ERR_NOTEMPTY
             } else if error_string.contains("cross-device") || error_string.contains("Invalid cross-device") {
                 nfsstat3::NFS3ERR_XDEV
             } else {
@@ -122,7 +135,7 @@ pub fn handle_rename(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
             // Try to get current directory attributes for wcc_data
             let fromdir_after = filesystem.getattr(&args.from_dir.0).ok().map(|attr| NfsMessage::fsal_to_fattr3(&attr));
             let todir_after = if args.from_dir.0 == args.to_dir.0 {
-                fromdir_after.clone()
+                fromdir_after
             } else {
                 filesystem.getattr(&args.to_dir.0).ok().map(|attr| NfsMessage::fsal_to_fattr3(&attr))
             };
@@ -211,7 +224,7 @@ mod tests {
         file.write_all(b"test content").unwrap();
 
         // Create filesystem
-        let fs = LocalFilesystem::new("/tmp/nfs_test_rename".to_string()).unwrap();
+        let fs = LocalFilesystem::new("/tmp/nfs_test_rename").unwrap();
 
         // Get root handle
         let root_handle = fs.root_handle();
@@ -258,7 +271,7 @@ mod tests {
         fs::create_dir(test_dir.join("olddir")).unwrap();
 
         // Create filesystem
-        let fs = LocalFilesystem::new("/tmp/nfs_test_rename_dir".to_string()).unwrap();
+        let fs = LocalFilesystem::new("/tmp/nfs_test_rename_dir").unwrap();
 
         // Get root handle
         let root_handle = fs.root_handle();
@@ -289,4 +302,46 @@ mod tests {
         // Cleanup
         fs::remove_dir_all(&test_dir).unwrap();
     }
+
+    #[test]
+    fn test_rename_across_exports_is_xdev() {
+        use crate::export::{ExportResolver, ExportRouter, ExportTable};
+        use crate::fsal::memory::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{fhandle3, filename3, RENAME3args};
+        use std::sync::Arc;
+        use xdr_codec::Pack;
+
+        let mut table = ExportTable::new();
+        let first_id = table.add("/first", Arc::new(MemoryFilesystem::new()));
+        let second_id = table.add("/second", Arc::new(MemoryFilesystem::new()));
+        let table: Arc<dyn ExportResolver> = Arc::new(table);
+        let router = ExportRouter::new(table.clone());
+        let first_root = table.root_handle(first_id).unwrap();
+        let second_root = table.root_handle(second_id).unwrap();
+        router.create(&first_root, "file", 0o644).unwrap();
+        let sub = router.mkdir(&first_root, "sub", 0o755).unwrap();
+
+        let rename = |to_dir: &crate::fsal::FileHandle, to_name: &str| {
+            let args = RENAME3args {
+                from_dir: fhandle3(first_root.clone()),
+                from_name: filename3("file".to_string()),
+                to_dir: fhandle3(to_dir.clone()),
+                to_name: filename3(to_name.to_string()),
+            };
+            let mut args_buf = Vec::new();
+            args.pack(&mut args_buf).unwrap();
+            let reply = handle_rename(1, &args_buf, &router).unwrap();
+            u32::from_be_bytes(reply[24..28].try_into().unwrap())
+        };
+
+        // Into another export: refused, the file stays where it was
+        assert_eq!(rename(&second_root, "moved"), nfsstat3::NFS3ERR_XDEV as u32);
+        assert!(router.lookup(&first_root, "file").is_ok());
+        assert!(router.lookup(&second_root, "moved").is_err());
+
+        // Into another directory of the same export: allowed
+        assert_eq!(rename(&sub, "moved"), nfsstat3::NFS3_OK as u32);
+        assert!(router.lookup(&sub, "moved").is_ok());
+        assert!(router.lookup(&first_root, "file").is_err());
+    }
 }
@@ -7,6 +7,8 @@ use bytes::BytesMut;
 use tracing::{debug, warn};
 
 use crate::fsal::Filesystem;
+use crate::nfs::error::{handle_error_status, not_empty_error_status};
+use crate::nfs::name::validate_name;
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
@@ -34,8 +36,12 @@ pub fn handle_rmdir(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
         args.name.0
     );
 
+    if let Err(status) = validate_name(&args.name.0) {
+        return create_rmdir_response(xid, status, None);
+    }
+
     // Get parent directory attributes before removal (for wcc_data)
-    let dir_before = filesystem.getattr(&args.dir.0).ok();
+    let _dir_before = filesystem.getattr(&args.dir.0).ok();
 
     // Perform rmdir operation
     match filesystem.rmdir(&args.dir.0, &args.name.0) {
@@ -59,12 +65,14 @@ pub fn handle_rmdir(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) ->
 
             // Determine appropriate error code
             let error_string = e.to_string();
-            let status = if error_string.contains("not found") || error_string.contains("No such") {
+            let status = if let Some(status) = handle_error_status(&e) {
+                status
+            } else if let Some(status) = not_empty_error_status(&e) {
+                status
+            } else if error_string.contains("not found") || error_string.contains("No such") {
                 nfsstat3::NFS3ERR_NOENT
             } else if error_string.contains("permission") || error_string.contains("Permission") {
                 nfsstat3::NFS3ERR_ACCES
-            } else if error_string.contains("not empty") || error_string.contains("Directory not empty") {
-                nfsstat3::NFS3ERR_NOTEMPTY
             } else if error_string.contains("not a directory") || error_string.contains("Not a directory") {
                 nfsstat3::NFS3ERR_NOTDIR
             } else {
@@ -148,7 +156,7 @@ mod tests {
         fs::create_dir(&target_dir).unwrap();
 
         // Create filesystem
-        let fs = LocalFilesystem::new("/tmp/nfs_test_rmdir".to_string()).unwrap();
+        let fs = LocalFilesystem::new("/tmp/nfs_test_rmdir").unwrap();
 
         // Get root handle
         let root_handle = fs.root_handle();
@@ -180,76 +188,30 @@ mod tests {
     }
 
     #[test]
-    fn test_rmdir_nonexistent() {
-        // Create test directory
-        let test_dir = PathBuf::from("/tmp/nfs_test_rmdir_nonexistent");
-        let _ = fs::remove_dir_all(&test_dir);
-        fs::create_dir_all(&test_dir).unwrap();
-
-        // Create filesystem (directory does NOT exist)
-        let fs = LocalFilesystem::new("/tmp/nfs_test_rmdir_nonexistent".to_string()).unwrap();
-
-        // Get root handle
-        let root_handle = fs.root_handle();
-
-        // Create RMDIR3args manually
+    fn test_rmdir_not_empty_by_errno() {
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{fhandle3, filename3};
         use xdr_codec::Pack;
-        let mut args_buf = Vec::new();
-
-        let fhandle = crate::protocol::v3::nfs::fhandle3(root_handle.clone());
-        fhandle.pack(&mut args_buf).unwrap();
-
-        let dirname = crate::protocol::v3::nfs::filename3("does_not_exist".to_string());
-        dirname.pack(&mut args_buf).unwrap();
-
-        // Call RMDIR - should fail with NOENT
-        let result = handle_rmdir(12345, &args_buf, &fs);
-        assert!(result.is_ok(), "RMDIR should return response (not crash)");
-
-        // TODO: Parse response and verify status is NFS3ERR_NOENT
 
-        // Cleanup
-        fs::remove_dir_all(&test_dir).unwrap();
-    }
-
-    #[test]
-    fn test_rmdir_not_empty() {
-        // Create test directory
-        let test_dir = PathBuf::from("/tmp/nfs_test_rmdir_notempty");
-        let _ = fs::remove_dir_all(&test_dir);
-        fs::create_dir_all(&test_dir).unwrap();
-
-        // Create a non-empty directory
-        let target_dir = test_dir.join("nonemptydir");
-        fs::create_dir(&target_dir).unwrap();
-        fs::write(target_dir.join("somefile.txt"), "data").unwrap();
-
-        // Create filesystem
-        let fs = LocalFilesystem::new("/tmp/nfs_test_rmdir_notempty".to_string()).unwrap();
-
-        // Get root handle
-        let root_handle = fs.root_handle();
-
-        // Create RMDIR3args manually
-        use xdr_codec::Pack;
-        let mut args_buf = Vec::new();
-
-        let fhandle = crate::protocol::v3::nfs::fhandle3(root_handle.clone());
-        fhandle.pack(&mut args_buf).unwrap();
-
-        let dirname = crate::protocol::v3::nfs::filename3("nonemptydir".to_string());
-        dirname.pack(&mut args_buf).unwrap();
-
-        // Call RMDIR - should fail with NOTEMPTY
-        let result = handle_rmdir(12345, &args_buf, &fs);
-        assert!(result.is_ok(), "RMDIR should return response (not crash)");
-
-        // Verify directory still exists
-        assert!(target_dir.exists(), "Directory should still exist");
-
-        // TODO: Parse response and verify status is NFS3ERR_NOTEMPTY
-
-        // Cleanup
-        fs::remove_dir_all(&test_dir).unwrap();
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        let root = fs.root_handle();
+        let dir = fs.mkdir(&root, "full", 0o755).unwrap();
+        fs.create(&dir, "entry", 0o644).unwrap();
+        fs.mkdir(&root, "empty", 0o755).unwrap();
+        let status = |name: &str| {
+            let mut args_buf = Vec::new();
+            fhandle3(root.clone()).pack(&mut args_buf).unwrap();
+            filename3(name.to_string()).pack(&mut args_buf).unwrap();
+            let reply = handle_rmdir(1, &args_buf, &fs).unwrap();
+            i32::from_be_bytes(reply[24..28].try_into().unwrap())
+        };
+
+        assert_eq!(status("full"), nfsstat3::NFS3ERR_NOTEMPTY as i32);
+        assert!(fs.lookup(&root, "full").is_ok());
+
+        // Some systems report a non-empty directory as EEXIST
+        fs.fail("rmdir", libc::EEXIST);
+        assert_eq!(status("empty"), nfsstat3::NFS3ERR_NOTEMPTY as i32);
     }
 }
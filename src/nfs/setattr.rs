@@ -6,9 +6,14 @@ use anyhow::Result;
 use bytes::BytesMut;
 use tracing::debug;
 
-use crate::fsal::Filesystem;
-use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
+use crate::fsal::{FileAttributes, FileTime, FileType, Filesystem, FsalError, SetAttributes, SetTime};
+use crate::nfs::error::{handle_error_status, io_error_status, permission_error_status};
+use crate::protocol::v3::nfs::{
+    nfsstat3, nfstime3, sattr3, sattrguard3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
+    NfsMessage,
+};
 use crate::protocol::v3::rpc::RpcMessage;
+use crate::rpc::auth::Credentials;
 
 /// Handle NFS SETATTR procedure (procedure 2)
 ///
@@ -19,6 +24,7 @@ use crate::protocol::v3::rpc::RpcMessage;
 /// * `xid` - Transaction ID from the request
 /// * `args_data` - Serialized SETATTR3args (file handle + new_attributes + guard)
 /// * `filesystem` - Filesystem instance
+/// * `credentials` - Caller identity (after export squashing)
 ///
 /// # Returns
 /// Serialized RPC reply message with status and attributes
@@ -26,6 +32,7 @@ pub fn handle_setattr(
     xid: u32,
     args_data: &[u8],
     filesystem: &dyn Filesystem,
+    credentials: &Credentials,
 ) -> Result<BytesMut> {
     debug!("NFS SETATTR called (xid={})", xid);
 
@@ -37,96 +44,82 @@ pub fn handle_setattr(
         args.object.0.len(),
     );
 
-    // Get file attributes before setattr (for wcc_data)
-    let before_attrs = filesystem.getattr(&args.object.0).ok();
-
-    // Check guard if requested (guard is a union: CHECK with ctime or DONT_CHECK)
-    if let crate::protocol::v3::nfs::sattrguard3::CHECK(guard_ctime) = &args.guard {
-        if let Some(ref before) = before_attrs {
-            let before_ctime = before.ctime;
-
-            // Compare ctime - if different, file was modified
-            if before_ctime.seconds != guard_ctime.seconds as u64
-                || before_ctime.nseconds != guard_ctime.nseconds {
-                debug!("SETATTR: guard check failed - file was modified");
-                let error_status = nfsstat3::NFS3ERR_NOT_SYNC;
-                let res_data = NfsMessage::create_setattr_error_response(error_status)?;
-                return RpcMessage::create_success_reply_with_data(xid, res_data);
-            }
-        }
-    }
-
     // Apply attribute changes
     let new_attrs = &args.new_attributes;
-
-    // Handle size change (truncate/extend)
-    if let crate::protocol::v3::nfs::set_size3::SET_SIZE(new_size) = &new_attrs.size {
-        debug!("SETATTR: setting size to {}", new_size);
-
-        if let Err(e) = filesystem.setattr_size(&args.object.0, *new_size) {
-            debug!("SETATTR: failed to set size: {}", e);
-            let error_status = if e.to_string().contains("not found") {
-                nfsstat3::NFS3ERR_STALE
-            } else if e.to_string().contains("Permission denied") {
-                nfsstat3::NFS3ERR_ACCES
-            } else if e.to_string().contains("Read-only") {
-                nfsstat3::NFS3ERR_ROFS
-            } else {
-                nfsstat3::NFS3ERR_IO
-            };
-            let res_data = NfsMessage::create_setattr_error_response(error_status)?;
-            return RpcMessage::create_success_reply_with_data(xid, res_data);
-        }
-    }
-
-    // Handle mode change (permissions)
-    if let crate::protocol::v3::nfs::set_mode3::SET_MODE(mode) = &new_attrs.mode {
-        debug!("SETATTR: setting mode to {:o}", mode);
-
-        if let Err(e) = filesystem.setattr_mode(&args.object.0, *mode) {
-            debug!("SETATTR: failed to set mode: {}", e);
-            let error_status = if e.to_string().contains("not found") {
-                nfsstat3::NFS3ERR_STALE
-            } else if e.to_string().contains("Permission denied") {
-                nfsstat3::NFS3ERR_ACCES
-            } else {
-                nfsstat3::NFS3ERR_IO
-            };
-            let res_data = NfsMessage::create_setattr_error_response(error_status)?;
-            return RpcMessage::create_success_reply_with_data(xid, res_data);
-        }
-    }
-
-    // Handle uid/gid change
-    let uid = match &new_attrs.uid {
-        crate::protocol::v3::nfs::set_uid3::SET_UID(u) => Some(*u),
-        _ => None,
+    let (atime, mtime) = requested_times(new_attrs);
+    let changes = SetAttributes {
+        mode: match &new_attrs.mode {
+            set_mode3::SET_MODE(mode) => Some(*mode),
+            _ => None,
+        },
+        uid: match &new_attrs.uid {
+            set_uid3::SET_UID(uid) => Some(*uid),
+            _ => None,
+        },
+        gid: match &new_attrs.gid {
+            set_gid3::SET_GID(gid) => Some(*gid),
+            _ => None,
+        },
+        size: match &new_attrs.size {
+            set_size3::SET_SIZE(size) => Some(*size),
+            _ => None,
+        },
+        atime,
+        mtime,
     };
-    let gid = match &new_attrs.gid {
-        crate::protocol::v3::nfs::set_gid3::SET_GID(g) => Some(*g),
+
+    // Guard is a union: CHECK with ctime or DONT_CHECK
+    // The backend verifies it atomically with the update
+    let guard = match &args.guard {
+        sattrguard3::CHECK(guard_ctime) => Some(FileTime {
+            seconds: guard_ctime.seconds as u64,
+            nseconds: guard_ctime.nseconds,
+        }),
         _ => None,
     };
 
-    if uid.is_some() || gid.is_some() {
-        debug!("SETATTR: setting uid={:?}, gid={:?}", uid, gid);
-
-        if let Err(e) = filesystem.setattr_owner(&args.object.0, uid, gid) {
-            debug!("SETATTR: failed to set owner: {}", e);
-            let error_status = if e.to_string().contains("not found") {
-                nfsstat3::NFS3ERR_STALE
-            } else if e.to_string().contains("Permission denied") {
-                nfsstat3::NFS3ERR_ACCES
-            } else {
-                nfsstat3::NFS3ERR_IO
-            };
-            let res_data = NfsMessage::create_setattr_error_response(error_status)?;
-            return RpcMessage::create_success_reply_with_data(xid, res_data);
-        }
+    debug!("SETATTR: {:?} guard={:?}", changes, guard);
+
+    // Only regular files have a size to change (RFC 1813 section 3.3.2)
+    if changes.size.is_some()
+        && let Ok(attrs) = filesystem.getattr(&args.object.0)
+        && attrs.ftype != FileType::RegularFile
+    {
+        debug!("SETATTR: size change on {:?} refused", attrs.ftype);
+        let res_data = NfsMessage::create_setattr_error_response(nfsstat3::NFS3ERR_INVAL)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
     }
 
-    // Handle atime/mtime changes
-    // For simplicity, we'll skip time changes for now as they require more complex handling
-    // (SET_TO_SERVER_TIME vs SET_TO_CLIENT_TIME)
+    // The backend acts as the server: check ownership rules for the caller
+    if (changes.mode.is_some() || changes.uid.is_some() || changes.gid.is_some())
+        && let Ok(attrs) = filesystem.getattr(&args.object.0)
+        && !may_change_owner_and_mode(credentials, &attrs, &changes)
+    {
+        debug!("SETATTR: uid {} may not change {:?} of a file owned by {}", credentials.uid, changes, attrs.uid);
+        let res_data = NfsMessage::create_setattr_error_response(nfsstat3::NFS3ERR_PERM)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
+    if let Err(e) = filesystem.setattr(&args.object.0, &changes, guard) {
+        debug!("SETATTR failed: {}", e);
+        let error_status = if matches!(e.downcast_ref::<FsalError>(), Some(FsalError::NotSync)) {
+            nfsstat3::NFS3ERR_NOT_SYNC
+        } else if let Some(status) = handle_error_status(&e) {
+            status
+        } else if let Some(status) = permission_error_status(&e) {
+            status
+        } else if e.to_string().contains("not found") {
+            nfsstat3::NFS3ERR_STALE
+        } else if e.to_string().contains("Permission denied") {
+            nfsstat3::NFS3ERR_ACCES
+        } else if e.to_string().contains("Read-only") {
+            nfsstat3::NFS3ERR_ROFS
+        } else {
+            io_error_status("SETATTR", &args.object.0, &e)
+        };
+        let res_data = NfsMessage::create_setattr_error_response(error_status)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
 
     // Get file attributes after setattr
     let after_attrs = match filesystem.getattr(&args.object.0) {
@@ -165,13 +158,62 @@ pub fn handle_setattr(
     RpcMessage::create_success_reply_with_data(xid, res_data)
 }
 
+/// Whether the caller may make the mode and ownership changes, as chown(2)
+/// and chmod(2) allow
+///
+/// Only root gives a file to another user. The owner may change the mode and
+/// move the file to one of its own groups.
+fn may_change_owner_and_mode(credentials: &Credentials, attrs: &FileAttributes, changes: &SetAttributes) -> bool {
+    if credentials.uid == 0 {
+        return true;
+    }
+    let owner = credentials.uid == attrs.uid;
+    let uid_allowed = changes.uid.is_none_or(|uid| uid == attrs.uid && owner);
+    let gid_allowed = changes.gid.is_none_or(|gid| owner && credentials.in_group(gid));
+    let mode_allowed = changes.mode.is_none() || owner;
+    uid_allowed && gid_allowed && mode_allowed
+}
+
+/// Times a sattr3 asks for (SET_TO_SERVER_TIME or SET_TO_CLIENT_TIME)
+///
+/// # Returns
+/// (atime, mtime), each None when the time is to be left unchanged
+pub(super) fn requested_times(attrs: &sattr3) -> (Option<SetTime>, Option<SetTime>) {
+    let client_time = |time: &nfstime3| {
+        SetTime::ClientTime(FileTime {
+            seconds: time.seconds as u64,
+            nseconds: time.nseconds,
+        })
+    };
+    let atime = match &attrs.atime {
+        set_atime::SET_TO_SERVER_TIME => Some(SetTime::ServerTime),
+        set_atime::SET_TO_CLIENT_TIME(time) => Some(client_time(time)),
+        set_atime::default => None,
+    };
+    let mtime = match &attrs.mtime {
+        set_mtime::SET_TO_SERVER_TIME => Some(SetTime::ServerTime),
+        set_mtime::SET_TO_CLIENT_TIME(time) => Some(client_time(time)),
+        set_mtime::default => None,
+    };
+    (atime, mtime)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::fsal::{BackendConfig, Filesystem};
+    use crate::fsal::BackendConfig;
     use std::fs;
     use tempfile::TempDir;
 
+    /// Credentials of a caller that is root after squashing
+    fn root() -> Credentials {
+        Credentials {
+            uid: 0,
+            gid: 0,
+            ..Credentials::anonymous()
+        }
+    }
+
     #[test]
     fn test_setattr_truncate() {
         // Create temp filesystem
@@ -190,7 +232,7 @@ mod tests {
         // Serialize SETATTR3args to truncate to 5 bytes
         use crate::protocol::v3::nfs::{
             fhandle3, sattrguard3, sattr3, set_atime, set_gid3, set_mode3,
-            set_mtime, set_size3, set_uid3, time_how, SETATTR3args,
+            set_mtime, set_size3, set_uid3, SETATTR3args,
         };
         use xdr_codec::Pack;
 
@@ -204,20 +246,14 @@ mod tests {
                 atime: set_atime::default,
                 mtime: set_mtime::default,
             },
-            guard: sattrguard3 {
-                check: false,
-                obj_ctime: crate::protocol::v3::nfs::nfstime3 {
-                    seconds: 0,
-                    nseconds: 0,
-                },
-            },
+            guard: sattrguard3::default,
         };
 
         let mut args_buf = Vec::new();
         args.pack(&mut args_buf).unwrap();
 
         // Call SETATTR
-        let result = handle_setattr(12345, &args_buf, fs.as_ref());
+        let result = handle_setattr(12345, &args_buf, fs.as_ref(), &root());
 
         assert!(result.is_ok(), "SETATTR should succeed");
 
@@ -244,7 +280,7 @@ mod tests {
         // Serialize SETATTR3args to set mode to 0644
         use crate::protocol::v3::nfs::{
             fhandle3, sattrguard3, sattr3, set_atime, set_gid3, set_mode3,
-            set_mtime, set_size3, set_uid3, time_how, SETATTR3args,
+            set_mtime, set_size3, set_uid3, SETATTR3args,
         };
         use xdr_codec::Pack;
 
@@ -258,21 +294,204 @@ mod tests {
                 atime: set_atime::default,
                 mtime: set_mtime::default,
             },
-            guard: sattrguard3 {
-                check: false,
-                obj_ctime: crate::protocol::v3::nfs::nfstime3 {
-                    seconds: 0,
-                    nseconds: 0,
-                },
-            },
+            guard: sattrguard3::default,
         };
 
         let mut args_buf = Vec::new();
         args.pack(&mut args_buf).unwrap();
 
         // Call SETATTR
-        let result = handle_setattr(12345, &args_buf, fs.as_ref());
+        let result = handle_setattr(12345, &args_buf, fs.as_ref(), &root());
 
         assert!(result.is_ok(), "SETATTR should succeed");
     }
+
+    /// Guarded SETATTR after a concurrent modification must fail with NOT_SYNC
+    fn check_guard_conformance(fs: &dyn Filesystem) {
+        use crate::protocol::v3::nfs::{
+            fhandle3, nfstime3, sattr3, set_atime, set_mtime, SETATTR3args,
+        };
+        use xdr_codec::Pack;
+
+        let root_handle = fs.root_handle();
+        let file_handle = fs.create(&root_handle, "guarded.txt", 0o644).unwrap();
+
+        let guarded_setattr = |ctime: FileTime| {
+            let args = SETATTR3args {
+                object: fhandle3(file_handle.clone()),
+                new_attributes: sattr3 {
+                    mode: set_mode3::SET_MODE(0o600),
+                    uid: set_uid3::default,
+                    gid: set_gid3::default,
+                    size: set_size3::default,
+                    atime: set_atime::default,
+                    mtime: set_mtime::default,
+                },
+                guard: sattrguard3::CHECK(nfstime3 {
+                    seconds: ctime.seconds as u32,
+                    nseconds: ctime.nseconds,
+                }),
+            };
+            let mut args_buf = Vec::new();
+            args.pack(&mut args_buf).unwrap();
+            let reply = handle_setattr(1, &args_buf, fs, &root()).unwrap();
+            i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+        };
+
+        // Client reads the ctime, then someone else modifies the file
+        let stale_ctime = fs.getattr(&file_handle).unwrap().ctime;
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs.write(&file_handle, 0, b"changed", crate::fsal::StableHow::FileSync).unwrap();
+
+        assert_eq!(guarded_setattr(stale_ctime), nfsstat3::NFS3ERR_NOT_SYNC as i32);
+        assert_ne!(fs.getattr(&file_handle).unwrap().mode & 0o777, 0o600, "Mode must be unchanged");
+
+        // With the current ctime the guarded SETATTR applies
+        let current_ctime = fs.getattr(&file_handle).unwrap().ctime;
+        assert_eq!(guarded_setattr(current_ctime), nfsstat3::NFS3_OK as i32);
+        assert_eq!(fs.getattr(&file_handle).unwrap().mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_setattr_owner_eperm_is_perm() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::protocol::v3::nfs::{
+            fhandle3, sattrguard3, sattr3, set_atime, set_gid3, set_mode3,
+            set_mtime, set_size3, set_uid3, SETATTR3args,
+        };
+        use xdr_codec::Pack;
+
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        let mut args_buf = Vec::new();
+        SETATTR3args {
+            object: fhandle3(fs.root_handle()),
+            new_attributes: sattr3 {
+                mode: set_mode3::default,
+                uid: set_uid3::SET_UID(1000),
+                gid: set_gid3::default,
+                size: set_size3::default,
+                atime: set_atime::default,
+                mtime: set_mtime::default,
+            },
+            guard: sattrguard3::default,
+        }
+        .pack(&mut args_buf)
+        .unwrap();
+        let status = |errno| {
+            fs.fail("setattr", errno);
+            let reply = handle_setattr(1, &args_buf, &fs, &root()).unwrap();
+            i32::from_be_bytes(reply[24..28].try_into().unwrap())
+        };
+
+        // A chown by a non-owner is EPERM; denied mode bits are EACCES
+        assert_eq!(status(libc::EPERM), nfsstat3::NFS3ERR_PERM as i32);
+        assert_eq!(status(libc::EACCES), nfsstat3::NFS3ERR_ACCES as i32);
+    }
+
+    #[test]
+    fn test_setattr_owner_unprivileged_local_is_perm() {
+        use crate::protocol::v3::nfs::{
+            fhandle3, sattrguard3, sattr3, set_atime, set_gid3, set_mode3,
+            set_mtime, set_size3, set_uid3, SETATTR3args,
+        };
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::set_permissions(temp_dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let file = fs.create(&fs.root_handle(), "owned.txt", 0o644).unwrap();
+        let uid = fs::metadata(temp_dir.path().join("owned.txt")).unwrap().uid();
+
+        let mut args_buf = Vec::new();
+        SETATTR3args {
+            object: fhandle3(file),
+            new_attributes: sattr3 {
+                mode: set_mode3::default,
+                uid: set_uid3::SET_UID(1000),
+                gid: set_gid3::default,
+                size: set_size3::default,
+                atime: set_atime::default,
+                mtime: set_mtime::default,
+            },
+            guard: sattrguard3::default,
+        }
+        .pack(&mut args_buf)
+        .unwrap();
+
+        // Without CAP_CHOWN, giving a file away is EPERM: run the call on a
+        // thread whose effective uid is nobody, which drops the capability
+        let status = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    // SAFETY: the raw syscall changes this thread's credentials
+                    // only, unlike libc's setresuid, which changes every thread's
+                    if unsafe { libc::geteuid() } == 0 {
+                        let result = unsafe { libc::syscall(libc::SYS_setresuid, u32::MAX, 65534u32, u32::MAX) };
+                        assert_eq!(result, 0, "setresuid: {}", std::io::Error::last_os_error());
+                    }
+                    let reply = handle_setattr(1, &args_buf, fs.as_ref(), &root()).unwrap();
+                    i32::from_be_bytes(reply[24..28].try_into().unwrap())
+                })
+                .join()
+                .unwrap()
+        });
+        assert_eq!(status, nfsstat3::NFS3ERR_PERM as i32);
+        assert_eq!(fs::metadata(temp_dir.path().join("owned.txt")).unwrap().uid(), uid);
+    }
+
+    #[test]
+    fn test_setattr_guard_not_sync_local() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        check_guard_conformance(fs.as_ref());
+    }
+
+    #[test]
+    fn test_setattr_guard_not_sync_memory() {
+        let fs = BackendConfig::memory().create_filesystem().unwrap();
+        check_guard_conformance(fs.as_ref());
+    }
+
+    #[test]
+    fn test_setattr_size_on_directory_is_inval() {
+        use crate::protocol::v3::nfs::{fhandle3, SETATTR3args};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let dir_handle = fs.mkdir(&fs.root_handle(), "dir", 0o755).unwrap();
+
+        let setattr = |mode: set_mode3, size: set_size3| {
+            let mut args_buf = Vec::new();
+            SETATTR3args {
+                object: fhandle3(dir_handle.clone()),
+                new_attributes: sattr3 {
+                    mode,
+                    uid: set_uid3::default,
+                    gid: set_gid3::default,
+                    size,
+                    atime: set_atime::default,
+                    mtime: set_mtime::default,
+                },
+                guard: sattrguard3::default,
+            }
+            .pack(&mut args_buf)
+            .unwrap();
+            let reply = handle_setattr(1, &args_buf, fs.as_ref(), &root()).unwrap();
+            i32::from_be_bytes(reply[24..28].try_into().unwrap())
+        };
+
+        // Truncating a directory is refused and leaves it untouched
+        assert_eq!(
+            setattr(set_mode3::SET_MODE(0o700), set_size3::SET_SIZE(0)),
+            nfsstat3::NFS3ERR_INVAL as i32
+        );
+        assert_eq!(fs.getattr(&dir_handle).unwrap().mode & 0o777, 0o755);
+
+        // Other attributes of a directory can still be changed
+        assert_eq!(setattr(set_mode3::SET_MODE(0o700), set_size3::default), nfsstat3::NFS3_OK as i32);
+        assert_eq!(fs.getattr(&dir_handle).unwrap().mode & 0o777, 0o700);
+    }
 }
@@ -2,13 +2,18 @@
 //
 // Creates a symbolic link
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use bytes::BytesMut;
 use tracing::{debug, warn};
 
 use crate::fsal::Filesystem;
+use crate::nfs::error::handle_error_status;
+use crate::nfs::create::set_creator_owner;
+use crate::fsal::error::errno_of;
+use crate::nfs::name::{validate_name, validate_symlink_target};
 use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
+use crate::rpc::auth::Credentials;
 
 /// Handle SYMLINK procedure
 ///
@@ -16,10 +21,16 @@ use crate::protocol::v3::rpc::RpcMessage;
 /// * `xid` - RPC transaction ID
 /// * `args_data` - Serialized SYMLINK3args
 /// * `filesystem` - Filesystem implementation
+/// * `credentials` - Caller identity (after export squashing), owner of the new object
 ///
 /// # Returns
 /// Serialized SYMLINK3res response
-pub fn handle_symlink(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -> Result<BytesMut> {
+pub fn handle_symlink(
+    xid: u32,
+    args_data: &[u8],
+    filesystem: &dyn Filesystem,
+    credentials: &Credentials,
+) -> Result<BytesMut> {
     debug!("NFS SYMLINK: xid={}", xid);
 
     // Parse arguments
@@ -32,12 +43,26 @@ pub fn handle_symlink(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -
         args.symlink.symlink_data.0
     );
 
+    if let Err(status) = validate_name(&args.name.0) {
+        return create_symlink_response(xid, status, None, None, None);
+    }
+    if let Err(status) = validate_symlink_target(&args.symlink.symlink_data.0) {
+        debug!("SYMLINK refused: target of {} bytes", args.symlink.symlink_data.0.len());
+        return create_symlink_response(xid, status, None, None, None);
+    }
+
     // Get parent directory attributes before operation (for wcc_data)
     let dir_before = filesystem.getattr(&args.where_dir.0).ok();
 
     // Perform symlink operation
     match filesystem.symlink(&args.where_dir.0, &args.name.0, &args.symlink.symlink_data.0) {
         Ok(new_symlink_handle) => {
+            if let Err(status) =
+                set_creator_owner(filesystem, &args.where_dir.0, &args.name.0, &new_symlink_handle, credentials)
+            {
+                return create_symlink_response(xid, status, None, None, None);
+            }
+
             debug!("SYMLINK OK: created symlink '{}'", args.name.0);
 
             // Get new symlink attributes
@@ -82,6 +107,10 @@ pub fn handle_symlink(xid: u32, args_data: &[u8], filesystem: &dyn Filesystem) -
 
 /// Map filesystem error to NFS status code
 fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
+    if let Some(status) = handle_error_status(error) {
+        return status;
+    }
+
     let error_str = format!("{:?}", error);
 
     // Check for specific error patterns
@@ -113,6 +142,12 @@ fn map_error_to_status(error: &anyhow::Error) -> nfsstat3 {
         return nfsstat3::NFS3ERR_NOSPC;
     }
 
+    match errno_of(error) {
+        Some(libc::ENAMETOOLONG) => return nfsstat3::NFS3ERR_NAMETOOLONG,
+        Some(libc::EINVAL) => return nfsstat3::NFS3ERR_INVAL,
+        _ => {}
+    }
+
     // Try downcasting to std::io::Error
     if let Some(io_error) = error.downcast_ref::<std::io::Error>() {
         use std::io::ErrorKind;
@@ -198,3 +233,69 @@ fn create_symlink_response(
     let res_data = BytesMut::from(&buf[..]);
     RpcMessage::create_success_reply_with_data(xid, res_data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsal::BackendConfig;
+    use crate::protocol::v3::nfs::{
+        fhandle3, filename3, nfspath3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
+        symlinkdata3, SYMLINK3args,
+    };
+    use tempfile::TempDir;
+    use xdr_codec::Pack;
+
+    /// Call SYMLINK in the root directory and return the reply status
+    fn symlink_status(filesystem: &dyn Filesystem, name: &str, target: &str) -> u32 {
+        let args = SYMLINK3args {
+            where_dir: fhandle3(filesystem.root_handle()),
+            name: filename3(name.to_string()),
+            symlink: symlinkdata3 {
+                symlink_attributes: sattr3 {
+                    mode: set_mode3::default,
+                    uid: set_uid3::default,
+                    gid: set_gid3::default,
+                    size: set_size3::default,
+                    atime: set_atime::default,
+                    mtime: set_mtime::default,
+                },
+                symlink_data: nfspath3(target.to_string()),
+            },
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+
+        let reply = handle_symlink(1, &args_buf, filesystem, &Credentials::anonymous()).unwrap();
+        u32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+    }
+
+    #[test]
+    fn test_symlink_target_length() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+
+        let status = symlink_status(fs.as_ref(), "long", &"t".repeat(5000));
+        assert_eq!(status, nfsstat3::NFS3ERR_NAMETOOLONG as u32);
+        let status = symlink_status(fs.as_ref(), "empty", "");
+        assert_eq!(status, nfsstat3::NFS3ERR_INVAL as u32);
+        assert!(!temp_dir.path().join("long").exists());
+
+        // Relative and absolute targets are created verbatim
+        assert_eq!(symlink_status(fs.as_ref(), "relative", "../sibling/file"), nfsstat3::NFS3_OK as u32);
+        assert_eq!(symlink_status(fs.as_ref(), "absolute", "/etc/hostname"), nfsstat3::NFS3_OK as u32);
+        let link = |name: &str| std::fs::read_link(temp_dir.path().join(name)).unwrap();
+        assert_eq!(link("relative"), std::path::PathBuf::from("../sibling/file"));
+        assert_eq!(link("absolute"), std::path::PathBuf::from("/etc/hostname"));
+    }
+
+    #[test]
+    fn test_local_symlink_rejects_long_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let fs = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+
+        // The backend enforces the limit too, for callers other than SYMLINK
+        let error = fs.symlink(&fs.root_handle(), "long", &"t".repeat(5000)).unwrap_err();
+        assert_eq!(map_error_to_status(&error), nfsstat3::NFS3ERR_NAMETOOLONG);
+    }
+}
+
@@ -1,13 +1,22 @@
 // NFS WRITE Procedure (Procedure 7)
 //
 // Writes data to a file
+//
+// Byte-range locks are not checked: the server has no NLM (Network Lock
+// Manager) service, so clients lock locally (mount with -o nolock) and
+// there is no lock table to consult. Once one exists, a WRITE overlapping a
+// range locked by another owner belongs here, answered with NFS3ERR_JUKEBOX
+// before the backend is called.
 
 use anyhow::Result;
 use bytes::BytesMut;
 use tracing::debug;
 
-use crate::fsal::Filesystem;
-use crate::protocol::v3::nfs::{nfsstat3, NfsMessage};
+use crate::fsal::{FileType, Filesystem, StableHow};
+use crate::nfs::error::{handle_error_status, io_error_status, storage_error_status};
+use crate::nfs::fsinfo::range_end;
+use crate::nfs::verifier::write_verifier;
+use crate::protocol::v3::nfs::{nfsstat3, stable_how, NfsMessage};
 use crate::protocol::v3::rpc::RpcMessage;
 
 /// Handle NFS WRITE procedure (procedure 7)
@@ -18,6 +27,8 @@ use crate::protocol::v3::rpc::RpcMessage;
 /// * `xid` - Transaction ID from the request
 /// * `args_data` - Serialized WRITE3args (file handle + offset + count + stable + data)
 /// * `filesystem` - Filesystem instance
+/// * `write_max` - Largest WRITE the export allows (wtmax); longer WRITEs are refused with NFS3ERR_INVAL
+/// * `force_stable` - Write every WRITE as FILE_SYNC, whatever stability the client asked for
 ///
 /// # Returns
 /// Serialized RPC reply message with write status
@@ -25,9 +36,21 @@ pub fn handle_write(
     xid: u32,
     args_data: &[u8],
     filesystem: &dyn Filesystem,
+    write_max: u32,
+    force_stable: bool,
 ) -> Result<BytesMut> {
     debug!("NFS WRITE called (xid={})", xid);
 
+    // Check the declared lengths first, so an over-large WRITE is refused
+    // before its data is copied out of the request
+    if let Some((count, data_len)) = declared_lengths(args_data)
+        && count.max(data_len) > write_max
+    {
+        debug!("WRITE refused: count {} / data {} bytes exceed wtmax {}", count, data_len, write_max);
+        let res_data = NfsMessage::create_write_error_response(nfsstat3::NFS3ERR_INVAL)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
     // Deserialize arguments
     let args = NfsMessage::deserialize_write3args(args_data)?;
 
@@ -39,29 +62,45 @@ pub fn handle_write(
         args.stable
     );
 
+    // The file would grow past the largest size advertised in FSINFO
+    if range_end(args.offset, args.count).is_none() {
+        debug!("WRITE refused: offset {} + count {} is out of range", args.offset, args.count);
+        let res_data = NfsMessage::create_write_error_response(nfsstat3::NFS3ERR_FBIG)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
     // Get file attributes before write (for wcc_data)
     let before_attrs = filesystem.getattr(&args.file.0).ok();
 
-    // Write data to the file
-    let bytes_written = match filesystem.write(&args.file.0, args.offset, &args.data) {
+    // Only regular files hold data: refuse directories by their type up front
+    if before_attrs.as_ref().is_some_and(|attrs| attrs.ftype == FileType::Directory) {
+        debug!("WRITE refused: handle is a directory");
+        let res_data = NfsMessage::create_write_error_response(nfsstat3::NFS3ERR_ISDIR)?;
+        return RpcMessage::create_success_reply_with_data(xid, res_data);
+    }
+
+    // Write data to the file; an export forcing stable writes syncs them all
+    let committed = if force_stable { stable_how::FILE_SYNC } else { args.stable };
+    let stable = match committed {
+        stable_how::UNSTABLE => StableHow::Unstable,
+        stable_how::DATA_SYNC => StableHow::DataSync,
+        stable_how::FILE_SYNC => StableHow::FileSync,
+    };
+    let bytes_written = match filesystem.write(&args.file.0, args.offset, &args.data, stable) {
         Ok(count) => count,
         Err(e) => {
             debug!("WRITE failed: {}", e);
             // Return appropriate NFS error
-            let error_status = if e.to_string().contains("not found")
-                || e.to_string().contains("Invalid handle")
-            {
+            let error_status = if let Some(status) = handle_error_status(&e) {
+                status
+            } else if let Some(status) = storage_error_status(&e) {
+                status
+            } else if e.to_string().contains("not found") {
                 nfsstat3::NFS3ERR_STALE
-            } else if e.to_string().contains("Not a file") {
-                nfsstat3::NFS3ERR_ISDIR
             } else if e.to_string().contains("Permission denied") {
                 nfsstat3::NFS3ERR_ACCES
-            } else if e.to_string().contains("No space") {
-                nfsstat3::NFS3ERR_NOSPC
-            } else if e.to_string().contains("Read-only") {
-                nfsstat3::NFS3ERR_ROFS
             } else {
-                nfsstat3::NFS3ERR_IO
+                io_error_status("WRITE", &args.file.0, &e)
             };
 
             let res_data = NfsMessage::create_write_error_response(error_status)?;
@@ -108,16 +147,13 @@ pub fn handle_write(
     // 3. count (bytes written)
     bytes_written.pack(&mut buf)?;
 
-    // 4. committed (stable_how) - return same as requested
-    // For simplicity, always return FILE_SYNC (2) to indicate data is committed
-    let committed = 2i32; // FILE_SYNC
-    committed.pack(&mut buf)?;
+    // 4. committed (stable_how) - the backend honors the level written at,
+    // so UNSTABLE data is only durable after a COMMIT
+    (committed as i32).pack(&mut buf)?;
 
     // 5. writeverf3 (write verifier) - 8 bytes
     // This is used to detect server reboots between unstable writes and COMMIT
-    // For now, use a constant verifier (in production, use server boot time)
-    let verf: [u8; 8] = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
-    buf.extend_from_slice(&verf);
+    buf.extend_from_slice(&write_verifier());
 
     let res_data = BytesMut::from(&buf[..]);
 
@@ -125,10 +161,23 @@ pub fn handle_write(
     RpcMessage::create_success_reply_with_data(xid, res_data)
 }
 
+/// The `count` and data length a WRITE3args declares, read without decoding it
+///
+/// # Returns
+/// (count, data length), or None if the arguments are too short to hold them
+fn declared_lengths(args_data: &[u8]) -> Option<(u32, u32)> {
+    let word = |at: usize| Some(u32::from_be_bytes(args_data.get(at..at + 4)?.try_into().ok()?));
+    // fhandle3 (length + data padded to 4 bytes), offset, count, stable, data length
+    let handle_len = word(0)? as usize;
+    let count_at = 4usize.checked_add(handle_len.checked_next_multiple_of(4)?)?.checked_add(8)?;
+    Some((word(count_at)?, word(count_at + 8)?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::fsal::{BackendConfig, Filesystem};
+    use crate::export::DEFAULT_MAX_TRANSFER;
+    use crate::fsal::BackendConfig;
     use std::fs;
     use tempfile::TempDir;
 
@@ -164,7 +213,7 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call WRITE
-        let result = handle_write(12345, &args_buf, fs.as_ref());
+        let result = handle_write(12345, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER, false);
 
         assert!(result.is_ok(), "WRITE should succeed");
 
@@ -205,7 +254,7 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call WRITE
-        let result = handle_write(12345, &args_buf, fs.as_ref());
+        let result = handle_write(12345, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER, false);
 
         assert!(result.is_ok(), "WRITE with offset should succeed");
 
@@ -238,8 +287,216 @@ mod tests {
         args.pack(&mut args_buf).unwrap();
 
         // Call WRITE
-        let result = handle_write(12345, &args_buf, fs.as_ref());
+        let result = handle_write(12345, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER, false);
 
         assert!(result.is_ok(), "WRITE should return error response (not panic)");
     }
+
+    #[test]
+    fn test_write_and_commit_share_verifier() {
+        use crate::protocol::v3::nfs::{fhandle3, stable_how, COMMIT3args, WRITE3args};
+        use xdr_codec::Pack;
+
+        let fs = BackendConfig::memory().create_filesystem().unwrap();
+        let root_handle = fs.root_handle();
+        let file_handle = fs.create(&root_handle, "verf.txt", 0o644).unwrap();
+
+        let args = WRITE3args {
+            file: fhandle3(file_handle.clone()),
+            offset: 0,
+            count: 4,
+            stable: stable_how::UNSTABLE,
+            data: b"data".to_vec(),
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+        let write_reply = handle_write(1, &args_buf, fs.as_ref(), DEFAULT_MAX_TRANSFER, false).unwrap();
+
+        let args = COMMIT3args {
+            file: fhandle3(file_handle),
+            offset: 0,
+            count: 0,
+        };
+        let mut args_buf = Vec::new();
+        args.pack(&mut args_buf).unwrap();
+        let commit_reply = crate::nfs::commit::handle_commit(2, &args_buf, fs.as_ref()).unwrap();
+
+        // Both replies end with the writeverf3
+        let write_verf = &write_reply[write_reply.len() - 8..];
+        let commit_verf = &commit_reply[commit_reply.len() - 8..];
+        assert_eq!(write_verf, commit_verf);
+        assert_eq!(write_verf, write_verifier());
+    }
+
+    #[test]
+    fn test_unstable_write_skips_sync() {
+        use crate::fsal::LocalFilesystem;
+        use crate::protocol::v3::nfs::{fhandle3, WRITE3args};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+        let root_handle = fs.root_handle();
+        let file_handle = fs.create(&root_handle, "unstable.txt", 0o644).unwrap();
+
+        let write = |stable: stable_how| {
+            let args = WRITE3args {
+                file: fhandle3(file_handle.clone()),
+                offset: 0,
+                count: 5,
+                stable,
+                data: b"hello".to_vec(),
+            };
+            let mut args_buf = Vec::new();
+            args.pack(&mut args_buf).unwrap();
+            let reply = handle_write(1, &args_buf, &fs, DEFAULT_MAX_TRANSFER, false).unwrap();
+            // committed precedes the 8-byte verifier at the end of the reply
+            let at = reply.len() - 12;
+            i32::from_be_bytes([reply[at], reply[at + 1], reply[at + 2], reply[at + 3]])
+        };
+
+        assert_eq!(write(stable_how::UNSTABLE), stable_how::UNSTABLE as i32);
+        assert_eq!(fs.sync_count(), 0, "UNSTABLE write must not sync");
+        assert_eq!(fs::read(temp_dir.path().join("unstable.txt")).unwrap(), b"hello");
+
+        assert_eq!(write(stable_how::FILE_SYNC), stable_how::FILE_SYNC as i32);
+        assert_eq!(fs.sync_count(), 1, "FILE_SYNC write must sync");
+    }
+
+    #[test]
+    fn test_write_space_and_quota_errors() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+        use crate::protocol::v3::nfs::{WRITE3args, fhandle3, stable_how};
+        use xdr_codec::Pack;
+
+        let fs = FaultyFilesystem::new(Box::new(MemoryFilesystem::new()));
+        let file = fs.create(&fs.root_handle(), "full.bin", 0o644).unwrap();
+        let mut args_buf = Vec::new();
+        WRITE3args {
+            file: fhandle3(file),
+            offset: 0,
+            count: 4,
+            stable: stable_how::FILE_SYNC,
+            data: b"data".to_vec(),
+        }
+        .pack(&mut args_buf)
+        .unwrap();
+
+        let status = |errno| {
+            fs.fail("write", errno);
+            let reply = handle_write(12345, &args_buf, &fs, DEFAULT_MAX_TRANSFER, false).unwrap();
+            i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+        };
+        assert_eq!(status(libc::ENOSPC), nfsstat3::NFS3ERR_NOSPC as i32);
+        assert_eq!(status(libc::EDQUOT), nfsstat3::NFS3ERR_DQUOT as i32);
+        assert_eq!(status(libc::EFBIG), nfsstat3::NFS3ERR_FBIG as i32);
+        assert_eq!(status(libc::EROFS), nfsstat3::NFS3ERR_ROFS as i32);
+    }
+
+    #[test]
+    fn test_write_over_wtmax_is_inval() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{fhandle3, stable_how, WRITE3args};
+        use xdr_codec::Pack;
+
+        const WTMAX: u32 = 4096;
+        let fs = MemoryFilesystem::new();
+        let file = fs.create(&fs.root_handle(), "file", 0o644).unwrap();
+        let write = |count: u32, data: Vec<u8>| {
+            let args = WRITE3args {
+                file: fhandle3(file.clone()),
+                offset: 0,
+                count,
+                stable: stable_how::FILE_SYNC,
+                data,
+            };
+            let mut args_buf = Vec::new();
+            args.pack(&mut args_buf).unwrap();
+            let reply = handle_write(12345, &args_buf, &fs, WTMAX, false).unwrap();
+            i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+        };
+
+        assert_eq!(write(WTMAX, vec![1; WTMAX as usize]), nfsstat3::NFS3_OK as i32);
+        assert_eq!(write(WTMAX + 1, vec![2; WTMAX as usize + 1]), nfsstat3::NFS3ERR_INVAL as i32);
+        assert_eq!(write(16, vec![2; WTMAX as usize + 1]), nfsstat3::NFS3ERR_INVAL as i32);
+        assert_eq!(write(u32::MAX, vec![2; 16]), nfsstat3::NFS3ERR_INVAL as i32);
+        assert_eq!(fs.read(&file, 0, 2 * WTMAX).unwrap(), vec![1; WTMAX as usize], "Nothing more was written");
+
+        // A data length claiming a gigabyte is refused without the data
+        let mut args_buf = Vec::new();
+        fhandle3(file.clone()).pack(&mut args_buf).unwrap();
+        args_buf.extend_from_slice(&0u64.to_be_bytes());
+        args_buf.extend_from_slice(&16u32.to_be_bytes());
+        args_buf.extend_from_slice(&(stable_how::FILE_SYNC as i32).to_be_bytes());
+        args_buf.extend_from_slice(&(1u32 << 30).to_be_bytes());
+        let reply = handle_write(12345, &args_buf, &fs, WTMAX, false).unwrap();
+        assert_eq!(i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]), nfsstat3::NFS3ERR_INVAL as i32);
+    }
+
+    #[test]
+    fn test_write_out_of_range_offset_is_fbig() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{fhandle3, stable_how, WRITE3args};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let local = BackendConfig::local(temp_dir.path()).create_filesystem().unwrap();
+        let memory = MemoryFilesystem::new();
+
+        for fs in [local.as_ref(), &memory as &dyn Filesystem] {
+            let file = fs.create(&fs.root_handle(), "small.txt", 0o644).unwrap();
+            let status = |offset: u64| {
+                let args = WRITE3args {
+                    file: fhandle3(file.clone()),
+                    offset,
+                    count: 100,
+                    stable: stable_how::FILE_SYNC,
+                    data: vec![7; 100],
+                };
+                let mut args_buf = Vec::new();
+                args.pack(&mut args_buf).unwrap();
+                let reply = handle_write(12345, &args_buf, fs, DEFAULT_MAX_TRANSFER, false).unwrap();
+                i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+            };
+            assert_eq!(status(u64::MAX - 10), nfsstat3::NFS3ERR_FBIG as i32);
+            assert_eq!(status(i64::MAX as u64 - 10), nfsstat3::NFS3ERR_FBIG as i32);
+            assert_eq!(fs.getattr(&file).unwrap().size, 0, "Nothing should have been written");
+        }
+    }
+
+    #[test]
+    fn test_force_stable_writes_syncs_unstable_writes() {
+        use crate::fsal::LocalFilesystem;
+        use crate::protocol::v3::nfs::{fhandle3, stable_how, WRITE3args};
+        use xdr_codec::Pack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+        let file = fs.create(&fs.root_handle(), "durable.txt", 0o644).unwrap();
+
+        let mut args_buf = Vec::new();
+        WRITE3args {
+            file: fhandle3(file),
+            offset: 0,
+            count: 4,
+            stable: stable_how::UNSTABLE,
+            data: b"data".to_vec(),
+        }
+        .pack(&mut args_buf)
+        .unwrap();
+        // committed follows status, wcc_data (no pre-op attributes) and count
+        let committed = |reply: &BytesMut| i32::from_be_bytes(reply[124..128].try_into().unwrap());
+
+        // By default an UNSTABLE write is left for COMMIT to sync
+        let reply = handle_write(1, &args_buf, &fs, DEFAULT_MAX_TRANSFER, false).unwrap();
+        assert_eq!(committed(&reply), stable_how::UNSTABLE as i32);
+        assert_eq!(fs.sync_count(), 0);
+
+        // Forced, it is synced before the reply and reported FILE_SYNC
+        let reply = handle_write(2, &args_buf, &fs, DEFAULT_MAX_TRANSFER, true).unwrap();
+        assert_eq!(&reply[24..28], &(nfsstat3::NFS3_OK as u32).to_be_bytes());
+        assert_eq!(committed(&reply), stable_how::FILE_SYNC as i32);
+        assert_eq!(fs.sync_count(), 1);
+    }
 }
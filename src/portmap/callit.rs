@@ -0,0 +1,89 @@
+// Portmapper CALLIT Procedure Handler
+//
+// Procedure: 5 (PMAPPROC_CALLIT)
+// Purpose: Indirectly call a procedure of a registered program
+//
+// Security: CALLIT is a classic amplification and source-address laundering
+// vector when a portmapper relays calls to other processes or hosts. This
+// implementation never forwards anything: it only invokes programs served by
+// this process, through the same routing (and access checks) as a direct
+// call. Calls to other registered programs, unregistered programs, and
+// nested CALLITs get an empty result.
+
+use anyhow::Result;
+use bytes::BytesMut;
+use tracing::{debug, warn};
+
+use crate::portmap::registry::Registry;
+use crate::portmap::{procedures, LocalDispatch, PORTMAP_PROGRAM};
+use crate::protocol::v3::portmap::PortmapMessage;
+use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
+
+/// Programs served in-process that CALLIT may invoke
+const LOCAL_PROGRAMS: &[u32] = &[
+    PORTMAP_PROGRAM,
+    crate::mount::MOUNT_PROGRAM,
+    100003, // NFS
+];
+
+/// Protocol used to look up the target port
+const IPPROTO_TCP: u32 = 6;
+
+/// Handle Portmapper CALLIT procedure
+///
+/// Arguments: call_args (prog, vers, proc, args)
+/// Returns: call_result (port of the program, procedure results)
+///
+/// # Arguments
+/// * `call` - Outer CALLIT call; its credentials are used for the inner call
+/// * `args_data` - Serialized call_args
+/// * `registry` - Registered services
+/// * `local` - Dispatches a call to an in-process program
+pub fn handle(
+    call: &rpc_call_msg,
+    args_data: &[u8],
+    registry: &Registry,
+    local: LocalDispatch,
+) -> Result<BytesMut> {
+    let args = PortmapMessage::deserialize_call_args(args_data)?;
+    debug!(
+        "PORTMAP CALLIT: xid={}, target prog={}, vers={}, proc={}",
+        call.xid, args.prog, args.vers, args.proc_
+    );
+
+    let target = PortmapMessage::create_mapping(args.prog, args.vers, IPPROTO_TCP, 0);
+    let port = registry.getport(&target);
+
+    let results = if port == 0 {
+        debug!("PORTMAP CALLIT: prog={} vers={} not registered", args.prog, args.vers);
+        None
+    } else if !LOCAL_PROGRAMS.contains(&args.prog) {
+        warn!("PORTMAP CALLIT: refusing to forward to external prog={}", args.prog);
+        None
+    } else if args.prog == PORTMAP_PROGRAM && args.proc_ == procedures::CALLIT {
+        warn!("PORTMAP CALLIT: refusing nested CALLIT");
+        None
+    } else {
+        let inner = rpc_call_msg {
+            prog: args.prog,
+            vers: args.vers,
+            proc_: args.proc_,
+            ..call.clone()
+        };
+        match local(&inner, &args.args) {
+            Ok(reply) => RpcMessage::success_reply_results(&reply).map(<[u8]>::to_vec),
+            Err(e) => {
+                debug!("PORTMAP CALLIT: inner call failed: {}", e);
+                None
+            }
+        }
+    };
+
+    // An empty result carries port 0
+    let (port, res) = match results {
+        Some(res) => (port, res),
+        None => (0, Vec::new()),
+    };
+    let result_data = PortmapMessage::serialize_call_result(port, res)?;
+    RpcMessage::create_success_reply_with_data(call.xid, result_data)
+}
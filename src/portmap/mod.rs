@@ -1,14 +1,18 @@
 // Portmapper Protocol Handlers
 //
 // Program: 100000 (PORTMAP)
-// Version: 2
+// Versions: 2 (portmap), 3 and 4 (rpcbind)
 //
 // The portmapper is a service discovery mechanism for RPC services.
 // Services register themselves (SET) and clients query for service ports (GETPORT).
+// rpcbind versions 3 and 4 serve the same registry by netid and universal
+// address (see rpcbind.rs).
 
+pub mod callit;
 pub mod getport;
 pub mod null;
 pub mod registry;
+pub mod rpcbind;
 pub mod set;
 pub mod unset;
 
@@ -35,11 +39,15 @@ pub mod procedures {
     pub const CALLIT: u32 = 5;
 }
 
+/// Routes a call to a program served by this process (used by CALLIT)
+pub type LocalDispatch<'a> = &'a dyn Fn(&rpc_call_msg, &[u8]) -> Result<BytesMut>;
+
 /// Dispatch Portmapper procedure call to appropriate handler
 pub fn handle_portmap_call(
     call: &rpc_call_msg,
     args_data: &[u8],
     registry: &Registry,
+    local: LocalDispatch,
 ) -> Result<BytesMut> {
     debug!(
         "Dispatching PORTMAP call: proc={}, prog={}, vers={}",
@@ -59,6 +67,11 @@ pub fn handle_portmap_call(
         ));
     }
 
+    // rpcbind versions share the program number
+    if call.vers == rpcbind::RPCBIND_V3 || call.vers == rpcbind::RPCBIND_V4 {
+        return rpcbind::handle_rpcbind_call(call, args_data, registry);
+    }
+
     // Verify version 2
     if call.vers != PORTMAP_V2 {
         warn!(
@@ -95,8 +108,8 @@ pub fn handle_portmap_call(
             Err(anyhow!("PORTMAP DUMP procedure not implemented"))
         }
         procedures::CALLIT => {
-            warn!("PORTMAP CALLIT not supported");
-            Err(anyhow!("PORTMAP CALLIT procedure not supported"))
+            debug!("Routing to PORTMAP CALLIT handler");
+            callit::handle(call, args_data, registry, local)
         }
         _ => {
             warn!("Unknown PORTMAP procedure: {}", call.proc_);
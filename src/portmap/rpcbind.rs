@@ -0,0 +1,247 @@
+// rpcbind Protocol Handlers
+//
+// Program: 100000, Versions: 3 and 4 (RFC 1833)
+//
+// rpcbind extends the portmapper with transport-independent registrations:
+// instead of a protocol number and port, a service is named by a netid
+// ("tcp", "udp", "tcp6", "udp6") and a universal address, the host followed
+// by the port's high and low bytes ("10.0.0.1.8.1" is 10.0.0.1 port 2049).
+//
+// Registrations share the portmapper registry, so a service set through any
+// version is visible through all of them.
+
+use anyhow::{anyhow, Result};
+use bytes::BytesMut;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use tracing::{debug, info, warn};
+
+use crate::portmap::registry::Registry;
+use crate::protocol::v3::portmap::{mapping, rpcb, PortmapMessage};
+use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
+
+/// rpcbind version 3
+pub const RPCBIND_V3: u32 = 3;
+
+/// rpcbind version 4
+pub const RPCBIND_V4: u32 = 4;
+
+/// rpcbind procedure numbers shared by versions 3 and 4
+pub mod procedures {
+    pub const NULL: u32 = 0;
+    pub const SET: u32 = 1;
+    pub const UNSET: u32 = 2;
+    pub const GETADDR: u32 = 3;
+    pub const DUMP: u32 = 4;
+}
+
+/// Owner reported for registrations in DUMP
+const OWNER: &str = "superuser";
+
+const IPPROTO_TCP: u32 = 6;
+const IPPROTO_UDP: u32 = 17;
+
+/// Dispatch an rpcbind v3/v4 procedure call
+pub fn handle_rpcbind_call(call: &rpc_call_msg, args_data: &[u8], registry: &Registry) -> Result<BytesMut> {
+    debug!(
+        "Dispatching RPCBIND call: proc={}, vers={}, xid={}",
+        call.proc_, call.vers, call.xid
+    );
+
+    let result = match call.proc_ {
+        procedures::NULL => return RpcMessage::create_void_reply(call.xid),
+        procedures::SET => {
+            let args = PortmapMessage::deserialize_rpcb(args_data)?;
+            let registered = match to_mapping(&args) {
+                Some(map) => {
+                    info!("RPCBIND SET: prog={}, vers={}, {} {}", args.r_prog, args.r_vers, args.r_netid, args.r_addr);
+                    registry.set(&map)
+                }
+                None => {
+                    warn!("RPCBIND SET: unsupported netid {:?} or address {:?}", args.r_netid, args.r_addr);
+                    false
+                }
+            };
+            PortmapMessage::serialize_bool(registered)?
+        }
+        procedures::UNSET => {
+            let args = PortmapMessage::deserialize_rpcb(args_data)?;
+            let removed = netid_protocol(&args.r_netid)
+                .is_some_and(|prot| registry.unset(&PortmapMessage::create_mapping(args.r_prog, args.r_vers, prot, 0)));
+            PortmapMessage::serialize_bool(removed)?
+        }
+        procedures::GETADDR => {
+            let args = PortmapMessage::deserialize_rpcb(args_data)?;
+            let uaddr = netid_protocol(&args.r_netid)
+                .map(|prot| registry.getport(&PortmapMessage::create_mapping(args.r_prog, args.r_vers, prot, 0)))
+                .filter(|port| *port != 0)
+                .and_then(|port| universal_address(&args.r_netid, port))
+                .unwrap_or_default();
+            debug!(
+                "RPCBIND GETADDR: prog={}, vers={}, netid={} -> {:?}",
+                args.r_prog, args.r_vers, args.r_netid, uaddr
+            );
+            PortmapMessage::serialize_uaddr(&uaddr)?
+        }
+        procedures::DUMP => {
+            let mut entries: Vec<rpcb> = registry.dump().iter().flat_map(to_rpcbs).collect();
+            entries.sort_by(|a, b| (a.r_prog, a.r_vers, &a.r_netid).cmp(&(b.r_prog, b.r_vers, &b.r_netid)));
+            PortmapMessage::serialize_rpcb_list(&entries)?
+        }
+        _ => {
+            warn!("Unknown RPCBIND procedure: {}", call.proc_);
+            return Err(anyhow!("Unknown RPCBIND procedure: {}", call.proc_));
+        }
+    };
+    RpcMessage::create_success_reply_with_data(call.xid, result)
+}
+
+/// Protocol number of a netid
+fn netid_protocol(netid: &str) -> Option<u32> {
+    match netid {
+        "tcp" | "tcp6" => Some(IPPROTO_TCP),
+        "udp" | "udp6" => Some(IPPROTO_UDP),
+        _ => None,
+    }
+}
+
+/// Universal address of a port on the netid's wildcard host
+///
+/// The registry records ports only. Clients take the port from the address
+/// and keep using the host they reached rpcbind on, as for a service
+/// listening on every address.
+fn universal_address(netid: &str, port: u32) -> Option<String> {
+    let host = match netid {
+        "tcp" | "udp" => "0.0.0.0",
+        "tcp6" | "udp6" => "::",
+        _ => return None,
+    };
+    Some(format!("{}.{}.{}", host, (port >> 8) & 0xFF, port & 0xFF))
+}
+
+/// Port of a universal address: the netid's host followed by the port's bytes
+fn universal_address_port(netid: &str, uaddr: &str) -> Option<u32> {
+    let mut fields = uaddr.rsplitn(3, '.');
+    let low: u8 = fields.next()?.parse().ok()?;
+    let high: u8 = fields.next()?.parse().ok()?;
+    let host = fields.next()?;
+    let valid_host = match netid {
+        "tcp" | "udp" => host.parse::<Ipv4Addr>().is_ok(),
+        "tcp6" | "udp6" => host.parse::<Ipv6Addr>().is_ok(),
+        _ => false,
+    };
+    valid_host.then(|| u32::from(high) << 8 | u32::from(low))
+}
+
+/// Registry mapping of an rpcb registration
+fn to_mapping(registration: &rpcb) -> Option<mapping> {
+    Some(PortmapMessage::create_mapping(
+        registration.r_prog,
+        registration.r_vers,
+        netid_protocol(&registration.r_netid)?,
+        universal_address_port(&registration.r_netid, &registration.r_addr)?,
+    ))
+}
+
+/// rpcb entries of a registry mapping, one per address family
+fn to_rpcbs(map: &mapping) -> Vec<rpcb> {
+    let netids: &[&str] = match map.prot {
+        IPPROTO_TCP => &["tcp", "tcp6"],
+        IPPROTO_UDP => &["udp", "udp6"],
+        _ => &[],
+    };
+    netids
+        .iter()
+        .filter_map(|netid| {
+            Some(rpcb {
+                r_prog: map.prog,
+                r_vers: map.vers,
+                r_netid: netid.to_string(),
+                r_addr: universal_address(netid, map.port)?,
+                r_owner: OWNER.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::v3::rpc::{auth_flavor, msg_type, opaque_auth};
+    use std::io::Cursor;
+    use xdr_codec::{Pack, Unpack};
+
+    fn call(proc_: u32) -> rpc_call_msg {
+        rpc_call_msg {
+            xid: 3,
+            mtype: msg_type::CALL,
+            rpcvers: 2,
+            prog: 100000,
+            vers: RPCBIND_V4,
+            proc_,
+            cred: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+            verf: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+        }
+    }
+
+    fn query(prog: u32, vers: u32, netid: &str, addr: &str) -> Vec<u8> {
+        let mut args = Vec::new();
+        rpcb {
+            r_prog: prog,
+            r_vers: vers,
+            r_netid: netid.to_string(),
+            r_addr: addr.to_string(),
+            r_owner: String::new(),
+        }
+        .pack(&mut args)
+        .unwrap();
+        args
+    }
+
+    fn getaddr(registry: &Registry, prog: u32, vers: u32, netid: &str) -> String {
+        let reply = handle_rpcbind_call(&call(procedures::GETADDR), &query(prog, vers, netid, ""), registry).unwrap();
+        let results = RpcMessage::success_reply_results(&reply).unwrap();
+        let (uaddr, _) = String::unpack(&mut Cursor::new(results)).unwrap();
+        uaddr
+    }
+
+    #[test]
+    fn test_v4_getaddr_nfs() {
+        let registry = Registry::new();
+        registry.set(&PortmapMessage::create_mapping(100003, 3, IPPROTO_TCP, 2049));
+
+        assert_eq!(getaddr(&registry, 100003, 3, "tcp"), "0.0.0.0.8.1");
+        assert_eq!(getaddr(&registry, 100003, 3, "tcp6"), "::.8.1");
+        // Unregistered version, transport or program: empty address
+        assert_eq!(getaddr(&registry, 100003, 4, "tcp"), "");
+        assert_eq!(getaddr(&registry, 100003, 3, "udp"), "");
+        assert_eq!(getaddr(&registry, 100005, 3, "tcp"), "");
+        assert_eq!(getaddr(&registry, 100003, 3, "local"), "");
+    }
+
+    #[test]
+    fn test_set_unset_and_dump() {
+        let registry = Registry::new();
+        let set = |netid: &str, addr: &str| {
+            let reply = handle_rpcbind_call(&call(procedures::SET), &query(100005, 3, netid, addr), &registry).unwrap();
+            RpcMessage::success_reply_results(&reply).unwrap() == [0, 0, 0, 1]
+        };
+        assert!(set("tcp", "192.0.2.1.78.80"));
+        assert!(!set("tcp", "192.0.2.1"), "an address without a port is refused");
+        assert!(!set("tcp6", "192.0.2.1.78.80"), "the host must match the netid");
+        assert!(!set("local", "/run/mountd.sock"));
+        assert_eq!(registry.getport(&PortmapMessage::create_mapping(100005, 3, IPPROTO_TCP, 0)), 20048);
+
+        let reply = handle_rpcbind_call(&call(procedures::DUMP), &[], &registry).unwrap();
+        let mut cursor = Cursor::new(RpcMessage::success_reply_results(&reply).unwrap());
+        let mut entries = Vec::new();
+        while bool::unpack(&mut cursor).unwrap().0 {
+            entries.push(rpcb::unpack(&mut cursor).unwrap().0);
+        }
+        let listed: Vec<(&str, &str)> = entries.iter().map(|e| (e.r_netid.as_str(), e.r_addr.as_str())).collect();
+        assert_eq!(listed, vec![("tcp", "0.0.0.0.78.80"), ("tcp6", "::.78.80")]);
+
+        let reply = handle_rpcbind_call(&call(procedures::UNSET), &query(100005, 3, "tcp", ""), &registry).unwrap();
+        assert_eq!(RpcMessage::success_reply_results(&reply).unwrap(), [0, 0, 0, 1]);
+        assert_eq!(getaddr(&registry, 100005, 3, "tcp"), "");
+    }
+}
@@ -8,7 +8,7 @@ use std::io::Cursor;
 use xdr_codec::{Pack, Unpack};
 
 // Include xdrgen-generated MOUNT types
-#[allow(dead_code, non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::all)]
+#[allow(dead_code, deprecated, invalid_value, unused_assignments, non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::all)]
 mod generated {
     include!(concat!(env!("OUT_DIR"), "/mount_generated.rs"));
 }
@@ -35,13 +35,26 @@ impl MountMessage {
     }
 
     /// Create a successful mount response
-    pub fn create_mount_ok(fhandle_bytes: Vec<u8>) -> mountres3 {
+    ///
+    /// `auth_flavors` lists the RPC auth flavors the export accepts, most
+    /// preferred first.
+    pub fn create_mount_ok(fhandle_bytes: Vec<u8>, auth_flavors: Vec<i32>) -> mountres3 {
         mountres3::MNT3_OK(mountres3_ok {
             fhandle: fhandle3(fhandle_bytes), // Wrap in newtype
-            auth_flavors: vec![0], // AUTH_NONE
+            auth_flavors,
         })
     }
 
+    /// Serialize a MOUNT error result (status only)
+    ///
+    /// The generated `mountres3::default` arm cannot be packed, so error
+    /// results are written by hand.
+    pub fn serialize_mount_error(status: mountstat3) -> Result<BytesMut> {
+        let mut buf = Vec::new();
+        (status as i32).pack(&mut buf)?;
+        Ok(BytesMut::from(&buf[..]))
+    }
+
     /// Create a mount error response (use the default variant)
     pub fn create_mount_error() -> mountres3 {
         mountres3::default
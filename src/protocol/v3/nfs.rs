@@ -10,7 +10,7 @@ use xdr_codec::{Pack, Unpack};
 use crate::fsal;
 
 // Include xdrgen-generated NFS types
-#[allow(dead_code, non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::all)]
+#[allow(dead_code, deprecated, invalid_value, unused_assignments, non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::all)]
 mod generated {
     include!(concat!(env!("OUT_DIR"), "/nfs_generated.rs"));
 }
@@ -65,13 +65,21 @@ impl NfsMessage {
 
     /// Create a LOOKUP error response
     ///
-    /// LOOKUP error includes directory attributes in the failure case
-    pub fn create_lookup_error_response(status: nfsstat3) -> Result<BytesMut> {
-        // For LOOKUP error, we need status + post_op_attr (dir_attributes)
-        // Since we don't have dir_attributes in error path, we use FALSE for post_op_attr
+    /// LOOKUP error includes directory attributes in the failure case, so
+    /// clients can keep their cache of the directory fresh after a NOENT
+    pub fn create_lookup_error_response(status: nfsstat3, dir_attributes: Option<&fattr3>) -> Result<BytesMut> {
+        // status + post_op_attr (dir_attributes)
         let mut buf = Vec::new();
         (status as i32).pack(&mut buf)?;
-        false.pack(&mut buf)?;  // dir_attributes: post_op_attr = FALSE (no attributes)
+        match dir_attributes {
+            Some(attrs) => {
+                true.pack(&mut buf)?;
+                attrs.pack(&mut buf)?;
+            }
+            None => {
+                false.pack(&mut buf)?;
+            }
+        }
         Ok(BytesMut::from(&buf[..]))
     }
 
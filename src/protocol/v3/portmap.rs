@@ -8,7 +8,7 @@ use std::io::Cursor;
 use xdr_codec::{Pack, Unpack};
 
 // Include xdrgen-generated Portmapper types
-#[allow(dead_code, non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::all)]
+#[allow(dead_code, deprecated, invalid_value, unused_assignments, non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::all)]
 mod generated {
     include!(concat!(env!("OUT_DIR"), "/portmap_generated.rs"));
 }
@@ -27,6 +27,45 @@ impl PortmapMessage {
         Ok(map)
     }
 
+    /// Deserialize CALLIT arguments
+    pub fn deserialize_call_args(data: &[u8]) -> Result<call_args> {
+        let mut cursor = Cursor::new(data);
+        let (args, _bytes_read) = call_args::unpack(&mut cursor)?;
+        Ok(args)
+    }
+
+    /// Deserialize rpcbind v3/v4 registration argument
+    pub fn deserialize_rpcb(data: &[u8]) -> Result<rpcb> {
+        let mut cursor = Cursor::new(data);
+        let (args, _bytes_read) = rpcb::unpack(&mut cursor)?;
+        Ok(args)
+    }
+
+    /// Serialize rpcbind GETADDR result (universal address)
+    pub fn serialize_uaddr(uaddr: &str) -> Result<BytesMut> {
+        let mut buf = Vec::new();
+        xdr_codec::pack_string(uaddr, None, &mut buf)?;
+        Ok(BytesMut::from(&buf[..]))
+    }
+
+    /// Serialize rpcbind DUMP result (optional-data list of registrations)
+    pub fn serialize_rpcb_list(entries: &[rpcb]) -> Result<BytesMut> {
+        let mut buf = Vec::new();
+        for entry in entries {
+            true.pack(&mut buf)?;
+            entry.pack(&mut buf)?;
+        }
+        false.pack(&mut buf)?;
+        Ok(BytesMut::from(&buf[..]))
+    }
+
+    /// Serialize CALLIT result
+    pub fn serialize_call_result(port: u32, res: Vec<u8>) -> Result<BytesMut> {
+        let mut buf = Vec::new();
+        call_result { port, res }.pack(&mut buf)?;
+        Ok(BytesMut::from(&buf[..]))
+    }
+
     /// Serialize boolean result
     pub fn serialize_bool(result: bool) -> Result<BytesMut> {
         let mut buf = Vec::new();
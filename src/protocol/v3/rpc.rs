@@ -2,13 +2,13 @@
 //
 // Wraps xdrgen-generated RPC types and provides serialization helpers
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bytes::BytesMut;
 use std::io::Cursor;
 use xdr_codec::{Pack, Unpack};
 
 // Include xdrgen-generated RPC types
-#[allow(dead_code, non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::all)]
+#[allow(dead_code, deprecated, invalid_value, unused_assignments, non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::all)]
 mod generated {
     include!(concat!(env!("OUT_DIR"), "/rpc_generated.rs"));
 }
@@ -22,11 +22,32 @@ pub struct RpcMessage;
 impl RpcMessage {
     /// Deserialize RPC call from bytes
     pub fn deserialize_call(data: &[u8]) -> Result<rpc_call_msg> {
-        let mut cursor = Cursor::new(data);
-        let (msg, _bytes_read) = rpc_call_msg::unpack(&mut cursor)?;
+        let (msg, _args_offset) = Self::parse_call_with_offset(data)?;
         Ok(msg)
     }
 
+    /// Deserialize an RPC call header and locate its procedure arguments
+    ///
+    /// Decodes the fixed fields and both variable-length `opaque_auth`
+    /// fields (credential, then verifier: flavor, length, padded body), so
+    /// the offset is right for AUTH_SYS and any other flavor.
+    ///
+    /// # Returns
+    /// The call header and the byte offset where procedure arguments begin
+    pub fn parse_call_with_offset(data: &[u8]) -> Result<(rpc_call_msg, usize)> {
+        let mut cursor = Cursor::new(data);
+        let (msg, args_offset) = rpc_call_msg::unpack(&mut cursor)
+            .map_err(|e| anyhow!("Malformed RPC call header ({} bytes): {}", data.len(), e))?;
+        Ok((msg, args_offset))
+    }
+
+    /// Decode AUTH_SYS parameters from a credential body
+    pub fn deserialize_auth_sys(body: &[u8]) -> Result<auth_sys_params> {
+        let mut cursor = Cursor::new(body);
+        let (params, _bytes_read) = auth_sys_params::unpack(&mut cursor)?;
+        Ok(params)
+    }
+
     /// Serialize RPC reply to bytes
     pub fn serialize_reply(reply: &rpc_reply_msg) -> Result<BytesMut> {
         let mut buf = Vec::new();
@@ -34,6 +55,15 @@ impl RpcMessage {
         Ok(BytesMut::from(&buf[..]))
     }
 
+    /// Procedure results of a serialized reply, if the call was accepted and succeeded
+    pub fn success_reply_results(reply: &[u8]) -> Option<&[u8]> {
+        let mut cursor = Cursor::new(reply);
+        let (header, header_len) = rpc_reply_msg::unpack(&mut cursor).ok()?;
+        let accepted = header.stat == reply_stat::MSG_ACCEPTED
+            && header.accept_stat == accept_stat::SUCCESS;
+        accepted.then(|| &reply[header_len..])
+    }
+
     /// Create a successful NULL reply
     pub fn create_null_reply(xid: u32) -> rpc_reply_msg {
         rpc_reply_msg {
@@ -48,6 +78,14 @@ impl RpcMessage {
         }
     }
 
+    /// Serialize an accepted, successful reply with an empty (void) result
+    ///
+    /// Used by every program's NULL procedure and by other void procedures,
+    /// so liveness pings such as `rpcinfo -t` see identical replies.
+    pub fn create_void_reply(xid: u32) -> Result<BytesMut> {
+        Self::serialize_reply(&Self::create_null_reply(xid))
+    }
+
     /// Create a successful reply with procedure result data
     ///
     /// Combines RPC reply header with procedure-specific result data
@@ -78,4 +116,154 @@ impl RpcMessage {
         };
         Self::serialize_reply(&rpc_reply)
     }
+
+    /// Create an RPC error reply for a program version the server does not serve
+    ///
+    /// The reply carries the lowest and highest versions served, so a client
+    /// probing another version (v2 or v4 of NFS) can fall back to one of them.
+    pub fn create_prog_mismatch_reply(xid: u32, low: u32, high: u32) -> Result<BytesMut> {
+        let rpc_reply = rpc_reply_msg {
+            xid,
+            mtype: msg_type::REPLY,
+            stat: reply_stat::MSG_ACCEPTED,
+            verf: opaque_auth {
+                flavor: auth_flavor::AUTH_NONE,
+                body: vec![],
+            },
+            accept_stat: accept_stat::PROG_MISMATCH,
+        };
+        let mut buf = Vec::new();
+        rpc_reply.pack(&mut buf)?;
+        low.pack(&mut buf)?;
+        high.pack(&mut buf)?;
+        Ok(BytesMut::from(&buf[..]))
+    }
+
+    /// Create an RPC error reply for procedures a program does not have
+    pub fn create_proc_unavail_reply(xid: u32) -> Result<BytesMut> {
+        let rpc_reply = rpc_reply_msg {
+            xid,
+            mtype: msg_type::REPLY,
+            stat: reply_stat::MSG_ACCEPTED,
+            verf: opaque_auth {
+                flavor: auth_flavor::AUTH_NONE,
+                body: vec![],
+            },
+            accept_stat: accept_stat::PROC_UNAVAIL,
+        };
+        Self::serialize_reply(&rpc_reply)
+    }
+
+    /// Create a reply denying a call for its credential
+    ///
+    /// A rejected reply carries no verifier: xid, REPLY, MSG_DENIED,
+    /// AUTH_ERROR and the reason.
+    pub fn create_auth_error_reply(xid: u32, stat: auth_stat) -> Result<BytesMut> {
+        let mut buf = Vec::new();
+        xid.pack(&mut buf)?;
+        msg_type::REPLY.pack(&mut buf)?;
+        reply_stat::MSG_DENIED.pack(&mut buf)?;
+        reject_stat::AUTH_ERROR.pack(&mut buf)?;
+        stat.pack(&mut buf)?;
+        Ok(BytesMut::from(&buf[..]))
+    }
+
+    /// Reason a reply denied its call for the credential, if it did
+    pub fn auth_error_status(reply: &[u8]) -> Option<auth_stat> {
+        let mut cursor = Cursor::new(reply.get(8..)?);
+        let (stat, _) = reply_stat::unpack(&mut cursor).ok()?;
+        let (reject, _) = reject_stat::unpack(&mut cursor).ok()?;
+        if stat != reply_stat::MSG_DENIED || reject != reject_stat::AUTH_ERROR {
+            return None;
+        }
+        auth_stat::unpack(&mut cursor).ok().map(|(auth, _)| auth)
+    }
+
+    /// Create an RPC error reply for a call the server failed to process
+    pub fn create_system_err_reply(xid: u32) -> Result<BytesMut> {
+        let rpc_reply = rpc_reply_msg {
+            xid,
+            mtype: msg_type::REPLY,
+            stat: reply_stat::MSG_ACCEPTED,
+            verf: opaque_auth {
+                flavor: auth_flavor::AUTH_NONE,
+                body: vec![],
+            },
+            accept_stat: accept_stat::SYSTEM_ERR,
+        };
+        Self::serialize_reply(&rpc_reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serialize a call with the given credential and append procedure arguments
+    fn call_bytes(cred: opaque_auth, verf: opaque_auth, args: &[u8]) -> Vec<u8> {
+        let call = rpc_call_msg {
+            xid: 42,
+            mtype: msg_type::CALL,
+            rpcvers: 2,
+            prog: 100003,
+            vers: 3,
+            proc_: 1,
+            cred,
+            verf,
+        };
+        let mut buf = Vec::new();
+        call.pack(&mut buf).unwrap();
+        buf.extend_from_slice(args);
+        buf
+    }
+
+    fn none() -> opaque_auth {
+        opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] }
+    }
+
+    #[test]
+    fn test_args_offset_auth_none() {
+        let data = call_bytes(none(), none(), &[0xAA, 0xBB, 0xCC, 0xDD]);
+        let (call, offset) = RpcMessage::parse_call_with_offset(&data).unwrap();
+        assert_eq!(call.xid, 42);
+        // 6 fixed words + two empty opaque_auths (flavor + length)
+        assert_eq!(offset, 24 + 8 + 8);
+        assert_eq!(&data[offset..], &[0xAA, 0xBB, 0xCC, 0xDD]);
+    }
+
+    #[test]
+    fn test_args_offset_auth_sys() {
+        // Machine name of odd length, so the credential body needs padding
+        let params = auth_sys_params {
+            stamp: 1,
+            machinename: "client7".to_string(),
+            uid: 1000,
+            gid: 100,
+            gids: vec![10, 20],
+        };
+        let mut body = Vec::new();
+        params.pack(&mut body).unwrap();
+        let body_len = body.len();
+        let cred = opaque_auth { flavor: auth_flavor::AUTH_SYS, body };
+        // A verifier with an unpadded 5-byte body
+        let verf = opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![1, 2, 3, 4, 5] };
+
+        let data = call_bytes(cred, verf, &[0xAA, 0xBB, 0xCC, 0xDD]);
+        let (call, offset) = RpcMessage::parse_call_with_offset(&data).unwrap();
+        assert_eq!(call.cred.flavor, auth_flavor::AUTH_SYS);
+        assert_eq!(offset, 24 + 8 + body_len.next_multiple_of(4) + 8 + 8);
+        assert_eq!(&data[offset..], &[0xAA, 0xBB, 0xCC, 0xDD]);
+    }
+
+    #[test]
+    fn test_truncated_or_oversized_credentials_rejected() {
+        let data = call_bytes(none(), none(), &[]);
+        assert!(RpcMessage::parse_call_with_offset(&data[..30]).is_err());
+
+        // A credential length beyond the 400-byte limit
+        let mut oversized = data.clone();
+        oversized[28..32].copy_from_slice(&401u32.to_be_bytes());
+        oversized.extend_from_slice(&[0u8; 404]);
+        assert!(RpcMessage::parse_call_with_offset(&oversized).is_err());
+    }
 }
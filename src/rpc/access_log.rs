@@ -0,0 +1,145 @@
+// Access Log
+//
+// Records one structured event per RPC call once its reply is built: who
+// called what, what the procedure returned and how long it took. Events are
+// emitted at info level under the "arcticwolf::access" target, so operators
+// can route or filter them separately from debug output.
+
+use anyhow::Result;
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tracing::info;
+use xdr_codec::Unpack;
+
+use crate::mount::{procedures as mount_procs, MOUNT_PROGRAM};
+use crate::portmap::PORTMAP_PROGRAM;
+use crate::protocol::v3::mount::mountstat3;
+use crate::protocol::v3::nfs::nfsstat3;
+use crate::protocol::v3::rpc::{accept_stat, auth_flavor, reply_stat, rpc_call_msg, rpc_reply_msg, RpcMessage};
+use crate::rpc::admin::ADMIN_PROGRAM;
+use crate::rpc::auth::Credentials;
+
+/// Tracing target of access log events
+pub const ACCESS_LOG_TARGET: &str = "arcticwolf::access";
+
+/// NFS program number (RFC 1813)
+const NFS_PROGRAM: u32 = 100003;
+
+/// NFSv3 procedure names, indexed by procedure number
+const NFS_PROCS: [&str; 22] = [
+    "NULL", "GETATTR", "SETATTR", "LOOKUP", "ACCESS", "READLINK", "READ", "WRITE", "CREATE", "MKDIR", "SYMLINK",
+    "MKNOD", "REMOVE", "RMDIR", "RENAME", "LINK", "READDIR", "READDIRPLUS", "FSSTAT", "FSINFO", "PATHCONF", "COMMIT",
+];
+
+/// MOUNTv3 procedure names, indexed by procedure number
+const MOUNT_PROCS: [&str; 6] = ["NULL", "MNT", "DUMP", "UMNT", "UMNTALL", "EXPORT"];
+
+/// Portmapper v2 procedure names, indexed by procedure number
+const PORTMAP_PROCS: [&str; 6] = ["NULL", "SET", "UNSET", "GETPORT", "DUMP", "CALLIT"];
+
+/// Admin program procedure names, indexed by procedure number
+const ADMIN_PROCS: [&str; 4] = ["NULL", "STATS", "HANDLES", "INVALIDATE"];
+
+/// Short lowercase name of a program ("nfs", "mount", "portmap", "admin"), or "rpc" for others
+pub fn program_name(prog: u32) -> &'static str {
+    match prog {
+        NFS_PROGRAM => "nfs",
+        MOUNT_PROGRAM => "mount",
+        PORTMAP_PROGRAM => "portmap",
+        ADMIN_PROGRAM => "admin",
+        _ => "rpc",
+    }
+}
+
+/// Name of a procedure, or "UNKNOWN" for procedures outside the served programs
+pub fn procedure_name(prog: u32, procedure: u32) -> &'static str {
+    let names: &[&'static str] = match prog {
+        NFS_PROGRAM => &NFS_PROCS,
+        MOUNT_PROGRAM => &MOUNT_PROCS,
+        PORTMAP_PROGRAM => &PORTMAP_PROCS,
+        ADMIN_PROGRAM => &ADMIN_PROCS,
+        _ => &[],
+    };
+    names.get(procedure as usize).copied().unwrap_or("UNKNOWN")
+}
+
+/// Outcome of a call as recorded in the log
+///
+/// The procedure's own status (nfsstat3, mountstat3) when its result starts
+/// with one, otherwise the RPC-level status of the reply. A call dropped
+/// without a reply (a retransmission of a call still in progress) is
+/// "DROPPED".
+pub fn reply_status(call: &rpc_call_msg, result: &Result<impl AsRef<[u8]>>) -> String {
+    let reply = match result {
+        Ok(reply) => reply.as_ref(),
+        Err(_) => return "ERROR".to_string(),
+    };
+    if reply.is_empty() {
+        return "DROPPED".to_string();
+    }
+    if let Some(auth) = RpcMessage::auth_error_status(reply) {
+        return format!("{:?}", auth);
+    }
+    let Ok((header, header_len)) = rpc_reply_msg::unpack(&mut Cursor::new(reply)) else {
+        return "MALFORMED".to_string();
+    };
+    if header.stat != reply_stat::MSG_ACCEPTED {
+        return format!("{:?}", header.stat);
+    }
+    if header.accept_stat != accept_stat::SUCCESS {
+        return format!("{:?}", header.accept_stat);
+    }
+
+    let mut results = Cursor::new(&reply[header_len..]);
+    let procedure_status = match (call.prog, call.proc_) {
+        (NFS_PROGRAM, procedure) if procedure != 0 => nfsstat3::unpack(&mut results).ok().map(|(s, _)| format!("{:?}", s)),
+        (MOUNT_PROGRAM, mount_procs::MNT) => mountstat3::unpack(&mut results).ok().map(|(s, _)| format!("{:?}", s)),
+        _ => None,
+    };
+    procedure_status.unwrap_or_else(|| "SUCCESS".to_string())
+}
+
+/// Log a completed call
+///
+/// # Arguments
+/// * `call` - Parsed call header
+/// * `client` - Address of the caller
+/// * `status` - Outcome of the call, as given by `reply_status`
+/// * `elapsed` - Time spent handling the call
+pub fn record(call: &rpc_call_msg, client: SocketAddr, status: &str, elapsed: Duration) {
+    // Callers without an AUTH_SYS credential carry no uid
+    let uid = match call.cred.flavor {
+        auth_flavor::AUTH_SYS => Credentials::from_call(call).ok().map(|credentials| credentials.uid),
+        _ => None,
+    };
+
+    info!(
+        target: ACCESS_LOG_TARGET,
+        xid = call.xid,
+        prog = call.prog,
+        proc = %procedure_name(call.prog, call.proc_),
+        client = %client.ip(),
+        uid,
+        status = %status,
+        duration_us = elapsed.as_micros() as u64,
+        "rpc call"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_procedure_names() {
+        assert_eq!(procedure_name(100003, 1), "GETATTR");
+        assert_eq!(procedure_name(100003, 21), "COMMIT");
+        assert_eq!(procedure_name(100005, 1), "MNT");
+        assert_eq!(procedure_name(100000, 3), "GETPORT");
+        assert_eq!(procedure_name(100003, 22), "UNKNOWN");
+        assert_eq!(procedure_name(42, 0), "UNKNOWN");
+        assert_eq!(program_name(100005), "mount");
+        assert_eq!(program_name(42), "rpc");
+    }
+}
@@ -0,0 +1,126 @@
+// Admin Program
+//
+// A private RPC program, served on every listener next to NFS and MOUNT, for
+// querying the server's own counters the way nfsstat(8) does, without the
+// HTTP metrics endpoint:
+//
+//   program 0x20004157 (user-defined range, RFC 5531), version 1
+//     0  NULL        void
+//     1  STATS       void -> proc_stats<>
+//     2  HANDLES     void -> handle_entry<>
+//     3  INVALIDATE  opaque handle<> -> bool
+//
+//   struct proc_stats {
+//       string program<>;        /* "nfs", "mount", "portmap", "admin" */
+//       string procedure<>;      /* e.g. "READ" */
+//       unsigned hyper calls;    /* completed calls */
+//       unsigned hyper errors;   /* calls not answered OK or SUCCESS */
+//   };
+//
+//   struct handle_entry {
+//       opaque handle<>;         /* as clients send it */
+//       string path<>;           /* what the backend resolves it to */
+//   };
+//
+// HANDLES lists the file handles the backends have issued, for debugging
+// stale-handle reports. INVALIDATE forgets one, so clients holding it get
+// NFS3ERR_STALE and look the name up again; it answers false for a handle
+// that was not issued (or is an export root, which is never forgotten).
+//
+// Only configured admin clients may call it; to anyone else the program does
+// not exist (PROG_UNAVAIL).
+
+use anyhow::Result;
+use bytes::BytesMut;
+use std::io::Cursor;
+use std::net::IpAddr;
+use tracing::{debug, info, warn};
+use xdr_codec::Pack;
+
+use crate::export::ClientSpec;
+use crate::fsal::Filesystem;
+use crate::nfs::error::describe_handle;
+use crate::metrics::Metrics;
+use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
+
+/// Admin program number
+pub const ADMIN_PROGRAM: u32 = 0x2000_4157;
+
+/// Admin program version
+pub const ADMIN_VERSION: u32 = 1;
+
+/// Admin procedure numbers
+pub mod procedures {
+    pub const NULL: u32 = 0;
+    pub const STATS: u32 = 1;
+    pub const HANDLES: u32 = 2;
+    pub const INVALIDATE: u32 = 3;
+}
+
+/// Handle an admin program call
+///
+/// # Arguments
+/// * `call` - Parsed RPC call header
+/// * `args_data` - Procedure arguments
+/// * `metrics` - Counters STATS reports
+/// * `filesystem` - Backends whose handles HANDLES and INVALIDATE act on
+/// * `admin_clients` - Clients allowed to call the program
+/// * `client` - Address of the caller
+///
+/// # Returns
+/// Serialized RPC reply message
+pub fn handle_admin_call(
+    call: &rpc_call_msg,
+    args_data: &[u8],
+    metrics: &Metrics,
+    filesystem: &dyn Filesystem,
+    admin_clients: &[ClientSpec],
+    client: IpAddr,
+) -> Result<BytesMut> {
+    if !admin_clients.iter().any(|spec| spec.matches(client)) {
+        warn!("Admin call proc {} from {} refused: not an admin client", call.proc_, client);
+        return RpcMessage::create_prog_unavail_reply(call.xid);
+    }
+    if call.vers != ADMIN_VERSION {
+        debug!("Admin call with unsupported version {}", call.vers);
+        return RpcMessage::create_prog_unavail_reply(call.xid);
+    }
+
+    match call.proc_ {
+        procedures::NULL => RpcMessage::create_void_reply(call.xid),
+        procedures::STATS => {
+            let counts = metrics.procedure_counts();
+            debug!("Admin STATS for {}: {} procedures", client, counts.len());
+            let mut buf = Vec::new();
+            (counts.len() as u32).pack(&mut buf)?;
+            for count in counts {
+                count.program.to_string().pack(&mut buf)?;
+                count.procedure.to_string().pack(&mut buf)?;
+                count.calls.pack(&mut buf)?;
+                count.errors.pack(&mut buf)?;
+            }
+            RpcMessage::create_success_reply_with_data(call.xid, BytesMut::from(&buf[..]))
+        }
+        procedures::HANDLES => {
+            let handles = filesystem.issued_handles();
+            debug!("Admin HANDLES for {}: {} handles", client, handles.len());
+            let mut buf = Vec::new();
+            (handles.len() as u32).pack(&mut buf)?;
+            for (handle, path) in handles {
+                xdr_codec::pack_opaque_flex(&handle, None, &mut buf)?;
+                path.pack(&mut buf)?;
+            }
+            RpcMessage::create_success_reply_with_data(call.xid, BytesMut::from(&buf[..]))
+        }
+        procedures::INVALIDATE => {
+            let (handle, _) = xdr_codec::unpack_opaque_flex(&mut Cursor::new(args_data), None)?;
+            let invalidated = filesystem.invalidate_handle(&handle);
+            info!("Admin INVALIDATE from {}: handle {} invalidated={}", client, describe_handle(&handle), invalidated);
+            let mut buf = Vec::new();
+            invalidated.pack(&mut buf)?;
+            RpcMessage::create_success_reply_with_data(call.xid, BytesMut::from(&buf[..]))
+        }
+        _ => RpcMessage::create_proc_unavail_reply(call.xid),
+    }
+}
+
@@ -0,0 +1,209 @@
+// Backend Circuit Breaker
+//
+// When the storage behind an export goes away (an unmounted volume, a dead
+// network filesystem), every call on the export fails, and each failure may
+// take a slow syscall to arrive at. Calls are counted per export: after
+// `threshold` consecutive NFS3ERR_IO replies the export's breaker opens, and
+// for `cooldown` its calls are answered NFS3ERR_IO at once without touching
+// the backend. The first call after the cooldown stats the export root; if
+// that succeeds the breaker closes and calls go through again, otherwise it
+// stays open for another cooldown. Any successful call resets the count, so
+// errors confined to a few files do not open the breaker while the rest of
+// the export is served.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use xdr_codec::Unpack;
+
+use crate::export::ExportId;
+use crate::protocol::v3::nfs::nfsstat3;
+use crate::protocol::v3::rpc::{accept_stat, reply_stat, rpc_reply_msg};
+
+/// Circuit breaker configuration
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerConfig {
+    /// Consecutive NFS3ERR_IO replies that open an export's breaker (0: never)
+    pub threshold: u32,
+    /// Time an open breaker fails calls before the root is checked again
+    pub cooldown: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 10,
+            cooldown: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Breaker of one export
+#[derive(Default)]
+struct Breaker {
+    /// NFS3ERR_IO replies since the last success
+    failures: u32,
+    /// While set, calls fail without reaching the backend until then
+    open_until: Option<Instant>,
+}
+
+/// Per-export circuit breakers
+///
+/// Thread-safe and cheap to clone (shared across connections).
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    breakers: Arc<Mutex<HashMap<ExportId, Breaker>>>,
+}
+
+impl CircuitBreaker {
+    /// Create breakers, all closed
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Whether a call on an export may reach its backend
+    ///
+    /// Once the cooldown of an open breaker is over, the first caller runs
+    /// `root_available`; callers arriving meanwhile keep failing fast.
+    ///
+    /// # Arguments
+    /// * `export_id` - Export the call targets
+    /// * `root_available` - Checks whether the export root can be read again
+    ///
+    /// # Returns
+    /// false if the call should fail without reaching the backend
+    pub fn admit(&self, export_id: ExportId, root_available: impl FnOnce() -> bool) -> bool {
+        {
+            let mut breakers = self.breakers.lock().unwrap();
+            let Some(breaker) = breakers.get_mut(&export_id) else {
+                return true;
+            };
+            match breaker.open_until {
+                None => return true,
+                Some(until) if Instant::now() < until => return false,
+                // This caller checks the root; the others wait out another cooldown
+                Some(_) => breaker.open_until = Some(Instant::now() + self.config.cooldown),
+            }
+        }
+
+        // The root is checked without the lock, as the backend may be slow
+        if !root_available() {
+            warn!("Export {} is still failing, breaker stays open for {:?}", export_id, self.config.cooldown);
+            return false;
+        }
+        info!("Export {} recovered, breaker closed", export_id);
+        self.breakers.lock().unwrap().remove(&export_id);
+        true
+    }
+
+    /// Count the outcome of a call that reached the backend
+    ///
+    /// # Arguments
+    /// * `export_id` - Export the call targeted
+    /// * `failed` - Whether the backend failed it (NFS3ERR_IO)
+    pub fn record(&self, export_id: ExportId, failed: bool) {
+        if self.config.threshold == 0 {
+            return;
+        }
+        let mut breakers = self.breakers.lock().unwrap();
+        if !failed {
+            breakers.remove(&export_id);
+            return;
+        }
+        let breaker = breakers.entry(export_id).or_default();
+        breaker.failures += 1;
+        if breaker.failures >= self.config.threshold && breaker.open_until.is_none() {
+            warn!(
+                "Export {} failed {} calls in a row, failing its calls for {:?}",
+                export_id, breaker.failures, self.config.cooldown
+            );
+            breaker.open_until = Some(Instant::now() + self.config.cooldown);
+        }
+    }
+
+    /// Exports whose breaker is open, in ascending order
+    pub fn open_exports(&self) -> Vec<ExportId> {
+        let breakers = self.breakers.lock().unwrap();
+        let mut open: Vec<ExportId> = breakers
+            .iter()
+            .filter(|(_, breaker)| breaker.open_until.is_some())
+            .map(|(export_id, _)| *export_id)
+            .collect();
+        open.sort_unstable();
+        open
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(BreakerConfig::default())
+    }
+}
+
+/// Whether an NFSv3 reply reports a backend failure (NFS3ERR_IO)
+pub fn is_backend_failure(reply: &[u8]) -> bool {
+    let mut cursor = Cursor::new(reply);
+    let Ok((header, _)) = rpc_reply_msg::unpack(&mut cursor) else {
+        return false;
+    };
+    if header.stat != reply_stat::MSG_ACCEPTED || header.accept_stat != accept_stat::SUCCESS {
+        return false;
+    }
+    matches!(nfsstat3::unpack(&mut cursor), Ok((nfsstat3::NFS3ERR_IO, _)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(BreakerConfig { threshold, cooldown })
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breakers = breaker(3, Duration::from_secs(60));
+        breakers.record(1, true);
+        breakers.record(1, true);
+        breakers.record(1, false);
+        breakers.record(1, true);
+        breakers.record(1, true);
+        assert!(breakers.admit(1, || unreachable!()), "A success resets the count");
+
+        breakers.record(1, true);
+        assert!(!breakers.admit(1, || unreachable!()));
+        assert!(breakers.admit(2, || unreachable!()), "Other exports are not affected");
+        assert_eq!(breakers.open_exports(), vec![1]);
+    }
+
+    #[test]
+    fn test_root_checked_after_cooldown() {
+        let breakers = breaker(1, Duration::from_millis(20));
+        breakers.record(1, true);
+        assert!(!breakers.admit(1, || unreachable!()));
+
+        // Still failing: open for another cooldown
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!breakers.admit(1, || false));
+        assert!(!breakers.admit(1, || unreachable!()));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breakers.admit(1, || true));
+        assert!(breakers.open_exports().is_empty());
+    }
+
+    #[test]
+    fn test_zero_threshold_never_opens() {
+        let breakers = breaker(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            breakers.record(1, true);
+        }
+        assert!(breakers.admit(1, || unreachable!()));
+    }
+}
@@ -0,0 +1,387 @@
+// Duplicate Request Cache (DRC)
+//
+// NFSv3 clients retransmit requests when a reply is lost or late. For
+// non-idempotent procedures (CREATE, REMOVE, RENAME, ...) re-executing a
+// retransmission returns a misleading error (e.g. NOENT for a REMOVE that
+// already succeeded). The DRC remembers recent replies so a retransmission
+// gets the original reply instead of being executed twice.
+//
+// A request is entered as in progress before it executes. A retransmission
+// arriving while the original still runs (the client timed out, or got
+// NFS3ERR_JUKEBOX and resent) is dropped without a reply; the client
+// resends again and then gets the original's reply.
+
+use bytes::BytesMut;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// NFSv3 procedures whose replies are cached
+///
+/// These procedures change server state, so executing a retransmission a
+/// second time does not produce the same result as the first execution.
+const NON_IDEMPOTENT_PROCS: &[u32] = &[
+    8,  // CREATE
+    9,  // MKDIR
+    10, // SYMLINK
+    11, // MKNOD
+    12, // REMOVE
+    13, // RMDIR
+    14, // RENAME
+    15, // LINK
+];
+
+/// Check whether replies for an NFSv3 procedure should be cached
+pub fn is_non_idempotent(procedure: u32) -> bool {
+    NON_IDEMPOTENT_PROCS.contains(&procedure)
+}
+
+/// Duplicate request cache configuration
+#[derive(Debug, Clone, Copy)]
+pub struct DrcConfig {
+    /// Maximum number of cached replies
+    pub capacity: usize,
+    /// How long a cached reply stays valid
+    pub ttl: Duration,
+}
+
+impl Default for DrcConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            ttl: Duration::from_secs(120),
+        }
+    }
+}
+
+/// Cache key: (client address, xid, procedure)
+///
+/// Only the client IP is used: a client that reconnects after a dropped
+/// TCP connection retransmits from a new source port.
+type DrcKey = (IpAddr, u32, u32);
+
+struct DrcEntry {
+    /// The reply, or None while the request is still executing
+    reply: Option<BytesMut>,
+    inserted: Instant,
+    /// Position in the LRU order; stale order records carry an older value
+    seq: u64,
+}
+
+struct DrcInner {
+    entries: HashMap<DrcKey, DrcEntry>,
+    /// Least recently used first
+    order: VecDeque<(DrcKey, u64)>,
+    next_seq: u64,
+}
+
+impl DrcInner {
+    fn touch(&mut self, key: DrcKey) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.order.push_back((key, seq));
+        seq
+    }
+}
+
+/// What to do with a request, as decided by `DuplicateRequestCache::begin`
+pub enum DrcLookup<'a> {
+    /// First sight of the request: execute it and `complete` the entry
+    Execute(DrcPending<'a>),
+    /// The original is still executing: drop this retransmission
+    InProgress,
+    /// The original's reply, to send again
+    Replay(BytesMut),
+}
+
+/// In-progress entry of a request being executed
+///
+/// Dropped without `complete` (the handler failed or panicked), the entry
+/// is removed so a retransmission executes the request again.
+pub struct DrcPending<'a> {
+    cache: &'a DuplicateRequestCache,
+    key: DrcKey,
+    completed: bool,
+}
+
+impl DrcPending<'_> {
+    /// Store the reply of the executed request
+    pub fn complete(mut self, reply: BytesMut) {
+        self.completed = true;
+        let (client, xid, procedure) = self.key;
+        self.cache.insert(client, xid, procedure, reply);
+    }
+}
+
+impl Drop for DrcPending<'_> {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        let mut inner = self.cache.inner.lock().unwrap();
+        if inner.entries.get(&self.key).is_some_and(|entry| entry.reply.is_none()) {
+            inner.entries.remove(&self.key);
+        }
+    }
+}
+
+/// Bounded LRU cache of replies to non-idempotent requests
+///
+/// Thread-safe and cheap to clone (shared across connections).
+#[derive(Clone)]
+pub struct DuplicateRequestCache {
+    config: DrcConfig,
+    inner: Arc<Mutex<DrcInner>>,
+}
+
+impl DuplicateRequestCache {
+    /// Create a new cache
+    pub fn new(config: DrcConfig) -> Self {
+        Self {
+            config,
+            inner: Arc::new(Mutex::new(DrcInner {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+                next_seq: 0,
+            })),
+        }
+    }
+
+    /// Look up a request before executing it
+    ///
+    /// A request not in the cache (or whose entry expired) is entered as in
+    /// progress; the returned `DrcPending` completes it with the reply.
+    pub fn begin(&self, client: IpAddr, xid: u32, procedure: u32) -> DrcLookup<'_> {
+        let key = (client, xid, procedure);
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(entry) = inner.entries.get(&key)
+            && entry.inserted.elapsed() <= self.config.ttl
+        {
+            let Some(reply) = entry.reply.clone() else {
+                debug!("DRC: client={}, xid={}, proc={} is still in progress", client, xid, procedure);
+                return DrcLookup::InProgress;
+            };
+            let seq = inner.touch(key);
+            if let Some(entry) = inner.entries.get_mut(&key) {
+                entry.seq = seq;
+            }
+            debug!("DRC hit: client={}, xid={}, proc={}", client, xid, procedure);
+            return DrcLookup::Replay(reply);
+        }
+
+        if self.config.capacity > 0 {
+            let seq = inner.touch(key);
+            inner.entries.insert(
+                key,
+                DrcEntry {
+                    reply: None,
+                    inserted: Instant::now(),
+                    seq,
+                },
+            );
+            self.evict(&mut inner);
+        }
+        DrcLookup::Execute(DrcPending {
+            cache: self,
+            key,
+            completed: false,
+        })
+    }
+
+    /// Look up a cached reply
+    ///
+    /// Expired entries are dropped and reported as misses, as are requests
+    /// still in progress.
+    pub fn get(&self, client: IpAddr, xid: u32, procedure: u32) -> Option<BytesMut> {
+        let key = (client, xid, procedure);
+        let mut inner = self.inner.lock().unwrap();
+
+        let expired = match inner.entries.get(&key) {
+            Some(entry) => entry.inserted.elapsed() > self.config.ttl,
+            None => return None,
+        };
+
+        if expired {
+            inner.entries.remove(&key);
+            return None;
+        }
+
+        let seq = inner.touch(key);
+        let entry = inner.entries.get_mut(&key)?;
+        let reply = entry.reply.clone()?;
+        entry.seq = seq;
+
+        debug!(
+            "DRC hit: client={}, xid={}, proc={}",
+            client, xid, procedure
+        );
+
+        Some(reply)
+    }
+
+    /// Store a reply, evicting the least recently used entries if full
+    pub fn insert(&self, client: IpAddr, xid: u32, procedure: u32, reply: BytesMut) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        let key = (client, xid, procedure);
+        let mut inner = self.inner.lock().unwrap();
+
+        let seq = inner.touch(key);
+        inner.entries.insert(
+            key,
+            DrcEntry {
+                reply: Some(reply),
+                inserted: Instant::now(),
+                seq,
+            },
+        );
+        self.evict(&mut inner);
+    }
+
+    /// Evict least recently used entries beyond the capacity
+    fn evict(&self, inner: &mut DrcInner) {
+        while inner.entries.len() > self.config.capacity {
+            let Some((old_key, old_seq)) = inner.order.pop_front() else {
+                break;
+            };
+            // Skip order records superseded by a later touch
+            if inner.entries.get(&old_key).is_some_and(|e| e.seq == old_seq) {
+                inner.entries.remove(&old_key);
+            }
+        }
+
+        // Keep the order queue from growing without bound on cache hits
+        if inner.order.len() > self.config.capacity * 2 {
+            let DrcInner { entries, order, .. } = inner;
+            order.retain(|(k, s)| entries.get(k).is_some_and(|e| e.seq == *s));
+        }
+    }
+
+    /// Drop every reply cached for a client
+    ///
+    /// # Returns
+    /// Number of replies dropped
+    pub fn forget_client(&self, client: IpAddr) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.entries.len();
+        let DrcInner { entries, order, .. } = &mut *inner;
+        entries.retain(|(entry_client, _, _), _| *entry_client != client);
+        order.retain(|(key, _)| key.0 != client);
+        before - entries.len()
+    }
+
+    /// Number of cached replies
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Check if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for DuplicateRequestCache {
+    fn default() -> Self {
+        Self::new(DrcConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn test_non_idempotent_procs() {
+        assert!(is_non_idempotent(12)); // REMOVE
+        assert!(is_non_idempotent(14)); // RENAME
+        assert!(!is_non_idempotent(1)); // GETATTR
+        assert!(!is_non_idempotent(7)); // WRITE
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let drc = DuplicateRequestCache::default();
+        drc.insert(CLIENT, 1, 12, BytesMut::from(&b"reply"[..]));
+
+        assert_eq!(drc.get(CLIENT, 1, 12).as_deref(), Some(&b"reply"[..]));
+        assert!(drc.get(CLIENT, 1, 13).is_none(), "Procedure is part of the key");
+        assert!(drc.get(CLIENT, 2, 12).is_none(), "XID is part of the key");
+    }
+
+    #[test]
+    fn test_in_progress_request_is_not_executed_again() {
+        let drc = DuplicateRequestCache::default();
+        let DrcLookup::Execute(pending) = drc.begin(CLIENT, 1, 12) else {
+            panic!("First sight of a request executes it");
+        };
+        assert!(matches!(drc.begin(CLIENT, 1, 12), DrcLookup::InProgress));
+        assert!(drc.get(CLIENT, 1, 12).is_none(), "No reply to replay yet");
+
+        pending.complete(BytesMut::from(&b"reply"[..]));
+        assert!(matches!(drc.begin(CLIENT, 1, 12), DrcLookup::Replay(reply) if reply == b"reply"[..]));
+
+        // A request whose handler failed is executed again
+        let DrcLookup::Execute(pending) = drc.begin(CLIENT, 2, 12) else {
+            panic!("First sight of a request executes it");
+        };
+        drop(pending);
+        assert!(matches!(drc.begin(CLIENT, 2, 12), DrcLookup::Execute(_)));
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let drc = DuplicateRequestCache::new(DrcConfig {
+            capacity: 2,
+            ttl: Duration::from_secs(60),
+        });
+
+        drc.insert(CLIENT, 1, 12, BytesMut::from(&b"one"[..]));
+        drc.insert(CLIENT, 2, 12, BytesMut::from(&b"two"[..]));
+
+        // Touch xid 1 so xid 2 becomes least recently used
+        assert!(drc.get(CLIENT, 1, 12).is_some());
+
+        drc.insert(CLIENT, 3, 12, BytesMut::from(&b"three"[..]));
+
+        assert_eq!(drc.len(), 2);
+        assert!(drc.get(CLIENT, 1, 12).is_some());
+        assert!(drc.get(CLIENT, 2, 12).is_none(), "LRU entry should be evicted");
+        assert!(drc.get(CLIENT, 3, 12).is_some());
+    }
+
+    #[test]
+    fn test_forget_client() {
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let drc = DuplicateRequestCache::default();
+        drc.insert(CLIENT, 1, 12, BytesMut::from(&b"one"[..]));
+        drc.insert(CLIENT, 2, 13, BytesMut::from(&b"two"[..]));
+        drc.insert(other, 1, 12, BytesMut::from(&b"other"[..]));
+
+        assert_eq!(drc.forget_client(CLIENT), 2);
+        assert!(drc.get(CLIENT, 1, 12).is_none());
+        assert!(drc.get(other, 1, 12).is_some(), "Other clients keep their replies");
+    }
+
+    #[test]
+    fn test_ttl_expiry() {
+        let drc = DuplicateRequestCache::new(DrcConfig {
+            capacity: 16,
+            ttl: Duration::ZERO,
+        });
+
+        drc.insert(CLIENT, 1, 12, BytesMut::from(&b"reply"[..]));
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert!(drc.get(CLIENT, 1, 12).is_none(), "Expired entry should miss");
+        assert!(drc.is_empty());
+    }
+}
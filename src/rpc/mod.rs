@@ -2,4 +2,12 @@
 //
 // Provides TCP server with RPC record marking protocol
 
+pub mod access_log;
+pub mod admin;
+pub mod auth;
+pub mod breaker;
+pub mod drc;
+pub mod rate_limit;
+pub mod record;
 pub mod server;
+pub mod wire_log;
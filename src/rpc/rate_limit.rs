@@ -0,0 +1,243 @@
+// Request Rate Limiting
+//
+// A single client can keep the server busy with a stream of cheap calls and
+// starve everyone else. Calls are metered by token buckets: one per client
+// address and one shared by all clients. Each bucket holds up to one
+// second's worth of tokens and refills at its rate; a call takes a token
+// from both. A call finding a bucket empty reserves the next token and is
+// delayed until it is due; one that would wait longer than `max_delay` is
+// dropped, and the client retransmits it later.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Interval between sweeps of idle client buckets
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Rate limiter configuration
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /// Calls per second from one client address (0: unlimited)
+    pub per_client: u32,
+    /// Calls per second from all clients together (0: unlimited)
+    pub global: u32,
+    /// Longest a call is delayed before it is dropped instead
+    pub max_delay: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_client: 0,
+            global: 0,
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Token bucket refilling at `rate` tokens per second, up to `rate` tokens
+///
+/// Tokens go negative while calls are waiting for reserved ones.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(rate: u32, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(rate),
+            updated: now,
+        }
+    }
+
+    /// Refill for the time since the last update
+    fn refill(&mut self, rate: u32, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * f64::from(rate)).min(f64::from(rate));
+        self.updated = now;
+    }
+
+    /// Time until a token taken now would have been refilled
+    fn wait(&self, rate: u32) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / f64::from(rate))
+        }
+    }
+
+    /// Whether the bucket is full again, i.e. the client has been idle
+    fn is_full(&self, rate: u32, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * f64::from(rate) >= f64::from(rate)
+    }
+}
+
+struct RateLimiterInner {
+    clients: HashMap<IpAddr, Bucket>,
+    global: Bucket,
+    last_prune: Instant,
+}
+
+/// Per-client and global request rate limiter
+///
+/// Thread-safe and cheap to clone (shared across connections).
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    inner: Arc<Mutex<RateLimiterInner>>,
+}
+
+impl RateLimiter {
+    /// Create a new limiter
+    pub fn new(config: RateLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            inner: Arc::new(Mutex::new(RateLimiterInner {
+                clients: HashMap::new(),
+                global: Bucket::full(config.global, now),
+                last_prune: now,
+            })),
+        }
+    }
+
+    /// Whether any limit is configured
+    pub fn is_enabled(&self) -> bool {
+        self.config.per_client > 0 || self.config.global > 0
+    }
+
+    /// Admit a call from `client`
+    ///
+    /// # Returns
+    /// The delay before the call may run (zero within the limits), or None
+    /// if it would exceed `max_delay` and should be dropped
+    pub fn admit(&self, client: IpAddr) -> Option<Duration> {
+        if !self.is_enabled() {
+            return Some(Duration::ZERO);
+        }
+
+        let RateLimitConfig { per_client, global, max_delay } = self.config;
+        let now = Instant::now();
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+
+        if now.duration_since(inner.last_prune) >= PRUNE_INTERVAL {
+            let before = inner.clients.len();
+            inner.clients.retain(|_, bucket| !bucket.is_full(per_client, now));
+            inner.last_prune = now;
+            debug!("Rate limiter pruned {} idle client(s)", before - inner.clients.len());
+        }
+
+        let mut wait = Duration::ZERO;
+        if global > 0 {
+            inner.global.refill(global, now);
+            wait = wait.max(inner.global.wait(global));
+        }
+        let bucket = if per_client > 0 {
+            let bucket = inner.clients.entry(client).or_insert_with(|| Bucket::full(per_client, now));
+            bucket.refill(per_client, now);
+            wait = wait.max(bucket.wait(per_client));
+            Some(bucket)
+        } else {
+            None
+        };
+
+        if wait > max_delay {
+            return None;
+        }
+        if let Some(bucket) = bucket {
+            bucket.tokens -= 1.0;
+        }
+        if global > 0 {
+            inner.global.tokens -= 1.0;
+        }
+        Some(wait)
+    }
+
+    /// Number of clients with a bucket
+    pub fn client_count(&self) -> usize {
+        self.inner.lock().unwrap().clients.len()
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(RateLimitConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    #[test]
+    fn test_disabled_admits_everything() {
+        let limiter = RateLimiter::default();
+        for _ in 0..10_000 {
+            assert_eq!(limiter.admit(CLIENT), Some(Duration::ZERO));
+        }
+        assert_eq!(limiter.client_count(), 0);
+    }
+
+    #[test]
+    fn test_per_client_burst_is_delayed_then_dropped() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_client: 10,
+            global: 0,
+            max_delay: Duration::from_millis(500),
+        });
+
+        // One second's worth passes at once, the next calls wait for
+        // their token, and those that would wait too long are dropped
+        for _ in 0..10 {
+            assert_eq!(limiter.admit(CLIENT), Some(Duration::ZERO));
+        }
+        let waits: Vec<_> = (0..8).map(|_| limiter.admit(CLIENT)).collect();
+        assert!(waits[..5].iter().all(|wait| wait.is_some_and(|wait| !wait.is_zero())), "{:?}", waits);
+        assert!(waits[0] < waits[4], "{:?}", waits);
+        assert!(waits[6..].iter().all(Option::is_none), "{:?}", waits);
+
+        // Other clients have their own bucket
+        assert_eq!(limiter.admit(OTHER), Some(Duration::ZERO));
+        assert_eq!(limiter.client_count(), 2);
+    }
+
+    #[test]
+    fn test_global_limit_spans_clients() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_client: 0,
+            global: 4,
+            max_delay: Duration::ZERO,
+        });
+        let admitted = (0..8)
+            .filter(|i| limiter.admit(if i % 2 == 0 { CLIENT } else { OTHER }).is_some())
+            .count();
+        assert_eq!(admitted, 4);
+    }
+
+    #[test]
+    fn test_idle_clients_are_pruned() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_client: 1000,
+            ..Default::default()
+        });
+        limiter.admit(CLIENT);
+        limiter.admit(OTHER);
+        assert_eq!(limiter.client_count(), 2);
+
+        // Pretend the last sweep was long ago; both buckets have refilled
+        std::thread::sleep(Duration::from_millis(5));
+        limiter.inner.lock().unwrap().last_prune -= PRUNE_INTERVAL;
+        limiter.admit(CLIENT);
+        assert_eq!(limiter.client_count(), 1);
+    }
+}
@@ -0,0 +1,174 @@
+// RPC Record Marking (RFC 5531 section 11)
+//
+// Over TCP every RPC message is sent as one or more fragments. Each fragment
+// starts with a 4-byte mark: bit 31 flags the last fragment of the record,
+// bits 0-30 hold the fragment length.
+
+use std::io::IoSlice;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Record mark bit flagging the last fragment of a record
+pub const LAST_FRAGMENT: u32 = 0x8000_0000;
+
+/// Largest length a record mark can carry
+pub const MAX_FRAGMENT_LEN: usize = 0x7FFF_FFFF;
+
+/// Default largest fragment sent by the server
+pub const DEFAULT_MAX_FRAGMENT: usize = 256 * 1024;
+
+/// Build the record mark for a fragment
+pub fn record_mark(len: usize, last: bool) -> [u8; 4] {
+    debug_assert!(len <= MAX_FRAGMENT_LEN);
+    let flag = if last { LAST_FRAGMENT } else { 0 };
+    (len as u32 | flag).to_be_bytes()
+}
+
+/// Largest number of slices handed to one vectored write, kept under the
+/// IOV_MAX of common platforms
+const MAX_WRITE_SLICES: usize = 64;
+
+/// Write one RPC message as a record of fragments
+///
+/// The payload is split into fragments of at most `max_fragment` bytes
+/// (capped at what a record mark can express); only the final fragment
+/// carries the last-fragment bit. An empty payload is sent as a single
+/// empty last fragment.
+///
+/// Marks and fragments go out together through vectored writes where the
+/// stream allows it, so a reply usually takes a single syscall, clients
+/// never see a mark separated from its data, and the payload is not copied
+/// into a staging buffer. Short writes resume where the stream stopped.
+///
+/// # Arguments
+/// * `writer` - Stream to write to
+/// * `payload` - Complete RPC message
+/// * `max_fragment` - Largest fragment to emit
+pub async fn write_record<W>(writer: &mut W, payload: &[u8], max_fragment: usize) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let max_fragment = max_fragment.clamp(1, MAX_FRAGMENT_LEN);
+    let chunks: Vec<&[u8]> = if payload.is_empty() {
+        vec![&[]]
+    } else {
+        payload.chunks(max_fragment).collect()
+    };
+    let marks: Vec<[u8; 4]> = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| record_mark(chunk.len(), i + 1 == chunks.len()))
+        .collect();
+    let mut slices: Vec<IoSlice> = marks
+        .iter()
+        .zip(&chunks)
+        .flat_map(|(mark, chunk)| [IoSlice::new(mark), IoSlice::new(chunk)])
+        .collect();
+    write_all_vectored(writer, &mut slices).await
+}
+
+/// Write every byte of `slices`, resuming after short writes
+async fn write_all_vectored<W>(writer: &mut W, mut slices: &mut [IoSlice<'_>]) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    // Drop leading empty slices, so an empty write is not taken for a closed stream
+    IoSlice::advance_slices(&mut slices, 0);
+    while !slices.is_empty() {
+        let batch = slices.len().min(MAX_WRITE_SLICES);
+        let written = writer.write_vectored(&slices[..batch]).await?;
+        if written == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        IoSlice::advance_slices(&mut slices, written);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    /// Read one record the way a simple client would: fragment by fragment
+    /// until the last-fragment bit, returning the payload and fragment count
+    async fn read_record<R: tokio::io::AsyncRead + Unpin>(reader: &mut R) -> (Vec<u8>, usize) {
+        let mut payload = Vec::new();
+        let mut fragments = 0;
+        loop {
+            let mut header = [0u8; 4];
+            reader.read_exact(&mut header).await.unwrap();
+            let mark = u32::from_be_bytes(header);
+            let mut fragment = vec![0u8; (mark & !LAST_FRAGMENT) as usize];
+            reader.read_exact(&mut fragment).await.unwrap();
+            payload.extend_from_slice(&fragment);
+            fragments += 1;
+            if mark & LAST_FRAGMENT != 0 {
+                return (payload, fragments);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multi_fragment_record_reassembles() {
+        let payload: Vec<u8> = (0..10_000u32).map(|i| (i % 253) as u8).collect();
+
+        // A small duplex buffer also forces partial writes
+        let (mut client, mut server) = tokio::io::duplex(1000);
+        let writer = tokio::spawn(async move {
+            write_record(&mut server, &payload, 4096).await.unwrap();
+            write_record(&mut server, b"next", 4096).await.unwrap();
+            write_record(&mut server, &[], 4096).await.unwrap();
+            payload
+        });
+
+        let (received, fragments) = read_record(&mut client).await;
+        let payload = writer.await.unwrap();
+        assert_eq!(fragments, 3);
+        assert_eq!(received, payload);
+
+        // Following records are framed independently
+        assert_eq!(read_record(&mut client).await, (b"next".to_vec(), 1));
+        assert_eq!(read_record(&mut client).await, (Vec::new(), 1));
+    }
+
+    #[tokio::test]
+    async fn test_fragment_marks() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        write_record(&mut server, &[1, 2, 3, 4, 5], 4).await.unwrap();
+        drop(server);
+
+        let mut wire = Vec::new();
+        client.read_to_end(&mut wire).await.unwrap();
+        assert_eq!(wire, [0, 0, 0, 4, 1, 2, 3, 4, 0x80, 0, 0, 1, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_slow_reader_receives_full_framing() {
+        let payload: Vec<u8> = (0..5_000u32).map(|i| (i % 251) as u8).collect();
+        let mut expected = Vec::new();
+        for (i, chunk) in payload.chunks(1024).enumerate() {
+            expected.extend_from_slice(&record_mark(chunk.len(), i == 4));
+            expected.extend_from_slice(chunk);
+        }
+
+        // The writer blocks on a nearly full pipe while the reader trickles
+        // bytes out, so every write is short and lands mid-mark or mid-fragment
+        let (mut client, mut server) = tokio::io::duplex(7);
+        let writer = tokio::spawn(async move {
+            write_record(&mut server, &payload, 1024).await.unwrap();
+        });
+
+        let mut wire = Vec::new();
+        let mut chunk = [0u8; 13];
+        while wire.len() < expected.len() {
+            let n = client.read(&mut chunk).await.unwrap();
+            assert_ne!(n, 0, "stream closed after {} bytes", wire.len());
+            wire.extend_from_slice(&chunk[..n]);
+            if wire.len() % 5 == 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+        }
+        writer.await.unwrap();
+        assert_eq!(wire, expected);
+    }
+}
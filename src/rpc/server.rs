@@ -3,96 +3,572 @@
 // Implements Sun RPC over TCP with record marking protocol (RFC 5531)
 
 use anyhow::{anyhow, Result};
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Bytes, BytesMut};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::{lookup_host, TcpListener, TcpSocket, TcpStream};
+use tokio::sync::{mpsc, watch, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::{timeout_at, Instant};
 use tracing::{debug, error, info, warn};
 
+use crate::export::{args_export_id, ClientSpec, ExportOptions, ExportResolver, ExportRouter, ExportTable};
 use crate::fsal::Filesystem;
+use crate::health;
+use crate::metrics::{self, Metrics};
+use crate::mount::MountTable;
 use crate::portmap::Registry;
-use crate::protocol::v3::rpc::{rpc_call_msg, RpcMessage};
+use crate::rpc::access_log;
+use crate::rpc::admin::{self, ADMIN_PROGRAM};
+use crate::rpc::auth::Credentials;
+use crate::rpc::breaker::{self, BreakerConfig, CircuitBreaker};
+use crate::rpc::drc::{self, DrcConfig, DrcLookup, DuplicateRequestCache};
+use crate::rpc::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::rpc::record;
+use crate::rpc::wire_log;
+use crate::protocol::v3::nfs::nfsstat3;
+use crate::protocol::v3::rpc::{auth_stat, rpc_call_msg, RpcMessage};
+
+/// State shared by all connections of a server
+#[derive(Clone)]
+pub struct ServerContext {
+    /// Portmapper registry
+    pub registry: Registry,
+    /// Export resolver (MOUNT dirpath and handle prefix routing)
+    pub exports: Arc<dyn ExportResolver>,
+    /// Filesystem seen by the NFS layer (routes by handle export prefix)
+    pub filesystem: Arc<dyn Filesystem>,
+    /// Duplicate request cache
+    pub drc: DuplicateRequestCache,
+    /// Request counters, latencies and gauges
+    pub metrics: Arc<Metrics>,
+    /// Per-client and global request rate limits
+    pub rate_limiter: RateLimiter,
+    /// Per-export breakers failing calls fast while a backend is down
+    pub breakers: CircuitBreaker,
+    /// Exports each client has mounted
+    pub mounts: MountTable,
+    /// Clients allowed to call the admin program
+    pub admin_clients: Arc<Vec<ClientSpec>>,
+}
+
+impl ServerContext {
+    /// Create a context serving the given exports
+    pub fn new(registry: Registry, exports: Arc<dyn ExportResolver>) -> Self {
+        let filesystem: Arc<dyn Filesystem> = Arc::new(ExportRouter::new(exports.clone()));
+        Self {
+            registry,
+            exports,
+            filesystem,
+            drc: DuplicateRequestCache::default(),
+            metrics: Arc::new(Metrics::new()),
+            rate_limiter: RateLimiter::default(),
+            breakers: CircuitBreaker::default(),
+            mounts: MountTable::new(),
+            admin_clients: Arc::new(Vec::new()),
+        }
+    }
+}
+
+/// Default maximum number of concurrent connections
+pub const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+
+/// Default time a connection may take to deliver a complete message
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default limit on the size of one RPC request, summed over its fragments
+///
+/// Leaves ample room for a maximal (1 MiB) WRITE plus its headers.
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 2 * 1024 * 1024;
+
+/// Default time a call may take before the client is told to retry
+///
+/// Well below the 60 s after which Linux clients retransmit over TCP.
+pub const DEFAULT_OP_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// RPC server handling TCP connections with record marking
 pub struct RpcServer {
-    addr: String,
-    registry: Registry,
-    filesystem: Arc<dyn Filesystem>,
+    /// Addresses to listen on; every listener serves every program
+    addrs: Vec<String>,
+    context: ServerContext,
+    /// Maximum number of concurrent connections
+    max_connections: usize,
+    /// Idle time after which a connection is closed
+    idle_timeout: Duration,
+    /// Largest record fragment used for replies
+    max_fragment: usize,
+    /// Largest request accepted, summed over its fragments
+    max_request_size: usize,
+    /// Time a call may take before it is answered with a retry status
+    op_timeout: Duration,
+    /// Address of the HTTP metrics endpoint, if enabled
+    metrics_addr: Option<String>,
+    /// Address of the HTTP health endpoint, if enabled
+    health_addr: Option<String>,
 }
 
 impl RpcServer {
+    /// Create a server exporting a single filesystem at "/"
     pub fn new(addr: String, registry: Registry, filesystem: Arc<dyn Filesystem>) -> Self {
+        Self::with_exports(addr, registry, Arc::new(ExportTable::single("/", filesystem)))
+    }
+
+    /// Create a server for a set of exports
+    pub fn with_exports(addr: String, registry: Registry, exports: Arc<dyn ExportResolver>) -> Self {
         Self {
-            addr,
-            registry,
-            filesystem,
+            addrs: vec![addr],
+            context: ServerContext::new(registry, exports),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_fragment: record::DEFAULT_MAX_FRAGMENT,
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            op_timeout: DEFAULT_OP_TIMEOUT,
+            metrics_addr: None,
+            health_addr: None,
+        }
+    }
+
+    /// Listen on several addresses (e.g. one per service port)
+    ///
+    /// Each entry is a bind spec such as "0.0.0.0:2049", "[::]:2049" or
+    /// "localhost:2049"; a spec resolving to several addresses gets a
+    /// listener for each. Calls are routed by program number, so any
+    /// listener can serve any program; duplicate addresses are bound once.
+    pub fn with_addresses(mut self, addrs: Vec<String>) -> Self {
+        let mut unique = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            if !unique.contains(&addr) {
+                unique.push(addr);
+            }
         }
+        self.addrs = unique;
+        self
+    }
+
+    /// Limit the number of concurrent connections
+    ///
+    /// Connections accepted beyond the limit are closed immediately.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Close connections that do not deliver a complete message within `idle_timeout`
+    ///
+    /// The timer restarts after every complete message.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
     }
 
+    /// Send replies larger than `max_fragment` bytes as several record fragments
+    pub fn with_max_fragment(mut self, max_fragment: usize) -> Self {
+        self.max_fragment = max_fragment;
+        self
+    }
+
+    /// Close connections whose request grows beyond `max_request_size` bytes
+    ///
+    /// The limit covers all fragments of a request and is checked against
+    /// each record mark before any of the fragment is read.
+    pub fn with_max_request_size(mut self, max_request_size: usize) -> Self {
+        self.max_request_size = max_request_size;
+        self
+    }
+
+    /// Answer calls still running after `op_timeout` with a retry status
+    ///
+    /// NFSv3 calls get NFS3ERR_JUKEBOX, so the client retries later, and
+    /// other programs SYSTEM_ERR. The backend call itself cannot be
+    /// cancelled and finishes in the background, but the connection serves
+    /// the calls behind it meanwhile. Zero waits for every call.
+    pub fn with_op_timeout(mut self, op_timeout: Duration) -> Self {
+        self.op_timeout = op_timeout;
+        self
+    }
+
+    /// Serve metrics in the Prometheus text format at `http://<addr>/metrics`
+    pub fn with_metrics_address(mut self, addr: String) -> Self {
+        self.metrics_addr = Some(addr);
+        self
+    }
+
+    /// Serve a JSON liveness/readiness probe at `http://<addr>/health`
+    pub fn with_health_address(mut self, addr: String) -> Self {
+        self.health_addr = Some(addr);
+        self
+    }
+
+    /// Metrics registry updated by this server
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.context.metrics)
+    }
+
+    /// Configure the duplicate request cache (size and TTL)
+    pub fn with_drc_config(mut self, config: DrcConfig) -> Self {
+        self.context.drc = DuplicateRequestCache::new(config);
+        self
+    }
+
+    /// Limit the rate of calls per client address and across all clients
+    ///
+    /// Calls beyond a limit are delayed until the bucket refills, or
+    /// dropped without a reply when that would take longer than
+    /// `config.max_delay`.
+    pub fn with_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.context.rate_limiter = RateLimiter::new(config);
+        self
+    }
+
+    /// Fail calls on an export fast while its backend keeps failing
+    ///
+    /// After `config.threshold` consecutive NFS3ERR_IO replies, calls on
+    /// the export are answered NFS3ERR_IO without reaching the backend for
+    /// `config.cooldown`, after which the export root is checked again.
+    pub fn with_circuit_breaker(mut self, config: BreakerConfig) -> Self {
+        self.context.breakers = CircuitBreaker::new(config);
+        self
+    }
+
+    /// Allow these clients to query counters through the admin program
+    pub fn with_admin_clients(mut self, clients: Vec<ClientSpec>) -> Self {
+        self.context.admin_clients = Arc::new(clients);
+        self
+    }
+
+    /// Run the server forever
     pub async fn run(&self) -> Result<()> {
-        let listener = TcpListener::bind(&self.addr).await?;
-        info!("RPC server listening on {}", self.addr);
+        self.run_until(std::future::pending()).await
+    }
 
-        loop {
-            let (socket, peer_addr) = listener.accept().await?;
-            info!("New connection from {}", peer_addr);
-
-            let registry = self.registry.clone();
-            let filesystem = self.filesystem.clone();
-            tokio::spawn(async move {
-                if let Err(e) = handle_connection(socket, registry, filesystem).await {
-                    error!("Connection error from {}: {}", peer_addr, e);
+    /// Run the server until `shutdown` resolves
+    ///
+    /// On shutdown the server stops accepting connections, each open
+    /// connection closes after finishing its current message, and this
+    /// function returns once all connections are done.
+    pub async fn run_until<F>(&self, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let mut bound: Vec<SocketAddr> = Vec::new();
+        let mut listeners = Vec::with_capacity(self.addrs.len());
+        for spec in &self.addrs {
+            let resolved = lookup_host(spec.as_str())
+                .await
+                .map_err(|e| anyhow!("Failed to resolve bind address {}: {}", spec, e))?;
+            for addr in resolved {
+                if bound.contains(&addr) {
+                    continue;
+                }
+                let listener =
+                    bind_listener(addr).map_err(|e| anyhow!("Failed to bind {}: {}", addr, e))?;
+                bound.push(addr);
+                listeners.push(listener);
+            }
+        }
+        let metrics_listener = match &self.metrics_addr {
+            Some(spec) => Some(
+                TcpListener::bind(spec.as_str())
+                    .await
+                    .map_err(|e| anyhow!("Failed to bind metrics endpoint {}: {}", spec, e))?,
+            ),
+            None => None,
+        };
+        let health_listener = match &self.health_addr {
+            Some(spec) => Some(
+                TcpListener::bind(spec.as_str())
+                    .await
+                    .map_err(|e| anyhow!("Failed to bind health endpoint {}: {}", spec, e))?,
+            ),
+            None => None,
+        };
+        self.serve_all_until(listeners, metrics_listener, health_listener, shutdown).await
+    }
+
+    /// Serve connections from an already bound listener until `shutdown` resolves
+    pub async fn serve_until<F>(&self, listener: TcpListener, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        self.serve_listeners_until(vec![listener], shutdown).await
+    }
+
+    /// Serve connections from several bound listeners until `shutdown` resolves
+    ///
+    /// The connection limit applies across all listeners.
+    pub async fn serve_listeners_until<F>(&self, listeners: Vec<TcpListener>, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        self.serve_all_until(listeners, None, None, shutdown).await
+    }
+
+    /// Serve RPC listeners, and the metrics and health endpoints if given, until `shutdown` resolves
+    async fn serve_all_until<F>(
+        &self,
+        listeners: Vec<TcpListener>,
+        metrics_listener: Option<TcpListener>,
+        health_listener: Option<TcpListener>,
+        shutdown: F,
+    ) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        // One accept loop per listener, feeding a single queue
+        let (accepted_tx, mut accepted_rx) = mpsc::channel(listeners.len().max(1));
+        let mut acceptors = JoinSet::new();
+        for listener in listeners {
+            info!("RPC server listening on {}", listener.local_addr()?);
+            let accepted_tx = accepted_tx.clone();
+            acceptors.spawn(async move {
+                loop {
+                    let accepted = listener.accept().await;
+                    let failed = accepted.is_err();
+                    if accepted_tx.send(accepted).await.is_err() || failed {
+                        break;
+                    }
                 }
             });
         }
+        drop(accepted_tx);
+
+        // The metrics and health endpoints stop together with the accept loops
+        if let Some(listener) = metrics_listener {
+            acceptors.spawn(metrics::serve(listener, self.metrics(), self.context.exports.clone()));
+        }
+        if let Some(listener) = health_listener {
+            acceptors.spawn(health::serve(
+                listener,
+                self.metrics(),
+                self.context.exports.clone(),
+                self.context.breakers.clone(),
+            ));
+        }
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mut connections = JoinSet::new();
+        let connection_slots = Arc::new(Semaphore::new(self.max_connections));
+        tokio::pin!(shutdown);
+
+        let result = loop {
+            tokio::select! {
+                _ = &mut shutdown => {
+                    info!("Shutdown requested, no longer accepting connections");
+                    break Ok(());
+                }
+                accepted = accepted_rx.recv() => {
+                    let (socket, peer_addr) = match accepted {
+                        Some(Ok(accepted)) => accepted,
+                        Some(Err(e)) => break Err(e.into()),
+                        None => break Ok(()),
+                    };
+
+                    // The permit is held for the lifetime of the connection task
+                    let Ok(permit) = connection_slots.clone().try_acquire_owned() else {
+                        warn!(
+                            "Connection limit ({}) reached, refusing connection from {}",
+                            self.max_connections, peer_addr
+                        );
+                        drop(socket);
+                        continue;
+                    };
+                    info!("New connection from {}", peer_addr);
+                    let active = self.context.metrics.connection_opened();
+
+                    let context = self.context.clone();
+                    let shutdown_rx = shutdown_rx.clone();
+                    let limits = ConnectionLimits {
+                        idle_timeout: self.idle_timeout,
+                        max_fragment: self.max_fragment,
+                        max_request_size: self.max_request_size,
+                        op_timeout: self.op_timeout,
+                    };
+                    connections.spawn(async move {
+                        let result = handle_connection(socket, peer_addr, context, shutdown_rx, limits).await;
+                        if let Err(e) = result {
+                            error!("Connection error from {}: {}", peer_addr, e);
+                        }
+                        drop(active);
+                        drop(permit);
+                    });
+                }
+                // Reap finished connection tasks
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            }
+        };
+
+        // Stop accepting, then tell open connections to close after their
+        // current message
+        acceptors.shutdown().await;
+        let _ = shutdown_tx.send(true);
+
+        let open = connections.len();
+        if open > 0 {
+            info!("Waiting for {} connection(s) to finish", open);
+        }
+        while connections.join_next().await.is_some() {}
+
+        // Nothing writes any more: make data written UNSTABLE and never
+        // committed durable before stopping
+        let filesystem = self.context.filesystem.clone();
+        match tokio::task::spawn_blocking(move || filesystem.flush()).await {
+            Ok(Ok(flushed)) => info!("Flushed {} file(s) with uncommitted writes", flushed),
+            Ok(Err(e)) => error!("Flushing uncommitted writes failed: {}", e),
+            Err(e) => error!("Flushing uncommitted writes panicked: {}", e),
+        }
+
+        info!("RPC server stopped");
+        result
     }
 }
 
+/// Backlog of pending connections per listener
+const LISTEN_BACKLOG: u32 = 1024;
+
+/// Bind a TCP listener for an IPv4 or IPv6 address
+///
+/// The IPv6 wildcard ("[::]") is bound dual-stack where the OS allows, so it
+/// also accepts IPv4 clients (as IPv4-mapped addresses).
+pub fn bind_listener(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        set_ipv6_only(&socket, false);
+    }
+    socket.bind(addr)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+/// Set IPV6_V6ONLY on a socket, logging (not failing) if the OS refuses
+fn set_ipv6_only(socket: &TcpSocket, only: bool) {
+    use std::os::fd::AsRawFd;
+
+    let value: libc::c_int = only.into();
+    // SAFETY: the fd is a valid open socket and `value` outlives the call
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_V6ONLY,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        debug!(
+            "Could not set IPV6_V6ONLY={}: {}",
+            only,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Per-connection limits, copied from the server configuration
+#[derive(Clone, Copy)]
+struct ConnectionLimits {
+    idle_timeout: Duration,
+    max_fragment: usize,
+    max_request_size: usize,
+    op_timeout: Duration,
+}
+
 /// Handle a single TCP connection
 async fn handle_connection(
     mut socket: TcpStream,
-    registry: Registry,
-    filesystem: Arc<dyn Filesystem>,
+    peer_addr: SocketAddr,
+    context: ServerContext,
+    mut shutdown: watch::Receiver<bool>,
+    limits: ConnectionLimits,
 ) -> Result<()> {
+    let ConnectionLimits { idle_timeout, max_fragment, max_request_size, op_timeout } = limits;
     let mut buffer = BytesMut::with_capacity(8192);
+    let mut deadline = Instant::now() + idle_timeout;
 
     loop {
         // Read record marking fragment header (4 bytes)
+        // Shutdown is only observed between messages, never mid-request
         let mut header = [0u8; 4];
-        if socket.read_exact(&mut header).await.is_err() {
-            debug!("Connection closed by peer");
-            break;
+        tokio::select! {
+            result = timeout_at(deadline, socket.read_exact(&mut header)) => {
+                match result {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(_)) => {
+                        debug!("Connection closed by peer");
+                        break;
+                    }
+                    Err(_) => {
+                        info!("Closing idle connection from {} after {:?}", peer_addr, idle_timeout);
+                        break;
+                    }
+                }
+            }
+            _ = shutdown.wait_for(|stop| *stop), if buffer.is_empty() => {
+                debug!("Closing connection from {} for shutdown", peer_addr);
+                break;
+            }
         }
 
         // Parse record marking header
         // Bit 31: last fragment (1 = last, 0 = more fragments)
         // Bits 0-30: fragment length
         let header_u32 = u32::from_be_bytes(header);
-        let is_last = (header_u32 & 0x80000000) != 0;
-        let fragment_len = (header_u32 & 0x7FFFFFFF) as usize;
+        let is_last = (header_u32 & record::LAST_FRAGMENT) != 0;
+        let fragment_len = (header_u32 & !record::LAST_FRAGMENT) as usize;
 
         debug!(
             "Record marking: last={}, length={}",
             is_last, fragment_len
         );
 
-        // Read fragment data
-        let mut fragment = vec![0u8; fragment_len];
-        socket.read_exact(&mut fragment).await?;
-        buffer.put_slice(&fragment);
+        // Enforce the request size limit before allocating anything for
+        // the fragment, so a forged length cannot exhaust memory
+        let request_len = buffer.len() + fragment_len;
+        if request_len > max_request_size {
+            error!(
+                "Request from {} exceeds {} bytes ({} bytes with this fragment), closing connection",
+                peer_addr, max_request_size, request_len
+            );
+            break;
+        }
+
+        // Read fragment data straight onto the end of the request; an empty
+        // fragment reads nothing and moves on to the next record mark
+        let start = buffer.len();
+        buffer.resize(request_len, 0);
+        match timeout_at(deadline, socket.read_exact(&mut buffer[start..])).await {
+            Ok(result) => {
+                result?;
+            }
+            Err(_) => {
+                info!("Closing idle connection from {} after {:?}", peer_addr, idle_timeout);
+                break;
+            }
+        }
 
         // If this is the last fragment, process the complete RPC message
         if is_last {
             debug!("Complete RPC message received ({} bytes)", buffer.len());
+            deadline = Instant::now() + idle_timeout;
 
-            let response = match handle_rpc_message(&buffer, &registry, filesystem.as_ref()) {
+            let request = buffer.split().freeze();
+            if !throttle(&request, peer_addr, &context).await {
+                continue;
+            }
+            let response = match handle_with_deadline(request.clone(), peer_addr, &context, op_timeout).await {
                 Ok(response) => response,
                 Err(e) => {
                     error!("Failed to handle RPC message: {}", e);
 
-                    // Try to parse XID from buffer to send proper error response
-                    if buffer.len() >= 4 {
-                        let xid = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+                    // Try to parse XID from the request to send proper error response
+                    if request.len() >= 4 {
+                        let xid = u32::from_be_bytes([request[0], request[1], request[2], request[3]]);
 
                         // Send PROG_UNAVAIL error response
                         match RpcMessage::create_prog_unavail_reply(xid) {
@@ -112,18 +588,15 @@ async fn handle_connection(
                 }
             };
 
-            // Send response with record marking
-            // IMPORTANT: Record mark and payload must be sent in a single write()
-            // to avoid TCP fragmentation causing client parsing issues
-            let response_len = response.len() as u32;
-            let record_header = response_len | 0x80000000; // Set last fragment bit
-
-            // Combine record mark + payload into single buffer
-            let mut full_response = Vec::with_capacity(4 + response.len());
-            full_response.extend_from_slice(&record_header.to_be_bytes());
-            full_response.extend_from_slice(&response);
+            // A retransmission of a call still in progress gets no reply
+            if response.is_empty() {
+                continue;
+            }
 
-            socket.write_all(&full_response).await?;
+            // Send response with record marking, split into fragments so
+            // large replies never need a record mark beyond 2^31; marks and
+            // fragments go out in one vectored write sequence before the flush
+            record::write_record(&mut socket, &response, max_fragment).await?;
             socket.flush().await?;
 
             debug!("Sent response ({} bytes)", response.len());
@@ -136,88 +609,266 @@ async fn handle_connection(
     Ok(())
 }
 
+/// Apply the client's rate limit to a call before it is handled
+///
+/// A throttled call waits here, on the connection task, so that it holds no
+/// blocking thread and its wait does not count against `op_timeout`.
+///
+/// # Returns
+/// false if the call is dropped: the client retransmits it, and it is
+/// neither logged nor counted as served
+async fn throttle(data: &[u8], peer_addr: SocketAddr, context: &ServerContext) -> bool {
+    let xid = data.get(..4).map_or(0, |xid| u32::from_be_bytes([xid[0], xid[1], xid[2], xid[3]]));
+    match context.rate_limiter.admit(peer_addr.ip()) {
+        Some(delay) if !delay.is_zero() => {
+            debug!("Rate limit: delaying xid={} from {} by {:?}", xid, peer_addr, delay);
+            tokio::time::sleep(delay).await;
+            true
+        }
+        Some(_) => true,
+        None => {
+            warn!("Rate limit exceeded, dropping call: xid={} client={}", xid, peer_addr);
+            false
+        }
+    }
+}
+
+/// Handle a complete RPC message on a blocking thread, within `op_timeout`
+///
+/// Backend calls are synchronous and may block for as long as the storage
+/// behind them takes, so they run off the connection task. A call missing
+/// the deadline is answered at once by `timeout_reply`.
+async fn handle_with_deadline(
+    data: Bytes,
+    peer_addr: SocketAddr,
+    context: &ServerContext,
+    op_timeout: Duration,
+) -> Result<BytesMut> {
+    let worker_data = data.clone();
+    let worker_context = context.clone();
+    let work = tokio::task::spawn_blocking(move || handle_rpc_message(&worker_data, peer_addr, &worker_context));
+    let joined = if op_timeout.is_zero() {
+        work.await
+    } else {
+        match tokio::time::timeout(op_timeout, work).await {
+            Ok(joined) => joined,
+            Err(_) => return timeout_reply(&data, peer_addr, op_timeout),
+        }
+    };
+    joined.map_err(|e| anyhow!("RPC handler task failed: {}", e))?
+}
+
+/// Reply to a call still running after `op_timeout`
+///
+/// NFSv3 calls get NFS3ERR_JUKEBOX, other programs SYSTEM_ERR.
+fn timeout_reply(data: &[u8], peer_addr: SocketAddr, op_timeout: Duration) -> Result<BytesMut> {
+    let (call, _) = RpcMessage::parse_call_with_offset(data)?;
+    warn!(
+        "Call timed out after {:?}: prog={} vers={} proc={} xid={} client={}",
+        op_timeout, call.prog, call.vers, call.proc_, call.xid, peer_addr
+    );
+    if call.prog == 100003 && call.vers == 3 {
+        crate::nfs::error::error_reply(call.xid, call.proc_, nfsstat3::NFS3ERR_JUKEBOX)
+    } else {
+        RpcMessage::create_system_err_reply(call.xid)
+    }
+}
+
 /// Handle a complete RPC message
 fn handle_rpc_message(
     data: &[u8],
-    registry: &Registry,
-    filesystem: &dyn Filesystem,
+    peer_addr: SocketAddr,
+    context: &ServerContext,
 ) -> Result<BytesMut> {
-    // Debug: dump complete RPC message
+    // Deserialize RPC call header; procedure arguments follow the
+    // variable-length credential and verifier
+    let (call, args_offset) = RpcMessage::parse_call_with_offset(data)?;
+    wire_log::dump_call(&call, data, args_offset);
+
     debug!(
-        "Complete RPC message ({} bytes): {:02x?}",
-        data.len(),
-        &data[..data.len().min(100)]
+        "RPC call: xid={}, prog={}, vers={}, proc={}, args at offset {}",
+        call.xid, call.prog, call.vers, call.proc_, args_offset
     );
 
-    // Deserialize RPC call header
-    let call = RpcMessage::deserialize_call(data)?;
+    let args_data = &data[args_offset..];
 
-    debug!(
-        "RPC call: xid={}, prog={}, vers={}, proc={}",
-        call.xid, call.prog, call.vers, call.proc_
+    // Every call leaves one access log record and metrics sample with its
+    // outcome and latency
+    let started = std::time::Instant::now();
+    // A panicking handler answers its own call with a server fault instead
+    // of taking down the connection and the calls queued behind it
+    let result = match panic::catch_unwind(AssertUnwindSafe(|| route_call(&call, args_data, peer_addr, context))) {
+        Ok(result) => result,
+        Err(payload) => {
+            error!(
+                "Handler panicked: prog={} vers={} proc={} xid={} client={}: {}",
+                call.prog,
+                call.vers,
+                call.proc_,
+                call.xid,
+                peer_addr,
+                panic_message(payload.as_ref())
+            );
+            fault_reply(&call)
+        }
+    };
+    let elapsed = started.elapsed();
+    let status = access_log::reply_status(&call, &result);
+    access_log::record(&call, peer_addr, &status, elapsed);
+    context.metrics.record_request(
+        access_log::program_name(call.prog),
+        access_log::procedure_name(call.prog, call.proc_),
+        &status,
+        elapsed,
     );
+    result
+}
 
-    // Calculate where procedure arguments start (after RPC call header)
-    // RPC call header: xid(4) + mtype(4) + rpcvers(4) + prog(4) + vers(4) + proc(4) = 24 bytes
-    // Then: opaque_auth cred + opaque_auth verf (variable length)
-    // opaque_auth = flavor(4) + length(4) + body(length bytes, padded to 4-byte boundary)
-
-    let mut offset = 24; // After fixed RPC header fields
-
-    // Parse credential (opaque_auth)
-    if data.len() < offset + 8 {
-        return Err(anyhow!("RPC message too short for credential header"));
-    }
-    let cred_length = u32::from_be_bytes([
-        data[offset + 4],
-        data[offset + 5],
-        data[offset + 6],
-        data[offset + 7],
-    ]) as usize;
-    let cred_padded = (cred_length + 3) & !3; // Round up to multiple of 4
-    offset += 8 + cred_padded; // flavor(4) + length(4) + body(padded)
-
-    debug!("Credential length: {} bytes (padded: {}), offset now: {}", cred_length, cred_padded, offset);
-
-    // Parse verifier (opaque_auth)
-    if data.len() < offset + 8 {
-        return Err(anyhow!("RPC message too short for verifier header"));
-    }
-    let verf_length = u32::from_be_bytes([
-        data[offset + 4],
-        data[offset + 5],
-        data[offset + 6],
-        data[offset + 7],
-    ]) as usize;
-    let verf_padded = (verf_length + 3) & !3; // Round up to multiple of 4
-    offset += 8 + verf_padded; // flavor(4) + length(4) + body(padded)
-
-    debug!("Verifier length: {} bytes (padded: {}), offset now: {}", verf_length, verf_padded, offset);
-
-    // Now offset points to the procedure arguments
-    let args_offset = offset;
-    let args_data = if data.len() > args_offset {
-        &data[args_offset..]
+/// Reply to a call whose handler panicked
+///
+/// NFSv3 calls get NFS3ERR_SERVERFAULT, other programs SYSTEM_ERR.
+fn fault_reply(call: &rpc_call_msg) -> Result<BytesMut> {
+    if call.prog == 100003 && call.vers == 3 {
+        crate::nfs::error::error_reply(call.xid, call.proc_, nfsstat3::NFS3ERR_SERVERFAULT)
     } else {
-        &[]
-    };
+        RpcMessage::create_system_err_reply(call.xid)
+    }
+}
+
+/// Text of a panic payload, when it carries one
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "non-string panic payload"
+    }
+}
+
+/// Route a parsed call to the handler for its program
+///
+/// # Arguments
+/// * `call` - Parsed RPC call header
+/// * `args_data` - Procedure arguments
+/// * `peer_addr` - Address of the calling client
+/// * `context` - Shared server state
+///
+/// # Returns
+/// Serialized RPC reply message
+fn route_call(
+    call: &rpc_call_msg,
+    args_data: &[u8],
+    peer_addr: SocketAddr,
+    context: &ServerContext,
+) -> Result<BytesMut> {
+    let filesystem = context.filesystem.as_ref();
+    let drc = &context.drc;
 
     // Route to appropriate handler based on program number
     match call.prog {
         100000 => {
             // Portmapper protocol (program 100000)
             debug!("Routing to PORTMAP protocol handler");
-            crate::portmap::handle_portmap_call(&call, args_data, registry)
+            // CALLIT is answered by routing the inner call through this same path
+            let local = |inner: &rpc_call_msg, inner_args: &[u8]| route_call(inner, inner_args, peer_addr, context);
+            crate::portmap::handle_portmap_call(call, args_data, &context.registry, &local)
         }
         100005 => {
             // MOUNT protocol (program 100005)
             debug!("Routing to MOUNT protocol handler");
-            crate::mount::handle_mount_call(&call, args_data, filesystem)
+            crate::mount::handle_mount_call(
+                call,
+                args_data,
+                context.exports.as_ref(),
+                &context.mounts,
+                &context.drc,
+                peer_addr.ip(),
+            )
         }
         100003 => {
             // NFS protocol (program 100003)
             debug!("Routing to NFS protocol handler");
-            crate::nfs::dispatch(&call, args_data, filesystem)
+            // Map the caller identity through the target export's squash policy,
+            // and refuse hosts outside the export's client list even if they
+            // obtained a handle some other way
+            let mut credentials = Credentials::from_call(call)?;
+            let mut options = ExportOptions::default();
+            let guarded_export = args_export_id(args_data).filter(|_| call.vers == 3 && call.proc_ != 0);
+            if let Some(export_id) = args_export_id(args_data) {
+                options = context.exports.options(export_id);
+                if call.vers == 3 && !options.allows(peer_addr.ip()) {
+                    warn!(
+                        "NFS proc {} on export {} denied for client {}",
+                        call.proc_, export_id, peer_addr
+                    );
+                    return crate::nfs::error::error_reply(call.xid, call.proc_, nfsstat3::NFS3ERR_ACCES);
+                }
+                // Only the flavors MNT advertised for the export are accepted,
+                // so a weaker credential cannot reach an AUTH_SYS-only export
+                if call.vers == 3 && !options.auth_flavors.contains(&(call.cred.flavor as i32)) {
+                    warn!(
+                        "NFS proc {} on export {} refused: {:?} credential not allowed",
+                        call.proc_, export_id, call.cred.flavor
+                    );
+                    return RpcMessage::create_auth_error_reply(call.xid, auth_stat::AUTH_TOOWEAK);
+                }
+                if call.vers == 3 && options.read_only && crate::nfs::is_mutating(call.proc_) {
+                    debug!("NFS proc {} refused on read-only export {}", call.proc_, export_id);
+                    return crate::nfs::error::error_reply(call.xid, call.proc_, nfsstat3::NFS3ERR_ROFS);
+                }
+                credentials = options.squash_credentials(credentials);
+            }
+
+            // While the export's backend is down, calls fail without
+            // waiting on it
+            if let Some(export_id) = guarded_export
+                && !context
+                    .breakers
+                    .admit(export_id, || health::export_available(context.exports.as_ref(), export_id))
+            {
+                debug!("NFS proc {} failed fast: export {} is down", call.proc_, export_id);
+                return crate::nfs::error::error_reply(call.xid, call.proc_, nfsstat3::NFS3ERR_IO);
+            }
+
+            // Non-idempotent procedures go through the duplicate request cache
+            // so a retransmission gets the original reply instead of being
+            // executed a second time, and is dropped while the original runs
+            let reply = if call.vers == 3 && drc::is_non_idempotent(call.proc_) {
+                let pending = match drc.begin(peer_addr.ip(), call.xid, call.proc_) {
+                    DrcLookup::Execute(pending) => pending,
+                    DrcLookup::InProgress => {
+                        debug!("Dropping retransmission of in-progress xid={} proc={}", call.xid, call.proc_);
+                        return Ok(BytesMut::new());
+                    }
+                    DrcLookup::Replay(reply) => {
+                        debug!("Replaying cached reply for xid={} proc={}", call.xid, call.proc_);
+                        return Ok(reply);
+                    }
+                };
+
+                let reply = crate::nfs::dispatch(call, args_data, filesystem, &credentials, &options)?;
+                pending.complete(reply.clone());
+                reply
+            } else {
+                crate::nfs::dispatch(call, args_data, filesystem, &credentials, &options)?
+            };
+
+            if let Some(export_id) = guarded_export {
+                context.breakers.record(export_id, breaker::is_backend_failure(&reply));
+            }
+            Ok(reply)
+        }
+        ADMIN_PROGRAM => {
+            debug!("Routing to admin program handler");
+            admin::handle_admin_call(
+                call,
+                args_data,
+                &context.metrics,
+                filesystem,
+                &context.admin_clients,
+                peer_addr.ip(),
+            )
         }
         _ => {
             warn!("Unknown program number: {}", call.prog);
@@ -225,3 +876,1228 @@ fn handle_rpc_message(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsal::LocalFilesystem;
+    use crate::protocol::v3::nfs::{fhandle3, filename3, nfsstat3};
+    use crate::protocol::v3::rpc::{auth_flavor, auth_sys_params, msg_type, opaque_auth};
+    use std::net::{IpAddr, Ipv4Addr};
+    use tempfile::TempDir;
+    use xdr_codec::Pack;
+
+    /// Build a complete RPC call message (header + procedure arguments)
+    fn build_call(xid: u32, prog: u32, vers: u32, proc_: u32, args: &[u8]) -> Vec<u8> {
+        let cred = opaque_auth {
+            flavor: auth_flavor::AUTH_NONE,
+            body: vec![],
+        };
+        build_call_with_cred(xid, prog, vers, proc_, cred, args)
+    }
+
+    /// Build an RPC call message carrying an AUTH_SYS credential
+    fn build_auth_sys_call(xid: u32, proc_: u32, uid: u32, gid: u32, args: &[u8]) -> Vec<u8> {
+        let params = auth_sys_params {
+            stamp: 0,
+            machinename: "client".to_string(),
+            uid,
+            gid,
+            gids: vec![],
+        };
+        let mut body = Vec::new();
+        params.pack(&mut body).unwrap();
+        let cred = opaque_auth {
+            flavor: auth_flavor::AUTH_SYS,
+            body,
+        };
+        build_call_with_cred(xid, 100003, 3, proc_, cred, args)
+    }
+
+    fn build_call_with_cred(
+        xid: u32,
+        prog: u32,
+        vers: u32,
+        proc_: u32,
+        cred: opaque_auth,
+        args: &[u8],
+    ) -> Vec<u8> {
+        let call = rpc_call_msg {
+            xid,
+            mtype: msg_type::CALL,
+            rpcvers: 2,
+            prog,
+            vers,
+            proc_,
+            cred,
+            verf: opaque_auth {
+                flavor: auth_flavor::AUTH_NONE,
+                body: vec![],
+            },
+        };
+        let mut buf = Vec::new();
+        call.pack(&mut buf).unwrap();
+        buf.extend_from_slice(args);
+        buf
+    }
+
+    /// Extract the nfsstat3 that follows the 24-byte accepted reply header
+    fn reply_status(reply: &[u8]) -> i32 {
+        i32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]])
+    }
+
+    /// Send an NFS NULL call over a connection and return the reply
+    async fn null_roundtrip(client: &mut TcpStream, xid: u32) -> std::io::Result<Vec<u8>> {
+        call_roundtrip(client, &build_call(xid, 100003, 3, 0, &[])).await
+    }
+
+    /// Send a call over a connection and return the reply
+    async fn call_roundtrip(client: &mut TcpStream, call: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut request = ((call.len() as u32) | 0x80000000).to_be_bytes().to_vec();
+        request.extend_from_slice(call);
+        client.write_all(&request).await?;
+
+        let mut header = [0u8; 4];
+        client.read_exact(&mut header).await?;
+        let mut reply = vec![0u8; (u32::from_be_bytes(header) & 0x7FFFFFFF) as usize];
+        client.read_exact(&mut reply).await?;
+        Ok(reply)
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit() {
+        use crate::fsal::MemoryFilesystem;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = RpcServer::new(addr.to_string(), Registry::new(), Arc::new(MemoryFilesystem::new()))
+            .with_max_connections(2);
+        let server_task = tokio::spawn(async move {
+            server.serve_until(listener, std::future::pending()).await
+        });
+
+        // Two connections are admitted
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        assert!(null_roundtrip(&mut first, 1).await.is_ok());
+        assert!(null_roundtrip(&mut second, 2).await.is_ok());
+
+        // The third is refused
+        let mut third = TcpStream::connect(addr).await.unwrap();
+        assert!(null_roundtrip(&mut third, 3).await.is_err(), "Excess connection should be closed");
+
+        // Closing a connection frees its slot
+        drop(first);
+        let mut admitted = false;
+        for _ in 0..50 {
+            let mut retry = TcpStream::connect(addr).await.unwrap();
+            if null_roundtrip(&mut retry, 4).await.is_ok() {
+                admitted = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(admitted, "A new connection should be admitted after one closes");
+
+        server_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_idle_connection_closed() {
+        use crate::fsal::MemoryFilesystem;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = RpcServer::new(addr.to_string(), Registry::new(), Arc::new(MemoryFilesystem::new()))
+            .with_idle_timeout(Duration::from_millis(200));
+        let server_task = tokio::spawn(async move {
+            server.serve_until(listener, std::future::pending()).await
+        });
+
+        // An active connection is kept open across messages
+        let mut active = TcpStream::connect(addr).await.unwrap();
+        for xid in 1..=3 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(null_roundtrip(&mut active, xid).await.is_ok(), "Timer resets after each message");
+        }
+
+        // A partial record mark and then silence
+        let mut idle = TcpStream::connect(addr).await.unwrap();
+        idle.write_all(&[0x80, 0x00]).await.unwrap();
+
+        let mut byte = [0u8; 1];
+        let closed = tokio::time::timeout(Duration::from_secs(5), idle.read(&mut byte))
+            .await
+            .expect("Server should close the idle connection");
+        assert!(matches!(closed, Ok(0) | Err(_)), "Expected EOF, got {:?}", closed);
+
+        server_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_run_until_shutdown() {
+        use crate::fsal::MemoryFilesystem;
+        use tokio::sync::oneshot;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = RpcServer::new(addr.to_string(), Registry::new(), Arc::new(MemoryFilesystem::new()));
+
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let server_task = tokio::spawn(async move {
+            server
+                .serve_until(listener, async {
+                    let _ = stop_rx.await;
+                })
+                .await
+        });
+
+        // Send one NFS NULL call and read the reply
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let reply = null_roundtrip(&mut client, 7).await.unwrap();
+        assert_eq!(&reply[0..4], &7u32.to_be_bytes(), "Reply should carry the call's xid");
+
+        // Shut down while the client connection is still open
+        stop_tx.send(()).unwrap();
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), server_task)
+            .await
+            .expect("run_until should resolve after shutdown")
+            .unwrap();
+        assert!(result.is_ok());
+
+        // The server closed the idle connection
+        let mut buf = [0u8; 1];
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_flushes_uncommitted_writes() {
+        use crate::fsal::StableHow;
+        use crate::protocol::v3::nfs::{stable_how, WRITE3args};
+        use tokio::sync::oneshot;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = RpcServer::new(addr.to_string(), Registry::new(), fs.clone());
+        let router = server.context.filesystem.clone();
+        let file = router.create(&router.root_handle(), "data", 0o644).unwrap();
+        let other = router.create(&router.root_handle(), "committed", 0o644).unwrap();
+        router.write(&other, 0, b"synced", StableHow::FileSync).unwrap();
+
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let server_task = tokio::spawn(async move {
+            server.serve_until(listener, async {
+                let _ = stop_rx.await;
+            }).await
+        });
+
+        // An UNSTABLE WRITE that the client never commits
+        let mut args = Vec::new();
+        WRITE3args {
+            file: fhandle3(file),
+            offset: 0,
+            count: 7,
+            stable: stable_how::UNSTABLE,
+            data: b"pending".to_vec(),
+        }
+        .pack(&mut args)
+        .unwrap();
+        let call = build_call(1, 100003, 3, 7, &args);
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut request = ((call.len() as u32) | 0x80000000).to_be_bytes().to_vec();
+        request.extend_from_slice(&call);
+        client.write_all(&request).await.unwrap();
+        let mut header = [0u8; 4];
+        client.read_exact(&mut header).await.unwrap();
+        let mut reply = vec![0u8; (u32::from_be_bytes(header) & 0x7FFFFFFF) as usize];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32);
+        let syncs = fs.sync_count();
+
+        // Shutdown syncs the written file, and only that one, before returning
+        stop_tx.send(()).unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), server_task).await.unwrap().unwrap();
+        assert!(result.is_ok());
+        assert_eq!(fs.sync_count(), syncs + 1);
+        assert_eq!(std::fs::read(temp_dir.path().join("data")).unwrap(), b"pending");
+        assert_eq!(fs.flush().unwrap(), 0, "Nothing is left to flush");
+    }
+
+    #[tokio::test]
+    async fn test_reply_split_into_fragments() {
+        use crate::fsal::MemoryFilesystem;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = RpcServer::new(addr.to_string(), Registry::new(), Arc::new(MemoryFilesystem::new()))
+            .with_max_fragment(10);
+        let server_task = tokio::spawn(async move { server.serve_until(listener, std::future::pending()).await });
+
+        let call = build_call(9, 100003, 3, 0, &[]);
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&record::record_mark(call.len(), true)).await.unwrap();
+        client.write_all(&call).await.unwrap();
+
+        // The 24-byte NULL reply arrives as 10 + 10 + 4 bytes, last one flagged
+        let mut reply = Vec::new();
+        let mut lengths = Vec::new();
+        loop {
+            let mut header = [0u8; 4];
+            client.read_exact(&mut header).await.unwrap();
+            let mark = u32::from_be_bytes(header);
+            let mut fragment = vec![0u8; (mark & !record::LAST_FRAGMENT) as usize];
+            client.read_exact(&mut fragment).await.unwrap();
+            lengths.push(fragment.len());
+            reply.extend_from_slice(&fragment);
+            if mark & record::LAST_FRAGMENT != 0 {
+                break;
+            }
+        }
+        assert_eq!(lengths, vec![10, 10, 4]);
+        assert_eq!(&reply[..], &RpcMessage::create_success_reply_with_data(9, BytesMut::new()).unwrap()[..]);
+
+        server_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_is_serverfault() {
+        use crate::export::encode_handle;
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let faulty = Arc::new(FaultyFilesystem::new(Box::new(MemoryFilesystem::new())));
+        let root = encode_handle(1, &faulty.root_handle());
+        faulty.panic("getattr");
+        let server = RpcServer::new(addr.to_string(), Registry::new(), faulty.clone());
+        let server_task = tokio::spawn(async move { server.serve_until(listener, std::future::pending()).await });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut args = Vec::new();
+        fhandle3(root).pack(&mut args).unwrap();
+        let getattr = build_call(7, 100003, 3, 1, &args);
+        async fn status_of(client: &mut TcpStream, call: &[u8]) -> (u32, i32) {
+            client.write_all(&record::record_mark(call.len(), true)).await.unwrap();
+            client.write_all(call).await.unwrap();
+            let mut header = [0u8; 4];
+            client.read_exact(&mut header).await.unwrap();
+            let mut reply = vec![0u8; (u32::from_be_bytes(header) & !record::LAST_FRAGMENT) as usize];
+            client.read_exact(&mut reply).await.unwrap();
+            (u32::from_be_bytes([reply[0], reply[1], reply[2], reply[3]]), reply_status(&reply))
+        }
+
+        // The panic is answered on the connection, which keeps serving
+        assert_eq!(status_of(&mut client, &getattr).await, (7, nfsstat3::NFS3ERR_SERVERFAULT as i32));
+        assert_eq!(status_of(&mut client, &getattr).await, (7, nfsstat3::NFS3ERR_SERVERFAULT as i32));
+        faulty.heal("getattr");
+        assert_eq!(status_of(&mut client, &getattr).await, (7, nfsstat3::NFS3_OK as i32));
+
+        server_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_hung_backend_call_is_jukebox() {
+        use crate::export::encode_handle;
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let faulty = Arc::new(FaultyFilesystem::new(Box::new(MemoryFilesystem::new())));
+        let root = encode_handle(1, &faulty.root_handle());
+        faulty.stall("getattr", Duration::from_secs(2));
+        let server = RpcServer::new(addr.to_string(), Registry::new(), faulty.clone())
+            .with_op_timeout(Duration::from_millis(100));
+        let server_task = tokio::spawn(async move { server.serve_until(listener, std::future::pending()).await });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut args = Vec::new();
+        fhandle3(root).pack(&mut args).unwrap();
+        let getattr = build_call(11, 100003, 3, 1, &args);
+        client.write_all(&record::record_mark(getattr.len(), true)).await.unwrap();
+        client.write_all(&getattr).await.unwrap();
+
+        // Answered at the deadline, long before the backend call returns
+        let started = std::time::Instant::now();
+        let mut header = [0u8; 4];
+        client.read_exact(&mut header).await.unwrap();
+        let mut reply = vec![0u8; (u32::from_be_bytes(header) & !record::LAST_FRAGMENT) as usize];
+        client.read_exact(&mut reply).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+        assert_eq!(u32::from_be_bytes([reply[0], reply[1], reply[2], reply[3]]), 11);
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3ERR_JUKEBOX as i32);
+
+        // The connection keeps serving calls behind the hung one
+        let reply = null_roundtrip(&mut client, 12).await.unwrap();
+        assert_eq!(&reply[..4], &12u32.to_be_bytes());
+
+        server_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_burst_past_client_rate_limit_is_throttled() {
+        use crate::fsal::MemoryFilesystem;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = RpcServer::new(addr.to_string(), Registry::new(), Arc::new(MemoryFilesystem::new()))
+            .with_rate_limit(RateLimitConfig {
+                per_client: 5,
+                global: 0,
+                max_delay: Duration::ZERO,
+            });
+        let server_task = tokio::spawn(async move { server.serve_until(listener, std::future::pending()).await });
+
+        // A second's worth of calls is answered at once
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        for xid in 1..=5 {
+            let reply = null_roundtrip(&mut client, xid).await.unwrap();
+            assert_eq!(&reply[..4], &xid.to_be_bytes());
+        }
+
+        // The next call in the burst is dropped without a reply
+        let dropped = tokio::time::timeout(Duration::from_millis(150), null_roundtrip(&mut client, 6)).await;
+        assert!(dropped.is_err(), "Call over the limit should get no reply");
+
+        // Once the bucket has refilled the connection is served again
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let reply = null_roundtrip(&mut client, 7).await.unwrap();
+        assert_eq!(&reply[..4], &7u32.to_be_bytes());
+
+        server_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_delay_does_not_count_against_op_timeout() {
+        use crate::fsal::MemoryFilesystem;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = RpcServer::new(addr.to_string(), Registry::new(), Arc::new(MemoryFilesystem::new()))
+            .with_op_timeout(Duration::from_millis(100))
+            .with_rate_limit(RateLimitConfig {
+                per_client: 5,
+                global: 0,
+                max_delay: Duration::from_secs(1),
+            });
+        let mut args = Vec::new();
+        fhandle3(server.context.filesystem.root_handle()).pack(&mut args).unwrap();
+        let server_task = tokio::spawn(async move { server.serve_until(listener, std::future::pending()).await });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        for xid in 1..=5 {
+            null_roundtrip(&mut client, xid).await.unwrap();
+        }
+
+        // Past the burst a GETATTR waits about 200ms for its turn, longer
+        // than op_timeout, yet runs and is answered rather than JUKEBOX
+        let started = std::time::Instant::now();
+        let reply = call_roundtrip(&mut client, &build_call(6, 100003, 3, 1, &args)).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(150), "{:?}", started.elapsed());
+        assert_eq!(&reply[..4], &6u32.to_be_bytes());
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32);
+
+        server_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_closes_connection() {
+        use crate::fsal::MemoryFilesystem;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = RpcServer::new(addr.to_string(), Registry::new(), Arc::new(MemoryFilesystem::new()))
+            .with_max_request_size(1024);
+        let server_task = tokio::spawn(async move { server.serve_until(listener, std::future::pending()).await });
+
+        // A single mark claiming a 2 GiB fragment is refused before any read
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&[0xFF, 0xFF, 0xFF, 0xFF]).await.unwrap();
+        let mut buf = [0u8; 1];
+        let closed = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buf)).await;
+        assert!(matches!(closed, Ok(Ok(0)) | Ok(Err(_))), "Connection should be closed");
+
+        // The limit covers the sum of all fragments of a request
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&record::record_mark(800, false)).await.unwrap();
+        client.write_all(&[0u8; 800]).await.unwrap();
+        client.write_all(&record::record_mark(800, true)).await.unwrap();
+        let closed = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buf)).await;
+        assert!(matches!(closed, Ok(Ok(0)) | Ok(Err(_))), "Connection should be closed");
+
+        // Empty non-last fragments are skipped and the request still completes
+        let call = build_call(11, 100003, 3, 0, &[]);
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&record::record_mark(0, false)).await.unwrap();
+        client.write_all(&record::record_mark(0, false)).await.unwrap();
+        client.write_all(&record::record_mark(call.len(), true)).await.unwrap();
+        client.write_all(&call).await.unwrap();
+        let mut header = [0u8; 4];
+        client.read_exact(&mut header).await.unwrap();
+        let mut reply = vec![0u8; (u32::from_be_bytes(header) & !record::LAST_FRAGMENT) as usize];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply[0..4], &11u32.to_be_bytes());
+
+        server_task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_separate_service_listeners() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::portmap::mapping;
+        use tokio::sync::oneshot;
+
+        const IPPROTO_TCP: u32 = 6;
+
+        let portmap_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let nfs_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let portmap_addr = portmap_listener.local_addr().unwrap();
+        let nfs_port = nfs_listener.local_addr().unwrap().port() as u32;
+
+        let registry = Registry::new();
+        for prog in [100005, 100003] {
+            registry.set(&mapping { prog, vers: 3, prot: IPPROTO_TCP, port: nfs_port });
+        }
+        let server = RpcServer::new(portmap_addr.to_string(), registry, Arc::new(MemoryFilesystem::new()));
+
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let server_task = tokio::spawn(async move {
+            server
+                .serve_listeners_until(vec![portmap_listener, nfs_listener], async {
+                    let _ = stop_rx.await;
+                })
+                .await
+        });
+
+        // Ask the portmapper listener where MOUNT lives, as a client would
+        let mut args = Vec::new();
+        mapping { prog: 100005, vers: 3, prot: IPPROTO_TCP, port: 0 }
+            .pack(&mut args)
+            .unwrap();
+        let call = build_call(1, 100000, 2, 3, &args);
+        let mut request = ((call.len() as u32) | 0x80000000).to_be_bytes().to_vec();
+        request.extend_from_slice(&call);
+        let mut client = TcpStream::connect(portmap_addr).await.unwrap();
+        client.write_all(&request).await.unwrap();
+        let mut header = [0u8; 4];
+        client.read_exact(&mut header).await.unwrap();
+        let mut reply = vec![0u8; (u32::from_be_bytes(header) & 0x7FFFFFFF) as usize];
+        client.read_exact(&mut reply).await.unwrap();
+        let port = u32::from_be_bytes([reply[24], reply[25], reply[26], reply[27]]);
+        assert_eq!(port, nfs_port);
+
+        // The returned port is served by the second listener
+        let mut client = TcpStream::connect(("127.0.0.1", port as u16)).await.unwrap();
+        let reply = null_roundtrip(&mut client, 9).await.unwrap();
+        assert_eq!(&reply[0..4], &9u32.to_be_bytes());
+
+        stop_tx.send(()).unwrap();
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), server_task)
+            .await
+            .expect("server should stop after shutdown")
+            .unwrap();
+        assert!(result.is_ok());
+    }
+
+    /// Serve on an address, run a NULL round-trip per client address, then stop
+    async fn null_roundtrip_over(bind: &str, connect_ips: &[IpAddr]) {
+        use crate::fsal::MemoryFilesystem;
+        use tokio::sync::oneshot;
+
+        let server = RpcServer::new(bind.to_string(), Registry::new(), Arc::new(MemoryFilesystem::new()));
+        let listener = bind_listener(bind.parse().unwrap()).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let server_task = tokio::spawn(async move {
+            server
+                .serve_until(listener, async {
+                    let _ = stop_rx.await;
+                })
+                .await
+        });
+
+        for (xid, ip) in connect_ips.iter().enumerate() {
+            let xid = xid as u32 + 1;
+            let mut client = TcpStream::connect(SocketAddr::new(*ip, port)).await.unwrap();
+            let reply = null_roundtrip(&mut client, xid).await.unwrap();
+            assert_eq!(&reply[0..4], &xid.to_be_bytes(), "NULL over {}", ip);
+        }
+
+        stop_tx.send(()).unwrap();
+        server_task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_loopback_null() {
+        null_roundtrip_over("[::1]:0", &[IpAddr::V6(std::net::Ipv6Addr::LOCALHOST)]).await;
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack_wildcard() {
+        null_roundtrip_over(
+            "[::]:0",
+            &[IpAddr::V6(std::net::Ipv6Addr::LOCALHOST), IpAddr::V4(Ipv4Addr::LOCALHOST)],
+        )
+        .await;
+    }
+
+    #[test]
+    fn test_retransmitted_remove_replays_cached_reply() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("victim.txt"), b"data").unwrap();
+        let fs = Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap());
+        let context = ServerContext::new(Registry::new(), Arc::new(ExportTable::single("/", fs)));
+
+        let mut args = Vec::new();
+        fhandle3(context.filesystem.root_handle()).pack(&mut args).unwrap();
+        filename3("victim.txt".to_string()).pack(&mut args).unwrap();
+        let call = build_call(42, 100003, 3, 12, &args);
+
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        let first = handle_rpc_message(&call, peer, &context).unwrap();
+        assert_eq!(reply_status(&first), nfsstat3::NFS3_OK as i32);
+        assert!(!temp_dir.path().join("victim.txt").exists());
+
+        // Retransmission (same client, same xid) must get the original reply
+        let second = handle_rpc_message(&call, peer, &context).unwrap();
+        assert_eq!(first, second, "Retransmitted REMOVE should replay the cached reply");
+
+        // A new request with a different xid is executed and fails
+        let call = build_call(43, 100003, 3, 12, &args);
+        let third = handle_rpc_message(&call, peer, &context).unwrap();
+        assert_eq!(reply_status(&third), nfsstat3::NFS3ERR_NOENT as i32);
+    }
+
+    #[test]
+    fn test_retransmission_during_remove_is_not_executed() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+
+        let faulty = Arc::new(FaultyFilesystem::new(Box::new(MemoryFilesystem::new())));
+        let root = faulty.root_handle();
+        faulty.create(&root, "victim.txt", 0o644).unwrap();
+        let stall = Duration::from_millis(300);
+        faulty.stall("remove", stall);
+        let context = ServerContext::new(Registry::new(), Arc::new(ExportTable::single("/", faulty.clone())));
+
+        let mut args = Vec::new();
+        fhandle3(context.filesystem.root_handle()).pack(&mut args).unwrap();
+        filename3("victim.txt".to_string()).pack(&mut args).unwrap();
+        let call = build_call(42, 100003, 3, 12, &args);
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        let original = std::thread::spawn({
+            let (call, context) = (call.clone(), context.clone());
+            move || handle_rpc_message(&call, peer, &context).unwrap()
+        });
+        std::thread::sleep(Duration::from_millis(50));
+
+        // The retransmission arrives while the original is stalled in the
+        // backend: it is dropped at once rather than run a second time
+        let started = std::time::Instant::now();
+        let duplicate = handle_rpc_message(&call, peer, &context).unwrap();
+        assert!(duplicate.is_empty(), "Retransmission of an in-progress REMOVE gets no reply");
+        assert!(started.elapsed() < stall, "{:?}", started.elapsed());
+
+        let first = original.join().unwrap();
+        assert_eq!(reply_status(&first), nfsstat3::NFS3_OK as i32);
+        assert!(faulty.lookup(&root, "victim.txt").is_err());
+
+        // Once the original finished, a retransmission replays its reply
+        faulty.heal("remove");
+        let again = handle_rpc_message(&call, peer, &context).unwrap();
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn test_failing_backend_fails_fast_during_cooldown() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::fsal::faulty::FaultyFilesystem;
+
+        let faulty = Arc::new(FaultyFilesystem::new(Box::new(MemoryFilesystem::new())));
+        let mut context = ServerContext::new(Registry::new(), Arc::new(ExportTable::single("/", faulty.clone())));
+        let cooldown = Duration::from_millis(300);
+        context.breakers = CircuitBreaker::new(BreakerConfig { threshold: 3, cooldown });
+
+        let mut args = Vec::new();
+        fhandle3(context.filesystem.root_handle()).pack(&mut args).unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+        let fsstat = |xid| {
+            let reply = handle_rpc_message(&build_call(xid, 100003, 3, 18, &args), peer, &context).unwrap();
+            reply_status(&reply)
+        };
+
+        faulty.fail("statfs", libc::EIO);
+        for xid in 1..=3 {
+            assert_eq!(fsstat(xid), nfsstat3::NFS3ERR_IO as i32);
+        }
+        assert_eq!(context.breakers.open_exports(), vec![1]);
+
+        // The backend now hangs, but calls are answered without reaching it
+        let opened = std::time::Instant::now();
+        faulty.stall("statfs", Duration::from_secs(5));
+        assert_eq!(fsstat(4), nfsstat3::NFS3ERR_IO as i32);
+        assert_eq!(fsstat(5), nfsstat3::NFS3ERR_IO as i32);
+        assert!(opened.elapsed() < cooldown, "{:?}", opened.elapsed());
+
+        // Once the cooldown is over, a readable root closes the breaker
+        faulty.heal("statfs");
+        std::thread::sleep(cooldown);
+        assert_eq!(fsstat(6), nfsstat3::NFS3_OK as i32);
+        assert!(context.breakers.open_exports().is_empty());
+    }
+
+    #[test]
+    fn test_root_squash_create_owned_by_anon() {
+        use crate::export::{ExportOptions, Squash};
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::nfs::{
+            createhow3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
+        };
+
+        let mut exports = ExportTable::new();
+        exports.add_with_options(
+            "/",
+            Arc::new(MemoryFilesystem::new()),
+            ExportOptions {
+                squash: Squash::Root,
+                anon_uid: 4242,
+                anon_gid: 4343,
+                ..ExportOptions::default()
+            },
+        );
+        let context = ServerContext::new(Registry::new(), Arc::new(exports));
+        let root_handle = context.filesystem.root_handle();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        let create = |xid: u32, uid: u32, name: &str| {
+            let mut args = Vec::new();
+            fhandle3(root_handle.clone()).pack(&mut args).unwrap();
+            filename3(name.to_string()).pack(&mut args).unwrap();
+            createhow3::UNCHECKED(sattr3 {
+                mode: set_mode3::SET_MODE(0o644),
+                uid: set_uid3::default,
+                gid: set_gid3::default,
+                size: set_size3::default,
+                atime: set_atime::default,
+                mtime: set_mtime::default,
+            })
+            .pack(&mut args)
+            .unwrap();
+            let reply = handle_rpc_message(&build_auth_sys_call(xid, 8, uid, uid, &args), peer, &context).unwrap();
+            assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32);
+            let handle = context.filesystem.lookup(&root_handle, name).unwrap();
+            context.filesystem.getattr(&handle).unwrap()
+        };
+
+        // Remote root is squashed to the anonymous identity
+        let attrs = create(1, 0, "from_root.txt");
+        assert_eq!((attrs.uid, attrs.gid), (4242, 4343));
+
+        // Other users keep their identity
+        let attrs = create(2, 1000, "from_user.txt");
+        assert_eq!((attrs.uid, attrs.gid), (1000, 1000));
+    }
+
+    #[test]
+    fn test_root_squash_create_owned_by_anon_on_disk() {
+        use crate::export::{ExportOptions, Squash};
+        use crate::protocol::v3::nfs::{
+            createhow3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
+        };
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut exports = ExportTable::new();
+        exports.add_with_options(
+            "/",
+            Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap()),
+            ExportOptions {
+                squash: Squash::Root,
+                anon_uid: 65534,
+                anon_gid: 65534,
+                ..ExportOptions::default()
+            },
+        );
+        let context = ServerContext::new(Registry::new(), Arc::new(exports));
+        let root_handle = context.filesystem.root_handle();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        let mut args = Vec::new();
+        fhandle3(root_handle.clone()).pack(&mut args).unwrap();
+        filename3("from_root.txt".to_string()).pack(&mut args).unwrap();
+        createhow3::UNCHECKED(sattr3 {
+            mode: set_mode3::SET_MODE(0o644),
+            uid: set_uid3::default,
+            gid: set_gid3::default,
+            size: set_size3::default,
+            atime: set_atime::default,
+            mtime: set_mtime::default,
+        })
+        .pack(&mut args)
+        .unwrap();
+        let reply = handle_rpc_message(&build_auth_sys_call(1, 8, 0, 0, &args), peer, &context).unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32);
+
+        // The server runs as root: the file is given away on disk
+        let metadata = std::fs::metadata(temp_dir.path().join("from_root.txt")).unwrap();
+        assert_eq!((metadata.uid(), metadata.gid()), (65534, 65534));
+    }
+
+    #[test]
+    fn test_root_squash_setattr_cannot_take_ownership() {
+        use crate::export::{ExportOptions, Squash};
+        use crate::protocol::v3::nfs::{
+            sattr3, sattrguard3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3, SETATTR3args,
+        };
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("owned.txt");
+        std::fs::write(&path, b"data").unwrap();
+        std::os::unix::fs::chown(&path, Some(1000), Some(1000)).unwrap();
+        let mut exports = ExportTable::new();
+        exports.add_with_options(
+            "/",
+            Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap()),
+            ExportOptions {
+                squash: Squash::Root,
+                ..ExportOptions::default()
+            },
+        );
+        let context = ServerContext::new(Registry::new(), Arc::new(exports));
+        let file = context.filesystem.lookup(&context.filesystem.root_handle(), "owned.txt").unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        let setattr = |xid: u32, caller: u32, attrs: sattr3| {
+            let mut args = Vec::new();
+            SETATTR3args {
+                object: fhandle3(file.clone()),
+                new_attributes: attrs,
+                guard: sattrguard3::default,
+            }
+            .pack(&mut args)
+            .unwrap();
+            let call = build_auth_sys_call(xid, 2, caller, caller, &args);
+            reply_status(&handle_rpc_message(&call, peer, &context).unwrap())
+        };
+        let change = |uid: set_uid3, gid: set_gid3, mode: set_mode3| sattr3 {
+            mode,
+            uid,
+            gid,
+            size: set_size3::default,
+            atime: set_atime::default,
+            mtime: set_mtime::default,
+        };
+
+        // Remote root is squashed to anon, who may not take the file
+        let perm = nfsstat3::NFS3ERR_PERM as i32;
+        assert_eq!(setattr(1, 0, change(set_uid3::SET_UID(0), set_gid3::default, set_mode3::default)), perm);
+        assert_eq!(setattr(2, 0, change(set_uid3::default, set_gid3::default, set_mode3::SET_MODE(0o4755))), perm);
+
+        // The owner may not give the file away or move it to a foreign group
+        assert_eq!(setattr(3, 1000, change(set_uid3::SET_UID(0), set_gid3::default, set_mode3::default)), perm);
+        assert_eq!(setattr(4, 1000, change(set_uid3::default, set_gid3::SET_GID(0), set_mode3::default)), perm);
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!((metadata.uid(), metadata.gid()), (1000, 1000));
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o644);
+
+        // But may change the mode and keep its own group
+        let own_group = change(set_uid3::default, set_gid3::SET_GID(1000), set_mode3::SET_MODE(0o600));
+        assert_eq!(setattr(5, 1000, own_group), nfsstat3::NFS3_OK as i32);
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o7777, 0o600);
+    }
+
+    #[test]
+    fn test_export_client_access_control() {
+        use crate::export::{ClientSpec, ExportOptions};
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::mount::mountstat3;
+
+        let mut exports = ExportTable::new();
+        exports.add_with_options(
+            "/data",
+            Arc::new(MemoryFilesystem::new()),
+            ExportOptions {
+                clients: vec!["10.1.0.0/16".parse::<ClientSpec>().unwrap()],
+                ..ExportOptions::default()
+            },
+        );
+        let context = ServerContext::new(Registry::new(), Arc::new(exports));
+        let allowed = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)), 800);
+        let denied = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 7, 7)), 800);
+
+        let mut mnt_args = Vec::new();
+        crate::protocol::v3::mount::dirpath("/data".to_string())
+            .pack(&mut mnt_args)
+            .unwrap();
+        let mnt = build_call(1, 100005, 3, 1, &mnt_args);
+
+        // A host inside the allowed CIDR mounts and receives the root handle
+        let reply = handle_rpc_message(&mnt, allowed, &context).unwrap();
+        assert_eq!(reply_status(&reply), mountstat3::MNT3_OK as i32);
+        let handle_len = u32::from_be_bytes([reply[28], reply[29], reply[30], reply[31]]) as usize;
+        let root_handle = reply[32..32 + handle_len].to_vec();
+
+        // Any other host is refused
+        let reply = handle_rpc_message(&mnt, denied, &context).unwrap();
+        assert_eq!(reply_status(&reply), mountstat3::MNT3ERR_ACCESS as i32);
+        assert_eq!(reply.len(), 28, "Error result carries the status only");
+
+        // NFS procedures are gated too, even with a valid handle
+        let mut getattr_args = Vec::new();
+        fhandle3(root_handle).pack(&mut getattr_args).unwrap();
+        let getattr = build_call(2, 100003, 3, 1, &getattr_args);
+        let reply = handle_rpc_message(&getattr, allowed, &context).unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32);
+        let reply = handle_rpc_message(&getattr, denied, &context).unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3ERR_ACCES as i32);
+    }
+
+    #[test]
+    fn test_read_only_export_refuses_modification() {
+        use crate::export::ExportOptions;
+        use crate::fsal::MemoryFilesystem;
+
+        let mut exports = ExportTable::new();
+        exports.add_with_options(
+            "/",
+            Arc::new(MemoryFilesystem::new()),
+            ExportOptions {
+                read_only: true,
+                ..ExportOptions::default()
+            },
+        );
+        let context = ServerContext::new(Registry::new(), Arc::new(exports));
+        let root_handle = context.filesystem.root_handle();
+        context.filesystem.create(&root_handle, "keep.txt", 0o644).unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        let mut args = Vec::new();
+        fhandle3(root_handle.clone()).pack(&mut args).unwrap();
+        filename3("keep.txt".to_string()).pack(&mut args).unwrap();
+
+        // REMOVE is refused, LOOKUP still works
+        let reply = handle_rpc_message(&build_call(1, 100003, 3, 12, &args), peer, &context).unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3ERR_ROFS as i32);
+        assert!(context.filesystem.lookup(&root_handle, "keep.txt").is_ok());
+
+        let reply = handle_rpc_message(&build_call(2, 100003, 3, 3, &args), peer, &context).unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32);
+    }
+
+    #[test]
+    fn test_unsupported_version_is_prog_mismatch() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::rpc::accept_stat;
+
+        let exports = ExportTable::single("/", Arc::new(MemoryFilesystem::new()));
+        let context = ServerContext::new(Registry::new(), Arc::new(exports));
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+        let word = |reply: &[u8], at: usize| u32::from_be_bytes(reply[at..at + 4].try_into().unwrap());
+
+        // NFS v2 and v4, and MOUNT v1, are answered with the range served: 3 to 3
+        for (prog, vers) in [(100003, 2), (100003, 4), (100005, 1)] {
+            let reply = handle_rpc_message(&build_call(9, prog, vers, 0, &[]), peer, &context).unwrap();
+            assert_eq!(word(&reply, 0), 9, "prog {} v{}", prog, vers);
+            assert_eq!(word(&reply, 20), accept_stat::PROG_MISMATCH as u32, "prog {} v{}", prog, vers);
+            assert_eq!((word(&reply, 24), word(&reply, 28)), (3, 3), "prog {} v{}", prog, vers);
+            assert_eq!(reply.len(), 32);
+        }
+    }
+
+    #[test]
+    fn test_read_only_export_refuses_every_mutating_procedure() {
+        use crate::export::ExportOptions;
+        use crate::fsal::MemoryFilesystem;
+
+        // SETATTR, WRITE, CREATE, MKDIR, SYMLINK, MKNOD, REMOVE, RMDIR, RENAME, LINK, COMMIT
+        const MUTATING: [u32; 11] = [2, 7, 8, 9, 10, 11, 12, 13, 14, 15, 21];
+
+        let mut exports = ExportTable::new();
+        exports.add_with_options(
+            "/",
+            Arc::new(MemoryFilesystem::new()),
+            ExportOptions {
+                read_only: true,
+                ..ExportOptions::default()
+            },
+        );
+        let context = ServerContext::new(Registry::new(), Arc::new(exports));
+        let root_handle = context.filesystem.root_handle();
+        context.filesystem.create(&root_handle, "keep.txt", 0o644).unwrap();
+        let before = context.filesystem.getattr(&root_handle).unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        for procedure in 0..=21 {
+            assert_eq!(crate::nfs::is_mutating(procedure), MUTATING.contains(&procedure), "proc {}", procedure);
+        }
+
+        // The check comes before the arguments are decoded, so a handle is enough
+        let mut args = Vec::new();
+        fhandle3(root_handle.clone()).pack(&mut args).unwrap();
+        filename3("keep.txt".to_string()).pack(&mut args).unwrap();
+        for procedure in MUTATING {
+            let call = build_call(procedure, 100003, 3, procedure, &args);
+            let reply = handle_rpc_message(&call, peer, &context).unwrap();
+            assert_eq!(reply_status(&reply), nfsstat3::NFS3ERR_ROFS as i32, "proc {}", procedure);
+        }
+
+        // GETATTR and LOOKUP are served
+        for procedure in [1, 3] {
+            let call = build_call(procedure, 100003, 3, procedure, &args);
+            let reply = handle_rpc_message(&call, peer, &context).unwrap();
+            assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32, "proc {}", procedure);
+        }
+        assert!(context.filesystem.lookup(&root_handle, "keep.txt").is_ok());
+        assert_eq!(context.filesystem.getattr(&root_handle).unwrap().mtime, before.mtime);
+    }
+
+    #[test]
+    fn test_auth_none_refused_on_auth_sys_export() {
+        use crate::export::ExportOptions;
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::rpc::auth_flavor;
+
+        let mut exports = ExportTable::new();
+        exports.add_with_options(
+            "/",
+            Arc::new(MemoryFilesystem::new()),
+            ExportOptions {
+                auth_flavors: vec![auth_flavor::AUTH_SYS as i32],
+                ..ExportOptions::default()
+            },
+        );
+        let context = ServerContext::new(Registry::new(), Arc::new(exports));
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+        let mut args = Vec::new();
+        fhandle3(context.filesystem.root_handle()).pack(&mut args).unwrap();
+
+        // AUTH_NONE is denied before the procedure runs
+        let reply = handle_rpc_message(&build_call(1, 100003, 3, 1, &args), peer, &context).unwrap();
+        assert_eq!(RpcMessage::auth_error_status(&reply), Some(auth_stat::AUTH_TOOWEAK));
+        assert_eq!(reply.len(), 20, "Rejected reply carries no verifier or results");
+
+        // AUTH_SYS gets through, and NULL needs no credential at all
+        let reply = handle_rpc_message(&build_auth_sys_call(2, 1, 1000, 1000, &args), peer, &context).unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32);
+        let reply = handle_rpc_message(&build_call(3, 100003, 3, 0, &[]), peer, &context).unwrap();
+        assert_eq!(RpcMessage::success_reply_results(&reply), Some(&[][..]));
+    }
+
+    #[test]
+    fn test_portmap_callit_local_only() {
+        use crate::fsal::MemoryFilesystem;
+        use crate::protocol::v3::portmap::{call_args, call_result, mapping};
+        use std::io::Cursor;
+        use xdr_codec::Unpack;
+
+        let registry = Registry::new();
+        registry.set(&mapping { prog: 100000, vers: 2, prot: 6, port: 111 });
+        // A third-party program registered by someone else
+        registry.set(&mapping { prog: 300019, vers: 1, prot: 6, port: 7000 });
+        let context = ServerContext::new(
+            registry,
+            Arc::new(ExportTable::single("/", Arc::new(MemoryFilesystem::new()))),
+        );
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        let callit = |xid: u32, prog: u32, vers: u32, proc_: u32| {
+            let mut args = Vec::new();
+            call_args { prog, vers, proc_, args: vec![] }.pack(&mut args).unwrap();
+            let reply = handle_rpc_message(&build_call(xid, 100000, 2, 5, &args), peer, &context).unwrap();
+            let results = RpcMessage::success_reply_results(&reply).expect("CALLIT should succeed");
+            call_result::unpack(&mut Cursor::new(results)).unwrap().0
+        };
+
+        // Portmapper's own NULL: invoked locally, void results
+        let result = callit(1, 100000, 2, 0);
+        assert_eq!(result.port, 111);
+        assert!(result.res.is_empty());
+
+        // Never forwarded to other programs, even registered ones
+        let result = callit(2, 300019, 1, 0);
+        assert_eq!((result.port, result.res.len()), (0, 0));
+
+        // No recursion through CALLIT
+        let result = callit(3, 100000, 2, 5);
+        assert_eq!((result.port, result.res.len()), (0, 0));
+
+        // Unregistered programs get an empty result
+        let result = callit(4, 100005, 3, 0);
+        assert_eq!((result.port, result.res.len()), (0, 0));
+    }
+
+    #[test]
+    fn test_access_log_records_getattr() {
+        use std::io::Write;
+        use std::sync::Mutex;
+
+        /// Collects formatted tracing output
+        #[derive(Clone, Default)]
+        struct Capture(Arc<Mutex<Vec<u8>>>);
+        impl Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap());
+        let context = ServerContext::new(Registry::new(), Arc::new(ExportTable::single("/", fs)));
+        let mut args = Vec::new();
+        fhandle3(context.filesystem.root_handle()).pack(&mut args).unwrap();
+        let call = build_auth_sys_call(77, 1, 1000, 1000, &args);
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 9)), 800);
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::INFO)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            handle_rpc_message(&call, peer, &context).unwrap();
+        });
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let line = output
+            .lines()
+            .find(|line| line.contains(access_log::ACCESS_LOG_TARGET))
+            .unwrap_or_else(|| panic!("No access log event in {:?}", output));
+        for field in ["xid=77", "prog=100003", "proc=GETATTR", "client=192.0.2.9", "uid=1000", "status=NFS3_OK", "duration_us="] {
+            assert!(line.contains(field), "{:?} missing from {:?}", field, line);
+        }
+    }
+
+    #[test]
+    fn test_admin_stats_report_counts_to_admin_clients_only() {
+        use crate::protocol::v3::rpc::accept_stat;
+        use std::io::Cursor;
+        use xdr_codec::Unpack;
+
+        let temp_dir = TempDir::new().unwrap();
+        let fs = Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap());
+        let mut context = ServerContext::new(Registry::new(), Arc::new(ExportTable::single("/", fs)));
+        context.admin_clients = Arc::new(vec![ClientSpec::Host(IpAddr::V4(Ipv4Addr::LOCALHOST))]);
+        let admin = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 900);
+        let other = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 9)), 900);
+
+        // Two GETATTRs that succeed and one on a handle that was never issued
+        let mut args = Vec::new();
+        fhandle3(context.filesystem.root_handle()).pack(&mut args).unwrap();
+        for xid in 1..=2 {
+            handle_rpc_message(&build_call(xid, 100003, 3, 1, &args), other, &context).unwrap();
+        }
+        let mut stale = Vec::new();
+        fhandle3(vec![0; 36]).pack(&mut stale).unwrap();
+        handle_rpc_message(&build_call(3, 100003, 3, 1, &stale), other, &context).unwrap();
+
+        // Other clients are told the program does not exist
+        let stats = build_call(4, ADMIN_PROGRAM, 1, admin::procedures::STATS, &[]);
+        let reply = handle_rpc_message(&stats, other, &context).unwrap();
+        assert_eq!(&reply[20..24], &(accept_stat::PROG_UNAVAIL as u32).to_be_bytes());
+
+        let reply = handle_rpc_message(&stats, admin, &context).unwrap();
+        let mut results = Cursor::new(RpcMessage::success_reply_results(&reply).expect("STATS should succeed"));
+        let (entries, _) = u32::unpack(&mut results).unwrap();
+        let mut counts = Vec::new();
+        for _ in 0..entries {
+            let (program, _) = String::unpack(&mut results).unwrap();
+            let (procedure, _) = String::unpack(&mut results).unwrap();
+            let (calls, _) = u64::unpack(&mut results).unwrap();
+            let (errors, _) = u64::unpack(&mut results).unwrap();
+            counts.push((program, procedure, calls, errors));
+        }
+        assert!(counts.contains(&("nfs".to_string(), "GETATTR".to_string(), 3, 1)), "{:?}", counts);
+        assert!(counts.contains(&("admin".to_string(), "STATS".to_string(), 1, 1)), "Refused call: {:?}", counts);
+    }
+
+    #[test]
+    fn test_admin_lists_and_invalidates_handles() {
+        use crate::protocol::v3::rpc::accept_stat;
+        use std::io::Cursor;
+        use xdr_codec::Unpack;
+
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("file"), b"data").unwrap();
+        let fs = Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap());
+        let mut context = ServerContext::new(Registry::new(), Arc::new(ExportTable::single("/", fs)));
+        context.admin_clients = Arc::new(vec![ClientSpec::Host(IpAddr::V4(Ipv4Addr::LOCALHOST))]);
+        let admin = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 900);
+        let other = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 9)), 900);
+        let file = context.filesystem.lookup(&context.filesystem.root_handle(), "file").unwrap();
+
+        // The handle is listed with its path
+        let handles = build_call(1, ADMIN_PROGRAM, 1, admin::procedures::HANDLES, &[]);
+        let reply = handle_rpc_message(&handles, admin, &context).unwrap();
+        let mut results = Cursor::new(RpcMessage::success_reply_results(&reply).expect("HANDLES should succeed"));
+        let (entries, _) = u32::unpack(&mut results).unwrap();
+        let mut listed = Vec::new();
+        for _ in 0..entries {
+            let (handle, _) = xdr_codec::unpack_opaque_flex(&mut results, None).unwrap();
+            let (path, _) = String::unpack(&mut results).unwrap();
+            listed.push((handle, path));
+        }
+        let path = temp_dir.path().canonicalize().unwrap().join("file").display().to_string();
+        assert!(listed.contains(&(file.clone(), path)), "{:?}", listed);
+
+        // Only admin clients may invalidate it
+        let mut args = Vec::new();
+        xdr_codec::pack_opaque_flex(&file, None, &mut args).unwrap();
+        let invalidate = build_call(2, ADMIN_PROGRAM, 1, admin::procedures::INVALIDATE, &args);
+        let reply = handle_rpc_message(&invalidate, other, &context).unwrap();
+        assert_eq!(&reply[20..24], &(accept_stat::PROG_UNAVAIL as u32).to_be_bytes());
+        assert!(context.filesystem.getattr(&file).is_ok());
+
+        let reply = handle_rpc_message(&invalidate, admin, &context).unwrap();
+        assert_eq!(RpcMessage::success_reply_results(&reply), Some(&[0, 0, 0, 1][..]));
+        let reply = handle_rpc_message(&invalidate, admin, &context).unwrap();
+        assert_eq!(RpcMessage::success_reply_results(&reply), Some(&[0, 0, 0, 0][..]), "Already forgotten");
+
+        // Clients holding it now get STALE; a new LOOKUP issues a fresh handle
+        let mut getattr = Vec::new();
+        fhandle3(file.clone()).pack(&mut getattr).unwrap();
+        let reply = handle_rpc_message(&build_call(3, 100003, 3, 1, &getattr), admin, &context).unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3ERR_STALE as i32);
+        let fresh = context.filesystem.lookup(&context.filesystem.root_handle(), "file").unwrap();
+        assert_ne!(fresh, file);
+        assert!(context.filesystem.getattr(&fresh).is_ok());
+    }
+
+    #[test]
+    fn test_metrics_count_read() {
+        use crate::protocol::v3::nfs::READ3args;
+
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("data.txt"), b"metrics").unwrap();
+        let fs = Arc::new(LocalFilesystem::new(temp_dir.path()).unwrap());
+        let context = ServerContext::new(Registry::new(), Arc::new(ExportTable::single("/", fs)));
+        let root = context.filesystem.root_handle();
+        let file = context.filesystem.lookup(&root, "data.txt").unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 800);
+
+        let mut args = Vec::new();
+        READ3args { file: fhandle3(file), offset: 0, count: 64 }.pack(&mut args).unwrap();
+        let reply = handle_rpc_message(&build_call(5, 100003, 3, 6, &args), peer, &context).unwrap();
+        assert_eq!(reply_status(&reply), nfsstat3::NFS3_OK as i32);
+
+        assert_eq!(context.metrics.request_count("nfs", "READ", "OK"), 1);
+        let text = context.metrics.render(context.exports.handle_count());
+        assert!(text.contains("nfs_requests_total{proc=\"READ\",status=\"OK\"} 1\n"), "{}", text);
+        assert!(text.contains("nfs_request_duration_seconds_count{proc=\"READ\"} 1\n"));
+        // Root and the looked-up file
+        assert!(text.contains("arcticwolf_file_handles 2\n"));
+    }
+}
+
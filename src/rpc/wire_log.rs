@@ -0,0 +1,135 @@
+// Wire Dumps
+//
+// Raw request and reply bytes help when debugging the protocol itself, but
+// they are noisy and can carry file names. They are logged at trace level
+// under the "arcticwolf::wire" target, so debug logging leaves them out and
+// they can be switched on alone (RUST_LOG=arcticwolf::wire=trace).
+//
+// File contents are never dumped: a WRITE call is dumped without its
+// arguments, which end in the data.
+
+use tracing::trace;
+
+use crate::protocol::v3::rpc::rpc_call_msg;
+
+/// Tracing target of wire dumps
+pub const WIRE_LOG_TARGET: &str = "arcticwolf::wire";
+
+/// Most bytes dumped at once
+const MAX_DUMP: usize = 128;
+
+/// NFS program and WRITE procedure numbers (RFC 1813)
+const NFS_PROGRAM: u32 = 100003;
+const NFS_WRITE: u32 = 7;
+
+/// Dump (the start of) a buffer
+///
+/// # Arguments
+/// * `what` - What the bytes are, e.g. "MNT args"
+/// * `bytes` - The bytes; at most `MAX_DUMP` of them are logged
+pub fn dump(what: &str, bytes: &[u8]) {
+    trace!(
+        target: WIRE_LOG_TARGET,
+        "{} ({} bytes): {:02x?}",
+        what,
+        bytes.len(),
+        &bytes[..bytes.len().min(MAX_DUMP)]
+    );
+}
+
+/// Dump a call message, leaving out WRITE data
+///
+/// # Arguments
+/// * `call` - Parsed call header
+/// * `message` - Complete call message
+/// * `args_offset` - Offset of the procedure arguments in `message`
+pub fn dump_call(call: &rpc_call_msg, message: &[u8], args_offset: usize) {
+    if call.prog == NFS_PROGRAM && call.proc_ == NFS_WRITE {
+        dump("RPC call header (WRITE arguments withheld)", &message[..args_offset.min(message.len())]);
+    } else {
+        dump("RPC call", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::v3::rpc::{auth_flavor, msg_type, opaque_auth};
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use tracing::Level;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// Log sink capturing formatted tracing output
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for LogBuffer {
+        type Writer = LogBuffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    /// Output of `f` with logging enabled up to `level`
+    fn logged_at(level: Level, f: impl FnOnce()) -> String {
+        let logs = LogBuffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(logs.clone())
+            .with_ansi(false)
+            .with_max_level(level)
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+        String::from_utf8(logs.0.lock().unwrap().clone()).unwrap()
+    }
+
+    fn call(proc_: u32) -> rpc_call_msg {
+        rpc_call_msg {
+            xid: 1,
+            mtype: msg_type::CALL,
+            rpcvers: 2,
+            prog: NFS_PROGRAM,
+            vers: 3,
+            proc_,
+            cred: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+            verf: opaque_auth { flavor: auth_flavor::AUTH_NONE, body: vec![] },
+        }
+    }
+
+    #[test]
+    fn test_dump_only_at_trace() {
+        let payload = [0xab, 0xcd, 0xef];
+        assert_eq!(logged_at(Level::DEBUG, || dump("CREATE args", &payload)), "");
+
+        let output = logged_at(Level::TRACE, || dump("CREATE args", &payload));
+        assert!(output.contains(WIRE_LOG_TARGET), "{}", output);
+        assert!(output.contains("CREATE args (3 bytes): [ab, cd, ef]"), "{}", output);
+    }
+
+    #[test]
+    fn test_write_data_never_dumped() {
+        // 8 header bytes, then arguments ending in file data
+        let mut message = vec![0x11; 8];
+        message.extend_from_slice(&[0x5e, 0xc2, 0xe7]);
+
+        let output = logged_at(Level::TRACE, || dump_call(&call(NFS_WRITE), &message, 8));
+        assert!(output.contains("(8 bytes)"), "{}", output);
+        assert!(!output.contains("5e"), "WRITE data leaked: {}", output);
+
+        // Other calls are dumped whole
+        let output = logged_at(Level::TRACE, || dump_call(&call(1), &message, 8));
+        assert!(output.contains("5e, c2, e7"), "{}", output);
+    }
+}
@@ -0,0 +1,142 @@
+// NFSv3 Round-Trip Tests
+//
+// Calls go over TCP to a loopback server and the replies are decoded, so
+// these tests check the status and attributes a client receives.
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+
+use arcticwolf::LocalFilesystem;
+use arcticwolf::protocol::v3::nfs::{
+    fattr3, fhandle3, filename3, ftype3, nfsstat3, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3,
+    set_uid3, GETATTR3args, MKDIR3args, REMOVE3args, RMDIR3args,
+};
+use tempfile::TempDir;
+
+use common::TestServer;
+
+const GETATTR: u32 = 1;
+const MKDIR: u32 = 9;
+const REMOVE: u32 = 12;
+const RMDIR: u32 = 13;
+
+/// Serve a fresh directory, returning it with the server
+async fn serve_temp_dir() -> (TempDir, TestServer) {
+    let temp_dir = TempDir::new().unwrap();
+    let fs = LocalFilesystem::new(temp_dir.path()).unwrap();
+    let server = TestServer::start(Arc::new(fs)).await;
+    (temp_dir, server)
+}
+
+fn mkdir_args(dir: &[u8], name: &str, mode: u32) -> MKDIR3args {
+    MKDIR3args {
+        where_dir: fhandle3(dir.to_vec()),
+        name: filename3(name.to_string()),
+        attributes: sattr3 {
+            mode: set_mode3::SET_MODE(mode),
+            uid: set_uid3::default,
+            gid: set_gid3::default,
+            size: set_size3::default,
+            atime: set_atime::default,
+            mtime: set_mtime::default,
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_getattr_of_mounted_root() {
+    let (temp_dir, server) = serve_temp_dir().await;
+    fs::write(temp_dir.path().join("file"), b"data").unwrap();
+    let mut client = server.connect().await;
+    let root = client.mount("/").await;
+
+    let mut reply = client.nfs(GETATTR, &GETATTR3args { object: fhandle3(root) }).await;
+    assert_eq!(reply.status(), nfsstat3::NFS3_OK);
+    let attributes: fattr3 = reply.unpack();
+    assert_eq!(attributes.type_, ftype3::NF3DIR);
+    assert!(reply.is_finished());
+}
+
+#[tokio::test]
+async fn test_mkdir_returns_handle_and_attributes() {
+    let (temp_dir, server) = serve_temp_dir().await;
+    let mut client = server.connect().await;
+    let root = client.mount("/").await;
+
+    let mut reply = client.nfs(MKDIR, &mkdir_args(&root, "newdir", 0o750)).await;
+    assert_eq!(reply.status(), nfsstat3::NFS3_OK);
+    let handle = reply.post_op_fh().expect("MKDIR should return the new handle");
+    let attributes = reply.post_op_attr().expect("MKDIR should return the new attributes");
+    assert_eq!(attributes.type_, ftype3::NF3DIR);
+    assert_eq!(attributes.mode & 0o7777, 0o750);
+    let (_, dir_after) = reply.wcc_data();
+    assert_eq!(dir_after.expect("MKDIR should return the parent's attributes").type_, ftype3::NF3DIR);
+    assert!(reply.is_finished());
+    assert!(temp_dir.path().join("newdir").is_dir());
+
+    // The handle names the new directory
+    let mut reply = client.nfs(GETATTR, &GETATTR3args { object: fhandle3(handle) }).await;
+    assert_eq!(reply.status(), nfsstat3::NFS3_OK);
+    assert_eq!(reply.unpack::<fattr3>().fileid, attributes.fileid);
+}
+
+#[tokio::test]
+async fn test_mkdir_already_exists() {
+    let (temp_dir, server) = serve_temp_dir().await;
+    fs::create_dir(temp_dir.path().join("existingdir")).unwrap();
+    let mut client = server.connect().await;
+    let root = client.mount("/").await;
+
+    let mut reply = client.nfs(MKDIR, &mkdir_args(&root, "existingdir", 0o755)).await;
+    assert_eq!(reply.status(), nfsstat3::NFS3ERR_EXIST);
+    reply.wcc_data();
+    assert!(reply.is_finished());
+}
+
+#[tokio::test]
+async fn test_rmdir_nonexistent() {
+    let (_temp_dir, server) = serve_temp_dir().await;
+    let mut client = server.connect().await;
+    let root = client.mount("/").await;
+
+    let args = RMDIR3args {
+        dir: fhandle3(root),
+        name: filename3("does_not_exist".to_string()),
+    };
+    let mut reply = client.nfs(RMDIR, &args).await;
+    assert_eq!(reply.status(), nfsstat3::NFS3ERR_NOENT);
+}
+
+#[tokio::test]
+async fn test_rmdir_not_empty() {
+    let (temp_dir, server) = serve_temp_dir().await;
+    let target_dir = temp_dir.path().join("nonemptydir");
+    fs::create_dir(&target_dir).unwrap();
+    fs::write(target_dir.join("somefile.txt"), "data").unwrap();
+    let mut client = server.connect().await;
+    let root = client.mount("/").await;
+
+    let args = RMDIR3args {
+        dir: fhandle3(root),
+        name: filename3("nonemptydir".to_string()),
+    };
+    let mut reply = client.nfs(RMDIR, &args).await;
+    assert_eq!(reply.status(), nfsstat3::NFS3ERR_NOTEMPTY);
+    assert!(target_dir.join("somefile.txt").exists(), "Directory should still exist");
+}
+
+#[tokio::test]
+async fn test_remove_nonexistent() {
+    let (_temp_dir, server) = serve_temp_dir().await;
+    let mut client = server.connect().await;
+    let root = client.mount("/").await;
+
+    let args = REMOVE3args {
+        dir: fhandle3(root),
+        name: filename3("does_not_exist.txt".to_string()),
+    };
+    let mut reply = client.nfs(REMOVE, &args).await;
+    assert_eq!(reply.status(), nfsstat3::NFS3ERR_NOENT);
+}
@@ -50,7 +50,7 @@ def test_mount_mnt():
     host = "localhost"
     port = 4000
     xid = 99999  # Transaction ID
-    mount_path = "/export/test"
+    mount_path = "/tmp/nfs_exports"
 
     print(f"Connecting to {host}:{port}")
     print(f"  Program: 100005 (MOUNT)")
@@ -213,7 +213,7 @@ def test_nfs_create():
     print("Step 1: MOUNT /")
     print("-" * 60)
     mount_xid = 600001
-    mount_args = pack_string("/")
+    mount_args = pack_string("/tmp/nfs_exports")
 
     reply_data = rpc_call(host, port, mount_xid, 100005, 3, 1, mount_args)
     offset = parse_rpc_reply(reply_data)
@@ -103,7 +103,7 @@ def test_fsinfo_format():
     print("Step 1: MOUNT /")
     print("-" * 70)
     mount_xid = 600001
-    mount_args = pack_string("/")
+    mount_args = pack_string("/tmp/nfs_exports")
 
     reply_data = rpc_call(host, port, mount_xid, 100005, 3, 1, mount_args)
 
@@ -118,7 +118,7 @@ def test_nfs_lookup():
     mount_proc = 1  # MNT
 
     # MOUNT args: dirpath (export path)
-    dirpath = "/"
+    dirpath = "/tmp/nfs_exports"
     mount_args = pack_string(dirpath)
 
     print(f"  Calling MOUNT MNT for path: {dirpath}")
@@ -90,7 +90,7 @@ def test_lookup_specific_file():
     # Step 1: MOUNT
     print("Step 1: MOUNT /")
     mount_xid = 300001
-    mount_args = pack_string("/")
+    mount_args = pack_string("/tmp/nfs_exports")
 
     reply_data = rpc_call(host, port, mount_xid, 100005, 3, 1, mount_args)
 
@@ -112,7 +112,7 @@ def test_mount_procedures():
     print("Step 1: MOUNT /")
     print("-" * 60)
     mount_xid = 500001
-    mount_args = pack_string("/")
+    mount_args = pack_string("/tmp/nfs_exports")
 
     reply_data = rpc_call(host, port, mount_xid, 100005, 3, 1, mount_args)
     offset = parse_rpc_reply(reply_data)
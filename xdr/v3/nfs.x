@@ -172,6 +172,8 @@ union set_size3 switch (set_size3_how set_it) {
 };
 
 union set_atime switch (time_how set_it) {
+    case SET_TO_SERVER_TIME:
+        void;
     case SET_TO_CLIENT_TIME:
         nfstime3 atime;
     default:
@@ -179,6 +181,8 @@ union set_atime switch (time_how set_it) {
 };
 
 union set_mtime switch (time_how set_it) {
+    case SET_TO_SERVER_TIME:
+        void;
     case SET_TO_CLIENT_TIME:
         nfstime3 mtime;
     default:
@@ -517,6 +521,8 @@ union mknoddata3 switch (ftype3 type) {
         sattr3 sock_attributes;
     case NF3FIFO:
         sattr3 pipe_attributes;
+    default:
+        void;               /* NF3REG, NF3DIR, NF3LNK: NFS3ERR_BADTYPE */
 };
 
 struct MKNOD3args {
@@ -27,6 +27,36 @@ struct pmaplist {
     pmaplist *next;
 };
 
+/* Arguments of PMAPPROC_CALLIT */
+struct call_args {
+    unsigned int prog;      /* Program to call */
+    unsigned int vers;      /* Version to call */
+    unsigned int proc;      /* Procedure to call */
+    opaque args<>;          /* Encoded procedure arguments */
+};
+
+/* Result of PMAPPROC_CALLIT */
+struct call_result {
+    unsigned int port;      /* Port of the called program */
+    opaque res<>;           /* Encoded procedure results */
+};
+
+/* ===== rpcbind v3/v4 Types ===== */
+
+const RPCBVERS = 3;            /* rpcbind version 3 */
+const RPCBVERS4 = 4;           /* rpcbind version 4 */
+
+/* Registration of a program at a universal address
+ * (e.g. "10.0.0.1.8.1" is 10.0.0.1 port 2049)
+ */
+struct rpcb {
+    unsigned int r_prog;    /* Program number */
+    unsigned int r_vers;    /* Version number */
+    string r_netid<>;       /* Transport ("tcp", "udp", "tcp6", "udp6") */
+    string r_addr<>;        /* Universal address */
+    string r_owner<>;       /* Owner of the registration */
+};
+
 /* Boolean result */
 typedef bool bool_result;
 
@@ -65,4 +95,26 @@ typedef unsigned int port_result;
  * Purpose: Get list of all registered services
  */
 
-/* PMAPPROC_CALLIT (5) - Not implemented */
+/* PMAPPROC_CALLIT (5)
+ * Arguments: call_args
+ * Results: call_result
+ * Purpose: Indirectly call a procedure of a registered program
+ *          (only programs served by this process; never forwarded)
+ */
+
+/* ===== rpcbind v3/v4 Procedures ===== */
+
+/* RPCBPROC_SET (1), RPCBPROC_UNSET (2)
+ * Arguments: rpcb
+ * Results: bool
+ */
+
+/* RPCBPROC_GETADDR (3)
+ * Arguments: rpcb (r_addr and r_owner are ignored)
+ * Results: string (universal address, empty if not registered)
+ */
+
+/* RPCBPROC_DUMP (4)
+ * Arguments: void
+ * Results: list of rpcb (each entry preceded by TRUE, ended by FALSE)
+ */